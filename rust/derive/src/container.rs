@@ -0,0 +1,43 @@
+//! Support for the `#[veriform(...)]` attribute.
+//!
+//! This attribute configures how a whole struct or enum deriving `Message`
+//! is decoded, as opposed to `#[field(...)]`, which configures each field.
+
+use darling::FromDeriveInput;
+use syn::Path;
+
+/// Parsed `#[veriform(...)]` attribute, e.g.:
+///
+/// ```text
+/// #[derive(Message)]
+/// #[veriform(validate = "Range::validate")]
+/// pub struct Range {
+///     // ...
+/// }
+/// ```
+#[derive(Debug, FromDeriveInput)]
+#[darling(attributes(veriform))]
+pub(crate) struct Attrs {
+    /// Function called with each decoded message once all of its fields
+    /// have been decoded, which rejects invalid messages by returning an
+    /// error (i.e. a `fn(&Self) -> Result<(), E>` where `E: Into<Error>`)
+    #[darling(default)]
+    validate: Option<Path>,
+
+    /// Is this a single-field tuple struct which is encoded, decoded and
+    /// hashed exactly like the value it wraps (e.g. `UserId(u64)`)?
+    #[darling(default)]
+    transparent: bool,
+}
+
+impl Attrs {
+    /// Get the message's validator, if it has one
+    pub fn validator(&self) -> Option<&Path> {
+        self.validate.as_ref()
+    }
+
+    /// Is this a `#[veriform(transparent)]` newtype?
+    pub fn is_transparent(&self) -> bool {
+        self.transparent
+    }
+}
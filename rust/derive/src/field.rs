@@ -1,15 +1,25 @@
 //! Support for the `#[field(...)] attribute
 
-use darling::{FromField, FromVariant};
-use proc_macro2::TokenStream;
-use quote::quote;
-use syn::Ident;
+use darling::{util::Override, FromField, FromVariant};
+use proc_macro2::{TokenStream, TokenTree};
+use quote::{quote, ToTokens};
+use syn::{
+    Attribute, Expr, GenericArgument, Ident, Lit, Meta, NestedMeta, Path, PathArguments, Type,
+};
+
+/// Largest tag which can be encoded in a field header (`Tag::MAX`)
+const MAX_TAG: u64 = u64::MAX >> 4;
 
 /// Parsed `#[field(...)]` attribute.
 ///
 /// When deriving the [`Message`] trait, every enum variant or field of a
 /// struct MUST be tagged as a `field`.
 ///
+/// Tags are never assigned implicitly, so `tag` and `wire_type` are
+/// required. Deriving fails to compile if tags are repeated, if struct
+/// fields aren't declared in ascending tag order, or if a wire type is
+/// incompatible with the field's Rust type.
+///
 /// # Example
 ///
 /// ```ignore
@@ -42,22 +52,76 @@ pub(crate) struct Attrs {
 
     /// Is this field optional?
     #[darling(default)]
+    #[allow(dead_code)]
     optional: bool,
 
+    /// Is this field omitted when it has its default value (and decoded as
+    /// that value when missing)? This changes the field's canonical
+    /// encoding, so encoding its default value is rejected when decoding.
+    #[darling(default)]
+    skip_default: bool,
+
+    /// Value of this field when it's missing from a message: its type's
+    /// `Default` value for `#[field(default)]`, or the given expression for
+    /// `#[field(default = "expr")]`. Unlike other non-`Option` fields, a
+    /// field with a default isn't required.
+    #[darling(default)]
+    default: Option<Override<String>>,
+
+    /// Is this field (or for a `sequence`, each of its elements) `bytes`
+    /// held in a type whose wire type can't be inferred, e.g. a newtype
+    /// wrapping `Vec<u8>`? Such types are encoded with `AsRef<[u8]>` and
+    /// decoded with `TryFrom<&[u8]>`.
+    #[darling(default)]
+    bytes: bool,
+
+    /// Function called with a reference to the field's value once it's
+    /// decoded, which rejects invalid values by returning an error
+    #[darling(default)]
+    validate: Option<Path>,
+
     /// Size of a fixed-size field
     #[darling(default)]
+    #[allow(dead_code)]
     size: Option<usize>,
 
     /// Minimum size of a variable-sized field
     #[darling(default)]
+    #[allow(dead_code)]
     min: Option<usize>,
 
     /// Maximum size of a variable-sized field
     #[darling(default)]
+    #[allow(dead_code)]
     max: Option<usize>,
 }
 
 impl Attrs {
+    /// Check the attributes are valid, i.e. that the tag is at most
+    /// `Tag::MAX`
+    pub fn validate(self) -> darling::Result<Self> {
+        if self.tag > MAX_TAG {
+            return Err(darling::Error::custom(format!(
+                "tag {} exceeds the maximum of {}",
+                self.tag, MAX_TAG
+            )));
+        }
+
+        if let Some(Override::Explicit(expr)) = &self.default {
+            syn::parse_str::<Expr>(expr).map_err(|e| {
+                darling::Error::custom(format!("invalid `default` expression: {}", e))
+            })?;
+        }
+
+        if self.bytes && !matches!(self.wire_type(), WireType::Bytes | WireType::Sequence) {
+            return Err(darling::Error::custom(
+                "`bytes` can only be used with `bytes` and `sequence` fields",
+            ));
+        }
+
+        Ok(self)
+    }
+
     /// Get the field identifier tag
     pub fn tag(&self) -> u64 {
         self.tag
@@ -72,6 +136,140 @@ impl Attrs {
     pub fn is_critical(&self) -> bool {
         self.critical
     }
+
+    /// Is this field omitted when it has its default value?
+    pub fn skip_default(&self) -> bool {
+        self.skip_default
+    }
+
+    /// Can this field be missing from a message, i.e. is it decoded as its
+    /// default value when it's absent?
+    pub fn has_default(&self) -> bool {
+        self.skip_default || self.default.is_some()
+    }
+
+    /// Is the field's default value its type's `Default` value, rather than
+    /// an expression?
+    pub fn is_default_trait(&self) -> bool {
+        !matches!(self.default, Some(Override::Explicit(_)))
+    }
+
+    /// Get the expression for the field's default value
+    pub fn default_value(&self) -> TokenStream {
+        match &self.default {
+            Some(Override::Explicit(expr)) => {
+                let expr = syn::parse_str::<Expr>(expr).expect("validated `default` expression");
+                quote!((#expr))
+            }
+            _ => quote!(Default::default()),
+        }
+    }
+
+    /// Is this field (or are its elements) `bytes` held in an opaque type?
+    pub fn is_bytes(&self) -> bool {
+        self.bytes
+    }
+
+    /// Get the field's validator, if it has one
+    pub fn validator(&self) -> Option<&Path> {
+        self.validate.as_ref()
+    }
+
+    /// Get the element type of a `sequence` field with the given Rust type
+    pub fn element(&self, ty: &Type) -> Element {
+        if self.bytes {
+            Element::Bytes
+        } else {
+            Element::infer(ty)
+        }
+    }
+}
+
+/// Is this a `#[field(unknown)]` attribute, marking the struct member which
+/// preserves fields unknown to the schema?
+pub(crate) fn is_unknown(attr: &Attribute) -> bool {
+    match attr.parse_meta() {
+        Ok(Meta::List(list)) => {
+            list.nested.len() == 1
+                && matches!(
+                    &list.nested[0],
+                    NestedMeta::Meta(Meta::Path(path)) if path.is_ident("unknown")
+                )
+        }
+        _ => false,
+    }
+}
+
+/// Parsed `#[field(skip)]` attribute, marking a struct member which is
+/// in-memory only: it's never encoded, and is filled in when decoding from
+/// `Default::default()`, or from the expression given as
+/// `#[field(skip, default = "expr")]`.
+pub(crate) struct Skip {
+    /// Expression for the member's value when decoding
+    default: Option<Expr>,
+}
+
+impl Skip {
+    /// Parse a `#[field(skip)]` attribute, returning `None` if the given
+    /// attribute isn't one, or an error if it also has any field schema
+    /// information (e.g. a tag)
+    pub fn parse(attr: &Attribute) -> Option<darling::Result<Self>> {
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(list)) if list.path.is_ident("field") => list,
+            _ => return None,
+        };
+
+        if !list.nested.iter().any(
+            |nested| matches!(nested, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip")),
+        ) {
+            return None;
+        }
+
+        let mut default = None;
+
+        for nested in &list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => (),
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("default") => {
+                    let expr = match &nv.lit {
+                        Lit::Str(lit) => lit.parse::<Expr>(),
+                        _ => {
+                            return Some(Err(darling::Error::custom("`default` must be a string")))
+                        }
+                    };
+
+                    match expr {
+                        Ok(expr) => default = Some(expr),
+                        Err(e) => return Some(Err(darling::Error::custom(e))),
+                    }
+                }
+                NestedMeta::Meta(meta) => {
+                    return Some(Err(darling::Error::custom(format!(
+                        "skipped members can't have a `{}`",
+                        meta.path().to_token_stream()
+                    ))))
+                }
+                NestedMeta::Lit(_) => {
+                    return Some(Err(darling::Error::unsupported_format("literal")))
+                }
+            }
+        }
+
+        Some(Ok(Self { default }))
+    }
+
+    /// Is the member's value when decoding its type's `Default` value?
+    pub fn is_default(&self) -> bool {
+        self.default.is_none()
+    }
+
+    /// Get the expression for the member's value when decoding
+    pub fn default_value(&self) -> TokenStream {
+        match &self.default {
+            Some(expr) => expr.to_token_stream(),
+            None => quote!(Default::default()),
+        }
+    }
 }
 
 /// Wire type identifiers for Veriform types
@@ -118,6 +316,82 @@ impl WireType {
         }
     }
 
+    /// Get the name of this wire type, as used in `#[field(wire_type = ...)]`
+    pub fn name(self) -> &'static str {
+        match self {
+            WireType::Bool => "bool",
+            WireType::UInt64 => "uint64",
+            WireType::SInt64 => "sint64",
+            WireType::Bytes => "bytes",
+            WireType::String => "string",
+            WireType::Message => "message",
+            WireType::Sequence => "sequence",
+        }
+    }
+
+    /// Infer the wire type of a Rust type (or of the type wrapped by an
+    /// `Option<T>`), if it's one whose encoding is known, i.e. any type other
+    /// than a message (or a type converted from a primitive one)
+    pub fn infer(ty: &Type) -> Option<Self> {
+        let ty = option_inner(ty).unwrap_or(ty);
+
+        let segment = match ty {
+            Type::Path(path) => path.path.segments.last()?,
+            Type::Reference(reference) => return Self::infer(&reference.elem),
+            Type::Array(array) => return Self::infer_slice(&array.elem),
+            Type::Slice(slice) => return Self::infer_slice(&slice.elem),
+            _ => return None,
+        };
+
+        let wire_type = match segment.ident.to_string().as_str() {
+            "bool" => WireType::Bool,
+            "u8" | "u16" | "u32" | "u64" => WireType::UInt64,
+            "i8" | "i16" | "i32" | "i64" => WireType::SInt64,
+            "str" | "String" => WireType::String,
+            "BTreeMap" => WireType::Sequence,
+            "Vec" => match &segment.arguments {
+                PathArguments::AngleBracketed(args) => {
+                    match args.args.iter().find_map(|arg| match arg {
+                        GenericArgument::Type(ty) => Some(ty),
+                        _ => None,
+                    }) {
+                        Some(elem) if Self::infer_slice(elem).is_some() => WireType::Bytes,
+                        _ => WireType::Sequence,
+                    }
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(wire_type)
+    }
+
+    /// Infer the wire type of an array or slice with the given element type,
+    /// which is `bytes` for `u8` elements
+    fn infer_slice(elem: &Type) -> Option<Self> {
+        match elem {
+            Type::Path(path) if path.path.is_ident("u8") => Some(WireType::Bytes),
+            _ => None,
+        }
+    }
+
+    /// Check the given wire type is compatible with a field's Rust type,
+    /// if its wire type can be inferred
+    pub fn check_type(wire_type: Self, ty: &Type) -> darling::Result<()> {
+        let ty = option_inner(ty).unwrap_or(ty);
+
+        match Self::infer(ty) {
+            Some(inferred) if inferred != wire_type => Err(darling::Error::custom(format!(
+                "wire type `{}` is incompatible with `{}` (expected `{}`)",
+                wire_type.name(),
+                ty.to_token_stream(),
+                inferred.name()
+            ))),
+            _ => Ok(()),
+        }
+    }
+
     /// Get the Rust type for this token
     pub fn rust_type(self) -> Option<TokenStream> {
         let ty = match self {
@@ -139,17 +413,162 @@ impl WireType {
 
     /// Is this [`WireType`] a `Message`?
     pub fn is_message(self) -> bool {
-        match self {
-            WireType::Message => true,
-            _ => false,
-        }
+        matches!(self, WireType::Message)
     }
 
     /// Is this [`WireType`] a `Sequence`?
     pub fn is_sequence(self) -> bool {
-        match self {
-            WireType::Sequence => true,
+        matches!(self, WireType::Sequence)
+    }
+}
+
+/// Get the type wrapped by an `Option<T>` field type, or `None` if the
+/// field isn't optional
+pub(crate) fn option_inner(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+
+    if segment.ident != "Option" {
+        return None;
+    }
+
+    first_type_arg(ty)
+}
+
+/// Get the first type argument of a field's type, e.g. the element type
+/// `T` of a `sequence` field of type `Vec<T>` or `heapless::Vec<T, N>`
+pub(crate) fn first_type_arg(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None,
+    };
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => args.args.iter().find_map(|arg| match arg {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        }),
+        _ => None,
+    }
+}
+
+/// Does this type mention any of the given type parameters, e.g. `T` in
+/// `Batch<T>` or `Option<T>`?
+pub(crate) fn mentions_type_param(ty: &Type, params: &[Ident]) -> bool {
+    fn mentions(tokens: TokenStream, params: &[Ident]) -> bool {
+        tokens.into_iter().any(|token| match token {
+            TokenTree::Ident(ident) => params.contains(&ident),
+            TokenTree::Group(group) => mentions(group.stream(), params),
             _ => false,
+        })
+    }
+
+    mentions(ty.to_token_stream(), params)
+}
+
+/// Is this field type a `RawMessage<'a>`, i.e. a `message` field whose body
+/// is left undecoded?
+pub(crate) fn is_raw_message(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == "RawMessage")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Is this field type a struct with a lifetime parameter, e.g. `Foo<'a>`,
+/// which borrows from its input and so impls `MessageRef` (but not
+/// `Message`)?
+pub(crate) fn is_message_ref(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => match path.path.segments.last().map(|s| &s.arguments) {
+            Some(PathArguments::AngleBracketed(args)) => args
+                .args
+                .iter()
+                .any(|arg| matches!(arg, GenericArgument::Lifetime(_))),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Is this field type an `alloc` `String` or `Vec<u8>`, as opposed to their
+/// `heapless` counterparts (which take a capacity parameter)?
+pub(crate) fn is_alloc_owned(ty: &Type) -> bool {
+    let segment = match ty {
+        Type::Path(path) => match path.path.segments.last() {
+            Some(segment) => segment,
+            None => return false,
+        },
+        _ => return false,
+    };
+
+    match &segment.arguments {
+        PathArguments::None => segment.ident == "String",
+        PathArguments::AngleBracketed(args) => segment.ident == "Vec" && args.args.len() == 1,
+        _ => false,
+    }
+}
+
+/// Types of elements contained in a `sequence` field
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum Element {
+    /// `uint64` elements, for fields like `Vec<u64>`
+    UInt64,
+
+    /// `sint64` elements, for fields like `Vec<i64>`
+    SInt64,
+
+    /// `bytes` elements, for fields like `Vec<Vec<u8>>`
+    Bytes,
+
+    /// `string` elements, for fields like `Vec<String>`
+    String,
+
+    /// `message` elements (any other type)
+    Message,
+
+    /// key/value entry messages, for `BTreeMap<K, V>` fields
+    Map,
+}
+
+impl Element {
+    /// Infer the element type of a sequence from the first type argument of
+    /// the field's type, e.g. `u64` in `Vec<u64>` or `heapless::Vec<u64, N>`,
+    /// or whether the field is a map. `Vec<u8>` elements are `bytes`.
+    pub fn infer(ty: &Type) -> Self {
+        let segment = match ty {
+            Type::Path(path) => path.path.segments.last(),
+            _ => None,
+        };
+
+        if segment.map(|segment| segment.ident == "BTreeMap") == Some(true) {
+            return Element::Map;
+        }
+
+        let elem_ty = first_type_arg(ty);
+
+        let elem_ident = elem_ty.and_then(|ty| match ty {
+            Type::Path(path) => path.path.get_ident(),
+            _ => None,
+        });
+
+        match elem_ident.map(|ident| ident.to_string()).as_deref() {
+            Some("u64") => return Element::UInt64,
+            Some("i64") => return Element::SInt64,
+            _ => (),
+        }
+
+        match elem_ty.and_then(WireType::infer) {
+            Some(WireType::Bytes) => Element::Bytes,
+            Some(WireType::String) => Element::String,
+            _ => Element::Message,
         }
     }
 }
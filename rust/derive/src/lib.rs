@@ -13,6 +13,7 @@
 #![forbid(unsafe_code)]
 #![warn(rust_2018_idioms)]
 
+mod container;
 mod digest;
 mod field;
 mod message;
@@ -20,7 +21,7 @@ mod message;
 use synstructure::decl_derive;
 
 decl_derive!(
-    [Message, attributes(digest, field)] =>
+    [Message, attributes(digest, field, veriform)] =>
     /// Derive the [`Message`] trait for an `enum` or `struct`.
     ///
     /// When using this macro, every field in a `struct` or every variant of an
@@ -32,10 +33,117 @@ decl_derive!(
     ///   Verihash digest inside of a `struct` containing message fields.
     ///   The `digest` field MUST be the last in the message. For more
     ///   information, see the [`digest::Attrs`] docs.
+    /// - `#[field(unknown)]`: an [`UnknownFields`] member of a `struct`
+    ///   which preserves non-critical fields that aren't part of the schema,
+    ///   re-encoding them in tag order alongside the known fields. Without
+    ///   one, unknown non-critical fields are skipped. On an `enum`, it marks
+    ///   a variant holding an `UnknownField`, which preserves a non-critical
+    ///   field whose tag isn't one of the other variants'. Requires `alloc`.
+    /// - `#[field(skip)]`: (`struct` only) an in-memory only member, e.g. a
+    ///   cache, which isn't part of the message: it's never encoded or
+    ///   hashed, and is decoded as `Default::default()`, or as the value of
+    ///   the expression given with `#[field(skip, default = "expr")]`.
+    ///   Skipped members can't have a tag.
+    ///
+    /// An `enum` is a oneof: it's encoded as a message containing only the
+    /// field of its current variant. Each variant holds exactly one value,
+    /// except unit variants, which are `message` fields containing an empty
+    /// message. Decoding a message with more than one variant's field fails
+    /// with `MultipleVariants`, unless the decoder is permissive, in which
+    /// case the first is used. `Box<T>` is a message whenever `T` is, so
+    /// variants may be recursive. `sequence` variants (e.g. `Nums(Vec<u64>)`)
+    /// are encoded the same way as `sequence` fields of a `struct`.
+    ///
+    /// The element type of a `sequence` field is inferred from the field's
+    /// type: `u64` and `i64` elements (e.g. `Vec<u64>`) are encoded as
+    /// `uint64` and `sint64` values, `String` and `Vec<u8>` elements as
+    /// `string` and `bytes` values, and anything else as messages. A
+    /// `Vec<u8>` field is itself always `bytes`, never a sequence. Empty
+    /// sequences are encoded as a sequence with no elements, unless the
+    /// field is marked `skip_default`. `bytes` and `string` elements are
+    /// decoded by converting them with `TryFrom`, which requires `alloc`
+    /// for `Vec<u8>` and `String`.
+    ///
+    /// Marking a field `#[field(bytes)]` forces the `bytes` interpretation
+    /// for a type whose wire type can't be inferred, such as a newtype around
+    /// `Vec<u8>`: `bytes` fields (or the elements of `sequence` fields) are
+    /// then encoded with `AsRef<[u8]>` and decoded with `TryFrom<&[u8]>`.
+    ///
+    /// `uint64` fields may also be `u32`, `u16` or `u8`, and `sint64` fields
+    /// `i32`, `i16` or `i8`. Decoding values which don't fit in the field's
+    /// type fails with `OutOfRange`.
+    ///
+    /// Struct fields of type `Option<T>` are optional: `None` is encoded as
+    /// nothing at all, and a missing field is decoded as `None`. Any other
+    /// field is required, and decoding a message without it fails with
+    /// `MissingFields` naming every missing field. Marking an optional field
+    /// `critical` only sets the critical bit when it's present: an absent
+    /// critical field is never an error, but an unknown one (i.e. a field
+    /// not in the schema) is rejected with `UnknownCritical`. `bytes` and
+    /// `string` fields may be `Vec<u8>` and `String` when using `alloc`.
+    ///
+    /// Fields marked `#[field(default)]` or `#[field(default = "expr")]`
+    /// aren't required: a missing field is decoded as its type's `Default`
+    /// value or the value of the given expression, respectively. They're
+    /// still always encoded, so the canonical form of a message always
+    /// contains them: decoding a message which omits them and re-encoding it
+    /// adds them, which changes its Verihash. To make omitting the default
+    /// value canonical instead, mark the field `skip_default` too.
+    ///
+    /// Struct fields marked `#[field(skip_default)]` are omitted from the
+    /// encoded message when they're equal to their default value (their
+    /// type's `Default` value, unless `default = "expr"` is also given), and
+    /// decoded as that value when they're missing. This changes the field's
+    /// canonical form (and so the message's Verihash): encoding the default
+    /// value explicitly is rejected with `DefaultValue` when decoding, so
+    /// each value has exactly one encoding. As the default is part of the
+    /// schema, changing it changes the meaning of messages which omit the
+    /// field.
+    ///
+    /// Structs with a lifetime parameter (at most one) impl [`MessageRef`]
+    /// instead, and may borrow `bytes` and `string` fields from their input
+    /// as `&'a [u8]` and `&'a str`, and `message` fields as other such
+    /// structs, e.g. `Inner<'a>`. As they don't impl [`Message`], they can't
+    /// be decoded from a stream or used as the elements of sequences: doing
+    /// so is a compile error.
+    ///
+    /// Structs may be generic over the types of `message` fields, and of
+    /// the elements of `sequence` fields, e.g. `Envelope<T> { payload: T }`:
+    /// the generated impl requires `T: Message` for each such field (and
+    /// `T: Default` only for members which default to it), along with
+    /// any bounds on the struct itself. Fields of other wire types can't be
+    /// generic. Fixed-size `bytes` fields may be arrays with a const generic
+    /// length, e.g. `[u8; N]`.
+    ///
+    /// Decoded messages can be checked for invariants the wire format can't
+    /// express by naming a validator function in the `#[veriform(validate =
+    /// "path::to::fn")]` attribute of a struct or enum: it's called with
+    /// `&Self` once every field has been decoded (and the digest computed),
+    /// and returns a `Result<(), E>` where `E: Into<veriform::Error>`, e.g.
+    /// a [`Kind::Validation`] carrying the reason. A `#[field(validate =
+    /// "...")]` attribute on a struct member names a validator which is
+    /// called with a reference to the member's decoded value (the `T` of an
+    /// `Option<T>`, if it's present) as soon as it's decoded, so its errors
+    /// carry the position of the field. Either way, `decode` only returns
+    /// messages which passed validation. Encoding doesn't validate.
+    ///
+    /// A single-field tuple struct marked `#[veriform(transparent)]`, e.g.
+    /// `struct UserId(u64)`, is encoded, decoded and hashed exactly like the
+    /// value it wraps: rather than [`Message`], it impls `Decodable` and
+    /// `Encodable` (and `SequenceElement` with `alloc`) from the
+    /// [`value`] module by delegating to its inner type, which may itself be
+    /// a transparent newtype. It can be used anywhere its inner type can,
+    /// including in `Option` and as the elements of `Vec` sequences (but not
+    /// `heapless::Vec` ones), with the `wire_type` of its inner type. Such
+    /// structs can't be generic or have fields with attributes of their own.
     ///
     /// See [`tests/derive.rs`] for usage examples.
     ///
     /// [`Message`]: https://docs.rs/veriform/latest/veriform/message/trait.Message.html
+    /// [`Kind::Validation`]: https://docs.rs/veriform/latest/veriform/error/enum.Kind.html#variant.Validation
+    /// [`MessageRef`]: https://docs.rs/veriform/latest/veriform/message/trait.MessageRef.html
+    /// [`value`]: https://docs.rs/veriform/latest/veriform/value/index.html
+    /// [`UnknownFields`]: https://docs.rs/veriform/latest/veriform/field/struct.UnknownFields.html
     /// [`field::Attrs`]: https://docs.rs/veriform_derive/latest/veriform_derive/field/struct.Attrs.html
     /// [`digest::Attrs`]: https://docs.rs/veriform_derive/latest/veriform_derive/digest/struct.Attrs.html
     /// [`tests/derive.rs`]: https://github.com/iqlusioninc/veriform/blob/develop/rust/tests/derive.rs
@@ -1,24 +1,115 @@
 //! Custom derive support for the `Message` trait
 
 use crate::{
-    digest,
+    container, digest,
     field::{self, WireType},
 };
-use darling::{FromField, FromVariant};
+use darling::{FromDeriveInput, FromField, FromVariant};
 use proc_macro2::TokenStream;
 use quote::{quote, ToTokens};
-use syn::{DataEnum, DataStruct, Field, Ident};
-use synstructure::Structure;
+use syn::{
+    parse_quote, DataEnum, DataStruct, Field, Fields, Ident, Lifetime, Path, Type, Variant,
+    WherePredicate,
+};
+use synstructure::{AddBounds, Structure, VariantInfo};
 
 /// Custom derive for `Message`
-pub(crate) fn derive(s: Structure<'_>) -> TokenStream {
+pub(crate) fn derive(mut s: Structure<'_>) -> TokenStream {
+    s.underscore_const(true);
+
+    let attrs = container::Attrs::from_derive_input(s.ast()).unwrap_or_else(|e| {
+        panic!("error parsing veriform attributes: {}", e);
+    });
+
+    if attrs.is_transparent() {
+        return derive_transparent(s, &attrs);
+    }
+
     match &s.ast().data {
-        syn::Data::Enum(data) => DeriveEnum::derive(s, data),
-        syn::Data::Struct(data) => DeriveStruct::derive(s, data),
+        syn::Data::Enum(data) => DeriveEnum::derive(s, data, attrs),
+        syn::Data::Struct(data) => DeriveStruct::derive(s, data, attrs),
         other => panic!("can't derive `Message` on: {:?}", other),
     }
 }
 
+/// Derive a `#[veriform(transparent)]` newtype, which is encoded, decoded
+/// and hashed exactly like the value it wraps: rather than `Message`, it
+/// impls `Decodable`, `Encodable` and (with `alloc`) `SequenceElement` by
+/// delegating to its inner type
+fn derive_transparent(s: Structure<'_>, attrs: &container::Attrs) -> TokenStream {
+    let ast = s.ast();
+    let name = &ast.ident;
+
+    let inner = match &ast.data {
+        syn::Data::Struct(DataStruct {
+            fields: Fields::Unnamed(fields),
+            ..
+        }) if fields.unnamed.len() == 1 => &fields.unnamed[0],
+        _ => panic!("`#[veriform(transparent)]` requires a tuple struct with exactly one field"),
+    };
+
+    if inner
+        .attrs
+        .iter()
+        .any(|attr| attr.path.is_ident("field") || attr.path.is_ident("digest"))
+    {
+        panic!("the field of a `#[veriform(transparent)]` struct can't have attributes");
+    }
+
+    let inner = &inner.ty;
+
+    if !ast.generics.params.is_empty() {
+        panic!("`#[veriform(transparent)]` structs can't be generic");
+    }
+
+    if attrs.validator().is_some() {
+        panic!("`#[veriform(transparent)]` structs can't have a validator");
+    }
+
+    let impls = s.gen_impl(quote! {
+        gen impl veriform::value::Decodable for @Self {
+            const WIRE_TYPE: veriform::field::WireType =
+                <#inner as veriform::value::Decodable>::WIRE_TYPE;
+
+            fn decode_field<D>(
+                decoder: &mut veriform::decoder::Decoder<D>,
+                tag: veriform::field::Tag,
+                input: &mut &[u8],
+            ) -> Result<Self, veriform::Error>
+            where
+                D: veriform::digest::Digest,
+            {
+                <#inner as veriform::value::Decodable>::decode_field(decoder, tag, input).map(Self)
+            }
+        }
+
+        gen impl veriform::value::Encodable for @Self {
+            const WIRE_TYPE: veriform::field::WireType =
+                <#inner as veriform::value::Encodable>::WIRE_TYPE;
+
+            fn encode_field(
+                &self,
+                encoder: &mut veriform::encoder::Encoder<'_>,
+                tag: veriform::field::Tag,
+                critical: bool,
+            ) -> Result<(), veriform::Error> {
+                veriform::value::Encodable::encode_field(&self.0, encoder, tag, critical)
+            }
+
+            fn encoded_field_len(&self, tag: veriform::field::Tag) -> usize {
+                veriform::value::Encodable::encoded_field_len(&self.0, tag)
+            }
+        }
+    });
+
+    // `SequenceElement` requires `alloc`, which is a feature of `veriform`
+    // rather than of this crate, so its impl is left to a macro there
+    quote! {
+        #impls
+        veriform::impl_transparent_sequence_element!(#name, #inner);
+    }
+}
+
 /// Derive `Message` on an enum
 struct DeriveEnum {
     /// Body of `Message::decode()` in-progress for an enum
@@ -29,12 +120,18 @@ struct DeriveEnum {
 
     /// Body of `Message::encoded_len()` in-progress for an enum
     encoded_len_body: TokenStream,
+
+    /// Name of the `#[field(unknown)]` variant, if present
+    unknown: Option<Ident>,
+
+    /// Path to the `#[veriform(validate = "...")]` function, if present
+    validator: Option<Path>,
 }
 
 impl DeriveEnum {
     /// Derive `Message` on an enum
     // TODO(tarcieri): higher-level abstractions/implementation?
-    pub fn derive(s: Structure<'_>, data: &DataEnum) -> TokenStream {
+    pub fn derive(s: Structure<'_>, data: &DataEnum, attrs: container::Attrs) -> TokenStream {
         assert_eq!(
             s.variants().len(),
             data.variants.len(),
@@ -45,30 +142,173 @@ impl DeriveEnum {
             decode_body: TokenStream::new(),
             encode_body: TokenStream::new(),
             encoded_len_body: TokenStream::new(),
+            unknown: None,
+            validator: attrs.validator().cloned(),
         };
 
+        let mut tags = Vec::with_capacity(data.variants.len());
+
         for (variant_info, variant) in s.variants().iter().zip(&data.variants) {
-            let attrs = field::Attrs::from_variant(variant).unwrap_or_else(|e| {
-                panic!("error parsing field attributes: {}", e);
-            });
+            if variant.attrs.iter().any(field::is_unknown) {
+                state.derive_unknown(variant_info, variant);
+                continue;
+            }
 
-            state.derive_decode_match_arm(&variant.ident, &attrs);
+            let attrs = field::Attrs::from_variant(variant)
+                .and_then(field::Attrs::validate)
+                .unwrap_or_else(|e| {
+                    panic!("error parsing field attributes: {}", e);
+                });
+
+            if attrs.has_default() {
+                panic!(
+                    "variant `{}` can't have a default value (only struct fields can)",
+                    variant.ident
+                );
+            }
+
+            if attrs.validator().is_some() {
+                panic!(
+                    "`validate` can't be used on variant `{}` (use `#[veriform(validate = ...)]` \
+                     on the enum instead)",
+                    variant.ident
+                );
+            }
+
+            if tags.contains(&attrs.tag()) {
+                panic!(
+                    "duplicate tag {} on variant `{}`",
+                    attrs.tag(),
+                    variant.ident
+                );
+            }
+
+            tags.push(attrs.tag());
+
+            match variant.fields.len() {
+                0 => {
+                    state.derive_unit(variant_info, variant, &attrs);
+                    continue;
+                }
+                1 => (),
+                _ => panic!(
+                    "variant `{}` must have exactly one field (or none)",
+                    variant.ident
+                ),
+            }
+
+            for field in &variant.fields {
+                field::WireType::check_type(attrs.wire_type(), &field.ty).unwrap_or_else(|e| {
+                    panic!("invalid variant `{}`: {}", variant.ident, e);
+                });
+            }
+
+            let ty = &variant.fields.iter().next().unwrap().ty;
+            state.derive_decode_match_arm(&variant.ident, &attrs, ty);
+
+            // Sequences are encoded according to their element type, which
+            // is inferred from the variant's field as with struct members
+            let seq_ty = if attrs.wire_type().is_sequence() {
+                Some(ty)
+            } else {
+                None
+            };
 
             variant_info
-                .each(|bi| encode_field(&bi.binding, &attrs))
+                .each(|bi| encode_field(&variant.ident, &bi.binding, &attrs, seq_ty))
                 .to_tokens(&mut state.encode_body);
 
             variant_info
-                .each(|bi| encoded_len_for_field(&bi.binding, &attrs))
+                .each(|bi| encoded_len_for_field(&bi.binding, &attrs, seq_ty))
                 .to_tokens(&mut state.encoded_len_body)
         }
 
         state.finish(s)
     }
 
+    /// Derive handling for a unit variant of an enum, which is encoded as an
+    /// empty message
+    fn derive_unit(
+        &mut self,
+        variant_info: &VariantInfo<'_>,
+        variant: &Variant,
+        attrs: &field::Attrs,
+    ) {
+        let name = &variant.ident;
+        let name_str = name.to_string();
+        let tag_value = attrs.tag();
+        let tag = tag_tokens(tag_value);
+        let critical = attrs.is_critical();
+        let pat = variant_info.pat();
+
+        if !attrs.wire_type().is_message() {
+            panic!("unit variant `{}` must be a `message`", name);
+        }
+
+        let decode_arm = quote! {
+            #tag_value => decoder
+                .decode(#tag, &mut input)
+                .map(|_: veriform::derive_helpers::Unit| Self::#name),
+        };
+
+        let encode_arm = quote! {
+            #pat => {
+                encoder
+                    .message(#tag, #critical, &veriform::derive_helpers::Unit)
+                    .map_err(|err| veriform::derive_helpers::field_error(err, &#name_str))?
+            }
+        };
+
+        let encoded_len_arm = quote! {
+            #pat => veriform::field::length::message(#tag, &veriform::derive_helpers::Unit),
+        };
+
+        decode_arm.to_tokens(&mut self.decode_body);
+        encode_arm.to_tokens(&mut self.encode_body);
+        encoded_len_arm.to_tokens(&mut self.encoded_len_body);
+    }
+
+    /// Derive handling for the `#[field(unknown)]` variant of an enum, which
+    /// preserves a non-critical field whose tag isn't one of its variants
+    fn derive_unknown(&mut self, variant_info: &VariantInfo<'_>, variant: &Variant) {
+        if self.unknown.is_some() {
+            panic!("multiple `#[field(unknown)]` variants");
+        }
+
+        if variant.fields.len() != 1 {
+            panic!(
+                "`#[field(unknown)]` variant `{}` must have exactly one field",
+                variant.ident
+            );
+        }
+
+        let name_str = variant.ident.to_string();
+
+        variant_info
+            .each(|bi| {
+                let binding = &bi.binding;
+                quote! {
+                    encoder
+                        .unknown_field(#binding)
+                        .map_err(|err| veriform::derive_helpers::field_error(err, &#name_str))?
+                }
+            })
+            .to_tokens(&mut self.encode_body);
+
+        variant_info
+            .each(|bi| {
+                let binding = &bi.binding;
+                quote!(#binding.encoded_len())
+            })
+            .to_tokens(&mut self.encoded_len_body);
+
+        self.unknown = Some(variant.ident.clone());
+    }
+
     /// Derive a match arm of an enum `decode` method
-    fn derive_decode_match_arm(&mut self, name: &Ident, attrs: &field::Attrs) {
-        let tag = attrs.tag();
+    fn derive_decode_match_arm(&mut self, name: &Ident, attrs: &field::Attrs, ty: &Type) {
+        let tag_value = attrs.tag();
+        let tag = tag_tokens(tag_value);
         let wire_type = attrs.wire_type();
 
         let decode_variant = if wire_type.is_ref_type() {
@@ -81,7 +321,8 @@ impl DeriveEnum {
                     .map_err(|_| veriform::field::WireType::Bytes.decoding_error())
             }
         } else if wire_type.is_sequence() {
-            todo!();
+            let value = decode_value(attrs, ty);
+            quote!(Ok(Self::#name(#value)))
         } else {
             quote! {
                 decoder.decode(#tag, &mut input).map(Self::#name)
@@ -89,7 +330,7 @@ impl DeriveEnum {
         };
 
         let match_arm = quote! {
-            #tag => { #decode_variant }
+            #tag_value => { #decode_variant }
         };
 
         match_arm.to_tokens(&mut self.decode_body);
@@ -100,6 +341,17 @@ impl DeriveEnum {
         let decode_body = self.decode_body;
         let encode_body = self.encode_body;
         let encoded_len_body = self.encoded_len_body;
+        let validate = validate_message(self.validator.as_ref());
+
+        // Fields with unknown tags are preserved by the `#[field(unknown)]`
+        // variant if there is one, or otherwise rejected
+        let decode_unknown = match &self.unknown {
+            Some(name) => quote! {
+                veriform::derive_helpers::decode_unknown_variant(decoder, &mut input)
+                    .map(Self::#name)
+            },
+            None => quote!(Err(veriform::derive_helpers::unknown_tag(tag))),
+        };
 
         s.gen_impl(quote! {
             gen impl Message for @Self {
@@ -115,12 +367,14 @@ impl DeriveEnum {
                     #[allow(unused_imports)]
                     use veriform::decoder::{Decode, DecodeRef};
 
-                    let msg = match veriform::derive_helpers::decode_tag(input)? {
+                    let tag = veriform::derive_helpers::decode_tag(input)?;
+                    let msg = match tag.to_u64() {
                         #decode_body
-                        tag => Err(veriform::derive_helpers::unknown_tag(tag))
+                        _ => #decode_unknown
                     }?;
 
-                    veriform::derive_helpers::check_input_consumed(input)?;
+                    veriform::derive_helpers::check_oneof_consumed(decoder, input)?;
+                    #validate
                     Ok(msg)
                 }
 
@@ -128,13 +382,17 @@ impl DeriveEnum {
                     &self,
                     buffer: &'a mut [u8]
                 ) -> Result<&'a [u8], veriform::Error> {
-                    let mut encoder = veriform::Encoder::new(buffer);
-
-                    match self {
-                        #encode_body
-                    }
-
-                    Ok(encoder.finish())
+                    veriform::derive_helpers::encode_message(
+                        buffer,
+                        || self.encoded_len(),
+                        |encoder| {
+                            match self {
+                                #encode_body
+                            }
+
+                            Ok(())
+                        }
+                    )
                 }
 
                 fn encoded_len(&self) -> usize {
@@ -148,11 +406,14 @@ impl DeriveEnum {
 }
 
 /// Derive `Message` on a struct
-// TODO(tarcieri): make sure tags are in the right order and digest is the last field
+// TODO(tarcieri): make sure digest is the last field
 struct DeriveStruct {
     /// Body of `Message::decode()` in-progress for a struct
     decode_body: TokenStream,
 
+    /// Computation of the message digest at the end of `Message::decode()`
+    digest_body: TokenStream,
+
     /// Instantiation of the struct at the end of `Message::decode()`
     inst_body: TokenStream,
 
@@ -161,17 +422,61 @@ struct DeriveStruct {
 
     /// Body of `Message::encoded_len()` in-progress for a struct
     encoded_len_body: TokenStream,
+
+    /// Name and binding of the `#[field(unknown)]` member, if present
+    unknown: Option<(Ident, Ident)>,
+
+    /// Tag of the last field encoded so far
+    last_tag: Option<u64>,
+
+    /// Tags and names of the required fields
+    required: Vec<(u64, Ident)>,
+
+    /// Path to the `#[veriform(validate = "...")]` function, if present
+    validator: Option<Path>,
+
+    /// Type parameters of the struct
+    type_params: Vec<Ident>,
+
+    /// Lifetime parameter of the struct, if it borrows from its input
+    lifetime: Option<Lifetime>,
+
+    /// Bounds on member types which mention the struct's type parameters,
+    /// added to the `where` clause of the generated impl
+    bounds: Vec<WherePredicate>,
 }
 
 impl DeriveStruct {
-    pub fn derive(s: Structure<'_>, data: &DataStruct) -> TokenStream {
+    pub fn derive(s: Structure<'_>, data: &DataStruct, attrs: container::Attrs) -> TokenStream {
         assert_eq!(s.variants().len(), 1, "expected one variant");
 
+        if s.ast().generics.lifetimes().count() > 1 {
+            panic!("structs can have at most one lifetime parameter");
+        }
+
         let mut state = Self {
             decode_body: TokenStream::new(),
+            digest_body: TokenStream::new(),
             inst_body: TokenStream::new(),
             encode_body: TokenStream::new(),
             encoded_len_body: quote!(0),
+            unknown: None,
+            last_tag: None,
+            required: Vec::new(),
+            validator: attrs.validator().cloned(),
+            type_params: s
+                .ast()
+                .generics
+                .type_params()
+                .map(|param| param.ident.clone())
+                .collect(),
+            lifetime: s
+                .ast()
+                .generics
+                .lifetimes()
+                .next()
+                .map(|def| def.lifetime.clone()),
+            bounds: Vec::new(),
         };
 
         let variant = &s.variants()[0];
@@ -185,7 +490,28 @@ impl DeriveStruct {
             );
         }
 
+        // Find the `#[field(unknown)]` member first, as unknown fields are
+        // captured and encoded around every known field
+        for (binding_info, field) in bindings.iter().zip(&data.fields) {
+            if field.attrs.iter().any(field::is_unknown) {
+                if state.unknown.is_some() {
+                    panic!("multiple `#[field(unknown)]` members");
+                }
+
+                state.derive_unknown(field, &binding_info.binding);
+            }
+        }
+
         for (binding_info, field) in bindings.iter().zip(&data.fields) {
+            if field.attrs.iter().any(field::is_unknown) {
+                continue;
+            }
+
+            if let Some(skip) = field.attrs.iter().find_map(field::Skip::parse) {
+                state.derive_skip(field, skip);
+                continue;
+            }
+
             for attr in &field.attrs {
                 let attr_segments = &attr.path.segments;
 
@@ -202,61 +528,336 @@ impl DeriveStruct {
                     _ => (), // ignore other attributes
                 }
             }
+
+            // Tags are never assigned implicitly (e.g. by position), so
+            // reordering members can't silently change the wire format
+            if !field
+                .attrs
+                .iter()
+                .any(|attr| attr.path.is_ident("field") || attr.path.is_ident("digest"))
+            {
+                panic!(
+                    "member `{}` has no `#[field(tag = ..., wire_type = ...)]` attribute",
+                    parse_field_name(field)
+                );
+            }
+        }
+
+        let pattern = variant.pat();
+        state.finish(s, pattern)
+    }
+
+    /// Derive handling for a `#[field(unknown)]` member of a struct
+    fn derive_unknown(&mut self, field: &Field, binding: &Ident) {
+        let name = parse_field_name(field);
+
+        quote!(let mut #name = Default::default();).to_tokens(&mut self.decode_body);
+        quote!(#name,).to_tokens(&mut self.inst_body);
+        quote!(+ #binding.encoded_len()).to_tokens(&mut self.encoded_len_body);
+
+        self.unknown = Some((name.clone(), binding.clone()));
+    }
+
+    /// Derive handling for a `#[field(skip)]` member of a struct, which is
+    /// only filled in with its default value when decoding
+    fn derive_skip(&mut self, field: &Field, skip: darling::Result<field::Skip>) {
+        let name = parse_field_name(field);
+
+        let skip = skip.unwrap_or_else(|e| {
+            panic!("invalid skipped member `{}`: {}", name, e);
+        });
+
+        if field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("field") || attr.path.is_ident("digest"))
+            .count()
+            > 1
+        {
+            panic!(
+                "invalid skipped member `{}`: skipped members can't have other attributes",
+                name
+            );
         }
 
-        state.finish(&s, variant.pat())
+        if skip.is_default() && field::mentions_type_param(&field.ty, &self.type_params) {
+            let ty = &field.ty;
+            self.bounds.push(parse_quote!(#ty: Default));
+        }
+
+        let default_value = skip.default_value();
+        quote!(#name: #default_value,).to_tokens(&mut self.inst_body);
     }
 
     /// Derive handling for a particular `#[field(...)]`
     fn derive_field(&mut self, field: &Field, binding: &Ident) {
         let name = parse_field_name(field);
 
-        let attrs = field::Attrs::from_field(field).unwrap_or_else(|e| {
-            panic!("error parsing field attributes: {}", e);
+        let attrs = field::Attrs::from_field(field)
+            .and_then(field::Attrs::validate)
+            .unwrap_or_else(|e| {
+                panic!("error parsing field attributes: {}", e);
+            });
+
+        field::WireType::check_type(attrs.wire_type(), &field.ty).unwrap_or_else(|e| {
+            panic!("invalid field `{}`: {}", name, e);
         });
 
-        self.derive_decode_field(name, &attrs);
+        // Fields are decoded and encoded in the order they're declared, which
+        // must be the canonical (ascending) tag order
+        if let Some(last_tag) = self.last_tag {
+            if attrs.tag() == last_tag {
+                panic!("duplicate tag {} on field `{}`", last_tag, name);
+            }
+
+            if attrs.tag() < last_tag {
+                panic!(
+                    "field tags must be in ascending order: {} follows {}",
+                    attrs.tag(),
+                    last_tag
+                );
+            }
+        }
+
+        if field::mentions_type_param(&field.ty, &self.type_params) {
+            self.derive_bounds(name, &attrs, &field.ty);
+        }
+
+        self.derive_decode_field(name, &attrs, &field.ty);
 
         let inst_field = quote!(#name,);
         inst_field.to_tokens(&mut self.inst_body);
 
-        let enc_field = encode_field(binding, &attrs);
-        let enc_field_with_semicolon = quote!(#enc_field;);
-        enc_field_with_semicolon.to_tokens(&mut self.encode_body);
+        self.derive_encode_unknown(Some(attrs.tag()));
+
+        let (enc_field, enc_field_len) = match field::option_inner(&field.ty) {
+            Some(ty) => {
+                let enc_field = encode_field(name, binding, &attrs, Some(ty));
+                let enc_field_len = encoded_len_for_field(binding, &attrs, Some(ty));
+
+                (
+                    quote! {
+                        if let Some(#binding) = #binding {
+                            #enc_field;
+                        }
+                    },
+                    quote! {
+                        #binding.as_ref().map(|#binding| #enc_field_len).unwrap_or(0)
+                    },
+                )
+            }
+            None if attrs.skip_default() => {
+                let enc_field = encode_field(name, binding, &attrs, Some(&field.ty));
+                let enc_field_len = encoded_len_for_field(binding, &attrs, Some(&field.ty));
+                let default = attrs.default_value();
+
+                (
+                    quote! {
+                        if !veriform::derive_helpers::is_default(#binding, &#default) {
+                            #enc_field;
+                        }
+                    },
+                    quote! {
+                        if veriform::derive_helpers::is_default(#binding, &#default) {
+                            0
+                        } else {
+                            #enc_field_len
+                        }
+                    },
+                )
+            }
+            None => {
+                let enc_field = encode_field(name, binding, &attrs, Some(&field.ty));
+                let enc_field_len = encoded_len_for_field(binding, &attrs, Some(&field.ty));
+                (quote!(#enc_field;), enc_field_len)
+            }
+        };
+
+        enc_field.to_tokens(&mut self.encode_body);
 
-        let enc_field_len = encoded_len_for_field(binding, &attrs);
         let enc_field_len_with_plus = quote!(+ #enc_field_len);
         enc_field_len_with_plus.to_tokens(&mut self.encoded_len_body);
+
+        self.last_tag = Some(attrs.tag());
     }
 
-    /// Derive a match arm of an struct `decode` method
-    fn derive_decode_field(&mut self, name: &Ident, attrs: &field::Attrs) {
-        let tag = attrs.tag();
-        let wire_type = attrs.wire_type();
+    /// Derive the bounds needed by a field whose type mentions the struct's
+    /// type parameters, e.g. `T: Message` for a `message` field of type `T`
+    /// or `Option<T>`, or a `sequence` field of type `Vec<T>`.
+    ///
+    /// Bounds are only added for the traits each field actually needs, so
+    /// e.g. `Default` is only required of fields defaulting to it.
+    fn derive_bounds(&mut self, name: &Ident, attrs: &field::Attrs, ty: &Type) {
+        let ty = field::option_inner(ty).unwrap_or(ty);
+
+        match (
+            attrs.skip_default(),
+            attrs.has_default() && attrs.is_default_trait(),
+        ) {
+            (true, true) => self.bounds.push(parse_quote!(#ty: Default + PartialEq)),
+            (true, false) => self.bounds.push(parse_quote!(#ty: PartialEq)),
+            (false, true) => self.bounds.push(parse_quote!(#ty: Default)),
+            (false, false) => (),
+        }
 
-        match wire_type.rust_type() {
-            Some(ty) => {
-                if wire_type.is_ref_type() {
-                    quote! { let #name: #ty = decoder.decode_ref(#tag, &mut input)?; }
-                } else {
-                    quote! { let #name: #ty = decoder.decode(#tag, &mut input)?; }
+        let bound = match attrs.wire_type() {
+            WireType::Message => match &self.lifetime {
+                Some(lifetime) if field::is_message_ref(ty) => {
+                    parse_quote!(#ty: veriform::MessageRef<#lifetime>)
+                }
+                _ => parse_quote!(#ty: veriform::Message),
+            },
+            WireType::Sequence => {
+                let elem = field::first_type_arg(ty).filter(|elem| {
+                    field::mentions_type_param(elem, &self.type_params)
+                        && attrs.element(ty) == field::Element::Message
+                });
+
+                match elem {
+                    Some(elem) => parse_quote!(#elem: veriform::Message),
+                    None => panic!(
+                        "field `{}` has a generic type, but only generic messages \
+                         are supported as the elements of sequences",
+                        name
+                    ),
                 }
             }
-            None => {
-                if wire_type.is_message() {
-                    quote! { let #name = decoder.decode(#tag, &mut input)?; }
-                } else if wire_type.is_sequence() {
-                    // TODO(tarcieri): hoist more of this into a `derive_helper` function?
-                    quote! {
-                        let #name = veriform::derive_helpers::decode_message_seq(
-                            decoder,
-                            #tag,
-                            &mut input
-                        )?;
-                    }
+            _ => panic!(
+                "field `{}` has a generic type, but only `message` and \
+                 `sequence` fields can be generic",
+                name
+            ),
+        };
+
+        self.bounds.push(bound);
+    }
+
+    /// Derive encoding the unknown fields between the last field encoded and
+    /// the given tag (or all remaining unknown fields if `None`)
+    fn derive_encode_unknown(&mut self, next_tag: Option<u64>) {
+        let binding = match &self.unknown {
+            Some((_, binding)) => binding,
+            None => return,
+        };
+
+        let prev_tag = option_tokens(self.last_tag);
+        let next_tag = option_tokens(next_tag);
+
+        let encode_unknown = quote! {
+            veriform::derive_helpers::encode_unknown_fields(
+                encoder,
+                #binding,
+                #prev_tag,
+                #next_tag
+            )?;
+        };
+
+        encode_unknown.to_tokens(&mut self.encode_body);
+    }
+
+    /// Derive skipping (or capturing, if the struct has a `#[field(unknown)]`
+    /// member) the unknown fields preceding the given tag
+    fn derive_decode_unknown(&mut self, next_tag: Option<u64>) {
+        let next_tag = option_tokens(next_tag);
+
+        match &self.unknown {
+            Some((name, _)) => quote! {
+                veriform::derive_helpers::capture_unknown_fields(
+                    decoder,
+                    #next_tag,
+                    &mut input,
+                    &mut #name
+                )?;
+            },
+            None => quote! {
+                veriform::derive_helpers::skip_unknown_fields(decoder, #next_tag, &mut input)?;
+            },
+        }
+        .to_tokens(&mut self.decode_body);
+    }
+
+    /// Derive decoding a field in a struct `decode` method.
+    ///
+    /// Fields of type `Option<T>` are optional, `#[field(default)]` and
+    /// `#[field(skip_default)]` fields are decoded as their default value
+    /// when missing, and all others are required: missing required fields
+    /// are reported together once the rest of the message has been decoded.
+    fn derive_decode_field(&mut self, name: &Ident, attrs: &field::Attrs, ty: &Type) {
+        let tag_value = attrs.tag();
+        let tag = tag_tokens(tag_value);
+
+        // Skip unknown fields preceding this one
+        self.derive_decode_unknown(Some(tag_value));
+
+        let (ty, optional) = match field::option_inner(ty) {
+            Some(inner) => (inner, true),
+            None => (ty, false),
+        };
+
+        if optional && attrs.skip_default() {
+            panic!("`skip_default` can't be used with `Option` fields");
+        }
+
+        if optional && attrs.has_default() {
+            panic!("`default` can't be used with `Option` fields");
+        }
+
+        let default = attrs.default_value();
+        let mut decode_value = decode_value(attrs, ty);
+        let mut default_value = default.clone();
+
+        // Validators are called on each value as it's decoded, so errors
+        // carry the field's position
+        if let Some(validator) = attrs.validator() {
+            decode_value = quote! {{
+                let value = #decode_value;
+                veriform::derive_helpers::validate_field(decoder, &value, #validator)?;
+                value
+            }};
+
+            default_value = quote! {{
+                let value = #default;
+                veriform::derive_helpers::validate(&value, #validator)?;
+                value
+            }};
+        }
+
+        if attrs.skip_default() {
+            quote! {
+                let #name = if decoder.is_next_field(#tag, input)? {
+                    let value = #decode_value;
+                    veriform::derive_helpers::check_not_default(#tag, &value, &#default)?;
+                    value
                 } else {
-                    unreachable!();
-                }
+                    #default_value
+                };
+            }
+        } else if attrs.has_default() {
+            quote! {
+                let #name = if decoder.is_next_field(#tag, input)? {
+                    #decode_value
+                } else {
+                    #default_value
+                };
+            }
+        } else if optional {
+            quote! {
+                let #name = if decoder.is_next_field(#tag, input)? {
+                    Some(#decode_value)
+                } else {
+                    None
+                };
+            }
+        } else {
+            self.required.push((tag_value, name.clone()));
+
+            quote! {
+                let #name = if decoder.is_next_field(#tag, input)? {
+                    required.seen(#tag);
+                    Some(#decode_value)
+                } else {
+                    None
+                };
             }
         }
         .to_tokens(&mut self.decode_body);
@@ -272,7 +873,7 @@ impl DeriveStruct {
 
         // TODO(tarcieri): support additional algorithms?
         match attrs.alg() {
-            digest::Algorithm::Sha256 => self.derive_sha256_digest(&name),
+            digest::Algorithm::Sha256 => self.derive_sha256_digest(name),
         }
     }
 
@@ -283,24 +884,87 @@ impl DeriveStruct {
             decoder.fill_digest(&mut #name)?;
         };
 
-        fill_digest.to_tokens(&mut self.decode_body);
+        fill_digest.to_tokens(&mut self.digest_body);
 
         let inst_field = quote!(#name: Some(#name),);
         inst_field.to_tokens(&mut self.inst_body);
     }
 
+    /// Derive declaring the tracker for the struct's required fields, and
+    /// checking they were all present once the message has been decoded
+    fn derive_required(&self) -> (TokenStream, TokenStream) {
+        if self.required.is_empty() {
+            return (TokenStream::new(), TokenStream::new());
+        }
+
+        if self.required.len() > 64 {
+            panic!("structs can have at most 64 required fields");
+        }
+
+        let fields = self.required.iter().map(|(tag, name)| {
+            let tag = tag_tokens(*tag);
+            let name = name.to_string();
+            quote! {
+                veriform::decoder::RequiredField { tag: #tag, name: Some(#name) }
+            }
+        });
+
+        let decl = quote! {
+            const REQUIRED_FIELDS: &[veriform::decoder::RequiredField] = &[#(#fields),*];
+            let mut required = veriform::decoder::RequiredFields::new(REQUIRED_FIELDS);
+        };
+
+        let names = self.required.iter().map(|(_, name)| name);
+        let check = quote! {
+            required.check()?;
+            #(let #names = #names.unwrap();)*
+        };
+
+        (decl, check)
+    }
+
     /// Finish deriving a struct
-    fn finish(self, s: &Structure<'_>, pattern: TokenStream) -> TokenStream {
+    fn finish(mut self, mut s: Structure<'_>, pattern: TokenStream) -> TokenStream {
+        // Handle unknown fields following the last known one
+        self.derive_decode_unknown(None);
+        self.derive_encode_unknown(None);
+
+        let (required_decl, required_check) = self.derive_required();
         let decode_body = self.decode_body;
+        let digest_body = self.digest_body;
         let inst_body = self.inst_body;
         let encode_body = self.encode_body;
         let encoded_len_body = self.encoded_len_body;
+        let validate = validate_message(self.validator.as_ref());
+
+        // Only the bounds needed by each member are added (see
+        // `derive_bounds`), rather than bounding every type parameter
+        s.add_bounds(AddBounds::None);
+
+        for bound in self.bounds {
+            s.add_where_predicate(bound);
+        }
+
+        // Structs with a lifetime parameter borrow from the input they're
+        // decoded from, and so impl `MessageRef` rather than `Message`.
+        // The `encode` method's lifetime is named `'buffer` to avoid
+        // shadowing theirs.
+        let (message_trait, input_type) = match s.ast().generics.lifetimes().next() {
+            Some(def) => {
+                let lifetime = &def.lifetime;
+                (
+                    quote!(veriform::MessageRef<#lifetime>),
+                    quote!(&#lifetime [u8]),
+                )
+            }
+            None => (quote!(Message), quote!(&[u8])),
+        };
 
         s.gen_impl(quote! {
-            gen impl Message for @Self {
+            gen impl #message_trait for @Self {
                 fn decode<D>(
                     decoder: &mut veriform::decoder::Decoder<D>,
-                    mut input: &[u8]
+                    mut input: #input_type
                 ) -> Result<Self, veriform::Error>
                 where
                     D: veriform::digest::Digest,
@@ -308,22 +972,31 @@ impl DeriveStruct {
                     #[allow(unused_imports)]
                     use veriform::decoder::{Decode, DecodeRef};
 
+                    #required_decl
                     #decode_body
+                    #required_check
+                    #digest_body
 
-                    Ok(Self { #inst_body })
+                    let msg = Self { #inst_body };
+                    #validate
+                    Ok(msg)
                 }
 
-                fn encode<'a>(
+                fn encode<'buffer>(
                     &self,
-                    buffer: &'a mut [u8]
-                ) -> Result<&'a [u8], veriform::Error> {
-                    let mut encoder = veriform::Encoder::new(buffer);
-
-                    match self {
-                        #pattern => { #encode_body }
-                    }
-
-                    Ok(encoder.finish())
+                    buffer: &'buffer mut [u8]
+                ) -> Result<&'buffer [u8], veriform::Error> {
+                    veriform::derive_helpers::encode_message(
+                        buffer,
+                        || self.encoded_len(),
+                        |encoder| {
+                            match self {
+                                #pattern => { #encode_body }
+                            }
+
+                            Ok(())
+                        }
+                    )
                 }
 
                 fn encoded_len(&self) -> usize {
@@ -336,6 +1009,33 @@ impl DeriveStruct {
     }
 }
 
+/// Call the `#[veriform(validate = "...")]` function (if any) on a decoded
+/// message named `msg`
+fn validate_message(validator: Option<&Path>) -> TokenStream {
+    match validator {
+        Some(validator) => quote! {
+            veriform::derive_helpers::validate(&msg, #validator)?;
+        },
+        None => TokenStream::new(),
+    }
+}
+
+/// Convert a tag into tokens which construct a `Tag`
+fn tag_tokens(tag: u64) -> TokenStream {
+    quote!(veriform::field::Tag::new_const(#tag))
+}
+
+/// Convert an optional tag into tokens
+fn option_tokens(tag: Option<u64>) -> TokenStream {
+    match tag {
+        Some(tag) => {
+            let tag = tag_tokens(tag);
+            quote!(Some(#tag))
+        }
+        None => quote!(None),
+    }
+}
+
 /// Parse the name of a field
 fn parse_field_name(field: &Field) -> &Ident {
     field
@@ -344,42 +1044,230 @@ fn parse_field_name(field: &Field) -> &Ident {
         .unwrap_or_else(|| panic!("no name on struct field (e.g. tuple structs unsupported)"))
 }
 
-/// Encode a field of a message
-fn encode_field(binding: &Ident, attrs: &field::Attrs) -> TokenStream {
-    let tag = attrs.tag();
+/// Is this struct member decoded and encoded through the `Decodable` and
+/// `Encodable` traits, rather than the `Decoder` and `Encoder` method for its
+/// wire type?
+///
+/// This is the case for `uint64`, `sint64`, `bytes` and `string` fields
+/// whose Rust type can't be inferred (e.g. `#[veriform(transparent)]`
+/// newtypes), owned `message` fields, and `Vec` sequences of messages, whose
+/// elements may also be newtypes.
+fn is_value_type(attrs: &field::Attrs, ty: &Type) -> bool {
+    match attrs.wire_type() {
+        WireType::Bool => false,
+        WireType::UInt64 | WireType::SInt64 | WireType::Bytes | WireType::String => {
+            !attrs.is_bytes() && WireType::infer(ty).is_none()
+        }
+        WireType::Message => !field::is_raw_message(ty) && !field::is_message_ref(ty),
+        WireType::Sequence => {
+            field::is_alloc_owned(ty) && attrs.element(ty) == field::Element::Message
+        }
+    }
+}
+
+/// Decode the value of a field of a struct, as an expression
+fn decode_value(attrs: &field::Attrs, ty: &Type) -> TokenStream {
+    let tag = tag_tokens(attrs.tag());
+    let wire_type = attrs.wire_type();
+
+    if is_value_type(attrs, ty) {
+        return quote! {
+            <#ty as veriform::value::Decodable>::decode_field(decoder, #tag, &mut input)?
+        };
+    }
+
+    if let Some(rust_ty) = wire_type.rust_type() {
+        if !wire_type.is_ref_type() {
+            // Integers are decoded as the field's type, so narrower types
+            // like `u32` are range checked
+            let value_ty = match wire_type {
+                WireType::UInt64 | WireType::SInt64 => quote!(#ty),
+                _ => rust_ty,
+            };
+
+            return quote! {{
+                let value: #value_ty = decoder.decode(#tag, &mut input)?;
+                value
+            }};
+        }
+
+        // `bytes` and `string` fields are decoded as slices of the input if
+        // they're references, copied into `Vec<u8>` or `String` if they're
+        // `alloc` types, or otherwise copied into `heapless::Vec<u8, N>` or
+        // `heapless::String<N>` without allocating
+        if let Type::Reference(_) = ty {
+            return quote! {{
+                let value: #rust_ty = decoder.decode_ref(#tag, &mut input)?;
+                value
+            }};
+        }
+
+        // Fixed-size arrays (e.g. `[u8; N]`) are converted with `TryFrom`,
+        // so their length is checked
+        if attrs.is_bytes() || matches!(ty, Type::Array(_)) {
+            return quote! {
+                veriform::derive_helpers::decode_bytes_into(decoder, #tag, &mut input)?
+            };
+        }
+
+        if field::is_alloc_owned(ty) {
+            return quote! {
+                <#ty as veriform::value::Decodable>::decode_field(decoder, #tag, &mut input)?
+            };
+        }
+
+        let decode_into = if wire_type == WireType::Bytes {
+            quote!(decode_bytes_into_vec)
+        } else {
+            quote!(decode_string_into_string)
+        };
+
+        return quote! {
+            veriform::derive_helpers::#decode_into(decoder, #tag, &mut input)?
+        };
+    }
+
+    if wire_type.is_message() {
+        if field::is_raw_message(ty) {
+            return quote! { decoder.decode_message_raw(#tag, &mut input)? };
+        }
+
+        if field::is_message_ref(ty) {
+            return quote! { decoder.decode_message_ref(#tag, &mut input)? };
+        }
+
+        return quote! { decoder.decode(#tag, &mut input)? };
+    }
+
+    assert!(wire_type.is_sequence());
+
+    let decode_seq = match attrs.element(ty) {
+        field::Element::UInt64 => quote!(decode_uint64_seq),
+        field::Element::SInt64 => quote!(decode_sint64_seq),
+        field::Element::Bytes => quote!(decode_bytes_seq),
+        field::Element::String => quote!(decode_string_seq),
+        field::Element::Message => quote!(decode_message_seq),
+        field::Element::Map => return quote! { decoder.decode_map(#tag, &mut input)? },
+    };
+
+    quote! {
+        veriform::derive_helpers::#decode_seq(decoder, #tag, &mut input)?
+    }
+}
+
+/// Encode a field of a message, whose type is given for struct members and
+/// `sequence` enum variants (but not other enum variants, which are encoded
+/// according to their wire type alone).
+///
+/// Errors are annotated with the name of the struct member or enum variant.
+fn encode_field(
+    name: &Ident,
+    binding: &Ident,
+    attrs: &field::Attrs,
+    ty: Option<&Type>,
+) -> TokenStream {
+    let tag = tag_tokens(attrs.tag());
     let critical = attrs.is_critical();
+    let element = ty
+        .map(|ty| attrs.element(ty))
+        .unwrap_or(field::Element::Message);
 
-    match attrs.wire_type() {
+    if ty.map(field::is_raw_message) == Some(true) {
+        let call = quote! { encoder.raw_message(#tag, #critical, *#binding) };
+        return with_field_name(name, call);
+    }
+
+    if ty.map(|ty| is_value_type(attrs, ty)) == Some(true) {
+        let call = quote! {
+            veriform::value::Encodable::encode_field(#binding, encoder, #tag, #critical)
+        };
+        return with_field_name(name, call);
+    }
+
+    let call = match attrs.wire_type() {
         WireType::Bool => todo!(),
-        WireType::UInt64 => quote! { encoder.uint64(#tag, #critical, *#binding)? },
-        WireType::SInt64 => quote! { encoder.sint64(#tag, #critical, *#binding)? },
-        WireType::Bytes => quote! { encoder.bytes(#tag, #critical, #binding)? },
-        WireType::String => quote! { encoder.string(#tag, #critical, #binding)? },
-        WireType::Message => quote! { encoder.message(#tag, #critical, #binding)? },
-        WireType::Sequence => quote! {
-            // TODO(tarcieri): support other types of sequences besides messages
-            veriform::derive_helpers::encode_message_seq(&mut encoder, #tag, #critical, #binding)?;
+        WireType::UInt64 => quote! { encoder.uint64(#tag, #critical, (*#binding).into()) },
+        WireType::SInt64 => quote! { encoder.sint64(#tag, #critical, (*#binding).into()) },
+        WireType::Bytes if attrs.is_bytes() => {
+            quote! { encoder.bytes(#tag, #critical, AsRef::<[u8]>::as_ref(#binding)) }
+        }
+        WireType::Bytes => quote! { encoder.bytes(#tag, #critical, #binding) },
+        WireType::String => quote! { encoder.string(#tag, #critical, #binding) },
+        WireType::Message if ty.map(field::is_message_ref) == Some(true) => {
+            quote! { encoder.message_ref(#tag, #critical, #binding) }
+        }
+        WireType::Message => quote! { encoder.message(#tag, #critical, #binding) },
+        WireType::Sequence => match element {
+            field::Element::UInt64 => quote! { encoder.uint64_seq(#tag, #critical, #binding) },
+            field::Element::SInt64 => quote! { encoder.sint64_seq(#tag, #critical, #binding) },
+            field::Element::Bytes => quote! { encoder.bytes_seq(#tag, #critical, &#binding[..]) },
+            field::Element::String => {
+                quote! { encoder.string_seq(#tag, #critical, &#binding[..]) }
+            }
+            field::Element::Message => {
+                quote! { encoder.message_seq(#tag, #critical, #binding.iter()) }
+            }
+            field::Element::Map => quote! { encoder.map(#tag, #critical, #binding) },
         },
+    };
+
+    with_field_name(name, call)
+}
+
+/// Add the name of a struct member or enum variant to errors encoding it
+fn with_field_name(name: &Ident, call: TokenStream) -> TokenStream {
+    let name = name.to_string();
+
+    quote! {
+        #call.map_err(|err| veriform::derive_helpers::field_error(err, &#name))?
     }
 }
 
-/// Compute the encoded length of a field
-fn encoded_len_for_field(binding: &Ident, attrs: &field::Attrs) -> TokenStream {
-    let tag = attrs.tag();
+/// Compute the encoded length of a field (see [`encode_field`])
+fn encoded_len_for_field(binding: &Ident, attrs: &field::Attrs, ty: Option<&Type>) -> TokenStream {
+    let tag = tag_tokens(attrs.tag());
+    let element = ty
+        .map(|ty| attrs.element(ty))
+        .unwrap_or(field::Element::Message);
+
+    if ty.map(field::is_raw_message) == Some(true) {
+        return quote! { veriform::field::length::raw_message(#tag, *#binding) };
+    }
+
+    if ty.map(|ty| is_value_type(attrs, ty)) == Some(true) {
+        return quote! { veriform::value::Encodable::encoded_field_len(#binding, #tag) };
+    }
 
     match attrs.wire_type() {
         WireType::Bool => todo!(),
-        WireType::UInt64 => quote! { veriform::field::length::uint64(#tag, *#binding) },
-        WireType::SInt64 => quote! { veriform::field::length::sint64(#tag, *#binding) },
+        WireType::UInt64 => quote! { veriform::field::length::uint64(#tag, (*#binding).into()) },
+        WireType::SInt64 => quote! { veriform::field::length::sint64(#tag, (*#binding).into()) },
+        WireType::Bytes if attrs.is_bytes() => {
+            quote! { veriform::field::length::bytes(#tag, AsRef::<[u8]>::as_ref(#binding)) }
+        }
         WireType::Bytes => quote! { veriform::field::length::bytes(#tag, #binding) },
         WireType::String => quote! { veriform::field::length::string(#tag, #binding) },
+        WireType::Message if ty.map(field::is_message_ref) == Some(true) => {
+            quote! { veriform::field::length::message_ref(#tag, #binding) }
+        }
         WireType::Message => quote! { veriform::field::length::message(#tag, #binding) },
-        WireType::Sequence => quote! {
-            // TODO(tarcieri): support other types of sequences besides messages
-            veriform::field::length::message_seq(
-                #tag,
-                #binding.iter().map(|elem| elem as &dyn veriform::Message)
-            )
+        WireType::Sequence => match element {
+            field::Element::UInt64 => {
+                quote! { veriform::field::length::uint64_seq(#tag, #binding) }
+            }
+            field::Element::SInt64 => {
+                quote! { veriform::field::length::sint64_seq(#tag, #binding) }
+            }
+            field::Element::Bytes => {
+                quote! { veriform::field::length::bytes_seq(#tag, &#binding[..]) }
+            }
+            field::Element::String => {
+                quote! { veriform::field::length::string_seq(#tag, &#binding[..]) }
+            }
+            field::Element::Message => {
+                quote! { veriform::field::length::message_seq(#tag, #binding.iter()) }
+            }
+            field::Element::Map => quote! { veriform::field::length::map(#tag, #binding) },
         },
     }
 }
@@ -0,0 +1,330 @@
+//! Structure-aware fuzzing support using the [`arbitrary`][`::arbitrary`]
+//! crate.
+//!
+//! [`Arbitrary`] is implemented for [`Tag`] (producing tags up to
+//! [`Tag::MAX`]), [`WireType`], [`Header`], and [`Event`] (which borrows
+//! `bytes` from the fuzzer's input, so no owned equivalent is needed).
+//!
+//! When the `alloc` feature is also enabled, [`ArbitraryMessage`] generates
+//! messages which always encode successfully, so fuzzers can explore the
+//! decoder's deeper states rather than bouncing off its header parser.
+
+use crate::{
+    decoder::Event,
+    field::{Header, Tag, WireType},
+};
+use ::arbitrary::{Arbitrary, Result, Unstructured};
+
+#[cfg(feature = "alloc")]
+use crate::{
+    decoder::{Decode, Decoder},
+    error::{self, Error},
+    field,
+    message::{Element, Message},
+    Encoder,
+};
+#[cfg(feature = "alloc")]
+use alloc::{string::String, vec::Vec};
+#[cfg(feature = "alloc")]
+use digest::Digest;
+
+/// Maximum nesting depth of [`ArbitraryMessage`]s, leaving room for
+/// sequences within the decoder's default maximum depth
+#[cfg(feature = "alloc")]
+const MAX_DEPTH: usize = 6;
+
+impl<'a> Arbitrary<'a> for WireType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(WireType::from_unmasked(u.int_in_range(0..=7)?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Tag {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Tag::new_const(u.int_in_range(0..=Tag::MAX.to_u64())?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Header {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Header::new(u.arbitrary()?, u.arbitrary()?, u.arbitrary()?))
+    }
+}
+
+impl<'a> Arbitrary<'a> for Event<'a> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.int_in_range(0..=6)? {
+            0 => Event::FieldHeader(u.arbitrary()?),
+            1 => Event::Bool(u.arbitrary()?),
+            2 => Event::UInt64(u.arbitrary()?),
+            3 => Event::SInt64(u.arbitrary()?),
+            4 => Event::LengthDelimiter {
+                wire_type: dynamically_sized(u)?,
+                length: u.arbitrary()?,
+            },
+            5 => Event::ValueChunk {
+                wire_type: dynamically_sized(u)?,
+                bytes: u.arbitrary()?,
+                remaining: u.arbitrary()?,
+            },
+            _ => Event::SequenceHeader {
+                wire_type: u.arbitrary()?,
+                length: u.arbitrary()?,
+            },
+        })
+    }
+}
+
+/// Generate an arbitrary dynamically sized wire type
+fn dynamically_sized(u: &mut Unstructured<'_>) -> Result<WireType> {
+    Ok(*u.choose(&[
+        WireType::Bytes,
+        WireType::String,
+        WireType::Message,
+        WireType::Sequence,
+    ])?)
+}
+
+/// Schemaless message with arbitrary fields, which implements [`Message`]
+/// so it can be encoded and decoded like any other.
+///
+/// Arbitrary messages are always valid: their fields are in tag order,
+/// strings are ASCII, and messages and sequences are nested within the
+/// limits the [`Decoder`] supports. Decoding the encoding of an arbitrary
+/// message returns an identical message.
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ArbitraryMessage {
+    /// Fields of this message, in tag order
+    pub fields: Vec<Field>,
+}
+
+/// Field of an [`ArbitraryMessage`]
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Field {
+    /// Tag which identifies the field
+    pub tag: Tag,
+
+    /// Is this field critical?
+    pub critical: bool,
+
+    /// Value of the field
+    pub value: Value,
+}
+
+/// Value of a [`Field`]
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Value {
+    /// Boolean
+    Bool(bool),
+
+    /// Unsigned 64-bit integer
+    UInt64(u64),
+
+    /// Signed 64-bit integer
+    SInt64(i64),
+
+    /// Byte string
+    Bytes(Vec<u8>),
+
+    /// ASCII string
+    String(String),
+
+    /// Nested message
+    Message(ArbitraryMessage),
+
+    /// Sequence of unsigned 64-bit integers
+    UInt64Seq(Vec<u64>),
+
+    /// Sequence of signed 64-bit integers
+    SInt64Seq(Vec<i64>),
+
+    /// Sequence of messages
+    MessageSeq(Vec<ArbitraryMessage>),
+}
+
+#[cfg(feature = "alloc")]
+impl ArbitraryMessage {
+    /// Generate a message nested at the given depth, which may only contain
+    /// sequences if `sequences` is set (sequences can't be nested within
+    /// messages in sequences)
+    fn generate(u: &mut Unstructured<'_>, depth: usize, sequences: bool) -> Result<Self> {
+        let mut fields = Vec::new();
+        let mut next_tag: u64 = u.int_in_range(0..=0xFFFF)?;
+
+        while let Ok(tag) = Tag::new(next_tag) {
+            if !u.arbitrary()? {
+                break;
+            }
+
+            fields.push(Field {
+                tag,
+                critical: u.arbitrary()?,
+                value: Value::generate(u, depth, sequences)?,
+            });
+
+            next_tag = next_tag.saturating_add(u.int_in_range(1..=0x100)?);
+        }
+
+        Ok(Self { fields })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Value {
+    /// Generate a value in a message nested at the given depth
+    fn generate(u: &mut Unstructured<'_>, depth: usize, sequences: bool) -> Result<Self> {
+        let nested = depth < MAX_DEPTH;
+        let max_kind = match (nested, sequences) {
+            (true, true) => 8,
+            (true, false) => 5,
+            (false, _) => 4,
+        };
+
+        Ok(match u.int_in_range(0..=max_kind)? {
+            0 => Value::Bool(u.arbitrary()?),
+            1 => Value::UInt64(u.arbitrary()?),
+            2 => Value::SInt64(u.arbitrary()?),
+            3 => Value::Bytes(u.arbitrary()?),
+            4 => {
+                let bytes: Vec<u8> = u.arbitrary()?;
+                Value::String(bytes.iter().map(|&b| char::from(b & 0x7F)).collect())
+            }
+            5 => Value::Message(ArbitraryMessage::generate(u, depth + 1, sequences)?),
+            6 => Value::UInt64Seq(u.arbitrary()?),
+            7 => Value::SInt64Seq(u.arbitrary()?),
+            _ => {
+                let mut messages = Vec::new();
+
+                while u.arbitrary()? {
+                    messages.push(ArbitraryMessage::generate(u, depth + 1, false)?);
+                }
+
+                Value::MessageSeq(messages)
+            }
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<'a> Arbitrary<'a> for ArbitraryMessage {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Self::generate(u, 1, true)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Message for ArbitraryMessage {
+    fn decode<D>(decoder: &mut Decoder<D>, mut input: &[u8]) -> core::result::Result<Self, Error>
+    where
+        D: Digest,
+    {
+        let mut fields = Vec::new();
+
+        while let Some(header) = decoder.peek_header(input)? {
+            let tag = header.tag;
+
+            let value = match header.wire_type {
+                WireType::False | WireType::True => {
+                    decoder.decode_header(&mut input)?;
+                    decoder.skip(&header, &mut input)?;
+                    Value::Bool(header.wire_type == WireType::True)
+                }
+                WireType::UInt64 => Value::UInt64(decoder.decode(tag, &mut input)?),
+                WireType::SInt64 => Value::SInt64(decoder.decode(tag, &mut input)?),
+                WireType::Bytes => Value::Bytes(decoder.decode_bytes(tag, &mut input)?.into()),
+                WireType::String => Value::String(decoder.decode_string(tag, &mut input)?.into()),
+                WireType::Message => Value::Message(decoder.decode(tag, &mut input)?),
+                WireType::Sequence => {
+                    let mut seq_header = &input[header.encoded_len()..];
+                    let wire_type = WireType::from_unmasked(vint64::decode(&mut seq_header)?);
+
+                    match wire_type {
+                        WireType::UInt64 => {
+                            Value::UInt64Seq(decoder.decode_uint64_seq(tag, &mut input)?)
+                        }
+                        WireType::SInt64 => {
+                            Value::SInt64Seq(decoder.decode_sint64_seq(tag, &mut input)?)
+                        }
+                        WireType::Message => {
+                            Value::MessageSeq(decoder.decode_message_seq(tag, &mut input)?)
+                        }
+                        _ => {
+                            return Err(error::Kind::Decode {
+                                element: Element::SequenceHeader,
+                                wire_type,
+                            }
+                            .into())
+                        }
+                    }
+                }
+            };
+
+            fields.push(Field {
+                tag,
+                critical: header.critical,
+                value,
+            });
+        }
+
+        Ok(Self { fields })
+    }
+
+    fn encode<'a>(&self, buffer: &'a mut [u8]) -> core::result::Result<&'a [u8], Error> {
+        let mut encoder = Encoder::new(buffer);
+
+        for field in &self.fields {
+            let (tag, critical) = (field.tag, field.critical);
+
+            match &field.value {
+                Value::Bool(value) => encoder.bool(tag, critical, *value)?,
+                Value::UInt64(value) => encoder.uint64(tag, critical, *value)?,
+                Value::SInt64(value) => encoder.sint64(tag, critical, *value)?,
+                Value::Bytes(bytes) => encoder.bytes(tag, critical, bytes)?,
+                Value::String(string) => encoder.string(tag, critical, string)?,
+                Value::Message(message) => encoder.message(tag, critical, message)?,
+                Value::UInt64Seq(values) => encoder.uint64_seq(tag, critical, values)?,
+                Value::SInt64Seq(values) => encoder.sint64_seq(tag, critical, values)?,
+                Value::MessageSeq(messages) => {
+                    encoder.message_seq(tag, critical, messages.iter())?
+                }
+            }
+        }
+
+        Ok(encoder.finish())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.fields
+            .iter()
+            .map(|field| {
+                let tag = field.tag;
+
+                match &field.value {
+                    Value::Bool(value) => {
+                        let wire_type = if *value {
+                            WireType::True
+                        } else {
+                            WireType::False
+                        };
+
+                        Header::new(tag, field.critical, wire_type).encoded_len()
+                    }
+                    Value::UInt64(value) => field::length::uint64(tag, *value),
+                    Value::SInt64(value) => field::length::sint64(tag, *value),
+                    Value::Bytes(bytes) => field::length::bytes(tag, bytes),
+                    Value::String(string) => field::length::string(tag, string),
+                    Value::Message(message) => field::length::message(tag, message),
+                    Value::UInt64Seq(values) => field::length::uint64_seq(tag, values),
+                    Value::SInt64Seq(values) => field::length::sint64_seq(tag, values),
+                    Value::MessageSeq(messages) => field::length::message_seq(tag, messages.iter()),
+                }
+            })
+            .sum()
+    }
+}
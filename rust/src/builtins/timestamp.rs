@@ -13,21 +13,28 @@ pub use tai64::TAI64N as Timestamp;
 
 use crate::{
     decoder::{Decode, Decoder},
+    derive_helpers,
     digest::Digest,
     error::{self, Error},
-    field, Encoder, Message,
+    field, Message,
 };
 use core::convert::TryInto;
 
+/// Tag of the `secs` field
+const SECS: field::Tag = field::Tag::new_const(0);
+
+/// Tag of the `nanos` field
+const NANOS: field::Tag = field::Tag::new_const(1);
+
 impl Message for Timestamp {
     fn decode<D>(decoder: &mut Decoder<D>, mut input: &[u8]) -> Result<Self, Error>
     where
         D: Digest,
     {
-        let secs: u64 = decoder.decode(0, &mut input)?;
-        let nanos: u64 = decoder.decode(1, &mut input)?;
+        let secs: u64 = decoder.decode(SECS, &mut input)?;
+        let nanos: u64 = decoder.decode(NANOS, &mut input)?;
 
-        if nanos > core::u32::MAX as u64 {
+        if nanos > u32::MAX as u64 {
             return Err(error::Kind::Length.into());
         }
 
@@ -38,16 +45,21 @@ impl Message for Timestamp {
     }
 
     fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
-        let mut encoder = Encoder::new(buffer);
         let (secs, nanos) = tai64_to_ints(self);
-        encoder.uint64(0, true, secs)?;
-        encoder.uint64(1, false, nanos as u64)?;
-        Ok(encoder.finish())
+
+        derive_helpers::encode_message(
+            buffer,
+            || self.encoded_len(),
+            |encoder| {
+                encoder.uint64(SECS, true, secs)?;
+                encoder.uint64(NANOS, false, nanos as u64)
+            },
+        )
     }
 
     fn encoded_len(&self) -> usize {
         let (secs, nanos) = tai64_to_ints(self);
-        field::length::uint64(0, secs) + field::length::uint64(1, nanos as u64)
+        field::length::uint64(SECS, secs) + field::length::uint64(NANOS, nanos as u64)
     }
 }
 
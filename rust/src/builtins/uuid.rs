@@ -18,12 +18,15 @@ use crate::{
 };
 use core::convert::TryInto;
 
+/// Tag of the `value` field
+const VALUE: field::Tag = field::Tag::new_const(0);
+
 impl Message for Uuid {
     fn decode<D>(decoder: &mut Decoder<D>, mut input: &[u8]) -> Result<Self, Error>
     where
         D: Digest,
     {
-        let bytes: &[u8] = decoder.decode_ref(0, &mut input)?;
+        let bytes: &[u8] = decoder.decode_ref(VALUE, &mut input)?;
 
         bytes
             .try_into()
@@ -33,11 +36,11 @@ impl Message for Uuid {
 
     fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
         let mut encoder = Encoder::new(buffer);
-        encoder.bytes(0, true, self.as_bytes())?;
+        encoder.bytes(VALUE, true, self.as_bytes())?;
         Ok(encoder.finish())
     }
 
     fn encoded_len(&self) -> usize {
-        field::length::bytes(0, self.as_bytes())
+        field::length::bytes(VALUE, self.as_bytes())
     }
 }
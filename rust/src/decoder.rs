@@ -1,29 +1,118 @@
 //! Veriform decoder
 
 pub(crate) mod message;
+pub mod raw;
 pub mod sequence;
 
+#[cfg(feature = "async")]
+mod async_reader;
+#[cfg(feature = "buf")]
+mod buf_decoder;
 mod decodable;
+mod duplicates;
 mod event;
+mod fuel;
+mod incremental;
+mod limits;
+#[cfg(feature = "alloc")]
+mod lint;
+mod observer;
+#[cfg(feature = "std")]
+mod reader;
+mod required;
+mod stats;
 mod traits;
 mod vint64;
+mod visitor;
 
 #[cfg(feature = "log")]
 #[macro_use]
 mod trace;
 
-pub use self::traits::{Decode, DecodeRef, DecodeSeq};
+pub use self::{
+    duplicates::DuplicatePolicy,
+    event::{AsEvent, Event, EventKind},
+    incremental::{Incremental, Status},
+    limits::Limits,
+    message::{Events, HashEvents, Hasher, Position, RedactedField, ShapeHasher, StateDescription},
+    observer::Observer,
+    required::{MissingFields, RequiredField, RequiredFields, MAX_REQUIRED_FIELDS},
+    stats::DecodeStats,
+    traits::{Decode, DecodeRef, DecodeSeq},
+    visitor::Visitor,
+};
+
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub use self::{
+    event::EventBuf,
+    lint::{lint, Issue},
+    message::ResumableDigest,
+};
+
+#[cfg(feature = "resumable-sha2")]
+#[cfg_attr(docsrs, doc(cfg(feature = "resumable-sha2")))]
+pub use self::message::ResumableSha256;
+
+#[cfg(feature = "tracing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+pub use self::observer::TracingObserver;
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use self::reader::{ReadError, Reader, DEFAULT_CAPACITY};
+
+#[cfg(feature = "async")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async")))]
+pub use self::async_reader::{AsyncRead, AsyncReader};
+
+#[cfg(feature = "async-futures")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async-futures")))]
+pub use self::async_reader::FuturesRead;
 
-pub(crate) use self::{decodable::Decodable, event::Event};
+#[cfg(feature = "async-tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
+pub use self::async_reader::TokioRead;
 
+#[cfg(feature = "buf")]
+#[cfg_attr(docsrs, doc(cfg(feature = "buf")))]
+pub use self::buf_decoder::BufDecoder;
+
+#[cfg(feature = "mac")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mac")))]
+pub use self::message::{KeyedHasher, MacOutput};
+
+#[cfg(feature = "merkle")]
+#[cfg_attr(docsrs, doc(cfg(feature = "merkle")))]
+pub use self::message::{Proof, TreeHasher};
+
+pub(crate) use self::decodable::Decodable;
+
+use self::fuel::Fuel;
 use crate::{
     error::{self, Error},
-    field::{Tag, WireType},
+    field::{Header, Path, Tag, WireType},
+    message::{Element, MessageRef, RawMessage},
     verihash::DigestOutput,
     Message,
 };
+use core::convert::TryFrom;
 use digest::Digest;
-use heapless::consts::U16;
+use heapless::consts::{U16, U2};
+
+#[cfg(feature = "alloc")]
+use crate::{map, value};
+#[cfg(feature = "alloc")]
+use alloc::{
+    boxed::Box,
+    collections::{btree_map, BTreeMap},
+    string::String,
+    vec::Vec,
+};
+
+/// Default maximum nesting depth of messages and sequences, which is also
+/// the capacity of the [`Decoder`]'s stack
+pub const DEFAULT_MAX_DEPTH: usize = 16;
 
 /// Veriform decoder.
 ///
@@ -35,9 +124,30 @@ pub struct Decoder<D: Digest> {
     /// Stack of message decoders (max nesting depth 16)
     stack: heapless::Vec<message::Decoder<D>, U16>,
 
-    /// Sequence decoder if we're presently decoding a sequence
-    // TODO(tarcieri): support nested sequences?
-    seq_decoder: Option<sequence::Decoder<D>>,
+    /// Sequence decoders if we're presently decoding a sequence: the
+    /// outermost sequence, followed by a sequence nested within it (if any)
+    seq_stack: heapless::Vec<sequence::Decoder<D>, U2>,
+
+    /// Depth of the message stack when the sequence decoders were pushed
+    seq_depth: usize,
+
+    /// Maximum nesting depth of messages and sequences
+    max_depth: usize,
+
+    /// Limits on the lengths of values in messages and sequences
+    limits: Limits,
+
+    /// Reject nested messages whose decoders leave trailing data?
+    canonical: bool,
+
+    /// Policy for handling repeated fields
+    duplicates: DuplicatePolicy,
+
+    /// Policy for handling repeated keys in maps
+    key_duplicates: DuplicatePolicy,
+
+    /// Record [`DecodeStats`]?
+    stats: bool,
 }
 
 impl<D> Decoder<D>
@@ -50,266 +160,3417 @@ where
         stack.push(message::Decoder::new()).unwrap();
         Decoder {
             stack,
-            seq_decoder: None,
+            seq_stack: heapless::Vec::new(),
+            seq_depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            limits: Limits::default(),
+            canonical: true,
+            duplicates: DuplicatePolicy::default(),
+            key_duplicates: DuplicatePolicy::default(),
+            stats: false,
         }
     }
 
-    /// Fill the provided slice with the digest of the message if it fits
-    // TODO(tarcieri): find a better way to handle generic digest sizes
-    pub fn fill_digest(&mut self, output: &mut [u8]) -> Result<(), Error> {
-        let digest = self
-            .peek()
-            .compute_digest()?
-            .ok_or_else(|| error::Kind::Hashing)?;
+    /// Set the maximum nesting depth of messages and sequences, counting the
+    /// outermost message as depth 1 and each nested message or sequence as
+    /// one level deeper than the message or sequence containing it.
+    ///
+    /// Anything nested deeper is rejected with [`error::Kind::NestingDepth`]
+    /// before its body is decoded. The default is [`DEFAULT_MAX_DEPTH`],
+    /// which is also the largest supported depth: greater values are clamped
+    /// to it.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth.min(DEFAULT_MAX_DEPTH);
+        self
+    }
 
-        if digest.len() != output.len() {
-            return Err(error::Kind::Hashing)?;
+    /// Set the limits on the lengths of values, which apply to the message
+    /// being decoded and all messages and sequences nested within it.
+    ///
+    /// Length delimiters exceeding these limits are rejected with
+    /// [`error::Kind::TooLong`] before the value they delimit is decoded.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+
+        for decoder in self.stack.iter_mut() {
+            decoder.set_limits(limits);
         }
 
-        output.copy_from_slice(&digest);
-        Ok(())
+        self
     }
 
-    /// Get the depth of the pushdown stack
-    #[cfg(feature = "log")]
-    pub(crate) fn depth(&self) -> usize {
-        self.stack.len()
-    }
+    /// Set the policy for handling repeated fields, i.e. consecutive fields
+    /// with the same tag, in the message being decoded and all messages
+    /// nested within it.
+    ///
+    /// The policy is applied when fields are decoded by tag (e.g. with
+    /// [`Decode::decode`]). Fields returned by [`Decoder::decode_header`]
+    /// are left for the caller to handle. Repeats which are skipped or
+    /// overwritten are still included in the message's digest.
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+
+        for decoder in self.stack.iter_mut() {
+            decoder.set_allow_duplicates(policy != DuplicatePolicy::Error);
+        }
 
-    /// Push a new message decoder down onto the stack
-    fn push(&mut self) -> Result<(), Error> {
-        self.stack
-            .push(message::Decoder::new())
-            .map_err(|_| error::Kind::NestingDepth.into())
+        self
     }
 
-    /// Pop the message decoder from the stack when we've finished a message.
+    /// Set the policy for handling maps containing more than one entry with
+    /// the same key (see [`Decoder::decode_map`]).
     ///
-    /// Returns a digest of the nested message if message hashing is enabled.
-    ///
-    /// Panics if the decoder stack underflows.
-    // TODO(tarcieri): panic-free higher-level API, possibly RAII-based?
-    fn pop(&mut self) -> Option<DigestOutput<D>> {
-        self.stack.pop().unwrap().compute_digest().unwrap()
+    /// Keys are compared with their [`Ord`] impl, so keys with different
+    /// encodings can still be duplicates. When the policy is
+    /// [`DuplicatePolicy::Last`] the value is overwritten, but the key of
+    /// the first entry is kept.
+    pub fn with_duplicate_key_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.key_duplicates = policy;
+        self
     }
 
-    /// Peek at the message decoder on the top of the stack
-    fn peek(&mut self) -> &mut message::Decoder<D> {
-        self.stack.last_mut().unwrap()
+    /// Record [`DecodeStats`] about the message being decoded and all
+    /// messages and sequences nested within it, which can be retrieved with
+    /// [`Decoder::stats`].
+    pub fn with_stats(mut self) -> Self {
+        self.stats = true;
+
+        for (depth, decoder) in (1..).zip(self.stack.iter_mut()) {
+            decoder.enable_stats(depth);
+        }
+
+        self
     }
 
-    /// Push a sequence decoder
-    // TODO(tarcieri): support nested sequences?
-    fn push_seq(&mut self, wire_type: WireType, length: usize) -> Result<(), Error> {
-        if self.seq_decoder.is_none() {
-            self.seq_decoder = Some(sequence::Decoder::new(wire_type, length));
-            Ok(())
-        } else {
-            Err(error::Kind::NestedSequence.into())
+    /// Get the statistics recorded so far, if enabled with
+    /// [`Decoder::with_stats`]
+    pub fn stats(&self) -> Option<DecodeStats> {
+        // Merge the statistics of any messages still being decoded
+        let mut levels = self.stack.iter().rev();
+        let mut stats = levels.next().unwrap().stats()?;
+
+        for decoder in levels {
+            let mut parent = decoder.stats()?;
+            parent.merge(&stats);
+            stats = parent;
         }
+
+        Some(stats)
     }
 
-    /// Pop the sequence decoder.
+    /// Limit the amount of work done decoding the message and everything
+    /// nested within it to the given amount of fuel, bounding the time
+    /// spent decoding untrusted input.
     ///
-    /// Panics if the decoder stack underflows.
-    // TODO(tarcieri): panic-free higher-level API, possibly RAII-based?
-    fn pop_seq(&mut self) -> Option<DigestOutput<D>> {
-        self.seq_decoder.take().unwrap().compute_digest().unwrap()
+    /// Each event decoded (e.g. a field header, value, length delimiter or
+    /// chunk of a value), including those of skipped fields and sequence
+    /// elements, consumes one unit of fuel. Decoding fails with
+    /// [`error::Kind::FuelExhausted`] once it runs out. The amount consumed
+    /// can be retrieved with [`Decoder::fuel_consumed`], e.g. to calibrate
+    /// limits.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.stack[0].set_fuel(Some(Fuel::new(fuel)));
+        self
     }
 
-    /// Peek at the sequence decoder.
-    fn peek_seq(&mut self) -> &mut sequence::Decoder<D> {
-        self.seq_decoder.as_mut().unwrap()
+    /// Get the amount of fuel consumed so far, if limited with
+    /// [`Decoder::with_fuel`]
+    pub fn fuel_consumed(&self) -> Option<u64> {
+        let messages = self.stack.iter().map(message::Decoder::fuel);
+        let sequences = self.seq_stack.iter().map(sequence::Decoder::fuel);
+        messages
+            .chain(sequences)
+            .flatten()
+            .next()
+            .map(Fuel::consumed)
     }
-}
 
-impl<D> Default for Decoder<D>
-where
-    D: Digest,
-{
-    fn default() -> Self {
-        Self::new()
+    /// Call the given [`Observer`] with each event decoded from the message
+    /// and everything nested within it (including skipped fields and
+    /// sequence elements), along with the offset it was decoded from, e.g.
+    /// to instrument decoding with [`TracingObserver`] (which requires the
+    /// `tracing` feature).
+    ///
+    /// Decoders without an observer don't pay for the hook.
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn with_observer(mut self, observer: impl Observer + 'static) -> Self {
+        self.stack[0].set_observer(Some(Box::new(observer)));
+        self
     }
-}
-
-impl<D, M> Decode<M> for Decoder<D>
-where
-    D: Digest,
-    M: Message,
-{
-    fn decode(&mut self, tag: Tag, input: &mut &[u8]) -> Result<M, Error> {
-        #[cfg(feature = "log")]
-        begin!(self, "[{}]: msg?", tag);
 
-        self.peek().expect_header(input, tag, WireType::Message)?;
-        let msg_bytes = self.peek().decode_message(input)?;
+    /// Accept nested messages which aren't entirely consumed by their
+    /// [`Message::decode`] implementation, ignoring the data following the
+    /// last field it decoded.
+    ///
+    /// By default the decoder only accepts canonically encoded messages,
+    /// since Verihash digests are only unique if every message has exactly
+    /// one encoding. It rejects:
+    ///
+    /// - non-minimal `vint64` encodings ([`error::Kind::NonCanonicalVInt64`])
+    /// - fields whose tags aren't increasing ([`error::Kind::Order`])
+    /// - repeated fields ([`error::Kind::DuplicateTag`]), unless another
+    ///   [`DuplicatePolicy`] is set with [`Decoder::with_duplicate_policy`]
+    /// - data following the last field of a nested message
+    ///   ([`error::Kind::TrailingData`])
+    /// - maps whose keys aren't increasing ([`error::Kind::KeyOrder`])
+    /// - more than one variant of a oneof, i.e. an `enum` deriving
+    ///   [`Message`] ([`error::Kind::MultipleVariants`])
+    ///
+    /// The first three are required to decode and hash messages at all, so
+    /// only the last three are relaxed by this escape hatch (the variants
+    /// following the first being ignored like trailing data). Trailing data
+    /// isn't included in digests, so messages decoded in permissive mode may
+    /// have the same digest as other encodings.
+    pub fn permissive(mut self) -> Self {
+        self.canonical = false;
+        self
+    }
 
-        self.push()?;
-        let msg = M::decode(self, msg_bytes)?;
+    /// Decode a message which must span the entire input, returning
+    /// [`error::Kind::TrailingData`] if any data follows the last field its
+    /// [`Message::decode`] implementation decoded.
+    ///
+    /// Trailing data isn't included in the message's digest, so this should
+    /// be preferred over calling [`Message::decode`] directly for the
+    /// outermost message. Nested messages are always checked in the same
+    /// way (unless the decoder is [`Decoder::permissive`]).
+    pub fn decode_message<M: Message>(&mut self, input: &[u8]) -> Result<M, Error> {
+        let start = self.peek().position();
+        let (msg, consumed) = self.decode_prefix(input)?;
 
-        if let Some(digest) = self.pop() {
-            self.peek().hash_message_digest(tag, &digest)?;
-        }
+        self.with_context(|decoder| {
+            decoder.skip_duplicates(&mut &input[consumed..])?;
+            check_consumed(input.len(), decoder.peek().position() - start)
+        })?;
 
         Ok(msg)
     }
-}
 
-impl<D> Decode<u64> for Decoder<D>
-where
-    D: Digest,
-{
-    fn decode(&mut self, tag: Tag, input: &mut &[u8]) -> Result<u64, Error> {
-        #[cfg(feature = "log")]
-        begin!(self, "[{}]: uint64?", tag);
+    /// Decode a message from the start of the input, returning it along with
+    /// the number of bytes it spans, e.g. for inputs which intentionally
+    /// contain other data following the message.
+    ///
+    /// The message ends after the last field its [`Message::decode`]
+    /// implementation decoded (or skipped). Derived implementations skip
+    /// unknown fields until the end of the input, so they can only be
+    /// followed by data which can't be parsed as a field.
+    pub fn decode_prefix<M: Message>(&mut self, input: &[u8]) -> Result<(M, usize), Error> {
+        let start = self.peek().position();
+        let msg = M::decode(self, input)?;
+        Ok((msg, self.peek().position() - start))
+    }
 
-        self.peek().expect_header(input, tag, WireType::UInt64)?;
-        self.peek().decode_uint64(input)
+    /// Decode a `bytes` field as a slice borrowed from the input, without
+    /// copying it
+    pub fn decode_bytes<'a>(&mut self, tag: Tag, input: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+        self.decode_ref(tag, input)
     }
-}
 
-impl<D> Decode<i64> for Decoder<D>
-where
-    D: Digest,
-{
-    fn decode(&mut self, tag: Tag, input: &mut &[u8]) -> Result<i64, Error> {
-        #[cfg(feature = "log")]
-        begin!(self, "[{}]: sint64?", tag);
+    /// Decode a `string` field as a slice borrowed from the input, without
+    /// copying it. The string is still checked to be valid UTF-8.
+    pub fn decode_string<'a>(&mut self, tag: Tag, input: &mut &'a [u8]) -> Result<&'a str, Error> {
+        self.decode_ref(tag, input)
+    }
 
-        self.peek().expect_header(input, tag, WireType::SInt64)?;
-        self.peek().decode_sint64(input)
+    /// Decode a `uint64` field whose value must fit in a `u32`, returning
+    /// [`error::Kind::OutOfRange`] if it doesn't
+    pub fn decode_uint32(&mut self, tag: Tag, input: &mut &[u8]) -> Result<u32, Error> {
+        self.decode(tag, input)
     }
-}
 
-impl<D> DecodeRef<[u8]> for Decoder<D>
-where
-    D: Digest,
-{
-    fn decode_ref<'a>(&mut self, tag: Tag, input: &mut &'a [u8]) -> Result<&'a [u8], Error> {
-        #[cfg(feature = "log")]
-        begin!(self, "[{}]: bytes?", tag);
+    /// Decode an `sint64` field whose value must fit in an `i32`, returning
+    /// [`error::Kind::OutOfRange`] if it doesn't
+    pub fn decode_sint32(&mut self, tag: Tag, input: &mut &[u8]) -> Result<i32, Error> {
+        self.decode(tag, input)
+    }
+
+    /// Decode a `bytes` field by copying it into the provided buffer,
+    /// returning the length of the value, which is at the start of `out`.
+    ///
+    /// Returns [`error::Kind::BufferTooSmall`] if the value doesn't fit.
+    pub fn decode_bytes_into(
+        &mut self,
+        tag: Tag,
+        input: &mut &[u8],
+        out: &mut [u8],
+    ) -> Result<usize, Error> {
+        let bytes = self.decode_bytes(tag, input)?;
+        copy_into(bytes, out).map_err(|e| self.context(e))
+    }
 
-        self.peek().expect_header(input, tag, WireType::Bytes)?;
-        self.peek().decode_bytes(input)
+    /// Decode a `string` field by copying it into the provided buffer,
+    /// returning the length of the string, which is at the start of `out`.
+    ///
+    /// The string is checked to be valid UTF-8 before it's copied, so
+    /// `out[..len]` is always valid UTF-8. Returns
+    /// [`error::Kind::BufferTooSmall`] if the string doesn't fit.
+    pub fn decode_string_into(
+        &mut self,
+        tag: Tag,
+        input: &mut &[u8],
+        out: &mut [u8],
+    ) -> Result<usize, Error> {
+        let string = self.decode_string(tag, input)?;
+        copy_into(string.as_bytes(), out).map_err(|e| self.context(e))
     }
-}
 
-impl<D> DecodeRef<str> for Decoder<D>
-where
-    D: Digest,
-{
-    fn decode_ref<'a>(&mut self, tag: Tag, input: &mut &'a [u8]) -> Result<&'a str, Error> {
+    /// Decode a `bytes` field into a [`Vec`], rejecting values longer than
+    /// `max` bytes with [`error::Kind::TooLong`] (which includes the length
+    /// claimed by the value's length delimiter) before reading them.
+    ///
+    /// The value is bounds-checked against the input before it's copied, so
+    /// a forged length delimiter can't cause an allocation larger than the
+    /// input.
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_bytes_capped(
+        &mut self,
+        tag: Tag,
+        input: &mut &[u8],
+        max: usize,
+    ) -> Result<Vec<u8>, Error> {
+        self.with_max_field_len(max, |decoder| decoder.decode_bytes(tag, input))
+            .map(Vec::from)
+    }
+
+    /// Decode a `string` field into a [`String`], rejecting strings longer
+    /// than `max` bytes with [`error::Kind::TooLong`] (which includes the
+    /// length claimed by the string's length delimiter) before reading them.
+    ///
+    /// The string is bounds-checked against the input before it's copied, so
+    /// a forged length delimiter can't cause an allocation larger than the
+    /// input.
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_string_capped(
+        &mut self,
+        tag: Tag,
+        input: &mut &[u8],
+        max: usize,
+    ) -> Result<String, Error> {
+        self.with_max_field_len(max, |decoder| decoder.decode_string(tag, input))
+            .map(String::from)
+    }
+
+    /// Decode a `message` field containing a message which borrows from the
+    /// input (see [`MessageRef`]), e.g. a nested struct with a lifetime
+    /// parameter deriving `Message`
+    pub fn decode_message_ref<'a, M>(&mut self, tag: Tag, input: &mut &'a [u8]) -> Result<M, Error>
+    where
+        M: MessageRef<'a>,
+    {
         #[cfg(feature = "log")]
-        begin!(self, "[{}]: string?", tag);
+        begin!(self, "[{}]: msg (ref)?", tag);
+
+        self.decode_field(tag, WireType::Message, input, |decoder, input| {
+            let msg_bytes = decoder.peek().decode_message(input)?;
 
-        self.peek().expect_header(input, tag, WireType::String)?;
-        self.peek().decode_string(input)
+            decoder.push(msg_bytes)?;
+            let msg = M::decode(decoder, msg_bytes)?;
+
+            if let Some(digest) = decoder.finish_message(msg_bytes)? {
+                decoder.peek().hash_message_digest(tag, &digest)?;
+            }
+
+            Ok(msg)
+        })
     }
-}
 
-impl<D, M> DecodeSeq<M, D> for Decoder<D>
-where
-    D: Digest,
-    M: Message,
-{
-    fn decode_seq<'a, 'b>(
-        &'a mut self,
+    /// Decode a `message` field without decoding its body, returning the
+    /// body as a [`RawMessage`] borrowed from the input, e.g. so a router
+    /// can forward it unmodified, or decode it later with
+    /// [`RawMessage::decode`] once its type is known.
+    ///
+    /// The body is still bounds-checked against the input and the
+    /// decoder's [`Limits`], and its fields are hashed (without schema) so
+    /// the enclosing message's digest is the same as if the body had been
+    /// decoded.
+    pub fn decode_message_raw<'a>(
+        &mut self,
         tag: Tag,
-        input: &mut &'b [u8],
-    ) -> Result<sequence::Iter<'a, 'b, M, D>, Error> {
+        input: &mut &'a [u8],
+    ) -> Result<RawMessage<'a>, Error> {
         #[cfg(feature = "log")]
-        begin!(self, "[{}]: seq<msg>?", tag);
+        begin!(self, "[{}]: msg (raw)?", tag);
 
-        self.peek().expect_header(input, tag, WireType::Sequence)?;
-        let seq_bytes = self.peek().decode_sequence(WireType::Message, input)?;
-        self.push_seq(WireType::Message, seq_bytes.len())?;
+        self.decode_field(tag, WireType::Message, input, |decoder, input| {
+            let msg_bytes = decoder.peek().decode_message(input)?;
 
-        Ok(sequence::Iter::new(self, tag, seq_bytes))
+            if let Some(digest) = decoder.skip_message(msg_bytes)? {
+                decoder.peek().hash_message_digest(tag, &digest)?;
+            }
+
+            Ok(RawMessage::new(msg_bytes))
+        })
     }
-}
 
-impl<D> DecodeSeq<u64, D> for Decoder<D>
-where
-    D: Digest,
-{
-    fn decode_seq<'a, 'b>(
+    /// Decode a sequence of `uint64` values, returning an iterator over them
+    /// which doesn't allocate.
+    ///
+    /// The sequence's elements must all be decoded (i.e. the iterator must
+    /// be exhausted) before decoding the next field.
+    pub fn uint64_seq_iter<'a, 'b>(
         &'a mut self,
         tag: Tag,
         input: &mut &'b [u8],
     ) -> Result<sequence::Iter<'a, 'b, u64, D>, Error> {
-        #[cfg(feature = "log")]
-        begin!(self, "[{}]: seq<uint64>?", tag);
+        self.decode_seq(tag, input)
+    }
+
+    /// Decode a sequence of `sint64` values, returning an iterator over them
+    /// which doesn't allocate (see [`Decoder::uint64_seq_iter`])
+    pub fn sint64_seq_iter<'a, 'b>(
+        &'a mut self,
+        tag: Tag,
+        input: &mut &'b [u8],
+    ) -> Result<sequence::Iter<'a, 'b, i64, D>, Error> {
+        self.decode_seq(tag, input)
+    }
 
-        self.peek().expect_header(input, tag, WireType::Sequence)?;
-        let seq_bytes = self.peek().decode_sequence(WireType::UInt64, input)?;
-        self.push_seq(WireType::UInt64, seq_bytes.len())?;
+    /// Decode a sequence of `bytes` values, returning an iterator over
+    /// slices of the input (see [`Decoder::uint64_seq_iter`])
+    pub fn bytes_seq_iter<'a, 'b>(
+        &'a mut self,
+        tag: Tag,
+        input: &mut &'b [u8],
+    ) -> Result<sequence::Iter<'a, 'b, &'b [u8], D>, Error> {
+        self.decode_seq(tag, input)
+    }
 
-        Ok(sequence::Iter::new(self, tag, seq_bytes))
+    /// Decode a sequence of `string` values, returning an iterator over
+    /// slices of the input (see [`Decoder::uint64_seq_iter`])
+    pub fn string_seq_iter<'a, 'b>(
+        &'a mut self,
+        tag: Tag,
+        input: &mut &'b [u8],
+    ) -> Result<sequence::Iter<'a, 'b, &'b str, D>, Error> {
+        self.decode_seq(tag, input)
     }
-}
 
-impl<D> DecodeSeq<i64, D> for Decoder<D>
-where
-    D: Digest,
-{
-    fn decode_seq<'a, 'b>(
+    /// Decode a sequence of messages, returning an iterator which decodes
+    /// each one on demand (see [`Decoder::uint64_seq_iter`]).
+    ///
+    /// Each message is decoded with its own nested message decoder, and
+    /// counts towards the maximum nesting depth. Errors include the
+    /// [`Error::index`] of the element which failed to decode.
+    pub fn message_seq_iter<'a, 'b, M: Message>(
         &'a mut self,
         tag: Tag,
         input: &mut &'b [u8],
-    ) -> Result<sequence::Iter<'a, 'b, i64, D>, Error> {
+    ) -> Result<sequence::Iter<'a, 'b, M, D>, Error> {
+        self.decode_seq(tag, input)
+    }
+
+    /// Decode a sequence whose elements are sequences of `T` (`uint64`,
+    /// `sint64` or messages), returning an iterator over the inner
+    /// sequences.
+    ///
+    /// Only one level of nesting is supported: the elements of the inner
+    /// sequences can't themselves be sequences, and the inner sequences
+    /// count towards the maximum nesting depth.
+    pub fn nested_seq_iter<'a, 'b, T>(
+        &'a mut self,
+        tag: Tag,
+        input: &mut &'b [u8],
+    ) -> Result<sequence::NestedIter<'a, 'b, T, D>, Error> {
         #[cfg(feature = "log")]
-        begin!(self, "[{}]: seq<sint64>?", tag);
+        begin!(self, "[{}]: seq<seq>?", tag);
 
-        self.peek().expect_header(input, tag, WireType::Sequence)?;
-        let seq_bytes = self.peek().decode_sequence(WireType::SInt64, input)?;
-        self.push_seq(WireType::SInt64, seq_bytes.len())?;
+        let seq_bytes = self.with_context(|decoder| {
+            decoder.expect_header(input, tag, WireType::Sequence)?;
+            let seq_bytes = decoder.peek().decode_sequence(WireType::Sequence, input)?;
+            decoder.push_seq(tag, WireType::Sequence, seq_bytes.len())?;
+            Ok(seq_bytes)
+        })?;
 
-        Ok(sequence::Iter::new(self, tag, seq_bytes))
+        Ok(sequence::NestedIter::new(self, tag, seq_bytes))
     }
-}
 
-#[cfg(all(test, feature = "sha2"))]
-mod tests {
-    use super::{Decode, DecodeRef};
-    use crate::Decoder;
+    /// Decode a sequence of messages into a [`Vec`]
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_message_seq<M: Message>(
+        &mut self,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Vec<M>, Error> {
+        self.message_seq_iter::<M>(tag, input)?.collect()
+    }
 
-    #[test]
-    fn decode_uint64() {
-        let input = [138, 10, 85];
-        let mut input_ref = &input[..];
+    /// Decode a map, i.e. a sequence of key/value entry messages (see the
+    /// [`map`][`crate::map`] module).
+    ///
+    /// Keys must be increasing unless the decoder is
+    /// [`Decoder::permissive`], and repeated keys are handled according to
+    /// [`Decoder::with_duplicate_key_policy`]. Errors include the
+    /// [`Error::index`] of the offending entry.
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_map<K, V>(&mut self, tag: Tag, input: &mut &[u8]) -> Result<BTreeMap<K, V>, Error>
+    where
+        K: value::Decodable + value::Encodable + Ord,
+        V: value::Decodable + value::Encodable,
+    {
+        let canonical = self.canonical;
+        let policy = self.key_duplicates;
+        let mut entries = self.message_seq_iter::<map::Entry<K, V>>(tag, input)?;
+        let mut map = BTreeMap::new();
 
-        let value: u64 = Decoder::new().decode(42, &mut input_ref).unwrap();
-        assert_eq!(value, 42);
-        assert!(input_ref.is_empty());
+        while let Some(entry) = entries.next() {
+            let map::Entry { key, value } = entry?;
+
+            if canonical && map.keys().next_back().map(|last| key < *last) == Some(true) {
+                return Err(entries.element_error(error::Kind::KeyOrder { tag }.into()));
+            }
+
+            match map.entry(key) {
+                btree_map::Entry::Vacant(entry) => {
+                    entry.insert(value);
+                }
+                btree_map::Entry::Occupied(mut entry) => match policy {
+                    DuplicatePolicy::Error => {
+                        return Err(entries.element_error(error::Kind::DuplicateKey { tag }.into()))
+                    }
+                    DuplicatePolicy::First => (),
+                    DuplicatePolicy::Last => {
+                        entry.insert(value);
+                    }
+                },
+            }
+        }
+
+        Ok(map)
     }
 
-    #[test]
-    fn decode_sint64() {
-        let input = [206, 10, 167];
-        let mut input_ref = &input[..];
+    /// Decode a sequence of `uint64` values into a [`Vec`]
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_uint64_seq(&mut self, tag: Tag, input: &mut &[u8]) -> Result<Vec<u64>, Error> {
+        self.uint64_seq_iter(tag, input)?.collect()
+    }
 
-        let value: i64 = Decoder::new().decode(43, &mut input_ref).unwrap();
-        assert_eq!(value, -42);
-        assert!(input_ref.is_empty());
+    /// Decode a sequence of `sint64` values into a [`Vec`]
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_sint64_seq(&mut self, tag: Tag, input: &mut &[u8]) -> Result<Vec<i64>, Error> {
+        self.sint64_seq_iter(tag, input)?.collect()
     }
 
-    #[test]
-    fn decode_bytes() {
-        let input = [73, 11, 98, 121, 116, 101, 115];
-        let mut input_ref = &input[..];
+    /// Decode a sequence of `bytes` values into a [`Vec`]
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_bytes_seq(&mut self, tag: Tag, input: &mut &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        self.bytes_seq_iter(tag, input)?
+            .map(|bytes| bytes.map(<[u8]>::to_vec))
+            .collect()
+    }
 
-        let bytes: &[u8] = Decoder::new().decode_ref(2, &mut input_ref).unwrap();
-        assert_eq!(bytes, &[98, 121, 116, 101, 115]);
-        assert!(input_ref.is_empty());
+    /// Decode a sequence of `string` values into a [`Vec`]
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_string_seq(&mut self, tag: Tag, input: &mut &[u8]) -> Result<Vec<String>, Error> {
+        self.string_seq_iter(tag, input)?
+            .map(|string| string.map(String::from))
+            .collect()
     }
 
-    #[test]
-    fn decode_string() {
-        let input = [139, 7, 98, 97, 122];
-        let mut input_ref = &input[..];
+    /// Decode a sequence of sequences of `uint64` values into a [`Vec`]
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_uint64_seq_seq(
+        &mut self,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Vec<Vec<u64>>, Error> {
+        let mut nested = self.nested_seq_iter::<u64>(tag, input)?;
+        let mut seqs = Vec::new();
 
-        let string: &str = Decoder::new().decode_ref(4, &mut input_ref).unwrap();
-        assert_eq!(string, "baz");
-        assert!(input_ref.is_empty());
+        while let Some(seq) = nested.next_seq() {
+            seqs.push(seq?.collect::<Result<_, _>>()?);
+        }
+
+        Ok(seqs)
+    }
+
+    /// Decode a sequence of sequences of `sint64` values into a [`Vec`]
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn decode_sint64_seq_seq(
+        &mut self,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Vec<Vec<i64>>, Error> {
+        let mut nested = self.nested_seq_iter::<i64>(tag, input)?;
+        let mut seqs = Vec::new();
+
+        while let Some(seq) = nested.next_seq() {
+            seqs.push(seq?.collect::<Result<_, _>>()?);
+        }
+
+        Ok(seqs)
+    }
+
+    /// Decode the header of the next field, e.g. to determine whether it's a
+    /// known field or one to [`Decoder::skip`]
+    pub fn decode_header(&mut self, input: &mut &[u8]) -> Result<Header, Error> {
+        self.with_context(|decoder| decoder.peek().decode_header(input))
+    }
+
+    /// Parse the header of the next field without consuming it, e.g. to
+    /// choose between mutually exclusive fields based on its tag.
+    ///
+    /// The header is left in the input, so the next call which decodes a
+    /// field (or [`Decoder::decode_header`]) consumes it as usual. Returns
+    /// `None` at the end of the message, i.e. if the input is empty.
+    pub fn peek_header(&mut self, input: &[u8]) -> Result<Option<Header>, Error> {
+        self.with_context(|decoder| decoder.peek().peek_header(input))
+    }
+
+    /// Is the next field of the message the one with the given tag?
+    ///
+    /// Returns `false` at the end of the message, or if the next field has
+    /// a different tag (e.g. an unknown field which should be skipped
+    /// first).
+    pub fn is_next_field(&mut self, tag: Tag, input: &[u8]) -> Result<bool, Error> {
+        Ok(self.peek_header(input)?.map(|header| header.tag) == Some(tag))
+    }
+
+    /// Decode the field with the given tag if it's the next field of the
+    /// message, or return `None` if it's absent (see
+    /// [`Decoder::is_next_field`])
+    pub fn decode_optional<T>(&mut self, tag: Tag, input: &mut &[u8]) -> Result<Option<T>, Error>
+    where
+        Self: Decode<T>,
+    {
+        if self.is_next_field(tag, input)? {
+            self.decode(tag, input).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Decode the field with the given tag as a reference to the input if
+    /// it's the next field of the message, or return `None` if it's absent
+    /// (see [`Decoder::is_next_field`])
+    pub fn decode_optional_ref<'a, T>(
+        &mut self,
+        tag: Tag,
+        input: &mut &'a [u8],
+    ) -> Result<Option<&'a T>, Error>
+    where
+        T: ?Sized,
+        Self: DecodeRef<T>,
+    {
+        if self.is_next_field(tag, input)? {
+            self.decode_ref(tag, input).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Decode every remaining field of the current message, calling the
+    /// given [`Visitor`] with each header and value as it's decoded, e.g.
+    /// for inspecting messages without a schema.
+    ///
+    /// Values are checked and hashed as if they were decoded individually.
+    /// Nested messages and sequences are passed to the visitor as their
+    /// bodies, which are then skipped (see [`Decoder::skip`]).
+    pub fn decode_with_visitor<'a, V>(
+        &mut self,
+        input: &mut &'a [u8],
+        visitor: &mut V,
+    ) -> Result<(), Error>
+    where
+        V: Visitor<'a> + ?Sized,
+    {
+        while !input.is_empty() {
+            self.with_context(|decoder| {
+                let header = decoder.peek().decode_header(input)?;
+                visitor.field_header(&header)?;
+                decoder.visit_value(&header, input, visitor)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Decode the value of a field, calling the given [`Visitor`] with it
+    fn visit_value<'a, V>(
+        &mut self,
+        header: &Header,
+        input: &mut &'a [u8],
+        visitor: &mut V,
+    ) -> Result<(), Error>
+    where
+        V: Visitor<'a> + ?Sized,
+    {
+        let tag = header.tag;
+
+        match header.wire_type {
+            WireType::False | WireType::True => match self.peek().decode(input)? {
+                Some(Event::Bool(value)) => visitor.bool(tag, value),
+                _ => Err(error::Kind::Decode {
+                    element: Element::Value,
+                    wire_type: header.wire_type,
+                }
+                .into()),
+            },
+            WireType::UInt64 => {
+                let value = self.peek().decode_uint64(input)?;
+                visitor.uint64(tag, value)
+            }
+            WireType::SInt64 => {
+                let value = self.peek().decode_sint64(input)?;
+                visitor.sint64(tag, value)
+            }
+            WireType::Bytes => {
+                let bytes = self.peek().decode_bytes(input)?;
+                visitor.length_delimiter(tag, WireType::Bytes, bytes.len())?;
+
+                if !bytes.is_empty() {
+                    visitor.bytes_chunk(tag, bytes, 0)?;
+                }
+
+                Ok(())
+            }
+            WireType::String => {
+                let bytes = self
+                    .peek()
+                    .decode_dynamically_sized_value(WireType::String, input)?;
+                visitor.length_delimiter(tag, WireType::String, bytes.len())?;
+
+                if !bytes.is_empty() {
+                    visitor.string_chunk(tag, bytes, 0)?;
+                }
+
+                Ok(())
+            }
+            WireType::Message => {
+                let msg_bytes = self.peek().decode_message(input)?;
+                visitor.length_delimiter(tag, WireType::Message, msg_bytes.len())?;
+
+                if !msg_bytes.is_empty() {
+                    visitor.message_chunk(tag, msg_bytes, 0)?;
+                }
+
+                if let Some(digest) = self.skip_message(msg_bytes)? {
+                    self.peek().hash_message_digest(tag, &digest)?;
+                }
+
+                Ok(())
+            }
+            WireType::Sequence => {
+                let wire_type = peek_sequence_type(input)?;
+                let seq_bytes = self.peek().decode_sequence(wire_type, input)?;
+                visitor.sequence_header(tag, wire_type, seq_bytes.len())?;
+
+                if !seq_bytes.is_empty() {
+                    visitor.sequence_chunk(tag, seq_bytes, 0)?;
+                }
+
+                self.skip_sequence_body(tag, wire_type, seq_bytes)
+            }
+        }
+    }
+
+    /// Skip the value of a field whose header has already been decoded, e.g.
+    /// an unknown field which isn't critical.
+    ///
+    /// Skipped values are still decoded and hashed like any other: their
+    /// lengths are checked against the decoder's [`Limits`], and messages
+    /// and sequences nested within them are checked against the maximum
+    /// nesting depth and skipped recursively.
+    pub fn skip(&mut self, header: &Header, input: &mut &[u8]) -> Result<(), Error> {
+        self.with_context(|decoder| decoder.skip_value(header, input))
+    }
+
+    /// Decode a field with the given tag and wire type, using the given
+    /// function to decode its value, then apply the [`DuplicatePolicy`] to
+    /// any repeats of it
+    fn decode_field<'a, T>(
+        &mut self,
+        tag: Tag,
+        wire_type: WireType,
+        input: &mut &'a [u8],
+        mut decode_value: impl FnMut(&mut Self, &mut &'a [u8]) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.with_context(|decoder| {
+            decoder.expect_header(input, tag, wire_type)?;
+            let mut value = decode_value(decoder, input)?;
+
+            if decoder.duplicates == DuplicatePolicy::Last {
+                while decoder.peek_duplicate(input)? {
+                    decoder.peek().expect_header(input, tag, wire_type)?;
+                    value = decode_value(decoder, input)?;
+                }
+            }
+
+            decoder.skip_duplicates(input)?;
+            Ok(value)
+        })
+    }
+
+    /// Decode an expected field header, after skipping any repeats of the
+    /// previous field (see [`Decoder::skip_duplicates`])
+    fn expect_header(
+        &mut self,
+        input: &mut &[u8],
+        tag: Tag,
+        wire_type: WireType,
+    ) -> Result<(), Error> {
+        self.skip_duplicates(input)?;
+        self.peek().expect_header(input, tag, wire_type)
+    }
+
+    /// Is the next field a repeat of the previous field?
+    ///
+    /// Data which can't be parsed as a field header isn't a repeat: it's
+    /// left for whatever decodes the input next to reject (e.g. as trailing
+    /// data following the outermost message).
+    fn peek_duplicate(&mut self, input: &[u8]) -> Result<bool, Error> {
+        let last_tag = self.peek().last_tag();
+
+        match self.peek().peek_header(input) {
+            Ok(header) => Ok(matches!(header, Some(header) if Some(header.tag) == last_tag)),
+            Err(err) => match err.kind() {
+                error::Kind::DuplicateTag { .. } | error::Kind::Order { .. } => Err(err),
+                _ => Ok(false),
+            },
+        }
+    }
+
+    /// Skip repeats of the previous field under [`DuplicatePolicy::First`],
+    /// or reject them under [`DuplicatePolicy::Error`].
+    ///
+    /// Under [`DuplicatePolicy::Last`] repeats are overwritten as they're
+    /// decoded, so any remaining here are of fields which can't be
+    /// overwritten (i.e. sequences), and are also rejected.
+    fn skip_duplicates(&mut self, input: &mut &[u8]) -> Result<(), Error> {
+        while self.peek_duplicate(input)? {
+            if self.duplicates != DuplicatePolicy::First {
+                let tag = self.peek().last_tag().unwrap();
+                return Err(error::Kind::DuplicateTag { tag }.into());
+            }
+
+            let header = self.peek().decode_header(input)?;
+            self.skip_value(&header, input)?;
+        }
+
+        Ok(())
+    }
+
+    /// Skip the value of a field (see [`Decoder::skip`])
+    fn skip_value(&mut self, header: &Header, input: &mut &[u8]) -> Result<(), Error> {
+        match header.wire_type {
+            WireType::False | WireType::True => match self.peek().decode(input)? {
+                Some(Event::Bool(_)) => Ok(()),
+                _ => Err(error::Kind::Decode {
+                    element: Element::Value,
+                    wire_type: header.wire_type,
+                }
+                .into()),
+            },
+            WireType::UInt64 => self.peek().decode_uint64(input).map(|_| ()),
+            WireType::SInt64 => self.peek().decode_sint64(input).map(|_| ()),
+            WireType::Bytes => self.peek().decode_bytes(input).map(|_| ()),
+            WireType::String => self.peek().decode_string(input).map(|_| ()),
+            WireType::Message => {
+                let msg_bytes = self.peek().decode_message(input)?;
+
+                if let Some(digest) = self.skip_message(msg_bytes)? {
+                    self.peek().hash_message_digest(header.tag, &digest)?;
+                }
+
+                Ok(())
+            }
+            WireType::Sequence => self.skip_sequence(header.tag, input),
+        }
+    }
+
+    /// Fill the provided slice with the digest of the message if it fits
+    // TODO(tarcieri): find a better way to handle generic digest sizes
+    pub fn fill_digest(&mut self, output: &mut [u8]) -> Result<(), Error> {
+        self.with_context(|decoder| {
+            let digest = decoder
+                .peek()
+                .compute_digest()?
+                .ok_or(error::HashingReason::Incomplete)?;
+
+            if digest.len() != output.len() {
+                return Err(error::HashingReason::LengthMismatch)?;
+            }
+
+            output.copy_from_slice(&digest);
+            Ok(())
+        })
+    }
+
+    /// Add the context of the field presently being decoded to an error:
+    /// the current offset within the outermost message, the header of the
+    /// field, and the tags of the fields containing the current message.
+    ///
+    /// Errors which already have context (i.e. from a more deeply nested
+    /// message) are returned unmodified.
+    pub(crate) fn context(&self, err: Error) -> Error {
+        let (current, parents) = self.stack.split_last().unwrap();
+
+        let mut tags = heapless::Vec::<Tag, U16>::new();
+        for decoder in parents {
+            // Parent messages are always processing the field containing
+            // their child, so they've decoded its header
+            tags.push(decoder.last_tag().unwrap_or_default()).unwrap();
+        }
+
+        err.with_context(self.offset(), current.last_header(), Path::new(&tags))
+    }
+
+    /// Call the given function with the maximum length of `bytes`, `string`
+    /// and `sequence` fields in the current message lowered to `max`
+    #[cfg(feature = "alloc")]
+    fn with_max_field_len<T>(
+        &mut self,
+        max: usize,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let limits = self.peek().limits();
+
+        self.peek().set_limits(Limits {
+            max_field_len: limits.max_field_len.min(max),
+            ..limits
+        });
+
+        let result = f(self);
+        self.peek().set_limits(limits);
+        result
+    }
+
+    /// Call the given function, adding context to any errors it returns
+    fn with_context<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        f(self).map_err(|e| self.context(e))
+    }
+
+    /// Get the current offset within the outermost message
+    fn offset(&self) -> usize {
+        match self.seq_stack.last() {
+            // The sequence is innermost unless a message within it is being
+            // decoded
+            Some(seq_decoder) if self.stack.len() == self.seq_depth => seq_decoder.offset(),
+            _ => self.stack.last().unwrap().offset(),
+        }
+    }
+
+    /// Take the fuel from the innermost message or sequence decoder
+    fn take_fuel(&mut self) -> Option<Fuel> {
+        match self.seq_stack.last_mut() {
+            Some(seq_decoder) if self.stack.len() == self.seq_depth => seq_decoder.take_fuel(),
+            _ => self.peek().take_fuel(),
+        }
+    }
+
+    /// Give fuel to the innermost message or sequence decoder
+    fn put_fuel(&mut self, fuel: Option<Fuel>) {
+        match self.seq_stack.last_mut() {
+            Some(seq_decoder) if self.stack.len() == self.seq_depth => seq_decoder.set_fuel(fuel),
+            _ => self.peek().set_fuel(fuel),
+        }
+    }
+
+    /// Take the observer from the innermost message or sequence decoder
+    #[cfg(feature = "alloc")]
+    fn take_observer(&mut self) -> Option<Box<dyn Observer>> {
+        match self.seq_stack.last_mut() {
+            Some(seq_decoder) if self.stack.len() == self.seq_depth => seq_decoder.take_observer(),
+            _ => self.peek().take_observer(),
+        }
+    }
+
+    /// Give the observer to the innermost message or sequence decoder
+    #[cfg(feature = "alloc")]
+    fn put_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+        match self.seq_stack.last_mut() {
+            Some(seq_decoder) if self.stack.len() == self.seq_depth => {
+                seq_decoder.set_observer(observer)
+            }
+            _ => self.peek().set_observer(observer),
+        }
+    }
+
+    /// Get the depth of the pushdown stack
+    #[cfg(feature = "log")]
+    pub(crate) fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Push a new message decoder down onto the stack for a message body
+    /// which was just consumed from the input
+    fn push(&mut self, msg_bytes: &[u8]) -> Result<(), Error> {
+        let depth = self.nested_depth()?;
+
+        let mut decoder = message::Decoder::new();
+        decoder.set_limits(self.limits);
+        decoder.set_allow_duplicates(self.duplicates != DuplicatePolicy::Error);
+
+        decoder.set_offset(self.offset().checked_sub(msg_bytes.len()).unwrap());
+
+        if self.stats {
+            decoder.enable_stats(depth);
+        }
+
+        decoder.set_fuel(self.take_fuel());
+
+        #[cfg(feature = "alloc")]
+        decoder.set_observer(self.take_observer());
+
+        self.stack.push(decoder).map_err(|_| {
+            error::Kind::NestingDepth {
+                depth,
+                max: self.max_depth,
+            }
+            .into()
+        })
+    }
+
+    /// Get the depth of a message or sequence nested at the current position,
+    /// or an error if it exceeds the maximum depth
+    fn nested_depth(&self) -> Result<usize, Error> {
+        let depth = self.stack.len() + self.seq_stack.len() + 1;
+
+        if depth > self.max_depth {
+            return Err(error::Kind::NestingDepth {
+                depth,
+                max: self.max_depth,
+            }
+            .into());
+        }
+
+        Ok(depth)
+    }
+
+    /// Pop the message decoder from the stack when we've finished a message.
+    ///
+    /// Returns a digest of the nested message if message hashing is enabled.
+    ///
+    /// Panics if the decoder stack underflows.
+    // TODO(tarcieri): panic-free higher-level API, possibly RAII-based?
+    fn pop(&mut self) -> Option<DigestOutput<D>> {
+        let mut decoder = self.stack.pop().unwrap();
+
+        if let Some(stats) = decoder.stats() {
+            self.peek().merge_stats(&stats);
+        }
+
+        let fuel = decoder.take_fuel();
+        self.put_fuel(fuel);
+
+        #[cfg(feature = "alloc")]
+        {
+            let observer = decoder.take_observer();
+            self.put_observer(observer);
+        }
+
+        decoder.compute_digest().unwrap()
+    }
+
+    /// Is this decoder rejecting non-canonical messages, i.e. not
+    /// [`Decoder::permissive`]?
+    #[cfg(feature = "veriform_derive")]
+    pub(crate) fn is_canonical(&self) -> bool {
+        self.canonical
+    }
+
+    /// Pop the message decoder for the given nested message body once its
+    /// [`Message::decode`] implementation has returned.
+    ///
+    /// Returns a digest of the nested message if hashing is enabled, or an
+    /// error if it wasn't entirely consumed (unless the decoder is
+    /// permissive).
+    fn finish_message(&mut self, msg_bytes: &[u8]) -> Result<Option<DigestOutput<D>>, Error> {
+        // Repeats of the message's last field are only seen once it ends
+        let mut rest = &msg_bytes[self.peek().position()..];
+        self.skip_duplicates(&mut rest)?;
+
+        if self.canonical {
+            check_consumed(msg_bytes.len(), self.peek().position())?;
+        }
+
+        Ok(self.pop())
+    }
+
+    /// Peek at the message decoder on the top of the stack
+    fn peek(&mut self) -> &mut message::Decoder<D> {
+        self.stack.last_mut().unwrap()
+    }
+
+    /// Push a sequence decoder.
+    ///
+    /// Sequences can be nested one level deep, but only directly within
+    /// another sequence: sequences within messages within sequences aren't
+    /// supported.
+    fn push_seq(&mut self, tag: Tag, wire_type: WireType, length: usize) -> Result<(), Error> {
+        if !self.seq_stack.is_empty() && self.stack.len() != self.seq_depth {
+            return Err(error::Kind::NestedSequence.into());
+        }
+
+        self.nested_depth()?;
+
+        // The sequence body was just consumed from the input
+        let offset = self.offset().checked_sub(length).unwrap();
+
+        let mut seq_decoder = sequence::Decoder::new(wire_type, length)
+            .with_limits(tag, self.limits)
+            .with_offset(offset);
+
+        seq_decoder.set_fuel(self.take_fuel());
+
+        #[cfg(feature = "alloc")]
+        seq_decoder.set_observer(self.take_observer());
+
+        self.seq_stack
+            .push(seq_decoder)
+            .map_err(|_| Error::from(error::Kind::NestedSequence))?;
+
+        self.seq_depth = self.stack.len();
+        Ok(())
+    }
+
+    /// Pop the innermost sequence decoder.
+    ///
+    /// Returns a digest of the sequence if hashing is enabled, or an error
+    /// if the sequence was only partially consumed.
+    ///
+    /// Panics if the decoder stack underflows.
+    // TODO(tarcieri): panic-free higher-level API, possibly RAII-based?
+    fn pop_seq(&mut self) -> Result<Option<DigestOutput<D>>, Error> {
+        let depth = self.stack.len() + self.seq_stack.len();
+        let mut seq_decoder = self.seq_stack.pop().unwrap();
+        self.peek().record_sequence(depth, seq_decoder.elements());
+
+        let fuel = seq_decoder.take_fuel();
+        self.put_fuel(fuel);
+
+        #[cfg(feature = "alloc")]
+        {
+            let observer = seq_decoder.take_observer();
+            self.put_observer(observer);
+        }
+
+        seq_decoder.compute_digest()
+    }
+
+    /// Pop the innermost sequence decoder, hashing the sequence's digest
+    /// into the sequence or message containing it
+    fn finish_seq(&mut self, tag: Tag) -> Result<(), Error> {
+        if let Some(digest) = self.pop_seq()? {
+            match self.seq_stack.last_mut() {
+                Some(seq_decoder) => seq_decoder.hash_sequence_digest(&digest)?,
+                None => self.peek().hash_sequence_digest(tag, &digest)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Peek at the innermost sequence decoder.
+    fn peek_seq(&mut self) -> &mut sequence::Decoder<D> {
+        self.seq_stack.last_mut().unwrap()
+    }
+
+    /// Skip every field of a nested message, returning its digest if
+    /// hashing is enabled
+    fn skip_message(&mut self, mut input: &[u8]) -> Result<Option<DigestOutput<D>>, Error> {
+        self.push(input)?;
+
+        while !input.is_empty() {
+            let header = self.decode_header(&mut input)?;
+            self.skip_value(&header, &mut input)?;
+        }
+
+        Ok(self.pop())
+    }
+
+    /// Skip a sequence and each of its elements
+    fn skip_sequence(&mut self, tag: Tag, input: &mut &[u8]) -> Result<(), Error> {
+        let wire_type = peek_sequence_type(input)?;
+        let seq_bytes = self.peek().decode_sequence(wire_type, input)?;
+        self.skip_sequence_body(tag, wire_type, seq_bytes)
+    }
+
+    /// Skip each element in the body of a sequence, then hash its digest
+    fn skip_sequence_body(
+        &mut self,
+        tag: Tag,
+        wire_type: WireType,
+        mut seq_bytes: &[u8],
+    ) -> Result<(), Error> {
+        self.push_seq(tag, wire_type, seq_bytes.len())?;
+
+        let mut index = 0;
+
+        while self.peek_seq().remaining() > 0 {
+            self.skip_element(tag, wire_type, &mut seq_bytes)
+                .map_err(|e| self.context(e).with_index(index))?;
+
+            index += 1;
+        }
+
+        self.finish_seq(tag)
+    }
+
+    /// Skip an element of a sequence
+    fn skip_element(
+        &mut self,
+        tag: Tag,
+        wire_type: WireType,
+        seq_bytes: &mut &[u8],
+    ) -> Result<(), Error> {
+        match wire_type {
+            WireType::UInt64 => self.peek_seq().decode_uint64(seq_bytes).map(|_| ()),
+            WireType::SInt64 => self.peek_seq().decode_sint64(seq_bytes).map(|_| ()),
+            WireType::Bytes => self.peek_seq().decode_bytes(seq_bytes).map(|_| ()),
+            WireType::String => self.peek_seq().decode_string(seq_bytes).map(|_| ()),
+            WireType::Message => {
+                let msg_bytes = self.peek_seq().decode_message(seq_bytes)?;
+
+                if let Some(digest) = self.skip_message(msg_bytes)? {
+                    self.peek_seq().hash_message_digest(&digest)?;
+                }
+
+                Ok(())
+            }
+            WireType::Sequence => {
+                let inner_type = peek_sequence_type(seq_bytes)?;
+                let inner_bytes = self.peek_seq().decode_sequence(inner_type, seq_bytes)?;
+                self.skip_sequence_body(tag, inner_type, inner_bytes)
+            }
+            _ => Err(error::Kind::Decode {
+                element: Element::Value,
+                wire_type,
+            }
+            .into()),
+        }
+    }
+}
+
+/// Get the magnitude of a signed integer
+fn magnitude(value: i64) -> u64 {
+    if value < 0 {
+        value.wrapping_neg() as u64
+    } else {
+        value as u64
+    }
+}
+
+/// Check that a message of the given length was entirely consumed
+fn check_consumed(msg_len: usize, consumed: usize) -> Result<(), Error> {
+    match msg_len.checked_sub(consumed) {
+        Some(0) => Ok(()),
+        Some(remaining) => Err(error::Kind::TrailingData { remaining }.into()),
+        None => Err(error::Kind::Length.into()),
+    }
+}
+
+/// Copy a value into the start of the given buffer, if it fits
+fn copy_into(value: &[u8], out: &mut [u8]) -> Result<usize, Error> {
+    out.get_mut(..value.len())
+        .ok_or(error::Kind::BufferTooSmall {
+            needed: value.len(),
+        })?
+        .copy_from_slice(value);
+
+    Ok(value.len())
+}
+
+/// Peek at a sequence header to determine the wire type of its elements
+fn peek_sequence_type(input: &[u8]) -> Result<WireType, Error> {
+    let mut seq_header = input;
+    Ok(WireType::from_unmasked(::vint64::decode(&mut seq_header)?))
+}
+
+impl<D> Default for Decoder<D>
+where
+    D: Digest,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, M> Decode<M> for Decoder<D>
+where
+    D: Digest,
+    M: Message,
+{
+    fn decode(&mut self, tag: Tag, input: &mut &[u8]) -> Result<M, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: msg?", tag);
+
+        self.decode_field(tag, WireType::Message, input, |decoder, input| {
+            let msg_bytes = decoder.peek().decode_message(input)?;
+
+            decoder.push(msg_bytes)?;
+            let msg = M::decode(decoder, msg_bytes)?;
+
+            if let Some(digest) = decoder.finish_message(msg_bytes)? {
+                decoder.peek().hash_message_digest(tag, &digest)?;
+            }
+
+            Ok(msg)
+        })
+    }
+}
+
+impl<D> Decode<u64> for Decoder<D>
+where
+    D: Digest,
+{
+    fn decode(&mut self, tag: Tag, input: &mut &[u8]) -> Result<u64, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: uint64?", tag);
+
+        self.decode_field(tag, WireType::UInt64, input, |decoder, input| {
+            decoder.peek().decode_uint64(input)
+        })
+    }
+}
+
+impl<D> Decode<i64> for Decoder<D>
+where
+    D: Digest,
+{
+    fn decode(&mut self, tag: Tag, input: &mut &[u8]) -> Result<i64, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: sint64?", tag);
+
+        self.decode_field(tag, WireType::SInt64, input, |decoder, input| {
+            decoder.peek().decode_sint64(input)
+        })
+    }
+}
+
+impl<D> Decode<bool> for Decoder<D>
+where
+    D: Digest,
+{
+    fn decode(&mut self, tag: Tag, input: &mut &[u8]) -> Result<bool, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: bool?", tag);
+
+        // Either boolean wire type is accepted (see `expect_header`)
+        self.decode_field(tag, WireType::False, input, |decoder, input| match decoder
+            .peek()
+            .decode(input)?
+        {
+            Some(Event::Bool(value)) => Ok(value),
+            _ => Err(error::Kind::Decode {
+                element: Element::Value,
+                wire_type: WireType::False,
+            }
+            .into()),
+        })
+    }
+}
+
+/// Implement [`Decode`] for unsigned integer types narrower than `u64`,
+/// which are encoded as `uint64` and range checked when decoded
+macro_rules! impl_decode_uint {
+    ($($ty:ty),+) => {
+        $(
+            impl<D> Decode<$ty> for Decoder<D>
+            where
+                D: Digest,
+            {
+                fn decode(&mut self, tag: Tag, input: &mut &[u8]) -> Result<$ty, Error> {
+                    #[cfg(feature = "log")]
+                    begin!(self, "[{}]: {}?", tag, stringify!($ty));
+
+                    self.decode_field(tag, WireType::UInt64, input, |decoder, input| {
+                        let value = decoder.peek().decode_uint64(input)?;
+
+                        <$ty>::try_from(value).map_err(|_| {
+                            error::Kind::OutOfRange {
+                                tag,
+                                value,
+                                max: <$ty>::MAX.into(),
+                            }
+                            .into()
+                        })
+                    })
+                }
+            }
+        )+
+    };
+}
+
+/// Implement [`Decode`] for signed integer types narrower than `i64`, which
+/// are encoded as `sint64` and range checked when decoded
+macro_rules! impl_decode_sint {
+    ($($ty:ty),+) => {
+        $(
+            impl<D> Decode<$ty> for Decoder<D>
+            where
+                D: Digest,
+            {
+                fn decode(&mut self, tag: Tag, input: &mut &[u8]) -> Result<$ty, Error> {
+                    #[cfg(feature = "log")]
+                    begin!(self, "[{}]: {}?", tag, stringify!($ty));
+
+                    self.decode_field(tag, WireType::SInt64, input, |decoder, input| {
+                        let value = decoder.peek().decode_sint64(input)?;
+
+                        <$ty>::try_from(value).map_err(|_| {
+                            let bound = if value < 0 { <$ty>::MIN } else { <$ty>::MAX };
+
+                            error::Kind::OutOfRange {
+                                tag,
+                                value: magnitude(value),
+                                max: magnitude(bound.into()),
+                            }
+                            .into()
+                        })
+                    })
+                }
+            }
+        )+
+    };
+}
+
+impl_decode_uint!(u8, u16, u32);
+impl_decode_sint!(i8, i16, i32);
+
+impl<D> DecodeRef<[u8]> for Decoder<D>
+where
+    D: Digest,
+{
+    fn decode_ref<'a>(&mut self, tag: Tag, input: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: bytes?", tag);
+
+        self.decode_field(tag, WireType::Bytes, input, |decoder, input| {
+            decoder.peek().decode_bytes(input)
+        })
+    }
+}
+
+impl<D> DecodeRef<str> for Decoder<D>
+where
+    D: Digest,
+{
+    fn decode_ref<'a>(&mut self, tag: Tag, input: &mut &'a [u8]) -> Result<&'a str, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: string?", tag);
+
+        self.decode_field(tag, WireType::String, input, |decoder, input| {
+            decoder.peek().decode_string(input)
+        })
+    }
+}
+
+impl<D, M> DecodeSeq<M, D> for Decoder<D>
+where
+    D: Digest,
+    M: Message,
+{
+    fn decode_seq<'a, 'b>(
+        &'a mut self,
+        tag: Tag,
+        input: &mut &'b [u8],
+    ) -> Result<sequence::Iter<'a, 'b, M, D>, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: seq<msg>?", tag);
+
+        let seq_bytes = self.with_context(|decoder| {
+            decoder.expect_header(input, tag, WireType::Sequence)?;
+            let seq_bytes = decoder.peek().decode_sequence(WireType::Message, input)?;
+            decoder.push_seq(tag, WireType::Message, seq_bytes.len())?;
+            Ok(seq_bytes)
+        })?;
+
+        Ok(sequence::Iter::new(self, tag, seq_bytes))
+    }
+}
+
+impl<D> DecodeSeq<u64, D> for Decoder<D>
+where
+    D: Digest,
+{
+    fn decode_seq<'a, 'b>(
+        &'a mut self,
+        tag: Tag,
+        input: &mut &'b [u8],
+    ) -> Result<sequence::Iter<'a, 'b, u64, D>, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: seq<uint64>?", tag);
+
+        let seq_bytes = self.with_context(|decoder| {
+            decoder.expect_header(input, tag, WireType::Sequence)?;
+            let seq_bytes = decoder.peek().decode_sequence(WireType::UInt64, input)?;
+            decoder.push_seq(tag, WireType::UInt64, seq_bytes.len())?;
+            Ok(seq_bytes)
+        })?;
+
+        Ok(sequence::Iter::new(self, tag, seq_bytes))
+    }
+}
+
+impl<D> DecodeSeq<i64, D> for Decoder<D>
+where
+    D: Digest,
+{
+    fn decode_seq<'a, 'b>(
+        &'a mut self,
+        tag: Tag,
+        input: &mut &'b [u8],
+    ) -> Result<sequence::Iter<'a, 'b, i64, D>, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: seq<sint64>?", tag);
+
+        let seq_bytes = self.with_context(|decoder| {
+            decoder.expect_header(input, tag, WireType::Sequence)?;
+            let seq_bytes = decoder.peek().decode_sequence(WireType::SInt64, input)?;
+            decoder.push_seq(tag, WireType::SInt64, seq_bytes.len())?;
+            Ok(seq_bytes)
+        })?;
+
+        Ok(sequence::Iter::new(self, tag, seq_bytes))
+    }
+}
+
+impl<'c, D> DecodeSeq<&'c [u8], D> for Decoder<D>
+where
+    D: Digest,
+{
+    fn decode_seq<'a, 'b>(
+        &'a mut self,
+        tag: Tag,
+        input: &mut &'b [u8],
+    ) -> Result<sequence::Iter<'a, 'b, &'c [u8], D>, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: seq<bytes>?", tag);
+
+        let seq_bytes = self.with_context(|decoder| {
+            decoder.expect_header(input, tag, WireType::Sequence)?;
+            let seq_bytes = decoder.peek().decode_sequence(WireType::Bytes, input)?;
+            decoder.push_seq(tag, WireType::Bytes, seq_bytes.len())?;
+            Ok(seq_bytes)
+        })?;
+
+        Ok(sequence::Iter::new(self, tag, seq_bytes))
+    }
+}
+
+impl<'c, D> DecodeSeq<&'c str, D> for Decoder<D>
+where
+    D: Digest,
+{
+    fn decode_seq<'a, 'b>(
+        &'a mut self,
+        tag: Tag,
+        input: &mut &'b [u8],
+    ) -> Result<sequence::Iter<'a, 'b, &'c str, D>, Error> {
+        #[cfg(feature = "log")]
+        begin!(self, "[{}]: seq<string>?", tag);
+
+        let seq_bytes = self.with_context(|decoder| {
+            decoder.expect_header(input, tag, WireType::Sequence)?;
+            let seq_bytes = decoder.peek().decode_sequence(WireType::String, input)?;
+            decoder.push_seq(tag, WireType::String, seq_bytes.len())?;
+            Ok(seq_bytes)
+        })?;
+
+        Ok(sequence::Iter::new(self, tag, seq_bytes))
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{sequence, Decode, DecodeRef, DecodeSeq, DuplicatePolicy, Limits};
+    use crate::{
+        error,
+        field::{self, Tag},
+        message::Element,
+        verihash, Decoder, Encoder, Error, Message,
+    };
+    use digest::Digest;
+    use heapless::consts::U64;
+    use sha2::Sha256;
+
+    /// Example nested message
+    #[derive(Debug, Eq, PartialEq)]
+    struct Inner {
+        value: u64,
+    }
+
+    impl Message for Inner {
+        fn decode<D: Digest>(
+            decoder: &mut super::Decoder<D>,
+            mut input: &[u8],
+        ) -> Result<Self, Error> {
+            let value = decoder.decode(Tag::new_const(0), &mut input)?;
+            Ok(Self { value })
+        }
+
+        fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+            let mut encoder = Encoder::new(buffer);
+            encoder.uint64(Tag::new_const(0), false, self.value)?;
+            Ok(encoder.finish())
+        }
+
+        fn encoded_len(&self) -> usize {
+            field::length::uint64(Tag::new_const(0), self.value)
+        }
+    }
+
+    /// Example message containing one of two mutually exclusive fields
+    #[derive(Debug, Eq, PartialEq)]
+    enum Choice {
+        Unsigned(u64),
+        Signed(i64),
+    }
+
+    impl Message for Choice {
+        fn decode<D: Digest>(
+            decoder: &mut super::Decoder<D>,
+            mut input: &[u8],
+        ) -> Result<Self, Error> {
+            match decoder.peek_header(input)? {
+                Some(header) if header.tag == Tag::new_const(1) => Ok(Choice::Unsigned(
+                    decoder.decode(Tag::new_const(1), &mut input)?,
+                )),
+                Some(header) if header.tag == Tag::new_const(2) => Ok(Choice::Signed(
+                    decoder.decode(Tag::new_const(2), &mut input)?,
+                )),
+                _ => Err(error::Kind::FieldHeader {
+                    tag: None,
+                    wire_type: None,
+                }
+                .into()),
+            }
+        }
+
+        fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+            let mut encoder = Encoder::new(buffer);
+
+            match *self {
+                Choice::Unsigned(value) => encoder.uint64(Tag::new_const(1), false, value)?,
+                Choice::Signed(value) => encoder.sint64(Tag::new_const(2), false, value)?,
+            }
+
+            Ok(encoder.finish())
+        }
+
+        fn encoded_len(&self) -> usize {
+            match *self {
+                Choice::Unsigned(value) => field::length::uint64(Tag::new_const(1), value),
+                Choice::Signed(value) => field::length::sint64(Tag::new_const(2), value),
+            }
+        }
+    }
+
+    /// Example message containing a nested message
+    #[derive(Debug, Eq, PartialEq)]
+    struct Outer {
+        inner: Inner,
+    }
+
+    impl Message for Outer {
+        fn decode<D: Digest>(
+            decoder: &mut super::Decoder<D>,
+            mut input: &[u8],
+        ) -> Result<Self, Error> {
+            let inner = decoder.decode(Tag::new_const(1), &mut input)?;
+            Ok(Self { inner })
+        }
+
+        fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+            let mut encoder = Encoder::new(buffer);
+            encoder.message(Tag::new_const(1), false, &self.inner)?;
+            Ok(encoder.finish())
+        }
+
+        fn encoded_len(&self) -> usize {
+            field::length::message(Tag::new_const(1), &self.inner)
+        }
+    }
+
+    /// Example chain of nested messages: `{ 0: message({ 0: message(...) }) }`,
+    /// where the innermost message is [`LEAF`]
+    #[derive(Debug, Eq, PartialEq)]
+    struct Chain {
+        levels: usize,
+    }
+
+    impl Message for Chain {
+        fn decode<D: Digest>(
+            decoder: &mut super::Decoder<D>,
+            mut input: &[u8],
+        ) -> Result<Self, Error> {
+            // Field 0 (i.e. the next message in the chain) has header `13`
+            if input.first() != Some(&13) {
+                let _: &[u8] = decoder.decode_ref(Tag::new_const(2), &mut input)?;
+                return Ok(Self { levels: 1 });
+            }
+
+            let inner: Chain = decoder.decode(Tag::new_const(0), &mut input)?;
+            Ok(Self {
+                levels: inner.levels + 1,
+            })
+        }
+
+        fn encode<'a>(&self, _buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+            unimplemented!()
+        }
+
+        fn encoded_len(&self) -> usize {
+            unimplemented!()
+        }
+    }
+
+    /// Innermost message in a [`Chain`]: `{ 2: bytes("bytes") }`
+    const LEAF: &[u8] = &[73, 11, 98, 121, 116, 101, 115];
+
+    #[test]
+    fn decode_uint64() {
+        let input = [138, 10, 85];
+        let mut input_ref = &input[..];
+
+        let value: u64 = Decoder::new()
+            .decode(Tag::new_const(42), &mut input_ref)
+            .unwrap();
+        assert_eq!(value, 42);
+        assert!(input_ref.is_empty());
+    }
+
+    #[test]
+    fn decode_sint64() {
+        let input = [206, 10, 167];
+        let mut input_ref = &input[..];
+
+        let value: i64 = Decoder::new()
+            .decode(Tag::new_const(43), &mut input_ref)
+            .unwrap();
+        assert_eq!(value, -42);
+        assert!(input_ref.is_empty());
+    }
+
+    #[test]
+    fn decode_uint32() {
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint32(Tag::new_const(1), false, u32::MAX).unwrap();
+        encoder
+            .uint64(Tag::new_const(2), false, u64::from(u32::MAX) + 1)
+            .unwrap();
+        let mut input = encoder.finish();
+
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder
+                .decode_uint32(Tag::new_const(1), &mut input)
+                .unwrap(),
+            u32::MAX
+        );
+
+        let err = decoder
+            .decode_uint32(Tag::new_const(2), &mut input)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::OutOfRange {
+                tag: Tag::new_const(2),
+                value: u64::from(u32::MAX) + 1,
+                max: u64::from(u32::MAX),
+            }
+        );
+        assert_eq!(err.tag(), Some(Tag::new_const(2)));
+    }
+
+    #[test]
+    fn decode_sint32() {
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.sint32(Tag::new_const(1), false, i32::MIN).unwrap();
+        encoder.sint32(Tag::new_const(2), false, i32::MAX).unwrap();
+        let mut input = encoder.finish();
+
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder
+                .decode_sint32(Tag::new_const(1), &mut input)
+                .unwrap(),
+            i32::MIN
+        );
+        assert_eq!(
+            decoder
+                .decode_sint32(Tag::new_const(2), &mut input)
+                .unwrap(),
+            i32::MAX
+        );
+
+        let out_of_range = [
+            (i64::from(i32::MIN) - 1, 1 << 31 | 1, 1 << 31),
+            (i64::from(i32::MAX) + 1, 1 << 31, (1 << 31) - 1),
+        ];
+
+        for &(value, magnitude, max) in &out_of_range {
+            let mut buffer = [0u8; 16];
+            let mut encoder = Encoder::new(&mut buffer);
+            encoder.sint64(Tag::new_const(1), false, value).unwrap();
+            let mut input = encoder.finish();
+
+            let err = Decoder::new()
+                .decode_sint32(Tag::new_const(1), &mut input)
+                .err()
+                .unwrap();
+            assert_eq!(
+                err.kind(),
+                error::Kind::OutOfRange {
+                    tag: Tag::new_const(1),
+                    value: magnitude,
+                    max,
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn decode_bytes() {
+        let input = [73, 11, 98, 121, 116, 101, 115];
+        let mut input_ref = &input[..];
+
+        let bytes: &[u8] = Decoder::new()
+            .decode_ref(Tag::new_const(2), &mut input_ref)
+            .unwrap();
+        assert_eq!(bytes, &[98, 121, 116, 101, 115]);
+        assert!(input_ref.is_empty());
+    }
+
+    #[test]
+    fn decode_string() {
+        let input = [139, 7, 98, 97, 122];
+        let mut input_ref = &input[..];
+
+        let string: &str = Decoder::new()
+            .decode_ref(Tag::new_const(4), &mut input_ref)
+            .unwrap();
+        assert_eq!(string, "baz");
+        assert!(input_ref.is_empty());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn decode_capped() {
+        // `{ 2: bytes("bytes"), 4: string("bazquux") }`
+        let input = [
+            73, 11, 98, 121, 116, 101, 115, 139, 15, 98, 97, 122, 113, 117, 117, 120,
+        ];
+        let mut input_ref = &input[..];
+
+        // The cap only applies to the field being decoded
+        let mut decoder = Decoder::new();
+        let bytes = decoder
+            .decode_bytes_capped(Tag::new_const(2), &mut input_ref, 5)
+            .unwrap();
+        assert_eq!(bytes, b"bytes");
+        let string = decoder
+            .decode_string(Tag::new_const(4), &mut input_ref)
+            .unwrap();
+        assert_eq!(string, "bazquux");
+
+        let err = Decoder::new()
+            .decode_string_capped(Tag::new_const(4), &mut &input[7..], 6)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(4),
+                wire_type: field::WireType::String,
+                length: 7,
+                max: 6
+            }
+        );
+
+        let err = Decoder::new()
+            .decode_bytes_capped(Tag::new_const(2), &mut &input[..], 4)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(2),
+                wire_type: field::WireType::Bytes,
+                length: 5,
+                max: 4
+            }
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn decode_capped_forged_length() {
+        // `2: bytes` claiming to be 4 GiB, followed by 5 bytes
+        let mut input = [0u8; 11];
+        input[0] = 73;
+        input[1..6].copy_from_slice(vint64::encode(1 << 32).as_ref());
+
+        let err = Decoder::new()
+            .decode_bytes_capped(Tag::new_const(2), &mut &input[..], 1024)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(2),
+                wire_type: field::WireType::Bytes,
+                length: 1 << 32,
+                max: 1024
+            }
+        );
+
+        let err = Decoder::new()
+            .with_limits(Limits::unlimited())
+            .decode_bytes_capped(Tag::new_const(2), &mut &input[..], usize::MAX)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::Truncated {
+                remaining: (1 << 32) - 5,
+                wire_type: field::WireType::Bytes
+            }
+        );
+    }
+
+    #[test]
+    fn decode_nested_message_digest() {
+        let outer = Outer {
+            inner: Inner { value: 42 },
+        };
+
+        let mut buffer = [0u8; 16];
+        let encoded = outer.encode(&mut buffer).unwrap();
+
+        let mut decoder = Decoder::new();
+        assert_eq!(Outer::decode(&mut decoder, encoded).unwrap(), outer);
+
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest).unwrap();
+
+        // Hash the inner message independently, then absorb its digest
+        let mut inner_hasher = verihash::Hasher::<Sha256>::new_message();
+        inner_hasher.tagged_uint64(Tag::new_const(0), false, 42);
+        let inner_digest = inner_hasher.finalize_message();
+
+        let mut outer_hasher = verihash::Hasher::<Sha256>::new_message();
+        outer_hasher.field(Tag::new_const(1), false);
+        outer_hasher.fixed_size_value(field::WireType::Message, &inner_digest);
+
+        assert_eq!(&digest[..], &outer_hasher.finalize_message()[..]);
+    }
+
+    #[test]
+    fn decode_message_raw() {
+        let outer = Outer {
+            inner: Inner { value: 42 },
+        };
+
+        let mut buffer = [0u8; 16];
+        let encoded = outer.encode(&mut buffer).unwrap();
+
+        let mut decoder = Decoder::new();
+        let raw = decoder
+            .decode_message_raw(Tag::new_const(1), &mut &encoded[..])
+            .unwrap();
+        assert_eq!(raw.as_bytes(), &[5, 85]);
+
+        // The enclosing message's digest is the same as if the body had
+        // been decoded
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest).unwrap();
+        assert_eq!(&digest[..], verihash::<Sha256>(encoded).unwrap().as_bytes());
+
+        let mut decoder = Decoder::new();
+        let inner: Inner = raw.decode(&mut decoder).unwrap();
+        assert_eq!(inner, outer.inner);
+
+        decoder.fill_digest(&mut digest).unwrap();
+        assert_eq!(&digest[..], raw.verihash::<Sha256>().unwrap().as_bytes());
+
+        // Raw bodies are still subject to the decoder's limits
+        let limits = Limits {
+            max_message_len: 1,
+            ..Limits::default()
+        };
+
+        let err = Decoder::new()
+            .with_limits(limits)
+            .decode_message_raw(Tag::new_const(1), &mut &encoded[..])
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(1),
+                wire_type: field::WireType::Message,
+                length: 2,
+                max: 1
+            }
+        );
+
+        // ...and bounds-checked against the input
+        let err = Decoder::new()
+            .decode_message_raw(Tag::new_const(1), &mut &encoded[..3])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::Truncated {
+                remaining: 1,
+                wire_type: field::WireType::Message
+            }
+        );
+    }
+
+    #[test]
+    fn nesting_depth_limit() {
+        let mut decoder = Decoder::new();
+
+        for _ in 1..16 {
+            decoder.push(&[]).unwrap();
+        }
+
+        let err = decoder.push(&[]).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 17, max: 16 });
+    }
+
+    /// Serialize the body of a chain of `levels` nested messages, with the
+    /// given body in the innermost message
+    fn chain(levels: usize, innermost: &[u8]) -> heapless::Vec<u8, U64> {
+        let mut body = heapless::Vec::<u8, U64>::from_slice(innermost).unwrap();
+
+        for _ in 1..levels {
+            // `{ 0: message(body) }`
+            let mut outer = heapless::Vec::<u8, U64>::from_slice(&[13]).unwrap();
+            outer.push(body.len() as u8 * 2 + 1).unwrap();
+            outer.extend_from_slice(&body).unwrap();
+            body = outer;
+        }
+
+        body
+    }
+
+    #[test]
+    fn max_depth() {
+        let input = chain(16, LEAF);
+        let decoded = Chain::decode(&mut Decoder::new(), &input).unwrap();
+        assert_eq!(decoded, Chain { levels: 16 });
+
+        // The over-deep message's body is malformed, but is never decoded
+        let input = chain(17, &[0]);
+        let err = Chain::decode(&mut Decoder::new(), &input).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 17, max: 16 });
+
+        let mut decoder = Decoder::new().with_max_depth(4);
+        assert!(Chain::decode(&mut decoder, &chain(4, LEAF)).is_ok());
+
+        let mut decoder = Decoder::new().with_max_depth(4);
+        let err = Chain::decode(&mut decoder, &chain(5, &[0])).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 5, max: 4 });
+    }
+
+    #[test]
+    fn field_length_limit() {
+        let input = [73, 11, 98, 121, 116, 101, 115];
+        let limits = Limits {
+            max_field_len: 4,
+            ..Limits::default()
+        };
+
+        let mut decoder = Decoder::new().with_limits(limits);
+        let err = DecodeRef::<[u8]>::decode_ref(&mut decoder, Tag::new_const(2), &mut &input[..])
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(2),
+                wire_type: field::WireType::Bytes,
+                length: 5,
+                max: 4
+            }
+        );
+
+        // A length delimiter claiming 2^40 bytes is rejected without a body
+        let input = [73, 32, 0, 0, 0, 0, 64];
+        let mut decoder = Decoder::new();
+        let err = DecodeRef::<[u8]>::decode_ref(&mut decoder, Tag::new_const(2), &mut &input[..])
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(2),
+                wire_type: field::WireType::Bytes,
+                length: 1 << 40,
+                max: Limits::default().max_field_len
+            }
+        );
+
+        // Unlimited decoders only fail once they find the body is missing
+        let mut decoder = Decoder::new().with_limits(Limits::unlimited());
+        let err = DecodeRef::<[u8]>::decode_ref(&mut decoder, Tag::new_const(2), &mut &input[..])
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::Decode {
+                element: Element::Value,
+                wire_type: field::WireType::Bytes
+            }
+        );
+    }
+
+    #[test]
+    fn nested_length_limits() {
+        let input = chain(3, LEAF);
+
+        let limits = Limits {
+            max_field_len: 4,
+            ..Limits::default()
+        };
+
+        let mut decoder = Decoder::new().with_limits(limits);
+        let err = Chain::decode(&mut decoder, &input).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(2),
+                wire_type: field::WireType::Bytes,
+                length: 5,
+                max: 4
+            }
+        );
+
+        let limits = Limits {
+            max_message_len: 8,
+            ..Limits::default()
+        };
+
+        let mut decoder = Decoder::new().with_limits(limits);
+        let err = Chain::decode(&mut decoder, &input).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(0),
+                wire_type: field::WireType::Message,
+                length: 9,
+                max: 8
+            }
+        );
+    }
+
+    #[test]
+    fn error_context() {
+        let input = chain(3, LEAF);
+
+        let limits = Limits {
+            max_field_len: 4,
+            ..Limits::default()
+        };
+
+        // Fails at the length delimiter of field 2 in the innermost message
+        let mut decoder = Decoder::new().with_limits(limits);
+        let err = Chain::decode(&mut decoder, &input).err().unwrap();
+        assert_eq!(err.position(), Some(5));
+        assert_eq!(err.tag(), Some(Tag::new_const(2)));
+        assert_eq!(
+            err.path().iter().collect::<heapless::Vec<_, U64>>(),
+            [Tag::new_const(0), Tag::new_const(0)]
+        );
+
+        // Errors in the outermost message have an empty path
+        let mut decoder = Decoder::new().with_limits(limits);
+        let err = Chain::decode(&mut decoder, LEAF).err().unwrap();
+        assert_eq!(err.position(), Some(1));
+        assert_eq!(err.tag(), Some(Tag::new_const(2)));
+        assert!(err.path().is_empty());
+
+        // Errors in sequences have the offset of the element
+        // `{ 1: sequence<uint64>([42, 0]) }` with a non-canonical second element
+        let input = [47, 101, 85, 2, 0];
+        let mut decoder = Decoder::new();
+        let mut seq: sequence::Iter<'_, '_, u64, Sha256> = decoder
+            .decode_seq(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+        assert_eq!(seq.next().unwrap().unwrap(), 42);
+
+        let err = seq.next().unwrap().err().unwrap();
+        assert_eq!(err.position(), Some(3));
+        assert_eq!(err.tag(), Some(Tag::new_const(1)));
+        assert!(err.path().is_empty());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn error_display() {
+        use alloc::string::ToString;
+
+        let limits = Limits {
+            max_field_len: 4,
+            ..Limits::default()
+        };
+
+        let mut decoder = Decoder::new().with_limits(limits);
+        let err = Chain::decode(&mut decoder, &chain(3, LEAF)).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "error at offset 5 (field #2:bytes in field 0.0): \
+             field 2 is too long: wire_type=Bytes length=5 max=4"
+        );
+        assert_eq!(
+            err.header(),
+            Some(field::Header::new(
+                Tag::new_const(2),
+                false,
+                field::WireType::Bytes
+            ))
+        );
+
+        let err = Error::from(error::Kind::TrailingData { remaining: 1 });
+        assert_eq!(err.to_string(), "unexpected trailing data: remaining=1");
+    }
+
+    #[test]
+    fn sequence_limits() {
+        // `{ 1: sequence<message>([{ 0: uint64(42) }]) }`
+        let input = [47, 109, 5, 5, 85];
+
+        let limits = Limits {
+            max_field_len: 2,
+            ..Limits::default()
+        };
+
+        let mut decoder = Decoder::new().with_limits(limits);
+        let err = DecodeSeq::<Inner, Sha256>::decode_seq(
+            &mut decoder,
+            Tag::new_const(1),
+            &mut &input[..],
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(1),
+                wire_type: field::WireType::Sequence,
+                length: 3,
+                max: 2
+            }
+        );
+
+        let limits = Limits {
+            max_message_len: 1,
+            ..Limits::default()
+        };
+
+        let mut decoder = Decoder::new().with_limits(limits);
+        let mut seq: sequence::Iter<'_, '_, Inner, Sha256> = decoder
+            .decode_seq(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+
+        let err = seq.next().unwrap().err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(1),
+                wire_type: field::WireType::Message,
+                length: 2,
+                max: 1
+            }
+        );
+        drop(seq);
+
+        // The sequence was never completed, so its digest can't be computed
+        assert!(decoder.peek().compute_digest().is_err());
+
+        // `{ 1: sequence<uint64>([1, 2, 3]) }`
+        let input = [47, 101, 3, 5, 7];
+
+        let limits = Limits {
+            max_sequence_elements: 2,
+            ..Limits::default()
+        };
+
+        let mut decoder = Decoder::new().with_limits(limits);
+        let mut seq: sequence::Iter<'_, '_, u64, Sha256> = decoder
+            .decode_seq(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+
+        assert_eq!(seq.next().unwrap(), Ok(1));
+        assert_eq!(seq.next().unwrap(), Ok(2));
+        assert_eq!(
+            seq.next().unwrap().err().unwrap().kind(),
+            error::Kind::TooManyElements {
+                tag: Tag::new_const(1),
+                max: 2
+            }
+        );
+    }
+
+    #[test]
+    fn max_depth_sequence() {
+        // `{ 1: sequence<message>([{ 0: uint64(42) }]) }`
+        let input = [47, 109, 5, 5, 85];
+
+        let mut decoder = Decoder::new().with_max_depth(3);
+        let mut input_ref = &input[..];
+        let seq: sequence::Iter<'_, '_, Inner, Sha256> = decoder
+            .decode_seq(Tag::new_const(1), &mut input_ref)
+            .unwrap();
+
+        for msg in seq {
+            assert_eq!(msg.unwrap(), Inner { value: 42 });
+        }
+
+        // Messages within the sequence are one level deeper than it
+        let mut decoder = Decoder::new().with_max_depth(2);
+        let mut input_ref = &input[..];
+        let mut seq: sequence::Iter<'_, '_, Inner, Sha256> = decoder
+            .decode_seq(Tag::new_const(1), &mut input_ref)
+            .unwrap();
+
+        let err = seq.next().unwrap().err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 3, max: 2 });
+        drop(seq);
+
+        // The sequence itself is one level deeper than its message
+        let mut decoder = Decoder::new().with_max_depth(1);
+        let mut input_ref = &input[..];
+        let err =
+            DecodeSeq::<Inner, Sha256>::decode_seq(&mut decoder, Tag::new_const(1), &mut input_ref)
+                .err()
+                .unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 2, max: 1 });
+    }
+
+    /// Skip every field in the given message, returning its digest
+    fn skip_all(decoder: &mut Decoder, mut input: &[u8]) -> Result<[u8; 32], Error> {
+        while !input.is_empty() {
+            let header = decoder.decode_header(&mut input)?;
+            decoder.skip(&header, &mut input)?;
+        }
+
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest)?;
+        Ok(digest)
+    }
+
+    #[test]
+    fn skip_every_wire_type() {
+        // `{ 1: uint64(42), 2: sint64(-42), 3: string("baz"), 4: true,
+        //    5: message({ 0: uint64(42) }), 6: sequence<uint64>([42, 43]),
+        //    7: bytes([]), 8: message({}),
+        //    9: sequence<message>([{ 0: uint64(42) }]), 10: false }`
+        let input = [
+            37, 85, 71, 167, 107, 7, 98, 97, 122, 131, 173, 5, 5, 85, 207, 69, 85, 87, 233, 1, 26,
+            2, 1, 94, 2, 109, 5, 5, 85, 130, 2,
+        ];
+
+        // Skipped fields are hashed the same as the schemaless digest
+        let digest = skip_all(&mut Decoder::new(), &input).unwrap();
+        assert_eq!(digest, verihash::<Sha256>(&input).unwrap().as_bytes());
+    }
+
+    #[test]
+    fn skip_validates_structure() {
+        // Nested messages within skipped fields are subject to depth limits
+        let input = chain(17, LEAF);
+        let err = skip_all(&mut Decoder::new(), &input).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 17, max: 16 });
+
+        // ...and length limits
+        let limits = Limits {
+            max_field_len: 4,
+            ..Limits::default()
+        };
+
+        let input = chain(3, LEAF);
+        let err = skip_all(&mut Decoder::new().with_limits(limits), &input)
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooLong {
+                tag: Tag::new_const(2),
+                wire_type: field::WireType::Bytes,
+                length: 5,
+                max: 4
+            }
+        );
+
+        // Garbage within a skipped message is rejected
+        // `{ 5: message({ 1: uint64(42), 0: uint64(42) }) }`
+        let input = [173, 9, 37, 85, 5, 85];
+        let err = skip_all(&mut Decoder::new(), &input).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::Order {
+                tag: Tag::new_const(0)
+            }
+        );
+    }
+
+    /// Compute the expected digest of a message containing a single sequence
+    /// field with the given tag and sequence digest
+    fn sequence_field_digest(tag: Tag, seq_digest: &verihash::DigestOutput<Sha256>) -> [u8; 32] {
+        let mut hasher = verihash::Hasher::<Sha256>::new_message();
+        hasher.field(tag, false);
+        hasher.fixed_size_value(field::WireType::Sequence, seq_digest);
+
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&hasher.finalize_message());
+        digest
+    }
+
+    #[test]
+    fn decode_uint64_seq_digest() {
+        let input = [47, 101, 3, 5, 7];
+        let mut input_ref = &input[..];
+        let mut decoder = Decoder::new();
+
+        let seq: sequence::Iter<'_, '_, u64, Sha256> = decoder
+            .decode_seq(Tag::new_const(1), &mut input_ref)
+            .unwrap();
+
+        for (expected, actual) in [1, 2, 3].iter().zip(seq) {
+            assert_eq!(*expected, actual.unwrap());
+        }
+
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest).unwrap();
+
+        let mut seq_hasher = verihash::Hasher::<Sha256>::new_sequence(field::WireType::UInt64);
+
+        for n in &[1, 2, 3] {
+            seq_hasher.uint64(*n);
+        }
+
+        seq_hasher.uint64(3);
+        assert_eq!(
+            digest,
+            sequence_field_digest(Tag::new_const(1), &seq_hasher.finalize())
+        );
+    }
+
+    #[test]
+    fn decode_message_seq_digest() {
+        let input = [47, 205, 5, 5, 85, 5, 5, 85];
+        let mut input_ref = &input[..];
+        let mut decoder = Decoder::new();
+
+        let seq: sequence::Iter<'_, '_, Inner, Sha256> = decoder
+            .decode_seq(Tag::new_const(1), &mut input_ref)
+            .unwrap();
+
+        for msg in seq {
+            assert_eq!(msg.unwrap(), Inner { value: 42 });
+        }
+
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest).unwrap();
+
+        let mut inner_hasher = verihash::Hasher::<Sha256>::new_message();
+        inner_hasher.tagged_uint64(Tag::new_const(0), false, 42);
+        let inner_digest = inner_hasher.finalize_message();
+
+        let mut seq_hasher = verihash::Hasher::<Sha256>::new_sequence(field::WireType::Message);
+
+        for _ in 0..2 {
+            seq_hasher.dynamically_sized_value(field::WireType::Message, 2);
+            seq_hasher.update(&inner_digest);
+        }
+
+        seq_hasher.uint64(2);
+        assert_eq!(
+            digest,
+            sequence_field_digest(Tag::new_const(1), &seq_hasher.finalize())
+        );
+    }
+
+    #[test]
+    fn scalar_seq_iter() {
+        let input = [47, 101, 3, 5, 7];
+        let mut decoder = Decoder::new();
+        let seq = decoder
+            .uint64_seq_iter(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+        assert_eq!(seq.map(Result::unwrap).sum::<u64>(), 6);
+
+        // `{ 1: sequence<sint64>([-1, 1]) }`
+        let input = [47, 71, 3, 5];
+        let mut decoder = Decoder::new();
+        let seq = decoder
+            .sint64_seq_iter(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+        assert_eq!(seq.map(Result::unwrap).sum::<i64>(), 0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn decode_scalar_seq() {
+        let input = [47, 101, 3, 5, 7];
+        let mut input_ref = &input[..];
+        let mut decoder = Decoder::new();
+        assert_eq!(
+            decoder
+                .decode_uint64_seq(Tag::new_const(1), &mut input_ref)
+                .unwrap(),
+            [1, 2, 3]
+        );
+        assert!(input_ref.is_empty());
+
+        let input = [47, 71, 3, 5];
+        let mut decoder = Decoder::new();
+        let values = decoder
+            .decode_sint64_seq(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+        assert_eq!(values, [-1, 1]);
+
+        // Empty sequence
+        let input = [47, 5];
+        let mut decoder = Decoder::new();
+        assert!(decoder
+            .decode_uint64_seq(Tag::new_const(1), &mut &input[..])
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn wire_type_mismatch() {
+        // `{ 4: string("baz") }`, decoded as `bytes`
+        let input = [139, 7, 98, 97, 122];
+        let err = Decoder::new()
+            .decode_bytes(Tag::new_const(4), &mut &input[..])
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::WireTypeMismatch {
+                tag: Tag::new_const(4),
+                expected: field::WireType::Bytes,
+                actual: field::WireType::String
+            }
+        );
+
+        let mut message = heapless::String::<U64>::new();
+        core::fmt::write(&mut message, format_args!("{}", err.kind())).unwrap();
+        assert_eq!(message, "field 4 has wire type `string`, expected `bytes`");
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn decode_scalar_seq_errors() {
+        // Element wire type mismatch
+        let input = [47, 101, 3, 5, 7];
+        let mut decoder = Decoder::new();
+        let err = decoder
+            .decode_sint64_seq(Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::WireTypeMismatch {
+                tag: Tag::new_const(1),
+                expected: field::WireType::SInt64,
+                actual: field::WireType::UInt64
+            }
+        );
+
+        // Final element truncated: its first byte indicates a 2-byte `vint64`,
+        // which is detected at the end of the sequence
+        let input = [47, 101, 3, 5, 2];
+        let mut decoder = Decoder::new();
+        let err = decoder
+            .decode_uint64_seq(Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(err.position(), Some(5));
+        assert_eq!(
+            err.kind(),
+            error::Kind::Decode {
+                element: Element::Value,
+                wire_type: field::WireType::UInt64
+            }
+        );
+
+        // Element count limit
+        let limits = Limits {
+            max_sequence_elements: 2,
+            ..Limits::default()
+        };
+
+        let input = [47, 101, 3, 5, 7];
+        let mut decoder = Decoder::new().with_limits(limits);
+        let err = decoder
+            .decode_uint64_seq(Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::TooManyElements {
+                tag: Tag::new_const(1),
+                max: 2
+            }
+        );
+    }
+
+    /// `{ 1: sequence<message>([{ 0: uint64(42) }, { 0: uint64(42) }, { 1: uint64(42) }]) }`
+    const MESSAGE_SEQ_BAD_THIRD_ELEMENT: &[u8] = &[47, 90, 2, 5, 5, 85, 5, 5, 85, 5, 37, 85];
+
+    #[test]
+    fn message_seq_iter() {
+        let mut decoder = Decoder::new();
+        let mut input = MESSAGE_SEQ_BAD_THIRD_ELEMENT;
+        let mut seq = decoder
+            .message_seq_iter::<Inner>(Tag::new_const(1), &mut input)
+            .unwrap();
+
+        // Elements are decoded on demand
+        assert_eq!(seq.next().unwrap().unwrap(), Inner { value: 42 });
+        assert_eq!(seq.next().unwrap().unwrap(), Inner { value: 42 });
+
+        let err = seq.next().unwrap().err().unwrap();
+        assert_eq!(err.index(), Some(2));
+        assert_eq!(err.position(), Some(11));
+        assert_eq!(err.tag(), Some(Tag::new_const(1)));
+        assert_eq!(
+            err.path().iter().collect::<heapless::Vec<_, U64>>(),
+            [Tag::new_const(1)]
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn decode_message_seq() {
+        let input = [47, 205, 5, 5, 85, 5, 5, 85];
+        let mut decoder = Decoder::new();
+        let messages = decoder
+            .decode_message_seq::<Inner>(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+
+        assert_eq!(messages, [Inner { value: 42 }, Inner { value: 42 }]);
+
+        let mut decoder = Decoder::new();
+        let err = decoder
+            .decode_message_seq::<Inner>(Tag::new_const(1), &mut &MESSAGE_SEQ_BAD_THIRD_ELEMENT[..])
+            .err()
+            .unwrap();
+
+        assert_eq!(err.index(), Some(2));
+
+        // Skipping a sequence whose third element has a repeated field:
+        // `{ 1: sequence<message>([.., .., { 0: uint64(42), 0: uint64(42) }]) }`
+        let mut decoder = Decoder::new();
+        let mut input = &[47, 218, 2, 5, 5, 85, 5, 5, 85, 9, 5, 85, 5, 85][..];
+        let header = decoder.decode_header(&mut input).unwrap();
+        let err = decoder.skip(&header, &mut input).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(0)
+            }
+        );
+        assert_eq!(err.index(), Some(2));
+    }
+
+    #[test]
+    fn decode_empty_seq_digest() {
+        let input = [47, 5];
+        let mut input_ref = &input[..];
+        let mut decoder = Decoder::new();
+
+        let mut seq: sequence::Iter<'_, '_, u64, Sha256> = decoder
+            .decode_seq(Tag::new_const(1), &mut input_ref)
+            .unwrap();
+
+        assert!(seq.next().is_none());
+        drop(seq);
+
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest).unwrap();
+
+        let mut seq_hasher = verihash::Hasher::<Sha256>::new_sequence(field::WireType::UInt64);
+        seq_hasher.uint64(0);
+        assert_eq!(
+            digest,
+            sequence_field_digest(Tag::new_const(1), &seq_hasher.finalize())
+        );
+    }
+
+    #[test]
+    fn decode_partial_seq_digest() {
+        let input = [47, 101, 3, 5, 7];
+        let mut input_ref = &input[..];
+        let mut decoder = Decoder::new();
+
+        let mut seq: sequence::Iter<'_, '_, u64, Sha256> = decoder
+            .decode_seq(Tag::new_const(1), &mut input_ref)
+            .unwrap();
+
+        assert_eq!(seq.next().unwrap().unwrap(), 1);
+        drop(seq);
+
+        let mut digest = [0u8; 32];
+        let err = decoder.fill_digest(&mut digest).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Poisoned { index: 3 });
+    }
+
+    /// `{ 1: sequence<sequence<uint64>>([[1, 2], [], [3]]) }`
+    const NESTED_SEQ: &[u8] = &[47, 207, 69, 3, 5, 5, 37, 7];
+
+    #[test]
+    fn nested_seq_iter() {
+        let mut decoder = Decoder::new();
+        let mut nested = decoder
+            .nested_seq_iter::<u64>(Tag::new_const(1), &mut &NESTED_SEQ[..])
+            .unwrap();
+
+        let mut seq_hasher = verihash::Hasher::<Sha256>::new_sequence(field::WireType::Sequence);
+        let mut count = 0;
+
+        for expected in &[&[1, 2][..], &[], &[3]] {
+            let seq = nested.next_seq().unwrap().unwrap();
+            let values = seq.map(Result::unwrap).collect::<heapless::Vec<u64, U64>>();
+            assert_eq!(&values, expected);
+
+            let mut inner_hasher =
+                verihash::Hasher::<Sha256>::new_sequence(field::WireType::UInt64);
+
+            for &n in *expected {
+                inner_hasher.uint64(n);
+            }
+
+            inner_hasher.uint64(expected.len() as u64);
+
+            let body_len = field::length::uint64_seq_body(*expected);
+            seq_hasher.dynamically_sized_value(field::WireType::Sequence, body_len);
+            seq_hasher.update(&inner_hasher.finalize());
+            count += 1;
+        }
+
+        assert!(nested.next_seq().is_none());
+        drop(nested);
+
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest).unwrap();
+
+        seq_hasher.uint64(count);
+        assert_eq!(
+            digest,
+            sequence_field_digest(Tag::new_const(1), &seq_hasher.finalize())
+        );
+
+        // The schemaless digest and encoder agree with the decoder
+        assert_eq!(digest, verihash::<Sha256>(NESTED_SEQ).unwrap().as_bytes());
+
+        let mut buffer = [0u8; 16];
+        let mut hasher = crate::encoder::Hasher::<Sha256>::new(&mut buffer);
+        hasher
+            .uint64_seq_seq(Tag::new_const(1), false, &[&[1, 2][..], &[], &[3]])
+            .unwrap();
+
+        let (encoded, encoder_digest) = hasher.finish();
+        assert_eq!(encoded, NESTED_SEQ);
+        assert_eq!(digest, encoder_digest[..]);
+
+        // Skipped nested sequences are hashed the same way
+        assert_eq!(skip_all(&mut Decoder::new(), NESTED_SEQ).unwrap(), digest);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn decode_seq_seq() {
+        let mut decoder = Decoder::new();
+        let seqs = decoder
+            .decode_uint64_seq_seq(Tag::new_const(1), &mut &NESTED_SEQ[..])
+            .unwrap();
+        assert_eq!(seqs, [&[1, 2][..], &[], &[3]]);
+
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder
+            .sint64_seq_seq(Tag::new_const(2), false, &[[-1, 1], [2, -2]])
+            .unwrap();
+        let encoded = encoder.finish();
+        assert_eq!(
+            encoded.len(),
+            field::length::sint64_seq_seq(Tag::new_const(2), &[[-1, 1], [2, -2]])
+        );
+
+        let mut decoder = Decoder::new();
+        let seqs = decoder
+            .decode_sint64_seq_seq(Tag::new_const(2), &mut &encoded[..])
+            .unwrap();
+        assert_eq!(seqs, [[-1, 1], [2, -2]]);
+    }
+
+    #[test]
+    fn nested_seq_errors() {
+        // `{ 1: sequence<sequence<sequence<uint64>>>([[[1]]]) }`
+        let input = [47, 111, 79, 37, 3];
+
+        let mut decoder = Decoder::new();
+        let mut nested = decoder
+            .nested_seq_iter::<u64>(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+        let err = nested.next_seq().unwrap().err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestedSequence);
+        assert_eq!(err.index(), Some(0));
+        drop(nested);
+
+        let err = skip_all(&mut Decoder::new(), &input).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestedSequence);
+
+        let err = verihash::<Sha256>(&input).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestedSequence);
+
+        // Nested sequences count towards the maximum nesting depth
+        let mut decoder = Decoder::new().with_max_depth(2);
+        let mut nested = decoder
+            .nested_seq_iter::<u64>(Tag::new_const(1), &mut &NESTED_SEQ[..])
+            .unwrap();
+        let err = nested.next_seq().unwrap().err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 3, max: 2 });
+    }
+
+    #[test]
+    fn peek_header() {
+        // `{ 1: uint64(42), 2: uint64(43) }`
+        let input = [37, 85, 69, 87];
+        let mut input_ref = &input[..];
+        let mut decoder = Decoder::new();
+
+        // Peeking doesn't consume the header
+        let expected = field::Header::new(Tag::new_const(1), false, field::WireType::UInt64);
+        assert_eq!(decoder.peek_header(input_ref).unwrap(), Some(expected));
+        assert_eq!(decoder.peek_header(input_ref).unwrap(), Some(expected));
+        assert_eq!(input_ref.len(), input.len());
+
+        let value: u64 = decoder.decode(Tag::new_const(1), &mut input_ref).unwrap();
+        assert_eq!(value, 42);
+
+        // Peeked headers are still subject to ordering rules
+        let err = decoder.peek_header(&input[..2]).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(1)
+            }
+        );
+
+        // Skipping a peeked field
+        let header = decoder.peek_header(input_ref).unwrap().unwrap();
+        assert_eq!(header.tag, Tag::new_const(2));
+        assert_eq!(decoder.decode_header(&mut input_ref).unwrap(), header);
+        decoder.skip(&header, &mut input_ref).unwrap();
+
+        // End of message
+        assert_eq!(decoder.peek_header(input_ref).unwrap(), None);
+
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest).unwrap();
+        assert_eq!(digest, verihash::<Sha256>(&input).unwrap().as_bytes());
+
+        // Truncated header
+        let input = [2];
+        let err = Decoder::new().peek_header(&input).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::FieldHeader {
+                tag: None,
+                wire_type: None
+            }
+        );
+    }
+
+    #[test]
+    fn peek_nested_header() {
+        for choice in &[Choice::Unsigned(42), Choice::Signed(-1)] {
+            let mut buffer = [0u8; 16];
+            let mut encoder = Encoder::new(&mut buffer);
+            encoder.message(Tag::new_const(3), false, choice).unwrap();
+            let input = encoder.finish();
+
+            let mut decoder = Decoder::new();
+            let decoded: Choice = decoder.decode(Tag::new_const(3), &mut &input[..]).unwrap();
+            assert_eq!(&decoded, choice);
+
+            let mut digest = [0u8; 32];
+            decoder.fill_digest(&mut digest).unwrap();
+            assert_eq!(digest, verihash::<Sha256>(input).unwrap().as_bytes());
+        }
+
+        // Errors peeking within a nested message include its path
+        // `{ 3: message([2]) }` (truncated header)
+        let input = [109, 3, 2];
+        let err = Decode::<Choice>::decode(&mut Decoder::new(), Tag::new_const(3), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::FieldHeader {
+                tag: None,
+                wire_type: None
+            }
+        );
+        assert!(err.path().iter().eq([Tag::new_const(3)].iter().cloned()));
+    }
+
+    #[test]
+    fn canonical_violations() {
+        // `{ 1: uint64(42), 2: uint64(43) }`
+        let canonical = [37, 85, 69, 87];
+        assert!(skip_all(&mut Decoder::new(), &canonical).is_ok());
+
+        // Near-canonical encodings of the same message, each violating one rule
+        let corpus: &[(&[u8], error::Kind)] = &[
+            // 2-byte `vint64` value
+            (&[37, 170, 0, 69, 87], error::Kind::NonCanonicalVInt64),
+            // 2-byte `vint64` field header
+            (&[74, 0, 85, 69, 87], error::Kind::NonCanonicalVInt64),
+            // Fields out of order
+            (
+                &[69, 87, 37, 85],
+                error::Kind::Order {
+                    tag: Tag::new_const(1),
+                },
+            ),
+            // Repeated field
+            (
+                &[37, 85, 37, 85],
+                error::Kind::DuplicateTag {
+                    tag: Tag::new_const(1),
+                },
+            ),
+        ];
+
+        for (input, kind) in corpus {
+            let err = skip_all(&mut Decoder::new(), input).err().unwrap();
+            assert_eq!(err.kind(), *kind);
+
+            let err = verihash::<Sha256>(input).err().unwrap();
+            assert_eq!(err.kind(), *kind);
+        }
+    }
+
+    #[test]
+    fn trailing_data() {
+        // `{ 1: message({ 0: uint64(42), 1: uint64(43) }) }`, where `Inner`
+        // only decodes field 0
+        let input = [45, 9, 5, 85, 37, 87];
+
+        let err = Decode::<Inner>::decode(&mut Decoder::new(), Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), error::Kind::TrailingData { remaining: 2 });
+        assert_eq!(err.position(), Some(4));
+        assert!(err.path().iter().eq([Tag::new_const(1)].iter().cloned()));
+
+        // Permissive decoders ignore the trailing field
+        let mut decoder = Decoder::new().permissive();
+        let inner: Inner = decoder.decode(Tag::new_const(1), &mut &input[..]).unwrap();
+        assert_eq!(inner, Inner { value: 42 });
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn trailing_data_in_sequence_element() {
+        // `{ 1: sequence<message>([{ 0: uint64(42), 1: uint64(43) }]) }`
+        let input = [47, 173, 9, 5, 85, 37, 87];
+        let err = Decoder::new()
+            .decode_message_seq::<Inner>(Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), error::Kind::TrailingData { remaining: 2 });
+        assert_eq!(err.index(), Some(0));
+    }
+
+    #[test]
+    fn top_level_trailing_data() {
+        // `{ 0: uint64(42) }` followed by a stray zero byte
+        let input = [5, 85, 0];
+
+        let err = Decoder::new()
+            .decode_message::<Inner>(&input)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), error::Kind::TrailingData { remaining: 1 });
+        assert_eq!(err.position(), Some(2));
+
+        let inner: Inner = Decoder::new().decode_message(&input[..2]).unwrap();
+        assert_eq!(inner, Inner { value: 42 });
+
+        // Callers which expect trailing data can opt out
+        let (inner, consumed) = Decoder::new().decode_prefix::<Inner>(&input).unwrap();
+        assert_eq!(inner, Inner { value: 42 });
+        assert_eq!(consumed, 2);
+    }
+
+    #[test]
+    fn duplicate_policy() {
+        // `{ 1: uint64(1), 1: uint64(2), 2: uint64(3) }`
+        let input = [37, 3, 37, 5, 69, 7];
+
+        let err = Decode::<u64>::decode(&mut Decoder::new(), Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(1)
+            }
+        );
+        assert_eq!(err.position(), Some(2));
+
+        for &(policy, expected) in &[(DuplicatePolicy::First, 1), (DuplicatePolicy::Last, 2)] {
+            let mut decoder = Decoder::new().with_duplicate_policy(policy);
+            let mut input = &input[..];
+            let value: u64 = decoder.decode(Tag::new_const(1), &mut input).unwrap();
+            assert_eq!(value, expected);
+            let value: u64 = decoder.decode(Tag::new_const(2), &mut input).unwrap();
+            assert_eq!(value, 3);
+        }
+
+        // `{ 1: message({ 0: uint64(5), 0: uint64(6) }), 1: message({ 0: uint64(7) }) }`
+        let input = [45, 9, 5, 11, 5, 13, 45, 5, 5, 15];
+
+        let err = Decode::<Inner>::decode(&mut Decoder::new(), Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(0)
+            }
+        );
+        assert_eq!(err.position(), Some(4));
+        assert!(err.path().iter().eq([Tag::new_const(1)].iter().cloned()));
+
+        for &(policy, expected) in &[(DuplicatePolicy::First, 5), (DuplicatePolicy::Last, 7)] {
+            let mut decoder = Decoder::new().with_duplicate_policy(policy);
+            let mut input = &input[..];
+            let inner: Inner = decoder.decode(Tag::new_const(1), &mut input).unwrap();
+            assert_eq!(inner, Inner { value: expected });
+            assert!(input.is_empty());
+        }
+
+        // The policy applies to the nested message on its own
+        let mut decoder = Decoder::new().with_duplicate_policy(DuplicatePolicy::Last);
+        let inner: Inner = decoder.decode(Tag::new_const(1), &mut &input[..6]).unwrap();
+        assert_eq!(inner, Inner { value: 6 });
+
+        // `{ 1: sequence<uint64>([1]), 1: sequence<uint64>([2]), 2: uint64(3) }`
+        let input = [47, 37, 3, 47, 37, 5, 69, 7];
+
+        let mut decoder = Decoder::new().with_duplicate_policy(DuplicatePolicy::First);
+        let mut input_ref = &input[..];
+        let seq = decoder
+            .uint64_seq_iter(Tag::new_const(1), &mut input_ref)
+            .unwrap();
+        assert!(seq.map(Result::unwrap).eq([1].iter().cloned()));
+        let value: u64 = decoder.decode(Tag::new_const(2), &mut input_ref).unwrap();
+        assert_eq!(value, 3);
+
+        // Sequences which were already iterated can't be overwritten
+        let mut decoder = Decoder::new().with_duplicate_policy(DuplicatePolicy::Last);
+        let mut input_ref = &input[..];
+        let seq = decoder
+            .uint64_seq_iter(Tag::new_const(1), &mut input_ref)
+            .unwrap();
+        assert_eq!(seq.count(), 1);
+        let err = Decode::<u64>::decode(&mut decoder, Tag::new_const(2), &mut input_ref)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(1)
+            }
+        );
+        assert_eq!(err.position(), Some(3));
+    }
+
+    #[test]
+    fn decode_stats() {
+        /// Visitor which ignores every value
+        struct Ignore;
+        impl super::Visitor<'_> for Ignore {}
+
+        // `{ 1: uint64(42), 3: string("baz"), 4: true,
+        //    5: message({ 0: uint64(42) }), 6: sequence<uint64>([42, 43]) }`
+        let input = [
+            37, 85, 107, 7, 98, 97, 122, 131, 173, 5, 5, 85, 207, 69, 85, 87,
+        ];
+
+        let mut decoder = Decoder::new().with_stats();
+        decoder
+            .decode_with_visitor(&mut &input[..], &mut Ignore)
+            .unwrap();
+
+        let stats = decoder.stats().unwrap();
+        assert_eq!(stats.messages, 2);
+        assert_eq!(stats.fields, 6);
+        assert_eq!(stats.max_fields, 5);
+        assert_eq!(stats.sequence_elements, 2);
+        assert_eq!(stats.max_depth, 2);
+        assert_eq!(stats.max_value_len, 3);
+        assert_eq!(stats.value_bytes(field::WireType::UInt64), 2);
+        assert_eq!(stats.value_bytes(field::WireType::String), 3);
+        assert_eq!(stats.value_bytes(field::WireType::Message), 2);
+        assert_eq!(stats.value_bytes(field::WireType::Sequence), 2);
+        assert_eq!(stats.value_bytes(field::WireType::Bytes), 0);
+
+        assert!(Decoder::new().stats().is_none());
+    }
+
+    #[test]
+    fn fuel() {
+        /// Visitor which ignores every value
+        struct Ignore;
+        impl super::Visitor<'_> for Ignore {}
+
+        // `{ 1: uint64(42), 3: string("baz"), 4: true,
+        //    5: message({ 0: uint64(42) }), 6: sequence<uint64>([42, 43]) }`
+        let input = [
+            37, 85, 107, 7, 98, 97, 122, 131, 173, 5, 5, 85, 207, 69, 85, 87,
+        ];
+
+        // 13 events in the outer message, 2 in the nested message, and 2 in
+        // the sequence
+        let mut decoder = Decoder::new().with_fuel(17);
+        decoder
+            .decode_with_visitor(&mut &input[..], &mut Ignore)
+            .unwrap();
+        assert_eq!(decoder.fuel_consumed(), Some(17));
+
+        for fuel in 0..17 {
+            let mut decoder = Decoder::new().with_fuel(fuel);
+            let err = decoder
+                .decode_with_visitor(&mut &input[..], &mut Ignore)
+                .err()
+                .unwrap();
+
+            assert_eq!(err.kind(), error::Kind::FuelExhausted { consumed: fuel });
+            assert_eq!(decoder.fuel_consumed(), Some(fuel));
+        }
+
+        assert!(Decoder::new().fuel_consumed().is_none());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn observer() {
+        use super::{Event, EventKind};
+        use alloc::vec::Vec;
+        use std::sync::{Arc, Mutex};
+
+        /// Visitor which ignores every value
+        struct Ignore;
+        impl super::Visitor<'_> for Ignore {}
+
+        // `{ 1: uint64(42), 3: string("baz"), 4: true,
+        //    5: message({ 0: uint64(42) }), 6: sequence<uint64>([42, 43]) }`
+        let input = [
+            37, 85, 107, 7, 98, 97, 122, 131, 173, 5, 5, 85, 207, 69, 85, 87,
+        ];
+
+        let observed = Arc::new(Mutex::new(Vec::new()));
+        let recorder = observed.clone();
+
+        let mut decoder = Decoder::new().with_observer(move |event: &Event<'_>, offset| {
+            recorder.lock().unwrap().push((offset, event.kind()));
+        });
+
+        decoder
+            .decode_with_visitor(&mut &input[..], &mut Ignore)
+            .unwrap();
+
+        // Events within the nested message and sequence are observed too
+        assert_eq!(
+            *observed.lock().unwrap(),
+            [
+                (0, EventKind::FieldHeader),
+                (1, EventKind::UInt64),
+                (2, EventKind::FieldHeader),
+                (3, EventKind::LengthDelimiter),
+                (4, EventKind::ValueChunk),
+                (7, EventKind::FieldHeader),
+                (8, EventKind::Bool),
+                (8, EventKind::FieldHeader),
+                (9, EventKind::LengthDelimiter),
+                (10, EventKind::ValueChunk),
+                (10, EventKind::FieldHeader),
+                (11, EventKind::UInt64),
+                (12, EventKind::FieldHeader),
+                (13, EventKind::SequenceHeader),
+                (14, EventKind::ValueChunk),
+                (14, EventKind::UInt64),
+                (15, EventKind::UInt64),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_optional() {
+        // `{ 1: uint64(42), 3: string("baz") }`
+        let input = [37, 85, 107, 7, 98, 97, 122];
+
+        let mut decoder = Decoder::new();
+        let mut input_ref = &input[..];
+        assert_eq!(
+            decoder.decode_optional::<u64>(Tag::new_const(0), &mut input_ref),
+            Ok(None)
+        );
+        assert_eq!(
+            decoder.decode_optional(Tag::new_const(1), &mut input_ref),
+            Ok(Some(42u64))
+        );
+        assert_eq!(
+            decoder.decode_optional_ref::<str>(Tag::new_const(2), &mut input_ref),
+            Ok(None)
+        );
+        assert_eq!(
+            decoder.decode_optional_ref::<str>(Tag::new_const(3), &mut input_ref),
+            Ok(Some("baz"))
+        );
+        assert_eq!(
+            decoder.decode_optional::<u64>(Tag::new_const(4), &mut input_ref),
+            Ok(None)
+        );
+        assert!(input_ref.is_empty());
+    }
+
+    #[test]
+    fn decode_into_buffer() {
+        // `{ 1: bytes("abc"), 3: string("baz") }`
+        let input = [41, 7, 97, 98, 99, 107, 7, 98, 97, 122];
+
+        let mut decoder = Decoder::new();
+        let mut input_ref = &input[..];
+        let mut out = [0u8; 3];
+        let len = decoder
+            .decode_bytes_into(Tag::new_const(1), &mut input_ref, &mut out)
+            .unwrap();
+        assert_eq!(&out[..len], b"abc");
+        let len = decoder
+            .decode_string_into(Tag::new_const(3), &mut input_ref, &mut out)
+            .unwrap();
+        assert_eq!(&out[..len], b"baz");
+        assert!(input_ref.is_empty());
+
+        let err = Decoder::new()
+            .decode_bytes_into(Tag::new_const(1), &mut &input[..], &mut out[..2])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), error::Kind::BufferTooSmall { needed: 3 });
+        assert_eq!(err.tag(), Some(Tag::new_const(1)));
+
+        let err = Decoder::new()
+            .decode_string_into(Tag::new_const(3), &mut &input[5..], &mut [])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), error::Kind::BufferTooSmall { needed: 3 });
+        assert_eq!(err.tag(), Some(Tag::new_const(3)));
+    }
+
+    /// Encode a map in field 1 containing the given entry message bodies
+    #[cfg(feature = "alloc")]
+    fn encode_map(entries: &[&[u8]]) -> alloc::vec::Vec<u8> {
+        let mut body = alloc::vec::Vec::new();
+
+        for entry in entries {
+            body.extend_from_slice(vint64::encode(entry.len() as u64).as_ref());
+            body.extend_from_slice(entry);
+        }
+
+        let mut encoded = vec![47];
+        encoded.extend_from_slice(vint64::encode((body.len() as u64) << 4 | 6).as_ref());
+        encoded.extend_from_slice(&body);
+        encoded
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn decode_map() {
+        use alloc::collections::BTreeMap;
+
+        // `{ 1: map({ 1: 10, 2: 20 }) }`
+        let input = encode_map(&[&[5, 3, 37, 21], &[5, 5, 37, 41]]);
+        let map: BTreeMap<u64, u64> = Decoder::new()
+            .decode_map(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+        assert!(map.iter().eq([(&1, &10), (&2, &20)].iter().cloned()));
+
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.map(Tag::new_const(1), false, &map).unwrap();
+        assert_eq!(encoder.finish(), &input[..]);
+        assert_eq!(field::length::map(Tag::new_const(1), &map), input.len());
+
+        // Empty maps have no entries
+        let input = encode_map(&[]);
+        let map: BTreeMap<u64, u64> = Decoder::new()
+            .decode_map(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+        assert!(map.is_empty());
+
+        // Entries missing a value
+        let input = encode_map(&[&[5, 3, 37, 21], &[5, 5]]);
+        let err = Decoder::new()
+            .decode_map::<u64, u64>(Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(err.index(), Some(1));
+        assert!(err.path().iter().eq([Tag::new_const(1)].iter().cloned()));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn map_key_order() {
+        use alloc::collections::BTreeMap;
+
+        // `{ 1: map({ 2: 20, 1: 10 }) }`
+        let input = encode_map(&[&[5, 5, 37, 41], &[5, 3, 37, 21]]);
+        let err = Decoder::new()
+            .decode_map::<u64, u64>(Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::KeyOrder {
+                tag: Tag::new_const(1)
+            }
+        );
+        assert_eq!(err.index(), Some(1));
+
+        let map: BTreeMap<u64, u64> = Decoder::new()
+            .permissive()
+            .decode_map(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+        assert!(map.iter().eq([(&1, &10), (&2, &20)].iter().cloned()));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn map_duplicate_keys() {
+        use crate::value::{Decodable, Encodable};
+        use alloc::{collections::BTreeMap, string::String};
+        use core::cmp::Ordering;
+
+        // `{ 1: map({ 1: 10, 1: 20 }) }`
+        let input = encode_map(&[&[5, 3, 37, 21], &[5, 3, 37, 41]]);
+        let err = Decoder::new()
+            .decode_map::<u64, u64>(Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateKey {
+                tag: Tag::new_const(1)
+            }
+        );
+        assert_eq!(err.index(), Some(1));
+
+        for &(policy, expected) in &[(DuplicatePolicy::First, 10), (DuplicatePolicy::Last, 20)] {
+            let map: BTreeMap<u64, u64> = Decoder::new()
+                .with_duplicate_key_policy(policy)
+                .decode_map(Tag::new_const(1), &mut &input[..])
+                .unwrap();
+            assert!(map.iter().eq([(&1, &expected)].iter().cloned()));
+        }
+
+        /// Case-insensitive string key
+        #[derive(Debug, Eq)]
+        struct Key(String);
+
+        impl Ord for Key {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.to_lowercase().cmp(&other.0.to_lowercase())
+            }
+        }
+
+        impl PartialOrd for Key {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl PartialEq for Key {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+
+        impl Decodable for Key {
+            const WIRE_TYPE: field::WireType = field::WireType::String;
+
+            fn decode_field<D: Digest>(
+                decoder: &mut super::Decoder<D>,
+                tag: Tag,
+                input: &mut &[u8],
+            ) -> Result<Self, Error> {
+                String::decode_field(decoder, tag, input).map(Key)
+            }
+        }
+
+        impl Encodable for Key {
+            const WIRE_TYPE: field::WireType = field::WireType::String;
+
+            fn encode_field(
+                &self,
+                encoder: &mut Encoder<'_>,
+                tag: Tag,
+                critical: bool,
+            ) -> Result<(), Error> {
+                self.0.encode_field(encoder, tag, critical)
+            }
+
+            fn encoded_field_len(&self, tag: Tag) -> usize {
+                self.0.encoded_field_len(tag)
+            }
+        }
+
+        // `{ 1: map({ "A": 10, "a": 20 }) }`: the keys are encoded
+        // differently, but are duplicates as far as the map is concerned
+        let input = encode_map(&[&[11, 3, 65, 37, 21], &[11, 3, 97, 37, 41]]);
+        let err = Decoder::new()
+            .decode_map::<Key, u64>(Tag::new_const(1), &mut &input[..])
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateKey {
+                tag: Tag::new_const(1)
+            }
+        );
+
+        let map: BTreeMap<Key, u64> = Decoder::new()
+            .with_duplicate_key_policy(DuplicatePolicy::Last)
+            .decode_map(Tag::new_const(1), &mut &input[..])
+            .unwrap();
+        let (key, value) = map.iter().next().unwrap();
+        assert_eq!((key.0.as_str(), *value), ("A", 20));
     }
 }
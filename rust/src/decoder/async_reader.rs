@@ -0,0 +1,461 @@
+//! Asynchronous streaming decoder which pulls messages from an [`AsyncRead`]
+
+use super::{
+    reader::{Buffered, DEFAULT_CAPACITY},
+    Event, Limits, ReadError,
+};
+use core::{
+    fmt::{self, Debug},
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use digest::Digest;
+use std::io;
+
+/// Sources of bytes which can be read asynchronously.
+///
+/// This has the same signature as the `AsyncRead` traits of the `futures-io`
+/// crate and (modulo its `ReadBuf` type) `tokio`. Readers implementing those
+/// traits can be used with [`AsyncReader`] via the [`FuturesRead`] and
+/// [`TokioRead`] adapters (enabled by the `async-futures` and `async-tokio`
+/// features respectively).
+pub trait AsyncRead {
+    /// Attempt to read bytes into `buf`, returning the number of bytes read
+    /// (with zero indicating EOF).
+    ///
+    /// If no data is available, returns [`Poll::Pending`] and arranges for
+    /// the current task to be woken when the reader becomes readable.
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>;
+}
+
+impl<R> AsyncRead for &mut R
+where
+    R: AsyncRead + Unpin + ?Sized,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+/// Asynchronous counterpart of [`super::Reader`] which reads a message from
+/// an [`AsyncRead`] on demand.
+///
+/// The futures returned by [`AsyncReader::next_event`] are cancellation safe:
+/// input is only consumed when a read completes, so if a future is dropped
+/// before completing, calling `next_event` again resumes where it left off
+/// without losing or repeating any events.
+pub struct AsyncReader<D: Digest, R: AsyncRead + Unpin> {
+    /// Inner reader
+    inner: R,
+
+    /// Buffered input and the decoder consuming it
+    buffered: Buffered<D>,
+}
+
+impl<D, R> AsyncReader<D, R>
+where
+    D: Digest,
+    R: AsyncRead + Unpin,
+{
+    /// Create a new [`AsyncReader`] with the default buffer capacity
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(inner, DEFAULT_CAPACITY)
+    }
+
+    /// Create a new [`AsyncReader`] whose refill buffer has the given
+    /// capacity, which is also the maximum size of the value chunks it emits.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_capacity(inner: R, capacity: usize) -> Self {
+        Self {
+            inner,
+            buffered: Buffered::new(capacity),
+        }
+    }
+
+    /// Set the limits on the lengths of the message's fields, which are
+    /// enforced as soon as their length delimiters are read
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.buffered.set_limits(limits);
+        self
+    }
+
+    /// Borrow the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwrap the inner reader, discarding any buffered data
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read the next event, returning `Ok(None)` once the reader reaches EOF
+    /// at a field boundary.
+    ///
+    /// Errors are reported the same way as [`super::Reader::next_event`].
+    pub async fn next_event(&mut self) -> Result<Option<Event<'_>>, ReadError> {
+        let (start, pending) = loop {
+            if let Some(decoded) = self.buffered.decode()? {
+                break decoded;
+            }
+
+            let fill = Fill {
+                inner: &mut self.inner,
+                buffered: &mut self.buffered,
+            };
+
+            if !fill.await? {
+                self.buffered.ensure_complete()?;
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(self.buffered.event(start, pending)))
+    }
+}
+
+impl<D, R> Debug for AsyncReader<D, R>
+where
+    D: Digest,
+    R: AsyncRead + Unpin + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("decoder::AsyncReader")
+            .field("inner", &self.inner)
+            .field("buffered", &self.buffered.len())
+            .finish()
+    }
+}
+
+/// Future which refills the buffer from the inner reader, resolving to
+/// `false` on EOF.
+///
+/// The buffer is only modified once a read completes, so dropping this future
+/// leaves the reader in a resumable state.
+struct Fill<'a, D: Digest, R: AsyncRead + Unpin> {
+    inner: &'a mut R,
+    buffered: &'a mut Buffered<D>,
+}
+
+impl<D, R> Future for Fill<'_, D, R>
+where
+    D: Digest,
+    R: AsyncRead + Unpin,
+{
+    type Output = Result<bool, ReadError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        loop {
+            match Pin::new(&mut *this.inner).poll_read(cx, this.buffered.spare()) {
+                Poll::Ready(Ok(n)) => return Poll::Ready(Ok(this.buffered.filled(n))),
+                Poll::Ready(Err(err)) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(ReadError::Io(err))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Adapter for readers which implement the `futures-io` crate's `AsyncRead`
+#[cfg(feature = "async-futures")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async-futures")))]
+#[derive(Debug)]
+pub struct FuturesRead<R>(R);
+
+#[cfg(feature = "async-futures")]
+impl<R> FuturesRead<R>
+where
+    R: futures_io::AsyncRead + Unpin,
+{
+    /// Wrap the given reader
+    pub fn new(inner: R) -> Self {
+        FuturesRead(inner)
+    }
+
+    /// Unwrap the inner reader
+    pub fn into_inner(self) -> R {
+        self.0
+    }
+}
+
+#[cfg(feature = "async-futures")]
+impl<R> AsyncRead for FuturesRead<R>
+where
+    R: futures_io::AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+/// Adapter for readers which implement `tokio`'s `AsyncRead`
+#[cfg(feature = "async-tokio")]
+#[cfg_attr(docsrs, doc(cfg(feature = "async-tokio")))]
+#[derive(Debug)]
+pub struct TokioRead<R>(R);
+
+#[cfg(feature = "async-tokio")]
+impl<R> TokioRead<R>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    /// Wrap the given reader
+    pub fn new(inner: R) -> Self {
+        TokioRead(inner)
+    }
+
+    /// Unwrap the inner reader
+    pub fn into_inner(self) -> R {
+        self.0
+    }
+}
+
+#[cfg(feature = "async-tokio")]
+impl<R> AsyncRead for TokioRead<R>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let mut read_buf = tokio::io::ReadBuf::new(buf);
+
+        Pin::new(&mut self.0)
+            .poll_read(cx, &mut read_buf)
+            .map_ok(|()| read_buf.filled().len())
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{AsyncRead, AsyncReader};
+    use crate::decoder::{event::Coalesced, EventBuf, ReadError, Reader};
+    use sha2::Sha256;
+    use std::{
+        boxed::Box,
+        future::Future,
+        io,
+        pin::Pin,
+        sync::Arc,
+        task::{Context, Poll, Wake, Waker},
+        vec::Vec,
+    };
+
+    /// `{ 1: uint64(42), 2: sint64(-42), 3: string("baz"), 4: true,
+    ///    5: message({ 0: uint64(42) }), 6: sequence<uint64>([42, 43]),
+    ///    100: bytes([0; 300]) }`
+    fn example_message() -> Vec<u8> {
+        let mut message = vec![
+            37, 85, 71, 167, 107, 7, 98, 97, 122, 131, 173, 5, 5, 85, 207, 69, 85, 87,
+        ];
+
+        // Field 100 has a 2-byte header, as does its length
+        message.extend_from_slice(&[18, 25, 178, 4]);
+        message.extend_from_slice(&[0; 300]);
+        message
+    }
+
+    /// Reader which yields at most one byte per poll, returning `Pending` (and
+    /// immediately waking the task) on every other poll
+    struct OneByteReader<'a> {
+        bytes: &'a [u8],
+        pending: bool,
+    }
+
+    impl<'a> OneByteReader<'a> {
+        fn new(bytes: &'a [u8]) -> Self {
+            Self {
+                bytes,
+                pending: false,
+            }
+        }
+    }
+
+    impl AsyncRead for OneByteReader<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            self.pending = !self.pending;
+
+            if self.pending {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+
+            let n = self.bytes.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.bytes[..n]);
+            self.bytes = &self.bytes[n..];
+            Poll::Ready(Ok(n))
+        }
+    }
+
+    /// Reader which always fails
+    struct BrokenReader;
+
+    impl AsyncRead for BrokenReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()))
+        }
+    }
+
+    /// Waker which does nothing, as every future is polled in a loop
+    struct NoopWaker;
+
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Poll the given future until it completes
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    /// Read all events from an async reader
+    fn read_all<R: AsyncRead + Unpin>(
+        reader: &mut AsyncReader<Sha256, R>,
+    ) -> Result<Vec<EventBuf>, ReadError> {
+        block_on(async {
+            let mut events = Coalesced::default();
+
+            while let Some(event) = reader.next_event().await? {
+                events.push(event);
+            }
+
+            Ok(events.0)
+        })
+    }
+
+    /// Read all events from a slice with the blocking reader
+    fn read_all_blocking(message: &[u8]) -> Result<Vec<EventBuf>, ReadError> {
+        let mut reader = Reader::<Sha256, _>::new(message);
+        let mut events = Coalesced::default();
+
+        while let Some(event) = reader.next_event()? {
+            events.push(event);
+        }
+
+        Ok(events.0)
+    }
+
+    #[test]
+    fn one_byte_per_poll() {
+        let message = example_message();
+        let expected = read_all_blocking(&message).unwrap();
+        assert_eq!(expected.len(), 18);
+
+        for &capacity in &[1, 2, 3, 4096] {
+            let inner = OneByteReader::new(&message);
+            let mut reader = AsyncReader::<Sha256, _>::with_capacity(inner, capacity);
+            assert_eq!(read_all(&mut reader).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn cancellation() {
+        let message = example_message();
+        let waker = Waker::from(Arc::new(NoopWaker));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut reader = AsyncReader::<Sha256, _>::with_capacity(OneByteReader::new(&message), 1);
+        let mut events = Coalesced::default();
+
+        // Drop each future the first time it returns `Pending`
+        loop {
+            let mut future = Box::pin(reader.next_event());
+
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(Ok(Some(event))) => events.push(event),
+                Poll::Ready(Ok(None)) => break,
+                Poll::Ready(Err(err)) => panic!("unexpected error: {}", err),
+                Poll::Pending => continue,
+            }
+        }
+
+        assert_eq!(events.0, read_all_blocking(&message).unwrap());
+    }
+
+    #[test]
+    fn truncated() {
+        let message = example_message();
+
+        for len in 0..message.len() {
+            let input = &message[..len];
+            let mut reader = AsyncReader::<Sha256, _>::new(OneByteReader::new(input));
+
+            match (read_all(&mut reader), read_all_blocking(input)) {
+                (Ok(actual), Ok(expected)) => assert_eq!(actual, expected),
+                (Err(ReadError::Decode(actual)), Err(ReadError::Decode(expected))) => {
+                    assert_eq!(actual, expected)
+                }
+                (actual, expected) => panic!("length {}: {:?} != {:?}", len, actual, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn io_error() {
+        let mut reader = AsyncReader::<Sha256, _>::new(BrokenReader);
+
+        match read_all(&mut reader) {
+            Err(ReadError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::ConnectionReset),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "async-tokio")]
+    #[tokio::test]
+    async fn tokio_read() {
+        use super::TokioRead;
+        use tokio::io::AsyncWriteExt;
+
+        let message = example_message();
+        let expected = read_all_blocking(&message).unwrap();
+
+        // A small duplex buffer forces the reader to wait on the writer
+        let (mut writer, inner) = tokio::io::duplex(7);
+        let input = message.clone();
+        let write = tokio::spawn(async move { writer.write_all(&input).await });
+
+        let mut reader = AsyncReader::<Sha256, _>::with_capacity(TokioRead::new(inner), 5);
+        let mut events = Coalesced::default();
+
+        while let Some(event) = reader.next_event().await.unwrap() {
+            events.push(event);
+        }
+
+        write.await.unwrap().unwrap();
+        assert_eq!(events.0, expected);
+    }
+}
@@ -0,0 +1,282 @@
+//! Decoder for messages stored in a [`Buf`], e.g. a chain of non-contiguous
+//! byte segments
+
+use super::{event::Pending, message, Decodable, Event, Limits};
+use crate::{error::Error, field::Path};
+use bytes::Buf;
+use core::mem;
+use digest::Digest;
+
+/// Decoder which reads a message from a [`Buf`] whose contents may be split
+/// across any number of non-contiguous segments (e.g. a chain of `Bytes`
+/// received from the network), without first copying them into a single
+/// contiguous buffer.
+///
+/// Field headers and `vint64`s which straddle segment boundaries are
+/// reassembled by the decoder, and the bodies of `bytes`, `string`,
+/// `message`, and `sequence` values are emitted as [`Event::ValueChunk`]s
+/// borrowed from whichever segments contain them.
+///
+/// Contiguous input should be decoded with the slice-based decoders, which
+/// borrow values in their entirety.
+pub struct BufDecoder<D: Digest, B: Buf> {
+    /// Input being decoded
+    inner: B,
+
+    /// Decoder for the message being decoded
+    decoder: message::Decoder<D>,
+
+    /// Number of bytes of the input which were consumed decoding the last
+    /// event, which are only advanced past once the event is no longer
+    /// borrowed
+    consumed: usize,
+}
+
+impl<D, B> BufDecoder<D, B>
+where
+    D: Digest,
+    B: Buf,
+{
+    /// Create a new [`BufDecoder`] which decodes the given input
+    pub fn new(inner: B) -> Self {
+        Self {
+            inner,
+            decoder: message::Decoder::without_hasher(),
+            consumed: 0,
+        }
+    }
+
+    /// Set the limits on the lengths of the message's fields, which are
+    /// enforced as soon as their length delimiters are decoded
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.decoder.set_limits(limits);
+        self
+    }
+
+    /// Get the total number of bytes consumed so far
+    pub fn position(&self) -> usize {
+        self.decoder.offset()
+    }
+
+    /// Unwrap the input, advanced past everything which has been decoded
+    pub fn into_inner(mut self) -> B {
+        self.inner.advance(self.consumed);
+        self.inner
+    }
+
+    /// Decode the next event, returning `Ok(None)` once the input is
+    /// exhausted at a field boundary.
+    ///
+    /// Running out of input in the middle of a field is reported as a
+    /// truncation error.
+    pub fn next_event(&mut self) -> Result<Option<Event<'_>>, Error> {
+        self.inner.advance(mem::take(&mut self.consumed));
+
+        let pending = loop {
+            // Booleans are emitted without consuming input, so decode even
+            // if the input is exhausted
+            let chunk = self.inner.chunk();
+            let mut input = chunk;
+            let event = self
+                .decoder
+                .decode(&mut input)
+                .map_err(|e| self.context(e))?;
+            let consumed = chunk.len() - input.len();
+
+            if let Some(event) = event.map(Pending::from) {
+                self.consumed = consumed;
+                break event;
+            }
+
+            self.inner.advance(consumed);
+
+            if !self.inner.has_remaining() {
+                self.decoder
+                    .ensure_complete()
+                    .map_err(|e| self.context(e))?;
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(match pending {
+            Pending::Event(event) => event,
+            // Value chunks consist of exactly the bytes consumed decoding them
+            Pending::ValueChunk {
+                wire_type,
+                remaining,
+            } => Event::ValueChunk {
+                wire_type,
+                bytes: &self.inner.chunk()[..self.consumed],
+                remaining,
+            },
+        }))
+    }
+
+    /// Add the current position and field to an error
+    fn context(&self, err: Error) -> Error {
+        err.with_context(self.position(), self.decoder.last_header(), Path::default())
+    }
+}
+
+#[cfg(all(test, feature = "sha2", feature = "alloc"))]
+mod tests {
+    use super::BufDecoder;
+    use crate::{
+        decoder::{event::Coalesced, Event, EventBuf, Events},
+        error,
+        field::{Header, Tag, WireType},
+        Encoder,
+    };
+    use alloc::vec::Vec;
+    use bytes::Buf;
+    use sha2::Sha256;
+
+    /// Input split into the given segments (like a chain of `Bytes`)
+    struct Segments<'a>(Vec<&'a [u8]>);
+
+    impl Buf for Segments<'_> {
+        fn remaining(&self) -> usize {
+            self.0.iter().map(|segment| segment.len()).sum()
+        }
+
+        fn chunk(&self) -> &[u8] {
+            let mut segments = self.0.iter().skip_while(|segment| segment.is_empty());
+            segments.next().cloned().unwrap_or_default()
+        }
+
+        fn advance(&mut self, mut cnt: usize) {
+            for segment in &mut self.0 {
+                let n = cnt.min(segment.len());
+                *segment = &segment[n..];
+                cnt -= n;
+            }
+
+            assert_eq!(cnt, 0, "advanced past the end of the input");
+        }
+    }
+
+    /// Message exercising every wire type, multi-byte headers and values,
+    /// nested messages, and empty values
+    fn example_message() -> Vec<u8> {
+        let mut buffer = [0u8; 1024];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+        encoder.sint64(Tag::new_const(2), false, -300).unwrap();
+        encoder.string(Tag::new_const(3), false, "baz").unwrap();
+        encoder.bytes(Tag::new_const(4), false, &[0; 200]).unwrap();
+        encoder.bool(Tag::new_const(5), false, true).unwrap();
+        encoder
+            .uint64_seq(Tag::new_const(6), false, &[1, 300, 70000])
+            .unwrap();
+        encoder.bytes(Tag::new_const(7), false, &[]).unwrap();
+        encoder
+            .uint64(Tag::new_const(1000), false, u64::MAX)
+            .unwrap();
+
+        let mut message = encoder.finish().to_vec();
+
+        // `1001: message({ 0: uint64(42) })`
+        let header = Header::new(Tag::new_const(1001), false, WireType::Message);
+        message.extend_from_slice(header.encode().as_ref());
+        message.extend_from_slice(&[5, 5, 85]);
+        message
+    }
+
+    /// Decode the given segments, checking value chunks are borrowed from
+    /// them
+    fn decode(segments: &[&[u8]]) -> Vec<EventBuf> {
+        let mut decoder = BufDecoder::<Sha256, _>::new(Segments(segments.to_vec()));
+        let mut events = Coalesced::default();
+
+        while let Some(event) = decoder.next_event().unwrap() {
+            if let Event::ValueChunk { bytes, .. } = event {
+                let start = bytes.as_ptr() as usize;
+
+                assert!(segments.iter().any(|segment| {
+                    let segment_start = segment.as_ptr() as usize;
+                    start >= segment_start && start + bytes.len() <= segment_start + segment.len()
+                }));
+            }
+
+            events.push(event);
+        }
+
+        events.0
+    }
+
+    /// Decode the example message contiguously
+    fn expected() -> Vec<EventBuf> {
+        let mut expected = Coalesced::default();
+
+        for event in Events::new(&example_message()) {
+            expected.push(event.unwrap());
+        }
+
+        expected.0
+    }
+
+    #[test]
+    fn matches_contiguous_decoding() {
+        let message = example_message();
+        let expected = expected();
+        assert_eq!(decode(&[&message]), expected);
+
+        for split in 0..=message.len() {
+            let (a, b) = message.split_at(split);
+            assert_eq!(decode(&[a, b]), expected, "split: {}", split);
+        }
+    }
+
+    #[test]
+    fn split_into_many_segments() {
+        let message = example_message();
+        let expected = expected();
+
+        for split in 0..message.len() {
+            // Split at the given position, then every few bytes after it
+            let (first, rest) = message.split_at(split);
+            let mut segments = vec![first];
+            segments.extend(rest.chunks(1 + split % 3));
+
+            assert_eq!(decode(&segments), expected, "split: {}", split);
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        // `{ 3: string("baz") }`, missing its last byte
+        let message = [107, 7, 98, 97, 122];
+        let (a, b) = message[..4].split_at(2);
+        let mut decoder = BufDecoder::<Sha256, _>::new(a.chain(b));
+        let mut err = None;
+
+        while err.is_none() {
+            match decoder.next_event() {
+                Ok(event) => assert!(event.is_some()),
+                Err(e) => err = Some(e),
+            }
+        }
+
+        let err = err.unwrap();
+        assert_eq!(err.position(), Some(4));
+        assert_eq!(
+            err.kind(),
+            error::Kind::Truncated {
+                remaining: 1,
+                wire_type: WireType::String
+            }
+        );
+    }
+
+    #[test]
+    fn into_inner() {
+        // `{ 1: uint64(42) }` followed by another message
+        let message = [37, 85, 37, 87];
+        let (a, b) = message.split_at(1);
+        let mut decoder = BufDecoder::<Sha256, _>::new(a.chain(b));
+
+        decoder.next_event().unwrap();
+        assert_eq!(decoder.next_event().unwrap(), Some(Event::UInt64(42)));
+        assert_eq!(decoder.into_inner().chunk(), &[37, 87]);
+    }
+}
@@ -4,7 +4,7 @@
 use super::Event;
 use crate::{
     error::{self, Error},
-    field::WireType,
+    field::{Tag, WireType},
     message::Element,
     string,
 };
@@ -16,6 +16,34 @@ pub(crate) trait Decodable {
     /// data processed, and returning any decoded events.
     fn decode<'a>(&mut self, input: &mut &'a [u8]) -> Result<Option<Event<'a>>, Error>;
 
+    /// Get the tag of the field being decoded (or of the field containing
+    /// the sequence being decoded), for errors
+    fn field_tag(&self) -> Tag;
+
+    /// Create an error for an unexpected event (or lack of one) when
+    /// decoding the given element of a value with the expected wire type.
+    ///
+    /// Events which are part of a value with another wire type are reported
+    /// as [`error::Kind::WireTypeMismatch`].
+    fn unexpected_event(
+        &self,
+        event: Option<&Event<'_>>,
+        element: Element,
+        expected: WireType,
+    ) -> Error {
+        if let Some(actual) = event.and_then(Event::wire_type) {
+            if let Err(err) = actual.check(self.field_tag(), expected) {
+                return err;
+            }
+        }
+
+        error::Kind::Decode {
+            element,
+            wire_type: expected,
+        }
+        .into()
+    }
+
     /// Decode a length delimited value, expecting the given wire type
     fn decode_dynamically_sized_value<'a>(
         &mut self,
@@ -27,11 +55,7 @@ pub(crate) trait Decodable {
     fn decode_uint64(&mut self, input: &mut &[u8]) -> Result<u64, Error> {
         match self.decode(input)? {
             Some(Event::UInt64(value)) => Ok(value),
-            _ => Err(error::Kind::Decode {
-                element: Element::Value,
-                wire_type: WireType::UInt64,
-            }
-            .into()),
+            event => Err(self.unexpected_event(event.as_ref(), Element::Value, WireType::UInt64)),
         }
     }
 
@@ -39,11 +63,7 @@ pub(crate) trait Decodable {
     fn decode_sint64(&mut self, input: &mut &[u8]) -> Result<i64, Error> {
         match self.decode(input)? {
             Some(Event::SInt64(value)) => Ok(value),
-            _ => Err(error::Kind::Decode {
-                element: Element::Value,
-                wire_type: WireType::SInt64,
-            }
-            .into()),
+            event => Err(self.unexpected_event(event.as_ref(), Element::Value, WireType::SInt64)),
         }
     }
 
@@ -56,11 +76,7 @@ pub(crate) trait Decodable {
     fn decode_string<'a>(&mut self, input: &mut &'a [u8]) -> Result<&'a str, Error> {
         let bytes = self.decode_dynamically_sized_value(WireType::String, input)?;
 
-        let s = str::from_utf8(bytes).map_err(|e| error::Kind::Utf8 {
-            valid_up_to: e.valid_up_to(),
-        })?;
-
-        string::ensure_canonical(s)
+        string::ensure_canonical(str::from_utf8(bytes)?)
     }
 
     /// Decode an expected `message` field, returning an error for anything else
@@ -75,7 +91,8 @@ pub(crate) trait Decodable {
         input: &mut &'a [u8],
     ) -> Result<&'a [u8], Error> {
         let length = match self.decode(input)? {
-            Some(Event::SequenceHeader { wire_type, length }) if wire_type == expected_type => {
+            Some(Event::SequenceHeader { wire_type, length }) => {
+                wire_type.check(self.field_tag(), expected_type)?;
                 length
             }
             _ => {
@@ -87,6 +104,11 @@ pub(crate) trait Decodable {
             }
         };
 
+        // Empty sequences have no body
+        if length == 0 {
+            return Ok(&[]);
+        }
+
         match self.decode(input)? {
             Some(Event::ValueChunk {
                 bytes, remaining, ..
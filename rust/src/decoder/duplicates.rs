@@ -0,0 +1,37 @@
+//! Policies for handling repeated fields in messages being decoded
+
+/// Policy for handling a message containing more than one field with the
+/// same tag.
+///
+/// Fields must be encoded in tag order, so repeated fields always directly
+/// follow one another: a duplicate is a field with the same tag as the
+/// previous field in the same message. The policy applies to each nested
+/// message independently.
+///
+/// Repeated fields are ambiguous: unless every consumer of a message agrees
+/// which one takes effect, they can disagree about what a signed message
+/// means. The [`Default`] is therefore to reject them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Reject repeated fields with
+    /// [`error::Kind::DuplicateTag`][`crate::error::Kind::DuplicateTag`],
+    /// reporting the offset of the second occurrence
+    Error,
+
+    /// Keep the first occurrence of a field, skipping any repeats of it
+    First,
+
+    /// Keep the last occurrence of a field, overwriting the value of any
+    /// previous occurrences.
+    ///
+    /// Sequences are returned as iterators before any repeats of them are
+    /// seen, so repeated sequence fields are still rejected.
+    Last,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for DuplicatePolicy {
+    fn default() -> Self {
+        DuplicatePolicy::Error
+    }
+}
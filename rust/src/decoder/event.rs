@@ -1,10 +1,17 @@
 //! Events emitted by Veriform's decoder
 
+// `displaydoc` emits its `Display` impls inside of an anonymous `const`
+#![allow(unknown_lints, non_local_definitions)]
+
 use crate::field::{Header, WireType};
+use displaydoc::Display;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 
 /// Events emitted by Veriform's decoder
 #[derive(Clone, Debug, Eq, PartialEq)]
-pub(crate) enum Event<'a> {
+pub enum Event<'a> {
     /// Consumed field header with the given tag and wire type
     FieldHeader(Header),
 
@@ -47,3 +54,282 @@ pub(crate) enum Event<'a> {
         length: usize,
     },
 }
+
+impl<'a> Event<'a> {
+    /// Get the [`EventKind`] of this event
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::FieldHeader(_) => EventKind::FieldHeader,
+            Event::Bool(_) => EventKind::Bool,
+            Event::UInt64(_) => EventKind::UInt64,
+            Event::SInt64(_) => EventKind::SInt64,
+            Event::LengthDelimiter { .. } => EventKind::LengthDelimiter,
+            Event::ValueChunk { .. } => EventKind::ValueChunk,
+            Event::SequenceHeader { .. } => EventKind::SequenceHeader,
+        }
+    }
+
+    /// Get the wire type of the value this event is part of, or `None` for
+    /// field headers
+    pub fn wire_type(&self) -> Option<WireType> {
+        match self {
+            Event::FieldHeader(_) => None,
+            Event::Bool(false) => Some(WireType::False),
+            Event::Bool(true) => Some(WireType::True),
+            Event::UInt64(_) => Some(WireType::UInt64),
+            Event::SInt64(_) => Some(WireType::SInt64),
+            Event::LengthDelimiter { wire_type, .. } | Event::ValueChunk { wire_type, .. } => {
+                Some(*wire_type)
+            }
+            Event::SequenceHeader { .. } => Some(WireType::Sequence),
+        }
+    }
+}
+
+/// Types which can be viewed as an [`Event`], e.g. so events can be hashed
+/// whether they borrow from the input or own their contents
+pub trait AsEvent {
+    /// Borrow this value as an [`Event`]
+    fn as_event(&self) -> Event<'_>;
+}
+
+impl AsEvent for Event<'_> {
+    fn as_event(&self) -> Event<'_> {
+        self.clone()
+    }
+}
+
+impl<T> AsEvent for &T
+where
+    T: AsEvent + ?Sized,
+{
+    fn as_event(&self) -> Event<'_> {
+        T::as_event(self)
+    }
+}
+
+/// Owned counterpart of [`Event`], which copies the contents of value
+/// chunks rather than borrowing them from the input.
+///
+/// Events can be recorded as a `Vec<EventBuf>` which outlives the input,
+/// e.g. to replay a decode session for debugging, or to hash it on another
+/// thread.
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EventBuf {
+    /// Consumed field header with the given tag and wire type
+    FieldHeader(Header),
+
+    /// Consumed a boolean value
+    Bool(bool),
+
+    /// Consumed an unsigned 64-bit integer
+    UInt64(u64),
+
+    /// Consumed a signed 64-bit integer
+    SInt64(i64),
+
+    /// Consumed a length delimiter for the given wire type
+    LengthDelimiter {
+        /// Wire type of the value this length delimits
+        wire_type: WireType,
+
+        /// Length of the field body (sans delimiter)
+        length: usize,
+    },
+
+    /// Consumed a chunk of a dynamically sized value
+    ValueChunk {
+        /// Wire type of the value being consumed
+        wire_type: WireType,
+
+        /// Bytes in this chunk
+        bytes: Vec<u8>,
+
+        /// Remaining bytes in the message
+        remaining: usize,
+    },
+
+    /// Consumed the header of a sequence of the given wiretype
+    SequenceHeader {
+        /// Wire type contained in this sequence
+        wire_type: WireType,
+
+        /// Length of the sequence body
+        length: usize,
+    },
+}
+
+#[cfg(feature = "alloc")]
+impl EventBuf {
+    /// Get the [`EventKind`] of this event
+    pub fn kind(&self) -> EventKind {
+        self.as_event().kind()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl AsEvent for EventBuf {
+    fn as_event(&self) -> Event<'_> {
+        match self {
+            EventBuf::FieldHeader(header) => Event::FieldHeader(*header),
+            EventBuf::Bool(value) => Event::Bool(*value),
+            EventBuf::UInt64(value) => Event::UInt64(*value),
+            EventBuf::SInt64(value) => Event::SInt64(*value),
+            EventBuf::LengthDelimiter { wire_type, length } => Event::LengthDelimiter {
+                wire_type: *wire_type,
+                length: *length,
+            },
+            EventBuf::ValueChunk {
+                wire_type,
+                bytes,
+                remaining,
+            } => Event::ValueChunk {
+                wire_type: *wire_type,
+                bytes,
+                remaining: *remaining,
+            },
+            EventBuf::SequenceHeader { wire_type, length } => Event::SequenceHeader {
+                wire_type: *wire_type,
+                length: *length,
+            },
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<&Event<'_>> for EventBuf {
+    fn from(event: &Event<'_>) -> EventBuf {
+        match *event {
+            Event::FieldHeader(header) => EventBuf::FieldHeader(header),
+            Event::Bool(value) => EventBuf::Bool(value),
+            Event::UInt64(value) => EventBuf::UInt64(value),
+            Event::SInt64(value) => EventBuf::SInt64(value),
+            Event::LengthDelimiter { wire_type, length } => {
+                EventBuf::LengthDelimiter { wire_type, length }
+            }
+            Event::ValueChunk {
+                wire_type,
+                bytes,
+                remaining,
+            } => EventBuf::ValueChunk {
+                wire_type,
+                bytes: bytes.to_vec(),
+                remaining,
+            },
+            Event::SequenceHeader { wire_type, length } => {
+                EventBuf::SequenceHeader { wire_type, length }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<Event<'_>> for EventBuf {
+    fn from(event: Event<'_>) -> EventBuf {
+        EventBuf::from(&event)
+    }
+}
+
+/// Events recorded with the chunks of each value coalesced into a single
+/// `ValueChunk`, so the events of decoders which chunk values differently
+/// can be compared
+#[cfg(all(test, feature = "alloc"))]
+#[derive(Debug, Default)]
+pub(crate) struct Coalesced(pub Vec<EventBuf>);
+
+#[cfg(all(test, feature = "alloc"))]
+impl Coalesced {
+    /// Record an event, appending value chunks to the value in progress
+    pub fn push<E: AsEvent>(&mut self, event: E) {
+        let event = event.as_event();
+
+        if let Event::ValueChunk {
+            bytes, remaining, ..
+        } = event
+        {
+            if let Some(EventBuf::ValueChunk {
+                bytes: value,
+                remaining: value_remaining,
+                ..
+            }) = self.0.last_mut()
+            {
+                if *value_remaining > 0 {
+                    value.extend_from_slice(bytes);
+                    *value_remaining = remaining;
+                    return;
+                }
+            }
+        }
+
+        self.0.push(event.into());
+    }
+}
+
+/// Kinds of [`Event`], sans their contents
+#[derive(Copy, Clone, Debug, Display, Eq, PartialEq)]
+pub enum EventKind {
+    /// field header
+    FieldHeader,
+
+    /// bool
+    Bool,
+
+    /// uint64
+    UInt64,
+
+    /// sint64
+    SInt64,
+
+    /// length delimiter
+    LengthDelimiter,
+
+    /// value chunk
+    ValueChunk,
+
+    /// sequence header
+    SequenceHeader,
+}
+
+/// Event which has been decoded but not yet returned, so value chunks can be
+/// re-borrowed from the buffered input once decoding is complete
+#[cfg(any(feature = "std", feature = "buf"))]
+pub(super) enum Pending {
+    /// Event which doesn't borrow from the buffer
+    Event(Event<'static>),
+
+    /// Chunk of a dynamically sized value
+    ValueChunk {
+        wire_type: WireType,
+        remaining: usize,
+    },
+}
+
+#[cfg(any(feature = "std", feature = "buf"))]
+impl From<Event<'_>> for Pending {
+    fn from(event: Event<'_>) -> Pending {
+        Pending::Event(match event {
+            Event::FieldHeader(header) => Event::FieldHeader(header),
+            Event::Bool(value) => Event::Bool(value),
+            Event::UInt64(value) => Event::UInt64(value),
+            Event::SInt64(value) => Event::SInt64(value),
+            Event::LengthDelimiter { wire_type, length } => {
+                Event::LengthDelimiter { wire_type, length }
+            }
+            Event::SequenceHeader { wire_type, length } => {
+                Event::SequenceHeader { wire_type, length }
+            }
+            Event::ValueChunk {
+                wire_type,
+                remaining,
+                ..
+            } => {
+                return Pending::ValueChunk {
+                    wire_type,
+                    remaining,
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,43 @@
+//! Fuel which bounds the amount of work done decoding a message
+
+use crate::error::{self, Error};
+
+/// Budget of decoding steps: each event decoded from a message or sequence
+/// consumes one unit of fuel.
+///
+/// A single budget is shared by a message and everything nested within it,
+/// and is handed to whichever message or sequence decoder is innermost.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Fuel {
+    /// Total amount of fuel available
+    limit: u64,
+
+    /// Amount of fuel consumed so far
+    consumed: u64,
+}
+
+impl Fuel {
+    /// Create a new budget with the given amount of fuel
+    pub fn new(limit: u64) -> Self {
+        Self { limit, consumed: 0 }
+    }
+
+    /// Get the amount of fuel consumed so far
+    pub fn consumed(self) -> u64 {
+        self.consumed
+    }
+
+    /// Consume one unit of fuel, returning [`error::Kind::FuelExhausted`] if
+    /// none is left
+    pub fn consume(&mut self) -> Result<(), Error> {
+        if self.consumed >= self.limit {
+            return Err(error::Kind::FuelExhausted {
+                consumed: self.consumed,
+            }
+            .into());
+        }
+
+        self.consumed += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+//! Incremental decoder for messages which arrive in arbitrarily sized chunks
+
+use super::{message, Decodable, Event, Limits};
+use crate::{error::Error, field::Path};
+use digest::Digest;
+
+/// Outcome of decoding with an [`Incremental`] decoder
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Status<'a> {
+    /// Decoded an event
+    Event(Event<'a>),
+
+    /// The input ran out before the next event could be decoded. This isn't
+    /// an error: decoding resumes where it left off once more input is
+    /// provided.
+    Incomplete {
+        /// Number of bytes needed to finish decoding the current field
+        /// header, `vint64` value, or value body. This is 1 at a field
+        /// boundary, where the length of the next header is unknown.
+        needed_hint: usize,
+    },
+}
+
+/// Incremental decoder which is fed a message's bytes as they arrive (e.g.
+/// from the network), in chunks of any size.
+///
+/// Field headers and `vint64`s split across chunks are reassembled by the
+/// decoder, and the bodies of `bytes`, `string`, `message`, and `sequence`
+/// values are emitted as [`Event::ValueChunk`]s borrowed from whichever
+/// chunks contain them.
+///
+/// Unlike [`Reader`][`super::Reader`], which pulls input from an
+/// [`std::io::Read`], it's up to the caller to supply more input whenever
+/// [`Status::Incomplete`] is returned, and to call [`Incremental::finish`]
+/// once there's none left.
+pub struct Incremental<D: Digest> {
+    /// Decoder for the message being decoded
+    decoder: message::Decoder<D>,
+}
+
+impl<D> Incremental<D>
+where
+    D: Digest,
+{
+    /// Create a new [`Incremental`] decoder
+    pub fn new() -> Self {
+        Self {
+            decoder: message::Decoder::without_hasher(),
+        }
+    }
+
+    /// Set the limits on the lengths of the message's fields, which are
+    /// enforced as soon as their length delimiters are decoded
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.decoder.set_limits(limits);
+        self
+    }
+
+    /// Get the total number of bytes consumed so far
+    pub fn position(&self) -> usize {
+        self.decoder.offset()
+    }
+
+    /// Decode the next event from the given input, advancing the slice past
+    /// the bytes which were consumed.
+    ///
+    /// Returns [`Status::Incomplete`] once all of the input has been
+    /// consumed without completing an event, after which this method can be
+    /// called again with more input.
+    pub fn decode<'a>(&mut self, input: &mut &'a [u8]) -> Result<Status<'a>, Error> {
+        loop {
+            // Booleans are emitted without consuming input, so decode even
+            // if the input is empty
+            match self.decoder.decode(input).map_err(|e| self.context(e))? {
+                Some(event) => return Ok(Status::Event(event)),
+                None if input.is_empty() => {
+                    return Ok(Status::Incomplete {
+                        needed_hint: self.decoder.needed(),
+                    })
+                }
+                None => (),
+            }
+        }
+    }
+
+    /// Finish decoding once there's no more input, returning an error if
+    /// the message ended in the middle of a field
+    pub fn finish(self) -> Result<(), Error> {
+        self.decoder.ensure_complete().map_err(|e| self.context(e))
+    }
+
+    /// Add the current position and field to an error
+    fn context(&self, err: Error) -> Error {
+        err.with_context(self.position(), self.decoder.last_header(), Path::default())
+    }
+}
+
+impl<D> Default for Incremental<D>
+where
+    D: Digest,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "sha2", feature = "alloc"))]
+mod tests {
+    use super::{Incremental, Status};
+    use crate::{
+        decoder::{event::Coalesced, Event, EventBuf, Events},
+        error,
+        field::{Tag, WireType},
+        Encoder,
+    };
+    use alloc::vec::Vec;
+    use sha2::Sha256;
+
+    /// Messages exercising every wire type, multi-byte headers and values,
+    /// and empty values
+    fn corpus() -> Vec<Vec<u8>> {
+        let mut corpus = Vec::new();
+        let mut buffer = [0u8; 1024];
+
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+        encoder.sint64(Tag::new_const(2), false, -300).unwrap();
+        encoder.string(Tag::new_const(3), false, "baz").unwrap();
+        encoder.bytes(Tag::new_const(4), false, &[0; 300]).unwrap();
+        encoder.bool(Tag::new_const(5), false, true).unwrap();
+        encoder.bool(Tag::new_const(6), true, false).unwrap();
+        encoder.bytes(Tag::new_const(7), false, &[]).unwrap();
+        encoder
+            .uint64(Tag::new_const(1000), false, u64::MAX)
+            .unwrap();
+        corpus.push(encoder.finish().to_vec());
+
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder
+            .uint64_seq(Tag::new_const(1), false, &[1, 300, 70000])
+            .unwrap();
+        encoder.sint64_seq(Tag::new_const(2), false, &[]).unwrap();
+        encoder
+            .uint64_seq_seq(Tag::new_const(3), false, &[&[1, 2][..], &[]])
+            .unwrap();
+        corpus.push(encoder.finish().to_vec());
+
+        // `{ 1: message({ 0: uint64(42) }), 2: message({}), 3: true }`
+        corpus.push(vec![45, 5, 5, 85, 77, 1, 99]);
+
+        // Empty message
+        corpus.push(vec![]);
+
+        corpus
+    }
+
+    /// Decode a message fed to the decoder in chunks of the given size
+    fn decode_chunked(message: &[u8], chunk_size: usize) -> Vec<EventBuf> {
+        let mut decoder = Incremental::<Sha256>::new();
+        let mut events = Coalesced::default();
+
+        for mut chunk in message.chunks(chunk_size) {
+            loop {
+                match decoder.decode(&mut chunk).unwrap() {
+                    Status::Event(event) => events.push(event),
+                    Status::Incomplete { needed_hint } => {
+                        assert!(needed_hint > 0);
+                        break;
+                    }
+                }
+            }
+        }
+
+        assert_eq!(decoder.position(), message.len());
+        decoder.finish().unwrap();
+        events.0
+    }
+
+    #[test]
+    fn matches_one_shot_decoding() {
+        for message in &corpus() {
+            let mut expected = Coalesced::default();
+
+            for event in Events::new(message) {
+                expected.push(event.unwrap());
+            }
+
+            for chunk_size in 1..=message.len().max(1) {
+                assert_eq!(
+                    decode_chunked(message, chunk_size),
+                    expected.0,
+                    "chunk size: {}",
+                    chunk_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn split_vint64() {
+        // `{ 1000: uint64(u64::MAX) }`: 2-byte header and 9-byte value
+        let message = &corpus()[0];
+        let field = &message[message.len() - 11..];
+        let mut decoder = Incremental::<Sha256>::new();
+
+        let mut input = &message[..message.len() - 11];
+        while let Status::Event(_) = decoder.decode(&mut input).unwrap() {}
+
+        let mut input = &field[..1];
+        assert_eq!(
+            decoder.decode(&mut input).unwrap(),
+            Status::Incomplete { needed_hint: 1 }
+        );
+
+        let mut input = &field[1..3];
+        assert!(matches!(
+            decoder.decode(&mut input).unwrap(),
+            Status::Event(Event::FieldHeader(header)) if header.tag == Tag::new_const(1000)
+        ));
+        assert_eq!(
+            decoder.decode(&mut input).unwrap(),
+            Status::Incomplete { needed_hint: 8 }
+        );
+
+        let mut input = &field[3..];
+        assert_eq!(
+            decoder.decode(&mut input).unwrap(),
+            Status::Event(Event::UInt64(u64::MAX))
+        );
+        decoder.finish().unwrap();
+    }
+
+    #[test]
+    fn value_ends_with_input() {
+        // `{ 3: string("baz") }`
+        let message = [107, 7, 98, 97, 122];
+        let mut decoder = Incremental::<Sha256>::new();
+
+        let mut input = &message[..3];
+        decoder.decode(&mut input).unwrap();
+        decoder.decode(&mut input).unwrap();
+        assert_eq!(
+            decoder.decode(&mut input).unwrap(),
+            Status::Event(Event::ValueChunk {
+                wire_type: WireType::String,
+                bytes: b"b",
+                remaining: 2,
+            })
+        );
+        assert_eq!(
+            decoder.decode(&mut input).unwrap(),
+            Status::Incomplete { needed_hint: 2 }
+        );
+
+        // The chunk boundary lands exactly at the end of the value
+        let mut input = &message[3..];
+        assert_eq!(
+            decoder.decode(&mut input).unwrap(),
+            Status::Event(Event::ValueChunk {
+                wire_type: WireType::String,
+                bytes: b"az",
+                remaining: 0,
+            })
+        );
+        assert_eq!(
+            decoder.decode(&mut input).unwrap(),
+            Status::Incomplete { needed_hint: 1 }
+        );
+        decoder.finish().unwrap();
+    }
+
+    #[test]
+    fn split_code_point() {
+        // `{ 3: string("a\u{1F980}b") }`, with a 4-byte code point
+        let message = [107, 13, 97, 0xF0, 0x9F, 0xA6, 0x80, 98];
+
+        for split in 0..=message.len() {
+            let mut decoder = Incremental::<Sha256>::new();
+            let mut string = Vec::new();
+
+            for mut chunk in [&message[..split], &message[split..]].iter().cloned() {
+                while let Status::Event(event) = decoder.decode(&mut chunk).unwrap() {
+                    if let Event::ValueChunk { bytes, .. } = event {
+                        string.extend_from_slice(bytes);
+                    }
+                }
+            }
+
+            decoder.finish().unwrap();
+            assert_eq!(string, "a\u{1F980}b".as_bytes(), "split: {}", split);
+        }
+    }
+
+    #[test]
+    fn overlong_encoding() {
+        // `{ 3: string("a\xC0\xAF") }`: an overlong encoding of `/`
+        let message = [107, 7, 97, 0xC0, 0xAF];
+
+        for split in 0..=message.len() {
+            let mut decoder = Incremental::<Sha256>::new();
+            let mut result = Ok(());
+
+            for mut chunk in [&message[..split], &message[split..]].iter().cloned() {
+                result = loop {
+                    match decoder.decode(&mut chunk) {
+                        Ok(Status::Event(_)) => (),
+                        Ok(Status::Incomplete { .. }) => break Ok(()),
+                        Err(e) => break Err(e),
+                    }
+                };
+
+                if result.is_err() {
+                    break;
+                }
+            }
+
+            let err = result.err().unwrap();
+            assert_eq!(err.kind(), error::Kind::Utf8 { valid_up_to: 1 });
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let message = [107, 7, 98, 97, 122];
+        let mut decoder = Incremental::<Sha256>::new();
+        let mut input = &message[..4];
+        while let Status::Event(_) = decoder.decode(&mut input).unwrap() {}
+
+        let err = decoder.finish().err().unwrap();
+        assert_eq!(err.position(), Some(4));
+        assert_eq!(
+            err.kind(),
+            error::Kind::Truncated {
+                remaining: 1,
+                wire_type: WireType::String
+            }
+        );
+    }
+}
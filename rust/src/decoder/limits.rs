@@ -0,0 +1,101 @@
+//! Limits on the lengths of values in messages being decoded
+
+use super::Event;
+use crate::{
+    error::{self, Error},
+    field::{Tag, WireType},
+};
+
+/// Default maximum length of a nested message (16 MiB)
+const DEFAULT_MAX_MESSAGE_LEN: usize = 0x100_0000;
+
+/// Default maximum length of a `bytes`, `string`, or `sequence` field (16 MiB)
+const DEFAULT_MAX_FIELD_LEN: usize = 0x100_0000;
+
+/// Default maximum number of elements in a sequence
+const DEFAULT_MAX_SEQUENCE_ELEMENTS: usize = 0x10000;
+
+/// Limits on the lengths of values in messages being decoded.
+///
+/// Length delimiters which exceed these limits are rejected as soon as they
+/// are decoded, before their bodies are processed, so callers can safely use
+/// a claimed length to e.g. preallocate a buffer. Limits apply recursively to
+/// nested messages.
+///
+/// The [`Default`] limits are generous but finite. Use [`Limits::unlimited`]
+/// to explicitly opt out of them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Maximum length of a nested message, including messages in sequences
+    pub max_message_len: usize,
+
+    /// Maximum length of a `bytes`, `string`, or `sequence` field, including
+    /// `bytes` and `string` values in sequences
+    pub max_field_len: usize,
+
+    /// Maximum number of elements in a sequence
+    pub max_sequence_elements: usize,
+}
+
+impl Limits {
+    /// Limits which accept values of any length
+    pub const fn unlimited() -> Self {
+        Self {
+            max_message_len: usize::MAX,
+            max_field_len: usize::MAX,
+            max_sequence_elements: usize::MAX,
+        }
+    }
+
+    /// Ensure the length claimed by the given event (if any) is within these
+    /// limits, reporting the given tag if it isn't
+    pub(crate) fn check_event(&self, tag: Tag, event: &Event<'_>) -> Result<(), Error> {
+        let (wire_type, length) = match *event {
+            Event::LengthDelimiter { wire_type, length } => (wire_type, length),
+            Event::SequenceHeader { length, .. } => (WireType::Sequence, length),
+            _ => return Ok(()),
+        };
+
+        let max = match wire_type {
+            WireType::Message => self.max_message_len,
+            _ => self.max_field_len,
+        };
+
+        if length > max {
+            return Err(error::Kind::TooLong {
+                tag,
+                wire_type,
+                length,
+                max,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Ensure a sequence with the given number of elements is within these
+    /// limits, reporting the given tag if it isn't
+    pub(crate) fn check_sequence_elements(&self, tag: Tag, count: usize) -> Result<(), Error> {
+        if count > self.max_sequence_elements {
+            return Err(error::Kind::TooManyElements {
+                tag,
+                max: self.max_sequence_elements,
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Limits {
+    /// 16 MiB for messages and fields, and 65,536 elements per sequence
+    fn default() -> Self {
+        Self {
+            max_message_len: DEFAULT_MAX_MESSAGE_LEN,
+            max_field_len: DEFAULT_MAX_FIELD_LEN,
+            max_sequence_elements: DEFAULT_MAX_SEQUENCE_ELEMENTS,
+        }
+    }
+}
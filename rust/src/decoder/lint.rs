@@ -0,0 +1,341 @@
+//! Diagnostics which report every problem found in a message, rather than
+//! just the first
+
+use super::DEFAULT_MAX_DEPTH;
+use crate::{
+    error::{self, Error},
+    field::{Header, Path, Tag, WireType},
+    spec, string,
+};
+use alloc::vec::Vec;
+use core::{
+    convert::TryFrom,
+    fmt::{self, Display},
+    str,
+};
+
+/// Problem found in a message by [`lint`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Issue {
+    /// Offset within the message at which the problem was found
+    pub offset: usize,
+
+    /// Tags of the field containing the problem and of the fields it's
+    /// nested within, outermost first (empty if the problem is in the
+    /// outermost message but not in a particular field)
+    pub path: Path,
+
+    /// Kind of problem
+    pub kind: error::Kind,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "offset {}", self.offset)?;
+
+        if !self.path.is_empty() {
+            write!(f, " (field {})", self.path)?;
+        }
+
+        write!(f, ": {}", self.kind)
+    }
+}
+
+/// Check a message for problems without a schema, reporting all of them
+/// rather than stopping at the first (e.g. to validate an archive of
+/// messages).
+///
+/// Recoverable problems are recorded and checking continues with the next
+/// field, including:
+///
+/// - non-minimal `vint64` encodings
+/// - fields whose tags are out-of-order or repeated
+/// - non-empty sequences of unsupported wire types, which are skipped
+/// - strings which aren't valid, canonical UTF-8
+/// - messages and sequences nested too deeply, which are skipped
+///
+/// Length delimiters which exceed the remaining input (or truncated field
+/// headers and `vint64`s) make it impossible to find the next field, so
+/// checking of the message containing them stops there. Messages they're
+/// nested within are still checked, since their lengths are known.
+///
+/// Issues are returned in the order they appear in the input.
+pub fn lint(input: &[u8]) -> Vec<Issue> {
+    let mut linter = Linter::default();
+    linter.message(Cursor::new(input), 1);
+    linter.issues
+}
+
+/// Input remaining in the message or sequence being checked
+#[derive(Copy, Clone, Debug)]
+struct Cursor<'a> {
+    /// Remaining input
+    bytes: &'a [u8],
+
+    /// Offset of the remaining input within the outermost message
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    /// Create a cursor for the outermost message
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    /// Is all of the input consumed?
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Take the given number of bytes from the input
+    fn take(&mut self, len: usize) -> Option<Cursor<'a>> {
+        if len > self.bytes.len() {
+            return None;
+        }
+
+        let (taken, rest) = self.bytes.split_at(len);
+        let cursor = Cursor {
+            bytes: taken,
+            offset: self.offset,
+        };
+
+        self.bytes = rest;
+        self.offset += len;
+        Some(cursor)
+    }
+}
+
+/// Checks messages, recording the issues it finds
+#[derive(Default)]
+struct Linter {
+    /// Tags of the fields being checked, outermost first
+    tags: Vec<Tag>,
+
+    /// Issues found so far
+    issues: Vec<Issue>,
+}
+
+impl Linter {
+    /// Record an issue at the given offset in the current field
+    fn issue(&mut self, offset: usize, kind: error::Kind) {
+        self.issues.push(Issue {
+            offset,
+            path: Path::new(&self.tags),
+            kind,
+        });
+    }
+
+    /// Check the fields of a message nested at the given depth
+    fn message(&mut self, mut input: Cursor<'_>, depth: usize) {
+        if !self.check_depth(input.offset, depth) {
+            return;
+        }
+
+        let mut last_tag = None;
+
+        while !input.is_empty() {
+            let offset = input.offset;
+
+            let header = match self.vint64(&mut input) {
+                Some(header) => header,
+                None => return,
+            };
+
+            let tag = Header::from(header).tag;
+            self.tags.push(tag);
+
+            match last_tag {
+                Some(last) if tag < last => self.issue(offset, error::Kind::Order { tag }),
+                Some(last) if tag == last => self.issue(offset, error::Kind::DuplicateTag { tag }),
+                _ => (),
+            }
+
+            last_tag = Some(tag);
+
+            let resynchronized = self.value(WireType::from_unmasked(header), &mut input, depth);
+            self.tags.pop();
+
+            if !resynchronized {
+                return;
+            }
+        }
+    }
+
+    /// Check the value of a field in a message nested at the given depth,
+    /// returning `false` if the next field can't be found
+    fn value(&mut self, wire_type: WireType, input: &mut Cursor<'_>, depth: usize) -> bool {
+        match wire_type {
+            WireType::False | WireType::True => true,
+            WireType::UInt64 | WireType::SInt64 => self.vint64(input).is_some(),
+            WireType::Bytes | WireType::String | WireType::Message => {
+                match self.delimited(wire_type, input) {
+                    Some(body) => {
+                        self.body(wire_type, body, depth);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            WireType::Sequence => match self.sequence_header(input) {
+                Some((wire_type, body)) => {
+                    self.sequence(wire_type, body, depth + 1);
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Check the body of a `bytes`, `string`, or `message` value in a
+    /// message or sequence nested at the given depth
+    fn body(&mut self, wire_type: WireType, body: Cursor<'_>, depth: usize) {
+        match wire_type {
+            WireType::String => {
+                let result = str::from_utf8(body.bytes)
+                    .map_err(Error::from)
+                    .and_then(string::ensure_canonical);
+
+                if let Err(err) = result {
+                    self.issue(body.offset, err.kind());
+                }
+            }
+            WireType::Message => self.message(body, depth + 1),
+            _ => (),
+        }
+    }
+
+    /// Check the elements of a sequence nested at the given depth
+    fn sequence(&mut self, wire_type: WireType, mut input: Cursor<'_>, depth: usize) {
+        if !self.check_depth(input.offset, depth) {
+            return;
+        }
+
+        match wire_type {
+            // Empty sequences have no elements to decode
+            WireType::False | WireType::True if !input.is_empty() => {
+                self.issue(input.offset, error::Kind::InvalidWireType);
+            }
+            WireType::False | WireType::True => (),
+            WireType::UInt64 | WireType::SInt64 => {
+                while !input.is_empty() {
+                    if self.vint64(&mut input).is_none() {
+                        return;
+                    }
+                }
+            }
+            WireType::Bytes | WireType::String | WireType::Message => {
+                while !input.is_empty() {
+                    match self.delimited(wire_type, &mut input) {
+                        Some(body) => self.body(wire_type, body, depth),
+                        None => return,
+                    }
+                }
+            }
+            WireType::Sequence => {
+                while !input.is_empty() {
+                    let offset = input.offset;
+
+                    let (wire_type, body) = match self.sequence_header(&mut input) {
+                        Some(header) => header,
+                        None => return,
+                    };
+
+                    // Only one level of sequence nesting is supported
+                    if wire_type == WireType::Sequence {
+                        self.issue(offset, error::Kind::NestedSequence);
+                    } else {
+                        self.sequence(wire_type, body, depth + 1);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Check that a message or sequence isn't nested too deeply to decode,
+    /// recording an issue if it is
+    fn check_depth(&mut self, offset: usize, depth: usize) -> bool {
+        if depth > DEFAULT_MAX_DEPTH {
+            let max = DEFAULT_MAX_DEPTH;
+            self.issue(offset, error::Kind::NestingDepth { depth, max });
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Read a sequence header and the sequence body it delimits
+    fn sequence_header<'a>(&mut self, input: &mut Cursor<'a>) -> Option<(WireType, Cursor<'a>)> {
+        let (length, wire_type) = spec::unpack_sequence_header(self.vint64(input)?);
+        let body = self.take(WireType::Sequence, input, length)?;
+        Some((WireType::from_unmasked(wire_type.into()), body))
+    }
+
+    /// Read a length delimiter and the value it delimits
+    fn delimited<'a>(&mut self, wire_type: WireType, input: &mut Cursor<'a>) -> Option<Cursor<'a>> {
+        let length = self.vint64(input)?;
+        self.take(wire_type, input, length)
+    }
+
+    /// Take a value of the given length from the input, recording an issue
+    /// if it exceeds the remaining input
+    fn take<'a>(
+        &mut self,
+        wire_type: WireType,
+        input: &mut Cursor<'a>,
+        length: u64,
+    ) -> Option<Cursor<'a>> {
+        let available = input.bytes.len();
+        let body = usize::try_from(length)
+            .ok()
+            .and_then(|length| input.take(length));
+
+        if body.is_none() {
+            let remaining = usize::try_from(length - available as u64).unwrap_or(usize::MAX);
+            self.issue(
+                input.offset,
+                error::Kind::Truncated {
+                    remaining,
+                    wire_type,
+                },
+            );
+        }
+
+        body
+    }
+
+    /// Read a `vint64`, recording an issue if it isn't minimally encoded, or
+    /// returning `None` if it's truncated
+    fn vint64(&mut self, input: &mut Cursor<'_>) -> Option<u64> {
+        let offset = input.offset;
+        let bytes = input
+            .bytes
+            .first()
+            .and_then(|&first| input.take(vint64::decoded_len(first)));
+
+        let bytes = match bytes {
+            Some(cursor) => cursor.bytes,
+            None => {
+                self.issue(offset, error::Kind::VInt64);
+                return None;
+            }
+        };
+
+        match vint64::decode(&mut &bytes[..]) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.issue(offset, error::Kind::NonCanonicalVInt64);
+
+                // The value is still readable, just not minimally encoded
+                let mut encoded = [0u8; 8];
+
+                Some(if bytes.len() == 9 {
+                    encoded.copy_from_slice(&bytes[1..]);
+                    u64::from_le_bytes(encoded)
+                } else {
+                    encoded[..bytes.len()].copy_from_slice(bytes);
+                    u64::from_le_bytes(encoded) >> bytes.len()
+                })
+            }
+        }
+    }
+}
@@ -2,9 +2,27 @@
 
 mod body;
 mod decoder;
+mod events;
 mod hasher;
 mod header;
 mod state;
 mod value;
 
+pub use self::events::Events;
+pub use self::hasher::{
+    HashEvents, Hasher, Position, RedactedField, ShapeHasher, StateDescription,
+};
+
+#[cfg(feature = "mac")]
+pub use self::hasher::{KeyedHasher, MacOutput};
+
+#[cfg(feature = "alloc")]
+pub use self::hasher::ResumableDigest;
+
+#[cfg(feature = "resumable-sha2")]
+pub use self::hasher::ResumableSha256;
+
+#[cfg(feature = "merkle")]
+pub use self::hasher::{Proof, TreeHasher};
+
 pub(crate) use self::decoder::Decoder;
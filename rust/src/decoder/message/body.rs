@@ -1,7 +1,12 @@
 //! Decoder for the bodies of variable-length field values
 
 use super::state::State;
-use crate::{decoder::Event, error::Error, field::WireType};
+use crate::{
+    decoder::Event,
+    error::{self, Error},
+    field::WireType,
+    string::Utf8Validator,
+};
 
 /// Decoder for the bodies of variable-length field values
 #[derive(Debug)]
@@ -11,6 +16,10 @@ pub(super) struct Decoder {
 
     /// Remaining bytes in this field body
     remaining: usize,
+
+    /// UTF-8 validator for `string` bodies, which may be split across
+    /// chunks at any byte
+    utf8: Option<Utf8Validator>,
 }
 
 impl Decoder {
@@ -27,12 +36,34 @@ impl Decoder {
         Self {
             wire_type,
             remaining: length,
+            utf8: if wire_type == WireType::String {
+                Some(Utf8Validator::default())
+            } else {
+                None
+            },
+        }
+    }
+
+    /// Error to return if the message ended before this body was decoded
+    pub fn truncation_error(&self) -> Error {
+        error::Kind::Truncated {
+            remaining: self.remaining,
+            wire_type: self.wire_type,
         }
+        .into()
+    }
+
+    /// Get the number of bytes needed to finish decoding the body
+    pub fn needed(&self) -> usize {
+        self.remaining
     }
 
     /// Process the given input data, advancing the slice for the amount of
     /// data processed, and returning the new state.
-    pub fn decode<'a>(self, input: &mut &'a [u8]) -> Result<(State, Option<Event<'a>>), Error> {
+    ///
+    /// `string` bodies are validated as UTF-8 as each chunk arrives, and
+    /// are definitively valid (or not) once the final chunk is decoded.
+    pub fn decode<'a>(mut self, input: &mut &'a [u8]) -> Result<(State, Option<Event<'a>>), Error> {
         if input.is_empty() {
             return Ok((self.into(), None));
         }
@@ -44,16 +75,32 @@ impl Decoder {
         };
 
         let bytes = &input[..chunk_size];
+        let remaining = self.remaining.checked_sub(chunk_size).unwrap();
+
+        if let Some(utf8) = &mut self.utf8 {
+            utf8.update(bytes)?;
+
+            if remaining == 0 {
+                utf8.finish()?;
+            }
+        }
+
         *input = &input[chunk_size..];
 
-        let remaining = self.remaining.checked_sub(chunk_size).unwrap();
         let event = Event::ValueChunk {
             wire_type: self.wire_type,
             bytes,
             remaining,
         };
 
-        let new_state = State::transition(&event);
+        // Keep decoding the body (along with its UTF-8 validation state)
+        // until it's finished
+        let new_state = if remaining > 0 {
+            Self { remaining, ..self }.into()
+        } else {
+            State::transition(&event)
+        };
+
         Ok((new_state, Some(event)))
     }
 }
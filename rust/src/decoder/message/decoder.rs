@@ -2,7 +2,7 @@
 
 use super::{hasher::Hasher, state::State};
 use crate::{
-    decoder::{Decodable, Event},
+    decoder::{fuel::Fuel, Decodable, DecodeStats, Event, Limits},
     error::{self, Error},
     field::{Header, Tag, WireType},
     message::Element,
@@ -11,11 +11,16 @@ use crate::{
 use core::fmt::{self, Debug};
 use digest::Digest;
 
+#[cfg(feature = "alloc")]
+use crate::decoder::Observer;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
 /// Veriform message decoder: streaming zero-copy pull parser which emits
 /// events based on incoming data.
 pub(crate) struct Decoder<D: Digest> {
-    /// Last field tag that was decoded (to ensure monotonicity)
-    last_tag: Option<Tag>,
+    /// Last field header that was decoded (to ensure monotonicity of tags)
+    last_header: Option<Header>,
 
     /// Current position within the message (i.e. total bytes consumed)
     position: usize,
@@ -28,6 +33,30 @@ pub(crate) struct Decoder<D: Digest> {
 
     /// Cached output digest
     cached_digest: Option<DigestOutput<D>>,
+
+    /// Limits on the lengths of values in this message
+    limits: Limits,
+
+    /// Offset of this message's body within the outermost message
+    offset: usize,
+
+    /// Accept consecutive fields with the same tag?
+    allow_duplicates: bool,
+
+    /// Statistics about this message and those nested within it, if enabled
+    stats: Option<DecodeStats>,
+
+    /// Number of fields decoded in this message (if statistics are enabled)
+    fields: usize,
+
+    /// Fuel consumed by each event, if this is the innermost decoder and a
+    /// budget was set
+    fuel: Option<Fuel>,
+
+    /// Observer of each event, if this is the innermost decoder and an
+    /// observer was set
+    #[cfg(feature = "alloc")]
+    observer: Option<Box<dyn Observer>>,
 }
 
 impl<D> Decoder<D>
@@ -38,10 +67,159 @@ where
     pub fn new() -> Self {
         Self {
             state: Some(State::default()),
-            last_tag: None,
+            last_header: None,
             position: 0,
             hasher: Some(Hasher::new()), // TODO(tarcieri): support for disabling hasher
             cached_digest: None,
+            limits: Limits::default(),
+            offset: 0,
+            allow_duplicates: false,
+            stats: None,
+            fields: 0,
+            fuel: None,
+            #[cfg(feature = "alloc")]
+            observer: None,
+        }
+    }
+
+    /// Set the limits on the lengths of values in this message
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Get the limits on the lengths of values in this message
+    #[cfg(feature = "alloc")]
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Accept fields with the same tag as the previous field, rather than
+    /// rejecting them with [`error::Kind::DuplicateTag`]
+    pub fn set_allow_duplicates(&mut self, allow_duplicates: bool) {
+        self.allow_duplicates = allow_duplicates;
+    }
+
+    /// Record statistics about this message, which is nested at the given
+    /// depth
+    pub fn enable_stats(&mut self, depth: usize) {
+        self.stats = Some(DecodeStats::new(depth));
+    }
+
+    /// Get the statistics recorded so far, if enabled
+    pub fn stats(&self) -> Option<DecodeStats> {
+        self.stats.map(|mut stats| {
+            stats.max_fields = stats.max_fields.max(self.fields);
+            stats
+        })
+    }
+
+    /// Merge the statistics of a nested message
+    pub fn merge_stats(&mut self, nested: &DecodeStats) {
+        if let Some(stats) = &mut self.stats {
+            stats.merge(nested);
+        }
+    }
+
+    /// Record statistics about a sequence with the given number of elements,
+    /// nested at the given depth
+    pub fn record_sequence(&mut self, depth: usize, elements: usize) {
+        if let Some(stats) = &mut self.stats {
+            stats.record_sequence(depth, elements);
+        }
+    }
+
+    /// Set the fuel consumed by each event decoded from this message
+    pub fn set_fuel(&mut self, fuel: Option<Fuel>) {
+        self.fuel = fuel;
+    }
+
+    /// Take the fuel from this decoder, e.g. to hand it to a nested decoder
+    pub fn take_fuel(&mut self) -> Option<Fuel> {
+        self.fuel.take()
+    }
+
+    /// Get the fuel held by this decoder, if any
+    pub fn fuel(&self) -> Option<Fuel> {
+        self.fuel
+    }
+
+    /// Set the observer of each event decoded from this message
+    #[cfg(feature = "alloc")]
+    pub fn set_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+        self.observer = observer;
+    }
+
+    /// Take the observer from this decoder, e.g. to hand it to a nested
+    /// decoder
+    #[cfg(feature = "alloc")]
+    pub fn take_observer(&mut self) -> Option<Box<dyn Observer>> {
+        self.observer.take()
+    }
+
+    /// Set the offset of this message's body within the outermost message
+    pub fn set_offset(&mut self, offset: usize) {
+        self.offset = offset;
+    }
+
+    /// Get the current position within this message (i.e. number of bytes
+    /// consumed)
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Get the current offset within the outermost message
+    pub fn offset(&self) -> usize {
+        self.offset.checked_add(self.position).unwrap()
+    }
+
+    /// Get the tag of the last field header decoded, i.e. of the field
+    /// currently being processed
+    pub fn last_tag(&self) -> Option<Tag> {
+        self.last_header.map(|header| header.tag)
+    }
+
+    /// Get the last field header decoded, i.e. of the field currently being
+    /// processed
+    pub fn last_header(&self) -> Option<Header> {
+        self.last_header
+    }
+
+    /// Create a new decoder which doesn't compute a Verihash digest
+    pub fn without_hasher() -> Self {
+        Self {
+            hasher: None,
+            ..Self::new()
+        }
+    }
+
+    /// Get the number of bytes needed to finish decoding the current field
+    /// header, `vint64` value, or value body
+    pub fn needed(&self) -> usize {
+        self.state.as_ref().map(State::needed).unwrap_or(0)
+    }
+
+    /// Ensure the decoder is at a field boundary, i.e. the input ended
+    /// cleanly rather than in the middle of a field
+    pub fn ensure_complete(&self) -> Result<(), Error> {
+        match &self.state {
+            Some(state) => state.ensure_complete(),
+            None => Err(error::Kind::Failed.into()),
+        }
+    }
+
+    /// Parse the header of the next field without consuming it, returning
+    /// `None` at the end of the message
+    pub fn peek_header(&self, input: &[u8]) -> Result<Option<Header>, Error> {
+        match &self.state {
+            Some(State::Header(header)) => {
+                header.peek(input, self.last_tag(), self.allow_duplicates)
+            }
+            Some(_) => Err(error::Kind::FieldHeader {
+                tag: None,
+                wire_type: None,
+            }
+            .into()),
+            None => Err(error::Kind::Failed.into()),
         }
     }
 
@@ -66,12 +244,11 @@ where
         wire_type: WireType,
     ) -> Result<(), Error> {
         let header = self.decode_header(input).map_err(|e| match e.kind() {
-            error::Kind::FieldHeader { .. } => error::Kind::FieldHeader {
+            error::Kind::FieldHeader { .. } => Error::from(error::Kind::FieldHeader {
                 tag: Some(tag),
                 wire_type: Some(wire_type),
-            }
-            .position(self.position),
-            _ => unreachable!("unexpected decode_header error: {:?}", e),
+            }),
+            _ => e,
         })?;
 
         // TODO(tarcieri): actually skip unrecognized/unknown fields
@@ -83,15 +260,12 @@ where
             .into());
         }
 
-        if header.wire_type != wire_type {
-            return Err(error::Kind::UnexpectedWireType {
-                actual: header.wire_type,
-                wanted: wire_type,
-            }
-            .into());
+        // `bool` fields have either boolean wire type, depending on their value
+        if wire_type == WireType::False && header.wire_type == WireType::True {
+            return Ok(());
         }
 
-        Ok(())
+        header.wire_type.check(tag, wire_type)
     }
 
     /// Decode a length delimiter, expecting the given wire type
@@ -110,11 +284,9 @@ where
             Some(Event::LengthDelimiter { wire_type, length }) if wire_type == expected_type => {
                 Ok(length)
             }
-            _ => Err(error::Kind::Decode {
-                element: Element::LengthDelimiter,
-                wire_type: expected_type,
+            event => {
+                Err(self.unexpected_event(event.as_ref(), Element::LengthDelimiter, expected_type))
             }
-            .into()),
         }
     }
 
@@ -140,6 +312,14 @@ where
         Ok(())
     }
 
+    /// Poison the message hasher (if enabled), causing subsequent attempts
+    /// to compute a digest to fail
+    pub fn poison_hasher(&mut self) {
+        if let Some(hasher) = &mut self.hasher {
+            hasher.poison();
+        }
+    }
+
     /// Compute a Verihash digest of the message we're decoding.
     ///
     /// This method is invoked from proc macro-generated code in order to
@@ -156,11 +336,11 @@ where
             // Make sure we're not in the middle of parsing a field
             // TODO(tarcieri): make sure we haven't partially consumed the header!
             if let Some(State::Header(_)) = self.state.take() {
-                let digest = Some(hasher.finish()?);
+                let digest = Some(hasher.finish()?.into_bytes());
                 self.cached_digest = digest.clone();
                 Ok(digest)
             } else {
-                Err(error::Kind::Hashing.into())
+                Err(error::HashingReason::Incomplete.into())
             }
         } else {
             Ok(None)
@@ -183,19 +363,44 @@ where
 {
     fn decode<'a>(&mut self, input: &mut &'a [u8]) -> Result<Option<Event<'a>>, Error> {
         if let Some(state) = self.state.take() {
-            let (new_state, event) = state.decode(input, self.last_tag)?;
+            #[cfg(feature = "alloc")]
+            let offset = self.offset();
+            let orig_input_len = input.len();
+            let (new_state, event) = state.decode(input, self.last_tag(), self.allow_duplicates)?;
+            let consumed = orig_input_len.checked_sub(input.len()).unwrap();
+
+            if let (Some(fuel), Some(_)) = (&mut self.fuel, &event) {
+                fuel.consume()?;
+            }
+
+            match &event {
+                Some(Event::FieldHeader(header)) => self.last_header = Some(*header),
+                Some(ev) => self
+                    .limits
+                    .check_event(self.last_tag().unwrap_or_default(), ev)?,
+                None => (),
+            }
+
+            if let (Some(stats), Some(ev)) = (&mut self.stats, &event) {
+                if let Event::FieldHeader(_) = ev {
+                    self.fields = self.fields.saturating_add(1);
+                }
 
-            if let Some(Event::FieldHeader(header)) = &event {
-                self.last_tag = Some(header.tag);
+                stats.record(ev);
             }
 
             self.state = Some(new_state);
-            self.position = self.position.checked_add(input.len()).unwrap();
+            self.position = self.position.checked_add(consumed).unwrap();
 
             if let Some(ev) = &event {
                 if let Some(hasher) = &mut self.hasher {
                     hasher.hash_event(ev)?;
                 }
+
+                #[cfg(feature = "alloc")]
+                if let Some(observer) = &mut self.observer {
+                    observer.observe(ev, offset);
+                }
             }
 
             Ok(event)
@@ -204,6 +409,10 @@ where
         }
     }
 
+    fn field_tag(&self) -> Tag {
+        self.last_tag().unwrap_or_default()
+    }
+
     fn decode_dynamically_sized_value<'a>(
         &mut self,
         expected_type: WireType,
@@ -211,6 +420,11 @@ where
     ) -> Result<&'a [u8], Error> {
         let length = self.decode_length_delimiter(input, expected_type)?;
 
+        // Empty values have no body
+        if length == 0 {
+            return Ok(&[]);
+        }
+
         match self.decode(input)? {
             Some(Event::ValueChunk {
                 wire_type,
@@ -243,19 +457,20 @@ where
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Decoder")
-            .field("last_tag", &self.last_tag)
+            .field("last_header", &self.last_header)
             .field("position", &self.position)
             .field("state", &self.state)
             .field("hasher", &self.hasher)
             .field("cached_digest", &self.cached_digest)
+            .field("limits", &self.limits)
             .finish()
     }
 }
 
 #[cfg(all(test, feature = "sha2"))]
 mod tests {
-    use super::{Decodable, WireType};
-    use crate::error;
+    use super::{Decodable, Event, WireType};
+    use crate::{error, field::Tag};
 
     type Decoder = super::Decoder<sha2::Sha256>;
 
@@ -266,8 +481,12 @@ mod tests {
         let mut decoder = Decoder::new();
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 42);
+        assert_eq!(header.tag, Tag::new_const(42));
         assert_eq!(header.wire_type, WireType::False);
+
+        let value = decoder.decode(&mut input_ref).unwrap();
+        assert_eq!(value, Some(Event::Bool(false)));
+        assert!(input_ref.is_empty());
     }
 
     #[test]
@@ -277,8 +496,12 @@ mod tests {
         let mut decoder = Decoder::new();
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 43);
+        assert_eq!(header.tag, Tag::new_const(43));
         assert_eq!(header.wire_type, WireType::True);
+
+        let value = decoder.decode(&mut input_ref).unwrap();
+        assert_eq!(value, Some(Event::Bool(true)));
+        assert!(input_ref.is_empty());
     }
 
     #[test]
@@ -288,7 +511,7 @@ mod tests {
         let mut decoder = Decoder::new();
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 42);
+        assert_eq!(header.tag, Tag::new_const(42));
         assert_eq!(header.wire_type, WireType::UInt64);
 
         let value = decoder.decode_uint64(&mut input_ref).unwrap();
@@ -303,7 +526,7 @@ mod tests {
         let mut decoder = Decoder::new();
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 43);
+        assert_eq!(header.tag, Tag::new_const(43));
         assert_eq!(header.wire_type, WireType::SInt64);
 
         let value = decoder.decode_sint64(&mut input_ref).unwrap();
@@ -318,7 +541,7 @@ mod tests {
         let mut decoder = Decoder::new();
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 2);
+        assert_eq!(header.tag, Tag::new_const(2));
         assert_eq!(header.wire_type, WireType::Bytes);
 
         let bytes = decoder.decode_bytes(&mut input_ref).unwrap();
@@ -333,7 +556,7 @@ mod tests {
         let mut decoder = Decoder::new();
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 4);
+        assert_eq!(header.tag, Tag::new_const(4));
         assert_eq!(header.wire_type, WireType::String);
 
         let string = decoder.decode_string(&mut input_ref).unwrap();
@@ -348,7 +571,7 @@ mod tests {
         let mut decoder = Decoder::new();
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 1);
+        assert_eq!(header.tag, Tag::new_const(1));
         assert_eq!(header.wire_type, WireType::Message);
 
         let message = decoder.decode_message(&mut input_ref).unwrap();
@@ -363,14 +586,14 @@ mod tests {
         let mut decoder = Decoder::new();
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 42);
+        assert_eq!(header.tag, Tag::new_const(42));
         assert_eq!(header.wire_type, WireType::UInt64);
 
         let value = decoder.decode_uint64(&mut input_ref).unwrap();
         assert_eq!(value, 42);
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 43);
+        assert_eq!(header.tag, Tag::new_const(43));
         assert_eq!(header.wire_type, WireType::SInt64);
 
         let value = decoder.decode_sint64(&mut input_ref).unwrap();
@@ -388,7 +611,7 @@ mod tests {
 
         input_ref = &input[1..];
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 42);
+        assert_eq!(header.tag, Tag::new_const(42));
         assert_eq!(header.wire_type, WireType::UInt64);
     }
 
@@ -399,13 +622,18 @@ mod tests {
         let mut decoder = Decoder::new();
 
         let header = decoder.decode_header(&mut input_ref).unwrap();
-        assert_eq!(header.tag, 43);
+        assert_eq!(header.tag, Tag::new_const(43));
         assert_eq!(header.wire_type, WireType::SInt64);
 
         let value = decoder.decode_sint64(&mut input_ref).unwrap();
         assert_eq!(value, -42);
 
         let error = decoder.decode(&mut input_ref).err().unwrap();
-        assert_eq!(error.kind(), error::Kind::Order { tag: 42 })
+        assert_eq!(
+            error.kind(),
+            error::Kind::Order {
+                tag: Tag::new_const(42)
+            }
+        )
     }
 }
@@ -0,0 +1,341 @@
+//! Iterator over the events in a serialized message
+
+use super::state::State;
+use crate::{
+    decoder::{Event, DEFAULT_MAX_DEPTH},
+    error::{self, Error},
+    field::{Tag, WireType},
+};
+use core::mem;
+use heapless::consts::U16;
+
+/// Iterator over the [`Event`]s in a message which is entirely contained in
+/// a byte slice, e.g. for inspecting messages without a schema.
+///
+/// Events are emitted in the order they're decoded: a `FieldHeader`
+/// followed by either the field's value, or its `LengthDelimiter` (or
+/// `SequenceHeader`) and a single `ValueChunk` containing its body.
+/// Iteration ends at the end of the slice, or after the first error.
+///
+/// When [`Events::recursive`] is enabled, nested messages are descended into
+/// and their events are emitted inline in lieu of the `ValueChunk` containing
+/// their body, with [`Events::depth`] indicating which message each event
+/// belongs to. Sequences are never descended into.
+///
+/// Events from messages without nested messages or sequences can be hashed
+/// with [`super::Hasher::hash_events`] (see [`super::HashEvents`]).
+#[derive(Debug)]
+pub struct Events<'a> {
+    /// Stack of messages being decoded, outermost first
+    stack: heapless::Vec<Level<'a>, U16>,
+
+    /// Descend into nested messages?
+    recursive: bool,
+
+    /// Has iteration finished, either at the end of the input or an error?
+    done: bool,
+}
+
+impl<'a> Events<'a> {
+    /// Iterate over the events in the message contained in the given slice
+    pub fn new(input: &'a [u8]) -> Self {
+        let mut stack = heapless::Vec::new();
+        stack.push(Level::new(input)).unwrap();
+
+        Self {
+            stack,
+            recursive: false,
+            done: false,
+        }
+    }
+
+    /// Descend into nested messages, emitting their events inline
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Get the depth of the message the last event was emitted from, where
+    /// the outermost message is at depth 1
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Decode the next event, descending into and returning from nested
+    /// messages as needed
+    fn decode(&mut self) -> Result<Option<Event<'a>>, Error> {
+        loop {
+            let level = self.stack.last_mut().unwrap();
+
+            match level.decode()? {
+                Some(Event::ValueChunk {
+                    wire_type: WireType::Message,
+                    bytes,
+                    remaining: 0,
+                }) if self.recursive => {
+                    let depth = self.stack.len().checked_add(1).unwrap();
+
+                    self.stack.push(Level::new(bytes)).map_err(|_| {
+                        Error::from(error::Kind::NestingDepth {
+                            depth,
+                            max: DEFAULT_MAX_DEPTH,
+                        })
+                    })?;
+                }
+                Some(event) => return Ok(Some(event)),
+                None => {
+                    level.ensure_complete()?;
+
+                    if self.stack.len() == 1 {
+                        return Ok(None);
+                    }
+
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = Result<Event<'a>, Error>;
+
+    fn next(&mut self) -> Option<Result<Event<'a>, Error>> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.decode();
+
+        if let Ok(None) | Err(_) = result {
+            self.done = true;
+        }
+
+        result.transpose()
+    }
+}
+
+/// Message being decoded by [`Events`]
+#[derive(Debug)]
+struct Level<'a> {
+    /// Current state of the decoder
+    state: State,
+
+    /// Last field tag that was decoded (to ensure monotonicity)
+    last_tag: Option<Tag>,
+
+    /// Remaining input in this message
+    input: &'a [u8],
+}
+
+impl<'a> Level<'a> {
+    /// Begin decoding the message in the given input
+    fn new(input: &'a [u8]) -> Self {
+        Self {
+            state: State::default(),
+            last_tag: None,
+            input,
+        }
+    }
+
+    /// Decode the next event in this message, returning `None` once all of
+    /// its input has been consumed
+    fn decode(&mut self) -> Result<Option<Event<'a>>, Error> {
+        let state = mem::take(&mut self.state);
+        let (state, event) = state.decode(&mut self.input, self.last_tag, false)?;
+
+        if let Some(Event::FieldHeader(header)) = &event {
+            self.last_tag = Some(header.tag);
+        }
+
+        self.state = state;
+        Ok(event)
+    }
+
+    /// Ensure the message ended at a field boundary
+    fn ensure_complete(&self) -> Result<(), Error> {
+        self.state.ensure_complete()
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::Events;
+    use crate::{
+        decoder::{Event, HashEvents},
+        error,
+        field::{Header, Tag, WireType},
+        verihash,
+    };
+    use heapless::{consts::U32, Vec};
+    use sha2::Sha256;
+
+    /// `{ 1: uint64(42), 3: string("baz"), 4: true,
+    ///    5: message({ 0: uint64(42) }), 6: sequence<uint64>([42, 43]) }`
+    const EXAMPLE_MESSAGE: &[u8] = &[
+        37, 85, 107, 7, 98, 97, 122, 131, 173, 5, 5, 85, 207, 69, 85, 87,
+    ];
+
+    /// Collect events along with the depth each was emitted at
+    fn collect(mut events: Events<'_>) -> Vec<(usize, Event<'_>), U32> {
+        let mut collected = Vec::new();
+
+        while let Some(event) = events.next() {
+            collected.push((events.depth(), event.unwrap())).unwrap();
+        }
+
+        collected
+    }
+
+    fn header(tag: Tag, wire_type: WireType) -> Event<'static> {
+        Event::FieldHeader(Header::new(tag, false, wire_type))
+    }
+
+    #[test]
+    fn flat() {
+        let expected = [
+            header(Tag::new_const(1), WireType::UInt64),
+            Event::UInt64(42),
+            header(Tag::new_const(3), WireType::String),
+            Event::LengthDelimiter {
+                wire_type: WireType::String,
+                length: 3,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::String,
+                bytes: b"baz",
+                remaining: 0,
+            },
+            header(Tag::new_const(4), WireType::True),
+            Event::Bool(true),
+            header(Tag::new_const(5), WireType::Message),
+            Event::LengthDelimiter {
+                wire_type: WireType::Message,
+                length: 2,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::Message,
+                bytes: &[5, 85],
+                remaining: 0,
+            },
+            header(Tag::new_const(6), WireType::Sequence),
+            Event::SequenceHeader {
+                wire_type: WireType::UInt64,
+                length: 2,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::Sequence,
+                bytes: &[85, 87],
+                remaining: 0,
+            },
+        ];
+
+        let events = collect(Events::new(EXAMPLE_MESSAGE));
+        assert_eq!(events.len(), expected.len());
+
+        for ((depth, actual), expected) in events.iter().zip(&expected) {
+            assert_eq!(*depth, 1);
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn recursive() {
+        let events = collect(Events::new(EXAMPLE_MESSAGE).recursive(true));
+        assert_eq!(events.len(), 14);
+
+        // The nested message's body is replaced by its events
+        assert_eq!(
+            events[7..11],
+            [
+                (1, header(Tag::new_const(5), WireType::Message)),
+                (
+                    1,
+                    Event::LengthDelimiter {
+                        wire_type: WireType::Message,
+                        length: 2
+                    }
+                ),
+                (2, header(Tag::new_const(0), WireType::UInt64)),
+                (2, Event::UInt64(42)),
+            ]
+        );
+
+        assert_eq!(
+            events[11],
+            (1, header(Tag::new_const(6), WireType::Sequence))
+        );
+    }
+
+    #[test]
+    fn hash_events() {
+        // Fields 1-4 of the example message, which contain no nested messages
+        let input = &EXAMPLE_MESSAGE[..8];
+        let events = Events::new(input)
+            .collect::<Result<Vec<_, U32>, _>>()
+            .unwrap();
+
+        assert_eq!(
+            events.iter().verihash::<Sha256>().unwrap(),
+            verihash::<Sha256>(input).unwrap()
+        );
+    }
+
+    #[test]
+    fn trailing_error() {
+        // Truncated within the body of field 3
+        let mut events = Events::new(&EXAMPLE_MESSAGE[..5]);
+
+        assert_eq!(
+            events.next(),
+            Some(Ok(header(Tag::new_const(1), WireType::UInt64)))
+        );
+        assert_eq!(events.next(), Some(Ok(Event::UInt64(42))));
+        assert_eq!(
+            events.next(),
+            Some(Ok(header(Tag::new_const(3), WireType::String)))
+        );
+        assert!(events.next().unwrap().is_ok());
+        assert!(events.next().unwrap().is_ok());
+
+        let err = events.next().unwrap().err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::Truncated {
+                remaining: 2,
+                wire_type: WireType::String
+            }
+        );
+        assert_eq!(events.next(), None);
+
+        // Repeated fields
+        let mut events = Events::new(&[37, 85, 37, 85]);
+        assert!(events.next().unwrap().is_ok());
+        assert!(events.next().unwrap().is_ok());
+
+        let err = events.next().unwrap().err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(1)
+            }
+        );
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn nested_error() {
+        // `{ 5: message({ 0: uint64(42), 0: uint64(42) }) }`
+        let input = [173, 9, 5, 85, 5, 85];
+        let events = Events::new(&input).recursive(true);
+        let results = events.collect::<Vec<_, U32>>();
+
+        assert_eq!(results.len(), 5);
+        assert_eq!(
+            results[4].clone().err().unwrap().kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(0)
+            }
+        );
+    }
+}
@@ -3,33 +3,117 @@
 //! WARNING: this is an experimental PoC-quality implementation!
 //! It is NOT suitable for production use!
 //!
-//! # TODO
+//! Nested messages are hashed structurally: `drive_decoder` decodes each
+//! one's body with a child transcript via `nested_digest`, recursing at most
+//! as deep as configured with [`Hasher::with_max_depth`] (16 by default).
+//! The child digest is then absorbed into the parent transcript via
+//! [`Hasher::hash_message_digest`] in lieu of the nested message's
+//! serialized bytes. Sequences are handled similarly, with their digest
+//! computed by the sequence hasher and absorbed via
+//! [`Hasher::hash_sequence_digest`].
 //!
-//! - Nested message hashing
-//! - Sequence hashing
+//! String fields are hashed as the exact bytes which were serialized, which
+//! must be valid UTF-8. Although the `Encoder` restricts strings to ASCII,
+//! the hasher accepts any UTF-8 (e.g. via [`Hasher::hash_message`] or
+//! [`HashEvents::verihash`]), so canonically equivalent strings can hash
+//! differently. With the `unicode-normalization` feature, hashers created
+//! with `Hasher::new_nfc` normalize strings to NFC before absorbing them.
 
-// TODO(tarcieri): tests and test vectors!!!
 // TODO(tarcieri): DRY out repeated logic in sequence hasher
 
+mod description;
+#[cfg(feature = "mac")]
+mod keyed;
+#[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+mod nfc;
+mod redacted;
+mod shape;
+#[cfg(feature = "alloc")]
+mod snapshot;
+#[cfg(feature = "merkle")]
+mod tree;
+
+pub use self::{
+    description::{Position, StateDescription},
+    redacted::RedactedField,
+    shape::ShapeHasher,
+};
+
+#[cfg(feature = "mac")]
+pub use self::keyed::{KeyedHasher, MacOutput};
+
+#[cfg(feature = "alloc")]
+pub use self::snapshot::ResumableDigest;
+
+#[cfg(feature = "resumable-sha2")]
+pub use self::snapshot::ResumableSha256;
+
+#[cfg(feature = "merkle")]
+pub use self::tree::{Proof, TreeHasher};
+
+use super::Decoder;
 use crate::{
-    decoder::Event,
+    decoder::{AsEvent, Decodable, Event},
     error::{self, Error},
-    field::{self, Tag, WireType},
-    verihash::{self, DigestOutput},
+    field::{self, Path, Tag, WireType},
+    string::Utf8Validator,
+    verihash::{
+        self,
+        schemaless::{self, Depth},
+        DigestOutput, Output,
+    },
 };
 use core::fmt::{self, Debug};
 use digest::Digest;
 
+#[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+use alloc::vec::Vec;
+
 /// Verihash message hasher.
 ///
 /// This type computes a hash-based transcript of how a message was
 /// decoded, driven by incoming decoding events.
-pub(super) struct Hasher<D: Digest> {
+///
+/// When `D` is `Clone`, so is the hasher, which allows a transcript to be
+/// forked after hashing a common prefix of fields.
+#[derive(Clone)]
+pub struct Hasher<D: Digest> {
     /// Verihash hasher
     verihash: verihash::Hasher<D>,
 
     /// Current state of the decoder (or `None` if an error occurred)
     state: Option<State>,
+
+    /// Number of events hashed since construction or the last reset (i.e.
+    /// the index of the event which failed if the hasher is poisoned)
+    events: usize,
+
+    /// Number of bytes of `bytes` and `string` values absorbed since
+    /// construction or the last reset
+    value_bytes: u64,
+
+    /// Verihash hasher as it was after construction, if it absorbed a prefix
+    /// (e.g. a domain separation string) which must be restored on reset
+    initial: Option<verihash::Hasher<D>>,
+
+    /// Index of the currently open message frame (if any)
+    frame: Option<u64>,
+
+    /// Number of message frames which have been begun
+    frames: u64,
+
+    /// Maximum nesting depth of messages hashed by `hash_message`
+    max_depth: usize,
+
+    /// Are `bytes` and `string` fields absorbed as the digests of their
+    /// transcripts, so they can be redacted?
+    redaction: bool,
+
+    /// Header and transcript of the redactable field currently being hashed
+    redacting: Option<(field::Header, verihash::Hasher<D>)>,
+
+    /// Are `string` fields normalized to NFC before being absorbed?
+    nfc: bool,
 }
 
 impl<D> Hasher<D>
@@ -38,33 +122,207 @@ where
 {
     /// Create a new [`Hasher`]
     pub fn new() -> Self {
+        Self::from_verihash(verihash::Hasher::new())
+    }
+
+    /// Create a new [`Hasher`] from the given Verihash hasher, which may
+    /// have already absorbed a prefix
+    fn from_verihash(mut verihash: verihash::Hasher<D>) -> Self {
+        verihash.message_begin();
+
         Self {
-            verihash: verihash::Hasher::new(),
+            verihash,
             state: Some(State::default()),
+            events: 0,
+            value_bytes: 0,
+            initial: None,
+            frame: None,
+            frames: 0,
+            max_depth: schemaless::DEFAULT_MAX_DEPTH,
+            redaction: false,
+            redacting: None,
+            nfc: false,
         }
     }
 
-    /// Hash an incoming event
-    pub fn hash_event(&mut self, event: &Event<'_>) -> Result<(), Error> {
-        if let Some(state) = self.state.take() {
-            let new_state = state.transition(event, &mut self.verihash)?;
-            self.state = Some(new_state);
-            Ok(())
-        } else {
-            Err(error::Kind::Failed.into())
+    /// Set the maximum nesting depth of messages hashed by
+    /// [`Hasher::hash_message`], counting the outermost message as depth 1.
+    ///
+    /// Messages nested any deeper (including those within sequences) are
+    /// rejected with [`error::Kind::NestingDepth`]. The default is 16,
+    /// matching the `Decoder`.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Hash an incoming event.
+    ///
+    /// Events which are invalid in the hasher's current state are reported
+    /// as [`error::Kind::Transcript`], and any subsequent calls on the
+    /// (now poisoned) hasher return [`error::Kind::Poisoned`].
+    ///
+    /// Accepts anything which can be viewed as an [`Event`], including a
+    /// recorded [`EventBuf`][`crate::decoder::EventBuf`].
+    pub fn hash_event<E>(&mut self, event: &E) -> Result<(), Error>
+    where
+        E: AsEvent + ?Sized,
+    {
+        let event = &event.as_event();
+        let state = self.state.take().ok_or_else(|| self.poisoned_error())?;
+        let description = StateDescription::from(&state);
+
+        let result = match self.redactable_header(&state) {
+            Some(header) => {
+                let (_, field) = self
+                    .redacting
+                    .get_or_insert_with(|| (header, verihash::Hasher::new()));
+
+                state.transition(event, field, self.nfc)
+            }
+            None => state.transition(event, &mut self.verihash, self.nfc),
+        };
+
+        match result {
+            Ok(new_state) => {
+                if new_state == State::Initial {
+                    if let Some((header, field)) = self.redacting.take() {
+                        let digest = field.finalize();
+                        self.verihash
+                            .redacted_field(header.tag, header.critical, &digest);
+                    }
+                }
+
+                if let Event::ValueChunk {
+                    wire_type: WireType::Bytes,
+                    bytes,
+                    ..
+                }
+                | Event::ValueChunk {
+                    wire_type: WireType::String,
+                    bytes,
+                    ..
+                } = event
+                {
+                    self.value_bytes = self.value_bytes.checked_add(bytes.len() as u64).unwrap();
+                }
+
+                self.state = Some(new_state);
+                self.events = self.events.checked_add(1).unwrap();
+                Ok(())
+            }
+            Err(err) => match err.kind() {
+                error::Kind::Hashing { reason } => Err(error::Kind::Transcript {
+                    state: description,
+                    event: event.kind(),
+                    index: self.events,
+                    reason,
+                }
+                .into()),
+                _ => Err(err),
+            },
         }
     }
 
-    /// Hash a digest of a nested message within this message
+    /// Hash each of the given events in order, returning the number of
+    /// events which were hashed.
+    ///
+    /// Stops at the first event which fails to hash, returning its error
+    /// (whose index identifies the failing event) and leaving the hasher
+    /// poisoned, the same as [`Hasher::hash_event`].
+    pub fn hash_events<I>(&mut self, events: I) -> Result<usize, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsEvent,
+    {
+        let mut count = 0;
+
+        for event in events {
+            self.hash_event(&event)?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Describe the current state of the hasher, e.g. for logging
+    pub fn state_description(&self) -> StateDescription {
+        match &self.state {
+            Some(state) => state.into(),
+            None => StateDescription::Failed { index: self.events },
+        }
+    }
+
+    /// Get the number of events and value bytes hashed so far
+    pub fn position(&self) -> Position {
+        Position {
+            events: self.events,
+            value_bytes: self.value_bytes,
+        }
+    }
+
+    /// Hash a serialized message, decoding it and forwarding each of the
+    /// resulting events to [`Hasher::hash_event`].
+    ///
+    /// Nested messages and sequences are hashed structurally, with their
+    /// digests absorbed into this message's transcript.
+    ///
+    /// Malformed input is reported using the decoder's error kinds (e.g.
+    /// [`error::Kind::Decode`] or [`error::Kind::Truncated`]), whereas
+    /// transcript errors are reported as [`error::Kind::Transcript`] or
+    /// [`error::Kind::Poisoned`]. In either case the hasher is poisoned.
+    ///
+    /// As with the `Decoder`, errors carry the offset within `input` at which
+    /// they were detected, the header of the field being decoded, and the
+    /// path of the nested message they occurred in.
+    pub fn hash_message(&mut self, input: &[u8]) -> Result<(), Error> {
+        self.hash_message_chunked(input, input.len().max(1))
+    }
+
+    /// Hash a serialized message, feeding the decoder at most `chunk_size`
+    /// bytes at a time
+    pub(crate) fn hash_message_chunked(
+        &mut self,
+        input: &[u8],
+        chunk_size: usize,
+    ) -> Result<(), Error> {
+        let depth = Depth::new(self.max_depth);
+        self.hash_message_at_depth(input, chunk_size, depth)
+    }
+
+    /// Hash a serialized message nested at the given depth
+    pub(crate) fn hash_message_at_depth(
+        &mut self,
+        input: &[u8],
+        chunk_size: usize,
+        depth: Depth,
+    ) -> Result<(), Error> {
+        let result = drive_decoder(self, input, chunk_size, depth);
+
+        if result.is_err() {
+            self.poison();
+        }
+
+        result
+    }
+
+    /// Hash a digest of a nested message within this message.
+    ///
+    /// This absorbs the field's tag followed by the digest of the nested
+    /// message, domain separated by the `message` wire type.
     pub fn hash_message_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error> {
         match self.state {
-            Some(State::Message { remaining }) if remaining == 0 => {
-                self.verihash.tag(tag);
+            Some(State::Message {
+                remaining: 0,
+                critical,
+            }) => {
+                self.verihash.field(tag, critical);
                 self.verihash.fixed_size_value(WireType::Message, digest);
                 self.state = Some(State::Initial);
                 Ok(())
             }
-            _ => Err(error::Kind::Hashing.into()),
+            Some(_) => Err(error::HashingReason::UnexpectedEvent.into()),
+            None => Err(self.poisoned_error()),
         }
     }
 
@@ -75,24 +333,172 @@ where
         digest: &DigestOutput<D>,
     ) -> Result<(), Error> {
         match self.state {
-            Some(State::Sequence { remaining, .. }) if remaining == 0 => {
-                self.verihash.tag(tag);
+            Some(State::Sequence {
+                remaining: 0,
+                critical,
+                ..
+            }) => {
+                self.verihash.field(tag, critical);
                 self.verihash.fixed_size_value(WireType::Sequence, digest);
                 self.state = Some(State::Initial);
                 Ok(())
             }
-            _ => Err(error::Kind::Hashing.into()),
+            Some(_) => Err(error::HashingReason::UnexpectedEvent.into()),
+            None => Err(self.poisoned_error()),
         }
     }
 
-    /// Finish computing digest
-    pub fn finish(self) -> Result<DigestOutput<D>, Error> {
-        if self.state == Some(State::Initial) {
-            Ok(self.verihash.finalize())
-        } else {
-            Err(error::Kind::Hashing.into())
+    /// Poison this hasher, causing subsequent operations to fail
+    pub(crate) fn poison(&mut self) {
+        self.state = None;
+    }
+
+    /// Begin the next message in a stream of messages hashed into a single
+    /// transcript.
+    ///
+    /// Messages are framed by markers containing their index within the
+    /// stream, so hashing several messages back-to-back can't be confused
+    /// with hashing a single message containing all of their fields.
+    ///
+    /// Returns [`error::Kind::Hashing`] if the previous message's frame
+    /// hasn't been ended ([`error::HashingReason::Nesting`]) or if in the middle of
+    /// a field ([`error::HashingReason::Incomplete`]).
+    pub fn begin_message(&mut self) -> Result<(), Error> {
+        self.ensure_finished()?;
+
+        let index = self.frames;
+        self.verihash.frame_begin(index);
+        self.frame = Some(index);
+        self.frames = index.checked_add(1).unwrap();
+        Ok(())
+    }
+
+    /// End the current message in a stream of messages.
+    ///
+    /// Returns [`error::Kind::Hashing`] if no message frame is open
+    /// ([`error::HashingReason::Nesting`]) or if in the middle of a field
+    /// ([`error::HashingReason::Incomplete`]).
+    pub fn end_message(&mut self) -> Result<(), Error> {
+        match (&self.state, self.frame) {
+            (Some(State::Initial), Some(index)) => {
+                self.verihash.frame_end(index);
+                self.frame = None;
+                Ok(())
+            }
+            (Some(State::Initial), None) => Err(error::HashingReason::Nesting.into()),
+            (Some(_), _) => Err(error::HashingReason::Incomplete.into()),
+            (None, _) => Err(self.poisoned_error()),
+        }
+    }
+
+    /// Finish computing digest.
+    ///
+    /// Returns [`error::Kind::Hashing`] if the transcript ended in the middle
+    /// of a field or message frame, or [`error::Kind::Poisoned`] if a previous event failed to
+    /// hash (leaving the hasher poisoned).
+    pub fn finish(self) -> Result<Output<D>, Error> {
+        self.ensure_finished()?;
+        Ok(self.verihash.finalize_message().into())
+    }
+
+    /// Ensure the transcript is complete, i.e. we aren't in the middle of
+    /// hashing a field and no previous errors occurred
+    fn ensure_finished(&self) -> Result<(), Error> {
+        match self.state {
+            Some(State::Initial) if self.frame.is_none() => Ok(()),
+            Some(State::Initial) => Err(error::HashingReason::Nesting.into()),
+            Some(_) => Err(error::HashingReason::Incomplete.into()),
+            None => Err(self.poisoned_error()),
         }
     }
+
+    /// Error returned when the hasher has been poisoned by a previous error
+    fn poisoned_error(&self) -> Error {
+        error::Kind::Poisoned { index: self.events }.into()
+    }
+}
+
+impl<D> Hasher<D>
+where
+    D: Digest + Clone,
+{
+    /// Create a new [`Hasher`] whose transcript is domain separated by the
+    /// given context string, e.g. the name of the protocol the digest is
+    /// being computed for.
+    ///
+    /// The domain is absorbed before any events as the `0xFF` prefix byte,
+    /// followed by the length of the domain as a little endian `u64`,
+    /// followed by the bytes of the domain itself. Digests of nested
+    /// messages and sequences are computed without a domain.
+    pub fn new_with_domain(domain: &[u8]) -> Self {
+        Self::from_verihash_with_reset(verihash::Hasher::new_with_domain(domain))
+    }
+
+    /// Create a new [`Hasher`] from the given Verihash hasher, restoring the
+    /// prefix it has already absorbed whenever the hasher is reset
+    fn from_verihash_with_reset(verihash: verihash::Hasher<D>) -> Self {
+        let mut hasher = Self::from_verihash(verihash);
+        hasher.initial = Some(hasher.verihash.clone());
+        hasher
+    }
+
+    /// Reset the hasher to the state it was in when it was constructed
+    /// (including any domain), discarding any events hashed so far and
+    /// clearing a previous error.
+    pub fn reset(&mut self) {
+        match &self.initial {
+            Some(initial) => self.verihash = initial.clone(),
+            None => {
+                self.verihash.reset();
+                self.verihash.message_begin();
+            }
+        }
+
+        self.state = Some(State::Initial);
+        self.events = 0;
+        self.value_bytes = 0;
+        self.frame = None;
+        self.frames = 0;
+        self.redacting = None;
+    }
+
+    /// Finish computing digest, resetting the hasher so it can be reused to
+    /// hash another message.
+    ///
+    /// Errors are the same as [`Hasher::finish`], in which case the hasher is
+    /// left unmodified.
+    pub fn finish_reset(&mut self) -> Result<Output<D>, Error> {
+        self.ensure_finished()?;
+        self.verihash.message_end();
+
+        let digest = self.verihash.finalize_reset();
+        self.reset();
+        Ok(digest.into())
+    }
+}
+
+/// Extension trait for computing the Verihash digest of a sequence of
+/// decoding events.
+///
+/// Nested messages and sequences can't be hashed this way, as their digests
+/// aren't part of the event stream (see [`Hasher::hash_message_digest`]).
+pub trait HashEvents: IntoIterator + Sized
+where
+    Self::Item: AsEvent,
+{
+    /// Hash these events, returning the digest of the message they describe
+    fn verihash<D: Digest>(self) -> Result<Output<D>, Error> {
+        let mut hasher = Hasher::<D>::new();
+        hasher.hash_events(self)?;
+        hasher.finish()
+    }
+}
+
+impl<I> HashEvents for I
+where
+    I: IntoIterator,
+    I::Item: AsEvent,
+{
 }
 
 impl<D> Default for Hasher<D>
@@ -109,11 +515,194 @@ where
     D: Digest,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("message::Hasher").finish()
+        // The digest state is omitted, as it may reveal sensitive values
+        f.debug_struct("message::Hasher")
+            .field("state", &self.state_description())
+            .field("position", &self.position())
+            .finish()
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+/// Message transcripts which can be computed by decoding a serialized message
+trait Transcript<D: Digest>: Default {
+    /// Create a transcript for a message nested within this one
+    fn nested(&self) -> Self {
+        Self::default()
+    }
+
+    /// Hash an incoming event
+    fn hash_event(&mut self, event: &Event<'_>) -> Result<(), Error>;
+
+    /// Hash a digest of a nested message within this message
+    fn hash_message_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error>;
+
+    /// Hash a digest of a sequence within this message
+    fn hash_sequence_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error>;
+
+    /// Finish computing digest
+    fn finish(self) -> Result<DigestOutput<D>, Error>;
+}
+
+impl<D> Transcript<D> for Hasher<D>
+where
+    D: Digest,
+{
+    fn nested(&self) -> Self {
+        Self {
+            nfc: self.nfc,
+            ..Self::default()
+        }
+    }
+
+    fn hash_event(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        Hasher::hash_event(self, event)
+    }
+
+    fn hash_message_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error> {
+        Hasher::hash_message_digest(self, tag, digest)
+    }
+
+    fn hash_sequence_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error> {
+        Hasher::hash_sequence_digest(self, tag, digest)
+    }
+
+    fn finish(self) -> Result<DigestOutput<D>, Error> {
+        Hasher::finish(self).map(Output::into_bytes)
+    }
+}
+
+/// Decode the given input (a message at the given nesting depth), hashing
+/// the resulting events with the given transcript, feeding the decoder at
+/// most `chunk_size` bytes at a time.
+///
+/// Errors carry their offset within the input, the header of the field
+/// being decoded, and the tags of the fields containing the nested message
+/// they occurred in (if any), as with the `Decoder`.
+fn drive_decoder<D, T>(
+    transcript: &mut T,
+    input: &[u8],
+    chunk_size: usize,
+    depth: Depth,
+) -> Result<(), Error>
+where
+    D: Digest,
+    T: Transcript<D>,
+{
+    let mut decoder = Decoder::<D>::without_hasher();
+
+    hash_decoded(&mut decoder, transcript, input, chunk_size, depth)
+        .map_err(|err| err.with_context(decoder.position(), decoder.last_header(), Path::default()))
+}
+
+/// Decode the given input with the given decoder, hashing the resulting
+/// events with the given transcript
+fn hash_decoded<D, T>(
+    decoder: &mut Decoder<D>,
+    transcript: &mut T,
+    input: &[u8],
+    chunk_size: usize,
+    depth: Depth,
+) -> Result<(), Error>
+where
+    D: Digest,
+    T: Transcript<D>,
+{
+    let mut tag = None;
+    let mut element_type = None;
+    let mut body_start = 0;
+    let mut pos: usize = 0;
+
+    loop {
+        let end = pos.checked_add(chunk_size).unwrap().min(input.len());
+        let mut chunk = &input[pos..end];
+        let maybe_event = decoder.decode(&mut chunk)?;
+        pos = end.checked_sub(chunk.len()).unwrap();
+
+        let event = match maybe_event {
+            Some(event) => event,
+            // Booleans are emitted without consuming input, so continue
+            // decoding until no further events are produced
+            None if pos == input.len() => break,
+            None => continue,
+        };
+
+        transcript.hash_event(&event)?;
+
+        match event {
+            Event::FieldHeader(header) => tag = Some(header.tag),
+            Event::LengthDelimiter { wire_type, length } => {
+                body_start = pos;
+
+                if wire_type == WireType::Message && length == 0 {
+                    let tag = expect_tag(tag)?;
+                    let digest =
+                        nested_digest(transcript.nested(), &[], chunk_size, depth.nested()?)
+                            .map_err(|err| err.nested_in(body_start, tag))?;
+                    transcript.hash_message_digest(tag, &digest)?;
+                }
+            }
+            Event::SequenceHeader { wire_type, length } => {
+                body_start = pos;
+                element_type = Some(wire_type);
+
+                if length == 0 {
+                    let digest =
+                        schemaless::sequence_digest::<D>(wire_type, &[], chunk_size, depth)
+                            .map_err(|err| err.in_sequence(body_start, decoder.last_header()))?;
+                    transcript.hash_sequence_digest(expect_tag(tag)?, &digest)?;
+                }
+            }
+            Event::ValueChunk {
+                wire_type: WireType::Message,
+                remaining: 0,
+                ..
+            } => {
+                let tag = expect_tag(tag)?;
+                let body = &input[body_start..pos];
+                let digest = nested_digest(transcript.nested(), body, chunk_size, depth.nested()?)
+                    .map_err(|err| err.nested_in(body_start, tag))?;
+                transcript.hash_message_digest(tag, &digest)?;
+            }
+            Event::ValueChunk {
+                wire_type: WireType::Sequence,
+                remaining: 0,
+                ..
+            } => {
+                let body = &input[body_start..pos];
+                let wire_type = element_type.ok_or(error::HashingReason::UnexpectedEvent)?;
+                let digest = schemaless::sequence_digest::<D>(wire_type, body, chunk_size, depth)
+                    .map_err(|err| err.in_sequence(body_start, decoder.last_header()))?;
+                transcript.hash_sequence_digest(expect_tag(tag)?, &digest)?;
+            }
+            _ => (),
+        }
+    }
+
+    decoder.ensure_complete()
+}
+
+/// Compute the digest of a message nested at the given depth using the
+/// given transcript
+fn nested_digest<D, T>(
+    mut transcript: T,
+    input: &[u8],
+    chunk_size: usize,
+    depth: Depth,
+) -> Result<DigestOutput<D>, Error>
+where
+    D: Digest,
+    T: Transcript<D>,
+{
+    drive_decoder(&mut transcript, input, chunk_size, depth)?;
+    transcript.finish()
+}
+
+/// Get the tag of the field currently being decoded
+fn expect_tag(tag: Option<Tag>) -> Result<Tag, Error> {
+    tag.ok_or_else(|| error::HashingReason::UnexpectedEvent.into())
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
 enum State {
     /// At the start of a message with no data processed
     Initial,
@@ -124,19 +713,41 @@ enum State {
     /// Hashing a bytes field
     Bytes { remaining: usize },
 
-    /// Hashing a string field
-    String { remaining: usize },
+    /// Hashing a string field, validating it's UTF-8 as chunks arrive
+    String {
+        remaining: usize,
+        utf8: Utf8Validator,
+    },
 
-    /// Hashing a message value
-    Message { remaining: usize },
+    /// Buffering a string field, which is absorbed once complete after
+    /// normalizing it to NFC
+    #[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+    NormalizedString {
+        header: field::Header,
+        remaining: usize,
+        utf8: Utf8Validator,
+        buffer: Vec<u8>,
+    },
 
-    /// Hashing a sequence value
+    /// Hashing a message value.
+    ///
+    /// Chunks of nested messages aren't absorbed: the value is hashed
+    /// structurally and its digest supplied via `hash_message_digest`, which
+    /// absorbs the field's tag along with whether it's `critical`.
+    Message { remaining: usize, critical: bool },
+
+    /// Hashing a sequence value.
+    ///
+    /// As with messages, chunks aren't absorbed and the value's digest is
+    /// supplied via `hash_sequence_digest`.
     Sequence {
         wire_type: WireType,
         remaining: usize,
+        critical: bool,
     },
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for State {
     fn default() -> Self {
         State::Initial
@@ -144,16 +755,18 @@ impl Default for State {
 }
 
 impl State {
-    /// Transition to a new state based on an incoming event or return an error
+    /// Transition to a new state based on an incoming event or return an
+    /// error, optionally normalizing strings to NFC
     pub fn transition<D: Digest>(
         self,
         event: &Event<'_>,
         verihash: &mut verihash::Hasher<D>,
+        nfc: bool,
     ) -> Result<Self, Error> {
         match event {
             Event::FieldHeader(header) => self.handle_field_header(header),
             Event::LengthDelimiter { wire_type, length } => {
-                self.handle_length_delimiter(*wire_type, *length, verihash)
+                self.handle_length_delimiter(*wire_type, *length, verihash, nfc)
             }
             Event::Bool(_) | Event::UInt64(_) | Event::SInt64(_) => {
                 self.handle_fixed_sized_value(event, verihash)
@@ -174,35 +787,67 @@ impl State {
         if self == State::Initial {
             Ok(State::Header(*header))
         } else {
-            Err(error::Kind::Hashing.into())
+            Err(error::HashingReason::UnexpectedEvent.into())
         }
     }
 
     /// Handle length delimiter event
+    #[cfg_attr(
+        not(all(feature = "alloc", feature = "unicode-normalization")),
+        allow(unused_variables)
+    )]
     fn handle_length_delimiter<D: Digest>(
         self,
         wire_type: WireType,
         length: usize,
         verihash: &mut verihash::Hasher<D>,
+        nfc: bool,
     ) -> Result<Self, Error> {
         if let State::Header(header) = self {
-            if wire_type != header.wire_type {
-                return Err(error::Kind::Hashing.into());
+            wire_type.check(header.tag, header.wire_type)?;
+
+            // Normalized strings are absorbed along with their header and
+            // normalized length once complete
+            #[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+            {
+                if nfc && wire_type == WireType::String && length > 0 {
+                    return Ok(State::NormalizedString {
+                        header,
+                        remaining: length,
+                        utf8: Utf8Validator::default(),
+                        buffer: Vec::new(),
+                    });
+                }
             }
 
+            // Zero-length bytes and strings have no value chunks, so they're
+            // complete as soon as their length is absorbed. Zero-length
+            // messages are completed by `hash_message_digest`.
             let new_state = match wire_type {
+                WireType::Bytes | WireType::String if length == 0 => State::Initial,
                 WireType::Bytes => State::Bytes { remaining: length },
-                WireType::String => State::String { remaining: length },
-                WireType::Message => State::Message { remaining: length },
-                _ => unreachable!(),
+                WireType::String => State::String {
+                    remaining: length,
+                    utf8: Utf8Validator::default(),
+                },
+                WireType::Message => State::Message {
+                    remaining: length,
+                    critical: header.critical,
+                },
+                // Sequences are delimited by `SequenceHeader`s instead
+                _ => return Err(error::HashingReason::WireTypeMismatch.into()),
             };
 
-            verihash.tag(header.tag);
-            verihash.dynamically_sized_value(wire_type, length);
+            // Nested messages are hashed structurally by their own hasher,
+            // with the resulting digest absorbed in `hash_message_digest`
+            if wire_type != WireType::Message {
+                verihash.field(header.tag, header.critical);
+                verihash.dynamically_sized_value(wire_type, length);
+            }
 
             Ok(new_state)
         } else {
-            Err(error::Kind::Hashing.into())
+            Err(error::HashingReason::UnexpectedEvent.into())
         }
     }
 
@@ -213,14 +858,18 @@ impl State {
         verihash: &mut verihash::Hasher<D>,
     ) -> Result<Self, Error> {
         if let State::Header(header) = self {
+            if let Some(wire_type) = value.wire_type() {
+                wire_type.check(header.tag, header.wire_type)?;
+            }
+
             match value {
-                Event::Bool(value) => verihash.tagged_boolean(header.tag, *value),
-                Event::UInt64(value) => verihash.tagged_uint64(header.tag, *value),
-                Event::SInt64(value) => verihash.tagged_sint64(header.tag, *value),
-                _ => unreachable!(),
+                Event::Bool(value) => verihash.tagged_boolean(header.tag, header.critical, *value),
+                Event::UInt64(value) => verihash.tagged_uint64(header.tag, header.critical, *value),
+                Event::SInt64(value) => verihash.tagged_sint64(header.tag, header.critical, *value),
+                _ => return Err(error::HashingReason::UnexpectedEvent.into()),
             }
         } else {
-            return Err(error::Kind::Hashing.into());
+            return Err(error::HashingReason::UnexpectedEvent.into());
         }
 
         Ok(State::Initial)
@@ -234,12 +883,9 @@ impl State {
         new_remaining: usize,
         verihash: &mut verihash::Hasher<D>,
     ) -> Result<Self, Error> {
-        // TODO(tarcieri): DRY this out
         let new_state = match self {
-            State::Bytes { remaining } => {
-                if wire_type != WireType::Bytes || remaining - bytes.len() != new_remaining {
-                    return Err(error::Kind::Hashing.into());
-                }
+            State::Bytes { remaining } if wire_type == WireType::Bytes => {
+                check_chunk(remaining, bytes, new_remaining)?;
 
                 if new_remaining == 0 {
                     State::Initial
@@ -249,44 +895,81 @@ impl State {
                     }
                 }
             }
-            State::String { remaining } => {
-                if wire_type != WireType::String || remaining - bytes.len() != new_remaining {
-                    return Err(error::Kind::Hashing.into());
-                }
+            State::String {
+                remaining,
+                mut utf8,
+            } if wire_type == WireType::String => {
+                check_chunk(remaining, bytes, new_remaining)?;
+                utf8.update(bytes)?;
 
                 if new_remaining == 0 {
+                    utf8.finish()?;
                     State::Initial
                 } else {
                     State::String {
                         remaining: new_remaining,
+                        utf8,
                     }
                 }
             }
-            State::Message { remaining } => {
-                if wire_type != WireType::Message || remaining - bytes.len() != new_remaining {
-                    return Err(error::Kind::Hashing.into());
+            #[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+            State::NormalizedString {
+                header,
+                remaining,
+                mut utf8,
+                mut buffer,
+            } if wire_type == WireType::String => {
+                check_chunk(remaining, bytes, new_remaining)?;
+                utf8.update(bytes)?;
+                buffer.extend_from_slice(bytes);
+
+                if new_remaining > 0 {
+                    return Ok(State::NormalizedString {
+                        header,
+                        remaining: new_remaining,
+                        utf8,
+                        buffer,
+                    });
                 }
 
+                utf8.finish()?;
+                return nfc::absorb(header, &buffer, verihash);
+            }
+            State::Message {
+                remaining,
+                critical,
+            } if wire_type == WireType::Message => {
+                check_chunk(remaining, bytes, new_remaining)?;
+
                 return Ok(State::Message {
                     remaining: new_remaining,
+                    critical,
                 });
             }
             State::Sequence {
                 wire_type: value_type,
                 remaining,
-            } => {
-                if wire_type != WireType::Sequence || remaining - bytes.len() != new_remaining {
-                    return Err(error::Kind::Hashing.into());
-                } else {
-                    return Ok(State::Sequence {
-                        wire_type: value_type,
-                        remaining: new_remaining,
-                    });
-                }
+                critical,
+            } if wire_type == WireType::Sequence => {
+                check_chunk(remaining, bytes, new_remaining)?;
+
+                return Ok(State::Sequence {
+                    wire_type: value_type,
+                    remaining: new_remaining,
+                    critical,
+                });
+            }
+            State::Bytes { .. }
+            | State::String { .. }
+            | State::Message { .. }
+            | State::Sequence { .. } => {
+                return Err(error::HashingReason::WireTypeMismatch.into());
             }
-            _ => {
-                return Err(error::Kind::Hashing.into());
+            #[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+            State::NormalizedString { .. } => {
+                return Err(error::HashingReason::WireTypeMismatch.into());
             }
+            _ => return Err(error::HashingReason::UnexpectedEvent.into()),
         };
 
         verihash.update(bytes);
@@ -296,16 +979,1076 @@ impl State {
     /// Handle an incoming sequence header
     fn handle_sequence_header(self, wire_type: WireType, length: usize) -> Result<Self, Error> {
         if let State::Header(header) = self {
-            if header.wire_type != WireType::Sequence {
-                return Err(error::Kind::Hashing.into());
-            }
+            WireType::Sequence.check(header.tag, header.wire_type)?;
 
             Ok(State::Sequence {
                 wire_type,
                 remaining: length,
+                critical: header.critical,
             })
         } else {
-            Err(error::Kind::Hashing.into())
+            Err(error::HashingReason::UnexpectedEvent.into())
+        }
+    }
+}
+
+/// Ensure a value chunk is non-empty and consumes exactly the number of bytes
+/// between the `remaining` counts before and after it
+fn check_chunk(remaining: usize, bytes: &[u8], new_remaining: usize) -> Result<(), Error> {
+    if bytes.is_empty() || remaining.checked_sub(bytes.len()) != Some(new_remaining) {
+        return Err(error::HashingReason::LengthMismatch.into());
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{Error, Event, HashEvents, Hasher, Position, StateDescription, WireType};
+    use crate::{
+        decoder::EventKind,
+        error,
+        field::{Header, Tag},
+        message::Element,
+        verihash,
+    };
+    #[cfg(feature = "alloc")]
+    use alloc::string::ToString;
+    #[cfg(feature = "alloc")]
+    use alloc::vec::Vec;
+    use sha2::Sha256;
+
+    #[cfg(feature = "alloc")]
+    use crate::decoder::{EventBuf, Events};
+
+    /// Hash a `uint64` field with the given tag and value
+    fn hash_uint64(hasher: &mut Hasher<Sha256>, tag: Tag, value: u64) {
+        let header = Header::new(tag, false, WireType::UInt64);
+        hasher.hash_event(&Event::FieldHeader(header)).unwrap();
+        hasher.hash_event(&Event::UInt64(value)).unwrap();
+    }
+
+    #[test]
+    fn finish_uint64() {
+        let mut hasher = Hasher::<Sha256>::new();
+        hash_uint64(&mut hasher, Tag::new_const(1), 42);
+
+        let mut expected = verihash::Hasher::<Sha256>::new_message();
+        expected.tagged_uint64(Tag::new_const(1), false, 42);
+
+        assert_eq!(
+            hasher.finish().unwrap().into_bytes(),
+            expected.finalize_message()
+        );
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn finish_mid_field() {
+        let mut hasher = Hasher::<Sha256>::new();
+        let header = Header::new(Tag::new_const(1), false, WireType::UInt64);
+        hasher.hash_event(&Event::FieldHeader(header)).unwrap();
+
+        let err = hasher.finish().err().unwrap();
+        assert_eq!(err.kind(), error::HashingReason::Incomplete.into());
+        assert_eq!(err.to_string(), "hashing failed: transcript is incomplete");
+    }
+
+    #[test]
+    fn sequence_length_delimiter() {
+        // Sequences are delimited by `SequenceHeader`s, so a length delimiter
+        // for one is an error rather than a panic
+        let mut hasher = Hasher::<Sha256>::new();
+        let header = Header::new(Tag::new_const(1), false, WireType::Sequence);
+        hasher.hash_event(&Event::FieldHeader(header)).unwrap();
+
+        let event = Event::LengthDelimiter {
+            wire_type: WireType::Sequence,
+            length: 1,
+        };
+        assert!(hasher.hash_event(&event).is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn finish_poisoned() {
+        let mut hasher = Hasher::<Sha256>::new();
+        hash_uint64(&mut hasher, Tag::new_const(1), 42);
+
+        let err = hasher.hash_event(&Event::UInt64(42)).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::Transcript {
+                state: StateDescription::Initial,
+                event: EventKind::UInt64,
+                index: 2,
+                reason: error::HashingReason::UnexpectedEvent,
+            }
+        );
+
+        let err = hasher.finish().err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Poisoned { index: 2 });
+        assert_eq!(
+            err.kind().to_string(),
+            "hasher previously failed at event #2"
+        );
+    }
+
+    #[test]
+    fn nested_message_digest() {
+        let mut child = Hasher::<Sha256>::new();
+        hash_uint64(&mut child, Tag::new_const(1), 42);
+        let child_digest = child.finish().unwrap().into_bytes();
+
+        let mut parent = Hasher::<Sha256>::new();
+        let header = Header::new(Tag::new_const(2), false, WireType::Message);
+        parent.hash_event(&Event::FieldHeader(header)).unwrap();
+
+        for event in &[
+            Event::LengthDelimiter {
+                wire_type: WireType::Message,
+                length: 2,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::Message,
+                bytes: &[138, 85],
+                remaining: 0,
+            },
+        ] {
+            parent.hash_event(event).unwrap();
+        }
+
+        parent
+            .hash_message_digest(Tag::new_const(2), &child_digest)
+            .unwrap();
+
+        let mut expected = verihash::Hasher::<Sha256>::new_message();
+        expected.field(Tag::new_const(2), false);
+        expected.fixed_size_value(WireType::Message, &child_digest);
+
+        assert_eq!(
+            parent.finish().unwrap().into_bytes(),
+            expected.finalize_message()
+        );
+    }
+
+    #[test]
+    fn empty_bytes_and_string() {
+        for &wire_type in &[WireType::Bytes, WireType::String] {
+            let mut hasher = Hasher::<Sha256>::new();
+            let header = Header::new(Tag::new_const(1), false, wire_type);
+            hasher.hash_event(&Event::FieldHeader(header)).unwrap();
+            hasher
+                .hash_event(&Event::LengthDelimiter {
+                    wire_type,
+                    length: 0,
+                })
+                .unwrap();
+
+            let mut expected = verihash::Hasher::<Sha256>::new_message();
+            expected.field(Tag::new_const(1), false);
+            expected.dynamically_sized_value(wire_type, 0);
+
+            assert_eq!(
+                hasher.finish().unwrap().into_bytes(),
+                expected.finalize_message()
+            );
+        }
+    }
+
+    #[test]
+    fn hash_message_empty_values() {
+        // `{ 1: bytes(b"") }`, `{ 1: string("") }`
+        for &(input, wire_type) in &[(&[41, 1], WireType::Bytes), (&[43, 1], WireType::String)] {
+            let mut expected = verihash::Hasher::<Sha256>::new_message();
+            expected.field(Tag::new_const(1), false);
+            expected.dynamically_sized_value(wire_type, 0);
+            assert_eq!(
+                verihash::<Sha256>(input).unwrap(),
+                expected.finalize_message().into()
+            );
+        }
+
+        // `{ 1: message({}) }`
+        let mut expected = verihash::Hasher::<Sha256>::new_message();
+        expected.field(Tag::new_const(1), false);
+        expected.fixed_size_value(
+            WireType::Message,
+            &verihash::Hasher::<Sha256>::new_message().finalize_message(),
+        );
+        assert_eq!(
+            verihash::<Sha256>(&[45, 1]).unwrap(),
+            expected.finalize_message().into()
+        );
+
+        // `{ 1: sequence<uint64>([]) }`
+        let mut seq = verihash::Hasher::<Sha256>::new_sequence(WireType::UInt64);
+        seq.uint64(0);
+
+        let mut expected = verihash::Hasher::<Sha256>::new_message();
+        expected.field(Tag::new_const(1), false);
+        expected.fixed_size_value(WireType::Sequence, &seq.finalize());
+        assert_eq!(
+            verihash::<Sha256>(&[47, 5]).unwrap(),
+            expected.finalize_message().into()
+        );
+    }
+
+    /// Error for a rejected value chunk of a 2-byte `bytes` field
+    fn bytes_chunk_error(reason: error::HashingReason) -> error::Kind {
+        error::Kind::Transcript {
+            state: StateDescription::Bytes { remaining: 2 },
+            event: EventKind::ValueChunk,
+            index: 2,
+            reason,
+        }
+    }
+
+    /// Begin hashing a `bytes` field with the given length
+    fn bytes_header(hasher: &mut Hasher<Sha256>, length: usize) {
+        let header = Header::new(Tag::new_const(1), false, WireType::Bytes);
+        hasher.hash_event(&Event::FieldHeader(header)).unwrap();
+        hasher
+            .hash_event(&Event::LengthDelimiter {
+                wire_type: WireType::Bytes,
+                length,
+            })
+            .unwrap();
+    }
+
+    /// Hash a `string` field containing the given chunks
+    fn hash_string_chunks(hasher: &mut Hasher<Sha256>, chunks: &[&[u8]]) -> Result<(), Error> {
+        let mut remaining: usize = chunks.iter().map(|chunk| chunk.len()).sum();
+        let header = Header::new(Tag::new_const(1), false, WireType::String);
+        hasher.hash_event(&Event::FieldHeader(header))?;
+        hasher.hash_event(&Event::LengthDelimiter {
+            wire_type: WireType::String,
+            length: remaining,
+        })?;
+
+        for chunk in chunks {
+            remaining -= chunk.len();
+            hasher.hash_event(&Event::ValueChunk {
+                wire_type: WireType::String,
+                bytes: chunk,
+                remaining,
+            })?;
         }
+
+        Ok(())
+    }
+
+    #[test]
+    fn string_split_code_point() {
+        let mut hasher = Hasher::<Sha256>::new();
+        hash_string_chunks(&mut hasher, &[b"a\xF0", b"\x9F", b"\xA6\x80"]).unwrap();
+
+        let mut expected = verihash::Hasher::<Sha256>::new_message();
+        expected.field(Tag::new_const(1), false);
+        expected.dynamically_sized_value(WireType::String, 5);
+        expected.update("a\u{1F980}".as_bytes());
+
+        assert_eq!(
+            hasher.finish().unwrap().into_bytes(),
+            expected.finalize_message()
+        );
+    }
+
+    #[test]
+    fn string_invalid_utf8() {
+        let mut hasher = Hasher::<Sha256>::new();
+        let err = hash_string_chunks(&mut hasher, &[b"ab", b"\xF0z", b"c"])
+            .err()
+            .unwrap();
+
+        assert_eq!(err.kind(), error::Kind::Utf8 { valid_up_to: 2 });
+        assert_eq!(
+            hasher.finish().err().unwrap().kind(),
+            error::Kind::Poisoned { index: 3 }
+        );
+
+        // Truncated code point at the end of the field
+        let mut hasher = Hasher::<Sha256>::new();
+        let err = hash_string_chunks(&mut hasher, &[b"ab", b"\xF0\x9F"])
+            .err()
+            .unwrap();
+
+        assert_eq!(err.kind(), error::Kind::Utf8 { valid_up_to: 2 });
+
+        // `{ 1: string(b"\xFFa") }`
+        let err = verihash::<Sha256>(&[43, 5, 0xFF, 0x61]).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Utf8 { valid_up_to: 0 });
+    }
+
+    #[test]
+    fn chunk_overshoot() {
+        let mut hasher = Hasher::<Sha256>::new();
+        bytes_header(&mut hasher, 2);
+
+        let err = hasher
+            .hash_event(&Event::ValueChunk {
+                wire_type: WireType::Bytes,
+                bytes: b"foo",
+                remaining: 0,
+            })
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            bytes_chunk_error(error::HashingReason::LengthMismatch)
+        );
+        assert_eq!(
+            hasher.finish().err().unwrap().kind(),
+            error::Kind::Poisoned { index: 2 }
+        );
+    }
+
+    #[test]
+    fn chunk_remaining_mismatch() {
+        for &(bytes, remaining) in &[(&b"f"[..], 0), (&b"f"[..], 3), (&b"fo"[..], 1)] {
+            let mut hasher = Hasher::<Sha256>::new();
+            bytes_header(&mut hasher, 2);
+
+            let result = hasher.hash_event(&Event::ValueChunk {
+                wire_type: WireType::Bytes,
+                bytes,
+                remaining,
+            });
+
+            assert_eq!(
+                result.err().unwrap().kind(),
+                bytes_chunk_error(error::HashingReason::LengthMismatch)
+            );
+        }
+    }
+
+    #[test]
+    fn chunk_empty() {
+        let mut hasher = Hasher::<Sha256>::new();
+        bytes_header(&mut hasher, 2);
+
+        let result = hasher.hash_event(&Event::ValueChunk {
+            wire_type: WireType::Bytes,
+            bytes: b"",
+            remaining: 2,
+        });
+
+        assert_eq!(
+            result.err().unwrap().kind(),
+            bytes_chunk_error(error::HashingReason::LengthMismatch)
+        );
+    }
+
+    #[test]
+    fn chunk_wire_type_mismatch() {
+        let mut hasher = Hasher::<Sha256>::new();
+        bytes_header(&mut hasher, 2);
+
+        let result = hasher.hash_event(&Event::ValueChunk {
+            wire_type: WireType::String,
+            bytes: b"fo",
+            remaining: 0,
+        });
+
+        assert_eq!(
+            result.err().unwrap().kind(),
+            bytes_chunk_error(error::HashingReason::WireTypeMismatch)
+        );
+    }
+
+    #[test]
+    fn chunk_without_header() {
+        let mut hasher = Hasher::<Sha256>::new();
+        let result = hasher.hash_event(&Event::ValueChunk {
+            wire_type: WireType::Bytes,
+            bytes: b"fo",
+            remaining: usize::MAX,
+        });
+
+        assert_eq!(
+            result.err().unwrap().kind(),
+            error::Kind::Transcript {
+                state: StateDescription::Initial,
+                event: EventKind::ValueChunk,
+                index: 0,
+                reason: error::HashingReason::UnexpectedEvent,
+            }
+        );
+    }
+
+    /// Hash `{ 2: message(..) }` with the given digest, delivering the
+    /// 4-byte message body in the given chunks
+    fn hash_chunked_message(chunks: &[&[u8]], digest: &[u8]) -> verihash::Output<Sha256> {
+        let mut hasher = Hasher::<Sha256>::new();
+        let header = Header::new(Tag::new_const(2), false, WireType::Message);
+        hasher.hash_event(&Event::FieldHeader(header)).unwrap();
+        hasher
+            .hash_event(&Event::LengthDelimiter {
+                wire_type: WireType::Message,
+                length: 4,
+            })
+            .unwrap();
+
+        let mut remaining = 4;
+
+        for chunk in chunks {
+            remaining -= chunk.len();
+            hasher
+                .hash_event(&Event::ValueChunk {
+                    wire_type: WireType::Message,
+                    bytes: chunk,
+                    remaining,
+                })
+                .unwrap();
+        }
+
+        hasher
+            .hash_message_digest(Tag::new_const(2), digest.into())
+            .unwrap();
+        hasher.finish().unwrap()
+    }
+
+    #[test]
+    fn nested_message_multiple_chunks() {
+        let digest = [0x42u8; 32];
+        let expected = hash_chunked_message(&[&[21, 85, 37, 85]], &digest);
+
+        assert_eq!(
+            hash_chunked_message(&[&[21], &[85, 37], &[85]], &digest),
+            expected
+        );
+        assert_eq!(
+            hash_chunked_message(&[&[21], &[85], &[37], &[85]], &digest),
+            expected
+        );
+    }
+
+    #[test]
+    fn nested_message_chunks_not_absorbed() {
+        let digest = [0x42u8; 32];
+
+        // Only the nested message's digest contributes to the transcript
+        assert_eq!(
+            hash_chunked_message(&[&[21, 85, 37, 85]], &digest),
+            hash_chunked_message(&[&[0, 0, 0, 0]], &digest)
+        );
+        assert_ne!(
+            hash_chunked_message(&[&[21, 85, 37, 85]], &digest),
+            hash_chunked_message(&[&[21, 85, 37, 85]], &[0x43; 32])
+        );
+    }
+
+    #[test]
+    fn hash_message_chunked_nested() {
+        // `{ 1: message({ 0: uint64(42) }), 2: sequence<uint64>([42, 43]) }`
+        let input = [45, 5, 5, 85, 79, 69, 85, 87];
+
+        let mut expected = Hasher::<Sha256>::new();
+        expected.hash_message(&input).unwrap();
+        let expected = expected.finish().unwrap();
+
+        for chunk_size in 1..input.len() {
+            let mut hasher = Hasher::<Sha256>::new();
+            hasher.hash_message_chunked(&input, chunk_size).unwrap();
+            assert_eq!(hasher.finish().unwrap(), expected);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn out_of_order_events() {
+        // Field header in the middle of a field
+        let mut hasher = Hasher::<Sha256>::new();
+        bytes_header(&mut hasher, 14);
+
+        let err = hasher
+            .hash_event(&Event::FieldHeader(Header::new(
+                Tag::new_const(2),
+                false,
+                WireType::UInt64,
+            )))
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::Transcript {
+                state: StateDescription::Bytes { remaining: 14 },
+                event: EventKind::FieldHeader,
+                index: 2,
+                reason: error::HashingReason::UnexpectedEvent,
+            }
+        );
+
+        assert_eq!(
+            err.kind().to_string(),
+            "hasher rejected field header event #2 in state: bytes, 14 bytes remaining"
+        );
+
+        // Values with the wrong wire type
+        let mut hasher = Hasher::<Sha256>::new();
+        hash_uint64(&mut hasher, Tag::new_const(1), 42);
+        let header = Header::new(Tag::new_const(2), false, WireType::String);
+        hasher.hash_event(&Event::FieldHeader(header)).unwrap();
+
+        for event in &[
+            Event::LengthDelimiter {
+                wire_type: WireType::Bytes,
+                length: 3,
+            },
+            Event::SInt64(-1),
+        ] {
+            let actual = event.wire_type().unwrap();
+            let err = hasher.clone().hash_event(event).err().unwrap();
+
+            assert_eq!(
+                err.kind(),
+                error::Kind::WireTypeMismatch {
+                    tag: Tag::new_const(2),
+                    expected: WireType::String,
+                    actual
+                }
+            );
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn state_description() {
+        let mut hasher = Hasher::<Sha256>::new();
+        assert_eq!(hasher.state_description(), StateDescription::Initial);
+
+        bytes_header(&mut hasher, 14);
+        assert_eq!(
+            hasher.state_description().to_string(),
+            "bytes, 14 bytes remaining"
+        );
+
+        assert!(hasher.hash_event(&Event::UInt64(42)).is_err());
+        assert_eq!(
+            hasher.state_description(),
+            StateDescription::Failed { index: 2 }
+        );
+
+        hasher.reset();
+        assert_eq!(hasher.state_description(), StateDescription::Initial);
+    }
+
+    /// Events of `{ 1: uint64(42), 2: string("baz") }`
+    fn recorded_events() -> [Event<'static>; 6] {
+        [
+            Event::FieldHeader(Header::new(Tag::new_const(1), false, WireType::UInt64)),
+            Event::UInt64(42),
+            Event::FieldHeader(Header::new(Tag::new_const(2), false, WireType::String)),
+            Event::LengthDelimiter {
+                wire_type: WireType::String,
+                length: 3,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::String,
+                bytes: b"ba",
+                remaining: 1,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::String,
+                bytes: b"z",
+                remaining: 0,
+            },
+        ]
+    }
+
+    #[test]
+    fn hash_events() {
+        let events = recorded_events();
+
+        let mut incremental = Hasher::<Sha256>::new();
+
+        for event in &events {
+            incremental.hash_event(event).unwrap();
+        }
+
+        let expected = incremental.finish().unwrap();
+
+        let mut hasher = Hasher::<Sha256>::new();
+        assert_eq!(hasher.hash_events(&events).unwrap(), events.len());
+        assert_eq!(hasher.finish().unwrap(), expected);
+
+        assert_eq!(events.iter().verihash::<Sha256>().unwrap(), expected);
+        assert_eq!(
+            verihash::<Sha256>(&[37, 85, 75, 7, 98, 97, 122]).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn position() {
+        let mut hasher = Hasher::<Sha256>::new();
+        assert_eq!(hasher.position(), Position::default());
+
+        let expected_value_bytes = [0, 0, 0, 0, 2, 3];
+
+        for (i, event) in recorded_events().iter().enumerate() {
+            hasher.hash_event(event).unwrap();
+            assert_eq!(
+                hasher.position(),
+                Position {
+                    events: i + 1,
+                    value_bytes: expected_value_bytes[i],
+                }
+            );
+        }
+
+        hasher.reset();
+        assert_eq!(hasher.position(), Position::default());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn debug() {
+        let mut hasher = Hasher::<Sha256>::new();
+        let events = recorded_events();
+        hasher.hash_events(&events[..5]).unwrap();
+
+        assert_eq!(
+            format!("{:?}", hasher),
+            "message::Hasher { state: String { remaining: 1 }, \
+             position: Position { events: 5, value_bytes: 2 } }"
+        );
+    }
+
+    #[test]
+    fn hash_events_short_circuit() {
+        let mut events = recorded_events();
+        events.swap(0, 1);
+
+        let mut hasher = Hasher::<Sha256>::new();
+        let err = hasher.hash_events(&events).err().unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::Transcript {
+                state: StateDescription::Initial,
+                event: EventKind::UInt64,
+                index: 0,
+                reason: error::HashingReason::UnexpectedEvent,
+            }
+        );
+        assert_eq!(
+            hasher.state_description(),
+            StateDescription::Failed { index: 0 }
+        );
+        assert!(events.iter().verihash::<Sha256>().is_err());
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn replay_event_bufs() {
+        let input = vec![37, 85, 75, 7, 98, 97, 122];
+        let expected = verihash::<Sha256>(&input).unwrap();
+
+        // Record the live decode, which outlives the input it borrowed from
+        let recorded = Events::new(&input)
+            .map(|event| event.map(EventBuf::from))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        drop(input);
+
+        assert_eq!(
+            recorded[4],
+            EventBuf::ValueChunk {
+                wire_type: WireType::String,
+                bytes: b"baz".to_vec(),
+                remaining: 0,
+            }
+        );
+        assert_eq!(recorded.iter().verihash::<Sha256>().unwrap(), expected);
+
+        let mut hasher = Hasher::<Sha256>::new();
+
+        for event in &recorded {
+            hasher.hash_event(event).unwrap();
+        }
+
+        assert_eq!(hasher.finish().unwrap(), expected);
+    }
+
+    /// `{ 1: uint64(42) }`
+    const MESSAGE_A: &[u8] = &[37, 85];
+
+    /// `{ 2: string("baz") }`
+    const MESSAGE_B: &[u8] = &[75, 7, 98, 97, 122];
+
+    /// `{ 1: uint64(42), 2: string("baz") }`
+    const MERGED_MESSAGE: &[u8] = &[37, 85, 75, 7, 98, 97, 122];
+
+    /// Digest of the stream `MESSAGE_A`, `MESSAGE_B`
+    const FRAMED_DIGEST: [u8; 32] = [
+        0x40, 0x58, 0x4d, 0xc6, 0xe3, 0xfe, 0x97, 0xe6, 0xa2, 0x20, 0xe5, 0x7a, 0x9e, 0x04, 0x5c,
+        0xb7, 0xfc, 0x03, 0x89, 0x88, 0x33, 0x8c, 0xee, 0xf4, 0xae, 0xba, 0xa5, 0xb3, 0xf7, 0xbb,
+        0xca, 0x89,
+    ];
+
+    #[test]
+    fn message_frames() {
+        let mut hasher = Hasher::<Sha256>::new();
+
+        for message in &[MESSAGE_A, MESSAGE_B] {
+            hasher.begin_message().unwrap();
+            hasher.hash_message(message).unwrap();
+            hasher.end_message().unwrap();
+        }
+
+        let framed = hasher.finish().unwrap();
+        assert_eq!(framed.as_bytes(), &FRAMED_DIGEST);
+
+        // Without framing, concatenated messages collide with a merged one
+        let mut unframed = Hasher::<Sha256>::new();
+        unframed.hash_message(MESSAGE_A).unwrap();
+        unframed.hash_message(MESSAGE_B).unwrap();
+
+        let merged = verihash::<Sha256>(MERGED_MESSAGE).unwrap();
+        assert_eq!(unframed.finish().unwrap(), merged);
+        assert_ne!(framed, merged);
+
+        // Frames are bound to the index of each message
+        let mut single = Hasher::<Sha256>::new();
+        single.begin_message().unwrap();
+        single.hash_message(MERGED_MESSAGE).unwrap();
+        single.end_message().unwrap();
+        assert_ne!(single.finish().unwrap(), framed);
+    }
+
+    /// Serialize a message nested `depth` messages deep (counting the
+    /// outermost message), containing `{ 0: uint64(42) }` innermost
+    #[cfg(feature = "alloc")]
+    fn nested_message(depth: usize) -> Vec<u8> {
+        let mut message = vec![5, 85];
+
+        for _ in 1..depth {
+            let len = (message.len() * 2 + 1) as u8;
+            message.splice(0..0, [13, len].iter().cloned());
+        }
+
+        message
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn max_depth() {
+        let mut hasher = Hasher::<Sha256>::new();
+        hasher.hash_message(&nested_message(16)).unwrap();
+        hasher.finish().unwrap();
+
+        let mut hasher = Hasher::<Sha256>::new();
+        let err = hasher.hash_message(&nested_message(17)).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 17, max: 16 });
+
+        let mut hasher = Hasher::<Sha256>::new().with_max_depth(3);
+        hasher.hash_message(&nested_message(3)).unwrap();
+        hasher.finish().unwrap();
+
+        let mut hasher = Hasher::<Sha256>::new().with_max_depth(3);
+        let err = hasher.hash_message(&nested_message(4)).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 4, max: 3 });
+    }
+
+    #[test]
+    fn max_depth_sequence() {
+        // `{ 0: sequence<message>([{ 0: message({ 0: uint64(42) }) }]) }`
+        let input = [15, 173, 9, 13, 5, 5, 85];
+
+        let mut hasher = Hasher::<Sha256>::new().with_max_depth(3);
+        hasher.hash_message(&input).unwrap();
+        hasher.finish().unwrap();
+
+        let mut hasher = Hasher::<Sha256>::new().with_max_depth(2);
+        let err = hasher.hash_message(&input).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NestingDepth { depth: 3, max: 2 });
+    }
+
+    #[test]
+    fn message_frame_misuse() {
+        // Finishing with an open frame
+        let mut hasher = Hasher::<Sha256>::new();
+        hasher.begin_message().unwrap();
+        hasher.hash_message(MESSAGE_A).unwrap();
+        assert_eq!(
+            hasher.finish().err().unwrap().kind(),
+            error::HashingReason::Nesting.into()
+        );
+
+        // Nested frames
+        let mut hasher = Hasher::<Sha256>::new();
+        hasher.begin_message().unwrap();
+        assert_eq!(
+            hasher.begin_message().err().unwrap().kind(),
+            error::HashingReason::Nesting.into()
+        );
+
+        // Ending a frame which was never begun
+        let mut hasher = Hasher::<Sha256>::new();
+        assert_eq!(
+            hasher.end_message().err().unwrap().kind(),
+            error::HashingReason::Nesting.into()
+        );
+
+        // Ending a frame in the middle of a field
+        let mut hasher = Hasher::<Sha256>::new();
+        hasher.begin_message().unwrap();
+        bytes_header(&mut hasher, 2);
+        assert_eq!(
+            hasher.end_message().err().unwrap().kind(),
+            error::HashingReason::Incomplete.into()
+        );
+    }
+
+    #[test]
+    fn finish_reset() {
+        let mut hasher = Hasher::<Sha256>::new();
+        hash_uint64(&mut hasher, Tag::new_const(1), 42);
+        let digest = hasher.finish_reset().unwrap();
+
+        hash_uint64(&mut hasher, Tag::new_const(1), 42);
+        assert_eq!(hasher.finish().unwrap(), digest);
+    }
+
+    #[test]
+    fn finish_reset_domain() {
+        let mut hasher = Hasher::<Sha256>::new_with_domain(b"example");
+        hash_uint64(&mut hasher, Tag::new_const(1), 42);
+        let digest = hasher.finish_reset().unwrap();
+
+        hash_uint64(&mut hasher, Tag::new_const(1), 42);
+        assert_eq!(hasher.finish().unwrap(), digest);
+    }
+
+    #[test]
+    fn reset() {
+        let mut fresh = Hasher::<Sha256>::new();
+        hash_uint64(&mut fresh, Tag::new_const(2), 43);
+        let expected = fresh.finish().unwrap();
+
+        // Reset in the middle of a field
+        let mut hasher = Hasher::<Sha256>::new();
+        hash_uint64(&mut hasher, Tag::new_const(1), 42);
+        let header = Header::new(Tag::new_const(2), false, WireType::UInt64);
+        hasher.hash_event(&Event::FieldHeader(header)).unwrap();
+        hasher.reset();
+
+        hash_uint64(&mut hasher, Tag::new_const(2), 43);
+        assert_eq!(hasher.finish().unwrap(), expected);
+
+        // Reset after an error
+        let mut hasher = Hasher::<Sha256>::new();
+        assert!(hasher.hash_event(&Event::UInt64(42)).is_err());
+        hasher.reset();
+
+        hash_uint64(&mut hasher, Tag::new_const(2), 43);
+        assert_eq!(hasher.finish().unwrap(), expected);
+    }
+
+    #[test]
+    fn reset_domain() {
+        let mut fresh = Hasher::<Sha256>::new_with_domain(b"example");
+        hash_uint64(&mut fresh, Tag::new_const(1), 42);
+        let expected = fresh.finish().unwrap();
+
+        let mut hasher = Hasher::<Sha256>::new_with_domain(b"example");
+        hash_uint64(&mut hasher, Tag::new_const(1), 1);
+        hasher.reset();
+
+        hash_uint64(&mut hasher, Tag::new_const(1), 42);
+        assert_eq!(hasher.finish().unwrap(), expected);
+    }
+
+    #[test]
+    fn fork() {
+        let mut header = Hasher::<Sha256>::new();
+        hash_uint64(&mut header, Tag::new_const(1), 42);
+
+        let mut body_a = header.clone();
+        hash_uint64(&mut body_a, Tag::new_const(2), 1);
+
+        let mut body_b = header;
+        hash_uint64(&mut body_b, Tag::new_const(2), 2);
+
+        for (forked, body) in [(body_a, 1), (body_b, 2)].iter().cloned() {
+            let mut fresh = Hasher::<Sha256>::new();
+            hash_uint64(&mut fresh, Tag::new_const(1), 42);
+            hash_uint64(&mut fresh, Tag::new_const(2), body);
+            assert_eq!(forked.finish().unwrap(), fresh.finish().unwrap());
+        }
+    }
+
+    #[test]
+    fn hash_message_uint64() {
+        let mut hasher = Hasher::<Sha256>::new();
+        hasher.hash_message(&[138, 10, 85]).unwrap();
+
+        let mut expected = verihash::Hasher::<Sha256>::new_message();
+        expected.tagged_uint64(Tag::new_const(42), false, 42);
+
+        assert_eq!(
+            hasher.finish().unwrap().into_bytes(),
+            expected.finalize_message()
+        );
+    }
+
+    #[test]
+    fn hash_message_critical() {
+        let mut hasher = Hasher::<Sha256>::new();
+        hasher.hash_message(&[53, 85]).unwrap();
+
+        let mut expected = verihash::Hasher::<Sha256>::new_message();
+        expected.tagged_uint64(Tag::new_const(1), true, 42);
+
+        let expected = expected.finalize_message();
+        assert_eq!(hasher.finish().unwrap().into_bytes(), expected);
+
+        // Only the critical bit differs from `MESSAGE_A`
+        assert_ne!(
+            verihash::<Sha256>(MESSAGE_A).unwrap().into_bytes(),
+            expected
+        );
+    }
+
+    #[test]
+    fn hash_message_nested() {
+        let mut child = Hasher::<Sha256>::new();
+        hash_uint64(&mut child, Tag::new_const(0), 42);
+        let child_digest = child.finish().unwrap().into_bytes();
+
+        let mut hasher = Hasher::<Sha256>::new();
+        hasher.hash_message(&[45, 5, 5, 85]).unwrap();
+
+        let mut expected = verihash::Hasher::<Sha256>::new_message();
+        expected.field(Tag::new_const(1), false);
+        expected.fixed_size_value(WireType::Message, &child_digest);
+
+        let expected = expected.finalize_message();
+        assert_eq!(hasher.finish().unwrap().into_bytes(), expected);
+        assert_eq!(
+            verihash::<Sha256>(&[45, 5, 5, 85]).unwrap().into_bytes(),
+            expected
+        );
+    }
+
+    #[test]
+    fn hash_message_malformed() {
+        let mut hasher = Hasher::<Sha256>::new();
+        let err = hasher
+            .hash_message(&[206, 10, 167, 138, 10, 85])
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            error::Kind::Order {
+                tag: Tag::new_const(42)
+            }
+        );
+        assert_eq!(
+            hasher.finish().err().unwrap().kind(),
+            error::Kind::Poisoned { index: 2 }
+        );
+    }
+
+    #[test]
+    fn hash_message_duplicate_tag() {
+        // `{ 1: uint64(1), 1: uint64(2) }`
+        let err = verihash::<Sha256>(&[37, 3, 37, 5]).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(1)
+            }
+        );
+        assert_eq!(err.position(), Some(2));
+        assert!(err.path().is_empty());
+
+        // `{ 1: message({ 0: uint64(5), 0: uint64(6) }) }`
+        let mut hasher = Hasher::<Sha256>::new();
+        let err = hasher.hash_message(&[45, 9, 5, 11, 5, 13]).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(0)
+            }
+        );
+        assert_eq!(err.position(), Some(4));
+        assert!(err.path().iter().eq([Tag::new_const(1)].iter().cloned()));
+        assert!(hasher.finish().is_err());
+    }
+
+    #[test]
+    fn hash_message_truncated() {
+        let err = verihash::<Sha256>(&[138]).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::FieldHeader {
+                tag: None,
+                wire_type: None
+            }
+        );
+
+        let err = verihash::<Sha256>(&[138, 10]).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::Decode {
+                element: Element::Value,
+                wire_type: WireType::UInt64
+            }
+        );
+
+        let err = verihash::<Sha256>(&[73, 11, 98]).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::Truncated {
+                remaining: 4,
+                wire_type: WireType::Bytes
+            }
+        );
+    }
+
+    #[test]
+    fn error_context() {
+        // Errors in the outermost message have an empty path
+        // `{ 2: bytes(..) }` truncated after the first byte of its value
+        let err = verihash::<Sha256>(&[73, 11, 98]).err().unwrap();
+        assert_eq!(err.position(), Some(3));
+        assert_eq!(err.tag(), Some(Tag::new_const(2)));
+        assert!(err.path().is_empty());
+
+        // Errors in nested messages have their offset in the outermost
+        // message, and the tags of the fields containing them
+        // `{ 1: message({ 2: message({ 0: uint64(42), 0: uint64(43) }) }) }`
+        let input = [45, 13, 77, 9, 5, 85, 5, 87];
+        let mut hasher = Hasher::<Sha256>::new();
+        let err = hasher.hash_message(&input).err().unwrap();
+        assert_eq!(err.position(), Some(6));
+        assert_eq!(err.tag(), Some(Tag::new_const(0)));
+        assert!(err
+            .path()
+            .iter()
+            .eq([Tag::new_const(1), Tag::new_const(2)].iter().cloned()));
+
+        // Errors in sequences have the offset of the element
+        // `{ 1: sequence<uint64>([42, 0]) }` with a non-canonical second element
+        let err = verihash::<Sha256>(&[47, 101, 85, 2, 0]).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::NonCanonicalVInt64);
+        assert_eq!(err.position(), Some(3));
+        assert_eq!(err.tag(), Some(Tag::new_const(1)));
+        assert!(err.path().is_empty());
+
+        // Errors in messages within sequences have the sequence's tag in
+        // their path
+        // `{ 1: sequence<message>([{ 0: uint64(42), 0: uint64(43) }]) }`
+        let err = verihash::<Sha256>(&[47, 173, 9, 5, 85, 5, 87])
+            .err()
+            .unwrap();
+        assert_eq!(err.position(), Some(5));
+        assert_eq!(err.tag(), Some(Tag::new_const(0)));
+        assert!(err.path().iter().eq([Tag::new_const(1)].iter().cloned()));
     }
 }
@@ -0,0 +1,102 @@
+//! Descriptions of the message hasher's state, for diagnostics.
+
+// `displaydoc` emits its `Display` impls inside of an anonymous `const`
+#![allow(unknown_lints, non_local_definitions)]
+
+use super::State;
+use crate::field::{Tag, WireType};
+use displaydoc::Display;
+
+/// Description of the state a message hasher is in, e.g. for logging or for
+/// reporting why an event was rejected.
+#[derive(Copy, Clone, Debug, Display, Eq, PartialEq)]
+pub enum StateDescription {
+    /// initial
+    Initial,
+
+    /// field header: tag={tag} wire_type={wire_type:?}
+    Header {
+        /// tag of the field
+        tag: Tag,
+
+        /// wire type of the field
+        wire_type: WireType,
+    },
+
+    /// bytes, {remaining} bytes remaining
+    Bytes {
+        /// number of bytes remaining in the value
+        remaining: usize,
+    },
+
+    /// string, {remaining} bytes remaining
+    String {
+        /// number of bytes remaining in the value
+        remaining: usize,
+    },
+
+    /// message, {remaining} bytes remaining
+    Message {
+        /// number of bytes remaining in the value
+        remaining: usize,
+    },
+
+    /// sequence of {wire_type:?}, {remaining} bytes remaining
+    Sequence {
+        /// wire type of the sequence's elements
+        wire_type: WireType,
+
+        /// number of bytes remaining in the value
+        remaining: usize,
+    },
+
+    /// failed at event #{index}
+    Failed {
+        /// index of the event the hasher failed at
+        index: usize,
+    },
+}
+
+/// Position of a message hasher within its transcript, e.g. for locating
+/// where two transcripts which should have matched diverged.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Position {
+    /// Number of events hashed since construction or the last reset
+    pub events: usize,
+
+    /// Number of bytes of `bytes` and `string` values absorbed
+    pub value_bytes: u64,
+}
+
+impl From<&State> for StateDescription {
+    fn from(state: &State) -> StateDescription {
+        match state {
+            State::Initial => StateDescription::Initial,
+            State::Header(header) => StateDescription::Header {
+                tag: header.tag,
+                wire_type: header.wire_type,
+            },
+            State::Bytes { remaining } => StateDescription::Bytes {
+                remaining: *remaining,
+            },
+            State::String { remaining, .. } => StateDescription::String {
+                remaining: *remaining,
+            },
+            #[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+            State::NormalizedString { remaining, .. } => StateDescription::String {
+                remaining: *remaining,
+            },
+            State::Message { remaining, .. } => StateDescription::Message {
+                remaining: *remaining,
+            },
+            State::Sequence {
+                wire_type,
+                remaining,
+                ..
+            } => StateDescription::Sequence {
+                wire_type: *wire_type,
+                remaining: *remaining,
+            },
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Keyed Verihash: computes a MAC (e.g. HMAC) over the message transcript.
+//!
+//! WARNING: this is an experimental PoC-quality implementation!
+//! It is NOT suitable for production use!
+//!
+//! The MAC absorbs exactly the same transcript as the message `Hasher`, so a
+//! keyed digest can be computed in a single pass over the decoded events.
+//! Nested message and sequence digests are computed with the MAC under an
+//! all-zero key, i.e. they're unkeyed.
+
+use super::Hasher;
+use crate::{
+    decoder::Event,
+    error::{self, Error},
+    field::Tag,
+    verihash,
+};
+use core::fmt::{self, Debug};
+use crypto_mac::{Key, Mac, NewMac};
+use digest::{generic_array::GenericArray, FixedOutput, Reset, Update};
+
+/// Output of a MAC, e.g. a tag or the digest of a nested message
+pub type MacOutput<M> = GenericArray<u8, <M as Mac>::OutputSize>;
+
+/// Keyed Verihash hasher.
+///
+/// Computes a MAC over the Verihash transcript of a message, driven by
+/// incoming decoding events.
+#[derive(Clone)]
+pub struct KeyedHasher<M: Mac + NewMac> {
+    /// Message hasher absorbing the transcript into the keyed MAC
+    inner: Hasher<MacDigest<M>>,
+}
+
+impl<M> KeyedHasher<M>
+where
+    M: Mac + NewMac,
+{
+    /// Create a new [`KeyedHasher`] with the given key
+    pub fn new(key: &Key<M>) -> Self {
+        Self::from_mac(M::new(key))
+    }
+
+    /// Create a new [`KeyedHasher`] with a key of any length supported by
+    /// the MAC, returning [`error::Kind::Length`] if it's unsupported.
+    ///
+    /// HMAC supports keys of any length, hashing those longer than the
+    /// digest's block size.
+    pub fn new_varkey(key: &[u8]) -> Result<Self, Error> {
+        M::new_varkey(key)
+            .map(Self::from_mac)
+            .map_err(|_| error::Kind::Length.into())
+    }
+
+    /// Create a new [`KeyedHasher`] from a keyed MAC
+    fn from_mac(mac: M) -> Self {
+        let verihash = verihash::Hasher::from_digest(MacDigest(mac));
+
+        Self {
+            inner: Hasher::from_verihash_with_reset(verihash),
+        }
+    }
+
+    /// Set the maximum nesting depth of messages hashed by
+    /// [`KeyedHasher::hash_message`] (see `Hasher::with_max_depth`)
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.inner = self.inner.with_max_depth(max_depth);
+        self
+    }
+
+    /// Hash an incoming event
+    pub fn hash_event(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        self.inner.hash_event(event)
+    }
+
+    /// Hash a serialized message.
+    ///
+    /// See `Hasher::hash_message` for how errors are reported.
+    pub fn hash_message(&mut self, input: &[u8]) -> Result<(), Error> {
+        self.inner.hash_message(input)
+    }
+
+    /// Hash a digest of a nested message within this message
+    pub fn hash_message_digest(&mut self, tag: Tag, digest: &MacOutput<M>) -> Result<(), Error> {
+        self.inner.hash_message_digest(tag, digest)
+    }
+
+    /// Hash a digest of a sequence within this message
+    pub fn hash_sequence_digest(&mut self, tag: Tag, digest: &MacOutput<M>) -> Result<(), Error> {
+        self.inner.hash_sequence_digest(tag, digest)
+    }
+
+    /// Reset the hasher to the state it was in when it was constructed,
+    /// retaining its key
+    pub fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    /// Finish computing the keyed digest (i.e. MAC tag) of the message
+    pub fn finish(self) -> Result<MacOutput<M>, Error> {
+        self.inner.finish().map(verihash::Output::into_bytes)
+    }
+
+    /// Finish computing the keyed digest and compare it against the given
+    /// tag in constant time
+    pub fn verify(self, tag: &[u8]) -> Result<(), Error> {
+        self.inner.finish()?.verify(tag)
+    }
+}
+
+impl<M> Debug for KeyedHasher<M>
+where
+    M: Mac + NewMac,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("message::KeyedHasher")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// Adapter which drives a MAC as if it were a digest.
+///
+/// MACs created by `Default` (i.e. for nested digests) use an all-zero key.
+#[derive(Clone)]
+struct MacDigest<M>(M);
+
+impl<M> Default for MacDigest<M>
+where
+    M: Mac + NewMac,
+{
+    fn default() -> Self {
+        MacDigest(M::new(&Key::<M>::default()))
+    }
+}
+
+impl<M: Mac> Update for MacDigest<M> {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        self.0.update(data.as_ref());
+    }
+}
+
+impl<M: Mac> FixedOutput for MacDigest<M> {
+    type OutputSize = M::OutputSize;
+
+    fn finalize_into(self, out: &mut MacOutput<M>) {
+        *out = self.0.finalize().into_bytes();
+    }
+
+    fn finalize_into_reset(&mut self, out: &mut MacOutput<M>) {
+        *out = self.0.finalize_reset().into_bytes();
+    }
+}
+
+impl<M: Mac> Reset for MacDigest<M> {
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{KeyedHasher, MacDigest};
+    use crate::{
+        decoder::{Event, Hasher},
+        error,
+        field::{Header, Tag, WireType},
+    };
+    use hmac::Hmac;
+    use sha2::Sha256;
+
+    type HmacSha256 = Hmac<Sha256>;
+
+    /// `{ 1: uint64(42) }`
+    const EXAMPLE_MESSAGE: &[u8] = &[37, 85];
+
+    /// HMAC-SHA-256 with key `example key` of the example message transcript
+    const EXAMPLE_TAG: [u8; 32] = [
+        216, 159, 195, 251, 43, 107, 42, 152, 93, 14, 84, 120, 177, 0, 40, 95, 171, 23, 108, 50,
+        196, 164, 54, 6, 3, 40, 237, 57, 220, 94, 175, 115,
+    ];
+
+    #[test]
+    fn known_answer() {
+        let mut hasher = KeyedHasher::<HmacSha256>::new_varkey(b"example key").unwrap();
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+        assert_eq!(hasher.finish().unwrap().as_slice(), &EXAMPLE_TAG);
+    }
+
+    #[test]
+    fn verify() {
+        let mut hasher = KeyedHasher::<HmacSha256>::new_varkey(b"example key").unwrap();
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+        hasher.verify(&EXAMPLE_TAG).unwrap();
+
+        let mut hasher = KeyedHasher::<HmacSha256>::new_varkey(b"other key").unwrap();
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+        let err = hasher.verify(&EXAMPLE_TAG).err().unwrap();
+        assert_eq!(err.kind(), error::HashingReason::DigestMismatch.into());
+
+        let mut hasher = KeyedHasher::<HmacSha256>::new_varkey(b"example key").unwrap();
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+        assert!(hasher.verify(&EXAMPLE_TAG[..16]).is_err());
+    }
+
+    #[test]
+    fn reset() {
+        let mut hasher = KeyedHasher::<HmacSha256>::new_varkey(b"example key").unwrap();
+        hasher.hash_message(&[37, 87]).unwrap();
+        hasher.reset();
+
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+        hasher.verify(&EXAMPLE_TAG).unwrap();
+    }
+
+    #[test]
+    fn long_key() {
+        let key = [b'k'; 100];
+        let mut hasher = KeyedHasher::<HmacSha256>::new_varkey(&key).unwrap();
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        // Keys longer than the block size are hashed first
+        assert_eq!(
+            hasher.finish().unwrap().as_slice(),
+            &[
+                0xb8, 0xb1, 0xd0, 0x39, 0xd1, 0x66, 0x8d, 0x02, 0x86, 0xd9, 0x6d, 0xc0, 0xc1, 0x91,
+                0x33, 0x37, 0x8f, 0x90, 0xff, 0xd1, 0xd7, 0x1e, 0xe3, 0x75, 0x08, 0x4c, 0xbb, 0x3d,
+                0xfa, 0x14, 0x23, 0xfa,
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_digest() {
+        // `{ 1: message({ 0: uint64(42) }) }`
+        let mut hasher = KeyedHasher::<HmacSha256>::new_varkey(b"example key").unwrap();
+        hasher.hash_message(&[45, 5, 5, 85]).unwrap();
+
+        // Nested digests are computed under an all-zero key
+        let mut nested = Hasher::<MacDigest<HmacSha256>>::new();
+        nested.hash_message(&[5, 85]).unwrap();
+
+        let mut expected = KeyedHasher::<HmacSha256>::new_varkey(b"example key").unwrap();
+        let tag = Tag::new_const(1);
+        let events = [
+            Event::FieldHeader(Header::new(tag, false, WireType::Message)),
+            Event::LengthDelimiter {
+                wire_type: WireType::Message,
+                length: 2,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::Message,
+                bytes: &[5, 85],
+                remaining: 0,
+            },
+        ];
+
+        for event in &events {
+            expected.hash_event(event).unwrap();
+        }
+
+        expected
+            .hash_message_digest(tag, &nested.finish().unwrap().into_bytes())
+            .unwrap();
+
+        assert_eq!(hasher.finish().unwrap(), expected.finish().unwrap());
+    }
+
+    #[test]
+    fn differs_from_unkeyed() {
+        let mut unkeyed = Hasher::<Sha256>::new();
+        unkeyed.hash_message(EXAMPLE_MESSAGE).unwrap();
+        assert_ne!(unkeyed.finish().unwrap().as_bytes(), &EXAMPLE_TAG);
+    }
+}
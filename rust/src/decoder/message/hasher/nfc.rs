@@ -0,0 +1,199 @@
+//! Unicode normalization of `string` fields.
+//!
+//! A string's length is absorbed ahead of its contents, and normalizing it
+//! can change its length. Chunks of a string may also split a combining
+//! sequence, which can't be normalized until the rest of the sequence
+//! arrives. Strings are therefore buffered in their entirety, then
+//! normalized to NFC and absorbed along with their normalized length.
+
+use super::{Hasher, State};
+use crate::{
+    error::Error,
+    field::{Header, WireType},
+    verihash,
+};
+use alloc::string::String;
+use digest::Digest;
+use unicode_normalization::UnicodeNormalization;
+
+impl<D> Hasher<D>
+where
+    D: Digest,
+{
+    /// Create a new [`Hasher`] which normalizes strings to NFC
+    pub fn new_nfc() -> Self {
+        Self::new().with_nfc()
+    }
+
+    /// Normalize `string` fields to Unicode Normalization Form C before
+    /// absorbing them, so canonically equivalent strings produce the same
+    /// digest.
+    ///
+    /// Each string is buffered until its final chunk arrives. Strings in
+    /// nested messages hashed by [`Hasher::hash_message`] are normalized
+    /// too, whereas strings within sequences are absorbed as serialized.
+    pub fn with_nfc(mut self) -> Self {
+        self.nfc = true;
+        self
+    }
+}
+
+/// Absorb the header and NFC normalization of a complete, buffered string
+pub(super) fn absorb<D: Digest>(
+    header: Header,
+    buffer: &[u8],
+    verihash: &mut verihash::Hasher<D>,
+) -> Result<State, Error> {
+    let normalized: String = core::str::from_utf8(buffer)?.nfc().collect();
+    verihash.field(header.tag, header.critical);
+    verihash.dynamically_sized_value(WireType::String, normalized.len());
+    verihash.update(normalized.as_bytes());
+    Ok(State::Initial)
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use crate::{
+        decoder::{Event, HashEvents, Hasher},
+        error,
+        field::{Header, Tag, WireType},
+    };
+    use alloc::vec::Vec;
+    use sha2::Sha256;
+
+    /// "café", precomposed (NFC)
+    const COMPOSED: &str = "caf\u{e9}";
+
+    /// "café", decomposed (NFD): `e` followed by U+0301 COMBINING ACUTE ACCENT
+    const DECOMPOSED: &str = "cafe\u{301}";
+
+    /// Events for `{ 1: string(s) }`, split into chunks at the given offsets
+    fn string_events<'a>(s: &'a str, splits: &[usize]) -> Vec<Event<'a>> {
+        let bytes = s.as_bytes();
+        let mut events = vec![
+            Event::FieldHeader(Header::new(Tag::new_const(1), false, WireType::String)),
+            Event::LengthDelimiter {
+                wire_type: WireType::String,
+                length: bytes.len(),
+            },
+        ];
+
+        let mut start = 0;
+
+        for &end in splits.iter().chain(Some(&bytes.len())) {
+            events.push(Event::ValueChunk {
+                wire_type: WireType::String,
+                bytes: &bytes[start..end],
+                remaining: bytes.len() - end,
+            });
+            start = end;
+        }
+
+        events
+    }
+
+    /// Serialize a field with the given body, bypassing the `Encoder`'s
+    /// restriction of strings to ASCII
+    fn field(tag: u64, wire_type: WireType, body: &[u8]) -> Vec<u8> {
+        let header = Header::new(Tag::new(tag).unwrap(), false, wire_type);
+        let mut out = header.encode().as_ref().to_vec();
+        out.extend_from_slice(vint64::encode(body.len() as u64).as_ref());
+        out.extend_from_slice(body);
+        out
+    }
+
+    /// Hash the given events with an NFC hasher
+    fn nfc_digest(events: &[Event<'_>]) -> Vec<u8> {
+        let mut hasher = Hasher::<Sha256>::new_nfc();
+        hasher.hash_events(events).unwrap();
+        hasher.finish().unwrap().as_bytes().to_vec()
+    }
+
+    #[test]
+    fn canonically_equivalent_strings() {
+        let expected = string_events(COMPOSED, &[]).verihash::<Sha256>().unwrap();
+        assert_eq!(
+            nfc_digest(&string_events(COMPOSED, &[])),
+            expected.as_bytes().to_vec()
+        );
+
+        // Split between `e` and its combining accent, and within the
+        // two-byte encoding of the accent itself
+        for &split in &[4, 5] {
+            assert_eq!(
+                nfc_digest(&string_events(DECOMPOSED, &[split])),
+                expected.as_bytes().to_vec(),
+                "split at {}",
+                split
+            );
+        }
+
+        // Without normalization the decomposed string hashes differently
+        assert_ne!(
+            string_events(DECOMPOSED, &[4])
+                .verihash::<Sha256>()
+                .unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn nested_message() {
+        let message = |s: &str| {
+            let string = field(1, WireType::String, s.as_bytes());
+            field(2, WireType::Message, &string)
+        };
+
+        let digest = |mut hasher: Hasher<Sha256>, s| {
+            hasher.hash_message(&message(s)).unwrap();
+            hasher.finish().unwrap()
+        };
+
+        let expected = digest(Hasher::new(), COMPOSED);
+        assert_eq!(digest(Hasher::new_nfc(), DECOMPOSED), expected);
+        assert_ne!(digest(Hasher::new(), DECOMPOSED), expected);
+    }
+
+    #[test]
+    fn redacted_nfc() {
+        let digest = |s, splits: &[usize]| {
+            let mut hasher = Hasher::<Sha256>::new_nfc().with_redaction();
+            hasher.hash_events(&string_events(s, splits)).unwrap();
+            hasher.finish().unwrap()
+        };
+
+        assert_eq!(digest(DECOMPOSED, &[4]), digest(COMPOSED, &[]));
+    }
+
+    #[cfg(feature = "resumable-sha2")]
+    #[test]
+    fn resume_mid_string() {
+        use crate::decoder::ResumableSha256;
+
+        let events = string_events(DECOMPOSED, &[4]);
+
+        let mut hasher = Hasher::<ResumableSha256>::new_nfc();
+        hasher.hash_events(&events[..3]).unwrap();
+
+        let mut restored = Hasher::<ResumableSha256>::restore(&hasher.snapshot()).unwrap();
+        restored.hash_events(&events[3..]).unwrap();
+        assert_eq!(
+            restored.finish().unwrap().as_bytes().to_vec(),
+            nfc_digest(&string_events(COMPOSED, &[]))
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut events = string_events(COMPOSED, &[3]);
+        events[3] = Event::ValueChunk {
+            wire_type: WireType::String,
+            bytes: b"\xff\xff",
+            remaining: 0,
+        };
+
+        let mut hasher = Hasher::<Sha256>::new_nfc();
+        let err = hasher.hash_events(&events).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Utf8 { valid_up_to: 3 });
+    }
+}
@@ -0,0 +1,235 @@
+//! Redactable Verihash transcripts for selective disclosure.
+//!
+//! WARNING: this is an experimental PoC-quality implementation!
+//! It is NOT suitable for production use!
+//!
+//! In redaction mode, the transcript of each `bytes` and `string` field
+//! (its tag, wire type, length, and value, absorbed exactly as they are by
+//! the message `Hasher`) is hashed on its own. The message transcript then
+//! absorbs the field's tag (and criticality) followed by a redacted field
+//! prefix and this digest in place of the field's value.
+//!
+//! The holder of a message can therefore withhold any of these fields,
+//! revealing a [`RedactedField`] in its place, and the digest of the message
+//! can still be computed from a mix of plaintext and redacted fields.
+//!
+//! Nested messages and sequences are always absorbed as their digests, so
+//! they can be withheld the same way using `Hasher::hash_message_digest`
+//! and `Hasher::hash_sequence_digest`.
+
+use super::{Hasher, State};
+use crate::{
+    error::{self, Error},
+    field::{Header, Tag, WireType},
+    verihash::{self, DigestOutput},
+};
+use digest::Digest;
+
+/// A `bytes` or `string` field which has been withheld from a message,
+/// identified by its tag and the digest of its transcript.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RedactedField<D: Digest> {
+    /// Tag of the withheld field
+    pub tag: Tag,
+
+    /// Is the withheld field critical?
+    pub critical: bool,
+
+    /// Digest of the withheld field's transcript
+    pub digest: DigestOutput<D>,
+}
+
+impl<D> RedactedField<D>
+where
+    D: Digest,
+{
+    /// Redact the field with the given tag, criticality, wire type, and
+    /// value.
+    ///
+    /// Returns an error if the wire type isn't `bytes` or `string`.
+    pub fn new(tag: Tag, critical: bool, wire_type: WireType, value: &[u8]) -> Result<Self, Error> {
+        if wire_type != WireType::Bytes && wire_type != WireType::String {
+            return Err(error::Kind::InvalidWireType.into());
+        }
+
+        let mut field = verihash::Hasher::<D>::new();
+        field.field(tag, critical);
+        field.dynamically_sized_value(wire_type, value.len());
+        field.update(value);
+
+        Ok(Self {
+            tag,
+            critical,
+            digest: field.finalize(),
+        })
+    }
+}
+
+impl<D> Hasher<D>
+where
+    D: Digest,
+{
+    /// Enable redaction mode, absorbing `bytes` and `string` fields as the
+    /// digests of their transcripts so they can be withheld.
+    ///
+    /// Digests computed in redaction mode differ from those computed
+    /// without it.
+    pub fn with_redaction(mut self) -> Self {
+        self.redaction = true;
+        self
+    }
+
+    /// Hash a field which has been withheld from the message, in the place
+    /// the field would have appeared in its serialization.
+    ///
+    /// Returns [`error::Kind::Hashing`] if the hasher isn't in redaction mode
+    /// or is in the middle of a field.
+    pub fn hash_redacted_field(&mut self, field: &RedactedField<D>) -> Result<(), Error> {
+        match self.state {
+            Some(State::Initial) if self.redaction => {
+                self.verihash
+                    .redacted_field(field.tag, field.critical, &field.digest);
+                Ok(())
+            }
+            Some(_) => Err(error::HashingReason::UnexpectedEvent.into()),
+            None => Err(self.poisoned_error()),
+        }
+    }
+
+    /// Get the header of the field if the given state is part of a
+    /// redactable field's transcript and redaction is enabled
+    pub(super) fn redactable_header(&self, state: &State) -> Option<Header> {
+        if !self.redaction {
+            return None;
+        }
+
+        match state {
+            State::Header(header)
+                if header.wire_type == WireType::Bytes || header.wire_type == WireType::String =>
+            {
+                Some(*header)
+            }
+            State::Bytes { .. } | State::String { .. } => {
+                self.redacting.as_ref().map(|(header, _)| *header)
+            }
+            #[cfg(all(feature = "alloc", feature = "unicode-normalization"))]
+            State::NormalizedString { .. } => self.redacting.as_ref().map(|(header, _)| *header),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::RedactedField;
+    use crate::{
+        decoder::{Event, Hasher},
+        error,
+        field::{Header, Tag, WireType},
+        verihash::verihash,
+    };
+    use sha2::Sha256;
+
+    /// `{ 1: bytes("secret"), 2: string("baz"), 3: uint64(42) }`
+    const EXAMPLE_MESSAGE: &[u8] = &[
+        41, 13, 115, 101, 99, 114, 101, 116, 75, 7, 98, 97, 122, 101, 85,
+    ];
+
+    /// Redaction-mode Verihash digest of the example message
+    const EXAMPLE_DIGEST: [u8; 32] = [
+        85, 164, 78, 100, 105, 146, 105, 226, 2, 7, 246, 47, 149, 128, 101, 96, 184, 71, 185, 45,
+        112, 59, 104, 253, 97, 29, 172, 157, 118, 75, 54, 155,
+    ];
+
+    /// `{ 1: bytes("secret") }`
+    fn secret() -> RedactedField<Sha256> {
+        RedactedField::new(Tag::new_const(1), false, WireType::Bytes, b"secret").unwrap()
+    }
+
+    /// `{ 2: string("baz") }`
+    fn baz() -> RedactedField<Sha256> {
+        RedactedField::new(Tag::new_const(2), false, WireType::String, b"baz").unwrap()
+    }
+
+    #[test]
+    fn full() {
+        let mut hasher = Hasher::<Sha256>::new().with_redaction();
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        let digest = hasher.finish().unwrap();
+        assert_eq!(digest.as_bytes(), &EXAMPLE_DIGEST);
+        assert_ne!(digest, verihash::<Sha256>(EXAMPLE_MESSAGE).unwrap());
+    }
+
+    #[test]
+    fn partially_redacted() {
+        let mut hasher = Hasher::<Sha256>::new().with_redaction();
+        hasher.hash_redacted_field(&secret()).unwrap();
+        hasher.hash_message(&EXAMPLE_MESSAGE[8..]).unwrap();
+        assert_eq!(hasher.finish().unwrap().as_bytes(), &EXAMPLE_DIGEST);
+
+        let mut hasher = Hasher::<Sha256>::new().with_redaction();
+        hasher.hash_message(&EXAMPLE_MESSAGE[..8]).unwrap();
+        hasher.hash_redacted_field(&baz()).unwrap();
+        hasher.hash_message(&EXAMPLE_MESSAGE[13..]).unwrap();
+        assert_eq!(hasher.finish().unwrap().as_bytes(), &EXAMPLE_DIGEST);
+    }
+
+    #[test]
+    fn fully_redacted() {
+        let mut hasher = Hasher::<Sha256>::new().with_redaction();
+        hasher.hash_redacted_field(&secret()).unwrap();
+        hasher.hash_redacted_field(&baz()).unwrap();
+        hasher.hash_message(&EXAMPLE_MESSAGE[13..]).unwrap();
+        assert_eq!(hasher.finish().unwrap().as_bytes(), &EXAMPLE_DIGEST);
+    }
+
+    #[test]
+    fn chunked_value() {
+        let header = Header::new(Tag::new_const(1), false, WireType::Bytes);
+        let events = [
+            Event::FieldHeader(header),
+            Event::LengthDelimiter {
+                wire_type: WireType::Bytes,
+                length: 6,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::Bytes,
+                bytes: b"sec",
+                remaining: 3,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::Bytes,
+                bytes: b"ret",
+                remaining: 0,
+            },
+        ];
+
+        let mut hasher = Hasher::<Sha256>::new().with_redaction();
+        hasher.hash_events(&events).unwrap();
+        hasher.hash_message(&EXAMPLE_MESSAGE[8..]).unwrap();
+        assert_eq!(hasher.finish().unwrap().as_bytes(), &EXAMPLE_DIGEST);
+    }
+
+    #[test]
+    fn wrong_redacted_value() {
+        let mut hasher = Hasher::<Sha256>::new().with_redaction();
+        let field =
+            RedactedField::new(Tag::new_const(1), false, WireType::Bytes, b"public").unwrap();
+        hasher.hash_redacted_field(&field).unwrap();
+        hasher.hash_message(&EXAMPLE_MESSAGE[8..]).unwrap();
+        assert_ne!(hasher.finish().unwrap().as_bytes(), &EXAMPLE_DIGEST);
+    }
+
+    #[test]
+    fn misuse() {
+        let mut hasher = Hasher::<Sha256>::new();
+        let err = hasher.hash_redacted_field(&secret()).err().unwrap();
+        assert_eq!(err.kind(), error::HashingReason::UnexpectedEvent.into());
+
+        let err = RedactedField::<Sha256>::new(Tag::new_const(1), false, WireType::UInt64, &[])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), error::Kind::InvalidWireType);
+    }
+}
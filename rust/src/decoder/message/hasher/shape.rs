@@ -0,0 +1,299 @@
+//! Verihash shape hasher: computes a digest of a message's structure.
+//!
+//! WARNING: this is an experimental PoC-quality implementation!
+//! It is NOT suitable for production use!
+//!
+//! The shape transcript absorbs each field's tag and wire type (with both
+//! boolean wire types treated as `false`) but none of their values or
+//! lengths, so messages containing the same fields have the same shape
+//! digest regardless of their contents. Nested messages contribute the
+//! digest of their own shape, and sequences the wire type of their elements.
+//!
+//! Shape transcripts are domain separated from message transcripts, so a
+//! shape digest is never equal to a Verihash digest.
+
+use super::{drive_decoder, Transcript};
+use crate::{
+    decoder::Event,
+    error::{self, Error},
+    field::{Tag, WireType},
+    verihash::{self, DigestOutput},
+};
+use core::fmt::{self, Debug};
+use digest::Digest;
+
+/// Domain separation string for shape transcripts
+const SHAPE_DOMAIN: &[u8] = b"veriform.shape";
+
+/// Verihash shape hasher.
+///
+/// This type computes a digest of the tags and wire types of a message's
+/// fields, driven by incoming decoding events, e.g. to detect when a peer
+/// begins sending fields which aren't part of a known schema.
+///
+/// Events are expected to come from a decoder: only the placement of field
+/// headers and nested digests is validated.
+pub struct ShapeHasher<D: Digest> {
+    /// Verihash hasher for the shape transcript
+    verihash: verihash::Hasher<D>,
+
+    /// Wire type of the nested message or sequence whose digest is expected
+    /// next (if any)
+    pending: Option<WireType>,
+
+    /// Number of events hashed successfully (i.e. the index of the event
+    /// which failed if the hasher is poisoned)
+    events: usize,
+
+    /// Has an error occurred?
+    failed: bool,
+}
+
+impl<D> ShapeHasher<D>
+where
+    D: Digest,
+{
+    /// Create a new [`ShapeHasher`]
+    pub fn new() -> Self {
+        let mut verihash = verihash::Hasher::new_with_domain(SHAPE_DOMAIN);
+        verihash.message_begin();
+
+        Self {
+            verihash,
+            pending: None,
+            events: 0,
+            failed: false,
+        }
+    }
+
+    /// Hash an incoming event
+    pub fn hash_event(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        self.ensure_ok()?;
+
+        match event {
+            Event::FieldHeader(header) => {
+                if self.pending.is_some() {
+                    return self.fail();
+                }
+
+                let wire_type = match header.wire_type {
+                    WireType::True => WireType::False,
+                    wire_type => wire_type,
+                };
+
+                self.verihash.field(header.tag, header.critical);
+                self.verihash.update(&[wire_type.to_u8()]);
+
+                if wire_type == WireType::Message || wire_type == WireType::Sequence {
+                    self.pending = Some(wire_type);
+                }
+            }
+            Event::SequenceHeader { wire_type, .. } => {
+                if self.pending != Some(WireType::Sequence) {
+                    return self.fail();
+                }
+
+                self.verihash.update(&[wire_type.to_u8()]);
+            }
+            _ => (),
+        }
+
+        self.events = self.events.checked_add(1).unwrap();
+        Ok(())
+    }
+
+    /// Hash a serialized message, decoding it and forwarding each of the
+    /// resulting events to [`ShapeHasher::hash_event`].
+    ///
+    /// Errors are reported the same way as `Hasher::hash_message`.
+    pub fn hash_message(&mut self, input: &[u8]) -> Result<(), Error> {
+        let result = drive_decoder(self, input, input.len().max(1), Default::default());
+
+        if result.is_err() {
+            self.failed = true;
+        }
+
+        result
+    }
+
+    /// Hash the shape digest of a nested message within this message
+    pub fn hash_message_digest(
+        &mut self,
+        _tag: Tag,
+        digest: &DigestOutput<D>,
+    ) -> Result<(), Error> {
+        self.ensure_ok()?;
+
+        if self.pending.take() != Some(WireType::Message) {
+            return self.fail();
+        }
+
+        self.verihash.update(digest);
+        Ok(())
+    }
+
+    /// Complete a sequence within this message.
+    ///
+    /// The sequence's digest is ignored, as it commits to the sequence's
+    /// contents: only the wire type of its elements is part of the shape.
+    pub fn hash_sequence_digest(
+        &mut self,
+        _tag: Tag,
+        _digest: &DigestOutput<D>,
+    ) -> Result<(), Error> {
+        self.ensure_ok()?;
+
+        if self.pending.take() != Some(WireType::Sequence) {
+            return self.fail();
+        }
+
+        Ok(())
+    }
+
+    /// Finish computing the shape digest of the message
+    pub fn finish(self) -> Result<DigestOutput<D>, Error> {
+        self.ensure_ok()?;
+
+        if self.pending.is_some() {
+            return Err(error::HashingReason::Incomplete.into());
+        }
+
+        Ok(self.verihash.finalize_message())
+    }
+
+    /// Ensure no previous errors occurred, returning
+    /// [`error::Kind::Poisoned`] if one did
+    fn ensure_ok(&self) -> Result<(), Error> {
+        if self.failed {
+            Err(error::Kind::Poisoned { index: self.events }.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Mark the hasher as failed, returning an error
+    fn fail(&mut self) -> Result<(), Error> {
+        self.failed = true;
+        Err(error::HashingReason::UnexpectedEvent.into())
+    }
+}
+
+impl<D> Default for ShapeHasher<D>
+where
+    D: Digest,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> Debug for ShapeHasher<D>
+where
+    D: Digest,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("message::ShapeHasher").finish()
+    }
+}
+
+impl<D> Transcript<D> for ShapeHasher<D>
+where
+    D: Digest,
+{
+    fn hash_event(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        ShapeHasher::hash_event(self, event)
+    }
+
+    fn hash_message_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error> {
+        ShapeHasher::hash_message_digest(self, tag, digest)
+    }
+
+    fn hash_sequence_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error> {
+        ShapeHasher::hash_sequence_digest(self, tag, digest)
+    }
+
+    fn finish(self) -> Result<DigestOutput<D>, Error> {
+        ShapeHasher::finish(self)
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::ShapeHasher;
+    use crate::{
+        decoder::Event,
+        error,
+        field::WireType,
+        verihash::{verihash, DigestOutput},
+    };
+    use sha2::Sha256;
+
+    /// `{ 1: uint64(42), 2: string("baz"), 3: message({ 0: uint64(42) }) }`
+    const EXAMPLE_MESSAGE: &[u8] = &[37, 85, 75, 7, 98, 97, 122, 109, 5, 5, 85];
+
+    /// Compute the shape digest of a serialized message
+    fn shape(input: &[u8]) -> DigestOutput<Sha256> {
+        let mut hasher = ShapeHasher::<Sha256>::new();
+        hasher.hash_message(input).unwrap();
+        hasher.finish().unwrap()
+    }
+
+    #[test]
+    fn same_fields_different_values() {
+        // `{ 1: uint64(7), 2: string("quux"), 3: message({ 0: uint64(1) }) }`
+        let other = [37, 15, 75, 9, 113, 117, 117, 120, 109, 5, 5, 3];
+        assert_eq!(shape(EXAMPLE_MESSAGE), shape(&other));
+
+        // `{ 1: false }` and `{ 1: true }`
+        assert_eq!(shape(&[33]), shape(&[35]));
+
+        // `{ 1: sequence<uint64>([]) }` and `{ 1: sequence<uint64>([1, 2, 3]) }`
+        assert_eq!(shape(&[47, 5]), shape(&[47, 101, 3, 5, 7]));
+    }
+
+    #[test]
+    fn added_field() {
+        assert_ne!(shape(&EXAMPLE_MESSAGE[..2]), shape(EXAMPLE_MESSAGE));
+    }
+
+    #[test]
+    fn different_wire_type() {
+        // `{ 1: uint64(42) }` and `{ 1: sint64(-42) }`
+        assert_ne!(shape(&[37, 85]), shape(&[39, 167]));
+
+        // `{ 3: message({ 0: uint64(42) }) }` and `{ 3: message({ 0: sint64(-42) }) }`
+        assert_ne!(shape(&[109, 5, 5, 85]), shape(&[109, 5, 7, 167]));
+
+        // `{ 1: sequence<uint64>([]) }` and `{ 1: sequence<sint64>([]) }`
+        assert_ne!(shape(&[47, 5]), shape(&[47, 7]));
+    }
+
+    #[test]
+    fn differs_from_verihash() {
+        assert_ne!(
+            shape(EXAMPLE_MESSAGE),
+            verihash::<Sha256>(EXAMPLE_MESSAGE).unwrap().into_bytes()
+        );
+    }
+
+    #[test]
+    fn poisoned() {
+        // `{ 1: uint64(42) }` followed by a truncated field header
+        let mut hasher = ShapeHasher::<Sha256>::new();
+        assert!(hasher.hash_message(&[37, 85, 37]).is_err());
+
+        let err = hasher.finish().err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Poisoned { index: 2 });
+
+        // A sequence header outside of a sequence
+        let mut hasher = ShapeHasher::<Sha256>::new();
+        let event = Event::SequenceHeader {
+            wire_type: WireType::UInt64,
+            length: 0,
+        };
+        assert!(hasher.hash_event(&event).is_err());
+
+        let err = hasher.hash_event(&event).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Poisoned { index: 0 });
+    }
+}
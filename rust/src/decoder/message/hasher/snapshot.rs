@@ -0,0 +1,537 @@
+//! Hasher snapshots: checkpoint a transcript and resume it later, e.g. after
+//! a process restart partway through hashing a large message.
+//!
+//! Snapshot format (all integers are little-endian `u64` unless noted):
+//!
+//! - snapshot format version (`u8`)
+//! - transcript version (`u8`)
+//! - maximum nesting depth
+//! - number of events hashed
+//! - number of value bytes absorbed
+//! - hasher state (see `encode_state`)
+//! - open frame: `0x00`, or `0x01` followed by the frame index
+//! - number of frames begun
+//! - initial digest state: `0x00`, or `0x01` followed by a length-prefixed
+//!   digest state
+//! - redaction mode (`u8`)
+//! - redactable field being hashed: `0x00`, or `0x01` followed by its tag
+//!   and a length-prefixed digest state
+//! - normalization mode (`u8`)
+//! - length-prefixed digest state
+
+#[cfg(feature = "resumable-sha2")]
+mod sha256;
+
+#[cfg(feature = "resumable-sha2")]
+pub use self::sha256::ResumableSha256;
+
+use super::{Hasher, State};
+use crate::{
+    error::{self, Error},
+    field::{Header, Tag, WireType},
+    string::Utf8Validator,
+    verihash::{self, TRANSCRIPT_VERSION},
+};
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use digest::Digest;
+
+/// Version of the snapshot format
+const SNAPSHOT_VERSION: u8 = 2;
+
+/// Digests whose internal state can be serialized and later restored.
+///
+/// Implementations must restore a digest which behaves identically to the
+/// one whose state was saved.
+pub trait ResumableDigest: Digest + Sized {
+    /// Serialize the digest's internal state
+    fn save_state(&self) -> Vec<u8>;
+
+    /// Restore a digest from state serialized by
+    /// [`ResumableDigest::save_state`]
+    fn restore_state(state: &[u8]) -> Result<Self, Error>;
+}
+
+impl<D> Hasher<D>
+where
+    D: ResumableDigest,
+{
+    /// Serialize the hasher's current state into a snapshot which can be
+    /// restored with [`Hasher::restore`]
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut out = vec![SNAPSHOT_VERSION, TRANSCRIPT_VERSION];
+        put_u64(&mut out, self.max_depth as u64);
+        put_u64(&mut out, self.events as u64);
+        put_u64(&mut out, self.value_bytes);
+        encode_state(&mut out, self.state.as_ref());
+
+        match self.frame {
+            Some(index) => {
+                out.push(1);
+                put_u64(&mut out, index);
+            }
+            None => out.push(0),
+        }
+
+        put_u64(&mut out, self.frames);
+
+        match &self.initial {
+            Some(initial) => {
+                out.push(1);
+                put_bytes(&mut out, &initial.digest().save_state());
+            }
+            None => out.push(0),
+        }
+
+        out.push(self.redaction as u8);
+
+        match &self.redacting {
+            Some((header, field)) => {
+                out.push(1);
+                put_header(&mut out, header);
+                put_bytes(&mut out, &field.digest().save_state());
+            }
+            None => out.push(0),
+        }
+
+        out.push(self.nfc as u8);
+        put_bytes(&mut out, &self.verihash.digest().save_state());
+        out
+    }
+
+    /// Restore a hasher from a snapshot created by [`Hasher::snapshot`].
+    ///
+    /// Snapshots taken with a different snapshot format or transcript
+    /// version are rejected.
+    pub fn restore(snapshot: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader(snapshot);
+
+        if reader.u8()? != SNAPSHOT_VERSION || reader.u8()? != TRANSCRIPT_VERSION {
+            return Err(error::Kind::Snapshot.into());
+        }
+
+        let max_depth = reader.usize()?;
+        let events = reader.usize()?;
+        let value_bytes = reader.u64()?;
+        let state = decode_state(&mut reader)?;
+
+        let frame = match reader.u8()? {
+            0 => None,
+            1 => Some(reader.u64()?),
+            _ => return Err(error::Kind::Snapshot.into()),
+        };
+
+        let frames = reader.u64()?;
+
+        let initial = match reader.u8()? {
+            0 => None,
+            1 => Some(restore_verihash(reader.bytes()?)?),
+            _ => return Err(error::Kind::Snapshot.into()),
+        };
+
+        let redaction = match reader.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(error::Kind::Snapshot.into()),
+        };
+
+        let redacting = match reader.u8()? {
+            0 => None,
+            1 => Some((reader.header()?, restore_verihash(reader.bytes()?)?)),
+            _ => return Err(error::Kind::Snapshot.into()),
+        };
+
+        // Normalization requires the `unicode-normalization` feature
+        let nfc = reader.bool()?;
+
+        if nfc && cfg!(not(feature = "unicode-normalization")) {
+            return Err(error::Kind::Snapshot.into());
+        }
+
+        let verihash = restore_verihash(reader.bytes()?)?;
+
+        if !reader.0.is_empty() {
+            return Err(error::Kind::Snapshot.into());
+        }
+
+        Ok(Self {
+            verihash,
+            state,
+            events,
+            value_bytes,
+            initial,
+            frame,
+            frames,
+            max_depth,
+            redaction,
+            redacting,
+            nfc,
+        })
+    }
+}
+
+/// Restore a Verihash hasher from a serialized digest state
+fn restore_verihash<D: ResumableDigest>(state: &[u8]) -> Result<verihash::Hasher<D>, Error> {
+    D::restore_state(state).map(verihash::Hasher::from_digest)
+}
+
+/// Encode the hasher's state as a discriminant byte followed by its fields
+/// (`None`, i.e. a poisoned hasher, is encoded as `0x00`)
+fn encode_state(out: &mut Vec<u8>, state: Option<&State>) {
+    match state {
+        None => out.push(0),
+        Some(State::Initial) => out.push(1),
+        Some(State::Header(header)) => {
+            out.push(2);
+            put_header(out, header);
+        }
+        Some(State::Bytes { remaining }) => {
+            out.push(3);
+            put_u64(out, *remaining as u64);
+        }
+        Some(State::String { remaining, utf8 }) => {
+            let (carry, pos) = utf8.parts();
+            out.push(4);
+            put_u64(out, *remaining as u64);
+            put_u64(out, pos as u64);
+            put_bytes(out, carry);
+        }
+        Some(State::Message {
+            remaining,
+            critical,
+        }) => {
+            out.push(5);
+            put_u64(out, *remaining as u64);
+            out.push(*critical as u8);
+        }
+        Some(State::Sequence {
+            wire_type,
+            remaining,
+            critical,
+        }) => {
+            out.push(6);
+            out.push(wire_type.to_u8());
+            put_u64(out, *remaining as u64);
+            out.push(*critical as u8);
+        }
+        #[cfg(feature = "unicode-normalization")]
+        Some(State::NormalizedString {
+            header,
+            remaining,
+            utf8,
+            buffer,
+        }) => {
+            let (carry, pos) = utf8.parts();
+            out.push(7);
+            put_header(out, header);
+            put_u64(out, *remaining as u64);
+            put_u64(out, pos as u64);
+            put_bytes(out, carry);
+            put_bytes(out, buffer);
+        }
+    }
+}
+
+/// Decode a state encoded by `encode_state`
+fn decode_state(reader: &mut Reader<'_>) -> Result<Option<State>, Error> {
+    let state = match reader.u8()? {
+        0 => return Ok(None),
+        1 => State::Initial,
+        2 => State::Header(reader.header()?),
+        3 => State::Bytes {
+            remaining: reader.usize()?,
+        },
+        4 => {
+            let remaining = reader.usize()?;
+            let pos = reader.usize()?;
+            let utf8 =
+                Utf8Validator::from_parts(reader.bytes()?, pos).ok_or(error::Kind::Snapshot)?;
+            State::String { remaining, utf8 }
+        }
+        5 => State::Message {
+            remaining: reader.usize()?,
+            critical: reader.bool()?,
+        },
+        6 => State::Sequence {
+            wire_type: reader.wire_type()?,
+            remaining: reader.usize()?,
+            critical: reader.bool()?,
+        },
+        #[cfg(feature = "unicode-normalization")]
+        7 => {
+            let header = reader.header()?;
+            let remaining = reader.usize()?;
+            let pos = reader.usize()?;
+            let utf8 =
+                Utf8Validator::from_parts(reader.bytes()?, pos).ok_or(error::Kind::Snapshot)?;
+            let buffer = reader.bytes()?.into();
+            State::NormalizedString {
+                header,
+                remaining,
+                utf8,
+                buffer,
+            }
+        }
+        _ => return Err(error::Kind::Snapshot.into()),
+    };
+
+    Ok(Some(state))
+}
+
+/// Append a field header as its little-endian tag, criticality, and wire
+/// type
+fn put_header(out: &mut Vec<u8>, header: &Header) {
+    put_u64(out, header.tag.to_u64());
+    out.push(header.critical as u8);
+    out.push(header.wire_type.to_u8());
+}
+
+/// Append a little-endian `u64`
+fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Append a length-prefixed byte string
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_u64(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+/// Reader for snapshots: every read fails with `Kind::Snapshot` if the
+/// snapshot is truncated or malformed
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    /// Read the given number of bytes
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(error::Kind::Snapshot.into());
+        }
+
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    /// Read a single byte
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// Read a little-endian `u64`
+    fn u64(&mut self) -> Result<u64, Error> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Read a `u64` which must fit in a `usize`
+    fn usize(&mut self) -> Result<usize, Error> {
+        usize::try_from(self.u64()?).map_err(|_| error::Kind::Snapshot.into())
+    }
+
+    /// Read a length-prefixed byte string
+    fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.usize()?;
+        self.take(len)
+    }
+
+    /// Read a field header encoded by `put_header`
+    fn header(&mut self) -> Result<Header, Error> {
+        let tag = Tag::new(self.u64()?).map_err(|_| Error::from(error::Kind::Snapshot))?;
+        let critical = self.bool()?;
+        Ok(Header::new(tag, critical, self.wire_type()?))
+    }
+
+    /// Read a boolean encoded as a `0x00` or `0x01` byte
+    fn bool(&mut self) -> Result<bool, Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(error::Kind::Snapshot.into()),
+        }
+    }
+
+    /// Read a wire type
+    fn wire_type(&mut self) -> Result<WireType, Error> {
+        WireType::try_from(self.u8()?).map_err(|_| error::Kind::Snapshot.into())
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::ResumableDigest;
+    use crate::{
+        decoder::{Event, Hasher},
+        error::{self, Error},
+        field::{Header, Tag, WireType},
+    };
+    use alloc::vec::Vec;
+    use digest::{consts::U32, generic_array::GenericArray, FixedOutput, Reset, Update};
+    use sha2::{Digest, Sha256};
+
+    /// SHA-256 which buffers its input so its state is trivially resumable
+    #[derive(Clone, Default)]
+    struct BufferedSha256(Vec<u8>);
+
+    impl Update for BufferedSha256 {
+        fn update(&mut self, data: impl AsRef<[u8]>) {
+            self.0.extend_from_slice(data.as_ref());
+        }
+    }
+
+    impl FixedOutput for BufferedSha256 {
+        type OutputSize = U32;
+
+        fn finalize_into(self, out: &mut GenericArray<u8, U32>) {
+            out.copy_from_slice(&Sha256::digest(&self.0));
+        }
+
+        fn finalize_into_reset(&mut self, out: &mut GenericArray<u8, U32>) {
+            out.copy_from_slice(&Sha256::digest(&self.0));
+            self.0.clear();
+        }
+    }
+
+    impl Reset for BufferedSha256 {
+        fn reset(&mut self) {
+            self.0.clear();
+        }
+    }
+
+    impl ResumableDigest for BufferedSha256 {
+        fn save_state(&self) -> Vec<u8> {
+            self.0.clone()
+        }
+
+        fn restore_state(state: &[u8]) -> Result<Self, Error> {
+            Ok(BufferedSha256(state.into()))
+        }
+    }
+
+    /// Events for `{ 1: bytes("Hello, world!"), 2: string("crab") }`, with
+    /// each value split across two chunks
+    pub(super) fn chunked_events() -> [Event<'static>; 8] {
+        [
+            Event::FieldHeader(Header::new(Tag::new_const(1), false, WireType::Bytes)),
+            Event::LengthDelimiter {
+                wire_type: WireType::Bytes,
+                length: 13,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::Bytes,
+                bytes: b"Hello,",
+                remaining: 7,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::Bytes,
+                bytes: b" world!",
+                remaining: 0,
+            },
+            Event::FieldHeader(Header::new(Tag::new_const(2), false, WireType::String)),
+            Event::LengthDelimiter {
+                wire_type: WireType::String,
+                length: 4,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::String,
+                bytes: b"cr",
+                remaining: 2,
+            },
+            Event::ValueChunk {
+                wire_type: WireType::String,
+                bytes: b"ab",
+                remaining: 0,
+            },
+        ]
+    }
+
+    #[test]
+    fn resume_at_every_event() {
+        let events = chunked_events();
+
+        let mut expected = Hasher::<Sha256>::new();
+        expected.hash_events(&events).unwrap();
+        let expected = expected.finish().unwrap();
+
+        let hashers = [
+            Hasher::<BufferedSha256>::new_with_domain(b"example"),
+            Hasher::<BufferedSha256>::new().with_redaction(),
+        ];
+
+        for split in 0..=events.len() {
+            for mut hasher in hashers.iter().cloned() {
+                hasher.hash_events(&events[..split]).unwrap();
+                let snapshot = hasher.snapshot();
+
+                let mut restored = Hasher::<BufferedSha256>::restore(&snapshot).unwrap();
+                assert_eq!(restored.state_description(), hasher.state_description());
+
+                restored.hash_events(&events[split..]).unwrap();
+                hasher.hash_events(&events[split..]).unwrap();
+                assert_eq!(restored.finish().unwrap(), hasher.finish().unwrap());
+            }
+        }
+
+        let mut hasher = Hasher::<BufferedSha256>::new();
+        hasher.hash_events(&events[..3]).unwrap();
+
+        let mut restored = Hasher::<BufferedSha256>::restore(&hasher.snapshot()).unwrap();
+        restored.hash_events(&events[3..]).unwrap();
+        assert_eq!(restored.finish().unwrap().as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn restore_reset() {
+        let mut hasher = Hasher::<BufferedSha256>::new_with_domain(b"example");
+        hasher.hash_events(&chunked_events()[..3]).unwrap();
+
+        let mut restored = Hasher::<BufferedSha256>::restore(&hasher.snapshot()).unwrap();
+        restored.reset();
+
+        assert_eq!(
+            restored.finish().unwrap(),
+            Hasher::<BufferedSha256>::new_with_domain(b"example")
+                .finish()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn restore_poisoned() {
+        let events = chunked_events();
+        let mut hasher = Hasher::<BufferedSha256>::new();
+        assert!(hasher.hash_event(&events[2]).is_err());
+
+        let mut restored = Hasher::<BufferedSha256>::restore(&hasher.snapshot()).unwrap();
+        let err = restored.hash_event(&events[0]).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Poisoned { index: 0 });
+    }
+
+    #[test]
+    fn reject_incompatible_version() {
+        let snapshot = Hasher::<BufferedSha256>::new().snapshot();
+
+        for &index in &[0, 1] {
+            let mut snapshot = snapshot.clone();
+            snapshot[index] = snapshot[index].wrapping_add(1);
+
+            let err = Hasher::<BufferedSha256>::restore(&snapshot).err().unwrap();
+            assert_eq!(err.kind(), error::Kind::Snapshot);
+        }
+    }
+
+    #[test]
+    fn reject_malformed() {
+        let mut snapshot = Hasher::<BufferedSha256>::new().snapshot();
+
+        let err = Hasher::<BufferedSha256>::restore(&snapshot[..snapshot.len() - 1])
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), error::Kind::Snapshot);
+
+        snapshot.push(0);
+        let err = Hasher::<BufferedSha256>::restore(&snapshot).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Snapshot);
+    }
+}
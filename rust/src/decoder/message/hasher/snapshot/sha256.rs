@@ -0,0 +1,278 @@
+//! Resumable SHA-256, built on the `sha2` crate's compression function.
+//!
+//! State format:
+//!
+//! - chaining value: eight little-endian `u32` words
+//! - total number of bytes absorbed (little-endian `u64`)
+//! - buffered bytes of the current, incomplete block
+
+use super::ResumableDigest;
+use crate::error::{self, Error};
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use digest::{
+    consts::{U32, U64},
+    generic_array::GenericArray,
+    BlockInput, FixedOutput, Reset, Update,
+};
+
+/// SHA-256 block size in bytes
+const BLOCK_SIZE: usize = 64;
+
+/// SHA-256 initial chaining value
+const IV: [u32; 8] = [
+    0x6a09_e667,
+    0xbb67_ae85,
+    0x3c6e_f372,
+    0xa54f_f53a,
+    0x510e_527f,
+    0x9b05_688c,
+    0x1f83_d9ab,
+    0x5be0_cd19,
+];
+
+/// Size of a saved state, excluding buffered bytes
+const STATE_SIZE: usize = 8 * 4 + 8;
+
+/// SHA-256 whose internal state can be saved and restored.
+///
+/// Produces output identical to [`sha2::Sha256`].
+#[derive(Clone)]
+pub struct ResumableSha256 {
+    /// Chaining value
+    state: [u32; 8],
+
+    /// Buffered input which does not yet fill a block
+    buffer: [u8; BLOCK_SIZE],
+
+    /// Total number of bytes absorbed
+    length: u64,
+}
+
+impl ResumableSha256 {
+    /// Create a new SHA-256 hasher
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of bytes buffered in the current block
+    fn buffered(&self) -> usize {
+        (self.length % BLOCK_SIZE as u64) as usize
+    }
+
+    /// Compress a single block into the chaining value
+    fn compress(&mut self, block: &[u8]) {
+        sha2::compress256(&mut self.state, &[*GenericArray::from_slice(block)]);
+    }
+
+    /// Pad the final block and compress it, returning the output
+    fn finish(&mut self) -> GenericArray<u8, U32> {
+        let bit_length = self.length.wrapping_mul(8);
+        let pos = self.buffered();
+
+        let mut buffer = self.buffer;
+        buffer[pos] = 0x80;
+        buffer[pos + 1..].iter_mut().for_each(|b| *b = 0);
+
+        if pos + 1 > BLOCK_SIZE - 8 {
+            self.compress(&buffer);
+            buffer = [0; BLOCK_SIZE];
+        }
+
+        buffer[BLOCK_SIZE - 8..].copy_from_slice(&bit_length.to_be_bytes());
+        self.compress(&buffer);
+
+        let mut out = GenericArray::default();
+        for (chunk, word) in out.chunks_mut(4).zip(self.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+}
+
+impl Default for ResumableSha256 {
+    fn default() -> Self {
+        Self {
+            state: IV,
+            buffer: [0; BLOCK_SIZE],
+            length: 0,
+        }
+    }
+}
+
+impl BlockInput for ResumableSha256 {
+    type BlockSize = U64;
+}
+
+impl Update for ResumableSha256 {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        let mut data = data.as_ref();
+        let pos = self.buffered();
+        self.length = self.length.wrapping_add(data.len() as u64);
+
+        if pos > 0 {
+            let n = data.len().min(BLOCK_SIZE - pos);
+            self.buffer[pos..pos + n].copy_from_slice(&data[..n]);
+            data = &data[n..];
+
+            if pos + n < BLOCK_SIZE {
+                return;
+            }
+
+            let block = self.buffer;
+            self.compress(&block);
+        }
+
+        let mut blocks = data.chunks_exact(BLOCK_SIZE);
+
+        for block in &mut blocks {
+            self.compress(block);
+        }
+
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+    }
+}
+
+impl FixedOutput for ResumableSha256 {
+    type OutputSize = U32;
+
+    fn finalize_into(mut self, out: &mut GenericArray<u8, U32>) {
+        *out = self.finish();
+    }
+
+    fn finalize_into_reset(&mut self, out: &mut GenericArray<u8, U32>) {
+        *out = self.finish();
+        self.reset();
+    }
+}
+
+impl Reset for ResumableSha256 {
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl ResumableDigest for ResumableSha256 {
+    fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(STATE_SIZE + self.buffered());
+
+        for word in &self.state {
+            out.extend_from_slice(&word.to_le_bytes());
+        }
+
+        out.extend_from_slice(&self.length.to_le_bytes());
+        out.extend_from_slice(&self.buffer[..self.buffered()]);
+        out
+    }
+
+    fn restore_state(state: &[u8]) -> Result<Self, Error> {
+        if state.len() < STATE_SIZE {
+            return Err(error::Kind::Snapshot.into());
+        }
+
+        let (words, rest) = state.split_at(8 * 4);
+        let (length, buffered) = rest.split_at(8);
+
+        let mut restored = Self {
+            length: u64::from_le_bytes(length.try_into().unwrap()),
+            ..Self::default()
+        };
+
+        if buffered.len() != restored.buffered() {
+            return Err(error::Kind::Snapshot.into());
+        }
+
+        for (word, bytes) in restored.state.iter_mut().zip(words.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+
+        restored.buffer[..buffered.len()].copy_from_slice(buffered);
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::chunked_events;
+    use super::{ResumableDigest, ResumableSha256, STATE_SIZE};
+    use crate::{decoder::Hasher, error};
+    use alloc::vec::Vec;
+    use sha2::{Digest, Sha256};
+
+    /// Input of the given length
+    fn input(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + 3) as u8).collect()
+    }
+
+    #[test]
+    fn matches_sha256() {
+        for len in (0..200).chain([1000, 4096, 4097].iter().copied()) {
+            let input = input(len);
+            assert_eq!(
+                ResumableSha256::digest(&input),
+                Sha256::digest(&input),
+                "length {}",
+                len
+            );
+
+            let mut hasher = ResumableSha256::new();
+            for chunk in input.chunks(13) {
+                hasher.update(chunk);
+            }
+            assert_eq!(hasher.finalize(), Sha256::digest(&input), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn save_and_restore_mid_stream() {
+        let input = input(300);
+
+        for split in 0..=input.len() {
+            let mut hasher = ResumableSha256::new();
+            hasher.update(&input[..split]);
+
+            let state = hasher.save_state();
+            assert_eq!(state.len(), STATE_SIZE + split % 64);
+
+            let mut restored = ResumableSha256::restore_state(&state).unwrap();
+            restored.update(&input[split..]);
+            assert_eq!(
+                restored.finalize(),
+                Sha256::digest(&input),
+                "split {}",
+                split
+            );
+        }
+    }
+
+    #[test]
+    fn reject_malformed_state() {
+        let mut hasher = ResumableSha256::new();
+        hasher.update(b"crab");
+        let state = hasher.save_state();
+
+        for bad in &[&state[..STATE_SIZE - 1], &state[..state.len() - 1]] {
+            let err = ResumableSha256::restore_state(bad).err().unwrap();
+            assert_eq!(err.kind(), error::Kind::Snapshot);
+        }
+    }
+
+    #[test]
+    fn hasher_snapshot_round_trip() {
+        let events = chunked_events();
+
+        let mut expected = Hasher::<Sha256>::new();
+        expected.hash_events(&events).unwrap();
+        let expected = expected.finish().unwrap();
+
+        for split in 0..=events.len() {
+            let mut hasher = Hasher::<ResumableSha256>::new();
+            hasher.hash_events(&events[..split]).unwrap();
+
+            let mut restored = Hasher::<ResumableSha256>::restore(&hasher.snapshot()).unwrap();
+            restored.hash_events(&events[split..]).unwrap();
+            assert_eq!(restored.finish().unwrap().as_bytes(), expected.as_bytes());
+        }
+    }
+}
@@ -0,0 +1,701 @@
+//! Verihash tree hasher: computes per-field leaf digests of a message and
+//! the root of a binary Merkle tree over them, for inclusion proofs.
+//!
+//! WARNING: this is an experimental PoC-quality implementation!
+//! It is NOT suitable for production use!
+//!
+//! Each field's transcript (tag, wire type, and value, absorbed exactly as
+//! they are by the message `Hasher`) is hashed into its own leaf digest,
+//! prefixed by a `0x00` byte and (for salted hashers) the leaf's salt.
+//! Leaves are combined into a tree as in RFC 6962: each node is the hash of
+//! a `0x01` byte followed by its left and right children, where the left
+//! subtree holds the largest power of two of leaves less than the total.
+//!
+//! Salts keep the leaves of fields which aren't being proven from being
+//! brute forced from their siblings in a proof: each leaf's salt is derived
+//! from a random seed drawn when the hasher is created, and only the salt of
+//! the proven leaf is revealed.
+//!
+//! Nested messages are hashed recursively (without salts), with the root of
+//! the nested message's tree absorbed into the leaf of the field containing
+//! it. Sequences are absorbed into a single leaf using the sequence digest.
+
+use super::{drive_decoder, State, Transcript};
+use crate::{
+    decoder::Event,
+    error::{self, Error},
+    field::{Tag, WireType},
+    verihash::{self, DigestOutput},
+};
+use alloc::vec::Vec;
+use core::{
+    fmt::{self, Debug},
+    mem,
+};
+use digest::Digest;
+use rand_core::{CryptoRng, RngCore};
+
+/// Prefix absorbed at the beginning of every leaf digest
+const LEAF_PREFIX: u8 = 0x00;
+
+/// Prefix absorbed at the beginning of every node digest (including the
+/// root digest of a message with no fields)
+const NODE_PREFIX: u8 = 0x01;
+
+/// Prefix absorbed at the beginning of every leaf salt
+const SALT_PREFIX: u8 = 0x02;
+
+/// Size of the random seed leaf salts are derived from
+const SEED_SIZE: usize = 32;
+
+/// Verihash tree hasher.
+///
+/// This type computes per-field leaf digests of a message, driven by
+/// incoming decoding events, which can be used to prove a field was present
+/// in a message without revealing its other fields.
+pub struct TreeHasher<D: Digest> {
+    /// Verihash hasher for the leaf of the field presently being hashed
+    leaf: verihash::Hasher<D>,
+
+    /// Seed the salt of each leaf is derived from (if salted)
+    seed: Option<[u8; SEED_SIZE]>,
+
+    /// Current state of the hasher (or `None` if an error occurred)
+    state: Option<State>,
+
+    /// Number of events hashed successfully
+    events: usize,
+
+    /// Tag of the field presently being hashed
+    tag: Option<Tag>,
+
+    /// Tags of the fields which have been hashed
+    tags: Vec<Tag>,
+
+    /// Leaf digests of the fields which have been hashed
+    leaves: Vec<DigestOutput<D>>,
+}
+
+impl<D> TreeHasher<D>
+where
+    D: Digest,
+{
+    /// Create a new [`TreeHasher`] whose leaves are unsalted, e.g. to
+    /// compute the deterministic root of a message's tree.
+    ///
+    /// Proofs created by unsalted hashers don't hide the fields which
+    /// aren't being proven if they can be guessed.
+    pub fn new() -> Self {
+        Self {
+            leaf: new_leaf(None),
+            seed: None,
+            state: Some(State::default()),
+            events: 0,
+            tag: None,
+            tags: Vec::new(),
+            leaves: Vec::new(),
+        }
+    }
+
+    /// Create a new [`TreeHasher`] whose leaves are salted with salts
+    /// derived from a seed drawn from the given random number generator.
+    pub fn new_salted(rng: &mut (impl CryptoRng + RngCore)) -> Self {
+        let mut seed = [0u8; SEED_SIZE];
+        rng.fill_bytes(&mut seed);
+
+        Self {
+            leaf: new_leaf(Some(&leaf_salt::<D>(&seed, 0))),
+            seed: Some(seed),
+            ..Self::new()
+        }
+    }
+
+    /// Hash an incoming event
+    pub fn hash_event(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        if let Some(state) = self.state.take() {
+            if let Event::FieldHeader(header) = event {
+                self.tag = Some(header.tag);
+            }
+
+            let new_state = state.transition(event, &mut self.leaf, false)?;
+
+            if new_state == State::Initial {
+                self.finish_leaf();
+            }
+
+            self.state = Some(new_state);
+            self.events = self.events.checked_add(1).unwrap();
+            Ok(())
+        } else {
+            Err(self.poisoned_error())
+        }
+    }
+
+    /// Hash a serialized message, decoding it and forwarding each of the
+    /// resulting events to [`TreeHasher::hash_event`].
+    ///
+    /// Errors are reported the same way as `Hasher::hash_message`.
+    pub fn hash_message(&mut self, input: &[u8]) -> Result<(), Error> {
+        let result = drive_decoder(self, input, input.len().max(1), Default::default());
+
+        if result.is_err() {
+            self.state = None;
+        }
+
+        result
+    }
+
+    /// Hash the root digest of a nested message within this message
+    pub fn hash_message_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error> {
+        self.hash_nested_digest(tag, WireType::Message, digest)
+    }
+
+    /// Hash a digest of a sequence within this message
+    pub fn hash_sequence_digest(
+        &mut self,
+        tag: Tag,
+        digest: &DigestOutput<D>,
+    ) -> Result<(), Error> {
+        self.hash_nested_digest(tag, WireType::Sequence, digest)
+    }
+
+    /// Get the leaf digests of the fields hashed so far, in the order they
+    /// were hashed
+    pub fn leaf_digests(&self) -> &[DigestOutput<D>] {
+        &self.leaves
+    }
+
+    /// Compute the root digest of the message without consuming the hasher
+    pub fn root(&self) -> Result<DigestOutput<D>, Error> {
+        self.ensure_finished()?;
+        Ok(tree_root::<D>(&self.leaves))
+    }
+
+    /// Finish computing the root digest of the message
+    pub fn finish(self) -> Result<DigestOutput<D>, Error> {
+        self.root()
+    }
+
+    /// Create a proof that the field with the given tag is included in the
+    /// message hashed by this [`TreeHasher`]
+    pub fn prove(&self, tag: Tag) -> Result<Proof<D>, Error> {
+        self.ensure_finished()?;
+
+        let index = self.tags.iter().position(|&t| t == tag).ok_or_else(|| {
+            Error::from(error::Kind::FieldHeader {
+                tag: Some(tag),
+                wire_type: None,
+            })
+        })?;
+
+        let mut path = Vec::new();
+        audit_path::<D>(index, &self.leaves, &mut path);
+
+        Ok(Proof {
+            index,
+            leaf_count: self.leaves.len(),
+            salt: self.seed.as_ref().map(|seed| leaf_salt::<D>(seed, index)),
+            path,
+        })
+    }
+
+    /// Verify a proof that the given serialized field (i.e. field header
+    /// and value) is included in the message with the given root digest
+    pub fn verify_proof(
+        root: &DigestOutput<D>,
+        field: &[u8],
+        proof: &Proof<D>,
+    ) -> Result<(), Error> {
+        let mut hasher = Self::new();
+        hasher.leaf = new_leaf(proof.salt.as_ref());
+        hasher.hash_message(field)?;
+        hasher.ensure_finished()?;
+
+        if hasher.leaves.len() != 1 {
+            return Err(error::HashingReason::DigestMismatch.into());
+        }
+
+        match path_root::<D>(
+            proof.index,
+            proof.leaf_count,
+            &hasher.leaves[0],
+            &proof.path,
+        ) {
+            Some(ref computed) if computed == root => Ok(()),
+            _ => Err(error::HashingReason::DigestMismatch.into()),
+        }
+    }
+
+    /// Hash the digest of a nested message or sequence into the current leaf
+    fn hash_nested_digest(
+        &mut self,
+        tag: Tag,
+        wire_type: WireType,
+        digest: &DigestOutput<D>,
+    ) -> Result<(), Error> {
+        match (self.state.as_ref(), wire_type) {
+            (
+                Some(&State::Message {
+                    remaining: 0,
+                    critical,
+                }),
+                WireType::Message,
+            )
+            | (
+                Some(&State::Sequence {
+                    remaining: 0,
+                    critical,
+                    ..
+                }),
+                WireType::Sequence,
+            ) => {
+                self.leaf.field(tag, critical);
+                self.leaf.fixed_size_value(wire_type, digest);
+                self.finish_leaf();
+                self.state = Some(State::Initial);
+                Ok(())
+            }
+            _ => Err(error::HashingReason::UnexpectedEvent.into()),
+        }
+    }
+
+    /// Finish computing the leaf digest of the current field
+    fn finish_leaf(&mut self) {
+        let index = self.leaves.len().checked_add(1).unwrap();
+        let salt = self.seed.as_ref().map(|seed| leaf_salt::<D>(seed, index));
+        let leaf = mem::replace(&mut self.leaf, new_leaf(salt.as_ref()));
+
+        self.leaves.push(leaf.finalize());
+        self.tags.push(self.tag.take().unwrap());
+    }
+
+    /// Ensure we aren't in the middle of hashing a field and no previous
+    /// errors occurred
+    fn ensure_finished(&self) -> Result<(), Error> {
+        match self.state {
+            Some(State::Initial) => Ok(()),
+            Some(_) => Err(error::HashingReason::Incomplete.into()),
+            None => Err(self.poisoned_error()),
+        }
+    }
+
+    /// Error returned when the hasher has been poisoned by a previous error
+    fn poisoned_error(&self) -> Error {
+        error::Kind::Poisoned { index: self.events }.into()
+    }
+}
+
+impl<D> Default for TreeHasher<D>
+where
+    D: Digest,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> Debug for TreeHasher<D>
+where
+    D: Digest,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("message::TreeHasher")
+            .field("tags", &self.tags)
+            .field("salted", &self.seed.is_some())
+            .finish()
+    }
+}
+
+impl<D> Transcript<D> for TreeHasher<D>
+where
+    D: Digest,
+{
+    fn hash_event(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        TreeHasher::hash_event(self, event)
+    }
+
+    fn hash_message_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error> {
+        TreeHasher::hash_message_digest(self, tag, digest)
+    }
+
+    fn hash_sequence_digest(&mut self, tag: Tag, digest: &DigestOutput<D>) -> Result<(), Error> {
+        TreeHasher::hash_sequence_digest(self, tag, digest)
+    }
+
+    fn finish(self) -> Result<DigestOutput<D>, Error> {
+        TreeHasher::finish(self)
+    }
+}
+
+/// Proof that a field is included in a message: the index of the field's
+/// leaf and the number of leaves in the message's tree, along with the
+/// leaf's salt (if any) and the digests of the siblings on the path from
+/// the leaf to the root.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Proof<D: Digest> {
+    /// Index of the proven field's leaf
+    index: usize,
+
+    /// Number of leaves in the tree, i.e. fields in the message
+    leaf_count: usize,
+
+    /// Salt of the proven field's leaf
+    salt: Option<DigestOutput<D>>,
+
+    /// Digests of the siblings of the nodes on the path from the leaf to
+    /// the root, starting at the leaf
+    path: Vec<DigestOutput<D>>,
+}
+
+impl<D> Proof<D>
+where
+    D: Digest,
+{
+    /// Get the index of the proven field's leaf
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Get the number of leaves in the tree
+    pub fn leaf_count(&self) -> usize {
+        self.leaf_count
+    }
+
+    /// Get the salt of the proven field's leaf, if the tree was salted
+    pub fn salt(&self) -> Option<&DigestOutput<D>> {
+        self.salt.as_ref()
+    }
+
+    /// Get the digests of the siblings on the path from the leaf to the root
+    pub fn path(&self) -> &[DigestOutput<D>] {
+        &self.path
+    }
+}
+
+/// Create a new leaf hasher, absorbing the leaf's salt (if any)
+fn new_leaf<D: Digest>(salt: Option<&DigestOutput<D>>) -> verihash::Hasher<D> {
+    let mut leaf = verihash::Hasher::new();
+    leaf.update(&[LEAF_PREFIX]);
+
+    if let Some(salt) = salt {
+        leaf.update(salt);
+    }
+
+    leaf
+}
+
+/// Derive the salt of the leaf with the given index from a seed
+fn leaf_salt<D: Digest>(seed: &[u8; SEED_SIZE], index: usize) -> DigestOutput<D> {
+    let mut salt = verihash::Hasher::<D>::new();
+    salt.update(&[SALT_PREFIX]);
+    salt.update(seed);
+    salt.update(&(index as u64).to_le_bytes());
+    salt.finalize()
+}
+
+/// Compute the digest of a node from the digests of its children
+fn node_digest<D: Digest>(left: &DigestOutput<D>, right: &DigestOutput<D>) -> DigestOutput<D> {
+    let mut node = verihash::Hasher::<D>::new();
+    node.update(&[NODE_PREFIX]);
+    node.update(left);
+    node.update(right);
+    node.finalize()
+}
+
+/// Number of leaves in the left subtree of a tree with `n > 1` leaves: the
+/// largest power of two less than `n`
+fn split_point(n: usize) -> usize {
+    let mut k: usize = 1;
+
+    while k.checked_mul(2).unwrap() < n {
+        k *= 2;
+    }
+
+    k
+}
+
+/// Compute the root digest of the tree with the given leaves
+fn tree_root<D: Digest>(leaves: &[DigestOutput<D>]) -> DigestOutput<D> {
+    match leaves.len() {
+        0 => {
+            let mut root = verihash::Hasher::<D>::new();
+            root.update(&[NODE_PREFIX]);
+            root.finalize()
+        }
+        1 => leaves[0].clone(),
+        n => {
+            let (left, right) = leaves.split_at(split_point(n));
+            node_digest::<D>(&tree_root::<D>(left), &tree_root::<D>(right))
+        }
+    }
+}
+
+/// Append the digests of the siblings on the path from the leaf with the
+/// given index to the root of the tree with the given leaves to `path`
+fn audit_path<D: Digest>(
+    index: usize,
+    leaves: &[DigestOutput<D>],
+    path: &mut Vec<DigestOutput<D>>,
+) {
+    if leaves.len() <= 1 {
+        return;
+    }
+
+    let (left, right) = leaves.split_at(split_point(leaves.len()));
+
+    if index < left.len() {
+        audit_path::<D>(index, left, path);
+        path.push(tree_root::<D>(right));
+    } else {
+        audit_path::<D>(index - left.len(), right, path);
+        path.push(tree_root::<D>(left));
+    }
+}
+
+/// Compute the root digest of a tree with `leaf_count` leaves from the leaf
+/// with the given index and its audit path (as described in RFC 9162
+/// section 2.1.3.2), or `None` if the path doesn't fit the tree
+fn path_root<D: Digest>(
+    index: usize,
+    leaf_count: usize,
+    leaf: &DigestOutput<D>,
+    path: &[DigestOutput<D>],
+) -> Option<DigestOutput<D>> {
+    if index >= leaf_count {
+        return None;
+    }
+
+    let mut node_index = index;
+    let mut last_index = leaf_count - 1;
+    let mut digest = leaf.clone();
+
+    for sibling in path {
+        if last_index == 0 {
+            return None;
+        }
+
+        if node_index & 1 == 1 || node_index == last_index {
+            digest = node_digest::<D>(sibling, &digest);
+
+            // Skip the levels where the node has no sibling
+            while node_index & 1 == 0 && node_index != 0 {
+                node_index >>= 1;
+                last_index >>= 1;
+            }
+        } else {
+            digest = node_digest::<D>(&digest, sibling);
+        }
+
+        node_index >>= 1;
+        last_index >>= 1;
+    }
+
+    if last_index == 0 {
+        Some(digest)
+    } else {
+        None
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{new_leaf, node_digest, tree_root, Proof, TreeHasher, WireType};
+    use crate::{
+        decoder::Hasher,
+        error,
+        field::{self, Tag},
+        Encoder,
+    };
+    use alloc::vec::Vec;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+    use sha2::Sha256;
+
+    /// `{ 1: uint64(42), 2: string('baz'), 3: message({ 0: uint64(42) }) }`
+    const EXAMPLE_MESSAGE: &[u8] = &[37, 85, 75, 7, 98, 97, 122, 109, 5, 5, 85];
+
+    /// Serialized `2: string('baz')` field of the example message
+    const EXAMPLE_FIELD: &[u8] = &[75, 7, 98, 97, 122];
+
+    /// Create a salted hasher with a deterministic seed
+    fn salted(seed: u64) -> TreeHasher<Sha256> {
+        TreeHasher::new_salted(&mut ChaCha20Rng::seed_from_u64(seed))
+    }
+
+    /// Serialize a message with `count` `uint64` fields tagged from 0,
+    /// returning it along with the end offset of each field
+    fn uint64_fields(count: u64) -> (Vec<u8>, Vec<usize>) {
+        let mut buffer = [0u8; 64];
+        let mut encoder = Encoder::new(&mut buffer);
+        let mut ends = Vec::new();
+
+        for tag in 0..count {
+            let tag = Tag::new(tag).unwrap();
+            encoder.uint64(tag, false, 42).unwrap();
+
+            let start = ends.last().cloned().unwrap_or(0);
+            ends.push(start + field::length::uint64(tag, 42));
+        }
+
+        (encoder.finish().to_vec(), ends)
+    }
+
+    #[test]
+    fn leaf_digests() {
+        let mut hasher = TreeHasher::<Sha256>::new();
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        let mut leaf = new_leaf::<Sha256>(None);
+        leaf.tagged_uint64(Tag::new_const(1), false, 42);
+
+        let leaves = hasher.leaf_digests();
+        assert_eq!(leaves.len(), 3);
+        assert_eq!(leaves[0], leaf.finalize());
+
+        // Nested messages contribute the root of their own tree
+        let mut child = TreeHasher::<Sha256>::new();
+        child.hash_message(&[5, 85]).unwrap();
+
+        let mut leaf = new_leaf::<Sha256>(None);
+        leaf.field(Tag::new_const(3), false);
+        leaf.fixed_size_value(WireType::Message, &child.finish().unwrap());
+        assert_eq!(leaves[2], leaf.finalize());
+
+        // The left subtree holds the largest power of two of leaves
+        let left = node_digest::<Sha256>(&leaves[0], &leaves[1]);
+        let root = node_digest::<Sha256>(&left, &leaves[2]);
+        assert_eq!(hasher.root().unwrap(), root);
+        assert_eq!(tree_root::<Sha256>(leaves), root);
+    }
+
+    #[test]
+    fn differs_from_flat_transcript() {
+        let mut tree = TreeHasher::<Sha256>::new();
+        tree.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        let mut flat = Hasher::<Sha256>::new();
+        flat.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        assert_ne!(tree.finish().unwrap(), flat.finish().unwrap().into_bytes());
+    }
+
+    #[test]
+    fn salted_leaves() {
+        let mut unsalted = TreeHasher::<Sha256>::new();
+        unsalted.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        let mut first = salted(1);
+        first.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        let mut second = salted(2);
+        second.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        // Every leaf has its own salt
+        for (i, leaf) in first.leaf_digests().iter().enumerate() {
+            assert_ne!(leaf, &unsalted.leaf_digests()[i]);
+            assert_ne!(leaf, &second.leaf_digests()[i]);
+        }
+
+        // Identical fields have different leaves within a message
+        let (message, _) = uint64_fields(2);
+        let mut twice = salted(1);
+        twice.hash_message(&message).unwrap();
+        assert_ne!(twice.leaf_digests()[0], twice.leaf_digests()[1]);
+
+        assert_ne!(first.root().unwrap(), second.root().unwrap());
+        assert_ne!(first.root().unwrap(), unsalted.root().unwrap());
+    }
+
+    #[test]
+    fn prove_and_verify() {
+        let mut hasher = salted(1);
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        let root = hasher.root().unwrap();
+        let proof = hasher.prove(Tag::new_const(2)).unwrap();
+        assert_eq!(proof.index(), 1);
+        assert_eq!(proof.leaf_count(), 3);
+        assert_eq!(proof.path().len(), 2);
+        assert!(proof.salt().is_some());
+
+        TreeHasher::verify_proof(&root, EXAMPLE_FIELD, &proof).unwrap();
+
+        // Field with a different value
+        assert!(TreeHasher::verify_proof(&root, &[75, 7, 98, 97, 114], &proof).is_err());
+
+        // Field proven at the wrong position
+        let other_proof = hasher.prove(Tag::new_const(1)).unwrap();
+        assert!(TreeHasher::verify_proof(&root, EXAMPLE_FIELD, &other_proof).is_err());
+
+        // Proof with a tampered salt, index, or leaf count
+        let tampered = [
+            Proof {
+                salt: None,
+                ..proof.clone()
+            },
+            Proof {
+                index: 0,
+                ..proof.clone()
+            },
+            Proof {
+                leaf_count: 2,
+                ..proof.clone()
+            },
+            Proof {
+                leaf_count: 1,
+                ..proof.clone()
+            },
+        ];
+
+        for proof in &tampered {
+            let err = TreeHasher::verify_proof(&root, EXAMPLE_FIELD, proof)
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), error::HashingReason::DigestMismatch.into());
+        }
+    }
+
+    #[test]
+    fn prove_every_field() {
+        for count in 1..=13 {
+            let (message, ends) = uint64_fields(count);
+            let mut hasher = salted(count);
+            hasher.hash_message(&message).unwrap();
+            let root = hasher.root().unwrap();
+
+            // Paths have at most one sibling per level of the tree
+            let depth = (0..).find(|&d| 1u64 << d >= count).unwrap();
+
+            for (index, &end) in ends.iter().enumerate() {
+                let proof = hasher.prove(Tag::new(index as u64).unwrap()).unwrap();
+                assert!(proof.path().len() <= depth);
+
+                let start = if index == 0 { 0 } else { ends[index - 1] };
+                TreeHasher::verify_proof(&root, &message[start..end], &proof).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn prove_missing_tag() {
+        let mut hasher = TreeHasher::<Sha256>::new();
+        hasher.hash_message(EXAMPLE_MESSAGE).unwrap();
+
+        let err = hasher.prove(Tag::new_const(4)).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::FieldHeader {
+                tag: Some(Tag::new_const(4)),
+                wire_type: None
+            }
+        );
+    }
+
+    #[test]
+    fn poisoned() {
+        let mut hasher = TreeHasher::<Sha256>::new();
+        assert!(hasher.hash_message(&[37, 85, 37]).is_err());
+
+        let err = hasher.root().err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Poisoned { index: 2 });
+    }
+}
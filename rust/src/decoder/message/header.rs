@@ -12,22 +12,38 @@ use crate::{
 pub(super) struct Decoder(vint64::Decoder);
 
 impl Decoder {
+    /// Ensure we haven't partially consumed a field header
+    pub fn ensure_complete(&self) -> Result<(), Error> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(error::Kind::FieldHeader {
+                tag: None,
+                wire_type: None,
+            }
+            .into())
+        }
+    }
+
+    /// Get the number of bytes needed to finish decoding the header
+    pub fn needed(&self) -> usize {
+        self.0.needed()
+    }
+
     /// Process the given input data, advancing the slice for the amount of
     /// data processed, and returning the new state.
+    ///
+    /// Fields with the same tag as the previous field are rejected unless
+    /// `allow_duplicates` is set.
     pub fn decode<'a>(
         mut self,
         input: &mut &'a [u8],
         last_tag: Option<Tag>,
+        allow_duplicates: bool,
     ) -> Result<(State, Option<Event<'a>>), Error> {
         if let Some(value) = self.0.decode(input)? {
             let header = Header::from(value);
-
-            // Ensure field ordering is monotonically increasing
-            if let Some(tag) = last_tag {
-                if header.tag <= tag {
-                    return Err(error::Kind::Order { tag: header.tag }.into());
-                }
-            }
+            check_order(&header, last_tag, allow_duplicates)?;
 
             let event = Event::FieldHeader(header);
             let new_state = State::transition(&event);
@@ -36,4 +52,47 @@ impl Decoder {
             Ok((State::Header(self), None))
         }
     }
+
+    /// Parse the next field header from the given input without consuming
+    /// it, returning `None` if the input is empty at a field boundary
+    pub fn peek(
+        &self,
+        mut input: &[u8],
+        last_tag: Option<Tag>,
+        allow_duplicates: bool,
+    ) -> Result<Option<Header>, Error> {
+        if input.is_empty() {
+            self.ensure_complete()?;
+            return Ok(None);
+        }
+
+        match self.0.clone().decode(&mut input)? {
+            Some(value) => {
+                let header = Header::from(value);
+                check_order(&header, last_tag, allow_duplicates)?;
+                Ok(Some(header))
+            }
+            None => Err(error::Kind::FieldHeader {
+                tag: None,
+                wire_type: None,
+            }
+            .into()),
+        }
+    }
+}
+
+/// Ensure field ordering is strictly increasing, or merely non-decreasing if
+/// duplicates are allowed
+fn check_order(
+    header: &Header,
+    last_tag: Option<Tag>,
+    allow_duplicates: bool,
+) -> Result<(), Error> {
+    match last_tag {
+        Some(tag) if header.tag == tag && !allow_duplicates => {
+            Err(error::Kind::DuplicateTag { tag }.into())
+        }
+        Some(tag) if header.tag < tag => Err(error::Kind::Order { tag: header.tag }.into()),
+        _ => Ok(()),
+    }
 }
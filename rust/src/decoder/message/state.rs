@@ -27,14 +27,34 @@ impl State {
         self,
         input: &mut &'a [u8],
         last_tag: Option<Tag>,
+        allow_duplicates: bool,
     ) -> Result<(Self, Option<Event<'a>>), Error> {
         match self {
-            State::Header(header) => header.decode(input, last_tag),
+            State::Header(header) => header.decode(input, last_tag, allow_duplicates),
             State::Value(value) => value.decode(input),
             State::Body(body) => body.decode(input),
         }
     }
 
+    /// Ensure the decoder isn't in the middle of decoding a field
+    pub(super) fn ensure_complete(&self) -> Result<(), Error> {
+        match self {
+            State::Header(header) => header.ensure_complete(),
+            State::Value(value) => Err(value.truncation_error()),
+            State::Body(body) => Err(body.truncation_error()),
+        }
+    }
+
+    /// Get the number of bytes needed to finish decoding the current field
+    /// header, value, or body
+    pub(super) fn needed(&self) -> usize {
+        match self {
+            State::Header(header) => header.needed(),
+            State::Value(value) => value.needed(),
+            State::Body(body) => body.needed(),
+        }
+    }
+
     /// Get the new state to transition to based on a given event
     pub(super) fn transition(event: &Event<'_>) -> Self {
         match event {
@@ -2,12 +2,10 @@
 
 use super::state::State;
 use crate::{
-    decoder::{
-        vint64::{self, zigzag},
-        Event,
-    },
-    error::Error,
+    decoder::{raw, vint64, Event},
+    error::{self, Error},
     field::WireType,
+    message::Element,
 };
 
 /// Decoder for field values
@@ -29,33 +27,41 @@ impl Decoder {
         }
     }
 
+    /// Error to return if the message ended before this value was decoded
+    pub fn truncation_error(&self) -> Error {
+        let element = if self.wire_type.is_dynamically_sized() {
+            Element::LengthDelimiter
+        } else {
+            Element::Value
+        };
+
+        error::Kind::Decode {
+            element,
+            wire_type: self.wire_type,
+        }
+        .into()
+    }
+
+    /// Get the number of bytes needed to finish decoding the value (or its
+    /// length prefix)
+    pub fn needed(&self) -> usize {
+        match self.wire_type {
+            WireType::False | WireType::True => 0,
+            _ => self.decoder.needed(),
+        }
+    }
+
     /// Process the given input data, advancing the slice for the amount of
     /// data processed, and returning the new state.
     pub fn decode<'a>(mut self, input: &mut &'a [u8]) -> Result<(State, Option<Event<'a>>), Error> {
-        if let Some(value) = self.decoder.decode(input)? {
-            let event = match self.wire_type {
-                WireType::False => Event::Bool(false),
-                WireType::True => Event::Bool(true),
-                WireType::UInt64 => Event::UInt64(value),
-                WireType::SInt64 => Event::SInt64(zigzag::decode(value)),
-                WireType::Sequence => Event::SequenceHeader {
-                    wire_type: WireType::from_unmasked(value),
-                    length: (value >> 4) as usize,
-                },
-                wire_type => {
-                    debug_assert!(
-                        wire_type.is_dynamically_sized(),
-                        "not a dynamically sized wire type: {:?}",
-                        wire_type
-                    );
-
-                    Event::LengthDelimiter {
-                        wire_type,
-                        length: value as usize,
-                    }
-                }
-            };
+        // Booleans are encoded entirely by their wire type and have no body
+        let maybe_value = match self.wire_type {
+            WireType::False | WireType::True => Some(0),
+            _ => self.decoder.decode(input)?,
+        };
 
+        if let Some(value) = maybe_value {
+            let event = raw::value_event(self.wire_type, value);
             let new_state = State::transition(&event);
             Ok((new_state, Some(event)))
         } else {
@@ -0,0 +1,119 @@
+//! Hooks for observing the events emitted while decoding, e.g. for
+//! instrumentation
+
+use super::Event;
+
+#[cfg(feature = "tracing")]
+use crate::field::{Tag, WireType};
+
+/// Observer of the events emitted by a [`Decoder`][`super::Decoder`],
+/// including those of nested messages and sequences (see
+/// [`Decoder::with_observer`][`super::Decoder::with_observer`]).
+///
+/// Observers can't fail or otherwise change how a message is decoded.
+/// Closures which accept an [`Event`] and offset are observers.
+pub trait Observer: Send {
+    /// Observe an event which was decoded from the input beginning at the
+    /// given offset within the outermost message
+    fn observe(&mut self, event: &Event<'_>, offset: usize);
+}
+
+impl<F> Observer for F
+where
+    F: FnMut(&Event<'_>, usize) + Send,
+{
+    fn observe(&mut self, event: &Event<'_>, offset: usize) {
+        self(event, offset)
+    }
+}
+
+/// [`Observer`] which emits a `TRACE` level [`tracing`] event for each
+/// decoding event.
+///
+/// Each has an `offset` field, along with the `tag` of the field the event
+/// belongs to (if any), its `wire_type`, and the `length` of values, value
+/// chunks, and sequences.
+#[cfg(feature = "tracing")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tracing")))]
+#[derive(Clone, Debug, Default)]
+pub struct TracingObserver {
+    /// Tag of the last field header observed
+    tag: Option<Tag>,
+}
+
+#[cfg(feature = "tracing")]
+impl TracingObserver {
+    /// Create a new [`TracingObserver`]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl Observer for TracingObserver {
+    fn observe(&mut self, event: &Event<'_>, offset: usize) {
+        let kind = event.kind();
+
+        match *event {
+            Event::FieldHeader(header) => {
+                self.tag = Some(header.tag);
+
+                tracing::trace!(
+                    offset,
+                    tag = header.tag.to_u64(),
+                    wire_type = %header.wire_type,
+                    critical = header.critical,
+                    "veriform: {}",
+                    kind
+                );
+            }
+            Event::LengthDelimiter { wire_type, length } => {
+                tracing::trace!(
+                    offset,
+                    tag = self.tag.map(Tag::to_u64),
+                    wire_type = %wire_type,
+                    length,
+                    "veriform: {}",
+                    kind
+                );
+            }
+            Event::SequenceHeader { wire_type, length } => {
+                tracing::trace!(
+                    offset,
+                    tag = self.tag.map(Tag::to_u64),
+                    wire_type = %WireType::Sequence,
+                    element_type = %wire_type,
+                    length,
+                    "veriform: {}",
+                    kind
+                );
+            }
+            Event::ValueChunk {
+                wire_type,
+                bytes,
+                remaining,
+            } => {
+                tracing::trace!(
+                    offset,
+                    tag = self.tag.map(Tag::to_u64),
+                    wire_type = %wire_type,
+                    length = bytes.len(),
+                    remaining,
+                    "veriform: {}",
+                    kind
+                );
+            }
+            Event::Bool(_) | Event::UInt64(_) | Event::SInt64(_) => {
+                if let Some(wire_type) = event.wire_type() {
+                    tracing::trace!(
+                        offset,
+                        tag = self.tag.map(Tag::to_u64),
+                        wire_type = %wire_type,
+                        "veriform: {}",
+                        kind
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Low-level wire format primitives for decoding messages by hand, e.g. in
+//! a custom streaming pipeline.
+//!
+//! These are the same primitives the [`Decoder`][`super::Decoder`] and the
+//! other decoders are built on. Each parses a single element of a message
+//! from the start of a slice, returning it along with the number of bytes
+//! it consumed. If the slice ends before the element does,
+//! [`error::Kind::Incomplete`] reports how many more bytes are needed.
+//!
+//! Unlike the decoders, these primitives don't check that fields are in
+//! order, that values are within any limits, or that the bodies of `string`
+//! values are valid, and don't compute Verihash digests.
+//!
+//! # Example
+//!
+//! Decoding `{ 1: uint64(42), 3: string("baz") }`:
+//!
+//! ```
+//! use veriform::{
+//!     decoder::{raw, Event},
+//!     field::{Tag, WireType},
+//! };
+//!
+//! let message = [37, 85, 107, 7, 98, 97, 122];
+//!
+//! let (header, n) = raw::decode_header(&message).unwrap();
+//! assert_eq!((header.tag, header.wire_type), (Tag::new_const(1), WireType::UInt64));
+//! let rest = &message[n..];
+//!
+//! let (value, n) = raw::decode_value(header.wire_type, rest).unwrap();
+//! assert_eq!(value, Event::UInt64(42));
+//! let rest = &rest[n..];
+//!
+//! let (header, n) = raw::decode_header(rest).unwrap();
+//! assert_eq!((header.tag, header.wire_type), (Tag::new_const(3), WireType::String));
+//! let rest = &rest[n..];
+//!
+//! let (length, n) = raw::decode_length(rest).unwrap();
+//! assert_eq!(&rest[n..n + length], b"baz");
+//! assert_eq!(rest.len(), n + length);
+//! ```
+
+use super::{vint64, Event};
+use crate::{
+    error::{self, Error},
+    field::{Header, WireType},
+    spec, zigzag,
+};
+
+/// Decode a `vint64` from the start of the input, returning its value and
+/// encoded length
+pub fn decode_vint64(mut input: &[u8]) -> Result<(u64, usize), Error> {
+    let len = input.len();
+    let mut decoder = vint64::Decoder::new();
+
+    match decoder.decode(&mut input)? {
+        Some(value) => Ok((value, len - input.len())),
+        None => Err(error::Kind::Incomplete {
+            needed: decoder.needed(),
+        }
+        .into()),
+    }
+}
+
+/// Decode a field header from the start of the input, returning it and its
+/// encoded length
+pub fn decode_header(input: &[u8]) -> Result<(Header, usize), Error> {
+    decode_vint64(input).map(|(value, n)| (Header::from(value), n))
+}
+
+/// Decode the length delimiter of a `bytes`, `string`, or `message` value
+/// from the start of the input, returning the length of the value's body
+/// (which immediately follows it) and the delimiter's encoded length
+pub fn decode_length(input: &[u8]) -> Result<(usize, usize), Error> {
+    decode_vint64(input).map(|(length, n)| (length as usize, n))
+}
+
+/// Decode the value of a field with the given wire type from the start of
+/// the input, returning it as an event along with its encoded length.
+///
+/// - `bool` values are encoded entirely by their wire type, so they consume
+///   no input
+/// - `uint64` and `sint64` values are decoded as [`Event::UInt64`] and
+///   [`Event::SInt64`]
+/// - `bytes`, `string`, and `message` values are decoded as an
+///   [`Event::LengthDelimiter`], which is followed by their body
+/// - `sequence` values are decoded as an [`Event::SequenceHeader`], which is
+///   followed by their body
+pub fn decode_value(wire_type: WireType, input: &[u8]) -> Result<(Event<'static>, usize), Error> {
+    match wire_type {
+        WireType::False | WireType::True => Ok((value_event(wire_type, 0), 0)),
+        _ => decode_vint64(input).map(|(value, n)| (value_event(wire_type, value), n)),
+    }
+}
+
+/// Get the event for the `vint64` value (or length delimiter, or sequence
+/// header) of a field with the given wire type
+pub(crate) fn value_event(wire_type: WireType, value: u64) -> Event<'static> {
+    match wire_type {
+        WireType::False => Event::Bool(false),
+        WireType::True => Event::Bool(true),
+        WireType::UInt64 => Event::UInt64(value),
+        WireType::SInt64 => Event::SInt64(zigzag::decode(value)),
+        WireType::Sequence => {
+            let (length, wire_type) = spec::unpack_sequence_header(value);
+
+            Event::SequenceHeader {
+                wire_type: WireType::from_unmasked(wire_type.into()),
+                length: length as usize,
+            }
+        }
+        wire_type => {
+            debug_assert!(
+                wire_type.is_dynamically_sized(),
+                "not a dynamically sized wire type: {:?}",
+                wire_type
+            );
+
+            Event::LengthDelimiter {
+                wire_type,
+                length: value as usize,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_header, decode_length, decode_value, decode_vint64};
+    use crate::{
+        decoder::Event,
+        error,
+        field::{Header, Tag, WireType},
+    };
+
+    #[test]
+    fn incomplete() {
+        assert_eq!(
+            decode_vint64(&[]).err().unwrap().kind(),
+            error::Kind::Incomplete { needed: 1 }
+        );
+
+        // First byte of a 3-byte `vint64`
+        assert_eq!(
+            decode_length(&[4]).err().unwrap().kind(),
+            error::Kind::Incomplete { needed: 2 }
+        );
+
+        assert_eq!(
+            decode_value(WireType::UInt64, &[]).err().unwrap().kind(),
+            error::Kind::Incomplete { needed: 1 }
+        );
+    }
+
+    #[test]
+    fn non_canonical() {
+        assert_eq!(
+            decode_header(&[74, 0]).err().unwrap().kind(),
+            error::Kind::NonCanonicalVInt64
+        );
+    }
+
+    #[test]
+    fn header() {
+        let header = Header::new(Tag::new_const(1000), true, WireType::Sequence);
+        let encoded = header.encode();
+        let len = encoded.as_ref().len();
+
+        // Followed by another byte, which isn't consumed
+        let mut input = [0xFF; 10];
+        input[..len].copy_from_slice(encoded.as_ref());
+
+        assert_eq!(decode_header(&input).unwrap(), (header, len));
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(
+            decode_value(WireType::True, &[]).unwrap(),
+            (Event::Bool(true), 0)
+        );
+        assert_eq!(
+            decode_value(WireType::SInt64, &[167]).unwrap(),
+            (Event::SInt64(-42), 1)
+        );
+        assert_eq!(
+            decode_value(WireType::Sequence, &[69, 85]).unwrap(),
+            (
+                Event::SequenceHeader {
+                    wire_type: WireType::UInt64,
+                    length: 2
+                },
+                1
+            )
+        );
+        assert_eq!(
+            decode_value(WireType::Bytes, &[11]).unwrap(),
+            (
+                Event::LengthDelimiter {
+                    wire_type: WireType::Bytes,
+                    length: 5
+                },
+                1
+            )
+        );
+    }
+}
@@ -0,0 +1,434 @@
+//! Streaming decoder which pulls messages from an [`io::Read`]
+
+// `displaydoc` emits its `Display` impls inside of an anonymous `const`
+#![allow(unknown_lints, non_local_definitions)]
+
+use super::{event::Pending, message, Decodable, Event, Limits};
+use crate::error::Error;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug};
+use digest::Digest;
+use displaydoc::Display;
+use std::io;
+
+/// Default capacity of a [`Reader`]'s refill buffer
+pub const DEFAULT_CAPACITY: usize = 8192;
+
+/// Streaming decoder which reads a message from an [`io::Read`] on demand,
+/// emitting the same events as the slice-based message decoder.
+///
+/// Bytes are read into a fixed-capacity refill buffer, so `bytes`, `string`,
+/// `message`, and `sequence` values are emitted as [`Event::ValueChunk`]s no
+/// larger than the buffer's capacity, regardless of the size of the value.
+/// Field headers and `vint64`s split across calls to [`io::Read::read`] are
+/// reassembled by the decoder.
+pub struct Reader<D: Digest, R: io::Read> {
+    /// Inner reader
+    inner: R,
+
+    /// Buffered input and the decoder consuming it
+    buffered: Buffered<D>,
+}
+
+impl<D, R> Reader<D, R>
+where
+    D: Digest,
+    R: io::Read,
+{
+    /// Create a new [`Reader`] with the default buffer capacity
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(inner, DEFAULT_CAPACITY)
+    }
+
+    /// Create a new [`Reader`] whose refill buffer has the given capacity,
+    /// which is also the maximum size of the value chunks it emits.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_capacity(inner: R, capacity: usize) -> Self {
+        Self {
+            inner,
+            buffered: Buffered::new(capacity),
+        }
+    }
+
+    /// Set the limits on the lengths of the message's fields, which are
+    /// enforced as soon as their length delimiters are read
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.buffered.set_limits(limits);
+        self
+    }
+
+    /// Borrow the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwrap the inner reader, discarding any buffered data
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read the next event, returning `Ok(None)` once the reader reaches EOF
+    /// at a field boundary.
+    ///
+    /// EOF in the middle of a field is reported as a truncation error, and
+    /// errors from the inner reader are reported as [`ReadError::Io`].
+    /// [`io::ErrorKind::Interrupted`] errors are retried.
+    pub fn next_event(&mut self) -> Result<Option<Event<'_>>, ReadError> {
+        let (start, pending) = loop {
+            if let Some(decoded) = self.buffered.decode()? {
+                break decoded;
+            }
+
+            if !self.fill()? {
+                self.buffered.ensure_complete()?;
+                return Ok(None);
+            }
+        };
+
+        Ok(Some(self.buffered.event(start, pending)))
+    }
+
+    /// Refill the buffer from the inner reader, returning `false` on EOF
+    fn fill(&mut self) -> Result<bool, ReadError> {
+        loop {
+            match self.inner.read(self.buffered.spare()) {
+                Ok(n) => return Ok(self.buffered.filled(n)),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(ReadError::Io(err)),
+            }
+        }
+    }
+}
+
+impl<D, R> Debug for Reader<D, R>
+where
+    D: Digest,
+    R: io::Read + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("decoder::Reader")
+            .field("inner", &self.inner)
+            .field("buffered", &self.buffered.len())
+            .finish()
+    }
+}
+
+/// Errors which occur reading a message from an [`io::Read`]
+#[derive(Debug, Display)]
+pub enum ReadError {
+    /// I/O error
+    Io(io::Error),
+
+    /// decode error
+    Decode(Error),
+}
+
+impl From<io::Error> for ReadError {
+    fn from(err: io::Error) -> ReadError {
+        ReadError::Io(err)
+    }
+}
+
+impl From<Error> for ReadError {
+    fn from(err: Error) -> ReadError {
+        ReadError::Decode(err)
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(err) => Some(err),
+            ReadError::Decode(err) => Some(err),
+        }
+    }
+}
+
+/// Refill buffer along with the decoder consuming it, shared between the
+/// blocking and async readers
+pub(super) struct Buffered<D: Digest> {
+    /// Decoder for the message being read
+    decoder: message::Decoder<D>,
+
+    /// Refill buffer
+    buffer: Vec<u8>,
+
+    /// Position of the next unconsumed byte in the buffer
+    pos: usize,
+
+    /// End of the data which has been read into the buffer
+    end: usize,
+}
+
+impl<D> Buffered<D>
+where
+    D: Digest,
+{
+    /// Create a new buffer with the given capacity.
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "buffer capacity must be non-zero");
+
+        Self {
+            decoder: message::Decoder::without_hasher(),
+            buffer: vec![0; capacity],
+            pos: 0,
+            end: 0,
+        }
+    }
+
+    /// Set the limits on the lengths of values in the message being read
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.decoder.set_limits(limits);
+    }
+
+    /// Number of bytes which have been read but not yet decoded
+    pub fn len(&self) -> usize {
+        self.end - self.pos
+    }
+
+    /// Decode the buffered input, returning the position at which the next
+    /// event began along with the event, or `None` if more input is needed
+    pub fn decode(&mut self) -> Result<Option<(usize, Pending)>, Error> {
+        loop {
+            // Booleans are emitted without consuming input, so decode any
+            // buffered input (even if empty) before requesting more
+            let start = self.pos;
+            let mut input = &self.buffer[self.pos..self.end];
+            let event = self.decoder.decode(&mut input)?.map(Pending::from);
+            self.pos = self.end.checked_sub(input.len()).unwrap();
+
+            if let Some(event) = event {
+                return Ok(Some((start, event)));
+            }
+
+            if self.pos == self.end {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Get a decoded event, borrowing value chunks from the buffer
+    pub fn event(&self, start: usize, pending: Pending) -> Event<'_> {
+        match pending {
+            Pending::Event(event) => event,
+            // Value chunks consist of exactly the bytes consumed decoding them
+            Pending::ValueChunk {
+                wire_type,
+                remaining,
+            } => Event::ValueChunk {
+                wire_type,
+                bytes: &self.buffer[start..self.pos],
+                remaining,
+            },
+        }
+    }
+
+    /// Get the buffer to read more input into. Must only be called once all
+    /// buffered input has been decoded.
+    pub fn spare(&mut self) -> &mut [u8] {
+        debug_assert_eq!(self.pos, self.end);
+        &mut self.buffer
+    }
+
+    /// Record that `n` bytes were read into the buffer, returning `false` on
+    /// EOF (i.e. if `n` is zero)
+    pub fn filled(&mut self, n: usize) -> bool {
+        self.pos = 0;
+        self.end = n;
+        n > 0
+    }
+
+    /// Ensure the input ended at a field boundary
+    pub fn ensure_complete(&self) -> Result<(), Error> {
+        self.decoder.ensure_complete()
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{ReadError, Reader};
+    use crate::{
+        decoder::{event::Coalesced, message, Decodable, Event, EventBuf, Limits},
+        error,
+        field::{Tag, WireType},
+    };
+    use sha2::Sha256;
+    use std::{io, vec::Vec};
+
+    /// `{ 1: uint64(42), 2: string("baz"), 3: bytes([0; 300]), 4: true }`
+    fn example_message() -> Vec<u8> {
+        let mut message = vec![37, 85, 75, 7, 98, 97, 122, 105, 178, 4];
+        message.extend_from_slice(&[0; 300]);
+        message.push(131);
+        message
+    }
+
+    /// Reader which returns at most `max` bytes per call, and fails every
+    /// other call with `Interrupted`
+    struct TrickleReader<'a> {
+        bytes: &'a [u8],
+        max: usize,
+        interrupt: bool,
+    }
+
+    impl<'a> TrickleReader<'a> {
+        fn new(bytes: &'a [u8], max: usize) -> Self {
+            Self {
+                bytes,
+                max,
+                interrupt: false,
+            }
+        }
+    }
+
+    impl io::Read for TrickleReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+
+            let n = self.bytes.len().min(buf.len()).min(self.max);
+            buf[..n].copy_from_slice(&self.bytes[..n]);
+            self.bytes = &self.bytes[n..];
+            Ok(n)
+        }
+    }
+
+    /// Reader which always fails
+    struct BrokenReader;
+
+    impl io::Read for BrokenReader {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::ConnectionReset.into())
+        }
+    }
+
+    /// Read all events from a reader
+    fn read_all<R: io::Read>(reader: &mut Reader<Sha256, R>) -> Result<Vec<EventBuf>, ReadError> {
+        let mut events = Coalesced::default();
+
+        while let Some(event) = reader.next_event()? {
+            events.push(event);
+        }
+
+        Ok(events.0)
+    }
+
+    /// Decode all events from a slice with the slice-based decoder
+    fn decode_all(mut input: &[u8]) -> Vec<EventBuf> {
+        let mut decoder = message::Decoder::<Sha256>::without_hasher();
+        let mut events = Coalesced::default();
+
+        loop {
+            match decoder.decode(&mut input).unwrap() {
+                Some(event) => events.push(event),
+                None if input.is_empty() => break,
+                None => continue,
+            }
+        }
+
+        events.0
+    }
+
+    #[test]
+    fn matches_slice_decoder() {
+        let message = example_message();
+        let expected = decode_all(&message);
+        assert_eq!(expected.len(), 10);
+
+        for &(max, capacity) in &[(1, 1), (1, 4096), (7, 3), (4096, 16), (4096, 4096)] {
+            let inner = TrickleReader::new(&message, max);
+            let mut reader = Reader::<Sha256, _>::with_capacity(inner, capacity);
+            assert_eq!(read_all(&mut reader).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn bounded_chunks() {
+        let message = example_message();
+        let mut reader = Reader::<Sha256, _>::with_capacity(message.as_slice(), 16);
+
+        while let Some(event) = reader.next_event().unwrap() {
+            if let Event::ValueChunk { bytes, .. } = event {
+                assert!(bytes.len() <= 16);
+            }
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        let message = example_message();
+
+        for len in 0..message.len() {
+            let result = read_all(&mut Reader::<Sha256, _>::new(&message[..len]));
+
+            // Lengths which end on a field boundary are complete messages
+            if [0, 2, 7, 310].contains(&len) {
+                assert!(result.is_ok(), "length {} rejected", len);
+            } else {
+                match result {
+                    Err(ReadError::Decode(_)) => (),
+                    other => panic!("length {}: unexpected result: {:?}", len, other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn io_error() {
+        let mut reader = Reader::<Sha256, _>::new(BrokenReader);
+
+        match reader.next_event() {
+            Err(ReadError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::ConnectionReset),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn length_limit() {
+        let limits = Limits {
+            max_field_len: 299,
+            ..Limits::default()
+        };
+
+        // The body of field 3 is never read, as its length exceeds the limit
+        let message = example_message();
+        let input = &message[..10];
+
+        match read_all(&mut Reader::<Sha256, _>::new(input).with_limits(limits)) {
+            Err(ReadError::Decode(err)) => assert_eq!(
+                err.kind(),
+                error::Kind::TooLong {
+                    tag: Tag::new_const(3),
+                    wire_type: WireType::Bytes,
+                    length: 300,
+                    max: 299
+                }
+            ),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_message() {
+        // Duplicate field: repeated tags are rejected by the decoder
+        let input: &[u8] = &[37, 85, 37, 85];
+
+        match read_all(&mut Reader::<Sha256, _>::new(input)) {
+            Err(ReadError::Decode(err)) => {
+                assert_eq!(
+                    err.kind(),
+                    error::Kind::DuplicateTag {
+                        tag: Tag::new_const(1)
+                    }
+                )
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
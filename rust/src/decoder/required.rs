@@ -0,0 +1,198 @@
+//! Tracking of required fields in messages being decoded
+
+use crate::{
+    error::{self, Error},
+    field::Tag,
+};
+use core::fmt::{self, Display};
+
+/// Maximum number of required fields a [`RequiredFields`] tracker supports
+pub const MAX_REQUIRED_FIELDS: usize = 64;
+
+/// Field which must be present in a message
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RequiredField {
+    /// Tag which identifies the field
+    pub tag: Tag,
+
+    /// Name of the field in the schema (e.g. the name of a struct member),
+    /// if available
+    pub name: Option<&'static str>,
+}
+
+impl Display for RequiredField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.tag)?;
+
+        if let Some(name) = self.name {
+            write!(f, " (`{}`)", name)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Tracker which records which of a message's required fields were seen
+/// while decoding it, so all of the missing ones can be reported together
+/// once the end of the message is reached.
+///
+/// Supports up to [`MAX_REQUIRED_FIELDS`] required fields.
+#[derive(Copy, Clone, Debug)]
+pub struct RequiredFields {
+    /// Fields which are required
+    fields: &'static [RequiredField],
+
+    /// Bitmask of the indexes of the fields which were seen
+    seen: u64,
+}
+
+impl RequiredFields {
+    /// Create a tracker for the given required fields.
+    ///
+    /// Panics if there are more than [`MAX_REQUIRED_FIELDS`].
+    pub fn new(fields: &'static [RequiredField]) -> Self {
+        assert!(
+            fields.len() <= MAX_REQUIRED_FIELDS,
+            "too many required fields"
+        );
+
+        Self { fields, seen: 0 }
+    }
+
+    /// Record that the field with the given tag was seen. Tags which aren't
+    /// required are ignored.
+    pub fn seen(&mut self, tag: Tag) {
+        if let Some(index) = self.fields.iter().position(|field| field.tag == tag) {
+            self.seen |= 1 << index;
+        }
+    }
+
+    /// Get the required fields which haven't been seen
+    pub fn missing(&self) -> MissingFields {
+        MissingFields {
+            fields: self.fields,
+            missing: !self.seen & mask(self.fields.len()),
+        }
+    }
+
+    /// Check that every required field was seen, returning
+    /// [`error::Kind::MissingFields`] listing all of the missing ones if not
+    pub fn check(&self) -> Result<(), Error> {
+        let missing = self.missing();
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(error::Kind::MissingFields { fields: missing }.into())
+        }
+    }
+}
+
+/// Required fields which were missing from a message
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MissingFields {
+    /// Fields which are required
+    fields: &'static [RequiredField],
+
+    /// Bitmask of the indexes of the fields which are missing
+    missing: u64,
+}
+
+impl MissingFields {
+    /// Iterate over the missing fields, in the order they were required
+    pub fn iter(&self) -> impl Iterator<Item = &'static RequiredField> {
+        let missing = self.missing;
+
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(move |(index, _)| missing & (1 << index) != 0)
+            .map(|(_, field)| field)
+    }
+
+    /// Get the number of missing fields
+    pub fn len(&self) -> usize {
+        self.missing.count_ones() as usize
+    }
+
+    /// Are there no missing fields?
+    pub fn is_empty(&self) -> bool {
+        self.missing == 0
+    }
+}
+
+impl Display for MissingFields {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, field) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+
+            write!(f, "{}", field)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Get a bitmask with the given number of low bits set
+fn mask(len: usize) -> u64 {
+    if len == MAX_REQUIRED_FIELDS {
+        u64::MAX
+    } else {
+        (1 << len) - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RequiredField, RequiredFields};
+    use crate::{error, field::Tag};
+
+    const FIELDS: &[RequiredField] = &[
+        RequiredField {
+            tag: Tag::new_const(0),
+            name: Some("foo"),
+        },
+        RequiredField {
+            tag: Tag::new_const(2),
+            name: None,
+        },
+        RequiredField {
+            tag: Tag::new_const(5),
+            name: Some("baz"),
+        },
+    ];
+
+    #[test]
+    fn all_seen() {
+        let mut required = RequiredFields::new(FIELDS);
+
+        for &tag in &[0, 1, 2, 5] {
+            required.seen(Tag::new_const(tag));
+        }
+
+        assert!(required.missing().is_empty());
+        assert!(required.check().is_ok());
+    }
+
+    #[test]
+    fn missing_fields() {
+        let mut required = RequiredFields::new(FIELDS);
+        required.seen(Tag::new_const(2));
+
+        let missing = required.missing();
+        assert_eq!(missing.len(), 2);
+        assert!(missing
+            .iter()
+            .map(|field| field.tag)
+            .eq([Tag::new_const(0), Tag::new_const(5)].iter().cloned()));
+
+        let err = required.check().err().unwrap();
+        assert_eq!(err.kind(), error::Kind::MissingFields { fields: missing });
+
+        let mut message = heapless::String::<heapless::consts::U64>::new();
+        core::fmt::write(&mut message, format_args!("{}", err)).unwrap();
+        assert_eq!(message, "missing required fields: 0 (`foo`), 5 (`baz`)");
+    }
+}
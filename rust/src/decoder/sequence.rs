@@ -3,8 +3,9 @@
 mod decoder;
 mod hasher;
 mod iter;
+mod nested;
 mod state;
 
-pub use self::iter::Iter;
+pub use self::{iter::Iter, nested::NestedIter};
 
 pub(crate) use self::decoder::Decoder;
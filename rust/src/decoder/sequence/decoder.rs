@@ -2,14 +2,19 @@
 
 use super::{hasher::Hasher, state::State};
 use crate::{
-    decoder::{vint64, Decodable, Event},
+    decoder::{fuel::Fuel, vint64, Decodable, Event, Limits},
     error::{self, Error},
-    field::WireType,
+    field::{Tag, WireType},
     message::Element,
     verihash::DigestOutput,
 };
 use digest::Digest;
 
+#[cfg(feature = "alloc")]
+use crate::decoder::Observer;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
 /// Sequence decoder
 pub(crate) struct Decoder<D: Digest> {
     /// Wire type contained in this sequence
@@ -26,6 +31,27 @@ pub(crate) struct Decoder<D: Digest> {
 
     /// Verihash message hasher
     hasher: Option<Hasher<D>>,
+
+    /// Tag of the field containing this sequence
+    tag: Tag,
+
+    /// Limits on the lengths of values in this sequence
+    limits: Limits,
+
+    /// Number of elements decoded so far
+    elements: usize,
+
+    /// Offset of the sequence body within the outermost message
+    offset: usize,
+
+    /// Fuel consumed by each event, if this is the innermost decoder and a
+    /// budget was set
+    fuel: Option<Fuel>,
+
+    /// Observer of each event, if this is the innermost decoder and an
+    /// observer was set
+    #[cfg(feature = "alloc")]
+    observer: Option<Box<dyn Observer>>,
 }
 
 impl<D> Decoder<D>
@@ -40,15 +66,74 @@ where
             remaining: length,
             state: State::default(),
             hasher: Some(Hasher::new(wire_type)), // TODO(tarcieri): support for disabling hasher
+            tag: Tag::default(),
+            limits: Limits::default(),
+            elements: 0,
+            offset: 0,
+            fuel: None,
+            #[cfg(feature = "alloc")]
+            observer: None,
         }
     }
 
+    /// Apply the given limits to the elements of this sequence, reporting
+    /// the given tag of the field containing it when they're exceeded
+    pub fn with_limits(mut self, tag: Tag, limits: Limits) -> Self {
+        self.tag = tag;
+        self.limits = limits;
+        self
+    }
+
+    /// Set the offset of the sequence body within the outermost message
+    pub fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Set the fuel consumed by each event decoded from this sequence
+    pub fn set_fuel(&mut self, fuel: Option<Fuel>) {
+        self.fuel = fuel;
+    }
+
+    /// Take the fuel from this decoder, e.g. to hand it to a nested decoder
+    pub fn take_fuel(&mut self) -> Option<Fuel> {
+        self.fuel.take()
+    }
+
+    /// Get the fuel held by this decoder, if any
+    pub fn fuel(&self) -> Option<Fuel> {
+        self.fuel
+    }
+
+    /// Set the observer of each event decoded from this sequence
+    #[cfg(feature = "alloc")]
+    pub fn set_observer(&mut self, observer: Option<Box<dyn Observer>>) {
+        self.observer = observer;
+    }
+
+    /// Take the observer from this decoder, e.g. to hand it to a nested
+    /// decoder
+    #[cfg(feature = "alloc")]
+    pub fn take_observer(&mut self) -> Option<Box<dyn Observer>> {
+        self.observer.take()
+    }
+
+    /// Get the current offset within the outermost message
+    pub fn offset(&self) -> usize {
+        self.offset.checked_add(self.position()).unwrap()
+    }
+
     /// Get the current position (i.e. number of bytes processed) in the
     /// sequence being decoded
     pub fn position(&self) -> usize {
         self.length.checked_sub(self.remaining).unwrap()
     }
 
+    /// Get the number of elements decoded so far
+    pub fn elements(&self) -> usize {
+        self.elements
+    }
+
     /// Get the number of bytes remaining in the sequence
     pub fn remaining(&self) -> usize {
         self.remaining
@@ -57,11 +142,18 @@ where
     /// Perform a state transition after receiving an event
     fn transition<'a>(&mut self, event: &Event<'a>) {
         self.state = match &event {
-            Event::LengthDelimiter { wire_type, length }
-            | Event::SequenceHeader { wire_type, length } => State::Body {
+            // Empty values have no body
+            Event::LengthDelimiter { length: 0, .. } => State::default(),
+            Event::LengthDelimiter { wire_type, length } => State::Body {
                 wire_type: *wire_type,
                 remaining: *length,
             },
+            // Empty nested sequences have no body
+            Event::SequenceHeader { length: 0, .. } => State::default(),
+            Event::SequenceHeader { length, .. } => State::Body {
+                wire_type: WireType::Sequence,
+                remaining: *length,
+            },
             Event::UInt64(_) | Event::SInt64(_) => State::Value(vint64::Decoder::new()),
             Event::ValueChunk {
                 wire_type,
@@ -90,8 +182,23 @@ where
         Ok(())
     }
 
+    /// Hash a digest of a nested sequence within this sequence
+    pub fn hash_sequence_digest(&mut self, digest: &DigestOutput<D>) -> Result<(), Error> {
+        if let Some(hasher) = &mut self.hasher {
+            hasher.hash_sequence_digest(digest)?;
+        }
+
+        Ok(())
+    }
+
     /// Compute a Verihash digest of the sequence we're decoding.
+    ///
+    /// Returns an error if the sequence hasn't been completely consumed.
     pub fn compute_digest(self) -> Result<Option<DigestOutput<D>>, Error> {
+        if self.remaining != 0 {
+            return Err(error::HashingReason::Incomplete.into());
+        }
+
         self.hasher.map(|hasher| hasher.finish()).transpose()
     }
 }
@@ -101,9 +208,29 @@ where
     D: Digest,
 {
     fn decode<'a>(&mut self, input: &mut &'a [u8]) -> Result<Option<Event<'a>>, Error> {
+        #[cfg(feature = "alloc")]
+        let offset = self.offset();
         let orig_input_len = input.len();
         let maybe_event = self.state.decode(self.wire_type, input)?;
         let consumed = orig_input_len.checked_sub(input.len()).unwrap();
+
+        // Check limits before consuming the input, so the sequence can't be
+        // hashed as though it were complete if they're exceeded
+        if let Some(event) = &maybe_event {
+            if let Some(fuel) = &mut self.fuel {
+                fuel.consume()?;
+            }
+
+            // Each element begins with either its value or length delimiter
+            if let State::Value(_) = self.state {
+                self.elements = self.elements.checked_add(1).unwrap();
+                self.limits
+                    .check_sequence_elements(self.tag, self.elements)?;
+            }
+
+            self.limits.check_event(self.tag, event)?;
+        }
+
         self.remaining = self.remaining.checked_sub(consumed).unwrap();
 
         if let Some(event) = &maybe_event {
@@ -111,24 +238,27 @@ where
                 hasher.hash_event(event)?;
             }
 
-            self.transition(&event);
+            #[cfg(feature = "alloc")]
+            if let Some(observer) = &mut self.observer {
+                observer.observe(event, offset);
+            }
+
+            self.transition(event);
         }
 
         Ok(maybe_event)
     }
 
+    fn field_tag(&self) -> Tag {
+        self.tag
+    }
+
     fn decode_dynamically_sized_value<'a>(
         &mut self,
         expected_type: WireType,
         input: &mut &'a [u8],
     ) -> Result<&'a [u8], Error> {
-        if expected_type != self.wire_type {
-            return Err(error::Kind::UnexpectedWireType {
-                actual: self.wire_type,
-                wanted: expected_type,
-            }
-            .into());
-        }
+        self.wire_type.check(self.tag, expected_type)?;
 
         debug_assert!(
             self.wire_type.is_dynamically_sized(),
@@ -138,13 +268,17 @@ where
 
         let length = match self.decode(input)? {
             Some(Event::LengthDelimiter { length, .. }) => Ok(length),
-            _ => Err(error::Kind::Decode {
+            _ => Err(Error::from(error::Kind::Decode {
                 element: Element::LengthDelimiter,
                 wire_type: self.wire_type,
-            }
-            .position(self.length.checked_sub(self.remaining).unwrap())),
+            })),
         }?;
 
+        // Empty values have no body
+        if length == 0 {
+            return Ok(&[]);
+        }
+
         match self.decode(input)? {
             Some(Event::ValueChunk {
                 bytes, remaining, ..
@@ -169,7 +303,7 @@ where
     }
 }
 
-#[cfg(all(test, features = "sha2"))]
+#[cfg(all(test, feature = "sha2"))]
 mod tests {
     use super::{Decodable, Decoder, WireType};
     use sha2::Sha256;
@@ -224,4 +358,19 @@ mod tests {
 
         assert!(input_ref.is_empty());
     }
+
+    #[test]
+    fn decode_empty_elements() {
+        // Empty values have no body, including at the end of the sequence
+        let input = [1, 7, 102, 111, 111, 1];
+        let mut input_ref = &input[..];
+        let mut decoder: Decoder<Sha256> = Decoder::new(WireType::Bytes, input.len());
+
+        for &b in &[&b""[..], b"foo", b""] {
+            assert_eq!(b, decoder.decode_bytes(&mut input_ref).unwrap());
+        }
+
+        assert!(input_ref.is_empty());
+        assert!(decoder.compute_digest().unwrap().is_some());
+    }
 }
@@ -2,8 +2,12 @@
 //!
 //! WARNING: this is an experimental PoC-quality implementation!
 //! It is NOT suitable for production use!
+//!
+//! Sequence transcripts begin with a sequence prefix, the transcript version,
+//! and the wire type of the sequence's elements, followed by each element,
+//! and end with the total number of elements (hashed as a `uint64`). The resulting digest is absorbed into the
+//! enclosing message's transcript along with the sequence's tag.
 
-// TODO(tarcieri): tests and test vectors!!!
 // TODO(tarcieri): DRY out repeated message/sequence code into `verihash::Hasher`
 
 use crate::{
@@ -25,6 +29,9 @@ pub(super) struct Hasher<D: Digest> {
 
     /// Current state of the decoder (or `None` if an error occurred)
     state: Option<State>,
+
+    /// Number of elements hashed so far
+    count: u64,
 }
 
 impl<D> Hasher<D>
@@ -33,14 +40,11 @@ where
 {
     /// Create a new [`Hasher`]
     pub fn new(wire_type: WireType) -> Self {
-        let mut verihash = verihash::Hasher::new();
-
-        // Domain separate sequence hashes by their contained wire type
-        verihash.update(&[wire_type.to_u8()]);
-
         Self {
-            verihash,
+            // Domain separate sequence hashes by their contained wire type
+            verihash: verihash::Hasher::new_sequence(wire_type),
             state: Some(State::default()),
+            count: 0,
         }
     }
 
@@ -48,6 +52,12 @@ where
     pub fn hash_event(&mut self, event: &Event<'_>) -> Result<(), Error> {
         if let Some(state) = self.state.take() {
             let new_state = state.transition(event, &mut self.verihash)?;
+
+            // Messages and sequences are counted once their digest is absorbed
+            if new_state == State::Initial {
+                self.count = self.count.checked_add(1).unwrap();
+            }
+
             self.state = Some(new_state);
             Ok(())
         } else {
@@ -58,21 +68,38 @@ where
     /// Hash a digest of a nested message within this message
     pub fn hash_message_digest(&mut self, digest: &DigestOutput<D>) -> Result<(), Error> {
         match self.state {
-            Some(State::Message { remaining }) if remaining == 0 => {
+            Some(State::Message { remaining: 0 }) => {
                 self.verihash.update(digest);
                 self.state = Some(State::Initial);
+                self.count = self.count.checked_add(1).unwrap();
                 Ok(())
             }
-            _ => Err(error::Kind::Hashing.into()),
+            _ => Err(error::HashingReason::UnexpectedEvent.into()),
         }
     }
 
-    /// Finish computing digest
-    pub fn finish(self) -> Result<DigestOutput<D>, Error> {
-        if self.state == Some(State::Initial) {
-            Ok(self.verihash.finalize())
-        } else {
-            Err(error::Kind::Hashing.into())
+    /// Hash a digest of a nested sequence within this sequence
+    pub fn hash_sequence_digest(&mut self, digest: &DigestOutput<D>) -> Result<(), Error> {
+        match self.state {
+            Some(State::Sequence { remaining: 0 }) => {
+                self.verihash.update(digest);
+                self.state = Some(State::Initial);
+                self.count = self.count.checked_add(1).unwrap();
+                Ok(())
+            }
+            _ => Err(error::HashingReason::UnexpectedEvent.into()),
+        }
+    }
+
+    /// Finish computing digest, absorbing the total number of elements
+    pub fn finish(mut self) -> Result<DigestOutput<D>, Error> {
+        match self.state {
+            Some(State::Initial) => {
+                self.verihash.uint64(self.count);
+                Ok(self.verihash.finalize())
+            }
+            Some(_) => Err(error::HashingReason::Incomplete.into()),
+            None => Err(error::Kind::Failed.into()),
         }
     }
 }
@@ -99,8 +126,12 @@ enum State {
 
     /// Hashing a message value
     Message { remaining: usize },
+
+    /// Hashing a nested sequence value
+    Sequence { remaining: usize },
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for State {
     fn default() -> Self {
         State::Initial
@@ -118,13 +149,16 @@ impl State {
             Event::LengthDelimiter { wire_type, length } => {
                 self.handle_length_delimiter(*wire_type, *length, verihash)
             }
+            Event::SequenceHeader { length, .. } => {
+                self.handle_length_delimiter(WireType::Sequence, *length, verihash)
+            }
             Event::UInt64(_) | Event::SInt64(_) => self.handle_fixed_sized_value(event, verihash),
             Event::ValueChunk {
                 wire_type,
                 bytes,
                 remaining,
             } => self.handle_value_chunk(*wire_type, bytes, *remaining, verihash),
-            _ => Err(error::Kind::Hashing.into()),
+            _ => Err(error::HashingReason::UnexpectedEvent.into()),
         }
     }
 
@@ -136,14 +170,17 @@ impl State {
         verihash: &mut verihash::Hasher<D>,
     ) -> Result<Self, Error> {
         if self != State::Initial {
-            return Err(error::Kind::Hashing.into());
+            return Err(error::HashingReason::UnexpectedEvent.into());
         }
 
+        // Zero-length bytes and strings have no value chunks
         let new_state = match wire_type {
+            WireType::Bytes | WireType::String if length == 0 => State::Initial,
             WireType::Bytes => State::Bytes { remaining: length },
             WireType::String => State::String { remaining: length },
             WireType::Message => State::Message { remaining: length },
-            _ => unreachable!(),
+            WireType::Sequence => State::Sequence { remaining: length },
+            _ => return Err(error::HashingReason::WireTypeMismatch.into()),
         };
 
         verihash.dynamically_sized_value(wire_type, length);
@@ -157,17 +194,13 @@ impl State {
         verihash: &mut verihash::Hasher<D>,
     ) -> Result<Self, Error> {
         if self != State::Initial {
-            return Err(error::Kind::Hashing.into());
+            return Err(error::HashingReason::UnexpectedEvent.into());
         }
 
         match value {
-            Event::UInt64(value) => {
-                verihash.fixed_size_value(WireType::UInt64, &value.to_le_bytes())
-            }
-            Event::SInt64(value) => {
-                verihash.fixed_size_value(WireType::SInt64, &value.to_le_bytes())
-            }
-            _ => unreachable!(),
+            Event::UInt64(value) => verihash.uint64(*value),
+            Event::SInt64(value) => verihash.sint64(*value),
+            _ => return Err(error::HashingReason::UnexpectedEvent.into()),
         }
         Ok(State::Initial)
     }
@@ -183,8 +216,12 @@ impl State {
         // TODO(tarcieri): DRY this out (especially with the message decoder)
         let new_state = match self {
             State::Bytes { remaining } => {
-                if wire_type != WireType::Bytes || remaining - bytes.len() != new_remaining {
-                    return Err(error::Kind::Hashing.into());
+                if wire_type != WireType::Bytes {
+                    return Err(error::HashingReason::WireTypeMismatch.into());
+                }
+
+                if remaining - bytes.len() != new_remaining {
+                    return Err(error::HashingReason::LengthMismatch.into());
                 }
 
                 if new_remaining == 0 {
@@ -196,8 +233,12 @@ impl State {
                 }
             }
             State::String { remaining } => {
-                if wire_type != WireType::String || remaining - bytes.len() != new_remaining {
-                    return Err(error::Kind::Hashing.into());
+                if wire_type != WireType::String {
+                    return Err(error::HashingReason::WireTypeMismatch.into());
+                }
+
+                if remaining - bytes.len() != new_remaining {
+                    return Err(error::HashingReason::LengthMismatch.into());
                 }
 
                 if new_remaining == 0 {
@@ -214,15 +255,34 @@ impl State {
                 // then invoking the `hash_message_digest` method above.
                 //
                 // This code just handles length bookkeeping.
-                if wire_type != WireType::Message || remaining - bytes.len() != new_remaining {
-                    return Err(error::Kind::Hashing.into());
+                if wire_type != WireType::Message {
+                    return Err(error::HashingReason::WireTypeMismatch.into());
+                }
+
+                if remaining - bytes.len() != new_remaining {
+                    return Err(error::HashingReason::LengthMismatch.into());
                 }
 
                 return Ok(State::Message {
                     remaining: new_remaining,
                 });
             }
-            _ => return Err(error::Kind::Hashing.into()),
+            State::Sequence { remaining } => {
+                // As with messages, nested sequences are hashed by
+                // `hash_sequence_digest` once fully decoded
+                if wire_type != WireType::Sequence {
+                    return Err(error::HashingReason::WireTypeMismatch.into());
+                }
+
+                if remaining - bytes.len() != new_remaining {
+                    return Err(error::HashingReason::LengthMismatch.into());
+                }
+
+                return Ok(State::Sequence {
+                    remaining: new_remaining,
+                });
+            }
+            _ => return Err(error::HashingReason::UnexpectedEvent.into()),
         };
 
         verihash.update(bytes);
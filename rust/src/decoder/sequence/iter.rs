@@ -15,12 +15,14 @@ pub struct Iter<'a, 'b, T, D: Digest> {
     decoder: &'a mut Decoder<D>,
 
     /// Tag for the field this sequence is contained in
-    // TODO(tarcieri): support nested sequences?
     tag: Tag,
 
     /// Input data
     data: &'b [u8],
 
+    /// Index of the next element
+    index: usize,
+
     /// Type to decode
     decodable: PhantomData<T>,
 }
@@ -35,6 +37,7 @@ where
             decoder,
             tag,
             data,
+            index: 0,
             decodable: PhantomData,
         }
     }
@@ -43,6 +46,22 @@ where
     fn seq_decoder(&mut self) -> &mut sequence::Decoder<D> {
         self.decoder.peek_seq()
     }
+
+    /// Add the context of the current element to an error, then advance to
+    /// the next element
+    fn finish_element<V>(&mut self, result: Result<V, Error>) -> Result<V, Error> {
+        let index = self.index;
+        self.index = self.index.checked_add(1).unwrap();
+        result.map_err(|e| self.decoder.context(e).with_index(index))
+    }
+
+    /// Add the context of the element which was last decoded to an error
+    /// found after decoding it, e.g. by comparing it to previous elements
+    #[cfg(feature = "alloc")]
+    pub(crate) fn element_error(&self, err: Error) -> Error {
+        let index = self.index.checked_sub(1).unwrap();
+        self.decoder.context(err).with_index(index)
+    }
 }
 
 impl<'a, 'b, T, D> Iterator for Iter<'a, 'b, T, D>
@@ -63,17 +82,17 @@ where
             .seq_decoder()
             .decode_message(&mut input)
             .and_then(|msg_bytes| {
-                self.decoder.push()?;
-                let msg = T::decode(&mut self.decoder, msg_bytes)?;
+                self.decoder.push(msg_bytes)?;
+                let msg = T::decode(self.decoder, msg_bytes)?;
 
-                if let Some(digest) = self.decoder.pop() {
+                if let Some(digest) = self.decoder.finish_message(msg_bytes)? {
                     self.seq_decoder().hash_message_digest(&digest)?;
                 }
 
                 Ok(msg)
             });
 
-        Some(result)
+        Some(self.finish_element(result))
     }
 }
 
@@ -89,7 +108,8 @@ where
         }
 
         let mut input = &self.data[self.seq_decoder().position()..];
-        Some(self.seq_decoder().decode_uint64(&mut input))
+        let result = self.seq_decoder().decode_uint64(&mut input);
+        Some(self.finish_element(result))
     }
 }
 
@@ -105,7 +125,44 @@ where
         }
 
         let mut input = &self.data[self.seq_decoder().position()..];
-        Some(self.seq_decoder().decode_sint64(&mut input))
+        let result = self.seq_decoder().decode_sint64(&mut input);
+        Some(self.finish_element(result))
+    }
+}
+
+impl<'a, 'b, D> Iterator for Iter<'a, 'b, &'b [u8], D>
+where
+    D: Digest,
+{
+    type Item = Result<&'b [u8], Error>;
+
+    fn next(&mut self) -> Option<Result<&'b [u8], Error>> {
+        if self.seq_decoder().remaining() == 0 {
+            return None;
+        }
+
+        let data = self.data;
+        let mut input = &data[self.seq_decoder().position()..];
+        let result = self.seq_decoder().decode_bytes(&mut input);
+        Some(self.finish_element(result))
+    }
+}
+
+impl<'a, 'b, D> Iterator for Iter<'a, 'b, &'b str, D>
+where
+    D: Digest,
+{
+    type Item = Result<&'b str, Error>;
+
+    fn next(&mut self) -> Option<Result<&'b str, Error>> {
+        if self.seq_decoder().remaining() == 0 {
+            return None;
+        }
+
+        let data = self.data;
+        let mut input = &data[self.seq_decoder().position()..];
+        let result = self.seq_decoder().decode_string(&mut input);
+        Some(self.finish_element(result))
     }
 }
 
@@ -114,11 +171,10 @@ where
     D: Digest,
 {
     fn drop(&mut self) {
-        if let Some(digest) = self.decoder.pop_seq() {
-            self.decoder
-                .peek()
-                .hash_sequence_digest(self.tag, &digest)
-                .unwrap();
+        // We can't return errors from `drop`, so instead poison the message
+        // hasher so computing the message's digest fails
+        if self.decoder.finish_seq(self.tag).is_err() {
+            self.decoder.peek().poison_hasher();
         }
     }
 }
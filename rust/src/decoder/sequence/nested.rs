@@ -0,0 +1,123 @@
+//! Nested sequence iterator
+
+use super::Iter;
+use crate::{
+    decoder::{Decodable, Decoder},
+    field::{Tag, WireType},
+    Error, Message,
+};
+use core::marker::PhantomData;
+use digest::Digest;
+
+/// Nested sequence iterator: iterates over a sequence whose elements are
+/// themselves sequences of `T`, returning an [`Iter`] over each one.
+///
+/// Each inner [`Iter`] borrows this iterator, so it must be dropped before
+/// advancing to the next sequence. This means `NestedIter` can't implement
+/// [`Iterator`]: instead, call [`NestedIter::next_seq`] in a `while let`
+/// loop.
+pub struct NestedIter<'a, 'b, T, D: Digest> {
+    /// Decoder containing the outer sequence decoder
+    decoder: &'a mut Decoder<D>,
+
+    /// Tag for the field this sequence is contained in
+    tag: Tag,
+
+    /// Input data
+    data: &'b [u8],
+
+    /// Index of the next inner sequence
+    index: usize,
+
+    /// Type to decode
+    decodable: PhantomData<T>,
+}
+
+impl<'a, 'b, T, D> NestedIter<'a, 'b, T, D>
+where
+    D: Digest,
+{
+    /// Create a new nested sequence iterator from a sequence decoder
+    pub(crate) fn new(decoder: &'a mut Decoder<D>, tag: Tag, data: &'b [u8]) -> Self {
+        Self {
+            decoder,
+            tag,
+            data,
+            index: 0,
+            decodable: PhantomData,
+        }
+    }
+
+    /// Decode the next inner sequence, containing the given wire type
+    fn next_inner(&mut self, wire_type: WireType) -> Option<Result<Iter<'_, 'b, T, D>, Error>> {
+        // Any previous inner sequence has been dropped (and popped), so the
+        // outer sequence decoder is innermost
+        if self.decoder.peek_seq().remaining() == 0 {
+            return None;
+        }
+
+        let index = self.index;
+        self.index = self.index.checked_add(1).unwrap();
+
+        let mut input = &self.data[self.decoder.peek_seq().position()..];
+        let tag = self.tag;
+        let decoder = &mut self.decoder;
+
+        let result = decoder
+            .peek_seq()
+            .decode_sequence(wire_type, &mut input)
+            .and_then(|seq_bytes| {
+                decoder.push_seq(tag, wire_type, seq_bytes.len())?;
+                Ok(seq_bytes)
+            });
+
+        Some(match result {
+            Ok(seq_bytes) => Ok(Iter::new(self.decoder, tag, seq_bytes)),
+            Err(e) => Err(self.decoder.context(e).with_index(index)),
+        })
+    }
+}
+
+impl<'a, 'b, T, D> NestedIter<'a, 'b, T, D>
+where
+    T: Message,
+    D: Digest,
+{
+    /// Decode the next sequence of messages, if any remain
+    pub fn next_seq(&mut self) -> Option<Result<Iter<'_, 'b, T, D>, Error>> {
+        self.next_inner(WireType::Message)
+    }
+}
+
+impl<'a, 'b, D> NestedIter<'a, 'b, u64, D>
+where
+    D: Digest,
+{
+    /// Decode the next sequence of `uint64` values, if any remain
+    pub fn next_seq(&mut self) -> Option<Result<Iter<'_, 'b, u64, D>, Error>> {
+        self.next_inner(WireType::UInt64)
+    }
+}
+
+impl<'a, 'b, D> NestedIter<'a, 'b, i64, D>
+where
+    D: Digest,
+{
+    /// Decode the next sequence of `sint64` values, if any remain
+    pub fn next_seq(&mut self) -> Option<Result<Iter<'_, 'b, i64, D>, Error>> {
+        self.next_inner(WireType::SInt64)
+    }
+}
+
+impl<'a, 'b, T, D> Drop for NestedIter<'a, 'b, T, D>
+where
+    D: Digest,
+{
+    fn drop(&mut self) {
+        // We can't return errors from `drop`, so instead poison the message
+        // hasher so computing the message's digest fails
+        if self.decoder.finish_seq(self.tag).is_err() {
+            self.decoder.peek().poison_hasher();
+        }
+    }
+}
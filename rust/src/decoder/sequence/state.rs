@@ -1,13 +1,11 @@
 //! Veriform sequence decoder state machine
 
 use crate::{
-    decoder::{
-        vint64::{self, zigzag},
-        Event,
-    },
+    decoder::{vint64, Event},
     error::{self, Error},
     field::WireType,
     message::Element,
+    spec, zigzag,
 };
 
 /// Sequence decoder state machine
@@ -66,10 +64,20 @@ fn decode_value<'a>(wire_type: WireType, value: u64) -> Result<Event<'a>, Error>
     Ok(match wire_type {
         WireType::UInt64 => Event::UInt64(value),
         WireType::SInt64 => Event::SInt64(zigzag::decode(value)),
-        WireType::Sequence => Event::SequenceHeader {
-            wire_type: WireType::from_unmasked(value),
-            length: (value >> 4) as usize,
-        },
+        WireType::Sequence => {
+            let (length, wire_type) = spec::unpack_sequence_header(value);
+            let wire_type = WireType::from_unmasked(wire_type.into());
+
+            // Only one level of sequence nesting is supported
+            if wire_type == WireType::Sequence {
+                return Err(error::Kind::NestedSequence.into());
+            }
+
+            Event::SequenceHeader {
+                wire_type,
+                length: length as usize,
+            }
+        }
         WireType::False | WireType::True => {
             // TODO(tarcieri): support boolean sequences?
             return Err(error::Kind::Decode {
@@ -0,0 +1,104 @@
+//! Statistics about decoded messages
+
+use super::Event;
+use crate::{field::WireType, zigzag};
+
+/// Statistics recorded while decoding a message and everything nested
+/// within it, e.g. for capacity planning, or to detect peers sending
+/// unusually large or complex messages.
+///
+/// Statistics are only recorded when enabled with
+/// [`Decoder::with_stats`][`super::Decoder::with_stats`], and include
+/// fields which were skipped as well as those which were decoded.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DecodeStats {
+    /// Number of messages, including the outermost message
+    pub messages: usize,
+
+    /// Total number of fields in all messages
+    pub fields: usize,
+
+    /// Largest number of fields in a single message
+    pub max_fields: usize,
+
+    /// Total number of elements in all sequences, including sequences
+    /// nested in other sequences
+    pub sequence_elements: usize,
+
+    /// Deepest nesting of messages and sequences, where the outermost
+    /// message is at depth 1
+    pub max_depth: usize,
+
+    /// Length of the largest `bytes`, `string`, `message` or `sequence`
+    /// field value
+    pub max_value_len: usize,
+
+    /// Total bytes of field values, indexed by wire type
+    value_bytes: [u64; 8],
+}
+
+impl DecodeStats {
+    /// Create statistics for a message nested at the given depth
+    pub(crate) fn new(depth: usize) -> Self {
+        Self {
+            messages: 1,
+            max_depth: depth,
+            ..Default::default()
+        }
+    }
+
+    /// Get the total number of bytes in the values of fields with the given
+    /// wire type.
+    ///
+    /// Nested messages and sequences are counted in their entirety, and the
+    /// fields of nested messages are also counted by their own wire types.
+    pub fn value_bytes(&self, wire_type: WireType) -> u64 {
+        self.value_bytes[wire_type as usize]
+    }
+
+    /// Record an event decoded from a message
+    pub(crate) fn record(&mut self, event: &Event<'_>) {
+        let (wire_type, len) = match *event {
+            Event::FieldHeader(_) => {
+                self.fields = self.fields.saturating_add(1);
+                return;
+            }
+            Event::UInt64(value) => (WireType::UInt64, vint64::encoded_len(value)),
+            Event::SInt64(value) => (WireType::SInt64, vint64::encoded_len(zigzag::encode(value))),
+            Event::LengthDelimiter { length, .. } | Event::SequenceHeader { length, .. } => {
+                self.max_value_len = self.max_value_len.max(length);
+                return;
+            }
+            Event::ValueChunk {
+                wire_type, bytes, ..
+            } => (wire_type, bytes.len()),
+            Event::Bool(_) => return,
+        };
+
+        let total = &mut self.value_bytes[wire_type as usize];
+        *total = total.saturating_add(len as u64);
+    }
+
+    /// Record a sequence with the given number of elements, nested at the
+    /// given depth
+    pub(crate) fn record_sequence(&mut self, depth: usize, elements: usize) {
+        self.sequence_elements = self.sequence_elements.saturating_add(elements);
+        self.max_depth = self.max_depth.max(depth);
+    }
+
+    /// Merge the statistics of a nested message
+    pub(crate) fn merge(&mut self, nested: &DecodeStats) {
+        self.messages = self.messages.saturating_add(nested.messages);
+        self.fields = self.fields.saturating_add(nested.fields);
+        self.max_fields = self.max_fields.max(nested.max_fields);
+        self.sequence_elements = self
+            .sequence_elements
+            .saturating_add(nested.sequence_elements);
+        self.max_depth = self.max_depth.max(nested.max_depth);
+        self.max_value_len = self.max_value_len.max(nested.max_value_len);
+
+        for (total, &bytes) in self.value_bytes.iter_mut().zip(&nested.value_bytes) {
+            *total = total.saturating_add(bytes);
+        }
+    }
+}
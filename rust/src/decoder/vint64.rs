@@ -1,8 +1,6 @@
 //! Decoder for `vint64` values
 
-pub(crate) use vint64::signed::zigzag;
-
-use crate::error::{self, Error};
+use crate::error::Error;
 
 /// Decoder for `vint64` values
 #[derive(Clone, Debug, Default)]
@@ -23,6 +21,20 @@ impl Decoder {
         Self::default()
     }
 
+    /// Has this decoder consumed any input?
+    pub fn is_empty(&self) -> bool {
+        self.pos == 0
+    }
+
+    /// Get the number of bytes needed to finish decoding the current
+    /// `vint64`, or 1 if its length isn't known yet
+    pub fn needed(&self) -> usize {
+        match self.length {
+            Some(length) => length.checked_sub(self.pos).unwrap(),
+            None => 1,
+        }
+    }
+
     /// Decode a `vint64` from the incoming data
     pub fn decode(&mut self, input: &mut &[u8]) -> Result<Option<u64>, Error> {
         if let Some(length) = self.length {
@@ -44,7 +56,7 @@ impl Decoder {
 
         if input.len() < remaining {
             let new_pos = self.pos.checked_add(input.len()).unwrap();
-            self.buffer[self.pos..new_pos].copy_from_slice(*input);
+            self.buffer[self.pos..new_pos].copy_from_slice(input);
             self.pos = new_pos;
             *input = &[];
         } else {
@@ -61,8 +73,6 @@ impl Decoder {
         }
 
         let mut buffer = &self.buffer[..length];
-        vint64::decode(&mut buffer)
-            .map(Some)
-            .map_err(|_| error::Kind::VInt64.into())
+        vint64::decode(&mut buffer).map(Some).map_err(Error::from)
     }
 }
@@ -0,0 +1,354 @@
+//! Visitor-based alternative to decoding [`Event`]s
+
+use super::{message::Hasher, Event};
+use crate::{
+    error::Error,
+    field::{Header, Tag, WireType},
+};
+use digest::Digest;
+
+/// Visitor which is called with each value as it's decoded, as an
+/// alternative to matching on [`Event`]s (see
+/// [`Decoder::decode_with_visitor`][`super::Decoder::decode_with_visitor`]).
+///
+/// Each method is passed the tag of the field the value belongs to, and
+/// returns an error to abort decoding. All methods do nothing by default,
+/// so visitors need only implement the ones they're interested in.
+///
+/// Values of `bytes`, `string`, `message`, and `sequence` fields are passed
+/// as chunks of their bodies, preceded by their length: the final chunk of
+/// a value has `remaining` set to 0, and empty values have no chunks at
+/// all. Nested messages and sequences aren't descended into.
+pub trait Visitor<'a> {
+    /// Visit a field header with the given tag and wire type
+    fn field_header(&mut self, header: &Header) -> Result<(), Error> {
+        let _ = header;
+        Ok(())
+    }
+
+    /// Visit a boolean value
+    fn bool(&mut self, tag: Tag, value: bool) -> Result<(), Error> {
+        let _ = (tag, value);
+        Ok(())
+    }
+
+    /// Visit an unsigned 64-bit integer
+    fn uint64(&mut self, tag: Tag, value: u64) -> Result<(), Error> {
+        let _ = (tag, value);
+        Ok(())
+    }
+
+    /// Visit a signed 64-bit integer
+    fn sint64(&mut self, tag: Tag, value: i64) -> Result<(), Error> {
+        let _ = (tag, value);
+        Ok(())
+    }
+
+    /// Visit the length of a `bytes`, `string`, or `message` value
+    fn length_delimiter(
+        &mut self,
+        tag: Tag,
+        wire_type: WireType,
+        length: usize,
+    ) -> Result<(), Error> {
+        let _ = (tag, wire_type, length);
+        Ok(())
+    }
+
+    /// Visit a chunk of a `bytes` value
+    fn bytes_chunk(&mut self, tag: Tag, bytes: &'a [u8], remaining: usize) -> Result<(), Error> {
+        let _ = (tag, bytes, remaining);
+        Ok(())
+    }
+
+    /// Visit a chunk of a `string` value.
+    ///
+    /// The value is validated as UTF-8 as it's decoded, but a multi-byte
+    /// code point may be split across chunks.
+    fn string_chunk(&mut self, tag: Tag, bytes: &'a [u8], remaining: usize) -> Result<(), Error> {
+        let _ = (tag, bytes, remaining);
+        Ok(())
+    }
+
+    /// Visit a chunk of the body of a nested message
+    fn message_chunk(&mut self, tag: Tag, bytes: &'a [u8], remaining: usize) -> Result<(), Error> {
+        let _ = (tag, bytes, remaining);
+        Ok(())
+    }
+
+    /// Visit the header of a sequence containing the given wire type, with
+    /// a body of the given length
+    fn sequence_header(
+        &mut self,
+        tag: Tag,
+        wire_type: WireType,
+        length: usize,
+    ) -> Result<(), Error> {
+        let _ = (tag, wire_type, length);
+        Ok(())
+    }
+
+    /// Visit a chunk of the body of a sequence
+    fn sequence_chunk(&mut self, tag: Tag, bytes: &'a [u8], remaining: usize) -> Result<(), Error> {
+        let _ = (tag, bytes, remaining);
+        Ok(())
+    }
+}
+
+/// Verihash hashers can be driven directly by the decoder, hashing each
+/// value as it's visited.
+///
+/// As with [`Hasher::hash_event`], messages containing nested messages or
+/// sequences can't be hashed this way.
+impl<'a, D> Visitor<'a> for Hasher<D>
+where
+    D: Digest,
+{
+    fn field_header(&mut self, header: &Header) -> Result<(), Error> {
+        self.hash_event(&Event::FieldHeader(*header))
+    }
+
+    fn bool(&mut self, _tag: Tag, value: bool) -> Result<(), Error> {
+        self.hash_event(&Event::Bool(value))
+    }
+
+    fn uint64(&mut self, _tag: Tag, value: u64) -> Result<(), Error> {
+        self.hash_event(&Event::UInt64(value))
+    }
+
+    fn sint64(&mut self, _tag: Tag, value: i64) -> Result<(), Error> {
+        self.hash_event(&Event::SInt64(value))
+    }
+
+    fn length_delimiter(
+        &mut self,
+        _tag: Tag,
+        wire_type: WireType,
+        length: usize,
+    ) -> Result<(), Error> {
+        self.hash_event(&Event::LengthDelimiter { wire_type, length })
+    }
+
+    fn bytes_chunk(&mut self, _tag: Tag, bytes: &'a [u8], remaining: usize) -> Result<(), Error> {
+        self.hash_event(&Event::ValueChunk {
+            wire_type: WireType::Bytes,
+            bytes,
+            remaining,
+        })
+    }
+
+    fn string_chunk(&mut self, _tag: Tag, bytes: &'a [u8], remaining: usize) -> Result<(), Error> {
+        self.hash_event(&Event::ValueChunk {
+            wire_type: WireType::String,
+            bytes,
+            remaining,
+        })
+    }
+
+    fn message_chunk(&mut self, _tag: Tag, bytes: &'a [u8], remaining: usize) -> Result<(), Error> {
+        self.hash_event(&Event::ValueChunk {
+            wire_type: WireType::Message,
+            bytes,
+            remaining,
+        })
+    }
+
+    fn sequence_header(
+        &mut self,
+        _tag: Tag,
+        wire_type: WireType,
+        length: usize,
+    ) -> Result<(), Error> {
+        self.hash_event(&Event::SequenceHeader { wire_type, length })
+    }
+
+    fn sequence_chunk(
+        &mut self,
+        _tag: Tag,
+        bytes: &'a [u8],
+        remaining: usize,
+    ) -> Result<(), Error> {
+        self.hash_event(&Event::ValueChunk {
+            wire_type: WireType::Sequence,
+            bytes,
+            remaining,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::Visitor;
+    use crate::{
+        decoder::{Event, Events, Hasher},
+        error::Error,
+        field::{Header, Tag, WireType},
+        verihash::verihash,
+        Decoder,
+    };
+    use heapless::{consts::U32, Vec};
+    use sha2::Sha256;
+
+    /// `{ 1: uint64(42), 3: string("baz"), 4: true,
+    ///    5: message({ 0: uint64(42) }), 6: sequence<uint64>([42, 43]) }`
+    const EXAMPLE_MESSAGE: &[u8] = &[
+        37, 85, 107, 7, 98, 97, 122, 131, 173, 5, 5, 85, 207, 69, 85, 87,
+    ];
+
+    /// Visitor which rebuilds the events it visits
+    #[derive(Default)]
+    struct EventBuilder<'a>(Vec<Event<'a>, U32>);
+
+    impl<'a> EventBuilder<'a> {
+        fn push(&mut self, event: Event<'a>) -> Result<(), Error> {
+            self.0.push(event).unwrap();
+            Ok(())
+        }
+
+        fn chunk(
+            &mut self,
+            wire_type: WireType,
+            bytes: &'a [u8],
+            remaining: usize,
+        ) -> Result<(), Error> {
+            self.push(Event::ValueChunk {
+                wire_type,
+                bytes,
+                remaining,
+            })
+        }
+    }
+
+    impl<'a> Visitor<'a> for EventBuilder<'a> {
+        fn field_header(&mut self, header: &Header) -> Result<(), Error> {
+            self.push(Event::FieldHeader(*header))
+        }
+
+        fn bool(&mut self, _tag: Tag, value: bool) -> Result<(), Error> {
+            self.push(Event::Bool(value))
+        }
+
+        fn uint64(&mut self, _tag: Tag, value: u64) -> Result<(), Error> {
+            self.push(Event::UInt64(value))
+        }
+
+        fn sint64(&mut self, _tag: Tag, value: i64) -> Result<(), Error> {
+            self.push(Event::SInt64(value))
+        }
+
+        fn length_delimiter(
+            &mut self,
+            _tag: Tag,
+            wire_type: WireType,
+            length: usize,
+        ) -> Result<(), Error> {
+            self.push(Event::LengthDelimiter { wire_type, length })
+        }
+
+        fn bytes_chunk(
+            &mut self,
+            _tag: Tag,
+            bytes: &'a [u8],
+            remaining: usize,
+        ) -> Result<(), Error> {
+            self.chunk(WireType::Bytes, bytes, remaining)
+        }
+
+        fn string_chunk(
+            &mut self,
+            _tag: Tag,
+            bytes: &'a [u8],
+            remaining: usize,
+        ) -> Result<(), Error> {
+            self.chunk(WireType::String, bytes, remaining)
+        }
+
+        fn message_chunk(
+            &mut self,
+            _tag: Tag,
+            bytes: &'a [u8],
+            remaining: usize,
+        ) -> Result<(), Error> {
+            self.chunk(WireType::Message, bytes, remaining)
+        }
+
+        fn sequence_header(
+            &mut self,
+            _tag: Tag,
+            wire_type: WireType,
+            length: usize,
+        ) -> Result<(), Error> {
+            self.push(Event::SequenceHeader { wire_type, length })
+        }
+
+        fn sequence_chunk(
+            &mut self,
+            _tag: Tag,
+            bytes: &'a [u8],
+            remaining: usize,
+        ) -> Result<(), Error> {
+            self.chunk(WireType::Sequence, bytes, remaining)
+        }
+    }
+
+    #[test]
+    fn matches_events() {
+        let mut decoder = Decoder::new();
+        let mut builder = EventBuilder::default();
+        let mut input = EXAMPLE_MESSAGE;
+        decoder
+            .decode_with_visitor(&mut input, &mut builder)
+            .unwrap();
+        assert!(input.is_empty());
+
+        let events = Events::new(EXAMPLE_MESSAGE)
+            .collect::<Result<Vec<_, U32>, _>>()
+            .unwrap();
+        assert_eq!(builder.0, events);
+
+        // Nested messages and sequences are still hashed
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest).unwrap();
+        assert_eq!(
+            digest,
+            verihash::<Sha256>(EXAMPLE_MESSAGE).unwrap().as_bytes()
+        );
+    }
+
+    #[test]
+    fn hasher_visitor() {
+        // Fields 1-4 of the example message, which contain no nested messages
+        let input = &EXAMPLE_MESSAGE[..8];
+        let mut hasher = Hasher::<Sha256>::new();
+        Decoder::new()
+            .decode_with_visitor(&mut &input[..], &mut hasher)
+            .unwrap();
+
+        assert_eq!(hasher.finish().unwrap(), verihash::<Sha256>(input).unwrap());
+    }
+
+    #[test]
+    fn visitor_error() {
+        /// Visitor which rejects `uint64` values over 42
+        struct MaxValue;
+
+        impl<'a> Visitor<'a> for MaxValue {
+            fn uint64(&mut self, _tag: Tag, value: u64) -> Result<(), Error> {
+                if value > 42 {
+                    Err(crate::error::Kind::Failed.into())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        // `{ 1: uint64(42), 2: uint64(43) }`
+        let input = [37, 85, 69, 87];
+        let err = Decoder::new()
+            .decode_with_visitor(&mut &input[..], &mut MaxValue)
+            .err()
+            .unwrap();
+        assert_eq!(err.tag(), Some(Tag::new_const(2)));
+        assert_eq!(err.position(), Some(4));
+    }
+}
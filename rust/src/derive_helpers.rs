@@ -1,77 +1,250 @@
 //! Helper functions called from custom derive output
 
 use crate::{
-    decoder::sequence,
-    decoder::{DecodeSeq, Decoder},
+    decoder::Decoder,
     encoder::Encoder,
     error::{self, Error},
     field::{self, Tag, WireType},
     message::{Element, Message},
 };
+use core::convert::TryFrom;
 use digest::Digest;
 use heapless::ArrayLength;
 
+// Named by the `SequenceElement` impls of `#[veriform(transparent)]` newtypes
+#[cfg(feature = "alloc")]
+pub use alloc::vec::Vec;
+
 /// Make sure input has been consumed
 pub fn check_input_consumed(input: &[u8]) -> Result<(), Error> {
     if input.is_empty() {
         Ok(())
     } else {
-        Err(error::Kind::TrailingData.into())
+        Err(error::Kind::TrailingData {
+            remaining: input.len(),
+        }
+        .into())
     }
 }
 
-/// Decode a sequence of messages
-// TODO(tarcieri): support other sequence types
-pub fn decode_message_seq<T, N, D>(
+/// Decode a `bytes` field into a [`heapless::Vec`] without allocating
+pub fn decode_bytes_into_vec<N, D>(
+    decoder: &mut Decoder<D>,
+    tag: Tag,
+    input: &mut &[u8],
+) -> Result<heapless::Vec<u8, N>, Error>
+where
+    N: ArrayLength<u8>,
+    D: Digest,
+{
+    let bytes = decoder.decode_bytes(tag, input)?;
+
+    heapless::Vec::from_slice(bytes).map_err(|_| {
+        let err = error::Kind::BufferTooSmall {
+            needed: bytes.len(),
+        };
+        decoder.context(err.into())
+    })
+}
+
+/// Decode a `string` field into a [`heapless::String`] without allocating
+pub fn decode_string_into_string<N, D>(
     decoder: &mut Decoder<D>,
     tag: Tag,
     input: &mut &[u8],
-) -> Result<heapless::Vec<T, N>, Error>
+) -> Result<heapless::String<N>, Error>
+where
+    N: ArrayLength<u8>,
+    D: Digest,
+{
+    let string = decoder.decode_string(tag, input)?;
+    let mut result = heapless::String::new();
+
+    result.push_str(string).map_err(|_| {
+        let err = error::Kind::BufferTooSmall {
+            needed: string.len(),
+        };
+        decoder.context(err.into())
+    })?;
+
+    Ok(result)
+}
+
+/// Decode a `bytes` field into any type which can be converted from a
+/// byte slice, e.g. a newtype marked `#[field(bytes)]`
+pub fn decode_bytes_into<T, D>(
+    decoder: &mut Decoder<D>,
+    tag: Tag,
+    input: &mut &[u8],
+) -> Result<T, Error>
+where
+    T: for<'b> TryFrom<&'b [u8]>,
+    D: Digest,
+{
+    let bytes = decoder.decode_bytes(tag, input)?;
+    T::try_from(bytes).map_err(|_| decoder.context(WireType::Bytes.decoding_error()))
+}
+
+/// Decode a sequence of messages into a collection
+pub fn decode_message_seq<T, C, D>(
+    decoder: &mut Decoder<D>,
+    tag: Tag,
+    input: &mut &[u8],
+) -> Result<C, Error>
 where
     T: Message,
-    N: ArrayLength<T>,
+    C: Default + TryExtend<T>,
     D: Digest,
 {
-    let mut result = heapless::Vec::new();
-    let seq_iter: sequence::Iter<'_, '_, T, D> = decoder.decode_seq(tag, input)?;
+    try_collect(decoder.message_seq_iter::<T>(tag, input)?)
+}
+
+/// Decode a sequence of `uint64` values into a collection
+pub fn decode_uint64_seq<C, D>(
+    decoder: &mut Decoder<D>,
+    tag: Tag,
+    input: &mut &[u8],
+) -> Result<C, Error>
+where
+    C: Default + TryExtend<u64>,
+    D: Digest,
+{
+    try_collect(decoder.uint64_seq_iter(tag, input)?)
+}
+
+/// Decode a sequence of `sint64` values into a collection
+pub fn decode_sint64_seq<C, D>(
+    decoder: &mut Decoder<D>,
+    tag: Tag,
+    input: &mut &[u8],
+) -> Result<C, Error>
+where
+    C: Default + TryExtend<i64>,
+    D: Digest,
+{
+    try_collect(decoder.sint64_seq_iter(tag, input)?)
+}
+
+/// Decode a sequence of `bytes` values into a collection, converting each
+/// one from a byte slice
+pub fn decode_bytes_seq<T, C, D>(
+    decoder: &mut Decoder<D>,
+    tag: Tag,
+    input: &mut &[u8],
+) -> Result<C, Error>
+where
+    T: for<'b> TryFrom<&'b [u8]>,
+    C: Default + TryExtend<T>,
+    D: Digest,
+{
+    try_collect(decoder.bytes_seq_iter(tag, input)?.map(|bytes| {
+        bytes.and_then(|bytes| T::try_from(bytes).map_err(|_| WireType::Bytes.decoding_error()))
+    }))
+}
+
+/// Decode a sequence of `string` values into a collection, converting each
+/// one from a string slice
+pub fn decode_string_seq<T, C, D>(
+    decoder: &mut Decoder<D>,
+    tag: Tag,
+    input: &mut &[u8],
+) -> Result<C, Error>
+where
+    T: for<'b> TryFrom<&'b str>,
+    C: Default + TryExtend<T>,
+    D: Digest,
+{
+    try_collect(decoder.string_seq_iter(tag, input)?.map(|string| {
+        string.and_then(|string| T::try_from(string).map_err(|_| WireType::String.decoding_error()))
+    }))
+}
+
+/// Collect the values of a sequence, returning an error if any fail to
+/// decode or the collection's capacity is exceeded
+fn try_collect<T, C>(seq_iter: impl Iterator<Item = Result<T, Error>>) -> Result<C, Error>
+where
+    C: Default + TryExtend<T>,
+{
+    let mut result = C::default();
 
     for elem in seq_iter {
-        result.push(elem?).map_err(|_| error::Kind::Decode {
-            element: Element::Value,
-            wire_type: WireType::Sequence,
-        })?
+        result
+            .try_extend(Some(elem?))
+            .map_err(|_| error::Kind::Decode {
+                element: Element::Value,
+                wire_type: WireType::Sequence,
+            })?;
     }
 
     Ok(result)
 }
 
-/// Encode a sequence of messages
-pub fn encode_message_seq<T>(
-    encoder: &mut Encoder<'_>,
-    tag: Tag,
-    critical: bool,
-    seq: &[T],
+/// Encode a message's fields into the given buffer.
+///
+/// If the buffer is too small, the error reports the message's whole
+/// encoded length (computed by `encoded_len`) as the length needed.
+pub fn encode_message<'a>(
+    buffer: &'a mut [u8],
+    encoded_len: impl FnOnce() -> usize,
+    encode_fields: impl FnOnce(&mut Encoder<'a>) -> Result<(), Error>,
+) -> Result<&'a [u8], Error> {
+    let mut encoder = Encoder::new(buffer);
+
+    encode_fields(&mut encoder)
+        .and_then(|()| encoder.try_finish())
+        .map_err(|err| match err.kind() {
+            error::Kind::BufferTooSmall { .. } => err.with_kind(error::Kind::BufferTooSmall {
+                needed: encoded_len(),
+            }),
+            _ => err,
+        })
+}
+
+/// Add the name of the struct member (or enum variant) being encoded to an
+/// error, unless it occurred in a nested message
+pub fn field_error(err: Error, name: &'static &'static str) -> Error {
+    err.with_field_name(name)
+}
+
+/// Is the given value equal to the field's default value? Fields marked
+/// `#[field(skip_default)]` are omitted when they are.
+pub fn is_default<T: PartialEq>(value: &T, default: &T) -> bool {
+    value == default
+}
+
+/// Ensure the value decoded from a `#[field(skip_default)]` field isn't the
+/// default value, which must be omitted from canonical messages
+pub fn check_not_default<T: PartialEq>(tag: Tag, value: &T, default: &T) -> Result<(), Error> {
+    if is_default(value, default) {
+        Err(error::Kind::DefaultValue { tag }.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Run the `#[field(validate = "...")]` validator of a struct member on its
+/// decoded value, adding the context of the field to errors
+pub fn validate_field<T, E, D>(
+    decoder: &Decoder<D>,
+    value: &T,
+    validator: impl FnOnce(&T) -> Result<(), E>,
 ) -> Result<(), Error>
 where
-    T: Message,
+    T: ?Sized,
+    E: Into<Error>,
+    D: Digest,
 {
-    let body_len: usize = seq
-        .iter()
-        .map(|msg| {
-            // compute length with additional length prefix
-            let encoded_len = msg.encoded_len();
-            vint64::encoded_len(encoded_len as u64)
-                .checked_add(encoded_len)
-                .unwrap()
-        })
-        .sum();
+    validator(value).map_err(|err| decoder.context(err.into()))
+}
 
-    encoder.message_seq(
-        tag,
-        critical,
-        body_len,
-        seq.iter().map(|elem| elem as &dyn Message),
-    )
+/// Run the `#[veriform(validate = "...")]` validator of a message once all
+/// of its fields have been decoded (or the validator of a `skip_default`
+/// member on the default value of a missing field)
+pub fn validate<T, E>(value: &T, validator: impl FnOnce(&T) -> Result<(), E>) -> Result<(), Error>
+where
+    E: Into<Error>,
+{
+    validator(value).map_err(Into::into)
 }
 
 /// Decode tag (which identifies an enum variant)
@@ -80,6 +253,149 @@ pub fn decode_tag(mut input: &[u8]) -> Result<Tag, Error> {
     Ok(field::Header::from(header).tag)
 }
 
+/// Empty message encoding a unit variant of an enum.
+///
+/// Unknown non-critical fields are skipped when decoding it, so a unit
+/// variant can later gain fields by becoming a message variant.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Unit;
+
+impl Message for Unit {
+    fn decode<D>(decoder: &mut Decoder<D>, mut input: &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        skip_unknown_fields(decoder, None, &mut input)?;
+        Ok(Unit)
+    }
+
+    fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        Ok(&buffer[..0])
+    }
+
+    fn encoded_len(&self) -> usize {
+        0
+    }
+}
+
+/// Make sure no other variant of an enum (i.e. a oneof) follows the one
+/// which was decoded, unless the decoder is permissive
+pub fn check_oneof_consumed<D: Digest>(decoder: &Decoder<D>, input: &[u8]) -> Result<(), Error> {
+    if input.is_empty() || !decoder.is_canonical() {
+        return Ok(());
+    }
+
+    let tag = decode_tag(input)?;
+    Err(decoder.context(error::Kind::MultipleVariants { tag }.into()))
+}
+
+/// Decode the value of an enum's `#[field(unknown)]` variant, which
+/// preserves a (non-critical) field whose tag isn't one of its variants
+#[cfg(feature = "alloc")]
+pub fn decode_unknown_variant<D: Digest>(
+    decoder: &mut Decoder<D>,
+    input: &mut &[u8],
+) -> Result<field::UnknownField, Error> {
+    // Rejects critical fields
+    peek_unknown_field(None, input).map_err(|e| decoder.context(e))?;
+
+    let header = decoder.decode_header(input)?;
+    let value = *input;
+    decoder.skip(&header, input)?;
+
+    let value_len = value.len().checked_sub(input.len()).unwrap();
+    Ok(field::UnknownField::new(header, &value[..value_len]))
+}
+
+/// Skip unknown fields preceding the field with the given tag, or all
+/// remaining fields if `next_tag` is `None`.
+///
+/// Returns an error if any of the skipped fields are critical.
+pub fn skip_unknown_fields<D: Digest>(
+    decoder: &mut Decoder<D>,
+    next_tag: Option<Tag>,
+    input: &mut &[u8],
+) -> Result<(), Error> {
+    while peek_unknown_field(next_tag, input).map_err(|e| decoder.context(e))? {
+        let header = decoder.decode_header(input)?;
+        decoder.skip(&header, input)?;
+    }
+
+    Ok(())
+}
+
+/// Capture unknown fields preceding the field with the given tag, or all
+/// remaining fields if `next_tag` is `None`, preserving them in `unknown`.
+///
+/// Returns an error if any of the captured fields are critical.
+#[cfg(feature = "alloc")]
+pub fn capture_unknown_fields<D: Digest>(
+    decoder: &mut Decoder<D>,
+    next_tag: Option<Tag>,
+    input: &mut &[u8],
+    unknown: &mut field::UnknownFields,
+) -> Result<(), Error> {
+    while peek_unknown_field(next_tag, input).map_err(|e| decoder.context(e))? {
+        let header = decoder.decode_header(input)?;
+        let value = *input;
+        decoder.skip(&header, input)?;
+
+        let value_len = value.len().checked_sub(input.len()).unwrap();
+        unknown.insert(field::UnknownField::new(header, &value[..value_len]));
+    }
+
+    Ok(())
+}
+
+/// Encode the unknown fields whose tags fall after `prev_tag` and before
+/// `next_tag` (either bound being `None` means unbounded)
+#[cfg(feature = "alloc")]
+pub fn encode_unknown_fields(
+    encoder: &mut Encoder<'_>,
+    unknown: &field::UnknownFields,
+    prev_tag: Option<Tag>,
+    next_tag: Option<Tag>,
+) -> Result<(), Error> {
+    for field in unknown {
+        let tag = field.tag();
+
+        if prev_tag.map(|prev| tag <= prev).unwrap_or(false) {
+            continue;
+        }
+
+        if next_tag.map(|next| tag >= next).unwrap_or(false) {
+            break;
+        }
+
+        encoder.unknown_field(field)?;
+    }
+
+    Ok(())
+}
+
+/// Peek at the header of the next field in the input, returning `true` if
+/// it's an unknown field preceding `next_tag`.
+///
+/// Returns an error if the field is unknown and critical.
+fn peek_unknown_field(next_tag: Option<Tag>, input: &[u8]) -> Result<bool, Error> {
+    if input.is_empty() {
+        return Ok(false);
+    }
+
+    let mut peek = input;
+    let header = field::Header::from(vint64::decode(&mut peek)?);
+
+    if next_tag.map(|tag| header.tag >= tag).unwrap_or(false) {
+        return Ok(false);
+    }
+
+    if header.critical {
+        return Err(error::Kind::UnknownCritical { tag: header.tag }.into());
+    }
+
+    Ok(true)
+}
+
 /// Unknown tag in enum
 pub fn unknown_tag(tag: Tag) -> Error {
     error::Kind::FieldHeader {
@@ -91,6 +407,7 @@ pub fn unknown_tag(tag: Tag) -> Error {
 
 /// Fallible version of the `Extend` trait used for consuming Veriform
 /// sequences but with potential max limits (e.g. `heapless::Vec` size)
+#[allow(clippy::result_unit_err)]
 pub trait TryExtend<A> {
     /// Try to extend this type using the given iterator, returning an error if
     /// capacity in the underlying buffer is exceeded
@@ -113,9 +430,73 @@ where
 }
 
 #[cfg(feature = "alloc")]
-impl<T> TryExtend<T> for alloc::vec::Vec<T> {
+impl<T> TryExtend<T> for Vec<T> {
     fn try_extend<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Result<(), ()> {
         self.extend(iter);
         Ok(())
     }
 }
+
+/// Impl `SequenceElement` for a `#[veriform(transparent)]` newtype by
+/// delegating to its inner type, if the `alloc` feature is enabled
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! impl_transparent_sequence_element {
+    ($newtype:ty, $inner:ty) => {
+        impl $crate::value::SequenceElement for $newtype {
+            const ELEMENT_TYPE: $crate::field::WireType =
+                <$inner as $crate::value::SequenceElement>::ELEMENT_TYPE;
+
+            fn decode_seq<D>(
+                decoder: &mut $crate::decoder::Decoder<D>,
+                tag: $crate::field::Tag,
+                input: &mut &[u8],
+            ) -> Result<$crate::derive_helpers::Vec<Self>, $crate::Error>
+            where
+                D: $crate::digest::Digest,
+            {
+                let values =
+                    <$inner as $crate::value::SequenceElement>::decode_seq(decoder, tag, input)?;
+
+                Ok(values.into_iter().map(Self).collect())
+            }
+
+            fn encode_seq_with<T>(
+                values: &[T],
+                element: impl Fn(&T) -> &Self,
+                encoder: &mut $crate::encoder::Encoder<'_>,
+                tag: $crate::field::Tag,
+                critical: bool,
+            ) -> Result<(), $crate::Error> {
+                <$inner as $crate::value::SequenceElement>::encode_seq_with(
+                    values,
+                    |value| &element(value).0,
+                    encoder,
+                    tag,
+                    critical,
+                )
+            }
+
+            fn encoded_seq_len_with<T>(
+                values: &[T],
+                element: impl Fn(&T) -> &Self,
+                tag: $crate::field::Tag,
+            ) -> usize {
+                <$inner as $crate::value::SequenceElement>::encoded_seq_len_with(
+                    values,
+                    |value| &element(value).0,
+                    tag,
+                )
+            }
+        }
+    };
+}
+
+/// Without `alloc` there are no sequences of newtypes
+#[cfg(not(feature = "alloc"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! impl_transparent_sequence_element {
+    ($newtype:ty, $inner:ty) => {};
+}
@@ -1,37 +1,138 @@
 //! Veriform encoder
 
+mod bytes_writer;
+mod hasher;
+#[cfg(feature = "std")]
+mod writer;
+
+pub use self::{bytes_writer::BytesWriter, hasher::Hasher};
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use self::writer::{WriteError, Writer};
+
 use crate::{
     error::{self, Error},
-    field::{Header, Tag, WireType},
-    message::Message,
-    string,
+    field::{length, Header, Tag, WireType},
+    message::{Message, MessageRef, RawMessage},
+    spec, string, zigzag,
 };
+use core::{cmp, ops::Range};
 
-/// Veriform encoder
+#[cfg(feature = "alloc")]
+use crate::{field::UnknownField, map, value::Encodable};
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeMap;
+
+/// Veriform encoder.
+///
+/// Fields must be written in canonical order, i.e. with increasing tags:
+/// writing a field whose tag is less than or equal to that of the previous
+/// field fails with [`error::Kind::TagOrder`] (see [`Encoder::unordered`]).
 pub struct Encoder<'a> {
     /// Mutable buffer containing the message
     buffer: &'a mut [u8],
 
     /// Running total length of the message
     length: usize,
+
+    /// Tag of the last field written
+    last_tag: Option<Tag>,
+
+    /// Are fields required to be written in tag order?
+    ordered: bool,
+
+    /// Tag and offset of a field whose [`BytesWriter`] was dropped before it
+    /// was finished
+    abandoned: Option<(Tag, usize)>,
+
+    /// Number of rollbacks, which invalidate the checkpoints taken before
+    /// them
+    generation: u64,
+}
+
+/// Position of an [`Encoder`] between fields, which it can be rolled back to
+/// (see [`Encoder::checkpoint`])
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Checkpoint {
+    /// Address of the encoder's buffer, identifying the encoder
+    buffer: usize,
+
+    /// Length of the message at the checkpoint
+    length: usize,
+
+    /// Tag of the last field written before the checkpoint
+    last_tag: Option<Tag>,
+
+    /// Generation of the encoder when the checkpoint was taken
+    generation: u64,
 }
 
 impl<'a> Encoder<'a> {
     /// Create a new [`Encoder`] which writes into the provided buffer
     pub fn new(buffer: &'a mut [u8]) -> Self {
-        Self { buffer, length: 0 }
+        Self {
+            buffer,
+            length: 0,
+            last_tag: None,
+            ordered: true,
+            abandoned: None,
+            generation: 0,
+        }
+    }
+
+    /// Create a new [`Encoder`] which writes into the provided buffer
+    /// without checking fields are written in tag order.
+    ///
+    /// Messages whose fields are out of order aren't canonical, so they're
+    /// rejected by decoders and don't have a well-defined Verihash digest.
+    pub fn unordered(buffer: &'a mut [u8]) -> Self {
+        Self {
+            ordered: false,
+            ..Self::new(buffer)
+        }
     }
 
     /// Write a field containing an unsigned 64-bit integer
     pub fn uint64(&mut self, tag: Tag, critical: bool, value: u64) -> Result<(), Error> {
-        self.write_header(tag, critical, WireType::UInt64)?;
-        self.write(vint64::encode(value))
+        self.write_field(tag, length::uint64(tag, value), |encoder| {
+            encoder.write_header(tag, critical, WireType::UInt64)?;
+            encoder.write(vint64::encode(value))
+        })
     }
 
     /// Write a field containing a signed 64-bit integer
     pub fn sint64(&mut self, tag: Tag, critical: bool, value: i64) -> Result<(), Error> {
-        self.write_header(tag, critical, WireType::SInt64)?;
-        self.write(vint64::signed::encode(value))
+        self.write_field(tag, length::sint64(tag, value), |encoder| {
+            encoder.write_header(tag, critical, WireType::SInt64)?;
+            encoder.write(vint64::encode(zigzag::encode(value)))
+        })
+    }
+
+    /// Write a field containing an unsigned 32-bit integer, which is encoded
+    /// as a `uint64`
+    pub fn uint32(&mut self, tag: Tag, critical: bool, value: u32) -> Result<(), Error> {
+        self.uint64(tag, critical, value.into())
+    }
+
+    /// Write a field containing a signed 32-bit integer, which is encoded as
+    /// an `sint64`
+    pub fn sint32(&mut self, tag: Tag, critical: bool, value: i32) -> Result<(), Error> {
+        self.sint64(tag, critical, value.into())
+    }
+
+    /// Write a field containing a boolean
+    pub fn bool(&mut self, tag: Tag, critical: bool, value: bool) -> Result<(), Error> {
+        // Booleans are encoded entirely by their wire type and have no body
+        let wire_type = if value {
+            WireType::True
+        } else {
+            WireType::False
+        };
+
+        self.write_field(tag, length::bool(tag), |encoder| {
+            encoder.write_header(tag, critical, wire_type)
+        })
     }
 
     /// Write a message (nested inside of a field)
@@ -41,81 +142,632 @@ impl<'a> Encoder<'a> {
         critical: bool,
         message: &dyn Message,
     ) -> Result<(), Error> {
-        let encoded_len = message.encoded_len();
-
-        self.write_header(tag, critical, WireType::Message)?;
-        self.write(vint64::encode(encoded_len as u64))?;
+        // The message's length is only computed if encoding it fails (see
+        // `Encoder::write_message`)
+        let checkpoint = self.checkpoint();
 
-        // Ensure there's remaining space in the buffer
-        if encoded_len > (self.buffer.len() - self.length) {
-            return Err(error::Kind::Length.into());
-        }
+        self.write_header(tag, critical, WireType::Message)
+            .and_then(|()| self.write_message(tag, message))
+            .map(|_| ())
+            .map_err(|err| {
+                let field_len = length::message(tag, message);
+                self.abort_field(checkpoint, field_len, err)
+                    .in_field(checkpoint.length, tag)
+            })
+    }
 
-        let new_length = self.length.checked_add(encoded_len).unwrap();
-        message.encode(&mut self.buffer[self.length..new_length])?;
-        self.length = new_length;
+    /// Write a message which borrows from the input it was decoded from
+    /// (see [`MessageRef`]) nested inside of a field
+    pub fn message_ref<'m, M>(&mut self, tag: Tag, critical: bool, message: &M) -> Result<(), Error>
+    where
+        M: MessageRef<'m>,
+    {
+        let checkpoint = self.checkpoint();
 
-        Ok(())
+        self.write_header(tag, critical, WireType::Message)
+            .and_then(|()| {
+                self.write_message_body(tag, |buffer| message.encode(buffer).map(<[u8]>::len))
+            })
+            .map(|_| ())
+            .map_err(|err| {
+                let field_len = length::message_ref(tag, message);
+                self.abort_field(checkpoint, field_len, err)
+                    .in_field(checkpoint.length, tag)
+            })
     }
 
-    /// Write a sequence of messages (nested inside of a field)
-    pub fn message_seq<'m>(
+    /// Write a message (nested inside of a field) whose body has the given
+    /// length, calling the given function to write its fields to a nested
+    /// [`Encoder`] over exactly that much of the buffer (e.g. the length
+    /// computed by [`Message::encoded_len`]), so it's encoded in place.
+    ///
+    /// Returns [`error::Kind::Length`] if the function writes a different
+    /// number of bytes. If it (or the function) fails, the field is cleared
+    /// and the encoder is left at the start of it, as with other fields.
+    pub fn message_with<F>(
         &mut self,
         tag: Tag,
         critical: bool,
         length: usize,
-        messages: impl Iterator<Item = &'m dyn Message>,
-    ) -> Result<(), Error> {
-        self.write_header(tag, critical, WireType::Sequence)?;
+        write_body: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Encoder<'_>) -> Result<(), Error>,
+    {
+        let field_len = length::dynamically_sized(tag, WireType::Message, length);
+
+        self.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Message)?;
+            encoder.write(vint64::encode(length as u64))?;
 
-        // sequence header (type + length)
-        self.write(vint64::encode(
-            (length as u64) << 4 | WireType::Message as u64,
-        ))?;
+            let start = encoder.length;
+            let end = start.checked_add(length).unwrap();
+            let mut nested = Encoder {
+                ordered: encoder.ordered,
+                ..Encoder::new(&mut encoder.buffer[start..end])
+            };
 
-        let orig_length = self.length;
+            // Running out of space means the function wrote too much
+            write_body(&mut nested).map_err(|err| {
+                match err.kind() {
+                    error::Kind::BufferTooSmall { .. } => err.with_kind(error::Kind::Length),
+                    _ => err,
+                }
+                .nested_in(start, tag)
+            })?;
 
-        for message in messages {
-            let encoded_len = message.encoded_len();
-            self.write(vint64::encode(encoded_len as u64))?;
+            nested.check_abandoned()?;
 
-            // Ensure there's remaining space in the buffer
-            if encoded_len > self.buffer.len().checked_sub(self.length).unwrap() {
+            if nested.length != length {
                 return Err(error::Kind::Length.into());
             }
 
-            let new_length = self.length.checked_add(encoded_len).unwrap();
-            message.encode(&mut self.buffer[self.length..new_length])?;
-            self.length = new_length;
-        }
+            encoder.length = end;
+            Ok(())
+        })
+    }
 
-        // Ensure we wrote the expected number of bytes
-        debug_assert_eq!(length, self.length.checked_sub(orig_length).unwrap());
-        Ok(())
+    /// Write a message field containing the given encoded message body,
+    /// e.g. a [`RawMessage`] which was decoded without decoding its body
+    pub fn raw_message(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        message: RawMessage<'_>,
+    ) -> Result<(), Error> {
+        self.write_field(tag, length::raw_message(tag, message), |encoder| {
+            encoder.write_header(tag, critical, WireType::Message)?;
+            encoder.write_value(message.as_bytes())
+        })
+    }
+
+    /// Write a sequence of messages (nested inside of a field).
+    ///
+    /// The sequence's length precedes its elements, so it's computed with a
+    /// pre-pass over the messages using [`Message::encoded_len`] (which is
+    /// why the iterator must be [`Clone`]), before they're each encoded
+    /// directly into the buffer.
+    pub fn message_seq<'m, M>(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        messages: impl Iterator<Item = &'m M> + Clone,
+    ) -> Result<(), Error>
+    where
+        M: Message + ?Sized + 'm,
+    {
+        let body_len = length::message_seq_body(messages.clone());
+        let field_len = length::sequence(tag, WireType::Message, body_len);
+
+        self.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Sequence)?;
+            encoder.write_sequence_header(WireType::Message, body_len)?;
+
+            let orig_length = encoder.length;
+
+            for (index, message) in messages.enumerate() {
+                encoder
+                    .write_message(tag, message)
+                    .map_err(|err| err.with_index(index))?;
+            }
+
+            // Ensure we wrote the expected number of bytes
+            debug_assert_eq!(body_len, encoder.length.checked_sub(orig_length).unwrap());
+            Ok(())
+        })
+    }
+
+    /// Write a map as a sequence of key/value entry messages, in key order
+    /// (see the [`map`][`crate::map`] module)
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn map<K, V>(&mut self, tag: Tag, critical: bool, map: &BTreeMap<K, V>) -> Result<(), Error>
+    where
+        K: Encodable,
+        V: Encodable,
+    {
+        let body_len = length::map_body(map);
+        let field_len = length::sequence(tag, WireType::Message, body_len);
+
+        self.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Sequence)?;
+            encoder.write_sequence_header(WireType::Message, body_len)?;
+
+            // Each entry is a nested message with its own tag order
+            for (index, (key, value)) in map.iter().enumerate() {
+                encoder.write(vint64::encode(map::entry_len(key, value) as u64))?;
+                encoder.last_tag = None;
+                key.encode_field(encoder, map::KEY_TAG, false)
+                    .and_then(|()| value.encode_field(encoder, map::VALUE_TAG, false))
+                    .map_err(|err| err.nested_in(0, tag).with_index(index))?;
+            }
+
+            encoder.last_tag = Some(tag);
+            Ok(())
+        })
+    }
+
+    /// Write a sequence of `uint64` values
+    pub fn uint64_seq(&mut self, tag: Tag, critical: bool, values: &[u64]) -> Result<(), Error> {
+        self.uint64_seq_iter(tag, critical, values)
+    }
+
+    /// Write a sequence of `uint64` values from an iterator, which is
+    /// iterated twice (to compute the sequence's length, then encode it)
+    pub(crate) fn uint64_seq_iter<'v>(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        values: impl IntoIterator<Item = &'v u64> + Clone,
+    ) -> Result<(), Error> {
+        let body_len = length::uint64_seq_body(values.clone());
+        let field_len = length::sequence(tag, WireType::UInt64, body_len);
+
+        self.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Sequence)?;
+            encoder.write_sequence_header(WireType::UInt64, body_len)?;
+
+            for &value in values {
+                encoder.write(vint64::encode(value))?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Write a sequence of `sint64` values
+    pub fn sint64_seq(&mut self, tag: Tag, critical: bool, values: &[i64]) -> Result<(), Error> {
+        self.sint64_seq_iter(tag, critical, values)
+    }
+
+    /// Write a sequence of `sint64` values from an iterator (see
+    /// [`Encoder::uint64_seq_iter`])
+    pub(crate) fn sint64_seq_iter<'v>(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        values: impl IntoIterator<Item = &'v i64> + Clone,
+    ) -> Result<(), Error> {
+        let body_len = length::sint64_seq_body(values.clone());
+        let field_len = length::sequence(tag, WireType::SInt64, body_len);
+
+        self.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Sequence)?;
+            encoder.write_sequence_header(WireType::SInt64, body_len)?;
+
+            for &value in values {
+                encoder.write(vint64::encode(zigzag::encode(value)))?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Write a sequence of sequences of `uint64` values
+    pub fn uint64_seq_seq<S>(&mut self, tag: Tag, critical: bool, seqs: &[S]) -> Result<(), Error>
+    where
+        S: AsRef<[u64]>,
+    {
+        self.write_field(tag, length::uint64_seq_seq(tag, seqs), |encoder| {
+            let body_len = seqs
+                .iter()
+                .map(|seq| {
+                    length::nested_sequence(WireType::UInt64, length::uint64_seq_body(seq.as_ref()))
+                })
+                .sum();
+
+            encoder.write_header(tag, critical, WireType::Sequence)?;
+            encoder.write_sequence_header(WireType::Sequence, body_len)?;
+
+            for seq in seqs {
+                let seq = seq.as_ref();
+                encoder.write_sequence_header(WireType::UInt64, length::uint64_seq_body(seq))?;
+
+                for &value in seq {
+                    encoder.write(vint64::encode(value))?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Write a sequence of sequences of `sint64` values
+    pub fn sint64_seq_seq<S>(&mut self, tag: Tag, critical: bool, seqs: &[S]) -> Result<(), Error>
+    where
+        S: AsRef<[i64]>,
+    {
+        self.write_field(tag, length::sint64_seq_seq(tag, seqs), |encoder| {
+            let body_len = seqs
+                .iter()
+                .map(|seq| {
+                    length::nested_sequence(WireType::SInt64, length::sint64_seq_body(seq.as_ref()))
+                })
+                .sum();
+
+            encoder.write_header(tag, critical, WireType::Sequence)?;
+            encoder.write_sequence_header(WireType::Sequence, body_len)?;
+
+            for seq in seqs {
+                let seq = seq.as_ref();
+                encoder.write_sequence_header(WireType::SInt64, length::sint64_seq_body(seq))?;
+
+                for &value in seq {
+                    encoder.write(vint64::encode(zigzag::encode(value)))?;
+                }
+            }
+
+            Ok(())
+        })
     }
 
     /// Write a field containing bytes
     pub fn bytes(&mut self, tag: Tag, critical: bool, bytes: &[u8]) -> Result<(), Error> {
-        self.write_header(tag, critical, WireType::Bytes)?;
-        self.write_value(bytes)
+        self.write_field(tag, length::bytes(tag, bytes), |encoder| {
+            encoder.write_header(tag, critical, WireType::Bytes)?;
+            encoder.write_value(bytes)
+        })
+    }
+
+    /// Write a sequence of `bytes` values
+    pub fn bytes_seq<B>(&mut self, tag: Tag, critical: bool, values: &[B]) -> Result<(), Error>
+    where
+        B: AsRef<[u8]>,
+    {
+        self.bytes_seq_iter(tag, critical, values)
+    }
+
+    /// Write a sequence of `bytes` values from an iterator (see
+    /// [`Encoder::uint64_seq_iter`])
+    pub(crate) fn bytes_seq_iter<B>(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        values: impl IntoIterator<Item = B> + Clone,
+    ) -> Result<(), Error>
+    where
+        B: AsRef<[u8]>,
+    {
+        let body_len = length::bytes_seq_body(values.clone());
+        let field_len = length::sequence(tag, WireType::Bytes, body_len);
+
+        self.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Sequence)?;
+            encoder.write_sequence_header(WireType::Bytes, body_len)?;
+
+            for value in values {
+                encoder.write_value(value.as_ref())?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Write a sequence of `string` values
+    pub fn string_seq<S>(&mut self, tag: Tag, critical: bool, values: &[S]) -> Result<(), Error>
+    where
+        S: AsRef<str>,
+    {
+        self.string_seq_iter(tag, critical, values)
+    }
+
+    /// Write a sequence of `string` values from an iterator (see
+    /// [`Encoder::uint64_seq_iter`])
+    pub(crate) fn string_seq_iter<S>(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        values: impl IntoIterator<Item = S> + Clone,
+    ) -> Result<(), Error>
+    where
+        S: AsRef<str>,
+    {
+        for (index, value) in values.clone().into_iter().enumerate() {
+            string::ensure_canonical(value.as_ref())
+                .map_err(|err| err.with_index(index).in_field(self.length, tag))?;
+        }
+
+        let body_len = length::string_seq_body(values.clone());
+        let field_len = length::sequence(tag, WireType::String, body_len);
+
+        self.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Sequence)?;
+            encoder.write_sequence_header(WireType::String, body_len)?;
+
+            for value in values {
+                encoder.write_value(value.as_ref().as_bytes())?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Write a field containing bytes of the given length, which are
+    /// written incrementally with the returned [`BytesWriter`] (e.g. as
+    /// they're generated) and must total exactly that length.
+    ///
+    /// If the [`BytesWriter`] is dropped without being
+    /// [finished][`BytesWriter::finish`], the field is cleared and the
+    /// encoder is poisoned: writing further fields and
+    /// [`Encoder::try_finish`] fail with [`error::Kind::Abandoned`] (and
+    /// [`Encoder::finish`] panics) until it's
+    /// [rolled back][`Encoder::rollback`] to before the field.
+    pub fn bytes_writer(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        length: usize,
+    ) -> Result<BytesWriter<'_, 'a>, Error> {
+        let checkpoint = self.checkpoint();
+        let field_len = length::dynamically_sized(tag, WireType::Bytes, length);
+
+        self.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Bytes)?;
+            encoder.write(vint64::encode(length as u64))
+        })?;
+
+        Ok(BytesWriter::new(self, checkpoint, tag, length))
     }
 
     /// Write a field containing a string
     pub fn string(&mut self, tag: Tag, critical: bool, string: &str) -> Result<(), Error> {
-        string::ensure_canonical(string)?;
-        self.write_header(tag, critical, WireType::String)?;
-        self.write_value(string.as_bytes())
+        string::ensure_canonical(string).map_err(|err| err.in_field(self.length, tag))?;
+
+        self.write_field(tag, length::string(tag, string), |encoder| {
+            encoder.write_header(tag, critical, WireType::String)?;
+            encoder.write_value(string.as_bytes())
+        })
+    }
+
+    /// Write a field unknown to the message's schema, exactly as it was
+    /// originally serialized
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn unknown_field(&mut self, field: &UnknownField) -> Result<(), Error> {
+        let field_len = field.header.encoded_len() + field.value.len();
+
+        self.write_field(field.tag(), field_len, |encoder| {
+            encoder.check_order(field.tag())?;
+            encoder.write(field.header.encode())?;
+            encoder.write(&field.value)?;
+            encoder.last_tag = Some(field.tag());
+            Ok(())
+        })
+    }
+
+    /// Take a checkpoint of the encoder's position, which it can be rolled
+    /// back to with [`Encoder::rollback`]
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            buffer: self.buffer.as_ptr() as usize,
+            length: self.length,
+            last_tag: self.last_tag,
+            generation: self.generation,
+        }
+    }
+
+    /// Roll back to the given checkpoint, clearing the fields written since
+    /// it was taken as if they'd never been written (including the tag order
+    /// they established).
+    ///
+    /// Nested messages are written by encoders of their own (e.g. the one
+    /// passed to [`Encoder::message_with`]'s function), so rolling back
+    /// can't stop partway through one: rolling back to a checkpoint taken
+    /// before a nested message field removes the whole field.
+    ///
+    /// Rolling back invalidates every checkpoint taken before it (including
+    /// the one rolled back to), since the fields they were taken between may
+    /// have been overwritten: take a new checkpoint to roll back again.
+    ///
+    /// Returns [`error::Kind::Checkpoint`] if the checkpoint was taken from
+    /// another encoder (including a nested one), or was invalidated by an
+    /// earlier rollback.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) -> Result<(), Error> {
+        if checkpoint.buffer != self.buffer.as_ptr() as usize
+            || checkpoint.generation != self.generation
+            || checkpoint.length > self.length
+        {
+            return Err(error::Kind::Checkpoint.into());
+        }
+
+        self.truncate(checkpoint, self.length);
+        self.generation = self.generation.checked_add(1).unwrap();
+
+        // Rolling back to before an abandoned field recovers the encoder
+        if let Some((_, start)) = self.abandoned {
+            if checkpoint.length <= start {
+                self.abandoned = None;
+            }
+        }
+
+        Ok(())
     }
 
     /// Finish constructing a message, returning a slice of the buffer
-    /// containing the serialized message
+    /// containing the serialized message.
+    ///
+    /// Panics if the encoder was poisoned by abandoning a
+    /// [`BytesWriter`] (see [`Encoder::bytes_writer`]): use
+    /// [`Encoder::try_finish`] if that's possible.
     pub fn finish(self) -> &'a [u8] {
-        &self.buffer[..self.length]
+        self.try_finish().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Finish constructing a message, returning a slice of the buffer
+    /// containing the serialized message, or [`error::Kind::Abandoned`] if
+    /// the encoder was poisoned by abandoning a [`BytesWriter`] (see
+    /// [`Encoder::bytes_writer`]).
+    pub fn try_finish(self) -> Result<&'a [u8], Error> {
+        self.check_abandoned()?;
+        Ok(&self.buffer[..self.length])
+    }
+
+    /// Write a field of the given length with the given function.
+    ///
+    /// Fields which don't fit in the remaining space are rejected with
+    /// [`error::Kind::BufferTooSmall`] before any of them is written, so a
+    /// failed encode leaves the buffer truncated at a field boundary.
+    ///
+    /// Errors are annotated with the field's tag and offset, unless they
+    /// occurred within a nested message.
+    fn write_field(
+        &mut self,
+        tag: Tag,
+        field_len: usize,
+        write: impl FnOnce(&mut Self) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let start = self.length;
+        self.check_abandoned()
+            .map_err(|err| err.in_field(start, tag))?;
+
+        let checkpoint = self.checkpoint();
+        let needed = start.checked_add(field_len).unwrap();
+
+        if needed > self.buffer.len() {
+            return Err(Error::from(error::Kind::BufferTooSmall { needed }).in_field(start, tag));
+        }
+
+        write(self).map_err(|err| {
+            self.abort_field(checkpoint, field_len, err)
+                .in_field(start, tag)
+        })
+    }
+
+    /// Clear a field of the given length which failed to be written
+    /// partway through (e.g. because a nested message failed to encode),
+    /// rolling back to the checkpoint taken before it began.
+    ///
+    /// If the field didn't fit, the error reports the length needed for it.
+    fn abort_field(&mut self, checkpoint: Checkpoint, field_len: usize, err: Error) -> Error {
+        let needed = checkpoint.length.checked_add(field_len).unwrap();
+        self.truncate(checkpoint, cmp::min(needed, self.buffer.len()));
+
+        match err.kind() {
+            error::Kind::BufferTooSmall { .. } => {
+                err.with_kind(error::Kind::BufferTooSmall { needed })
+            }
+            _ => err,
+        }
+    }
+
+    /// Restore the given checkpoint, zeroing the buffer from its position up
+    /// to the given offset
+    fn truncate(&mut self, checkpoint: Checkpoint, end: usize) {
+        for byte in &mut self.buffer[checkpoint.length..end] {
+            *byte = 0;
+        }
+
+        self.length = checkpoint.length;
+        self.last_tag = checkpoint.last_tag;
     }
 
     /// Write a field header to the underlying buffer
     fn write_header(&mut self, tag: Tag, critical: bool, wire_type: WireType) -> Result<(), Error> {
-        self.write(Header::new(tag, critical, wire_type).encode())
+        self.check_order(tag)?;
+        self.write(Header::new(tag, critical, wire_type).encode())?;
+        self.last_tag = Some(tag);
+        Ok(())
+    }
+
+    /// Ensure the encoder hasn't been poisoned by an abandoned field
+    fn check_abandoned(&self) -> Result<(), Error> {
+        match self.abandoned {
+            Some((tag, _)) => Err(error::Kind::Abandoned { tag }.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Ensure a field with the given tag can be written after the last one
+    fn check_order(&self, tag: Tag) -> Result<(), Error> {
+        self.check_abandoned()?;
+
+        match self.last_tag {
+            Some(prev) if self.ordered && tag <= prev => {
+                Err(error::Kind::TagOrder { prev, next: tag }.into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Write a sequence header (element type + body length) to the
+    /// underlying buffer
+    fn write_sequence_header(&mut self, wire_type: WireType, length: usize) -> Result<(), Error> {
+        self.write(vint64::encode(spec::pack_sequence_header(
+            length as u64,
+            wire_type.to_u8(),
+        )))
+    }
+
+    /// Write a length-prefixed nested message in the field with the given
+    /// tag to the underlying buffer, returning the range of the buffer
+    /// containing the message body.
+    ///
+    /// The body is encoded before its length prefix, so the lengths of
+    /// nested messages aren't recomputed at every level of nesting: it's
+    /// written after space for a 1-byte prefix, then moved along if the
+    /// prefix turns out to be longer.
+    fn write_message<M>(&mut self, tag: Tag, message: &M) -> Result<Range<usize>, Error>
+    where
+        M: Message + ?Sized,
+    {
+        self.write_message_body(tag, |buffer| message.encode(buffer).map(<[u8]>::len))
+    }
+
+    /// Write the body of a nested message along with its length prefix (see
+    /// [`Encoder::write_message`]), calling the given function to encode it
+    /// at the start of a buffer and return its length
+    fn write_message_body<F>(&mut self, tag: Tag, encode: F) -> Result<Range<usize>, Error>
+    where
+        F: FnOnce(&mut [u8]) -> Result<usize, Error>,
+    {
+        let prefix_start = self.length;
+
+        // Ensure there's remaining space in the buffer for the prefix
+        if prefix_start >= self.buffer.len() {
+            return Err(error::Kind::BufferTooSmall {
+                needed: prefix_start + 1,
+            }
+            .into());
+        }
+
+        let body_start = prefix_start.checked_add(1).unwrap();
+        let encoded_len =
+            encode(&mut self.buffer[body_start..]).map_err(|err| err.nested_in(body_start, tag))?;
+        let prefix = vint64::encode(encoded_len as u64);
+        let start = prefix_start.checked_add(prefix.as_ref().len()).unwrap();
+        let end = start.checked_add(encoded_len).unwrap();
+
+        // Ensure there's remaining space in the buffer for a longer prefix
+        if end > self.buffer.len() {
+            return Err(error::Kind::BufferTooSmall { needed: end }.into());
+        }
+
+        self.buffer
+            .copy_within(body_start..body_start + encoded_len, start);
+        self.buffer[prefix_start..start].copy_from_slice(prefix.as_ref());
+        self.length = end;
+
+        Ok(start..end)
     }
 
     /// Write a dynamically sized value to the underlying buffer
@@ -130,12 +782,13 @@ impl<'a> Encoder<'a> {
     fn write(&mut self, bytes: impl AsRef<[u8]>) -> Result<(), Error> {
         let bytes = bytes.as_ref();
 
+        let new_length = self.length.checked_add(bytes.len()).unwrap();
+
         // Ensure there's remaining space in the buffer
-        if bytes.len() > (self.buffer.len() - self.length) {
-            return Err(error::Kind::Length.into());
+        if new_length > self.buffer.len() {
+            return Err(error::Kind::BufferTooSmall { needed: new_length }.into());
         }
 
-        let new_length = self.length.checked_add(bytes.len()).unwrap();
         self.buffer[self.length..new_length].copy_from_slice(bytes);
         self.length = new_length;
 
@@ -146,7 +799,14 @@ impl<'a> Encoder<'a> {
 #[cfg(all(test, feature = "sha2"))]
 mod tests {
     use super::Encoder;
-    use crate::{decoder::Decodable, field::WireType};
+    use crate::{
+        decoder::Decodable,
+        error::Kind,
+        field::{self, Path, Tag, WireType},
+        Error, Message,
+    };
+    use core::cell::Cell;
+    use digest::Digest;
 
     // TODO(tarcieri): rewrite tests with `crate::Decoder`
     type Decoder = crate::decoder::message::Decoder<sha2::Sha256>;
@@ -154,43 +814,62 @@ mod tests {
     const EXAMPLE_BYTES: &[u8] = b"foobar";
     const EXAMPLE_STRING: &str = "baz";
 
+    #[test]
+    fn non_ascii_string() {
+        // Both the composed and decomposed forms of "é" are rejected, so
+        // canonically equivalent strings can't be serialized differently
+        for string in &["\u{e9}", "e\u{301}"] {
+            let mut buffer = [0u8; 16];
+            let mut encoder = Encoder::new(&mut buffer);
+            let err = encoder
+                .string(Tag::new_const(1), false, string)
+                .err()
+                .unwrap();
+            assert_eq!(err.kind(), Kind::UnicodeNormalization);
+        }
+    }
+
     #[test]
     fn encode_then_decode() {
         let mut buffer = [0u8; 1024];
         let mut encoder = Encoder::new(&mut buffer);
 
-        encoder.uint64(1, false, 42).unwrap();
-        encoder.sint64(2, false, -1).unwrap();
-        encoder.bytes(3, false, EXAMPLE_BYTES).unwrap();
-        encoder.string(4, false, EXAMPLE_STRING).unwrap();
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+        encoder.sint64(Tag::new_const(2), false, -1).unwrap();
+        encoder
+            .bytes(Tag::new_const(3), false, EXAMPLE_BYTES)
+            .unwrap();
+        encoder
+            .string(Tag::new_const(4), false, EXAMPLE_STRING)
+            .unwrap();
 
         let length = encoder.finish().len();
         let mut message = &buffer[..length];
 
         let mut decoder = Decoder::new();
         let header = decoder.decode_header(&mut message).unwrap();
-        assert_eq!(header.tag, 1);
+        assert_eq!(header.tag, Tag::new_const(1));
         assert_eq!(header.wire_type, WireType::UInt64);
 
         let value = decoder.decode_uint64(&mut message).unwrap();
         assert_eq!(value, 42);
 
         let header = decoder.decode_header(&mut message).unwrap();
-        assert_eq!(header.tag, 2);
+        assert_eq!(header.tag, Tag::new_const(2));
         assert_eq!(header.wire_type, WireType::SInt64);
 
         let value = decoder.decode_sint64(&mut message).unwrap();
         assert_eq!(value, -1);
 
         let header = decoder.decode_header(&mut message).unwrap();
-        assert_eq!(header.tag, 3);
+        assert_eq!(header.tag, Tag::new_const(3));
         assert_eq!(header.wire_type, WireType::Bytes);
 
         let bytes = decoder.decode_bytes(&mut message).unwrap();
         assert_eq!(bytes, EXAMPLE_BYTES);
 
         let header = decoder.decode_header(&mut message).unwrap();
-        assert_eq!(header.tag, 4);
+        assert_eq!(header.tag, Tag::new_const(4));
         assert_eq!(header.wire_type, WireType::String);
 
         let string = decoder.decode_string(&mut message).unwrap();
@@ -198,4 +877,440 @@ mod tests {
 
         assert!(message.is_empty());
     }
+
+    #[test]
+    fn sequences() {
+        let mut buffer = [0u8; 1024];
+        let mut encoder = Encoder::new(&mut buffer);
+
+        let values: &[&[u8]] = &[EXAMPLE_BYTES, b"", EXAMPLE_STRING.as_bytes()];
+        encoder.bytes_seq(Tag::new_const(1), false, values).unwrap();
+
+        // Messages can be written from any (cloneable) iterator
+        let calls = Cell::new(0);
+        let leaf = Chain {
+            child: None,
+            calls: &calls,
+        };
+        encoder
+            .message_seq(Tag::new_const(2), false, (0..3).map(|_| &leaf))
+            .unwrap();
+
+        let length = encoder.finish().len();
+        assert_eq!(
+            length,
+            field::length::bytes_seq(Tag::new_const(1), values)
+                + field::length::message_seq(Tag::new_const(2), [&leaf; 3].iter().copied())
+        );
+
+        let mut message = &buffer[..length];
+        let header = field::Header::from(vint64::decode(&mut message).unwrap());
+        assert_eq!(header.tag, Tag::new_const(1));
+        assert_eq!(header.wire_type, WireType::Sequence);
+
+        let seq_header = vint64::decode(&mut message).unwrap();
+        assert_eq!(WireType::from_unmasked(seq_header), WireType::Bytes);
+
+        let body_len = (seq_header >> 4) as usize;
+        let mut seq_decoder =
+            crate::decoder::sequence::Decoder::<sha2::Sha256>::new(WireType::Bytes, body_len);
+
+        for &value in values {
+            assert_eq!(seq_decoder.decode_bytes(&mut message).unwrap(), value);
+        }
+
+        let header = field::Header::from(vint64::decode(&mut message).unwrap());
+        assert_eq!(header.tag, Tag::new_const(2));
+        assert_eq!(header.wire_type, WireType::Sequence);
+
+        let seq_header = vint64::decode(&mut message).unwrap();
+        assert_eq!(WireType::from_unmasked(seq_header), WireType::Message);
+        assert_eq!((seq_header >> 4) as usize, message.len());
+
+        for _ in 0..3 {
+            let len = vint64::decode(&mut message).unwrap() as usize;
+            assert_eq!(len, leaf.encoded_len());
+            message = &message[len..];
+        }
+
+        assert!(message.is_empty());
+    }
+
+    #[test]
+    fn message_with() {
+        let calls = Cell::new(0);
+        let leaf = Chain {
+            child: None,
+            calls: &calls,
+        };
+
+        let mut expected = [0u8; 256];
+        let mut encoder = Encoder::new(&mut expected);
+        encoder.message(Tag::new_const(1), false, &leaf).unwrap();
+        let expected_len = encoder.finish().len();
+
+        let mut buffer = [0u8; 256];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder
+            .message_with(Tag::new_const(1), false, leaf.encoded_len(), |nested| {
+                nested.bytes(Tag::new_const(2), false, &[0; 200])
+            })
+            .unwrap();
+        assert_eq!(encoder.finish(), &expected[..expected_len]);
+    }
+
+    #[test]
+    fn message_with_wrong_length() {
+        let mut buffer = [0xFFu8; 64];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+        let start = field::length::uint64(Tag::new_const(1), 42);
+
+        // Too many bytes
+        let err = encoder
+            .message_with(Tag::new_const(2), false, 4, |nested| {
+                nested.bytes(Tag::new_const(1), false, EXAMPLE_BYTES)
+            })
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), Kind::Length);
+
+        // Too few bytes
+        let err = encoder
+            .message_with(Tag::new_const(2), false, 16, |nested| {
+                nested.bytes(Tag::new_const(1), false, EXAMPLE_BYTES)
+            })
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), Kind::Length);
+
+        // Errors from the function
+        let err = encoder
+            .message_with(Tag::new_const(2), false, 16, |nested| {
+                nested.string(Tag::new_const(1), false, "\u{e9}")
+            })
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), Kind::UnicodeNormalization);
+
+        // Each failed field was cleared, and the encoder can carry on
+        let field_len = field::length::dynamically_sized(Tag::new_const(2), WireType::Message, 16);
+        assert!(buffer[start..][..field_len].iter().all(|&byte| byte == 0));
+        assert!(buffer[start + field_len..].iter().all(|&byte| byte == 0xFF));
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+        encoder.bool(Tag::new_const(3), false, true).unwrap();
+        assert_eq!(
+            encoder.finish().len(),
+            start + field::length::bool(Tag::new_const(3))
+        );
+    }
+
+    /// Chain of nested messages, `{ 1: message(...) }`, ending with
+    /// `{ 2: bytes([0; 200]) }`, which counts calls to `encoded_len`
+    struct Chain<'a> {
+        child: Option<&'a Chain<'a>>,
+        calls: &'a Cell<usize>,
+    }
+
+    impl Message for Chain<'_> {
+        fn decode<D: Digest>(_: &mut crate::decoder::Decoder<D>, _: &[u8]) -> Result<Self, Error> {
+            unimplemented!()
+        }
+
+        fn encode<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], Error> {
+            let mut encoder = Encoder::new(buffer);
+
+            match self.child {
+                Some(child) => encoder.message(Tag::new_const(1), false, child)?,
+                None => encoder.bytes(Tag::new_const(2), false, &[0; 200])?,
+            }
+
+            Ok(encoder.finish())
+        }
+
+        fn encoded_len(&self) -> usize {
+            self.calls.set(self.calls.get() + 1);
+
+            match self.child {
+                Some(child) => field::length::message(Tag::new_const(1), child),
+                None => field::length::bytes(Tag::new_const(2), &[0; 200]),
+            }
+        }
+    }
+
+    #[test]
+    fn nested_lengths_computed_once() {
+        let calls = Cell::new(0);
+        let leaf = Chain {
+            child: None,
+            calls: &calls,
+        };
+        let middle = Chain {
+            child: Some(&leaf),
+            calls: &calls,
+        };
+        let root = Chain {
+            child: Some(&middle),
+            calls: &calls,
+        };
+
+        let len = root.encoded_len();
+        assert_eq!(calls.get(), 3);
+
+        // Every message is longer than 127 bytes, so has a 2-byte prefix
+        let mut buffer = [0u8; 256];
+        let mut input = root.encode(&mut buffer[..len]).unwrap();
+        assert_eq!(input.len(), len);
+        assert_eq!(calls.get(), 3);
+
+        for _ in 0..2 {
+            let nested = crate::Decoder::new()
+                .decode_message_raw(Tag::new_const(1), &mut input)
+                .unwrap();
+            assert!(input.is_empty());
+            input = nested.as_bytes();
+        }
+
+        let bytes = crate::Decoder::new()
+            .decode_bytes(Tag::new_const(2), &mut input)
+            .unwrap();
+        assert_eq!(bytes, &[0; 200][..]);
+
+        // Too small a buffer is rejected with the length needed, and the
+        // partially encoded fields are cleared
+        let mut buffer = [0xFFu8; 256];
+        let err = root.encode(&mut buffer[..len - 1]).err().unwrap();
+        assert_eq!(err.kind(), Kind::BufferTooSmall { needed: len });
+        assert!(buffer[..len - 1].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn tag_order() {
+        let mut buffer = [0u8; 64];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(5), false, 42).unwrap();
+
+        let err = encoder.uint64(Tag::new_const(3), false, 42).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            Kind::TagOrder {
+                prev: Tag::new_const(5),
+                next: Tag::new_const(3)
+            }
+        );
+        assert_eq!(err.tag(), Some(Tag::new_const(3)));
+        assert_eq!(
+            err.position(),
+            Some(field::length::uint64(Tag::new_const(5), 42))
+        );
+
+        // Repeated tags are also rejected
+        let err = encoder
+            .bytes(Tag::new_const(5), false, EXAMPLE_BYTES)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.kind(),
+            Kind::TagOrder {
+                prev: Tag::new_const(5),
+                next: Tag::new_const(5)
+            }
+        );
+
+        encoder.bool(Tag::new_const(6), false, true).unwrap();
+        assert_eq!(
+            encoder.finish().len(),
+            field::length::uint64(Tag::new_const(5), 42) + field::length::bool(Tag::new_const(6))
+        );
+
+        // ...unless the encoder is unordered
+        let mut encoder = Encoder::unordered(&mut buffer);
+        encoder.uint64(Tag::new_const(5), false, 42).unwrap();
+        encoder.uint64(Tag::new_const(3), false, 42).unwrap();
+        encoder.uint64(Tag::new_const(3), false, 42).unwrap();
+        assert_eq!(
+            encoder.finish().len(),
+            3 * field::length::uint64(Tag::new_const(3), 42)
+        );
+    }
+
+    #[test]
+    fn rollback() {
+        let mut buffer = [0xFFu8; 64];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+
+        let checkpoint = encoder.checkpoint();
+        encoder
+            .bytes(Tag::new_const(2), false, EXAMPLE_BYTES)
+            .unwrap();
+        encoder
+            .uint64_seq(Tag::new_const(3), false, &[1, 2, 3])
+            .unwrap();
+        let later = encoder.checkpoint();
+        encoder.rollback(checkpoint).unwrap();
+
+        // Tags after the checkpoint can be written again
+        encoder
+            .string(Tag::new_const(2), false, EXAMPLE_STRING)
+            .unwrap();
+
+        // Checkpoints taken before a rollback are rejected
+        let err = encoder.rollback(later).err().unwrap();
+        assert_eq!(err.kind(), Kind::Checkpoint);
+
+        let len = encoder.finish().len();
+        assert_eq!(
+            len,
+            field::length::uint64(Tag::new_const(1), 42)
+                + field::length::string(Tag::new_const(2), EXAMPLE_STRING)
+        );
+        assert!(buffer[len..].iter().all(|&byte| byte == 0 || byte == 0xFF));
+    }
+
+    #[test]
+    fn rollback_stale_checkpoint() {
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+
+        let a = encoder.checkpoint();
+        encoder.uint64(Tag::new_const(2), false, 42).unwrap();
+        let b = encoder.checkpoint();
+        encoder.rollback(a).unwrap();
+
+        // `b` is within the longer field written after rolling back
+        encoder.bytes(Tag::new_const(2), false, b"IIII").unwrap();
+        let err = encoder.rollback(b).err().unwrap();
+        assert_eq!(err.kind(), Kind::Checkpoint);
+
+        // So is `a`, which has already been rolled back to
+        let err = encoder.rollback(a).err().unwrap();
+        assert_eq!(err.kind(), Kind::Checkpoint);
+
+        assert_eq!(encoder.finish(), &[73, 9, 73, 73, 73, 73]);
+    }
+
+    #[test]
+    fn rollback_nested_message() {
+        let mut buffer = [0u8; 64];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+
+        let checkpoint = encoder.checkpoint();
+        let mut nested_checkpoint = None;
+        let length =
+            field::length::bool(Tag::new_const(1)) + field::length::bool(Tag::new_const(2));
+
+        encoder
+            .message_with(Tag::new_const(2), false, length, |nested| {
+                nested.bool(Tag::new_const(1), false, true)?;
+                nested_checkpoint = Some(nested.checkpoint());
+                nested.bool(Tag::new_const(2), false, false)
+            })
+            .unwrap();
+
+        // The nested encoder's checkpoint can't be used with its parent
+        let err = encoder.rollback(nested_checkpoint.unwrap()).err().unwrap();
+        assert_eq!(err.kind(), Kind::Checkpoint);
+
+        // Rolling back before the message removes the whole field
+        encoder.rollback(checkpoint).unwrap();
+        assert_eq!(
+            encoder.finish().len(),
+            field::length::uint64(Tag::new_const(1), 42)
+        );
+        assert!(buffer[field::length::uint64(Tag::new_const(1), 42)..]
+            .iter()
+            .all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn optional_trailer() {
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder
+            .bytes(Tag::new_const(1), false, EXAMPLE_BYTES)
+            .unwrap();
+
+        // Write a trailer of several fields only if all of them fit
+        let checkpoint = encoder.checkpoint();
+        let result = encoder
+            .uint64(Tag::new_const(2), false, 42)
+            .and_then(|()| encoder.bytes(Tag::new_const(3), false, EXAMPLE_BYTES));
+
+        assert!(matches!(
+            result.err().unwrap().kind(),
+            Kind::BufferTooSmall { .. }
+        ));
+        encoder.rollback(checkpoint).unwrap();
+
+        assert_eq!(
+            encoder.finish().len(),
+            field::length::bytes(Tag::new_const(1), EXAMPLE_BYTES)
+        );
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let mut buffer = [0xFFu8; 8];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+
+        let err = encoder
+            .bytes(Tag::new_const(2), false, EXAMPLE_BYTES)
+            .err()
+            .unwrap();
+        let needed = field::length::uint64(Tag::new_const(1), 42)
+            + field::length::bytes(Tag::new_const(2), EXAMPLE_BYTES);
+        assert_eq!(err.kind(), Kind::BufferTooSmall { needed });
+        assert_eq!(err.tag(), Some(Tag::new_const(2)));
+        assert_eq!(
+            err.position(),
+            Some(field::length::uint64(Tag::new_const(1), 42))
+        );
+
+        // The encoder is left at the field boundary, and can still encode
+        // fields which fit
+        encoder.bool(Tag::new_const(3), false, true).unwrap();
+        let len = encoder.finish().len();
+        assert_eq!(
+            len,
+            field::length::uint64(Tag::new_const(1), 42) + field::length::bool(Tag::new_const(3))
+        );
+
+        // Nothing past the encoded fields was written
+        assert!(buffer[len..].iter().all(|&byte| byte == 0xFF));
+    }
+
+    #[test]
+    fn nested_error_context() {
+        let mut buffer = [0u8; 64];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+
+        let length = 2 * field::length::uint64(Tag::new_const(3), 42);
+        let err = encoder
+            .message_with(Tag::new_const(2), false, length, |nested| {
+                nested.uint64(Tag::new_const(3), false, 42)?;
+                nested.uint64(Tag::new_const(3), false, 42)
+            })
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            Kind::TagOrder {
+                prev: Tag::new_const(3),
+                next: Tag::new_const(3)
+            }
+        );
+        assert_eq!(err.tag(), Some(Tag::new_const(3)));
+        assert_eq!(err.path(), Path::new(&[Tag::new_const(2)]));
+
+        // The offset is within the outermost message
+        let body_start = field::length::uint64(Tag::new_const(1), 42) + 2;
+        let position = body_start + field::length::uint64(Tag::new_const(3), 42);
+        assert_eq!(err.position(), Some(position));
+    }
 }
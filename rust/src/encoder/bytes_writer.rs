@@ -0,0 +1,302 @@
+//! Incremental writer for the value of a `bytes` field
+
+use super::{Checkpoint, Encoder};
+use crate::{
+    error::{self, Error},
+    field::Tag,
+};
+
+#[cfg(feature = "std")]
+use std::io;
+
+/// Writer for the value of a `bytes` field whose length is known up front,
+/// returned by [`Encoder::bytes_writer`].
+///
+/// Bytes are appended with [`BytesWriter::push`] (or [`io::Write`] with the
+/// `std` feature), and exactly the field's length must be written before
+/// it's [finished][`BytesWriter::finish`]. Dropping it before then clears
+/// the field and poisons the encoder.
+pub struct BytesWriter<'e, 'a> {
+    /// Encoder the field is being written to
+    encoder: &'e mut Encoder<'a>,
+
+    /// Checkpoint taken before the field's header
+    checkpoint: Checkpoint,
+
+    /// Tag of the field
+    tag: Tag,
+
+    /// Number of bytes of the value still to be written
+    remaining: usize,
+
+    /// Has the field been finished (or cleared)?
+    finished: bool,
+}
+
+impl<'e, 'a> BytesWriter<'e, 'a> {
+    /// Create a new [`BytesWriter`] for a field whose header and length
+    /// delimiter have been written
+    pub(super) fn new(
+        encoder: &'e mut Encoder<'a>,
+        checkpoint: Checkpoint,
+        tag: Tag,
+        length: usize,
+    ) -> Self {
+        Self {
+            encoder,
+            checkpoint,
+            tag,
+            remaining: length,
+            finished: false,
+        }
+    }
+
+    /// Get the number of bytes of the value still to be written
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Append the given bytes to the value.
+    ///
+    /// Returns [`error::Kind::Length`] without writing any of them if they'd
+    /// overrun the field's length.
+    pub fn push(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes.len() > self.remaining {
+            let err = Error::from(error::Kind::Length);
+            return Err(err.in_field(self.checkpoint.length, self.tag));
+        }
+
+        // Space for the whole value was checked for up front
+        self.encoder.write(bytes)?;
+        self.remaining -= bytes.len();
+        Ok(())
+    }
+
+    /// Finish writing the field.
+    ///
+    /// If fewer bytes than the field's length were written, the field is
+    /// cleared (leaving the encoder at the start of it, as with other
+    /// fields which fail to be written) and [`error::Kind::Length`] is
+    /// returned.
+    pub fn finish(mut self) -> Result<(), Error> {
+        self.finished = true;
+
+        if self.remaining != 0 {
+            let end = self.encoder.length;
+            self.encoder.truncate(self.checkpoint, end);
+            let err = Error::from(error::Kind::Length);
+            return Err(err.in_field(self.checkpoint.length, self.tag));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for BytesWriter<'_, '_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let end = self.encoder.length;
+            self.encoder.truncate(self.checkpoint, end);
+            self.encoder.abandoned = Some((self.tag, self.checkpoint.length));
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+impl io::Write for BytesWriter<'_, '_> {
+    /// Write as much of the given buffer as fits in the rest of the value,
+    /// so writing past its end fails with [`io::ErrorKind::WriteZero`]
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = core::cmp::min(buf.len(), self.remaining);
+        self.push(&buf[..n])
+            .map_err(|_| io::Error::from(io::ErrorKind::WriteZero))?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::Encoder;
+    use crate::{
+        error::Kind,
+        field::{self, Tag},
+    };
+
+    const EXAMPLE_BYTES: &[u8] = b"foobar";
+
+    #[test]
+    fn push() {
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+
+        let mut writer = encoder
+            .bytes_writer(Tag::new_const(1), false, EXAMPLE_BYTES.len())
+            .unwrap();
+        writer.push(&EXAMPLE_BYTES[..2]).unwrap();
+        writer.push(&EXAMPLE_BYTES[2..]).unwrap();
+
+        // Overruns are rejected
+        assert_eq!(writer.push(b"!").err().unwrap().kind(), Kind::Length);
+        writer.finish().unwrap();
+
+        let mut expected = [0u8; 16];
+        let mut expected_encoder = Encoder::new(&mut expected);
+        expected_encoder
+            .bytes(Tag::new_const(1), false, EXAMPLE_BYTES)
+            .unwrap();
+        assert_eq!(encoder.finish(), expected_encoder.finish());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn io_write() {
+        use std::io::Write;
+
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+
+        let mut writer = encoder.bytes_writer(Tag::new_const(1), false, 4).unwrap();
+        let err = writer.write_all(EXAMPLE_BYTES).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+        assert_eq!(writer.remaining(), 0);
+        writer.finish().unwrap();
+
+        let mut input = encoder.finish();
+        let bytes = crate::Decoder::new()
+            .decode_bytes(Tag::new_const(1), &mut input)
+            .unwrap();
+        assert_eq!(bytes, &EXAMPLE_BYTES[..4]);
+    }
+
+    #[test]
+    fn underrun() {
+        let mut buffer = [0xFFu8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+
+        let mut writer = encoder.bytes_writer(Tag::new_const(2), false, 8).unwrap();
+        writer.push(EXAMPLE_BYTES).unwrap();
+        assert_eq!(writer.finish().err().unwrap().kind(), Kind::Length);
+
+        // The field was cleared, and the encoder can carry on
+        encoder.bool(Tag::new_const(2), false, true).unwrap();
+        assert_eq!(
+            encoder.finish().len(),
+            field::length::uint64(Tag::new_const(1), 42) + field::length::bool(Tag::new_const(2))
+        );
+    }
+
+    #[test]
+    fn abandoned() {
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+        let checkpoint = encoder.checkpoint();
+
+        let mut writer = encoder.bytes_writer(Tag::new_const(2), false, 8).unwrap();
+        writer.push(EXAMPLE_BYTES).unwrap();
+        drop(writer);
+
+        let err = encoder.bool(Tag::new_const(3), false, true).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            Kind::Abandoned {
+                tag: Tag::new_const(2)
+            }
+        );
+
+        // Rolling back to before the field recovers the encoder
+        encoder.rollback(checkpoint).unwrap();
+        encoder.bool(Tag::new_const(3), false, true).unwrap();
+
+        let len = encoder.finish().len();
+        assert_eq!(
+            len,
+            field::length::uint64(Tag::new_const(1), 42) + field::length::bool(Tag::new_const(3))
+        );
+        assert!(buffer[len..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn abandoned_rejects_writes() {
+        let mut buffer = [0u8; 32];
+        let mut encoder = Encoder::unordered(&mut buffer);
+        drop(encoder.bytes_writer(Tag::new_const(1), false, 8).unwrap());
+
+        let abandoned = Kind::Abandoned {
+            tag: Tag::new_const(1),
+        };
+
+        let results = [
+            encoder.uint64(Tag::new_const(2), false, 42),
+            encoder.bool(Tag::new_const(2), false, true),
+            encoder.bytes(Tag::new_const(2), false, EXAMPLE_BYTES),
+            encoder.string(Tag::new_const(2), false, "foobar"),
+            encoder.uint64_seq(Tag::new_const(2), false, &[1, 2, 3]),
+            encoder.message_with(Tag::new_const(2), false, 0, |_| Ok(())),
+            encoder
+                .bytes_writer(Tag::new_const(2), false, 0)
+                .map(|_| ()),
+        ];
+
+        for result in results.iter() {
+            assert_eq!(result.err().unwrap().kind(), abandoned);
+        }
+
+        assert_eq!(encoder.try_finish().err().unwrap().kind(), abandoned);
+    }
+
+    #[test]
+    fn try_finish() {
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+
+        assert_eq!(
+            encoder.try_finish().unwrap().len(),
+            field::length::uint64(Tag::new_const(1), 42)
+        );
+    }
+
+    #[test]
+    fn abandoned_in_nested_message() {
+        let mut buffer = [0u8; 32];
+        let mut encoder = Encoder::new(&mut buffer);
+
+        let err = encoder
+            .message_with(Tag::new_const(1), false, 10, |nested| {
+                drop(nested.bytes_writer(Tag::new_const(1), false, 8)?);
+                Ok(())
+            })
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err.kind(),
+            Kind::Abandoned {
+                tag: Tag::new_const(1)
+            }
+        );
+
+        // The outer encoder isn't poisoned by the nested one
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+        assert_eq!(
+            encoder.try_finish().unwrap().len(),
+            field::length::uint64(Tag::new_const(1), 42)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "bytes field 1 was abandoned before it was finished")]
+    fn finish_abandoned() {
+        let mut buffer = [0u8; 16];
+        let mut encoder = Encoder::new(&mut buffer);
+        drop(encoder.bytes_writer(Tag::new_const(1), false, 8).unwrap());
+        encoder.finish();
+    }
+}
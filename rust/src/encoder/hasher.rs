@@ -0,0 +1,544 @@
+//! Encoder-side Verihash computation.
+//!
+//! WARNING: this is an experimental PoC-quality implementation!
+//! It is NOT suitable for production use!
+//!
+//! Absorbs the same transcript as the decoder's message hasher as each field
+//! is written, so a message can be serialized and hashed in a single pass.
+//!
+//! Nested messages are written with a nested hasher, which computes the
+//! digest of their transcript as their fields are written, and sequences are
+//! hashed from their values, so nothing is decoded again after it's encoded.
+
+use super::Encoder;
+use crate::{
+    error::{self, Error},
+    field::{length, Tag, WireType},
+    verihash::{self, DigestOutput},
+};
+use core::fmt::{self, Debug};
+use digest::Digest;
+
+/// Verihash encoder: writes a message into the provided buffer while
+/// computing its Verihash digest.
+///
+/// This type provides the same field-writing API as [`Encoder`].
+pub struct Hasher<'a, D: Digest> {
+    /// Underlying encoder
+    encoder: Encoder<'a>,
+
+    /// Verihash hasher
+    verihash: verihash::Hasher<D>,
+}
+
+impl<'a, D> Hasher<'a, D>
+where
+    D: Digest,
+{
+    /// Create a new [`Hasher`] which writes into the provided buffer
+    pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            encoder: Encoder::new(buffer),
+            verihash: verihash::Hasher::new_message(),
+        }
+    }
+
+    /// Create a new [`Hasher`] which writes into the provided buffer and
+    /// whose transcript is domain separated by the given context string.
+    ///
+    /// See `decoder::Hasher::new_with_domain` for how the domain is framed.
+    pub fn new_with_domain(buffer: &'a mut [u8], domain: &[u8]) -> Self {
+        let mut verihash = verihash::Hasher::new_with_domain(domain);
+        verihash.message_begin();
+
+        Self {
+            encoder: Encoder::new(buffer),
+            verihash,
+        }
+    }
+
+    /// Write a field containing an unsigned 64-bit integer
+    pub fn uint64(&mut self, tag: Tag, critical: bool, value: u64) -> Result<(), Error> {
+        self.encoder.uint64(tag, critical, value)?;
+        self.verihash.tagged_uint64(tag, critical, value);
+        Ok(())
+    }
+
+    /// Write a field containing a signed 64-bit integer
+    pub fn sint64(&mut self, tag: Tag, critical: bool, value: i64) -> Result<(), Error> {
+        self.encoder.sint64(tag, critical, value)?;
+        self.verihash.tagged_sint64(tag, critical, value);
+        Ok(())
+    }
+
+    /// Write a field containing a boolean
+    pub fn bool(&mut self, tag: Tag, critical: bool, value: bool) -> Result<(), Error> {
+        self.encoder.bool(tag, critical, value)?;
+        self.verihash.tagged_boolean(tag, critical, value);
+        Ok(())
+    }
+
+    /// Write a message (nested inside of a field) of the given length,
+    /// calling the given function to write its body with a nested hasher
+    /// (see [`Encoder::message_with`]).
+    ///
+    /// The nested hasher computes the digest of the message's transcript as
+    /// its fields are written, which is then absorbed into this one.
+    pub fn message_with<F>(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        length: usize,
+        write_body: F,
+    ) -> Result<(), Error>
+    where
+        F: FnOnce(&mut Hasher<'_, D>) -> Result<(), Error>,
+    {
+        let field_len = length::dynamically_sized(tag, WireType::Message, length);
+        let mut digest = None;
+
+        self.encoder.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Message)?;
+            encoder.write(vint64::encode(length as u64))?;
+
+            let start = encoder.length;
+            let nested = write_nested::<D, _>(encoder, length, write_body)
+                .map_err(|err| err.nested_in(start, tag))?;
+
+            digest = Some(nested);
+            Ok(())
+        })?;
+
+        self.verihash.field(tag, critical);
+        self.verihash
+            .fixed_size_value(WireType::Message, &digest.unwrap());
+        Ok(())
+    }
+
+    /// Write a sequence of messages (nested inside of a field) with the
+    /// given lengths, calling the given function with the index of each
+    /// message to write its body with a nested hasher (see
+    /// [`Hasher::message_with`]).
+    ///
+    /// The lengths are iterated twice: once to compute the length of the
+    /// sequence, which precedes its elements, then again to write them.
+    pub fn message_seq_with<I, F>(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        lengths: I,
+        mut write_element: F,
+    ) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = usize> + Clone,
+        F: FnMut(usize, &mut Hasher<'_, D>) -> Result<(), Error>,
+    {
+        let body_len = lengths
+            .clone()
+            .into_iter()
+            .map(|len| vint64::encoded_len(len as u64) + len)
+            .sum();
+        let field_len = length::sequence(tag, WireType::Message, body_len);
+
+        // Domain separate sequence hashes by their contained wire type
+        let mut seq_hasher = verihash::Hasher::<D>::new_sequence(WireType::Message);
+        let mut count: u64 = 0;
+
+        self.encoder.write_field(tag, field_len, |encoder| {
+            encoder.write_header(tag, critical, WireType::Sequence)?;
+            encoder.write_sequence_header(WireType::Message, body_len)?;
+
+            for (index, length) in lengths.into_iter().enumerate() {
+                encoder.write(vint64::encode(length as u64))?;
+
+                let start = encoder.length;
+                let digest =
+                    write_nested::<D, _>(encoder, length, |nested| write_element(index, nested))
+                        .map_err(|err| err.nested_in(start, tag).with_index(index))?;
+
+                seq_hasher.dynamically_sized_value(WireType::Message, length);
+                seq_hasher.update(&digest);
+                count = count.checked_add(1).unwrap();
+            }
+
+            Ok(())
+        })?;
+
+        // Sequence transcripts end with the total number of elements
+        seq_hasher.uint64(count);
+
+        self.sequence(tag, critical, &seq_hasher.finalize());
+        Ok(())
+    }
+
+    /// Write a sequence of `uint64` values
+    pub fn uint64_seq(&mut self, tag: Tag, critical: bool, values: &[u64]) -> Result<(), Error> {
+        self.encoder.uint64_seq(tag, critical, values)?;
+        self.sequence(tag, critical, &uint64_seq_digest::<D>(values));
+        Ok(())
+    }
+
+    /// Write a sequence of `sint64` values
+    pub fn sint64_seq(&mut self, tag: Tag, critical: bool, values: &[i64]) -> Result<(), Error> {
+        self.encoder.sint64_seq(tag, critical, values)?;
+        self.sequence(tag, critical, &sint64_seq_digest::<D>(values));
+        Ok(())
+    }
+
+    /// Write a sequence of sequences of `uint64` values
+    pub fn uint64_seq_seq<S>(&mut self, tag: Tag, critical: bool, seqs: &[S]) -> Result<(), Error>
+    where
+        S: AsRef<[u64]>,
+    {
+        self.encoder.uint64_seq_seq(tag, critical, seqs)?;
+
+        let digest = nested_seq_digest::<D, _>(seqs.iter().map(|seq| {
+            let seq = seq.as_ref();
+            (length::uint64_seq_body(seq), uint64_seq_digest::<D>(seq))
+        }));
+
+        self.sequence(tag, critical, &digest);
+        Ok(())
+    }
+
+    /// Write a sequence of sequences of `sint64` values
+    pub fn sint64_seq_seq<S>(&mut self, tag: Tag, critical: bool, seqs: &[S]) -> Result<(), Error>
+    where
+        S: AsRef<[i64]>,
+    {
+        self.encoder.sint64_seq_seq(tag, critical, seqs)?;
+
+        let digest = nested_seq_digest::<D, _>(seqs.iter().map(|seq| {
+            let seq = seq.as_ref();
+            (length::sint64_seq_body(seq), sint64_seq_digest::<D>(seq))
+        }));
+
+        self.sequence(tag, critical, &digest);
+        Ok(())
+    }
+
+    /// Write a field containing bytes
+    pub fn bytes(&mut self, tag: Tag, critical: bool, bytes: &[u8]) -> Result<(), Error> {
+        self.encoder.bytes(tag, critical, bytes)?;
+        self.dynamically_sized_value(tag, critical, WireType::Bytes, bytes);
+        Ok(())
+    }
+
+    /// Write a field containing a string
+    pub fn string(&mut self, tag: Tag, critical: bool, string: &str) -> Result<(), Error> {
+        self.encoder.string(tag, critical, string)?;
+        self.dynamically_sized_value(tag, critical, WireType::String, string.as_bytes());
+        Ok(())
+    }
+
+    /// Finish constructing a message, returning a slice of the buffer
+    /// containing the serialized message along with its Verihash digest
+    pub fn finish(self) -> (&'a [u8], DigestOutput<D>) {
+        (self.encoder.finish(), self.verihash.finalize_message())
+    }
+
+    /// Hash a sequence field with the given digest
+    fn sequence(&mut self, tag: Tag, critical: bool, digest: &DigestOutput<D>) {
+        self.verihash.field(tag, critical);
+        self.verihash.fixed_size_value(WireType::Sequence, digest);
+    }
+
+    /// Hash a tagged dynamically sized value
+    fn dynamically_sized_value(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        wire_type: WireType,
+        bytes: &[u8],
+    ) {
+        self.verihash.field(tag, critical);
+        self.verihash
+            .dynamically_sized_value(wire_type, bytes.len());
+        self.verihash.update(bytes);
+    }
+}
+
+impl<'a, D> Debug for Hasher<'a, D>
+where
+    D: Digest,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("encoder::Hasher")
+            .field("length", &self.encoder.length)
+            .finish()
+    }
+}
+
+/// Write the body of a nested message of the given length at the encoder's
+/// position with a nested hasher, returning the message's digest
+fn write_nested<D, F>(
+    encoder: &mut Encoder<'_>,
+    length: usize,
+    write_body: F,
+) -> Result<DigestOutput<D>, Error>
+where
+    D: Digest,
+    F: FnOnce(&mut Hasher<'_, D>) -> Result<(), Error>,
+{
+    let start = encoder.length;
+    let end = start.checked_add(length).unwrap();
+
+    let mut nested = Hasher {
+        encoder: Encoder {
+            ordered: encoder.ordered,
+            ..Encoder::new(&mut encoder.buffer[start..end])
+        },
+        verihash: verihash::Hasher::new_message(),
+    };
+
+    // Running out of space means the function wrote too much
+    write_body(&mut nested).map_err(|err| match err.kind() {
+        error::Kind::BufferTooSmall { .. } => err.with_kind(error::Kind::Length),
+        _ => err,
+    })?;
+
+    if nested.encoder.length != length {
+        return Err(error::Kind::Length.into());
+    }
+
+    let digest = nested.verihash.finalize_message();
+    encoder.length = end;
+    Ok(digest)
+}
+
+/// Compute the digest of a sequence of `uint64` values
+fn uint64_seq_digest<D: Digest>(values: &[u64]) -> DigestOutput<D> {
+    let mut hasher = verihash::Hasher::<D>::new_sequence(WireType::UInt64);
+
+    for &value in values {
+        hasher.uint64(value);
+    }
+
+    hasher.uint64(values.len() as u64);
+    hasher.finalize()
+}
+
+/// Compute the digest of a sequence of `sint64` values
+fn sint64_seq_digest<D: Digest>(values: &[i64]) -> DigestOutput<D> {
+    let mut hasher = verihash::Hasher::<D>::new_sequence(WireType::SInt64);
+
+    for &value in values {
+        hasher.sint64(value);
+    }
+
+    hasher.uint64(values.len() as u64);
+    hasher.finalize()
+}
+
+/// Compute the digest of a sequence of sequences from the body length and
+/// digest of each nested sequence
+fn nested_seq_digest<D, I>(seqs: I) -> DigestOutput<D>
+where
+    D: Digest,
+    I: Iterator<Item = (usize, DigestOutput<D>)>,
+{
+    let mut hasher = verihash::Hasher::<D>::new_sequence(WireType::Sequence);
+    let mut count: u64 = 0;
+
+    for (body_len, digest) in seqs {
+        hasher.dynamically_sized_value(WireType::Sequence, body_len);
+        hasher.update(&digest);
+        count = count.checked_add(1).unwrap();
+    }
+
+    hasher.uint64(count);
+    hasher.finalize()
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::Hasher;
+    use crate::{
+        decoder,
+        error::Kind,
+        field::{self, Tag},
+        verihash::{verihash, DigestOutput},
+        Encoder, Error,
+    };
+    use core::iter;
+    use sha2::Sha256;
+
+    /// Length of the message written by [`write_scalars`]
+    fn scalars_len() -> usize {
+        2 + field::length::uint64(Tag::new_const(2), 42)
+            + field::length::sint64(Tag::new_const(3), -42)
+            + field::length::bytes(Tag::new_const(4), b"foobar")
+            + field::length::string(Tag::new_const(5), "baz")
+    }
+
+    /// Write an example message containing one field of every scalar wire
+    /// type
+    fn write_scalars(hasher: &mut Hasher<'_, Sha256>) -> Result<(), Error> {
+        hasher.bool(Tag::new_const(0), false, false)?;
+        hasher.bool(Tag::new_const(1), false, true)?;
+        hasher.uint64(Tag::new_const(2), false, 42)?;
+        hasher.sint64(Tag::new_const(3), false, -42)?;
+        hasher.bytes(Tag::new_const(4), false, b"foobar")?;
+        hasher.string(Tag::new_const(5), false, "baz")
+    }
+
+    /// Compute the decoder-side digest of the given message
+    fn decoder_hash(encoded: &[u8]) -> DigestOutput<Sha256> {
+        verihash::<Sha256>(encoded).unwrap().into_bytes()
+    }
+
+    #[test]
+    fn scalars_round_trip() {
+        let mut buffer = [0u8; 64];
+        let mut hasher = Hasher::<Sha256>::new(&mut buffer);
+        write_scalars(&mut hasher).unwrap();
+
+        let (encoded, digest) = hasher.finish();
+        assert_eq!(encoded.len(), scalars_len());
+        assert_eq!(digest, decoder_hash(encoded));
+    }
+
+    #[test]
+    fn nested_message_round_trip() {
+        let mut buffer = [0u8; 64];
+        let mut hasher = Hasher::<Sha256>::new(&mut buffer);
+
+        hasher.uint64(Tag::new_const(1), false, 42).unwrap();
+        hasher
+            .message_with(Tag::new_const(2), true, scalars_len(), write_scalars)
+            .unwrap();
+        hasher.string(Tag::new_const(3), false, "baz").unwrap();
+
+        let (encoded, digest) = hasher.finish();
+        assert_eq!(digest, decoder_hash(encoded));
+    }
+
+    #[test]
+    fn deeply_nested_message_round_trip() {
+        let mut buffer = [0u8; 64];
+        let mut hasher = Hasher::<Sha256>::new(&mut buffer);
+        let inner_len = field::length::uint64(Tag::new_const(0), 42);
+
+        hasher
+            .message_with(Tag::new_const(1), false, inner_len + 2, |nested| {
+                nested.message_with(Tag::new_const(0), false, inner_len, |inner| {
+                    inner.uint64(Tag::new_const(0), false, 42)
+                })
+            })
+            .unwrap();
+
+        let (encoded, digest) = hasher.finish();
+        assert_eq!(encoded, &[45, 9, 13, 5, 5, 85]);
+        assert_eq!(digest, decoder_hash(encoded));
+    }
+
+    #[test]
+    fn nested_message_length_mismatch() {
+        let mut buffer = [0u8; 64];
+        let mut hasher = Hasher::<Sha256>::new(&mut buffer);
+        hasher.uint64(Tag::new_const(1), false, 42).unwrap();
+
+        let err = hasher
+            .message_with(Tag::new_const(2), false, scalars_len() + 1, write_scalars)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), Kind::Length);
+
+        let err = hasher
+            .message_with(Tag::new_const(2), false, scalars_len() - 1, write_scalars)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), Kind::Length);
+
+        // Failed fields are neither written nor hashed
+        let (encoded, digest) = hasher.finish();
+        assert_eq!(encoded, &[37, 85]);
+        assert_eq!(digest, decoder_hash(encoded));
+    }
+
+    #[test]
+    fn message_seq_round_trip() {
+        let mut buffer = [0u8; 256];
+        let mut hasher = Hasher::<Sha256>::new(&mut buffer);
+
+        hasher.uint64(Tag::new_const(1), false, 42).unwrap();
+        hasher
+            .message_seq_with(
+                Tag::new_const(2),
+                false,
+                [scalars_len(); 3].iter().copied(),
+                |_, nested| write_scalars(nested),
+            )
+            .unwrap();
+
+        let (encoded, digest) = hasher.finish();
+        assert_eq!(digest, decoder_hash(encoded));
+    }
+
+    #[test]
+    fn empty_message_seq_round_trip() {
+        let mut buffer = [0u8; 16];
+        let mut hasher = Hasher::<Sha256>::new(&mut buffer);
+        hasher
+            .message_seq_with(
+                Tag::new_const(1),
+                false,
+                iter::empty(),
+                |_, _| unreachable!(),
+            )
+            .unwrap();
+
+        let (encoded, digest) = hasher.finish();
+        assert_eq!(digest, decoder_hash(encoded));
+    }
+
+    #[test]
+    fn sequences_round_trip() {
+        let mut buffer = [0u8; 64];
+        let mut hasher = Hasher::<Sha256>::new(&mut buffer);
+
+        hasher
+            .uint64_seq(Tag::new_const(0), false, &[1, 2, 3])
+            .unwrap();
+        hasher
+            .sint64_seq(Tag::new_const(1), false, &[-1, 0])
+            .unwrap();
+        hasher.uint64_seq(Tag::new_const(2), false, &[]).unwrap();
+        hasher
+            .sint64_seq_seq(Tag::new_const(3), false, &[&[-1, 1][..], &[]])
+            .unwrap();
+
+        let (encoded, digest) = hasher.finish();
+
+        let mut expected = [0u8; 64];
+        let mut encoder = Encoder::new(&mut expected);
+        encoder
+            .uint64_seq(Tag::new_const(0), false, &[1, 2, 3])
+            .unwrap();
+        encoder
+            .sint64_seq(Tag::new_const(1), false, &[-1, 0])
+            .unwrap();
+        encoder.uint64_seq(Tag::new_const(2), false, &[]).unwrap();
+        encoder
+            .sint64_seq_seq(Tag::new_const(3), false, &[&[-1, 1][..], &[]])
+            .unwrap();
+
+        assert_eq!(encoded, encoder.finish());
+        assert_eq!(digest, decoder_hash(encoded));
+    }
+
+    #[test]
+    fn domain_round_trip() {
+        let mut buffer = [0u8; 64];
+        let mut hasher = Hasher::<Sha256>::new_with_domain(&mut buffer, b"example");
+        hasher.uint64(Tag::new_const(1), false, 42).unwrap();
+
+        let (encoded, digest) = hasher.finish();
+
+        let mut expected = decoder::Hasher::<Sha256>::new_with_domain(b"example");
+        expected.hash_message(encoded).unwrap();
+
+        assert_eq!(digest, expected.finish().unwrap().into_bytes());
+        assert_ne!(digest, decoder_hash(encoded));
+    }
+}
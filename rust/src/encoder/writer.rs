@@ -0,0 +1,648 @@
+//! Encoder which writes messages to an [`io::Write`]
+
+// `displaydoc` emits its `Display` impls inside of an anonymous `const`
+#![allow(unknown_lints, non_local_definitions)]
+
+use crate::{
+    error::{self, Error},
+    field::{length, Header, Tag, WireType},
+    message::Message,
+    spec, string, zigzag,
+};
+use alloc::vec::Vec;
+use core::cmp;
+use displaydoc::Display;
+use std::io;
+
+/// Encoder which streams fields directly to an [`io::Write`], rather than
+/// encoding the whole message into a buffer first, e.g. for messages with
+/// large `bytes` fields.
+///
+/// It has the same field-writing methods as the slice [`Encoder`][`super::Encoder`].
+/// Since a nested message's length delimiter precedes it, the length of its
+/// body must be known up front:
+///
+/// - [`Writer::message`] gets it from [`Message::encoded_len`], then
+///   encodes the message into a scratch buffer which is reused between
+///   messages (as [`Message::encode`] encodes into a slice)
+/// - [`Writer::message_with`] takes it as an argument (e.g. computed with
+///   the [`field::length`][`crate::field::length`] functions), and streams
+///   the body's fields to the writer as well, checking they have that length
+///
+/// The values of `bytes` and `string` fields are passed to the writer along
+/// with their header with [`io::Write::write_vectored`], rather than being
+/// copied (see [`Writer::bytes_vectored`]). Other fields are written with
+/// several small writes, so wrapping the writer in an [`io::BufWriter`] is
+/// recommended. [`Writer::bytes_written`] counts
+/// every byte the inner writer accepted, including those of a field which
+/// failed partway through.
+#[derive(Debug)]
+pub struct Writer<W: io::Write> {
+    /// Inner writer
+    inner: W,
+
+    /// Number of bytes written to the inner writer
+    written: usize,
+
+    /// Scratch buffer for encoding nested messages
+    scratch: Vec<u8>,
+}
+
+impl<W> Writer<W>
+where
+    W: io::Write,
+{
+    /// Create a new [`Writer`] which writes to the given writer
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            written: 0,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Get the number of bytes written to the inner writer so far
+    pub fn bytes_written(&self) -> usize {
+        self.written
+    }
+
+    /// Write a field containing an unsigned 64-bit integer
+    pub fn uint64(&mut self, tag: Tag, critical: bool, value: u64) -> Result<(), WriteError> {
+        self.write_header(tag, critical, WireType::UInt64)?;
+        self.write(vint64::encode(value))
+    }
+
+    /// Write a field containing a signed 64-bit integer
+    pub fn sint64(&mut self, tag: Tag, critical: bool, value: i64) -> Result<(), WriteError> {
+        self.write_header(tag, critical, WireType::SInt64)?;
+        self.write(vint64::encode(zigzag::encode(value)))
+    }
+
+    /// Write a field containing an unsigned 32-bit integer, which is encoded
+    /// as a `uint64`
+    pub fn uint32(&mut self, tag: Tag, critical: bool, value: u32) -> Result<(), WriteError> {
+        self.uint64(tag, critical, value.into())
+    }
+
+    /// Write a field containing a signed 32-bit integer, which is encoded as
+    /// an `sint64`
+    pub fn sint32(&mut self, tag: Tag, critical: bool, value: i32) -> Result<(), WriteError> {
+        self.sint64(tag, critical, value.into())
+    }
+
+    /// Write a field containing a boolean
+    pub fn bool(&mut self, tag: Tag, critical: bool, value: bool) -> Result<(), WriteError> {
+        let wire_type = if value {
+            WireType::True
+        } else {
+            WireType::False
+        };
+
+        self.write_header(tag, critical, wire_type)
+    }
+
+    /// Write a field containing bytes
+    pub fn bytes(&mut self, tag: Tag, critical: bool, bytes: &[u8]) -> Result<(), WriteError> {
+        self.write_value(tag, critical, WireType::Bytes, &[bytes])
+    }
+
+    /// Write a field containing bytes which are the concatenation of the
+    /// given slices.
+    ///
+    /// The slices are passed to the inner writer's
+    /// [`io::Write::write_vectored`] after the field's header and length
+    /// delimiter, so large values aren't copied.
+    pub fn bytes_vectored(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Result<(), WriteError> {
+        let value: Vec<&[u8]> = bufs.iter().map(|buf| &**buf).collect();
+        self.write_value(tag, critical, WireType::Bytes, &value)
+    }
+
+    /// Write a field containing a string
+    pub fn string(&mut self, tag: Tag, critical: bool, string: &str) -> Result<(), WriteError> {
+        string::ensure_canonical(string)?;
+        self.write_value(tag, critical, WireType::String, &[string.as_bytes()])
+    }
+
+    /// Write a message (nested inside of a field), encoding it into the
+    /// scratch buffer
+    pub fn message(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        message: &dyn Message,
+    ) -> Result<(), WriteError> {
+        self.write_header(tag, critical, WireType::Message)?;
+        self.write_message(message)
+    }
+
+    /// Write a message (nested inside of a field) whose body has the given
+    /// length, calling the given function to write its fields to a nested
+    /// [`Writer`].
+    ///
+    /// Returns [`error::Kind::Length`] if the function writes a different
+    /// number of bytes.
+    pub fn message_with<F>(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        length: usize,
+        write_body: F,
+    ) -> Result<(), WriteError>
+    where
+        F: FnOnce(&mut Writer<&mut W>) -> Result<(), WriteError>,
+    {
+        self.write_header(tag, critical, WireType::Message)?;
+        self.write(vint64::encode(length as u64))?;
+
+        let mut nested = Writer::new(&mut self.inner);
+        let result = write_body(&mut nested);
+        self.written += nested.written;
+        result?;
+
+        if nested.written != length {
+            return Err(error::Kind::Length.into());
+        }
+
+        Ok(())
+    }
+
+    /// Write a sequence of messages (nested inside of a field).
+    ///
+    /// The sequence's length is computed with a pre-pass over the messages
+    /// (like [`Encoder::message_seq`][`super::Encoder::message_seq`]), then
+    /// each of them is encoded into the scratch buffer, which is reused
+    /// across elements and calls.
+    pub fn message_seq<'m, M>(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        messages: impl Iterator<Item = &'m M> + Clone,
+    ) -> Result<(), WriteError>
+    where
+        M: Message + ?Sized + 'm,
+    {
+        let body_len = length::message_seq_body(messages.clone());
+
+        self.write_header(tag, critical, WireType::Sequence)?;
+        self.write_sequence_header(WireType::Message, body_len)?;
+
+        for message in messages {
+            self.write_message(message)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a sequence of `uint64` values
+    pub fn uint64_seq(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        values: &[u64],
+    ) -> Result<(), WriteError> {
+        self.write_header(tag, critical, WireType::Sequence)?;
+        self.write_sequence_header(WireType::UInt64, length::uint64_seq_body(values))?;
+
+        for &value in values {
+            self.write(vint64::encode(value))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a sequence of `sint64` values
+    pub fn sint64_seq(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        values: &[i64],
+    ) -> Result<(), WriteError> {
+        self.write_header(tag, critical, WireType::Sequence)?;
+        self.write_sequence_header(WireType::SInt64, length::sint64_seq_body(values))?;
+
+        for &value in values {
+            self.write(vint64::encode(zigzag::encode(value)))?;
+        }
+
+        Ok(())
+    }
+
+    /// Flush the inner writer
+    pub fn flush(&mut self) -> Result<(), WriteError> {
+        self.inner.flush().map_err(WriteError::Io)
+    }
+
+    /// Borrow the inner writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Unwrap the inner writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Write a field header
+    fn write_header(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        wire_type: WireType,
+    ) -> Result<(), WriteError> {
+        self.write(Header::new(tag, critical, wire_type).encode())
+    }
+
+    /// Write a sequence header (element type + body length)
+    fn write_sequence_header(
+        &mut self,
+        wire_type: WireType,
+        length: usize,
+    ) -> Result<(), WriteError> {
+        self.write(vint64::encode(spec::pack_sequence_header(
+            length as u64,
+            wire_type.to_u8(),
+        )))
+    }
+
+    /// Write a length-prefixed nested message, encoding it into the scratch
+    /// buffer
+    fn write_message<M>(&mut self, message: &M) -> Result<(), WriteError>
+    where
+        M: Message + ?Sized,
+    {
+        let encoded_len = message.encoded_len();
+        self.scratch.clear();
+        self.scratch.resize(encoded_len, 0);
+
+        let body_len = message.encode(&mut self.scratch)?.len();
+        self.write(vint64::encode(body_len as u64))?;
+
+        let scratch = core::mem::take(&mut self.scratch);
+        let result = self.write(&scratch[..body_len]);
+        self.scratch = scratch;
+        result
+    }
+
+    /// Write a field containing a dynamically sized value which is the
+    /// concatenation of the given slices
+    fn write_value(
+        &mut self,
+        tag: Tag,
+        critical: bool,
+        wire_type: WireType,
+        value: &[&[u8]],
+    ) -> Result<(), WriteError> {
+        let length: usize = value.iter().map(|part| part.len()).sum();
+        let header = Header::new(tag, critical, wire_type).encode();
+        let prefix = vint64::encode(length as u64);
+
+        let mut parts = Vec::with_capacity(value.len() + 2);
+        parts.push(header.as_ref());
+        parts.push(prefix.as_ref());
+        parts.extend_from_slice(value);
+        self.write_vectored(&mut parts)
+    }
+
+    /// Write the concatenation of the given slices to the inner writer with
+    /// [`io::Write::write_vectored`], counting every byte it accepts.
+    /// [`io::ErrorKind::Interrupted`] errors are retried.
+    fn write_vectored(&mut self, parts: &mut [&[u8]]) -> Result<(), WriteError> {
+        let mut start = 0;
+
+        loop {
+            // Skip the slices which have been written in full
+            while start < parts.len() && parts[start].is_empty() {
+                start += 1;
+            }
+
+            if start == parts.len() {
+                return Ok(());
+            }
+
+            let bufs: Vec<io::IoSlice<'_>> = parts[start..]
+                .iter()
+                .map(|part| io::IoSlice::new(part))
+                .collect();
+
+            match self.inner.write_vectored(&bufs) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                Ok(mut n) => {
+                    self.written += n;
+
+                    while n > 0 {
+                        let len = cmp::min(n, parts[start].len());
+                        parts[start] = &parts[start][len..];
+                        n -= len;
+
+                        if parts[start].is_empty() {
+                            start += 1;
+                        }
+                    }
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(WriteError::Io(err)),
+            }
+        }
+    }
+
+    /// Write the given bytes to the inner writer, counting every byte it
+    /// accepts. [`io::ErrorKind::Interrupted`] errors are retried.
+    fn write(&mut self, bytes: impl AsRef<[u8]>) -> Result<(), WriteError> {
+        let mut bytes = bytes.as_ref();
+
+        while !bytes.is_empty() {
+            match self.inner.write(bytes) {
+                Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero).into()),
+                Ok(n) => {
+                    self.written += n;
+                    bytes = &bytes[n..];
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(WriteError::Io(err)),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Errors which occur writing a message to an [`io::Write`]
+#[derive(Debug, Display)]
+pub enum WriteError {
+    /// I/O error
+    Io(io::Error),
+
+    /// encode error
+    Encode(Error),
+}
+
+impl From<io::Error> for WriteError {
+    fn from(err: io::Error) -> WriteError {
+        WriteError::Io(err)
+    }
+}
+
+impl From<Error> for WriteError {
+    fn from(err: Error) -> WriteError {
+        WriteError::Encode(err)
+    }
+}
+
+impl From<error::Kind> for WriteError {
+    fn from(kind: error::Kind) -> WriteError {
+        WriteError::Encode(kind.into())
+    }
+}
+
+impl std::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WriteError::Io(err) => Some(err),
+            WriteError::Encode(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{WriteError, Writer};
+    use crate::{
+        error,
+        field::{length, Tag},
+        Encoder, Message, RawMessage,
+    };
+    use alloc::vec::Vec;
+    use std::io;
+
+    /// Write `{ 1: uint64(42), 2: sint64(-1), 3: bytes([0; 300]),
+    ///   4: string("baz"), 5: true, 6: message({ 1: uint64(42) }),
+    ///   7: sequence<uint64>([1, 2]) }` with the given writer
+    fn write_example<W: io::Write>(writer: &mut Writer<W>) -> Result<(), WriteError> {
+        writer.uint64(Tag::new_const(1), false, 42)?;
+        writer.sint64(Tag::new_const(2), false, -1)?;
+        writer.bytes(Tag::new_const(3), false, &[0; 300])?;
+        writer.string(Tag::new_const(4), false, "baz")?;
+        writer.bool(Tag::new_const(5), false, true)?;
+        writer.message_with(
+            Tag::new_const(6),
+            false,
+            length::uint64(Tag::new_const(1), 42),
+            |nested| nested.uint64(Tag::new_const(1), false, 42),
+        )?;
+        writer.uint64_seq(Tag::new_const(7), false, &[1, 2])
+    }
+
+    /// Encode the same message as [`write_example`] with the slice encoder
+    fn encode_example(buffer: &mut [u8]) -> &[u8] {
+        let mut nested = [0u8; 2];
+        let mut encoder = Encoder::new(&mut nested);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+        let nested = RawMessage::new(encoder.finish());
+
+        let mut encoder = Encoder::new(buffer);
+        encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+        encoder.sint64(Tag::new_const(2), false, -1).unwrap();
+        encoder.bytes(Tag::new_const(3), false, &[0; 300]).unwrap();
+        encoder.string(Tag::new_const(4), false, "baz").unwrap();
+        encoder.bool(Tag::new_const(5), false, true).unwrap();
+        encoder
+            .raw_message(Tag::new_const(6), false, nested)
+            .unwrap();
+        encoder
+            .uint64_seq(Tag::new_const(7), false, &[1, 2])
+            .unwrap();
+        encoder.finish()
+    }
+
+    /// Writer which accepts a limited number of bytes, a few at a time
+    struct Limited {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl io::Write for Limited {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.written.len() >= self.limit {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+
+            let n = buf.len().min(3).min(self.limit - self.written.len());
+            self.written.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn same_as_encoder() {
+        let mut writer = Writer::new(Vec::new());
+        write_example(&mut writer).unwrap();
+        assert_eq!(writer.bytes_written(), writer.get_ref().len());
+
+        let mut buffer = [0u8; 512];
+        assert_eq!(writer.into_inner(), encode_example(&mut buffer));
+    }
+
+    #[test]
+    fn message() {
+        #[derive(Debug, Default, Eq, PartialEq)]
+        struct Example;
+
+        impl Message for Example {
+            fn decode<D: digest::Digest>(
+                _: &mut crate::decoder::Decoder<D>,
+                _: &[u8],
+            ) -> Result<Self, crate::Error> {
+                Ok(Example)
+            }
+
+            fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], crate::Error> {
+                let mut encoder = Encoder::new(buffer);
+                encoder.string(Tag::new_const(1), false, "example")?;
+                Ok(encoder.finish())
+            }
+
+            fn encoded_len(&self) -> usize {
+                length::string(Tag::new_const(1), "example")
+            }
+        }
+
+        let mut writer = Writer::new(Vec::new());
+        writer.message(Tag::new_const(1), false, &Example).unwrap();
+        writer
+            .message_seq(
+                Tag::new_const(2),
+                false,
+                [&Example as &dyn Message; 2].iter().copied(),
+            )
+            .unwrap();
+
+        let mut buffer = [0u8; 64];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.message(Tag::new_const(1), false, &Example).unwrap();
+        encoder
+            .message_seq(Tag::new_const(2), false, [Example, Example].iter())
+            .unwrap();
+
+        assert_eq!(writer.into_inner(), encoder.finish());
+    }
+
+    #[test]
+    fn io_error_after_partial_write() {
+        let mut writer = Writer::new(Limited {
+            written: Vec::new(),
+            limit: 100,
+        });
+
+        match write_example(&mut writer) {
+            Err(WriteError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::BrokenPipe),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The partially written `bytes` field is counted
+        assert_eq!(writer.bytes_written(), 100);
+    }
+
+    /// Writer which records the addresses and lengths of the slices passed
+    /// to [`io::Write::write_vectored`]
+    #[derive(Default)]
+    struct Recording {
+        written: Vec<u8>,
+        slices: Vec<(usize, usize)>,
+    }
+
+    impl io::Write for Recording {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+            let mut n = 0;
+
+            for buf in bufs {
+                self.slices.push((buf.as_ptr() as usize, buf.len()));
+                n += self.write(buf)?;
+            }
+
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bytes_vectored() {
+        let payload = vec![0x42u8; 0x10000];
+        let (first, second) = payload.split_at(100);
+
+        let mut writer = Writer::new(Recording::default());
+        writer
+            .bytes_vectored(
+                Tag::new_const(1),
+                false,
+                &[io::IoSlice::new(first), io::IoSlice::new(second)],
+            )
+            .unwrap();
+
+        // The payload is passed to the writer without being copied
+        let recording = writer.into_inner();
+        assert!(recording
+            .slices
+            .contains(&(first.as_ptr() as usize, first.len())));
+        assert!(recording
+            .slices
+            .contains(&(second.as_ptr() as usize, second.len())));
+
+        let mut buffer = vec![0u8; 0x10010];
+        let mut encoder = Encoder::new(&mut buffer);
+        encoder.bytes(Tag::new_const(1), false, &payload).unwrap();
+        assert_eq!(recording.written, encoder.finish());
+    }
+
+    #[test]
+    fn partial_vectored_writes() {
+        let mut writer = Writer::new(Limited {
+            written: Vec::new(),
+            limit: usize::MAX,
+        });
+        write_example(&mut writer).unwrap();
+
+        let mut buffer = [0u8; 512];
+        assert_eq!(writer.into_inner().written, encode_example(&mut buffer));
+    }
+
+    #[test]
+    fn encode_errors() {
+        let mut writer = Writer::new(Vec::new());
+
+        match writer.string(Tag::new_const(1), false, "\u{e9}") {
+            Err(WriteError::Encode(err)) => {
+                assert_eq!(err.kind(), error::Kind::UnicodeNormalization)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        // The nested message's body is shorter than its declared length
+        let result = writer.message_with(Tag::new_const(2), false, 10, |nested| {
+            nested.uint64(Tag::new_const(1), false, 42)
+        });
+
+        match result {
+            Err(WriteError::Encode(err)) => assert_eq!(err.kind(), error::Kind::Length),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        assert_eq!(writer.bytes_written(), 4);
+    }
+}
@@ -1,20 +1,60 @@
 //! Error types
 
+// `displaydoc` emits its `Display` impls inside of an anonymous `const`
+#![allow(unknown_lints, non_local_definitions)]
+
+mod code;
+
+pub use self::code::ErrorCode;
+
 use crate::{
-    field::{Tag, WireType},
+    decoder::{EventKind, MissingFields, StateDescription},
+    field::{Header, Path, Tag, WireType},
     message::Element,
 };
-use core::fmt::{self, Display};
+use core::{
+    fmt::{self, Display},
+    str,
+};
 use displaydoc::Display;
 
-/// Error type
+/// Error type.
+///
+/// Besides its [`Kind`], an error carries the context in which it occurred
+/// where it's known: the byte offset, the field's header and name, the path
+/// of the message containing it, and the index of the sequence element.
+/// This context is stored inline, so errors are `Copy` and available
+/// without `alloc`.
+///
+/// The [`Display`] impl renders errors on a single line, e.g. for logging,
+/// while `Debug` includes every detail.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Error {
     /// Kind of error
     kind: Kind,
 
-    /// Position inside of message where error occurred
+    /// Byte offset into the input where the error occurred
     position: Option<usize>,
+
+    /// Tag of the field being processed when the error occurred
+    tag: Option<Tag>,
+
+    /// Wire type of the field being decoded when the error occurred, if
+    /// its header is known
+    wire_type: Option<WireType>,
+
+    /// Is the field being decoded when the error occurred critical?
+    critical: bool,
+
+    /// Tags of the fields containing the message the error occurred in
+    path: Path,
+
+    /// Index of the sequence element the error occurred in
+    index: Option<usize>,
+
+    /// Name of the struct member for the field, if known (behind a thin
+    /// reference to keep errors small)
+    field_name: Option<&'static &'static str>,
 }
 
 impl Error {
@@ -23,58 +63,275 @@ impl Error {
         self.kind
     }
 
-    /// Get the position inside of the message where the error occurred
-    /// (if available)
+    /// Get the stable [`ErrorCode`] for the kind of error that occurred
+    pub fn code(self) -> ErrorCode {
+        self.kind.into()
+    }
+
+    /// Get the byte offset into the input where the error was detected
+    /// (if available).
+    ///
+    /// This is available for errors returned by the [`Decoder`] and the
+    /// [`Message`] impls which use it, and is relative to the start of the
+    /// outermost message being decoded. For errors returned by the
+    /// [`Encoder`], it's the offset of the field being written in the
+    /// outermost message.
     ///
-    /// NOTE: support for this is an unreliable work-in-progress. Most of the
-    /// time this will return `None`.
+    /// [`Decoder`]: crate::Decoder
+    /// [`Encoder`]: crate::Encoder
+    /// [`Message`]: crate::Message
     pub fn position(self) -> Option<usize> {
         self.position
     }
 
-    /// Extend the position within a message (for nested messages)
-    // TODO(tarcieri): remove `#[allow(dead_code)]` attrs once this method is used
-    #[allow(dead_code)]
-    pub(crate) fn extend_position(self, pos: usize) -> Self {
-        let new_position = self
-            .position
-            .map(|old_pos| old_pos.checked_add(pos).unwrap())
-            .unwrap_or(pos);
+    /// Get the tag of the field being processed when the error occurred
+    /// (if available)
+    pub fn tag(self) -> Option<Tag> {
+        self.tag
+    }
+
+    /// Get the header of the field being decoded when the error occurred
+    /// (if available).
+    ///
+    /// Only errors returned by the [`Decoder`] (and the [`Message`] impls
+    /// which use it) have headers, and only once the field's header has
+    /// been decoded.
+    ///
+    /// [`Decoder`]: crate::Decoder
+    /// [`Message`]: crate::Message
+    pub fn header(self) -> Option<Header> {
+        match (self.tag, self.wire_type) {
+            (Some(tag), Some(wire_type)) => Some(Header::new(tag, self.critical, wire_type)),
+            _ => None,
+        }
+    }
+
+    /// Get the tags of the fields containing the (possibly nested) message
+    /// in which the error occurred, outermost first. Empty if the error
+    /// occurred in the outermost message.
+    pub fn path(self) -> Path {
+        self.path
+    }
+
+    /// Get the index of the element of the (innermost) sequence in which the
+    /// error occurred, if it occurred within a sequence
+    pub fn index(self) -> Option<usize> {
+        self.index
+    }
+
+    /// Get the name of the struct member for the field being processed
+    /// when the error occurred, if it was encoded by a derived [`Message`]
+    /// impl
+    ///
+    /// [`Message`]: crate::Message
+    pub fn field_name(self) -> Option<&'static str> {
+        self.field_name.copied()
+    }
+
+    /// Add the context in which the error occurred, i.e. the header of the
+    /// field being decoded (if any), unless it already has context from a
+    /// more deeply nested message
+    pub(crate) fn with_context(self, position: usize, header: Option<Header>, path: Path) -> Self {
+        if self.position.is_some() {
+            return self;
+        }
+
+        Self {
+            position: Some(position),
+            tag: header.map(|header| header.tag),
+            wire_type: header.map(|header| header.wire_type),
+            critical: matches!(header, Some(header) if header.critical),
+            path,
+            ..self
+        }
+    }
+
+    /// Add the field being encoded at the given offset when the error
+    /// occurred, unless it already has one from a nested message
+    pub(crate) fn in_field(self, position: usize, tag: Tag) -> Self {
+        if self.tag.is_some() {
+            return self;
+        }
+
+        Self {
+            position: Some(position),
+            tag: Some(tag),
+            ..self
+        }
+    }
+
+    /// Add the field containing the nested message (whose body begins at
+    /// the given offset) in which an encoding or decoding error occurred
+    pub(crate) fn nested_in(self, position: usize, tag: Tag) -> Self {
+        if self.position.is_none() {
+            return self;
+        }
+
+        Self {
+            position: self.position.map(|pos| pos + position),
+            path: self.path.prepend(tag),
+            ..self
+        }
+    }
+
+    /// Add the field containing the sequence (whose body begins at the
+    /// given offset) in which a decoding error occurred.
+    ///
+    /// Errors in the sequence itself get the field's header, while errors in
+    /// a message within the sequence get the field's tag added to their path.
+    pub(crate) fn in_sequence(self, position: usize, header: Option<Header>) -> Self {
+        let pos = match self.position {
+            Some(pos) => pos + position,
+            None => return self,
+        };
+
+        match (self.tag, header) {
+            (None, _) => Self {
+                position: Some(pos),
+                tag: header.map(|header| header.tag),
+                wire_type: header.map(|header| header.wire_type),
+                critical: matches!(header, Some(header) if header.critical),
+                ..self
+            },
+            (Some(_), Some(header)) => Self {
+                position: Some(pos),
+                path: self.path.prepend(header.tag),
+                ..self
+            },
+            (Some(_), None) => Self {
+                position: Some(pos),
+                ..self
+            },
+        }
+    }
+
+    /// Add the name of the struct member for the field being encoded when
+    /// the error occurred, unless it occurred in a nested message
+    #[cfg(feature = "veriform_derive")]
+    pub(crate) fn with_field_name(self, name: &'static &'static str) -> Self {
+        if self.field_name.is_some() || !self.path.is_empty() {
+            return self;
+        }
+
+        Self {
+            field_name: Some(name),
+            ..self
+        }
+    }
+
+    /// Replace the kind of error, keeping its context
+    pub(crate) fn with_kind(self, kind: Kind) -> Self {
+        Self { kind, ..self }
+    }
 
+    /// Add the index of the sequence element in which the error occurred,
+    /// unless it occurred within a more deeply nested sequence
+    pub(crate) fn with_index(self, index: usize) -> Self {
         Self {
-            kind: self.kind,
-            position: Some(new_position),
+            index: self.index.or(Some(index)),
+            ..self
         }
     }
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.kind)?;
-
         if let Some(pos) = self.position {
-            write!(f, " position={}", pos)?;
+            write!(f, "error at offset {}", pos)?;
+
+            if self.tag.is_some() || !self.path.is_empty() {
+                write!(f, " (")?;
+
+                if let Some(header) = self.header() {
+                    write!(f, "field {}", header)?;
+                } else if let Some(tag) = self.tag {
+                    write!(f, "field #{}", tag)?;
+                }
+
+                if let Some(name) = self.field_name {
+                    write!(f, " `{}`", name)?;
+                }
+
+                if !self.path.is_empty() {
+                    if self.tag.is_some() {
+                        write!(f, " ")?;
+                    }
+
+                    write!(f, "in field {}", self.path)?;
+                }
+
+                if let Some(index) = self.index {
+                    write!(f, ", element {}", index)?;
+                }
+
+                write!(f, ")")?;
+            }
+
+            write!(f, ": ")?;
         }
 
-        Ok(())
+        write!(f, "{}", self.kind)
     }
 }
 
 impl From<vint64::Error> for Error {
-    fn from(_: vint64::Error) -> Error {
-        Kind::VInt64.into()
+    fn from(err: vint64::Error) -> Error {
+        match err {
+            vint64::Error::LeadingZeroes => Kind::NonCanonicalVInt64.into(),
+            vint64::Error::Truncated => Kind::VInt64.into(),
+        }
     }
 }
 
+impl From<str::Utf8Error> for Error {
+    fn from(err: str::Utf8Error) -> Error {
+        Kind::Utf8 {
+            valid_up_to: err.valid_up_to(),
+        }
+        .into()
+    }
+}
+
+/// Veriform errors are self-contained (they're `Copy` so they can be used
+/// without `alloc`), so they have no [`source`][`std::error::Error::source`].
+///
+/// Errors from the I/O APIs which wrap them, e.g.
+/// [`ReadError`][`crate::decoder::ReadError`], return them (or the
+/// underlying [`std::io::Error`]) as their source.
 #[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
 impl std::error::Error for Error {}
 
-/// Kinds of errors
+/// Requires Rust 1.81+, which stabilized [`core::error::Error`].
+#[cfg(all(feature = "core-error", not(feature = "std")))]
+#[cfg_attr(docsrs, doc(cfg(feature = "core-error")))]
+impl core::error::Error for Error {}
+
+/// Kinds of errors.
+///
+/// New kinds may be added in minor releases, so matches on them need a
+/// wildcard arm.
 #[derive(Copy, Clone, Debug, Display, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum Kind {
+    /// bytes field {tag} was abandoned before it was finished
+    Abandoned {
+        /// tag of the abandoned field
+        tag: Tag,
+    },
+
+    /// buffer too small: needed {needed} bytes
+    BufferTooSmall {
+        /// length of the value which didn't fit in the buffer
+        needed: usize,
+    },
+
     /// error decoding builtin type
     Builtin,
 
+    /// checkpoint is from another encoder, or was invalidated by a rollback
+    Checkpoint,
+
     /// decoding failed: wire_type={wire_type:?}
     Decode {
         /// element of the message that failed to decode
@@ -84,6 +341,24 @@ pub enum Kind {
         wire_type: WireType,
     },
 
+    /// field {tag} has its default value, which must be omitted
+    DefaultValue {
+        /// tag of the field
+        tag: Tag,
+    },
+
+    /// duplicate key in map field {tag}
+    DuplicateKey {
+        /// tag of the field containing the map
+        tag: Tag,
+    },
+
+    /// duplicate field {tag}
+    DuplicateTag {
+        /// tag of the repeated field
+        tag: Tag,
+    },
+
     /// operation failed
     Failed,
 
@@ -96,30 +371,167 @@ pub enum Kind {
         wire_type: Option<WireType>,
     },
 
-    /// hashing operation failed
-    // TODO(tarcieri): collect more info
-    Hashing,
+    /// frame of {length} bytes exceeds the maximum of {max}
+    FrameTooLong {
+        /// length claimed by the frame's length prefix
+        length: u64,
+
+        /// maximum allowed length
+        max: usize,
+    },
+
+    /// decoding ran out of fuel after {consumed} steps
+    FuelExhausted {
+        /// amount of fuel consumed before it ran out
+        consumed: u64,
+    },
+
+    /// hashing failed: {reason}
+    Hashing {
+        /// why hashing failed
+        reason: HashingReason,
+    },
+
+    /// malformed field header: expected `#<tag>:<wire type>`, followed by `!` if critical
+    HeaderSyntax,
+
+    /// incomplete input: at least {needed} more bytes are needed
+    Incomplete {
+        /// number of additional bytes of input needed, which is 1 if the
+        /// input was empty (the length of a `vint64` is only known once its
+        /// first byte is available)
+        needed: usize,
+    },
 
     /// invalid wire type
     InvalidWireType,
 
+    /// keys of map field {tag} are out-of-order
+    KeyOrder {
+        /// tag of the field containing the map
+        tag: Tag,
+    },
+
     /// bad length
     Length,
 
-    /// maximum message nesting depth exceeded
-    NestingDepth,
+    /// missing required fields: {fields}
+    MissingFields {
+        /// required fields which were missing
+        fields: MissingFields,
+    },
 
-    /// nested sequences not presently allowed
+    /// field {tag} is another variant of a oneof which already has one
+    MultipleVariants {
+        /// tag of the second variant
+        tag: Tag,
+    },
+
+    /// maximum nesting depth exceeded: depth={depth} max={max}
+    NestingDepth {
+        /// depth of the message or sequence which exceeded the maximum
+        depth: usize,
+
+        /// maximum allowed depth
+        max: usize,
+    },
+
+    /// sequences can only be nested one level deep
     NestedSequence,
 
-    /// field {tag:?} is out-of-order
+    /// non-canonical `vint64` encoding (superfluous leading zeroes)
+    NonCanonicalVInt64,
+
+    /// field {tag} is out-of-order
     Order {
         /// tag of the out-of-order field
         tag: Tag,
     },
 
-    /// unexpected trailing data
-    TrailingData,
+    /// field {tag} is out of range: value={value} max={max}
+    OutOfRange {
+        /// tag of the field whose value is out of range
+        tag: Tag,
+
+        /// decoded value (or, for signed fields, its magnitude)
+        value: u64,
+
+        /// largest value of the field's type (or, for negative values of
+        /// signed fields, the magnitude of the smallest)
+        max: u64,
+    },
+
+    /// hasher previously failed at event #{index}
+    Poisoned {
+        /// index of the event the hasher failed at
+        index: usize,
+    },
+
+    /// invalid or incompatible hasher snapshot
+    Snapshot,
+
+    /// field {next} written after field {prev} (tags must be increasing)
+    TagOrder {
+        /// tag of the field previously written
+        prev: Tag,
+
+        /// tag of the field which was out-of-order or repeated
+        next: Tag,
+    },
+
+    /// tag exceeds the maximum of 2^60-1
+    TagTooLarge,
+
+    /// malformed test vectors: {reason}
+    TestVectors {
+        /// what was wrong with the test vectors
+        reason: &'static str,
+    },
+
+    /// field {tag} is too long: wire_type={wire_type:?} length={length} max={max}
+    TooLong {
+        /// tag of the field whose length exceeded the limit
+        tag: Tag,
+
+        /// wire type of the value whose length exceeded the limit
+        wire_type: WireType,
+
+        /// length claimed by the value's length delimiter
+        length: usize,
+
+        /// maximum allowed length
+        max: usize,
+    },
+
+    /// sequence in field {tag} has more than {max} elements
+    TooManyElements {
+        /// tag of the field containing the sequence
+        tag: Tag,
+
+        /// maximum allowed number of elements
+        max: usize,
+    },
+
+    /// unexpected trailing data: remaining={remaining}
+    TrailingData {
+        /// number of bytes following the last field of the message
+        remaining: usize,
+    },
+
+    /// hasher rejected {event} event #{index} in state: {state}
+    Transcript {
+        /// state the hasher was in when it received the event
+        state: StateDescription,
+
+        /// kind of event the hasher received
+        event: EventKind,
+
+        /// index of the event since the hasher was created or last reset
+        index: usize,
+
+        /// why the hasher rejected the event
+        reason: HashingReason,
+    },
 
     /// truncated message: remaining={remaining:?} wire_type={wire_type:?}
     Truncated {
@@ -130,17 +542,29 @@ pub enum Kind {
         wire_type: WireType,
     },
 
-    /// unexpected wire type: actual={actual:?} wanted={wanted:?}
-    UnexpectedWireType {
-        /// actual wire type found in message
-        actual: WireType,
+    /// string contains non-normalized Unicode
+    UnicodeNormalization,
 
-        /// wire type we were looking for
-        wanted: WireType,
+    /// unknown critical field: tag={tag}
+    UnknownCritical {
+        /// tag of the unknown field
+        tag: Tag,
     },
 
-    /// string contains non-normalized Unicode
-    UnicodeNormalization,
+    /// unknown message type: {type_id}
+    UnknownType {
+        /// type ID which isn't registered
+        type_id: u64,
+    },
+
+    /// unknown wire type: {value}
+    UnknownWireType {
+        /// numeric value which isn't a wire type identifier
+        value: u64,
+    },
+
+    /// unknown wire type name: expected one of false, true, uint64, sint64, bytes, string, message, sequence
+    UnknownWireTypeName,
 
     /// malformed UTF-8 encountered at byte: {valid_up_to:?}
     Utf8 {
@@ -148,17 +572,64 @@ pub enum Kind {
         valid_up_to: usize,
     },
 
+    /// validation failed: {reason}
+    Validation {
+        /// why the validator rejected the decoded value
+        reason: &'static str,
+    },
+
     /// `vint64` encoding error
     VInt64,
+
+    /// field {tag} has wire type `{actual}`, expected `{expected}`
+    WireTypeMismatch {
+        /// tag of the field (or of the field containing the sequence whose
+        /// elements have the wrong wire type)
+        tag: Tag,
+
+        /// wire type the schema expects
+        expected: WireType,
+
+        /// wire type found in the message
+        actual: WireType,
+    },
 }
 
-impl Kind {
-    /// Create an error with the given position
-    pub(crate) fn position(self, pos: usize) -> Error {
-        Error {
-            kind: self,
-            position: Some(pos),
-        }
+/// Reasons hashing can fail, carried by [`Kind::Hashing`]
+#[derive(Copy, Clone, Debug, Display, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum HashingReason {
+    /// digest algorithm doesn't match the expected one
+    AlgorithmMismatch,
+
+    /// digest doesn't match the expected one
+    DigestMismatch,
+
+    /// transcript is incomplete
+    Incomplete,
+
+    /// length mismatch
+    LengthMismatch,
+
+    /// unbalanced nesting
+    Nesting,
+
+    /// unexpected event
+    UnexpectedEvent,
+
+    /// wire type mismatch
+    WireTypeMismatch,
+}
+
+impl From<HashingReason> for Kind {
+    fn from(reason: HashingReason) -> Kind {
+        Kind::Hashing { reason }
+    }
+}
+
+impl From<HashingReason> for Error {
+    fn from(reason: HashingReason) -> Error {
+        Kind::from(reason).into()
     }
 }
 
@@ -167,6 +638,12 @@ impl From<Kind> for Error {
         Self {
             kind,
             position: None,
+            field_name: None,
+            tag: None,
+            wire_type: None,
+            critical: false,
+            path: Path::default(),
+            index: None,
         }
     }
 }
@@ -0,0 +1,473 @@
+//! Stable numeric error codes
+
+use super::{Error, HashingReason, Kind};
+use core::fmt::{self, Display};
+
+/// Stable numeric codes for kinds of errors, e.g. for reporting them to
+/// other processes.
+///
+/// Unlike the [`Display`] impls of [`Error`] and [`Kind`], whose messages may
+/// change between releases, the value of each code is fixed and is never
+/// reused for a different code. Changing which errors map to which code is
+/// a breaking change.
+///
+/// Codes are displayed with their value and a short summary, e.g.
+/// `E0007: tag out of order`.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+#[non_exhaustive]
+#[repr(u16)]
+pub enum ErrorCode {
+    /// Message ended before a value was complete ([`Kind::Truncated`])
+    Truncated = 1,
+
+    /// Data followed the last field of a message ([`Kind::TrailingData`])
+    TrailingData = 2,
+
+    /// Malformed `vint64` ([`Kind::VInt64`])
+    VInt64 = 3,
+
+    /// Non-canonical `vint64` ([`Kind::NonCanonicalVInt64`])
+    NonCanonicalVInt64 = 4,
+
+    /// Unknown wire type ([`Kind::UnknownWireType`])
+    UnknownWireType = 5,
+
+    /// Field has a different wire type than expected
+    /// ([`Kind::WireTypeMismatch`])
+    WireTypeMismatch = 6,
+
+    /// Fields out of order ([`Kind::Order`] and [`Kind::TagOrder`])
+    TagOrder = 7,
+
+    /// Field repeated ([`Kind::DuplicateTag`])
+    DuplicateTag = 8,
+
+    /// Messages or sequences nested too deeply ([`Kind::NestingDepth`])
+    NestingDepth = 9,
+
+    /// Sequence nested in a sequence element ([`Kind::NestedSequence`])
+    NestedSequence = 10,
+
+    /// Digest didn't match the expected one ([`Kind::Hashing`] with
+    /// [`HashingReason::DigestMismatch`] or
+    /// [`HashingReason::AlgorithmMismatch`])
+    HashMismatch = 11,
+
+    /// Hashing failed for any other reason ([`Kind::Hashing`])
+    Hashing = 12,
+
+    /// Hasher rejected an event ([`Kind::Transcript`])
+    Transcript = 13,
+
+    /// Hasher previously failed ([`Kind::Poisoned`])
+    Poisoned = 14,
+
+    /// Bad length ([`Kind::Length`])
+    Length = 15,
+
+    /// Value exceeds the maximum length ([`Kind::TooLong`])
+    TooLong = 16,
+
+    /// Sequence exceeds the maximum number of elements
+    /// ([`Kind::TooManyElements`])
+    TooManyElements = 17,
+
+    /// Frame exceeds the maximum length ([`Kind::FrameTooLong`])
+    FrameTooLong = 18,
+
+    /// Decoding ran out of fuel ([`Kind::FuelExhausted`])
+    FuelExhausted = 19,
+
+    /// More input is needed ([`Kind::Incomplete`])
+    Incomplete = 20,
+
+    /// Malformed UTF-8 ([`Kind::Utf8`])
+    Utf8 = 21,
+
+    /// Non-normalized Unicode ([`Kind::UnicodeNormalization`])
+    UnicodeNormalization = 22,
+
+    /// Unknown critical field ([`Kind::UnknownCritical`])
+    UnknownCritical = 23,
+
+    /// Required fields are missing ([`Kind::MissingFields`])
+    MissingFields = 24,
+
+    /// Field has its default value ([`Kind::DefaultValue`])
+    DefaultValue = 25,
+
+    /// Value out of range for its field ([`Kind::OutOfRange`])
+    OutOfRange = 26,
+
+    /// Map key repeated ([`Kind::DuplicateKey`])
+    DuplicateKey = 27,
+
+    /// Map keys out of order ([`Kind::KeyOrder`])
+    KeyOrder = 28,
+
+    /// Decoding failed ([`Kind::Decode`])
+    Decode = 29,
+
+    /// Invalid field header ([`Kind::FieldHeader`])
+    FieldHeader = 30,
+
+    /// Invalid wire type ([`Kind::InvalidWireType`])
+    InvalidWireType = 31,
+
+    /// Error decoding a builtin type ([`Kind::Builtin`])
+    Builtin = 32,
+
+    /// Buffer too small ([`Kind::BufferTooSmall`])
+    BufferTooSmall = 33,
+
+    /// Field abandoned before it was finished ([`Kind::Abandoned`])
+    Abandoned = 34,
+
+    /// Invalid checkpoint ([`Kind::Checkpoint`])
+    Checkpoint = 35,
+
+    /// Invalid hasher snapshot ([`Kind::Snapshot`])
+    Snapshot = 36,
+
+    /// Malformed field header syntax ([`Kind::HeaderSyntax`])
+    HeaderSyntax = 37,
+
+    /// Tag exceeds the maximum ([`Kind::TagTooLarge`])
+    TagTooLarge = 38,
+
+    /// Unknown wire type name ([`Kind::UnknownWireTypeName`])
+    UnknownWireTypeName = 39,
+
+    /// Operation failed ([`Kind::Failed`])
+    Failed = 40,
+
+    /// More than one variant of a oneof ([`Kind::MultipleVariants`])
+    MultipleVariants = 41,
+
+    /// Decoded value was rejected by a validator ([`Kind::Validation`])
+    Validation = 42,
+
+    /// Message type ID isn't registered ([`Kind::UnknownType`])
+    UnknownType = 43,
+
+    /// Malformed test vectors ([`Kind::TestVectors`])
+    TestVectors = 44,
+}
+
+impl ErrorCode {
+    /// Get the numeric value of this code
+    pub const fn as_u16(self) -> u16 {
+        self as u16
+    }
+
+    /// Get a short summary of this code
+    pub const fn summary(self) -> &'static str {
+        match self {
+            ErrorCode::Truncated => "truncated message",
+            ErrorCode::TrailingData => "trailing data",
+            ErrorCode::VInt64 => "malformed vint64",
+            ErrorCode::NonCanonicalVInt64 => "non-canonical vint64",
+            ErrorCode::UnknownWireType => "unknown wire type",
+            ErrorCode::WireTypeMismatch => "wire type mismatch",
+            ErrorCode::TagOrder => "tag out of order",
+            ErrorCode::DuplicateTag => "duplicate field",
+            ErrorCode::NestingDepth => "nesting depth exceeded",
+            ErrorCode::NestedSequence => "nested sequence",
+            ErrorCode::HashMismatch => "hash mismatch",
+            ErrorCode::Hashing => "hashing failed",
+            ErrorCode::Transcript => "hasher rejected event",
+            ErrorCode::Poisoned => "hasher poisoned",
+            ErrorCode::Length => "bad length",
+            ErrorCode::TooLong => "value too long",
+            ErrorCode::TooManyElements => "too many elements",
+            ErrorCode::FrameTooLong => "frame too long",
+            ErrorCode::FuelExhausted => "fuel exhausted",
+            ErrorCode::Incomplete => "incomplete input",
+            ErrorCode::Utf8 => "malformed UTF-8",
+            ErrorCode::UnicodeNormalization => "non-normalized Unicode",
+            ErrorCode::UnknownCritical => "unknown critical field",
+            ErrorCode::MissingFields => "missing required fields",
+            ErrorCode::DefaultValue => "default value not omitted",
+            ErrorCode::OutOfRange => "value out of range",
+            ErrorCode::DuplicateKey => "duplicate map key",
+            ErrorCode::KeyOrder => "map keys out of order",
+            ErrorCode::Decode => "decoding failed",
+            ErrorCode::FieldHeader => "invalid field header",
+            ErrorCode::InvalidWireType => "invalid wire type",
+            ErrorCode::Builtin => "invalid builtin type",
+            ErrorCode::BufferTooSmall => "buffer too small",
+            ErrorCode::Abandoned => "field abandoned",
+            ErrorCode::Checkpoint => "invalid checkpoint",
+            ErrorCode::Snapshot => "invalid snapshot",
+            ErrorCode::HeaderSyntax => "malformed header syntax",
+            ErrorCode::TagTooLarge => "tag too large",
+            ErrorCode::UnknownWireTypeName => "unknown wire type name",
+            ErrorCode::Failed => "operation failed",
+            ErrorCode::MultipleVariants => "multiple oneof variants",
+            ErrorCode::Validation => "validation failed",
+            ErrorCode::UnknownType => "unknown message type",
+            ErrorCode::TestVectors => "malformed test vectors",
+        }
+    }
+}
+
+impl Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{:04}: {}", self.as_u16(), self.summary())
+    }
+}
+
+impl From<ErrorCode> for u16 {
+    fn from(code: ErrorCode) -> u16 {
+        code.as_u16()
+    }
+}
+
+impl From<Kind> for ErrorCode {
+    fn from(kind: Kind) -> ErrorCode {
+        match kind {
+            Kind::Abandoned { .. } => ErrorCode::Abandoned,
+            Kind::BufferTooSmall { .. } => ErrorCode::BufferTooSmall,
+            Kind::Builtin => ErrorCode::Builtin,
+            Kind::Checkpoint => ErrorCode::Checkpoint,
+            Kind::Decode { .. } => ErrorCode::Decode,
+            Kind::DefaultValue { .. } => ErrorCode::DefaultValue,
+            Kind::DuplicateKey { .. } => ErrorCode::DuplicateKey,
+            Kind::DuplicateTag { .. } => ErrorCode::DuplicateTag,
+            Kind::Failed => ErrorCode::Failed,
+            Kind::FieldHeader { .. } => ErrorCode::FieldHeader,
+            Kind::FrameTooLong { .. } => ErrorCode::FrameTooLong,
+            Kind::FuelExhausted { .. } => ErrorCode::FuelExhausted,
+            Kind::Hashing {
+                reason: HashingReason::AlgorithmMismatch,
+            }
+            | Kind::Hashing {
+                reason: HashingReason::DigestMismatch,
+            } => ErrorCode::HashMismatch,
+            Kind::Hashing { .. } => ErrorCode::Hashing,
+            Kind::HeaderSyntax => ErrorCode::HeaderSyntax,
+            Kind::Incomplete { .. } => ErrorCode::Incomplete,
+            Kind::InvalidWireType => ErrorCode::InvalidWireType,
+            Kind::KeyOrder { .. } => ErrorCode::KeyOrder,
+            Kind::Length => ErrorCode::Length,
+            Kind::MissingFields { .. } => ErrorCode::MissingFields,
+            Kind::MultipleVariants { .. } => ErrorCode::MultipleVariants,
+            Kind::NestingDepth { .. } => ErrorCode::NestingDepth,
+            Kind::NestedSequence => ErrorCode::NestedSequence,
+            Kind::NonCanonicalVInt64 => ErrorCode::NonCanonicalVInt64,
+            Kind::Order { .. } | Kind::TagOrder { .. } => ErrorCode::TagOrder,
+            Kind::OutOfRange { .. } => ErrorCode::OutOfRange,
+            Kind::Poisoned { .. } => ErrorCode::Poisoned,
+            Kind::Snapshot => ErrorCode::Snapshot,
+            Kind::TagTooLarge => ErrorCode::TagTooLarge,
+            Kind::TestVectors { .. } => ErrorCode::TestVectors,
+            Kind::TooLong { .. } => ErrorCode::TooLong,
+            Kind::TooManyElements { .. } => ErrorCode::TooManyElements,
+            Kind::TrailingData { .. } => ErrorCode::TrailingData,
+            Kind::Transcript { .. } => ErrorCode::Transcript,
+            Kind::Truncated { .. } => ErrorCode::Truncated,
+            Kind::UnicodeNormalization => ErrorCode::UnicodeNormalization,
+            Kind::UnknownCritical { .. } => ErrorCode::UnknownCritical,
+            Kind::UnknownType { .. } => ErrorCode::UnknownType,
+            Kind::UnknownWireType { .. } => ErrorCode::UnknownWireType,
+            Kind::UnknownWireTypeName => ErrorCode::UnknownWireTypeName,
+            Kind::Utf8 { .. } => ErrorCode::Utf8,
+            Kind::Validation { .. } => ErrorCode::Validation,
+            Kind::VInt64 => ErrorCode::VInt64,
+            Kind::WireTypeMismatch { .. } => ErrorCode::WireTypeMismatch,
+        }
+    }
+}
+
+impl From<Error> for ErrorCode {
+    fn from(err: Error) -> ErrorCode {
+        err.kind().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorCode, HashingReason, Kind};
+    use crate::{
+        decoder::{EventKind, StateDescription},
+        field::{Tag, WireType},
+        message::Element,
+    };
+
+    /// Every code and its value, which must never change
+    const CODES: &[(ErrorCode, u16)] = &[
+        (ErrorCode::Truncated, 1),
+        (ErrorCode::TrailingData, 2),
+        (ErrorCode::VInt64, 3),
+        (ErrorCode::NonCanonicalVInt64, 4),
+        (ErrorCode::UnknownWireType, 5),
+        (ErrorCode::WireTypeMismatch, 6),
+        (ErrorCode::TagOrder, 7),
+        (ErrorCode::DuplicateTag, 8),
+        (ErrorCode::NestingDepth, 9),
+        (ErrorCode::NestedSequence, 10),
+        (ErrorCode::HashMismatch, 11),
+        (ErrorCode::Hashing, 12),
+        (ErrorCode::Transcript, 13),
+        (ErrorCode::Poisoned, 14),
+        (ErrorCode::Length, 15),
+        (ErrorCode::TooLong, 16),
+        (ErrorCode::TooManyElements, 17),
+        (ErrorCode::FrameTooLong, 18),
+        (ErrorCode::FuelExhausted, 19),
+        (ErrorCode::Incomplete, 20),
+        (ErrorCode::Utf8, 21),
+        (ErrorCode::UnicodeNormalization, 22),
+        (ErrorCode::UnknownCritical, 23),
+        (ErrorCode::MissingFields, 24),
+        (ErrorCode::DefaultValue, 25),
+        (ErrorCode::OutOfRange, 26),
+        (ErrorCode::DuplicateKey, 27),
+        (ErrorCode::KeyOrder, 28),
+        (ErrorCode::Decode, 29),
+        (ErrorCode::FieldHeader, 30),
+        (ErrorCode::InvalidWireType, 31),
+        (ErrorCode::Builtin, 32),
+        (ErrorCode::BufferTooSmall, 33),
+        (ErrorCode::Abandoned, 34),
+        (ErrorCode::Checkpoint, 35),
+        (ErrorCode::Snapshot, 36),
+        (ErrorCode::HeaderSyntax, 37),
+        (ErrorCode::TagTooLarge, 38),
+        (ErrorCode::UnknownWireTypeName, 39),
+        (ErrorCode::Failed, 40),
+        (ErrorCode::MultipleVariants, 41),
+        (ErrorCode::Validation, 42),
+        (ErrorCode::UnknownType, 43),
+        (ErrorCode::TestVectors, 44),
+    ];
+
+    #[test]
+    fn values() {
+        for &(code, value) in CODES {
+            assert_eq!(code.as_u16(), value);
+        }
+    }
+
+    #[test]
+    fn kinds() {
+        let tag = Tag::new_const(1);
+
+        for &(kind, value) in &[
+            (Kind::Abandoned { tag }, 34),
+            (Kind::BufferTooSmall { needed: 1 }, 33),
+            (Kind::Builtin, 32),
+            (Kind::Checkpoint, 35),
+            (
+                Kind::Decode {
+                    element: Element::Value,
+                    wire_type: WireType::UInt64,
+                },
+                29,
+            ),
+            (Kind::DefaultValue { tag }, 25),
+            (Kind::DuplicateKey { tag }, 27),
+            (Kind::DuplicateTag { tag }, 8),
+            (Kind::Failed, 40),
+            (
+                Kind::FieldHeader {
+                    tag: None,
+                    wire_type: None,
+                },
+                30,
+            ),
+            (Kind::FrameTooLong { length: 2, max: 1 }, 18),
+            (Kind::FuelExhausted { consumed: 1 }, 19),
+            (HashingReason::AlgorithmMismatch.into(), 11),
+            (HashingReason::DigestMismatch.into(), 11),
+            (HashingReason::Incomplete.into(), 12),
+            (HashingReason::LengthMismatch.into(), 12),
+            (HashingReason::Nesting.into(), 12),
+            (HashingReason::UnexpectedEvent.into(), 12),
+            (HashingReason::WireTypeMismatch.into(), 12),
+            (Kind::HeaderSyntax, 37),
+            (Kind::Incomplete { needed: 1 }, 20),
+            (Kind::InvalidWireType, 31),
+            (Kind::KeyOrder { tag }, 28),
+            (Kind::Length, 15),
+            (Kind::MultipleVariants { tag }, 41),
+            (Kind::NestingDepth { depth: 2, max: 1 }, 9),
+            (Kind::NestedSequence, 10),
+            (Kind::NonCanonicalVInt64, 4),
+            (Kind::Order { tag }, 7),
+            (
+                Kind::OutOfRange {
+                    tag,
+                    value: 2,
+                    max: 1,
+                },
+                26,
+            ),
+            (Kind::Poisoned { index: 0 }, 14),
+            (Kind::Snapshot, 36),
+            (
+                Kind::TagOrder {
+                    prev: tag,
+                    next: tag,
+                },
+                7,
+            ),
+            (Kind::TagTooLarge, 38),
+            (Kind::TestVectors { reason: "invalid" }, 44),
+            (
+                Kind::TooLong {
+                    tag,
+                    wire_type: WireType::Bytes,
+                    length: 2,
+                    max: 1,
+                },
+                16,
+            ),
+            (Kind::TooManyElements { tag, max: 1 }, 17),
+            (Kind::TrailingData { remaining: 1 }, 2),
+            (
+                Kind::Transcript {
+                    state: StateDescription::Initial,
+                    event: EventKind::UInt64,
+                    index: 0,
+                    reason: HashingReason::UnexpectedEvent,
+                },
+                13,
+            ),
+            (
+                Kind::Truncated {
+                    remaining: 1,
+                    wire_type: WireType::Bytes,
+                },
+                1,
+            ),
+            (Kind::UnicodeNormalization, 22),
+            (Kind::UnknownCritical { tag }, 23),
+            (Kind::UnknownType { type_id: 1 }, 43),
+            (Kind::UnknownWireType { value: 8 }, 5),
+            (Kind::UnknownWireTypeName, 39),
+            (Kind::Utf8 { valid_up_to: 0 }, 21),
+            (Kind::Validation { reason: "invalid" }, 42),
+            (Kind::VInt64, 3),
+            (
+                Kind::WireTypeMismatch {
+                    tag,
+                    expected: WireType::Bytes,
+                    actual: WireType::String,
+                },
+                6,
+            ),
+        ] {
+            assert_eq!(ErrorCode::from(kind).as_u16(), value, "{:?}", kind);
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn display() {
+        use alloc::string::ToString;
+
+        assert_eq!(ErrorCode::TagOrder.to_string(), "E0007: tag out of order");
+        assert_eq!(ErrorCode::Failed.to_string(), "E0040: operation failed");
+    }
+}
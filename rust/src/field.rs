@@ -2,9 +2,19 @@
 
 mod header;
 pub mod length;
+mod path;
+mod tag;
+#[cfg(feature = "alloc")]
+mod unknown;
 mod wire_type;
 
-pub use self::{header::Header, wire_type::WireType};
+pub use self::{
+    header::Header,
+    path::{Iter as PathIter, Path},
+    tag::Tag,
+    wire_type::WireType,
+};
 
-/// Tag which identifies a field
-pub type Tag = u64;
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub use self::unknown::{UnknownField, UnknownFields};
@@ -1,9 +1,20 @@
 //! Field headers
 
 use super::{Tag, WireType};
+use crate::{
+    error::{self, Error},
+    spec,
+};
+use core::{
+    fmt::{self, Display},
+    str::FromStr,
+};
 use vint64::VInt64;
 
-/// Field headers
+/// Field headers.
+///
+/// Headers are displayed (and parsed by their [`FromStr`] impl) as the tag
+/// and wire type, followed by `!` for critical fields, e.g. `#7:bytes!`.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Header {
     /// Tag which identifies the field
@@ -19,7 +30,7 @@ pub struct Header {
 
 impl Header {
     /// Create a new header
-    pub fn new(tag: Tag, critical: bool, wire_type: WireType) -> Self {
+    pub const fn new(tag: Tag, critical: bool, wire_type: WireType) -> Self {
         Header {
             tag,
             critical,
@@ -40,16 +51,114 @@ impl Header {
 
 impl From<u64> for Header {
     fn from(encoded: u64) -> Self {
+        let (tag, critical, wire_type) = spec::unpack_header(encoded);
+
         Header {
-            tag: encoded >> 4,
-            critical: encoded >> 3 & 1 == 1,
-            wire_type: WireType::from_unmasked(encoded),
+            tag: Tag::from_header(tag),
+            critical,
+            wire_type: WireType::from_unmasked(wire_type.into()),
         }
     }
 }
 
 impl From<Header> for u64 {
     fn from(header: Header) -> u64 {
-        header.tag << 4 | (header.critical as u64) << 3 | header.wire_type as u64
+        spec::pack_header(
+            header.tag.to_u64(),
+            header.critical,
+            header.wire_type.to_u8(),
+        )
+    }
+}
+
+impl Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}:{}", self.tag, self.wire_type)?;
+
+        if self.critical {
+            write!(f, "!")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Header {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let (s, critical) = match s.strip_suffix('!') {
+            Some(s) => (s, true),
+            None => (s, false),
+        };
+
+        let mut parts = s
+            .strip_prefix('#')
+            .ok_or(error::Kind::HeaderSyntax)?
+            .splitn(2, ':');
+
+        let tag = parts.next().unwrap();
+        let wire_type = parts.next().ok_or(error::Kind::HeaderSyntax)?;
+
+        if tag.is_empty() || !tag.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(error::Kind::HeaderSyntax.into());
+        }
+
+        // All-digit tags only fail to parse if they overflow
+        let tag = tag
+            .parse::<u64>()
+            .map_err(|_| Error::from(error::Kind::TagTooLarge))
+            .and_then(Tag::new)?;
+
+        Ok(Header::new(tag, critical, wire_type.parse()?))
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{Header, WireType};
+    use crate::{error::Kind, field::Tag};
+    use alloc::string::ToString;
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            Header::new(Tag::new_const(7), true, WireType::Bytes).to_string(),
+            "#7:bytes!"
+        );
+        assert_eq!(
+            Header::new(Tag::new_const(0), false, WireType::UInt64).to_string(),
+            "#0:uint64"
+        );
+    }
+
+    #[test]
+    fn from_str() {
+        for header in &[
+            Header::new(Tag::new_const(7), true, WireType::Bytes),
+            Header::new(Tag::new_const(0), false, WireType::UInt64),
+            Header::new(Tag::MAX, false, WireType::Sequence),
+        ] {
+            assert_eq!(header.to_string().parse::<Header>(), Ok(*header));
+        }
+    }
+
+    #[test]
+    fn from_str_errors() {
+        for s in &["", "7:bytes", "#7", "#:bytes", "#+7:bytes", "#7!:bytes"] {
+            let err = s.parse::<Header>().err().unwrap();
+            assert_eq!(err.kind(), Kind::HeaderSyntax);
+        }
+
+        for s in &["#7:float64", "#7:bytes!!"] {
+            let err = s.parse::<Header>().err().unwrap();
+            assert_eq!(err.kind(), Kind::UnknownWireTypeName);
+        }
+
+        let too_large = (Tag::MAX.to_u64() + 1).to_string();
+        for tag in &[too_large.as_str(), "99999999999999999999999"] {
+            let err = format!("#{}:bytes", tag).parse::<Header>().err().unwrap();
+            assert_eq!(err.kind(), Kind::TagTooLarge);
+        }
     }
 }
@@ -1,7 +1,21 @@
 //! Field length calculations for various types
 
 use super::{Header, Tag, WireType};
-use crate::message::Message;
+use crate::{
+    message::{Message, MessageRef, RawMessage},
+    spec, zigzag,
+};
+
+#[cfg(feature = "alloc")]
+use crate::{map, value::Encodable};
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeMap;
+
+/// Compute length of a `bool` field, which is encoded entirely by its
+/// header
+pub fn bool(tag: Tag) -> usize {
+    header(tag, WireType::False)
+}
 
 /// Compute length of a `uint64` field
 pub fn uint64(tag: Tag, value: u64) -> usize {
@@ -10,7 +24,7 @@ pub fn uint64(tag: Tag, value: u64) -> usize {
 
 /// Compute length of an `sint64` field
 pub fn sint64(tag: Tag, value: i64) -> usize {
-    header(tag, WireType::SInt64) + vint64::signed::encoded_len(value)
+    header(tag, WireType::SInt64) + vint64::encoded_len(zigzag::encode(value))
 }
 
 /// Compute length of a `bytes` field
@@ -28,18 +42,153 @@ pub fn message(tag: Tag, message: &dyn Message) -> usize {
     dynamically_sized(tag, WireType::Message, message.encoded_len())
 }
 
+/// Compute length of a `message` field containing a [`MessageRef`]
+/// including the tag and delimiter
+pub fn message_ref<'a, M: MessageRef<'a>>(tag: Tag, message: &M) -> usize {
+    dynamically_sized(tag, WireType::Message, message.encoded_len())
+}
+
+/// Compute length of a `message` field containing a [`RawMessage`] body,
+/// including the tag and delimiter
+pub fn raw_message(tag: Tag, message: RawMessage<'_>) -> usize {
+    dynamically_sized(tag, WireType::Message, message.as_bytes().len())
+}
+
 /// Compute length of a `sequence` of `message` values including the tag and delimiter
-pub fn message_seq<'a>(tag: Tag, messages: impl Iterator<Item = &'a dyn Message>) -> usize {
-    let body_len: usize = messages
+pub fn message_seq<'a, M>(tag: Tag, messages: impl Iterator<Item = &'a M>) -> usize
+where
+    M: Message + ?Sized + 'a,
+{
+    sequence(tag, WireType::Message, message_seq_body(messages))
+}
+
+/// Compute length of a map (i.e. a `sequence` of key/value entry messages)
+/// including the tag and header
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub fn map<K, V>(tag: Tag, map: &BTreeMap<K, V>) -> usize
+where
+    K: Encodable,
+    V: Encodable,
+{
+    sequence(tag, WireType::Message, map_body(map))
+}
+
+/// Compute length of a `sequence` of `uint64` values including the tag and header
+pub fn uint64_seq(tag: Tag, values: &[u64]) -> usize {
+    sequence(tag, WireType::UInt64, uint64_seq_body(values))
+}
+
+/// Compute length of a `sequence` of `sint64` values including the tag and header
+pub fn sint64_seq(tag: Tag, values: &[i64]) -> usize {
+    sequence(tag, WireType::SInt64, sint64_seq_body(values))
+}
+
+/// Compute length of a `sequence` of `bytes` values including the tag and header
+pub fn bytes_seq<B: AsRef<[u8]>>(tag: Tag, values: &[B]) -> usize {
+    sequence(tag, WireType::Bytes, bytes_seq_body(values))
+}
+
+/// Compute length of a `sequence` of `string` values including the tag and header
+pub fn string_seq<S: AsRef<str>>(tag: Tag, values: &[S]) -> usize {
+    sequence(tag, WireType::String, string_seq_body(values))
+}
+
+/// Compute length of a `sequence` of sequences of `uint64` values including
+/// the tag and header
+pub fn uint64_seq_seq<S: AsRef<[u64]>>(tag: Tag, seqs: &[S]) -> usize {
+    let body_len = seqs
+        .iter()
+        .map(|seq| nested_sequence(WireType::UInt64, uint64_seq_body(seq.as_ref())))
+        .sum();
+
+    sequence(tag, WireType::Sequence, body_len)
+}
+
+/// Compute length of a `sequence` of sequences of `sint64` values including
+/// the tag and header
+pub fn sint64_seq_seq<S: AsRef<[i64]>>(tag: Tag, seqs: &[S]) -> usize {
+    let body_len = seqs
+        .iter()
+        .map(|seq| nested_sequence(WireType::SInt64, sint64_seq_body(seq.as_ref())))
+        .sum();
+
+    sequence(tag, WireType::Sequence, body_len)
+}
+
+/// Compute length of the body of a sequence of `uint64` values
+pub(crate) fn uint64_seq_body<'v>(values: impl IntoIterator<Item = &'v u64>) -> usize {
+    values
+        .into_iter()
+        .map(|&value| vint64::encoded_len(value))
+        .sum()
+}
+
+/// Compute length of the body of a sequence of `sint64` values
+pub(crate) fn sint64_seq_body<'v>(values: impl IntoIterator<Item = &'v i64>) -> usize {
+    values
+        .into_iter()
+        .map(|&value| vint64::encoded_len(zigzag::encode(value)))
+        .sum()
+}
+
+/// Compute length of the body of a sequence of `bytes` values
+pub(crate) fn bytes_seq_body<B: AsRef<[u8]>>(values: impl IntoIterator<Item = B>) -> usize {
+    values
+        .into_iter()
+        .map(|value| {
+            let len = value.as_ref().len();
+            vint64::encoded_len(len as u64) + len
+        })
+        .sum()
+}
+
+/// Compute length of the body of a sequence of `string` values
+pub(crate) fn string_seq_body<S: AsRef<str>>(values: impl IntoIterator<Item = S>) -> usize {
+    values
+        .into_iter()
+        .map(|value| {
+            let len = value.as_ref().len();
+            vint64::encoded_len(len as u64) + len
+        })
+        .sum()
+}
+
+/// Compute length of the body of a sequence of `message` values
+pub(crate) fn message_seq_body<'a, M>(messages: impl Iterator<Item = &'a M>) -> usize
+where
+    M: Message + ?Sized + 'a,
+{
+    messages
         .map(|msg| {
             let encoded_len = msg.encoded_len();
             vint64::encoded_len(encoded_len as u64) + encoded_len
         })
-        .sum();
+        .sum()
+}
 
-    header(tag, WireType::Sequence)
-        + vint64::encoded_len((body_len as u64) << 4 | WireType::Message as u64)
-        + body_len
+/// Compute length of the body of a map's sequence of entry messages
+#[cfg(feature = "alloc")]
+pub(crate) fn map_body<K, V>(map: &BTreeMap<K, V>) -> usize
+where
+    K: Encodable,
+    V: Encodable,
+{
+    map.iter()
+        .map(|(key, value)| {
+            let entry_len = map::entry_len(key, value);
+            vint64::encoded_len(entry_len as u64) + entry_len
+        })
+        .sum()
+}
+
+/// Compute length of a sequence nested in another sequence, including its
+/// header
+pub(crate) fn nested_sequence(wire_type: WireType, body_len: usize) -> usize {
+    vint64::encoded_len(spec::pack_sequence_header(
+        body_len as u64,
+        wire_type.to_u8(),
+    )) + body_len
 }
 
 /// Compute length of a field header
@@ -48,8 +197,13 @@ fn header(tag: Tag, wire_type: WireType) -> usize {
     Header::new(tag, false, wire_type).encoded_len()
 }
 
+/// Compute length of a sequence field with the given element type and body length
+pub(crate) fn sequence(tag: Tag, wire_type: WireType, body_len: usize) -> usize {
+    header(tag, WireType::Sequence) + nested_sequence(wire_type, body_len)
+}
+
 /// Compute length of a dynamically sized field
-fn dynamically_sized(tag: Tag, wire_type: WireType, length: usize) -> usize {
+pub(crate) fn dynamically_sized(tag: Tag, wire_type: WireType, length: usize) -> usize {
     header(tag, wire_type) + vint64::encoded_len(length as u64) + length
 }
 
@@ -59,21 +213,39 @@ mod tests {
 
     #[test]
     fn uint64_length() {
-        assert_eq!(uint64(1, 42), 2);
+        assert_eq!(uint64(Tag::new_const(1), 42), 2);
     }
 
     #[test]
     fn sint64_length() {
-        assert_eq!(sint64(2, -42), 2);
+        assert_eq!(sint64(Tag::new_const(2), -42), 2);
     }
 
     #[test]
     fn bytes_length() {
-        assert_eq!(bytes(3, b"foobar"), 8)
+        assert_eq!(bytes(Tag::new_const(3), b"foobar"), 8)
     }
 
     #[test]
     fn string_length() {
-        assert_eq!(string(4, "baz"), 5);
+        assert_eq!(string(Tag::new_const(4), "baz"), 5);
+    }
+
+    #[test]
+    fn uint64_seq_length() {
+        assert_eq!(uint64_seq(Tag::new_const(5), &[]), 2);
+        assert_eq!(uint64_seq(Tag::new_const(5), &[42, 128]), 5);
+    }
+
+    #[test]
+    fn sint64_seq_length() {
+        assert_eq!(sint64_seq(Tag::new_const(6), &[-42, 64]), 5);
+    }
+
+    #[test]
+    fn seq_seq_length() {
+        assert_eq!(uint64_seq_seq::<&[u64]>(Tag::new_const(7), &[]), 2);
+        assert_eq!(uint64_seq_seq(Tag::new_const(7), &[&[42, 128][..], &[]]), 7);
+        assert_eq!(sint64_seq_seq(Tag::new_const(8), &[[-42, 64]]), 7);
     }
 }
@@ -0,0 +1,211 @@
+//! Paths of field tags through nested messages
+
+use super::Tag;
+use core::fmt::{self, Debug, Display};
+
+/// Number of bytes available for storing the `vint64`-encoded tags of a
+/// [`Path`]
+const CAPACITY: usize = 22;
+
+/// Tags of the fields containing a nested message, outermost first (e.g.
+/// `3.7` for a message in field 7 of the message in field 3).
+///
+/// Tags are stored inline (`vint64`-encoded) so paths can be carried by
+/// [`Error`][`crate::Error`] without allocating. If a path is too long to
+/// fit, its outermost tags are dropped and it's marked as truncated.
+#[derive(Copy, Clone, Default, Eq, PartialEq)]
+pub struct Path {
+    /// `vint64`-encoded tags
+    bytes: [u8; CAPACITY],
+
+    /// Number of bytes of encoded tags
+    len: u8,
+
+    /// Were outermost tags dropped to fit the path?
+    truncated: bool,
+}
+
+impl Path {
+    /// Create a path from the given tags, outermost first
+    pub(crate) fn new(tags: &[Tag]) -> Self {
+        let mut path = Self::default();
+
+        // Find the innermost tags which fit
+        let mut encoded_len = 0;
+        let mut start = tags.len();
+
+        for tag in tags.iter().rev() {
+            let len = vint64::encoded_len(tag.to_u64());
+
+            if encoded_len + len > CAPACITY {
+                path.truncated = true;
+                break;
+            }
+
+            encoded_len += len;
+            start -= 1;
+        }
+
+        for tag in &tags[start..] {
+            let encoded = vint64::encode(tag.to_u64());
+            let len = path.len as usize;
+            let new_len = len + encoded.as_ref().len();
+            path.bytes[len..new_len].copy_from_slice(encoded.as_ref());
+            path.len = new_len as u8;
+        }
+
+        path
+    }
+
+    /// Create a new path with the given tag prepended as the outermost
+    pub(crate) fn prepend(&self, tag: Tag) -> Self {
+        let encoded = vint64::encode(tag.to_u64());
+        let tag_len = encoded.as_ref().len();
+        let len = self.len as usize;
+
+        // Drop the new outermost tag if it doesn't fit
+        if len + tag_len > CAPACITY {
+            return Self {
+                truncated: true,
+                ..*self
+            };
+        }
+
+        let mut path = Self {
+            len: (len + tag_len) as u8,
+            ..*self
+        };
+
+        path.bytes[..tag_len].copy_from_slice(encoded.as_ref());
+        path.bytes[tag_len..len + tag_len].copy_from_slice(&self.bytes[..len]);
+        path
+    }
+
+    /// Iterate over the tags in this path, outermost first
+    pub fn iter(&self) -> Iter<'_> {
+        Iter(&self.bytes[..self.len as usize])
+    }
+
+    /// Is this path empty, i.e. does it refer to the outermost message?
+    pub fn is_empty(&self) -> bool {
+        self.len == 0 && !self.truncated
+    }
+
+    /// Were the outermost tags of this path dropped because it was too long?
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl Debug for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Path")
+            .field("tags", &DebugTags(self))
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.truncated {
+            write!(f, "...")?;
+        }
+
+        for (i, tag) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+
+            write!(f, "{}", tag)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a Path {
+    type Item = Tag;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over the tags in a [`Path`]
+#[derive(Clone, Debug)]
+pub struct Iter<'a>(&'a [u8]);
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Tag;
+
+    fn next(&mut self) -> Option<Tag> {
+        if self.0.is_empty() {
+            None
+        } else {
+            // Tags were encoded by `Path::new` or `Path::prepend`, so they
+            // always decode
+            Some(Tag::new_const(vint64::decode(&mut self.0).unwrap()))
+        }
+    }
+}
+
+/// Helper for debug-formatting the tags in a path as a list
+struct DebugTags<'a>(&'a Path);
+
+impl<'a> Debug for DebugTags<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.iter()).finish()
+    }
+}
+
+#[cfg(all(test, feature = "alloc"))]
+mod tests {
+    use super::{Path, CAPACITY};
+    use crate::field::Tag;
+    use alloc::string::ToString;
+
+    #[test]
+    fn display() {
+        assert_eq!(Path::new(&[]).to_string(), "");
+        assert_eq!(
+            Path::new(&[Tag::new_const(3), Tag::new_const(7), Tag::new_const(1)]).to_string(),
+            "3.7.1"
+        );
+        assert_eq!(
+            Path::new(&[Tag::new_const(3), Tag::new_const(1 << 40)]).to_string(),
+            "3.1099511627776"
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let mut tags = [Tag::new_const(1); CAPACITY + 2];
+        tags[CAPACITY + 1] = Tag::new_const(2);
+
+        let path = Path::new(&tags);
+        assert!(path.is_truncated());
+        assert_eq!(path.iter().count(), CAPACITY);
+        assert_eq!(path.iter().last(), Some(Tag::new_const(2)));
+        assert!(path.to_string().starts_with("...1.1."));
+    }
+
+    #[test]
+    fn prepend() {
+        assert_eq!(
+            Path::new(&[]).prepend(Tag::new_const(3)),
+            Path::new(&[Tag::new_const(3)])
+        );
+        assert_eq!(
+            Path::new(&[Tag::new_const(7), Tag::new_const(1)]).prepend(Tag::new_const(3)),
+            Path::new(&[Tag::new_const(3), Tag::new_const(7), Tag::new_const(1)])
+        );
+
+        // Tags which don't fit are dropped
+        let path = Path::new(&[Tag::new_const(1); CAPACITY]).prepend(Tag::new_const(2));
+        assert!(path.is_truncated());
+        assert_eq!(path.iter().count(), CAPACITY);
+        assert!(path.iter().all(|tag| tag == Tag::new_const(1)));
+    }
+}
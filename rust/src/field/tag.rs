@@ -0,0 +1,97 @@
+//! Field tags
+
+use crate::{
+    error::{self, Error},
+    spec,
+};
+use core::{
+    convert::TryFrom,
+    fmt::{self, Display},
+};
+
+/// Tag which identifies a field.
+///
+/// Tags share a `vint64` with the wire type and critical bit in field
+/// headers, so they're at most [`Tag::MAX`] (2<sup>60</sup> - 1).
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq, PartialOrd, Ord)]
+pub struct Tag(u64);
+
+impl Tag {
+    /// Largest tag which can be encoded in a field header
+    pub const MAX: Tag = Tag(spec::MAX_TAG);
+
+    /// Create a new [`Tag`], returning [`error::Kind::TagTooLarge`] if it
+    /// exceeds [`Tag::MAX`]
+    pub fn new(tag: u64) -> Result<Self, Error> {
+        if tag <= Self::MAX.0 {
+            Ok(Tag(tag))
+        } else {
+            Err(error::Kind::TagTooLarge.into())
+        }
+    }
+
+    /// Create a new [`Tag`] from a literal, panicking if it exceeds
+    /// [`Tag::MAX`].
+    ///
+    /// Evaluated in a `const` context, e.g. `const TAG: Tag =
+    /// Tag::new_const(7);`, out-of-range tags fail to compile.
+    pub const fn new_const(tag: u64) -> Self {
+        // Panicking in `const fn` needs Rust 1.57, so fail with an
+        // out-of-bounds index instead if the tag exceeds `Tag::MAX`
+        #[allow(clippy::no_effect)]
+        [()][(tag > Self::MAX.0) as usize];
+        Tag(tag)
+    }
+
+    /// Create a new [`Tag`] from the tag unpacked from a field header,
+    /// which is always in range
+    pub(super) const fn from_header(tag: u64) -> Self {
+        Tag(tag)
+    }
+
+    /// Get the numeric value of this tag
+    pub const fn to_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl From<Tag> for u64 {
+    fn from(tag: Tag) -> u64 {
+        tag.0
+    }
+}
+
+impl TryFrom<u64> for Tag {
+    type Error = Error;
+
+    fn try_from(tag: u64) -> Result<Self, Error> {
+        Self::new(tag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tag;
+    use crate::error::Kind;
+
+    #[test]
+    fn new() {
+        assert_eq!(Tag::new(7).unwrap(), Tag::new_const(7));
+        assert_eq!(Tag::new(Tag::MAX.to_u64()).unwrap(), Tag::MAX);
+
+        let err = Tag::new(Tag::MAX.to_u64() + 1).err().unwrap();
+        assert_eq!(err.kind(), Kind::TagTooLarge);
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_const_too_large() {
+        Tag::new_const(u64::MAX);
+    }
+}
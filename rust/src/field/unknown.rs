@@ -0,0 +1,114 @@
+//! Preservation of fields unknown to a message's schema
+
+use super::{Header, Tag};
+use alloc::vec::Vec;
+use core::slice;
+
+/// Field which isn't part of a message's schema, preserved as it was
+/// serialized so it can be re-encoded byte-for-byte.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnknownField {
+    /// Header of the field
+    pub header: Header,
+
+    /// Serialized value of the field following its header, including any
+    /// length delimiter or sequence header
+    pub value: Vec<u8>,
+}
+
+impl UnknownField {
+    /// Create a new unknown field from its header and serialized value
+    pub fn new(header: Header, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            header,
+            value: value.into(),
+        }
+    }
+
+    /// Get the tag of this field
+    pub fn tag(&self) -> Tag {
+        self.header.tag
+    }
+
+    /// Get the length of this field when encoded, including its header
+    pub fn encoded_len(&self) -> usize {
+        self.header
+            .encoded_len()
+            .checked_add(self.value.len())
+            .unwrap()
+    }
+}
+
+/// Fields which aren't part of a message's schema, ordered by tag.
+///
+/// Used as a `#[field(unknown)]` member of a struct deriving `Message`, it
+/// collects the non-critical fields the decoder skips, which are re-encoded
+/// in tag order interleaved with the struct's known fields. This allows
+/// messages to pass through code built against an older schema unmodified,
+/// keeping their Verihash digests intact.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct UnknownFields(Vec<UnknownField>);
+
+impl UnknownFields {
+    /// Create an empty set of unknown fields
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the field with the given tag, if present
+    pub fn get(&self, tag: Tag) -> Option<&UnknownField> {
+        self.position(tag).ok().map(|i| &self.0[i])
+    }
+
+    /// Insert a field, replacing and returning any existing field with the
+    /// same tag
+    pub fn insert(&mut self, field: UnknownField) -> Option<UnknownField> {
+        match self.position(field.tag()) {
+            Ok(i) => Some(core::mem::replace(&mut self.0[i], field)),
+            Err(i) => {
+                self.0.insert(i, field);
+                None
+            }
+        }
+    }
+
+    /// Remove and return the field with the given tag, if present
+    pub fn remove(&mut self, tag: Tag) -> Option<UnknownField> {
+        self.position(tag).ok().map(|i| self.0.remove(i))
+    }
+
+    /// Iterate over the fields in tag order
+    pub fn iter(&self) -> slice::Iter<'_, UnknownField> {
+        self.0.iter()
+    }
+
+    /// Get the number of fields
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Are there no unknown fields?
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Get the total length of these fields when encoded
+    pub fn encoded_len(&self) -> usize {
+        self.0.iter().map(UnknownField::encoded_len).sum()
+    }
+
+    /// Find the index of the field with the given tag, or where it would be
+    /// inserted
+    fn position(&self, tag: Tag) -> Result<usize, usize> {
+        self.0.binary_search_by_key(&tag, UnknownField::tag)
+    }
+}
+
+impl<'a> IntoIterator for &'a UnknownFields {
+    type Item = &'a UnknownField;
+    type IntoIter = slice::Iter<'a, UnknownField>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
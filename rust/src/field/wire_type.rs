@@ -1,52 +1,105 @@
 //! Veriform wire types
 
+use super::Tag;
+use crate::spec;
 pub use crate::{
     error::{self, Error},
     message::Element,
 };
-use core::convert::TryFrom;
+use core::{
+    convert::TryFrom,
+    fmt::{self, Display},
+    str::FromStr,
+};
 
 /// Wire type identifiers for Veriform types
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
-#[repr(u64)]
+#[repr(u8)]
 pub enum WireType {
     /// False (boolean)
-    False = 0,
+    False = spec::WIRE_TYPE_FALSE,
 
     /// True (boolean)
-    True = 1,
+    True = spec::WIRE_TYPE_TRUE,
 
     /// 64-bit unsigned integer
-    UInt64 = 2,
+    UInt64 = spec::WIRE_TYPE_UINT64,
 
     /// 64-bit (zigzag) signed integer
-    SInt64 = 3,
+    SInt64 = spec::WIRE_TYPE_SINT64,
 
     /// Binary data
-    Bytes = 4,
+    Bytes = spec::WIRE_TYPE_BYTES,
 
     /// Unicode string
-    String = 5,
+    String = spec::WIRE_TYPE_STRING,
 
     /// Nested Veriform message
-    Message = 6,
+    Message = spec::WIRE_TYPE_MESSAGE,
 
-    /// Sequences
-    Sequence = 7,
+    /// Sequences, prefixed by their body length and element wire type.
+    ///
+    /// Elements may themselves be sequences (each with its own prefix), but
+    /// only one level of nesting is supported.
+    Sequence = spec::WIRE_TYPE_SEQUENCE,
 }
 
 impl WireType {
+    /// Decode a [`WireType`] from its numeric identifier, if it's valid.
+    ///
+    /// The identifiers themselves are defined in [`spec`]: the [`TryFrom`]
+    /// impls and [`WireType::from_unmasked`] are built on this function.
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            spec::WIRE_TYPE_FALSE => Some(WireType::False),
+            spec::WIRE_TYPE_TRUE => Some(WireType::True),
+            spec::WIRE_TYPE_UINT64 => Some(WireType::UInt64),
+            spec::WIRE_TYPE_SINT64 => Some(WireType::SInt64),
+            spec::WIRE_TYPE_BYTES => Some(WireType::Bytes),
+            spec::WIRE_TYPE_STRING => Some(WireType::String),
+            spec::WIRE_TYPE_MESSAGE => Some(WireType::Message),
+            spec::WIRE_TYPE_SEQUENCE => Some(WireType::Sequence),
+            _ => None,
+        }
+    }
+
     /// Decode a [`WireType`] from an unmasked u64
-    pub fn from_unmasked(value: u64) -> Self {
-        // Never panics because all 3-bit wire types are valid
-        Self::try_from(value & 0b111).unwrap()
+    pub const fn from_unmasked(value: u64) -> Self {
+        match Self::from_u8((value & spec::WIRE_TYPE_MASK) as u8) {
+            Some(wire_type) => wire_type,
+            // Unreachable, as all 3-bit wire types are valid (panicking in
+            // `const fn` needs Rust 1.57)
+            None => WireType::Sequence,
+        }
+    }
+
+    /// Is this a dynamically-sized [`WireType`], i.e. are its values length
+    /// prefixed?
+    pub const fn is_dynamically_sized(self) -> bool {
+        matches!(
+            self,
+            WireType::Bytes | WireType::String | WireType::Message | WireType::Sequence
+        )
+    }
+
+    /// Is this a fixed-size [`WireType`], i.e. a boolean or integer whose
+    /// value isn't length prefixed?
+    pub const fn is_fixed_size(self) -> bool {
+        !self.is_dynamically_sized()
     }
 
-    /// Is this a dynamically-sized [`WireType`]?
-    pub fn is_dynamically_sized(self) -> bool {
+    /// Get the name of this [`WireType`], as used by its [`Display`] and
+    /// [`FromStr`] impls (e.g. `uint64`)
+    pub const fn name(self) -> &'static str {
         match self {
-            WireType::Bytes | WireType::String | WireType::Message | WireType::Sequence => true,
-            _ => false,
+            WireType::False => "false",
+            WireType::True => "true",
+            WireType::UInt64 => "uint64",
+            WireType::SInt64 => "sint64",
+            WireType::Bytes => "bytes",
+            WireType::String => "string",
+            WireType::Message => "message",
+            WireType::Sequence => "sequence",
         }
     }
 
@@ -55,6 +108,22 @@ impl WireType {
         self as u8
     }
 
+    /// Check that this wire type, found in the field with the given tag (or
+    /// in the elements of a sequence in that field), is the expected one,
+    /// returning [`error::Kind::WireTypeMismatch`] if not
+    pub(crate) fn check(self, tag: Tag, expected: WireType) -> Result<(), Error> {
+        if self == expected {
+            Ok(())
+        } else {
+            Err(error::Kind::WireTypeMismatch {
+                tag,
+                expected,
+                actual: self,
+            }
+            .into())
+        }
+    }
+
     /// Create a decoding error for this given wire type.
     ///
     /// This method is primarily intended to be used by `veriform_derive`.
@@ -67,20 +136,87 @@ impl WireType {
     }
 }
 
+impl Display for WireType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for WireType {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Error> {
+        (0..=7)
+            .map(WireType::from_unmasked)
+            .find(|wire_type| wire_type.name() == name)
+            .ok_or_else(|| error::Kind::UnknownWireTypeName.into())
+    }
+}
+
+impl TryFrom<u8> for WireType {
+    type Error = Error;
+
+    fn try_from(encoded: u8) -> Result<Self, Error> {
+        Self::from_u8(encoded).ok_or_else(|| {
+            error::Kind::UnknownWireType {
+                value: encoded.into(),
+            }
+            .into()
+        })
+    }
+}
+
 impl TryFrom<u64> for WireType {
     type Error = Error;
 
     fn try_from(encoded: u64) -> Result<Self, Error> {
-        match encoded {
-            0 => Ok(WireType::False),
-            1 => Ok(WireType::True),
-            2 => Ok(WireType::UInt64),
-            3 => Ok(WireType::SInt64),
-            4 => Ok(WireType::Bytes),
-            5 => Ok(WireType::String),
-            6 => Ok(WireType::Message),
-            7 => Ok(WireType::Sequence),
-            _ => Err(error::Kind::InvalidWireType.into()),
+        u8::try_from(encoded)
+            .ok()
+            .and_then(Self::from_u8)
+            .ok_or_else(|| error::Kind::UnknownWireType { value: encoded }.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WireType;
+    use crate::error::Kind;
+    use core::convert::TryFrom;
+
+    #[test]
+    fn round_trip() {
+        for value in 0..=7u8 {
+            let wire_type = WireType::try_from(value).unwrap();
+            assert_eq!(wire_type.to_u8(), value);
+            assert_eq!(WireType::from_unmasked(u64::from(value) | 0xF0), wire_type);
+            assert_ne!(wire_type.is_fixed_size(), wire_type.is_dynamically_sized());
         }
     }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn names() {
+        use alloc::string::ToString;
+
+        for value in 0..=7 {
+            let wire_type = WireType::from_unmasked(value);
+            assert_eq!(wire_type.to_string().parse::<WireType>(), Ok(wire_type));
+        }
+
+        assert_eq!(WireType::UInt64.to_string(), "uint64");
+
+        for name in &["", "UInt64", "uint32", "bytes "] {
+            let err = name.parse::<WireType>().err().unwrap();
+            assert_eq!(err.kind(), Kind::UnknownWireTypeName);
+        }
+    }
+
+    #[test]
+    fn unknown() {
+        let err = WireType::try_from(8u8).err().unwrap();
+        assert_eq!(err.kind(), Kind::UnknownWireType { value: 8 });
+
+        let err = WireType::try_from(0x100u64).err().unwrap();
+        assert_eq!(err.kind(), Kind::UnknownWireType { value: 0x100 });
+    }
 }
@@ -0,0 +1,286 @@
+//! Length-prefixed framing for storing many messages in a single file or
+//! stream, e.g. an append-only log.
+//!
+//! Each frame consists of a `vint64` length prefix followed by that many
+//! bytes of message. Frames are read back from a byte slice with
+//! [`Frames`], or from an [`std::io::Read`] with [`FrameDecoder`] (which
+//! requires the `std` feature). With the `tokio-codec` feature,
+//! [`VeriformCodec`] decodes and encodes frames for [`tokio_util::codec`]
+//! transports.
+//!
+//! If a writer crashes in the middle of appending a frame, reading the last
+//! frame fails with [`error::Kind::Truncated`]. The error's position is the
+//! offset of the start of the truncated frame, so every preceding frame is
+//! still readable, and the file can be truncated there to recover it.
+
+#[cfg(feature = "tokio-codec")]
+mod codec;
+#[cfg(feature = "std")]
+mod stream;
+
+#[cfg(feature = "tokio-codec")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tokio-codec")))]
+pub use self::codec::{CodecError, VeriformCodec};
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use self::stream::{FrameDecoder, FrameEncoder};
+
+use crate::{
+    error::{self, Error},
+    field::{Path, WireType},
+};
+use core::convert::TryFrom;
+
+/// Default maximum length of a frame's message (16 MiB)
+pub const DEFAULT_MAX_FRAME_LEN: usize = 0x100_0000;
+
+/// Get the length of a frame containing a message of the given length,
+/// including its length prefix
+pub fn frame_len(message_len: usize) -> usize {
+    vint64::encoded_len(message_len as u64)
+        .checked_add(message_len)
+        .unwrap()
+}
+
+/// Encode a frame containing the given message into the given buffer,
+/// returning the encoded frame.
+///
+/// Returns [`error::Kind::BufferTooSmall`] if the frame doesn't fit.
+pub fn encode_frame<'a>(message: &[u8], buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+    let needed = frame_len(message.len());
+
+    if needed > buffer.len() {
+        return Err(error::Kind::BufferTooSmall { needed }.into());
+    }
+
+    let prefix = vint64::encode(message.len() as u64);
+    let (frame, _) = buffer.split_at_mut(needed);
+    let (prefix_buf, message_buf) = frame.split_at_mut(prefix.as_ref().len());
+    prefix_buf.copy_from_slice(prefix.as_ref());
+    message_buf.copy_from_slice(message);
+    Ok(frame)
+}
+
+/// Decode the frame at the start of the input, advancing it past the frame
+/// and returning its message, or `Ok(None)` if the input is empty.
+///
+/// Frames whose messages are longer than `max_frame_len` are rejected with
+/// [`error::Kind::FrameTooLong`], and frames which extend past the end of
+/// the input with [`error::Kind::Truncated`]. The input isn't advanced if
+/// an error occurs.
+pub fn decode_frame<'a>(
+    input: &mut &'a [u8],
+    max_frame_len: usize,
+) -> Result<Option<&'a [u8]>, Error> {
+    let first = match input.first() {
+        Some(&first) => first,
+        None => return Ok(None),
+    };
+
+    let prefix_len = vint64::decoded_len(first);
+
+    if prefix_len > input.len() {
+        return Err(truncated(prefix_len - input.len()));
+    }
+
+    let mut rest = *input;
+    let length = decode_frame_len(&mut rest, max_frame_len)?;
+
+    if length > rest.len() {
+        return Err(truncated(length - rest.len()));
+    }
+
+    let (message, rest) = rest.split_at(length);
+    *input = rest;
+    Ok(Some(message))
+}
+
+/// Iterator over the messages in a byte slice containing frames.
+///
+/// Iteration stops after the first error, which includes the offset of the
+/// start of the frame which couldn't be decoded.
+#[derive(Clone, Debug)]
+pub struct Frames<'a> {
+    /// Remaining input
+    input: &'a [u8],
+
+    /// Offset of the remaining input
+    position: usize,
+
+    /// Maximum length of a frame's message
+    max_frame_len: usize,
+
+    /// Did an error occur?
+    failed: bool,
+}
+
+impl<'a> Frames<'a> {
+    /// Iterate over the frames in the given input
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            input,
+            position: 0,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            failed: false,
+        }
+    }
+
+    /// Set the maximum length of a frame's message
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Get the offset of the next frame within the input
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Get the input which hasn't been decoded yet
+    pub fn remaining(&self) -> &'a [u8] {
+        self.input
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Result<&'a [u8], Error>;
+
+    fn next(&mut self) -> Option<Result<&'a [u8], Error>> {
+        if self.failed {
+            return None;
+        }
+
+        let len = self.input.len();
+
+        match decode_frame(&mut self.input, self.max_frame_len) {
+            Ok(frame) => {
+                self.position += len - self.input.len();
+                frame.map(Ok)
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err.with_context(self.position, None, Path::default())))
+            }
+        }
+    }
+}
+
+/// Decode the length prefix of a frame, ensuring it's within the maximum
+pub(crate) fn decode_frame_len(input: &mut &[u8], max_frame_len: usize) -> Result<usize, Error> {
+    let length = vint64::decode(input)?;
+
+    match usize::try_from(length) {
+        Ok(length) if length <= max_frame_len => Ok(length),
+        _ => Err(error::Kind::FrameTooLong {
+            length,
+            max: max_frame_len,
+        }
+        .into()),
+    }
+}
+
+/// Create an error for a frame which is missing the given number of bytes
+pub(crate) fn truncated(remaining: usize) -> Error {
+    error::Kind::Truncated {
+        remaining,
+        wire_type: WireType::Message,
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_frame, encode_frame, frame_len, Frames};
+    use crate::{error, field::WireType};
+
+    /// Three frames: `{ 1: uint64(42) }`, an empty message, and 200 bytes
+    fn example_frames(buffer: &mut [u8]) -> usize {
+        let mut len = 0;
+
+        for message in &[&[37, 85][..], &[], &[0; 200]] {
+            len += encode_frame(message, &mut buffer[len..]).unwrap().len();
+        }
+
+        len
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = [0u8; 256];
+        let len = example_frames(&mut buffer);
+        assert_eq!(len, frame_len(2) + frame_len(0) + frame_len(200));
+
+        let mut frames = Frames::new(&buffer[..len]);
+        assert_eq!(frames.next().unwrap().unwrap(), &[37, 85]);
+        assert_eq!(frames.next().unwrap().unwrap(), &[] as &[u8]);
+        assert_eq!(frames.next().unwrap().unwrap(), &[0; 200][..]);
+        assert!(frames.next().is_none());
+        assert_eq!(frames.position(), len);
+    }
+
+    #[test]
+    fn buffer_too_small() {
+        let err = encode_frame(&[37, 85], &mut [0u8; 2]).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::BufferTooSmall { needed: 3 });
+    }
+
+    #[test]
+    fn truncated_final_frame() {
+        let mut buffer = [0u8; 256];
+        let len = example_frames(&mut buffer);
+
+        // Every truncation of the last frame (including of its 2-byte length
+        // prefix) leaves the preceding frames readable
+        let last_frame = len - frame_len(200);
+
+        for truncated_len in last_frame + 1..len {
+            let mut frames = Frames::new(&buffer[..truncated_len]);
+            assert!(frames.next().unwrap().is_ok());
+            assert!(frames.next().unwrap().is_ok());
+
+            let remaining = if truncated_len == last_frame + 1 {
+                1
+            } else {
+                len - truncated_len
+            };
+
+            let err = frames.next().unwrap().err().unwrap();
+            assert_eq!(err.position(), Some(last_frame));
+            assert_eq!(
+                err.kind(),
+                error::Kind::Truncated {
+                    remaining,
+                    wire_type: WireType::Message
+                }
+            );
+
+            assert!(frames.next().is_none());
+        }
+    }
+
+    #[test]
+    fn frame_too_long() {
+        let mut buffer = [0u8; 256];
+        let len = example_frames(&mut buffer);
+
+        let mut frames = Frames::new(&buffer[..len]).with_max_frame_len(100);
+        assert!(frames.next().unwrap().is_ok());
+        assert!(frames.next().unwrap().is_ok());
+
+        let err = frames.next().unwrap().err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::FrameTooLong {
+                length: 200,
+                max: 100
+            }
+        );
+    }
+
+    #[test]
+    fn decode_empty() {
+        let mut input = &[][..];
+        assert_eq!(decode_frame(&mut input, 100).unwrap(), None);
+    }
+}
@@ -0,0 +1,267 @@
+//! [`tokio_util::codec`] integration for framed transports
+
+// `displaydoc` emits its `Display` impls inside of an anonymous `const`
+#![allow(unknown_lints, non_local_definitions)]
+
+use super::{decode_frame_len, frame_len, truncated, DEFAULT_MAX_FRAME_LEN};
+use crate::{
+    decoder,
+    error::{self, Error},
+    field::Path,
+    message::Message,
+};
+use bytes::{Buf, BufMut, BytesMut};
+use core::{
+    cmp,
+    fmt::{self, Debug},
+    marker::PhantomData,
+};
+use digest::Digest;
+use displaydoc::Display;
+use std::io;
+use tokio_util::codec;
+
+/// [`tokio_util::codec`] codec which decodes and encodes messages of type `M`
+/// as length-prefixed frames (see the [`framing`][`super`] module), e.g. for
+/// sending them over a TCP connection with [`tokio_util::codec::Framed`].
+///
+/// Messages are decoded directly from the buffer the codec is given, once
+/// the whole frame has arrived, and their Verihash digests are computed
+/// with `D`.
+///
+/// Decoding errors distinguish problems with the framing from problems with
+/// the message in a frame (see [`CodecError`]). After a
+/// [`CodecError::Message`], or a [`CodecError::Frame`] for a frame which is
+/// too long, the codec is positioned at the start of the next frame, so
+/// decoding can continue.
+pub struct VeriformCodec<M, D> {
+    /// Maximum length of a frame's message
+    max_frame_len: usize,
+
+    /// Number of bytes of an oversized frame still to be discarded
+    discarding: usize,
+
+    /// Offset of the next frame in the stream
+    position: usize,
+
+    /// Type of message and digest
+    types: PhantomData<fn() -> (M, D)>,
+}
+
+impl<M, D> VeriformCodec<M, D>
+where
+    M: Message,
+    D: Digest,
+{
+    /// Create a new [`VeriformCodec`]
+    pub fn new() -> Self {
+        Self {
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            discarding: 0,
+            position: 0,
+            types: PhantomData,
+        }
+    }
+
+    /// Set the maximum length of a frame's message.
+    ///
+    /// Longer frames are rejected with [`error::Kind::FrameTooLong`] when
+    /// decoding (without buffering them) and encoding.
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Get the offset of the next frame within the stream
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Discard as much of an oversized frame as has arrived, returning
+    /// `true` once all of it has been discarded
+    fn discard(&mut self, src: &mut BytesMut) -> bool {
+        let n = cmp::min(self.discarding, src.len());
+        src.advance(n);
+        self.discarding -= n;
+        self.position += n;
+        self.discarding == 0
+    }
+
+    /// Decode the length prefix of the frame at the start of the buffer,
+    /// returning the lengths of the prefix and message, or `None` if the
+    /// prefix hasn't fully arrived
+    fn decode_prefix(&self, src: &BytesMut) -> Result<Option<(usize, usize)>, Error> {
+        let prefix_len = match src.first() {
+            Some(&first) => vint64::decoded_len(first),
+            None => return Ok(None),
+        };
+
+        if prefix_len > src.len() {
+            return Ok(None);
+        }
+
+        let length = decode_frame_len(&mut &src[..prefix_len], self.max_frame_len)?;
+        Ok(Some((prefix_len, length)))
+    }
+
+    /// Create a framing error with the position of the next frame
+    fn frame_error(&self, err: Error) -> CodecError {
+        CodecError::Frame(err.with_context(self.position, None, Path::default()))
+    }
+}
+
+impl<M, D> Default for VeriformCodec<M, D>
+where
+    M: Message,
+    D: Digest,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, D> Clone for VeriformCodec<M, D> {
+    fn clone(&self) -> Self {
+        Self {
+            max_frame_len: self.max_frame_len,
+            discarding: self.discarding,
+            position: self.position,
+            types: PhantomData,
+        }
+    }
+}
+
+impl<M, D> Debug for VeriformCodec<M, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VeriformCodec")
+            .field("max_frame_len", &self.max_frame_len)
+            .field("discarding", &self.discarding)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl<M, D> codec::Decoder for VeriformCodec<M, D>
+where
+    M: Message,
+    D: Digest,
+{
+    type Item = M;
+    type Error = CodecError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<M>, CodecError> {
+        if !self.discard(src) {
+            return Ok(None);
+        }
+
+        let (prefix_len, length) = match self.decode_prefix(src) {
+            Ok(Some(lengths)) => lengths,
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                // Skip oversized frames, so decoding can resume at the next
+                if let error::Kind::FrameTooLong { length, .. } = err.kind() {
+                    let prefix_len = vint64::decoded_len(src[0]);
+                    src.advance(prefix_len);
+                    self.position += prefix_len;
+                    self.discarding = length as usize;
+                }
+
+                return Err(self.frame_error(err));
+            }
+        };
+
+        let frame_len = prefix_len + length;
+
+        if src.len() < frame_len {
+            src.reserve(frame_len - src.len());
+            return Ok(None);
+        }
+
+        let result = decoder::Decoder::<D>::new().decode_message(&src[prefix_len..frame_len]);
+        src.advance(frame_len);
+        self.position += frame_len;
+        result.map(Some).map_err(CodecError::Message)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<M>, CodecError> {
+        if let Some(message) = codec::Decoder::decode(self, src)? {
+            return Ok(Some(message));
+        }
+
+        let remaining = if self.discarding > 0 {
+            self.discarding
+        } else {
+            match self.decode_prefix(src).map_err(|e| self.frame_error(e))? {
+                Some((prefix_len, length)) => prefix_len + length - src.len(),
+                None if src.is_empty() => return Ok(None),
+                None => vint64::decoded_len(src[0]) - src.len(),
+            }
+        };
+
+        Err(self.frame_error(truncated(remaining)))
+    }
+}
+
+impl<M, D> codec::Encoder<M> for VeriformCodec<M, D>
+where
+    M: Message,
+    D: Digest,
+{
+    type Error = CodecError;
+
+    fn encode(&mut self, message: M, dst: &mut BytesMut) -> Result<(), CodecError> {
+        let length = message.encoded_len();
+
+        if length > self.max_frame_len {
+            return Err(CodecError::Frame(
+                error::Kind::FrameTooLong {
+                    length: length as u64,
+                    max: self.max_frame_len,
+                }
+                .into(),
+            ));
+        }
+
+        let frame_start = dst.len();
+        dst.reserve(frame_len(length));
+        dst.put_slice(vint64::encode(length as u64).as_ref());
+
+        let start = dst.len();
+        dst.resize(start + length, 0);
+
+        if let Err(err) = message.encode(&mut dst[start..]) {
+            dst.truncate(frame_start);
+            return Err(CodecError::Message(err));
+        }
+
+        Ok(())
+    }
+}
+
+/// Errors which occur decoding or encoding frames with a [`VeriformCodec`]
+#[derive(Debug, Display)]
+pub enum CodecError {
+    /// I/O error
+    Io(io::Error),
+
+    /// framing error
+    Frame(Error),
+
+    /// message error
+    Message(Error),
+}
+
+impl From<io::Error> for CodecError {
+    fn from(err: io::Error) -> CodecError {
+        CodecError::Io(err)
+    }
+}
+
+impl std::error::Error for CodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CodecError::Io(err) => Some(err),
+            CodecError::Frame(err) | CodecError::Message(err) => Some(err),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! Framing for [`io::Read`] and [`io::Write`] streams
+
+use super::{decode_frame_len, truncated, DEFAULT_MAX_FRAME_LEN};
+use crate::{
+    decoder::ReadError,
+    error::{self, Error},
+    field::Path,
+    message::Message,
+};
+use alloc::vec::Vec;
+use core::cmp;
+use std::io;
+
+/// Maximum number of bytes of a frame's message read at a time
+const READ_CHUNK_LEN: usize = 0x1_0000;
+
+/// Writes messages to an [`io::Write`] as length-prefixed frames.
+///
+/// Each frame is written with a single call to [`io::Write::write_all`], so
+/// wrapping the writer in an [`io::BufWriter`] is recommended.
+#[derive(Debug)]
+pub struct FrameEncoder<W: io::Write> {
+    /// Inner writer
+    inner: W,
+
+    /// Maximum length of a frame's message
+    max_frame_len: usize,
+
+    /// Buffer for the length prefix and message of the frame being written
+    buffer: Vec<u8>,
+}
+
+impl<W> FrameEncoder<W>
+where
+    W: io::Write,
+{
+    /// Create a new [`FrameEncoder`] which writes to the given writer
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Set the maximum length of a frame's message.
+    ///
+    /// Writing longer messages fails with an [`io::ErrorKind::InvalidInput`]
+    /// error, rather than writing frames a [`FrameDecoder`] with the same
+    /// limit would reject.
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Write a frame containing the given encoded message
+    pub fn write_frame(&mut self, message: &[u8]) -> io::Result<()> {
+        if message.len() > self.max_frame_len {
+            let err = Error::from(error::Kind::FrameTooLong {
+                length: message.len() as u64,
+                max: self.max_frame_len,
+            });
+
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, err));
+        }
+
+        self.buffer.clear();
+        self.buffer
+            .extend_from_slice(vint64::encode(message.len() as u64).as_ref());
+        self.buffer.extend_from_slice(message);
+        self.inner.write_all(&self.buffer)
+    }
+
+    /// Encode the given message and write a frame containing it
+    pub fn write_message(&mut self, message: &dyn Message) -> io::Result<()> {
+        let encoded = message
+            .encode_vec()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        self.write_frame(&encoded)
+    }
+
+    /// Flush the inner writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    /// Borrow the inner writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Unwrap the inner writer
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads length-prefixed frames from an [`io::Read`].
+///
+/// Frames are read a few bytes at a time, so wrapping the reader in an
+/// [`io::BufReader`] is recommended.
+#[derive(Debug)]
+pub struct FrameDecoder<R: io::Read> {
+    /// Inner reader
+    inner: R,
+
+    /// Maximum length of a frame's message
+    max_frame_len: usize,
+
+    /// Buffer for the message of the last frame read
+    buffer: Vec<u8>,
+
+    /// Offset of the next frame in the stream
+    position: usize,
+}
+
+impl<R> FrameDecoder<R>
+where
+    R: io::Read,
+{
+    /// Create a new [`FrameDecoder`] which reads from the given reader
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Set the maximum length of a frame's message, which is checked before
+    /// a buffer is allocated for it
+    pub fn with_max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self.max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Get the offset of the next frame within the stream
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Borrow the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Unwrap the inner reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Read the next frame, returning its message, or `Ok(None)` if the
+    /// reader reaches EOF at a frame boundary.
+    ///
+    /// EOF in the middle of a frame is reported as
+    /// [`error::Kind::Truncated`], with the offset of the start of the
+    /// frame as its position. [`io::ErrorKind::Interrupted`] errors are
+    /// retried.
+    pub fn read_frame(&mut self) -> Result<Option<&[u8]>, ReadError> {
+        let mut prefix = [0u8; 9];
+
+        if read_fully(&mut self.inner, &mut prefix[..1])? == 0 {
+            return Ok(None);
+        }
+
+        let prefix_len = vint64::decoded_len(prefix[0]);
+        let n = read_fully(&mut self.inner, &mut prefix[1..prefix_len])?;
+
+        if n + 1 < prefix_len {
+            return Err(self.context(truncated(prefix_len - n - 1)));
+        }
+
+        let length = decode_frame_len(&mut &prefix[..prefix_len], self.max_frame_len)
+            .map_err(|e| self.context(e))?;
+
+        // Grow the buffer as the message arrives, rather than trusting the
+        // length prefix with a single large allocation
+        self.buffer.clear();
+
+        while self.buffer.len() < length {
+            let start = self.buffer.len();
+            let chunk = cmp::min(length - start, READ_CHUNK_LEN);
+            self.buffer.resize(start + chunk, 0);
+            let n = read_fully(&mut self.inner, &mut self.buffer[start..])?;
+
+            if n < chunk {
+                return Err(self.context(truncated(length - start - n)));
+            }
+        }
+
+        self.position += prefix_len + length;
+        Ok(Some(&self.buffer))
+    }
+
+    /// Add the position of the frame being read to an error
+    fn context(&self, err: Error) -> ReadError {
+        err.with_context(self.position, None, Path::default())
+            .into()
+    }
+}
+
+/// Fill the given buffer from a reader, returning the number of bytes read,
+/// which is less than its length if EOF was reached
+fn read_fully(reader: &mut impl io::Read, buf: &mut [u8]) -> Result<usize, ReadError> {
+    let mut filled = 0;
+
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(ReadError::Io(err)),
+        }
+    }
+
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameDecoder, FrameEncoder};
+    use crate::{decoder::ReadError, error, field::WireType};
+    use alloc::vec::Vec;
+    use std::io;
+
+    /// Write three frames: `{ 1: uint64(42) }`, an empty message, and 200
+    /// bytes
+    fn example_frames() -> Vec<u8> {
+        let mut encoder = FrameEncoder::new(Vec::new());
+
+        for message in &[&[37, 85][..], &[], &[0; 200]] {
+            encoder.write_frame(message).unwrap();
+        }
+
+        encoder.into_inner()
+    }
+
+    #[test]
+    fn round_trip() {
+        let frames = example_frames();
+        let mut decoder = FrameDecoder::new(&frames[..]);
+        assert_eq!(decoder.read_frame().unwrap().unwrap(), &[37, 85]);
+        assert_eq!(decoder.read_frame().unwrap().unwrap(), &[] as &[u8]);
+        assert_eq!(decoder.read_frame().unwrap().unwrap(), &[0; 200][..]);
+        assert!(decoder.read_frame().unwrap().is_none());
+        assert_eq!(decoder.position(), frames.len());
+    }
+
+    #[test]
+    fn truncated_final_frame() {
+        let frames = example_frames();
+        let last_frame = 4;
+
+        for truncated_len in last_frame + 1..frames.len() {
+            let mut decoder = FrameDecoder::new(&frames[..truncated_len]);
+            decoder.read_frame().unwrap().unwrap();
+            decoder.read_frame().unwrap().unwrap();
+
+            let err = match decoder.read_frame() {
+                Err(ReadError::Decode(err)) => err,
+                other => panic!("unexpected result: {:?}", other),
+            };
+
+            assert_eq!(err.position(), Some(last_frame));
+            assert!(matches!(
+                err.kind(),
+                error::Kind::Truncated {
+                    wire_type: WireType::Message,
+                    ..
+                }
+            ));
+        }
+    }
+
+    #[test]
+    fn frame_too_long() {
+        let mut encoder = FrameEncoder::new(Vec::new()).with_max_frame_len(100);
+        let err = encoder.write_frame(&[0; 200]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(encoder.get_ref().is_empty());
+
+        let frames = example_frames();
+        let mut decoder = FrameDecoder::new(&frames[..]).with_max_frame_len(100);
+        decoder.read_frame().unwrap().unwrap();
+        decoder.read_frame().unwrap().unwrap();
+
+        match decoder.read_frame() {
+            Err(ReadError::Decode(err)) => assert_eq!(
+                err.kind(),
+                error::Kind::FrameTooLong {
+                    length: 200,
+                    max: 100
+                }
+            ),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
@@ -26,6 +26,13 @@
 //!
 //! [`Timestamp`]: https://docs.rs/veriform/latest/veriform/builtins/struct.Timestamp.html
 //! [`Uuid`]: https://docs.rs/veriform/latest/veriform/builtins/struct.Uuid.html
+//!
+//! # Minimum Supported Rust Version
+//!
+//! Rust **1.51** or higher.
+//!
+//! The `arbitrary` feature requires Rust **1.63** or higher, and the
+//! `core-error` feature requires Rust **1.81** or higher.
 
 #![no_std]
 #![cfg_attr(docsrs, feature(doc_cfg))]
@@ -40,31 +47,60 @@ extern crate alloc;
 #[cfg(feature = "std")]
 extern crate std;
 
+#[cfg(feature = "arbitrary")]
+#[cfg_attr(docsrs, doc(cfg(feature = "arbitrary")))]
+pub mod arbitrary;
 #[cfg(any(feature = "tai64", feature = "uuid"))]
 pub mod builtins;
 pub mod decoder;
 pub mod encoder;
 pub mod error;
 pub mod field;
+pub mod framing;
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub mod map;
 pub mod message;
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub mod registry;
+pub mod spec;
 mod string;
-mod verihash;
+pub mod value;
+pub mod verihash;
+pub mod zigzag;
 
 #[cfg(feature = "veriform_derive")]
 pub mod derive_helpers;
 
+// Re-export the `crypto-mac` crate
+#[cfg(feature = "mac")]
+#[cfg_attr(docsrs, doc(cfg(feature = "mac")))]
+pub use crypto_mac;
+
 // Re-export the `digest` crate
 pub use digest;
 
 // Re-export the `vint64` crate
 pub use vint64;
 
-pub use crate::{encoder::Encoder, error::Error, message::Message};
+pub use crate::{
+    encoder::Encoder,
+    error::Error,
+    message::{Message, MessageRef, RawMessage},
+    verihash::verihash,
+};
 
 /// Veriform decoder with the default SHA-256 hash
 #[cfg(feature = "sha2")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sha2")))]
-pub type Decoder = crate::decoder::Decoder<sha2::Sha256>;
+pub type Decoder = decoder::Decoder<sha2::Sha256>;
+
+/// [`framing::VeriformCodec`] for `tokio_util::codec` transports, with the
+/// default SHA-256 hash
+#[cfg(all(feature = "tokio-codec", feature = "sha2"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "tokio-codec", feature = "sha2"))))]
+pub type VeriformCodec<M> = framing::VeriformCodec<M, sha2::Sha256>;
 
 /// SHA-256 digests
 #[cfg(feature = "sha2")]
@@ -0,0 +1,76 @@
+//! Maps, encoded as sequences of key/value entry messages.
+//!
+//! Veriform has no dedicated map wire type. Instead, a map is conventionally
+//! encoded as a `sequence` of two-field messages, each containing a key in
+//! field [`KEY_TAG`] and its value in field [`VALUE_TAG`].
+//!
+//! Entries are encoded in key order, and when decoding canonically
+//! (see [`Decoder::permissive`]) keys must be strictly increasing.
+//! Repeated keys are handled according to the decoder's
+//! [`Decoder::with_duplicate_key_policy`].
+//!
+//! Keys and values can be of any type which is both
+//! [`Decodable`] and [`Encodable`], e.g. `u64`, [`String`], or a
+//! [`Message`].
+//!
+//! [`String`]: alloc::string::String
+
+use crate::{
+    decoder::Decoder,
+    encoder::Encoder,
+    error::Error,
+    field::Tag,
+    message::Message,
+    value::{Decodable, Encodable},
+};
+use digest::Digest;
+
+/// Tag of the field containing the key of a map entry
+pub const KEY_TAG: Tag = Tag::new_const(0);
+
+/// Tag of the field containing the value of a map entry
+pub const VALUE_TAG: Tag = Tag::new_const(1);
+
+/// Get the length of the body of a map entry message
+pub(crate) fn entry_len<K, V>(key: &K, value: &V) -> usize
+where
+    K: Encodable + ?Sized,
+    V: Encodable + ?Sized,
+{
+    key.encoded_field_len(KEY_TAG) + value.encoded_field_len(VALUE_TAG)
+}
+
+/// Key/value entry of a map, as decoded from a sequence element
+pub(crate) struct Entry<K, V> {
+    /// Key of the entry
+    pub(crate) key: K,
+
+    /// Value of the entry
+    pub(crate) value: V,
+}
+
+impl<K, V> Message for Entry<K, V>
+where
+    K: Decodable + Encodable,
+    V: Decodable + Encodable,
+{
+    fn decode<D>(decoder: &mut Decoder<D>, mut input: &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        let key = K::decode_field(decoder, KEY_TAG, &mut input)?;
+        let value = V::decode_field(decoder, VALUE_TAG, &mut input)?;
+        Ok(Self { key, value })
+    }
+
+    fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        let mut encoder = Encoder::new(buffer);
+        self.key.encode_field(&mut encoder, KEY_TAG, false)?;
+        self.value.encode_field(&mut encoder, VALUE_TAG, false)?;
+        Ok(encoder.finish())
+    }
+
+    fn encoded_len(&self) -> usize {
+        entry_len(&self.key, &self.value)
+    }
+}
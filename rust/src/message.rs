@@ -6,10 +6,16 @@
 // Copyright (c) 2017 Dan Burkert and released under the Apache 2.0 license.
 
 use crate::{decoder::Decoder, Error};
+use core::{
+    any::Any,
+    fmt::{self, Debug},
+};
 use digest::Digest;
 
+use crate::verihash::Output;
+
 #[cfg(feature = "alloc")]
-use alloc::vec::Vec;
+use alloc::{boxed::Box, vec::Vec};
 
 /// Veriform messages.
 ///
@@ -32,17 +38,225 @@ pub trait Message {
     /// a slice containing the encoded message on success.
     fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error>;
 
-    /// Get the length of a message after being encoded as Veriform.
+    /// Get the length of a message after being encoded as Veriform, e.g. to
+    /// size a buffer to [`Message::encode`] it into.
+    ///
+    /// This must be exactly the length of the slice returned by
+    /// [`Message::encode`].
     fn encoded_len(&self) -> usize;
 
+    /// Encode this message as Veriform into a fixed-size array, returning
+    /// it along with the length of the encoded message at its start.
+    ///
+    /// Fails with [`error::Kind::BufferTooSmall`] (giving the length
+    /// needed) if the message doesn't fit in `N` bytes.
+    ///
+    /// [`error::Kind::BufferTooSmall`]: crate::error::Kind::BufferTooSmall
+    fn encode_to_array<const N: usize>(&self) -> Result<([u8; N], usize), Error>
+    where
+        Self: Sized,
+    {
+        let mut encoded = [0; N];
+        let len = self.encode(&mut encoded)?.len();
+        debug_assert_eq!(len, self.encoded_len(), "encoded_len disagrees with encode");
+        Ok((encoded, len))
+    }
+
     /// Encode this message as Veriform, allocating returning a byte vector
     /// on success.
+    ///
+    /// The vector is allocated once, with the message's
+    /// [`Message::encoded_len`].
     #[cfg(feature = "alloc")]
     fn encode_vec(&self) -> Result<Vec<u8>, Error> {
         let mut encoded = vec![0; self.encoded_len()];
-        self.encode(&mut encoded)?;
+        let len = self.encode(&mut encoded)?.len();
+        debug_assert_eq!(len, encoded.len(), "encoded_len disagrees with encode");
+        encoded.truncate(len);
         Ok(encoded)
     }
+
+    /// Compute the Verihash digest of this message, encoding it into a
+    /// temporary buffer and hashing the result.
+    #[cfg(feature = "alloc")]
+    fn verihash<D>(&self) -> Result<Output<D>, Error>
+    where
+        D: Digest,
+        Self: Sized,
+    {
+        crate::verihash::hash::<D, Self>(self)
+    }
+}
+
+/// Boxed messages, e.g. for recursive types such as an `enum` with a variant
+/// containing itself
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+impl<M> Message for Box<M>
+where
+    M: Message,
+{
+    fn decode<D>(decoder: &mut Decoder<D>, input: &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        M::decode(decoder, input).map(Box::new)
+    }
+
+    fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        M::encode(self, buffer)
+    }
+
+    fn encoded_len(&self) -> usize {
+        M::encoded_len(self)
+    }
+}
+
+/// Object-safe subset of the [`Message`] API, for handling messages whose
+/// type is only known at runtime as `dyn AnyMessage`, e.g. those decoded by
+/// a [`Registry`].
+///
+/// It's implemented for every `'static` [`Message`], and like [`Message`] a
+/// `dyn AnyMessage` can be encoded with [`Message::encode`] and measured
+/// with [`Message::encoded_len`]. The concrete message can be recovered
+/// with [`downcast_ref`] or [`downcast`].
+///
+/// [`Registry`]: crate::registry::Registry
+/// [`downcast_ref`]: #method.downcast_ref
+/// [`downcast`]: #method.downcast
+pub trait AnyMessage: Message + Any {
+    /// Get this message as [`Any`], e.g. to downcast it
+    fn as_any(&self) -> &dyn Any;
+
+    /// Convert this boxed message into a boxed [`Any`], e.g. to downcast it
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<M> AnyMessage for M
+where
+    M: Message + Any,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    #[cfg(feature = "alloc")]
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl Debug for dyn AnyMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad("AnyMessage { .. }")
+    }
+}
+
+impl dyn AnyMessage {
+    /// Is the concrete type of this message `M`?
+    pub fn is<M: AnyMessage>(&self) -> bool {
+        self.as_any().is::<M>()
+    }
+
+    /// Get a reference to this message as `M`, if that's its concrete type
+    pub fn downcast_ref<M: AnyMessage>(&self) -> Option<&M> {
+        self.as_any().downcast_ref()
+    }
+
+    /// Convert this boxed message into `M`, if that's its concrete type, or
+    /// otherwise return it unmodified
+    #[cfg(feature = "alloc")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+    pub fn downcast<M: AnyMessage>(self: Box<Self>) -> Result<Box<M>, Box<Self>> {
+        if self.is::<M>() {
+            Ok(self.into_any().downcast().expect("type checked above"))
+        } else {
+            Err(self)
+        }
+    }
+}
+
+/// Veriform messages which borrow from the input they're decoded from.
+///
+/// This is the zero-copy counterpart of [`Message`]: `bytes` and `string`
+/// fields can be decoded as `&'a [u8]` and `&'a str` slices of the input,
+/// which must be contiguous in memory. `string` fields are still checked to
+/// be valid, normalized UTF-8.
+///
+/// Like [`Message`], it's not intended to be implemented directly, but is
+/// derived by the [`veriform::Message`] procedural macro for structs with a
+/// lifetime parameter.
+///
+/// Types which borrow from their input only impl `MessageRef`, not
+/// [`Message`], so using them where a [`Message`] is expected is a compile
+/// error rather than a runtime one. This includes decoding them from a
+/// stream (where the input isn't contiguous), as the elements of `message`
+/// sequences, and [`Message::verihash`]. Use an owned counterpart of the
+/// type there, or [`RawMessage`] to defer decoding the nested message.
+///
+/// [`veriform::Message`]: https://docs.rs/veriform/latest/veriform/derive.Message.html
+pub trait MessageRef<'a>: Sized {
+    /// Decode a Veriform message which borrows from the provided slice using
+    /// the given [`Decoder`].
+    fn decode<D>(decoder: &mut Decoder<D>, input: &'a [u8]) -> Result<Self, Error>
+    where
+        D: Digest;
+
+    /// Encode this message as Veriform into the provided buffer, returning
+    /// a slice containing the encoded message on success.
+    fn encode<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b [u8], Error>;
+
+    /// Get the length of a message after being encoded as Veriform.
+    fn encoded_len(&self) -> usize;
+}
+
+/// Body of a nested message which was left undecoded, e.g. so it can be
+/// forwarded unmodified, or decoded once its type is known.
+///
+/// Returned by [`Decoder::decode_message_raw`], and can be used as the type
+/// of `message` fields of structs deriving [`MessageRef`], where it's
+/// encoded exactly as it was decoded.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RawMessage<'a> {
+    /// Encoded body of the message (i.e. its fields)
+    body: &'a [u8],
+}
+
+impl<'a> RawMessage<'a> {
+    /// Wrap the encoded body of a message. It isn't checked to be valid
+    /// until it's decoded or hashed.
+    pub fn new(body: &'a [u8]) -> Self {
+        Self { body }
+    }
+
+    /// Get the encoded body of the message
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.body
+    }
+
+    /// Decode the message with the given [`Decoder`] (see
+    /// [`Decoder::decode_message`])
+    pub fn decode<M, D>(&self, decoder: &mut Decoder<D>) -> Result<M, Error>
+    where
+        M: Message,
+        D: Digest,
+    {
+        decoder.decode_message(self.body)
+    }
+
+    /// Compute the Verihash digest of the message without decoding it (see
+    /// [`verihash`][`crate::verihash()`])
+    pub fn verihash<D: Digest>(&self) -> Result<Output<D>, Error> {
+        crate::verihash::verihash::<D>(self.body)
+    }
+}
+
+impl AsRef<[u8]> for RawMessage<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.body
+    }
 }
 
 /// Elements of a message (used for errors)
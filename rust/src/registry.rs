@@ -0,0 +1,145 @@
+//! Runtime registry of message types, for decoding messages whose type is
+//! only known at runtime, e.g. from a numeric type ID which accompanies
+//! them.
+//!
+//! Each type is registered under a `u64` type ID, and decoded messages are
+//! returned as `Box<dyn AnyMessage>`, which can be encoded as-is or
+//! downcast to their concrete type:
+//!
+//! ```
+//! # #[cfg(all(feature = "sha2", feature = "uuid"))]
+//! # {
+//! use veriform::{builtins::Uuid, registry::Registry, Message};
+//!
+//! let mut registry = Registry::<sha2::Sha256>::new();
+//! registry.register::<Uuid>(1);
+//!
+//! let uuid = Uuid::from_u128(0x0123_4567_89ab_cdef);
+//! let encoded = uuid.encode_vec().unwrap();
+//!
+//! let decoded = registry.decode(1, &encoded).unwrap();
+//! assert_eq!(decoded.encoded_len(), encoded.len());
+//! assert_eq!(decoded.downcast_ref::<Uuid>(), Some(&uuid));
+//!
+//! // Type IDs which aren't registered are rejected
+//! assert!(registry.decode(2, &encoded).is_err());
+//! # }
+//! ```
+
+use crate::{
+    decoder::Decoder,
+    error::{self, Error},
+    message::{AnyMessage, Message},
+};
+use alloc::{boxed::Box, collections::BTreeMap};
+use core::fmt::{self, Debug};
+use digest::Digest;
+
+/// Function which decodes a message of a registered type
+pub type DecodeFn<D> = fn(&mut Decoder<D>, &[u8]) -> Result<Box<dyn AnyMessage>, Error>;
+
+/// Registry of message types, keyed by type ID (see the
+/// [module documentation][`crate::registry`])
+pub struct Registry<D: Digest> {
+    /// Decode functions for each registered type ID
+    types: BTreeMap<u64, DecodeFn<D>>,
+}
+
+impl<D> Registry<D>
+where
+    D: Digest,
+{
+    /// Create a new registry with no registered types
+    pub fn new() -> Self {
+        Self {
+            types: BTreeMap::new(),
+        }
+    }
+
+    /// Register the message type `M` under the given type ID.
+    ///
+    /// # Panics
+    ///
+    /// If a type is already registered under the same type ID.
+    pub fn register<M>(&mut self, type_id: u64) -> &mut Self
+    where
+        M: Message + 'static,
+    {
+        self.register_fn(type_id, decode_boxed::<M, D>)
+    }
+
+    /// Register a function which decodes messages with the given type ID,
+    /// e.g. to decode them as one of several types depending on their
+    /// contents.
+    ///
+    /// # Panics
+    ///
+    /// If a type is already registered under the same type ID.
+    pub fn register_fn(&mut self, type_id: u64, decode: DecodeFn<D>) -> &mut Self {
+        if self.types.insert(type_id, decode).is_some() {
+            panic!("type ID {} is already registered", type_id);
+        }
+
+        self
+    }
+
+    /// Is a type registered under the given type ID?
+    pub fn contains(&self, type_id: u64) -> bool {
+        self.types.contains_key(&type_id)
+    }
+
+    /// Decode a message of the type registered under the given type ID with
+    /// a new [`Decoder`].
+    ///
+    /// Fails with [`error::Kind::UnknownType`] if no type is registered
+    /// under the type ID.
+    pub fn decode(&self, type_id: u64, input: &[u8]) -> Result<Box<dyn AnyMessage>, Error> {
+        self.decode_with(&mut Decoder::new(), type_id, input)
+    }
+
+    /// Decode a message of the type registered under the given type ID with
+    /// the given [`Decoder`], e.g. to configure its limits, or to compute
+    /// the message's Verihash digest as it's decoded.
+    pub fn decode_with(
+        &self,
+        decoder: &mut Decoder<D>,
+        type_id: u64,
+        input: &[u8],
+    ) -> Result<Box<dyn AnyMessage>, Error> {
+        let decode = self
+            .types
+            .get(&type_id)
+            .ok_or(error::Kind::UnknownType { type_id })?;
+
+        decode(decoder, input)
+    }
+}
+
+impl<D> Default for Registry<D>
+where
+    D: Digest,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D> Debug for Registry<D>
+where
+    D: Digest,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("type_ids", &self.types.keys())
+            .finish()
+    }
+}
+
+/// Decode a message of type `M`, boxing it as a `dyn AnyMessage`
+fn decode_boxed<M, D>(decoder: &mut Decoder<D>, input: &[u8]) -> Result<Box<dyn AnyMessage>, Error>
+where
+    M: Message + 'static,
+    D: Digest,
+{
+    M::decode(decoder, input).map(|msg| Box::new(msg) as Box<dyn AnyMessage>)
+}
@@ -0,0 +1,204 @@
+//! Veriform wire format constants.
+//!
+//! This module is the single definition of the numeric details of the wire
+//! format shared by the encoder, the decoder, and Verihash: wire type
+//! identifiers, the bit layout of field and sequence headers, and the
+//! prefix bytes which frame Verihash transcripts.
+//!
+//! # Field headers
+//!
+//! Every field begins with a header encoded as a `vint64`, which packs the
+//! field's tag, critical bit, and wire type into a single integer:
+//!
+//! ```text
+//! tag << TAG_SHIFT | critical << CRITICAL_SHIFT | wire_type
+//! ```
+//!
+//! For example, a non-critical `uint64` in field 1 has header `0x12`, which
+//! encodes as the single `vint64` byte `37`:
+//!
+//! ```
+//! use veriform::spec;
+//!
+//! let header = spec::pack_header(1, false, spec::WIRE_TYPE_UINT64);
+//! assert_eq!(header, 0b1_0_010);
+//! assert_eq!(veriform::vint64::encode(header).as_ref(), &[37]);
+//!
+//! // Setting the critical bit changes only bit 3 of the header
+//! let critical = spec::pack_header(1, true, spec::WIRE_TYPE_UINT64);
+//! assert_eq!(critical, header | spec::CRITICAL_BIT);
+//! assert_eq!(veriform::vint64::encode(critical).as_ref(), &[53]);
+//!
+//! assert_eq!(spec::unpack_header(critical), (1, true, spec::WIRE_TYPE_UINT64));
+//! ```
+//!
+//! # Sequence headers
+//!
+//! The value of a sequence field begins with a `vint64` which packs the
+//! length of the sequence body with the wire type of its elements:
+//!
+//! ```
+//! use veriform::spec;
+//!
+//! // Three bytes of `uint64` elements
+//! let header = spec::pack_sequence_header(3, spec::WIRE_TYPE_UINT64);
+//! assert_eq!(header, 0x32);
+//! assert_eq!(spec::unpack_sequence_header(header), (3, spec::WIRE_TYPE_UINT64));
+//! ```
+
+/// Wire type of `false` booleans
+pub const WIRE_TYPE_FALSE: u8 = 0;
+
+/// Wire type of `true` booleans
+pub const WIRE_TYPE_TRUE: u8 = 1;
+
+/// Wire type of 64-bit unsigned integers
+pub const WIRE_TYPE_UINT64: u8 = 2;
+
+/// Wire type of 64-bit (zigzag) signed integers
+pub const WIRE_TYPE_SINT64: u8 = 3;
+
+/// Wire type of binary data
+pub const WIRE_TYPE_BYTES: u8 = 4;
+
+/// Wire type of Unicode strings
+pub const WIRE_TYPE_STRING: u8 = 5;
+
+/// Wire type of nested messages
+pub const WIRE_TYPE_MESSAGE: u8 = 6;
+
+/// Wire type of sequences
+pub const WIRE_TYPE_SEQUENCE: u8 = 7;
+
+/// Mask which selects the wire type from a field or sequence header.
+///
+/// All 3-bit wire types are assigned, so no more can be added without
+/// changing the header layout.
+pub const WIRE_TYPE_MASK: u64 = 0b111;
+
+/// Position of the critical bit in a field header
+pub const CRITICAL_SHIFT: u32 = 3;
+
+/// Critical bit of a field header
+pub const CRITICAL_BIT: u64 = 1 << CRITICAL_SHIFT;
+
+/// Position of the tag in a field header
+pub const TAG_SHIFT: u32 = 4;
+
+/// Largest tag which can be encoded in a field header
+pub const MAX_TAG: u64 = u64::MAX >> TAG_SHIFT;
+
+/// Position of the body length in a sequence header
+pub const SEQUENCE_LENGTH_SHIFT: u32 = 4;
+
+/// Version of the Verihash transcript format.
+///
+/// Absorbed at the beginning of every message and sequence transcript, and
+/// bumped whenever the transcript framing changes so digests computed under
+/// different framings can never be mistaken for one another.
+pub const VERIHASH_TRANSCRIPT_VERSION: u8 = 3;
+
+// Transcript prefixes lie outside the range of wire types (`0x00`-`0x07`),
+// so they can't be confused with the wire type bytes which precede values.
+
+/// Verihash prefix which introduces a field (followed by its tag)
+// TODO(tarcieri): support string tags?
+pub const VERIHASH_FIELD_PREFIX: u8 = 0x10;
+
+/// Verihash prefix which begins a sequence transcript (followed by the
+/// transcript version and the wire type of the sequence's elements)
+pub const VERIHASH_SEQUENCE_PREFIX: u8 = 0x11;
+
+/// Verihash prefix which begins a message transcript (followed by the
+/// transcript version)
+pub const VERIHASH_MESSAGE_BEGIN: u8 = 0x12;
+
+/// Verihash suffix which ends a message transcript
+pub const VERIHASH_MESSAGE_END: u8 = 0x13;
+
+/// Verihash prefix which begins a message frame within a stream of messages
+/// (followed by the index of the message)
+pub const VERIHASH_FRAME_BEGIN: u8 = 0x14;
+
+/// Verihash prefix which ends a message frame within a stream of messages
+/// (followed by the index of the message)
+pub const VERIHASH_FRAME_END: u8 = 0x15;
+
+/// Verihash prefix which introduces the digest of a redactable field's
+/// transcript, absorbed in place of the field's value
+pub const VERIHASH_REDACTED_FIELD: u8 = 0x16;
+
+/// Verihash prefix which introduces a critical field (followed by its tag),
+/// so a field's criticality can't be changed without changing the digest
+pub const VERIHASH_CRITICAL_FIELD_PREFIX: u8 = 0x17;
+
+/// Verihash prefix used by domain separation strings.
+///
+/// This value lies outside the range of valid wire types, so a transcript
+/// which begins with a domain can't be confused with one which doesn't.
+pub const VERIHASH_DOMAIN_PREFIX: u8 = 0xFF;
+
+/// Pack a tag, critical bit, and wire type into a field header.
+///
+/// Tag bits above [`MAX_TAG`] and wire type bits outside of
+/// [`WIRE_TYPE_MASK`] are discarded.
+pub const fn pack_header(tag: u64, critical: bool, wire_type: u8) -> u64 {
+    tag << TAG_SHIFT | (critical as u64) << CRITICAL_SHIFT | (wire_type as u64 & WIRE_TYPE_MASK)
+}
+
+/// Unpack a field header into its tag, critical bit, and wire type
+pub const fn unpack_header(header: u64) -> (u64, bool, u8) {
+    (
+        header >> TAG_SHIFT,
+        header & CRITICAL_BIT != 0,
+        (header & WIRE_TYPE_MASK) as u8,
+    )
+}
+
+/// Pack a sequence body length and element wire type into a sequence header
+pub const fn pack_sequence_header(length: u64, wire_type: u8) -> u64 {
+    length << SEQUENCE_LENGTH_SHIFT | (wire_type as u64 & WIRE_TYPE_MASK)
+}
+
+/// Unpack a sequence header into its body length and element wire type
+pub const fn unpack_sequence_header(header: u64) -> (u64, u8) {
+    (
+        header >> SEQUENCE_LENGTH_SHIFT,
+        (header & WIRE_TYPE_MASK) as u8,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_round_trip() {
+        for &(tag, critical, wire_type) in &[
+            (0, false, WIRE_TYPE_FALSE),
+            (1, true, WIRE_TYPE_UINT64),
+            (42, false, WIRE_TYPE_SEQUENCE),
+            (MAX_TAG, true, WIRE_TYPE_MESSAGE),
+        ] {
+            let header = pack_header(tag, critical, wire_type);
+            assert_eq!(unpack_header(header), (tag, critical, wire_type));
+        }
+    }
+
+    #[test]
+    fn verihash_prefixes_are_not_wire_types() {
+        for &prefix in &[
+            VERIHASH_FIELD_PREFIX,
+            VERIHASH_CRITICAL_FIELD_PREFIX,
+            VERIHASH_SEQUENCE_PREFIX,
+            VERIHASH_MESSAGE_BEGIN,
+            VERIHASH_MESSAGE_END,
+            VERIHASH_FRAME_BEGIN,
+            VERIHASH_FRAME_END,
+            VERIHASH_REDACTED_FIELD,
+            VERIHASH_DOMAIN_PREFIX,
+        ] {
+            assert!(u64::from(prefix) > WIRE_TYPE_MASK);
+        }
+    }
+}
@@ -19,3 +19,149 @@ pub fn ensure_canonical(s: &str) -> Result<&str, Error> {
         Err(error::Kind::UnicodeNormalization.into())
     }
 }
+
+/// Incremental UTF-8 validator for strings which arrive in chunks.
+///
+/// Code points split across chunk boundaries are carried over (at most 3
+/// bytes) and completed by the following chunk.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub(crate) struct Utf8Validator {
+    /// Bytes of a partial code point at the end of the previous chunk
+    carry: [u8; 3],
+
+    /// Number of bytes in `carry`
+    carry_len: usize,
+
+    /// Offset of the first byte which has not yet been validated
+    pos: usize,
+}
+
+impl Utf8Validator {
+    /// Validate the next chunk of a string
+    pub fn update(&mut self, mut bytes: &[u8]) -> Result<(), Error> {
+        if self.carry_len > 0 {
+            let mut buf = [0u8; 4];
+            let take = (buf.len() - self.carry_len).min(bytes.len());
+            let len = self.carry_len + take;
+            buf[..self.carry_len].copy_from_slice(&self.carry[..self.carry_len]);
+            buf[self.carry_len..len].copy_from_slice(&bytes[..take]);
+
+            match core::str::from_utf8(&buf[..len]) {
+                Ok(_) => {
+                    bytes = &bytes[take..];
+                    self.advance(len);
+                }
+                Err(e) if e.valid_up_to() > 0 => {
+                    bytes = &bytes[(e.valid_up_to() - self.carry_len)..];
+                    self.advance(e.valid_up_to());
+                }
+                Err(e) if e.error_len().is_none() => {
+                    // Still incomplete: this chunk was exhausted
+                    self.carry[..len].copy_from_slice(&buf[..len]);
+                    self.carry_len = len;
+                    return Ok(());
+                }
+                Err(_) => return Err(self.error(0)),
+            }
+        }
+
+        match core::str::from_utf8(bytes) {
+            Ok(_) => {
+                self.pos += bytes.len();
+                Ok(())
+            }
+            Err(e) if e.error_len().is_none() => {
+                let partial = &bytes[e.valid_up_to()..];
+                self.carry[..partial.len()].copy_from_slice(partial);
+                self.carry_len = partial.len();
+                self.pos += e.valid_up_to();
+                Ok(())
+            }
+            Err(e) => Err(self.error(e.valid_up_to())),
+        }
+    }
+
+    /// Finish validating a string, ensuring it doesn't end with a partial
+    /// code point
+    pub fn finish(&self) -> Result<(), Error> {
+        if self.carry_len == 0 {
+            Ok(())
+        } else {
+            Err(self.error(0))
+        }
+    }
+
+    /// Bytes of the partial code point being carried over, and the offset
+    /// of the first byte which has not yet been validated
+    #[cfg(feature = "alloc")]
+    pub fn parts(&self) -> (&[u8], usize) {
+        (&self.carry[..self.carry_len], self.pos)
+    }
+
+    /// Reconstruct a validator from the output of [`Utf8Validator::parts`]
+    #[cfg(feature = "alloc")]
+    pub fn from_parts(carry: &[u8], pos: usize) -> Option<Self> {
+        if carry.len() > 3 {
+            return None;
+        }
+
+        let mut validator = Self {
+            carry: [0u8; 3],
+            carry_len: carry.len(),
+            pos,
+        };
+
+        validator.carry[..carry.len()].copy_from_slice(carry);
+        Some(validator)
+    }
+
+    /// Mark `len` bytes (including the carry) as validated
+    fn advance(&mut self, len: usize) {
+        self.pos += len;
+        self.carry_len = 0;
+    }
+
+    /// Create an error for invalid UTF-8 at the given offset past `pos`
+    fn error(&self, offset: usize) -> Error {
+        error::Kind::Utf8 {
+            valid_up_to: self.pos + offset,
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Utf8Validator;
+    use crate::error;
+
+    #[test]
+    fn split_code_point() {
+        // U+1F980 split across three chunks
+        let mut validator = Utf8Validator::default();
+
+        for chunk in &[&b"a\xF0"[..], b"\x9F", b"\xA6\x80b"] {
+            validator.update(chunk).unwrap();
+        }
+
+        validator.finish().unwrap();
+    }
+
+    #[test]
+    fn truncated_code_point() {
+        let mut validator = Utf8Validator::default();
+        validator.update(b"ab\xF0\x9F").unwrap();
+
+        let err = validator.finish().err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Utf8 { valid_up_to: 2 });
+    }
+
+    #[test]
+    fn invalid_continuation() {
+        let mut validator = Utf8Validator::default();
+        validator.update(b"abc\xF0").unwrap();
+
+        let err = validator.update(b"\x9Fz").err().unwrap();
+        assert_eq!(err.kind(), error::Kind::Utf8 { valid_up_to: 3 });
+    }
+}
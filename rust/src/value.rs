@@ -0,0 +1,776 @@
+//! Field values: traits for decoding and encoding the value of a field
+//! generically, so containers like [`Option`], [`Vec`], and maps (see the
+//! [`map`][`crate::map`] module) can be written once for every type of
+//! value rather than once per type.
+//!
+//! [`Decodable`] is implemented for the types a field's value can be
+//! decoded into:
+//!
+//! - `bool`, `u64`, `i64`, and the narrower integer types, which are range
+//!   checked when decoded
+//! - [`String`] and `Vec<u8>` (with the `alloc` feature)
+//! - [`Message`]s
+//! - `Option<T>`, for optional fields
+//! - `Vec<T>` (with the `alloc` feature), for sequences of
+//!   [`SequenceElement`]s
+//! - newtypes deriving `Message` with `#[veriform(transparent)]`, which
+//!   delegate to the type they wrap
+//!
+//! ## Borrowed vs owned values
+//!
+//! [`Decodable`] values are owned: they can't borrow from the input, so
+//! they can be stored in containers which outlive it. `string` and `bytes`
+//! fields can also be decoded as references to the input, without copying
+//! them, using [`DecodeRef`][`crate::decoder::DecodeRef`] (or
+//! [`Decoder::decode_string`] and [`Decoder::decode_bytes`]).
+//!
+//! [`Encodable`] is also implemented for the borrowed `str` and `[u8]`
+//! types, so values encode the same way whether they're owned or borrowed.
+
+use crate::{
+    decoder::{Decode, Decoder},
+    encoder::Encoder,
+    error::Error,
+    field::{self, Tag, WireType},
+    message::Message,
+};
+use digest::Digest;
+
+#[cfg(feature = "alloc")]
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+/// Types which the value of a field can be decoded into.
+///
+/// See the [module documentation][`crate::value`] for the types this is
+/// implemented for.
+pub trait Decodable: Sized {
+    /// Wire type of fields containing values of this type.
+    ///
+    /// `bool` fields are encoded with the `true` or `false` wire type
+    /// depending on their value: their `WIRE_TYPE` is [`WireType::False`],
+    /// but either is accepted when decoding.
+    const WIRE_TYPE: WireType;
+
+    /// Decode the field with the given tag as a value of this type
+    fn decode_field<D>(
+        decoder: &mut Decoder<D>,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Self, Error>
+    where
+        D: Digest;
+}
+
+/// Types which can be encoded as the value of a field.
+///
+/// This is implemented for the same types as [`Decodable`], along with the
+/// borrowed `str` and `[u8]` types.
+pub trait Encodable {
+    /// Wire type of fields containing values of this type (see
+    /// [`Decodable::WIRE_TYPE`])
+    const WIRE_TYPE: WireType;
+
+    /// Encode this value as the field with the given tag
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error>;
+
+    /// Get the length of this value when encoded as the field with the given
+    /// tag
+    fn encoded_field_len(&self, tag: Tag) -> usize;
+}
+
+/// Types which can be the elements of a `sequence`: `u64`, `i64`,
+/// `Vec<u8>` (as `bytes`), `String`, and [`Message`]s.
+///
+/// `Vec<T>` is [`Decodable`] and [`Encodable`] for these types.
+#[cfg(feature = "alloc")]
+#[cfg_attr(docsrs, doc(cfg(feature = "alloc")))]
+pub trait SequenceElement: Sized {
+    /// Wire type of the elements of the sequence
+    const ELEMENT_TYPE: WireType;
+
+    /// Decode the sequence field with the given tag into a [`Vec`]
+    fn decode_seq<D>(
+        decoder: &mut Decoder<D>,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Vec<Self>, Error>
+    where
+        D: Digest;
+
+    /// Encode the given elements as the sequence field with the given tag
+    fn encode_seq(
+        elements: &[Self],
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        Self::encode_seq_with(elements, |element| element, encoder, tag, critical)
+    }
+
+    /// Get the length of the given elements when encoded as the sequence
+    /// field with the given tag
+    fn encoded_seq_len(elements: &[Self], tag: Tag) -> usize {
+        Self::encoded_seq_len_with(elements, |element| element, tag)
+    }
+
+    /// Encode the elements borrowed from each of the given values as the
+    /// sequence field with the given tag, e.g. the values wrapped by a slice
+    /// of `#[veriform(transparent)]` newtypes
+    fn encode_seq_with<T>(
+        values: &[T],
+        element: impl Fn(&T) -> &Self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error>;
+
+    /// Get the length of the elements borrowed from each of the given values
+    /// when encoded as the sequence field with the given tag (see
+    /// [`SequenceElement::encode_seq_with`])
+    fn encoded_seq_len_with<T>(values: &[T], element: impl Fn(&T) -> &Self, tag: Tag) -> usize;
+}
+
+impl Decodable for bool {
+    const WIRE_TYPE: WireType = WireType::False;
+
+    fn decode_field<D>(decoder: &mut Decoder<D>, tag: Tag, input: &mut &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode(tag, input)
+    }
+}
+
+impl Encodable for bool {
+    const WIRE_TYPE: WireType = WireType::False;
+
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.bool(tag, critical, *self)
+    }
+
+    fn encoded_field_len(&self, tag: Tag) -> usize {
+        field::length::bool(tag)
+    }
+}
+
+/// Implement [`Decodable`] and [`Encodable`] for unsigned integer types,
+/// which are encoded as `uint64`
+macro_rules! impl_value_uint {
+    ($($ty:ty),+) => {
+        $(
+            impl Decodable for $ty {
+                const WIRE_TYPE: WireType = WireType::UInt64;
+
+                fn decode_field<D>(
+                    decoder: &mut Decoder<D>,
+                    tag: Tag,
+                    input: &mut &[u8],
+                ) -> Result<Self, Error>
+                where
+                    D: Digest,
+                {
+                    decoder.decode(tag, input)
+                }
+            }
+
+            impl Encodable for $ty {
+                const WIRE_TYPE: WireType = WireType::UInt64;
+
+                fn encode_field(
+                    &self,
+                    encoder: &mut Encoder<'_>,
+                    tag: Tag,
+                    critical: bool,
+                ) -> Result<(), Error> {
+                    encoder.uint64(tag, critical, (*self).into())
+                }
+
+                fn encoded_field_len(&self, tag: Tag) -> usize {
+                    field::length::uint64(tag, (*self).into())
+                }
+            }
+        )+
+    };
+}
+
+/// Implement [`Decodable`] and [`Encodable`] for signed integer types,
+/// which are encoded as `sint64`
+macro_rules! impl_value_sint {
+    ($($ty:ty),+) => {
+        $(
+            impl Decodable for $ty {
+                const WIRE_TYPE: WireType = WireType::SInt64;
+
+                fn decode_field<D>(
+                    decoder: &mut Decoder<D>,
+                    tag: Tag,
+                    input: &mut &[u8],
+                ) -> Result<Self, Error>
+                where
+                    D: Digest,
+                {
+                    decoder.decode(tag, input)
+                }
+            }
+
+            impl Encodable for $ty {
+                const WIRE_TYPE: WireType = WireType::SInt64;
+
+                fn encode_field(
+                    &self,
+                    encoder: &mut Encoder<'_>,
+                    tag: Tag,
+                    critical: bool,
+                ) -> Result<(), Error> {
+                    encoder.sint64(tag, critical, (*self).into())
+                }
+
+                fn encoded_field_len(&self, tag: Tag) -> usize {
+                    field::length::sint64(tag, (*self).into())
+                }
+            }
+        )+
+    };
+}
+
+impl_value_uint!(u8, u16, u32, u64);
+impl_value_sint!(i8, i16, i32, i64);
+
+impl Encodable for str {
+    const WIRE_TYPE: WireType = WireType::String;
+
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.string(tag, critical, self)
+    }
+
+    fn encoded_field_len(&self, tag: Tag) -> usize {
+        field::length::string(tag, self)
+    }
+}
+
+impl Encodable for [u8] {
+    const WIRE_TYPE: WireType = WireType::Bytes;
+
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.bytes(tag, critical, self)
+    }
+
+    fn encoded_field_len(&self, tag: Tag) -> usize {
+        field::length::bytes(tag, self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Decodable for String {
+    const WIRE_TYPE: WireType = WireType::String;
+
+    fn decode_field<D>(decoder: &mut Decoder<D>, tag: Tag, input: &mut &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode_string(tag, input).map(Into::into)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Encodable for String {
+    const WIRE_TYPE: WireType = WireType::String;
+
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        self.as_str().encode_field(encoder, tag, critical)
+    }
+
+    fn encoded_field_len(&self, tag: Tag) -> usize {
+        self.as_str().encoded_field_len(tag)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Decodable for Vec<u8> {
+    const WIRE_TYPE: WireType = WireType::Bytes;
+
+    fn decode_field<D>(decoder: &mut Decoder<D>, tag: Tag, input: &mut &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode_bytes(tag, input).map(Into::into)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Encodable for Vec<u8> {
+    const WIRE_TYPE: WireType = WireType::Bytes;
+
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        self.as_slice().encode_field(encoder, tag, critical)
+    }
+
+    fn encoded_field_len(&self, tag: Tag) -> usize {
+        self.as_slice().encoded_field_len(tag)
+    }
+}
+
+impl<M> Decodable for M
+where
+    M: Message,
+{
+    const WIRE_TYPE: WireType = WireType::Message;
+
+    fn decode_field<D>(decoder: &mut Decoder<D>, tag: Tag, input: &mut &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode(tag, input)
+    }
+}
+
+impl<M> Encodable for M
+where
+    M: Message,
+{
+    const WIRE_TYPE: WireType = WireType::Message;
+
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.message(tag, critical, self)
+    }
+
+    fn encoded_field_len(&self, tag: Tag) -> usize {
+        field::length::message(tag, self)
+    }
+}
+
+/// Optional fields: `None` if the field is absent (see
+/// [`Decoder::is_next_field`])
+impl<T> Decodable for Option<T>
+where
+    T: Decodable,
+{
+    const WIRE_TYPE: WireType = T::WIRE_TYPE;
+
+    fn decode_field<D>(decoder: &mut Decoder<D>, tag: Tag, input: &mut &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        if decoder.is_next_field(tag, input)? {
+            T::decode_field(decoder, tag, input).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Optional fields: nothing is encoded for `None`
+impl<T> Encodable for Option<T>
+where
+    T: Encodable,
+{
+    const WIRE_TYPE: WireType = T::WIRE_TYPE;
+
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        match self {
+            Some(value) => value.encode_field(encoder, tag, critical),
+            None => Ok(()),
+        }
+    }
+
+    fn encoded_field_len(&self, tag: Tag) -> usize {
+        self.as_ref()
+            .map(|value| value.encoded_field_len(tag))
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Decodable for Vec<T>
+where
+    T: SequenceElement,
+{
+    const WIRE_TYPE: WireType = WireType::Sequence;
+
+    fn decode_field<D>(decoder: &mut Decoder<D>, tag: Tag, input: &mut &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        T::decode_seq(decoder, tag, input)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T> Encodable for Vec<T>
+where
+    T: SequenceElement,
+{
+    const WIRE_TYPE: WireType = WireType::Sequence;
+
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        T::encode_seq(self, encoder, tag, critical)
+    }
+
+    fn encoded_field_len(&self, tag: Tag) -> usize {
+        T::encoded_seq_len(self, tag)
+    }
+}
+
+/// Maps, encoded as sequences of key/value entry messages (see the
+/// [`map`][`crate::map`] module)
+#[cfg(feature = "alloc")]
+impl<K, V> Decodable for BTreeMap<K, V>
+where
+    K: Decodable + Encodable + Ord,
+    V: Decodable + Encodable,
+{
+    const WIRE_TYPE: WireType = WireType::Sequence;
+
+    fn decode_field<D>(decoder: &mut Decoder<D>, tag: Tag, input: &mut &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode_map(tag, input)
+    }
+}
+
+/// Maps, encoded as sequences of key/value entry messages (see the
+/// [`map`][`crate::map`] module)
+#[cfg(feature = "alloc")]
+impl<K, V> Encodable for BTreeMap<K, V>
+where
+    K: Encodable,
+    V: Encodable,
+{
+    const WIRE_TYPE: WireType = WireType::Sequence;
+
+    fn encode_field(
+        &self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.map(tag, critical, self)
+    }
+
+    fn encoded_field_len(&self, tag: Tag) -> usize {
+        field::length::map(tag, self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl SequenceElement for u64 {
+    const ELEMENT_TYPE: WireType = WireType::UInt64;
+
+    fn decode_seq<D>(
+        decoder: &mut Decoder<D>,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Vec<Self>, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode_uint64_seq(tag, input)
+    }
+
+    fn encode_seq_with<T>(
+        values: &[T],
+        element: impl Fn(&T) -> &Self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.uint64_seq_iter(tag, critical, values.iter().map(&element))
+    }
+
+    fn encoded_seq_len_with<T>(values: &[T], element: impl Fn(&T) -> &Self, tag: Tag) -> usize {
+        field::length::sequence(
+            tag,
+            WireType::UInt64,
+            field::length::uint64_seq_body(values.iter().map(&element)),
+        )
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl SequenceElement for i64 {
+    const ELEMENT_TYPE: WireType = WireType::SInt64;
+
+    fn decode_seq<D>(
+        decoder: &mut Decoder<D>,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Vec<Self>, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode_sint64_seq(tag, input)
+    }
+
+    fn encode_seq_with<T>(
+        values: &[T],
+        element: impl Fn(&T) -> &Self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.sint64_seq_iter(tag, critical, values.iter().map(&element))
+    }
+
+    fn encoded_seq_len_with<T>(values: &[T], element: impl Fn(&T) -> &Self, tag: Tag) -> usize {
+        field::length::sequence(
+            tag,
+            WireType::SInt64,
+            field::length::sint64_seq_body(values.iter().map(&element)),
+        )
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl SequenceElement for Vec<u8> {
+    const ELEMENT_TYPE: WireType = WireType::Bytes;
+
+    fn decode_seq<D>(
+        decoder: &mut Decoder<D>,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Vec<Self>, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode_bytes_seq(tag, input)
+    }
+
+    fn encode_seq_with<T>(
+        values: &[T],
+        element: impl Fn(&T) -> &Self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.bytes_seq_iter(tag, critical, values.iter().map(&element))
+    }
+
+    fn encoded_seq_len_with<T>(values: &[T], element: impl Fn(&T) -> &Self, tag: Tag) -> usize {
+        field::length::sequence(
+            tag,
+            WireType::Bytes,
+            field::length::bytes_seq_body(values.iter().map(&element)),
+        )
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl SequenceElement for String {
+    const ELEMENT_TYPE: WireType = WireType::String;
+
+    fn decode_seq<D>(
+        decoder: &mut Decoder<D>,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Vec<Self>, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode_string_seq(tag, input)
+    }
+
+    fn encode_seq_with<T>(
+        values: &[T],
+        element: impl Fn(&T) -> &Self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.string_seq_iter(tag, critical, values.iter().map(&element))
+    }
+
+    fn encoded_seq_len_with<T>(values: &[T], element: impl Fn(&T) -> &Self, tag: Tag) -> usize {
+        field::length::sequence(
+            tag,
+            WireType::String,
+            field::length::string_seq_body(values.iter().map(&element)),
+        )
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<M> SequenceElement for M
+where
+    M: Message,
+{
+    const ELEMENT_TYPE: WireType = WireType::Message;
+
+    fn decode_seq<D>(
+        decoder: &mut Decoder<D>,
+        tag: Tag,
+        input: &mut &[u8],
+    ) -> Result<Vec<Self>, Error>
+    where
+        D: Digest,
+    {
+        decoder.decode_message_seq(tag, input)
+    }
+
+    fn encode_seq_with<T>(
+        values: &[T],
+        element: impl Fn(&T) -> &Self,
+        encoder: &mut Encoder<'_>,
+        tag: Tag,
+        critical: bool,
+    ) -> Result<(), Error> {
+        encoder.message_seq(tag, critical, values.iter().map(&element))
+    }
+
+    fn encoded_seq_len_with<T>(values: &[T], element: impl Fn(&T) -> &Self, tag: Tag) -> usize {
+        field::length::message_seq(tag, values.iter().map(&element))
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{Decodable, Encodable};
+    use crate::{field::Tag, Decoder, Encoder};
+    #[cfg(feature = "alloc")]
+    use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+    use core::fmt::Debug;
+
+    /// Encode the given value as the field with the given tag, check its
+    /// encoded length, then decode it
+    fn round_trip<T>(tag: Tag, value: &T) -> T
+    where
+        T: Decodable + Encodable,
+    {
+        let mut buffer = [0u8; 256];
+        let mut encoder = Encoder::new(&mut buffer);
+        value.encode_field(&mut encoder, tag, false).unwrap();
+        let mut input = encoder.finish();
+        assert_eq!(input.len(), value.encoded_field_len(tag));
+
+        let decoded = T::decode_field(&mut Decoder::new(), tag, &mut input).unwrap();
+        assert!(input.is_empty());
+        decoded
+    }
+
+    fn check<T>(value: T)
+    where
+        T: Decodable + Encodable + Debug + PartialEq,
+    {
+        assert_eq!(round_trip(Tag::new_const(1), &value), value);
+    }
+
+    #[test]
+    fn scalars() {
+        check(true);
+        check(false);
+        check(42u64);
+        check(-42i64);
+        check(u8::MAX);
+        check(u32::MAX);
+        check(i16::MIN);
+        check(i32::MAX);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn owned() {
+        check(String::from("veriform"));
+        check(b"veriform".to_vec());
+        check(Some(String::from("veriform")));
+    }
+
+    #[test]
+    fn optional() {
+        check(Some(42u64));
+
+        // An absent field decodes as `None`, leaving the next field
+        let mut input = &[69, 85][..];
+        let decoded =
+            <Option<u64>>::decode_field(&mut Decoder::new(), Tag::new_const(1), &mut input);
+        assert_eq!(decoded.unwrap(), None);
+        assert_eq!(input, &[69, 85]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn sequences() {
+        check(vec![1u64, 2, 3]);
+        check(vec![-1i64, 0, 1]);
+        check(Vec::<u64>::new());
+        check(vec![b"veri".to_vec(), Vec::new(), b"form".to_vec()]);
+        check(vec![
+            String::from("veri"),
+            String::new(),
+            String::from("form"),
+        ]);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn maps() {
+        let mut map = BTreeMap::new();
+        map.insert(String::from("a"), Some(true));
+        map.insert(String::from("b"), Some(false));
+        check(map);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn borrowed_encodes_like_owned() {
+        let mut borrowed = [0u8; 16];
+        let mut owned = [0u8; 16];
+
+        let mut encoder = Encoder::new(&mut borrowed);
+        "veriform"
+            .encode_field(&mut encoder, Tag::new_const(1), false)
+            .unwrap();
+        let borrowed = encoder.finish();
+
+        let mut encoder = Encoder::new(&mut owned);
+        String::from("veriform")
+            .encode_field(&mut encoder, Tag::new_const(1), false)
+            .unwrap();
+
+        assert_eq!(borrowed, encoder.finish());
+    }
+}
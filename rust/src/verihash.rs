@@ -2,17 +2,66 @@
 
 // TODO(tarcieri): refactor/DRY out message/sequence hashers into this module
 
-use crate::field::{Tag, WireType};
+#[cfg(feature = "test-vectors")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-vectors")))]
+pub mod test_vectors;
+
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod consistency;
+
+mod output;
+pub(crate) mod schemaless;
+#[cfg(feature = "std")]
+mod writer;
+
+pub use self::output::Output;
+
+#[cfg(feature = "std")]
+#[cfg_attr(docsrs, doc(cfg(feature = "std")))]
+pub use self::writer::Writer;
+
+pub use crate::spec::VERIHASH_TRANSCRIPT_VERSION as TRANSCRIPT_VERSION;
+
+use crate::{
+    decoder,
+    error::Error,
+    field::{Tag, WireType},
+    spec, zigzag,
+};
 use digest::{generic_array::GenericArray, Digest};
 
+#[cfg(feature = "alloc")]
+use crate::message::Message;
+
 /// Output of a given digest algorithm
 pub type DigestOutput<D> = GenericArray<u8, <D as Digest>::OutputSize>;
 
-/// Verihash prefix used by tags (unsigned integer)
-// TODO(tarcieri): support string tags?
-const TAG_PREFIX: u8 = WireType::UInt64.to_u8();
+/// Compute the Verihash digest of a serialized message.
+///
+/// See [`decoder::Hasher::hash_message`] for information on how errors are
+/// reported.
+pub fn verihash<D: Digest>(input: &[u8]) -> Result<Output<D>, Error> {
+    let mut hasher = decoder::Hasher::<D>::new();
+    hasher.hash_message(input)?;
+    hasher.finish()
+}
+
+/// Compute the Verihash digest of a [`Message`], encoding it into a
+/// temporary buffer and hashing the result.
+///
+/// Returns an error if the message fails to encode.
+#[cfg(feature = "alloc")]
+pub fn hash<D, M>(message: &M) -> Result<Output<D>, Error>
+where
+    D: Digest,
+    M: Message + ?Sized,
+{
+    verihash::<D>(&message.encode_vec()?)
+}
 
 /// Verihash hasher: computes digests of both messages and sequences
+#[derive(Clone)]
 pub(crate) struct Hasher<D: Digest>(D);
 
 impl<D> Hasher<D>
@@ -24,9 +73,77 @@ where
         Hasher(D::new())
     }
 
+    /// Create a Verihash hasher from a digest which may have already
+    /// absorbed part of a transcript
+    #[cfg(any(feature = "alloc", feature = "mac"))]
+    pub fn from_digest(digest: D) -> Self {
+        Hasher(digest)
+    }
+
+    /// Borrow the underlying digest
+    #[cfg(feature = "alloc")]
+    pub fn digest(&self) -> &D {
+        &self.0
+    }
+
+    /// Create a new Verihash hasher for a message transcript
+    pub fn new_message() -> Self {
+        let mut hasher = Self::new();
+        hasher.message_begin();
+        hasher
+    }
+
+    /// Create a new Verihash hasher for the transcript of a sequence
+    /// containing the given wire type
+    pub fn new_sequence(wire_type: WireType) -> Self {
+        let mut hasher = Self::new();
+        hasher.update(&[
+            spec::VERIHASH_SEQUENCE_PREFIX,
+            TRANSCRIPT_VERSION,
+            wire_type.to_u8(),
+        ]);
+        hasher
+    }
+
+    /// Create a new Verihash hasher whose transcript begins with the given
+    /// domain separation string.
+    ///
+    /// The domain is framed as the `0xFF` prefix byte, followed by the
+    /// length of the domain as a little endian `u64`, followed by the bytes
+    /// of the domain itself.
+    pub fn new_with_domain(domain: &[u8]) -> Self {
+        let mut hasher = Self::new();
+        hasher.update(&[spec::VERIHASH_DOMAIN_PREFIX]);
+        hasher.update(&(domain.len() as u64).to_le_bytes());
+        hasher.update(domain);
+        hasher
+    }
+
+    /// Begin a message transcript
+    pub fn message_begin(&mut self) {
+        self.update(&[spec::VERIHASH_MESSAGE_BEGIN, TRANSCRIPT_VERSION]);
+    }
+
+    /// End a message transcript
+    pub fn message_end(&mut self) {
+        self.update(&[spec::VERIHASH_MESSAGE_END]);
+    }
+
+    /// Begin the frame of the message with the given index within a stream
+    pub fn frame_begin(&mut self, index: u64) {
+        self.update(&[spec::VERIHASH_FRAME_BEGIN]);
+        self.update(&index.to_le_bytes());
+    }
+
+    /// End the frame of the message with the given index within a stream
+    pub fn frame_end(&mut self, index: u64) {
+        self.update(&[spec::VERIHASH_FRAME_END]);
+        self.update(&index.to_le_bytes());
+    }
+
     /// Hash a tagged boolean value
-    pub fn tagged_boolean(&mut self, tag: Tag, value: bool) {
-        self.tag(tag);
+    pub fn tagged_boolean(&mut self, tag: Tag, critical: bool, value: bool) {
+        self.field(tag, critical);
         self.boolean(value);
     }
 
@@ -40,8 +157,8 @@ where
     }
 
     /// Hash a tagged unsigned 64-bit integer
-    pub fn tagged_uint64(&mut self, tag: Tag, value: u64) {
-        self.tag(tag);
+    pub fn tagged_uint64(&mut self, tag: Tag, critical: bool, value: u64) {
+        self.field(tag, critical);
         self.uint64(value);
     }
 
@@ -51,21 +168,34 @@ where
     }
 
     /// Hash a tagged signed 64-bit integer
-    pub fn tagged_sint64(&mut self, tag: Tag, value: i64) {
-        self.tag(tag);
+    pub fn tagged_sint64(&mut self, tag: Tag, critical: bool, value: i64) {
+        self.field(tag, critical);
         self.sint64(value);
     }
 
-    /// Hash a signed 64-bit integer
+    /// Hash a signed 64-bit integer, zigzag encoded as it is on the wire
     pub fn sint64(&mut self, value: i64) {
-        self.fixed_size_value(WireType::SInt64, &value.to_le_bytes());
+        self.fixed_size_value(WireType::SInt64, &zigzag::encode(value).to_le_bytes());
     }
 
-    /// Hash a numerical tag
-    // TODO(tarcieri): support string tags?
-    pub fn tag(&mut self, tag: Tag) {
-        self.update(&[TAG_PREFIX]);
-        self.update(&tag.to_le_bytes());
+    /// Hash the numerical tag of a field, prefixed according to whether
+    /// the field is critical
+    pub fn field(&mut self, tag: Tag, critical: bool) {
+        if critical {
+            self.update(&[spec::VERIHASH_CRITICAL_FIELD_PREFIX]);
+        } else {
+            self.update(&[spec::VERIHASH_FIELD_PREFIX]);
+        }
+
+        self.update(&tag.to_u64().to_le_bytes());
+    }
+
+    /// Hash the digest of a redactable field's transcript in place of its
+    /// value
+    pub fn redacted_field(&mut self, tag: Tag, critical: bool, digest: &[u8]) {
+        self.field(tag, critical);
+        self.update(&[spec::VERIHASH_REDACTED_FIELD]);
+        self.update(digest);
     }
 
     /// Hash a dynamically sized value
@@ -85,10 +215,27 @@ where
         self.0.update(data);
     }
 
+    /// Reset the underlying hash function to its initial state
+    pub fn reset(&mut self) {
+        self.0.reset();
+    }
+
     /// Finish computing the digest, returning the output value
     pub fn finalize(self) -> DigestOutput<D> {
         self.0.finalize()
     }
+
+    /// End a message transcript and finish computing its digest
+    pub fn finalize_message(mut self) -> DigestOutput<D> {
+        self.message_end();
+        self.finalize()
+    }
+
+    /// Finish computing the digest, returning the output value and resetting
+    /// the hasher to its initial state
+    pub fn finalize_reset(&mut self) -> DigestOutput<D> {
+        self.0.finalize_reset()
+    }
 }
 
 impl<D> Default for Hasher<D>
@@ -99,3 +246,119 @@ where
         Self::new()
     }
 }
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{schemaless, verihash, WireType};
+    use crate::{field::Tag, Encoder};
+    use sha2::Sha256;
+
+    /// Number of elements in the colliding sequence
+    const SEQ_LEN: usize = 58;
+
+    /// Length of the legacy transcript of the colliding sequence
+    const TRANSCRIPT_LEN: usize = 1 + 9 * SEQ_LEN + 9;
+
+    /// Elements of a `sequence<uint64>` whose transcript under the original
+    /// framing (where tags were prefixed with the `uint64` wire type and
+    /// sequences began with their element wire type) was also the transcript
+    /// of a message containing a single `bytes` field
+    fn colliding_seq() -> [u64; SEQ_LEN] {
+        let mut values = [0u64; SEQ_LEN];
+
+        // Parsed as tag `2` followed by the `bytes` wire type
+        values[0] = 0x0400_0000_0000_0000;
+
+        // Parsed (along with the preceding prefix) as a length of 514
+        values[1] = 0x02;
+        values
+    }
+
+    /// Compute the legacy transcript of the colliding sequence
+    fn legacy_seq_transcript() -> [u8; TRANSCRIPT_LEN] {
+        let mut transcript = [0u8; TRANSCRIPT_LEN];
+        transcript[0] = WireType::UInt64.to_u8();
+
+        let values = colliding_seq();
+        let count = [SEQ_LEN as u64];
+
+        for (i, value) in values.iter().chain(count.iter()).enumerate() {
+            let offset = 1 + i * 9;
+            transcript[offset] = WireType::UInt64.to_u8();
+            transcript[(offset + 1)..(offset + 9)].copy_from_slice(&value.to_le_bytes());
+        }
+
+        transcript
+    }
+
+    #[test]
+    fn sequence_message_ambiguity() {
+        let seq_transcript = legacy_seq_transcript();
+        let data = &seq_transcript[18..];
+
+        // Legacy transcript of `{ 2: bytes(data) }`
+        let mut msg_transcript = [0u8; TRANSCRIPT_LEN];
+        msg_transcript[0] = WireType::UInt64.to_u8();
+        msg_transcript[1..9].copy_from_slice(&2u64.to_le_bytes());
+        msg_transcript[9] = WireType::Bytes.to_u8();
+        msg_transcript[10..18].copy_from_slice(&(data.len() as u64).to_le_bytes());
+        msg_transcript[18..].copy_from_slice(data);
+
+        // Old framing: different values with identical transcripts
+        assert_eq!(&seq_transcript[..], &msg_transcript[..]);
+
+        let mut msg_buf = [0u8; TRANSCRIPT_LEN];
+        let mut encoder = Encoder::new(&mut msg_buf);
+        encoder.bytes(Tag::new_const(2), false, data).unwrap();
+        let msg_digest = verihash::<Sha256>(encoder.finish()).unwrap();
+
+        let mut seq_buf = [0u8; 128];
+        let mut seq_len = 0;
+
+        for value in colliding_seq().iter() {
+            let encoded = vint64::encode(*value);
+            let encoded = encoded.as_ref();
+            seq_buf[seq_len..(seq_len + encoded.len())].copy_from_slice(encoded);
+            seq_len += encoded.len();
+        }
+
+        let seq_digest = schemaless::sequence_digest::<Sha256>(
+            WireType::UInt64,
+            &seq_buf[..seq_len],
+            seq_len,
+            Default::default(),
+        )
+        .unwrap();
+
+        // New framing: message and sequence transcripts are distinct
+        assert_ne!(msg_digest.into_bytes(), seq_digest);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn hash_propagates_encoding_errors() {
+        use crate::{decoder::Decoder, error, message::Message, Error};
+        use digest::Digest;
+
+        /// Message whose encoding always fails
+        struct Unencodable;
+
+        impl Message for Unencodable {
+            fn decode<D: Digest>(_: &mut Decoder<D>, _: &[u8]) -> Result<Self, Error> {
+                Ok(Unencodable)
+            }
+
+            fn encode<'a>(&self, _: &'a mut [u8]) -> Result<&'a [u8], Error> {
+                Err(error::Kind::UnicodeNormalization.into())
+            }
+
+            fn encoded_len(&self) -> usize {
+                0
+            }
+        }
+
+        let err = super::hash::<Sha256, _>(&Unencodable).err().unwrap();
+        assert_eq!(err.kind(), error::Kind::UnicodeNormalization);
+        assert!(Unencodable.verihash::<Sha256>().is_err());
+    }
+}
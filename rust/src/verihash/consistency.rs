@@ -0,0 +1,480 @@
+//! Consistency checks between the encoder-side and decoder-side hashers.
+//!
+//! The encoder's [`crate::encoder::Hasher`] and the decoder's
+//! [`crate::decoder::Hasher`] must always compute the same digest for a given
+//! message. [`check`] verifies this invariant for an arbitrary [`Message`],
+//! e.g. as a `proptest` property or a fuzz target.
+
+// `displaydoc` emits its `Display` impls inside of an anonymous `const`
+#![allow(unknown_lints, non_local_definitions)]
+
+use super::{verihash, Output};
+use crate::{
+    decoder::{self, Decodable, Event},
+    encoder,
+    error::{self, Error},
+    field::{Header, Tag, WireType},
+    message::Message,
+};
+use alloc::vec::Vec;
+use core::str;
+use digest::Digest;
+use displaydoc::Display;
+
+/// Mismatch between the encoder-side and decoder-side digests of a message
+#[derive(Copy, Clone, Debug, Display, Eq, PartialEq)]
+pub enum Mismatch {
+    /// error checking message: {0}
+    Error(Error),
+
+    /// digests diverged at field: tag={tag}
+    Field {
+        /// tag of the first field whose inclusion made the digests diverge
+        tag: Tag,
+    },
+
+    /// digests of the empty message diverged
+    Empty,
+}
+
+impl From<Error> for Mismatch {
+    fn from(err: Error) -> Mismatch {
+        Mismatch::Error(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Mismatch {}
+
+/// Encode the given message, then compute its digest with both the encoder's
+/// and the decoder's hasher, ensuring they're identical.
+///
+/// If they differ, the message is hashed one field at a time to find the
+/// first field at which the digests diverge.
+pub fn check<D, M>(message: &M) -> Result<(), Mismatch>
+where
+    D: Digest,
+    M: Message,
+{
+    let encoded = message.encode_vec()?;
+    compare::<D, _, _>(&encoded, encoder_digest::<D>, verihash::<D>)
+}
+
+/// Compare the digests of an encoded message computed by the given
+/// encoder-side and decoder-side hash functions
+fn compare<D, E, F>(encoded: &[u8], encoder_hash: E, decoder_hash: F) -> Result<(), Mismatch>
+where
+    D: Digest,
+    E: Fn(&[u8]) -> Result<Output<D>, Error>,
+    F: Fn(&[u8]) -> Result<Output<D>, Error>,
+{
+    if encoder_hash(encoded)? == decoder_hash(encoded)? {
+        return Ok(());
+    }
+
+    // Every prefix of a message ending on a field boundary is itself a
+    // message, so hash successively longer prefixes until they diverge
+    for (tag, end) in Replay::<D>::fields(encoded)? {
+        let prefix = &encoded[..end];
+
+        if encoder_hash(prefix)? != decoder_hash(prefix)? {
+            return Err(Mismatch::Field { tag });
+        }
+    }
+
+    Err(Mismatch::Empty)
+}
+
+/// Compute the digest of an encoded message by decoding it and writing each
+/// of its fields with the encoder's hasher
+fn encoder_digest<D: Digest>(encoded: &[u8]) -> Result<Output<D>, Error> {
+    let mut buffer = vec![0; encoded.len()];
+    let mut hasher = encoder::Hasher::<D>::new(&mut buffer);
+    Replay::new(&mut hasher).run(encoded)?;
+
+    let (reencoded, digest) = hasher.finish();
+    debug_assert_eq!(reencoded, encoded);
+    Ok(digest.into())
+}
+
+/// Replays the fields of an encoded message into an encoder-side hasher
+struct Replay<'h, 'b, D: Digest> {
+    /// Encoder-side hasher (or `None` if only locating fields)
+    hasher: Option<&'h mut encoder::Hasher<'b, D>>,
+
+    /// Header of the field currently being decoded
+    header: Option<Header>,
+
+    /// Wire type of the elements of the sequence currently being decoded
+    element_type: Option<WireType>,
+
+    /// Value of the dynamically sized field currently being decoded
+    value: Vec<u8>,
+
+    /// Tag and end offset of each field which has been decoded
+    fields: Vec<(Tag, usize)>,
+}
+
+impl<'h, 'b, D> Replay<'h, 'b, D>
+where
+    'b: 'h,
+    D: Digest + 'h,
+{
+    /// Create a new [`Replay`] which writes fields with the given hasher
+    fn new(hasher: &'h mut encoder::Hasher<'b, D>) -> Self {
+        Self {
+            hasher: Some(hasher),
+            header: None,
+            element_type: None,
+            value: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Get the tag and end offset of each field of an encoded message
+    fn fields(encoded: &[u8]) -> Result<Vec<(Tag, usize)>, Error> {
+        let mut replay = Self {
+            hasher: None,
+            header: None,
+            element_type: None,
+            value: Vec::new(),
+            fields: Vec::new(),
+        };
+
+        replay.run(encoded)?;
+        Ok(replay.fields)
+    }
+
+    /// Decode the given message, writing each of its fields
+    fn run(&mut self, encoded: &[u8]) -> Result<(), Error> {
+        let mut decoder = decoder::message::Decoder::<D>::without_hasher();
+        let mut input = encoded;
+
+        loop {
+            let event = match decoder.decode(&mut input)? {
+                Some(event) => event,
+                // Booleans are emitted without consuming input, so continue
+                // decoding until no further events are produced
+                None if input.is_empty() => break,
+                None => continue,
+            };
+
+            if self.handle_event(event)? {
+                let header = self
+                    .header
+                    .take()
+                    .ok_or(error::HashingReason::UnexpectedEvent)?;
+                let end = encoded.len().checked_sub(input.len()).unwrap();
+                self.fields.push((header.tag, end));
+            }
+        }
+
+        decoder.ensure_complete()
+    }
+
+    /// Handle a decoding event, returning `true` if it completed a field
+    fn handle_event(&mut self, event: Event<'_>) -> Result<bool, Error> {
+        match event {
+            Event::FieldHeader(header) => {
+                self.header = Some(header);
+                Ok(false)
+            }
+            Event::Bool(value) => {
+                self.write(|hasher, tag, critical| hasher.bool(tag, critical, value))
+            }
+            Event::UInt64(value) => {
+                self.write(|hasher, tag, critical| hasher.uint64(tag, critical, value))
+            }
+            Event::SInt64(value) => {
+                self.write(|hasher, tag, critical| hasher.sint64(tag, critical, value))
+            }
+            Event::LengthDelimiter { wire_type, length } => {
+                if length == 0 {
+                    self.write_value(wire_type)
+                } else {
+                    Ok(false)
+                }
+            }
+            Event::SequenceHeader { wire_type, length } => {
+                self.element_type = Some(wire_type);
+
+                if length == 0 {
+                    self.write_value(WireType::Sequence)
+                } else {
+                    Ok(false)
+                }
+            }
+            Event::ValueChunk {
+                wire_type,
+                bytes,
+                remaining,
+            } => {
+                self.value.extend_from_slice(bytes);
+
+                if remaining == 0 {
+                    self.write_value(wire_type)
+                } else {
+                    Ok(false)
+                }
+            }
+        }
+    }
+
+    /// Write the buffered value of a dynamically sized field
+    fn write_value(&mut self, wire_type: WireType) -> Result<bool, Error> {
+        let value = core::mem::take(&mut self.value);
+
+        match wire_type {
+            WireType::Bytes => {
+                self.write(|hasher, tag, critical| hasher.bytes(tag, critical, &value))
+            }
+            WireType::String => {
+                let string = str::from_utf8(&value)?;
+
+                self.write(|hasher, tag, critical| hasher.string(tag, critical, string))
+            }
+            WireType::Message => self.write(|hasher, tag, critical| {
+                hasher.message_with(tag, critical, value.len(), |nested| {
+                    Replay::new(nested).run(&value)
+                })
+            }),
+            WireType::Sequence => {
+                // The encoder only supports sequences of messages
+                if self.element_type.take() != Some(WireType::Message) {
+                    return Err(error::Kind::InvalidWireType.into());
+                }
+
+                let mut elements = Vec::new();
+                let mut input = value.as_slice();
+
+                while !input.is_empty() {
+                    let element_len = vint64::decode(&mut input)? as usize;
+
+                    if element_len > input.len() {
+                        return Err(error::Kind::Truncated {
+                            remaining: element_len - input.len(),
+                            wire_type: WireType::Message,
+                        }
+                        .into());
+                    }
+
+                    elements.push(&input[..element_len]);
+                    input = &input[element_len..];
+                }
+
+                self.write(|hasher, tag, critical| {
+                    let lengths = elements.iter().map(|element| element.len());
+
+                    hasher.message_seq_with(tag, critical, lengths, |index, nested| {
+                        Replay::new(nested).run(elements[index])
+                    })
+                })
+            }
+            _ => Err(error::Kind::InvalidWireType.into()),
+        }
+    }
+
+    /// Write the current field with the hasher (if any), completing it
+    fn write<F>(&mut self, f: F) -> Result<bool, Error>
+    where
+        F: FnOnce(&mut encoder::Hasher<'b, D>, Tag, bool) -> Result<(), Error>,
+    {
+        let header = self.header.ok_or(error::HashingReason::UnexpectedEvent)?;
+
+        if let Some(hasher) = self.hasher.as_mut() {
+            f(hasher, header.tag, header.critical)?;
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::{check, compare, encoder_digest, Mismatch, Replay};
+    use crate::{
+        decoder,
+        field::{self, Tag},
+        verihash::{verihash, Output},
+        Encoder, Error, Message,
+    };
+    use alloc::{boxed::Box, string::ToString, vec::Vec};
+    use core::iter;
+    use digest::Digest;
+    use sha2::Sha256;
+
+    /// Message nested within other messages, containing its depth
+    struct Nested {
+        depth: u64,
+        child: Option<Box<Nested>>,
+    }
+
+    impl Nested {
+        /// Create a chain of messages nested `depth` levels deep
+        fn new(depth: u64) -> Self {
+            Self {
+                depth,
+                child: if depth > 1 {
+                    Some(Box::new(Self::new(depth - 1)))
+                } else {
+                    None
+                },
+            }
+        }
+    }
+
+    impl Message for Nested {
+        fn decode<D: Digest>(
+            _decoder: &mut decoder::Decoder<D>,
+            _input: &[u8],
+        ) -> Result<Self, Error> {
+            unimplemented!()
+        }
+
+        fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+            let mut encoder = Encoder::new(buffer);
+            encoder.uint64(Tag::new_const(0), false, self.depth)?;
+
+            if let Some(child) = &self.child {
+                encoder.message(Tag::new_const(1), true, child.as_ref())?;
+            }
+
+            Ok(encoder.finish())
+        }
+
+        fn encoded_len(&self) -> usize {
+            field::length::uint64(Tag::new_const(0), self.depth)
+                + self
+                    .child
+                    .as_ref()
+                    .map(|child| field::length::message(Tag::new_const(1), child.as_ref()))
+                    .unwrap_or(0)
+        }
+    }
+
+    /// Example message containing a field of every wire type
+    struct KitchenSink {
+        children: Vec<Nested>,
+    }
+
+    impl KitchenSink {
+        fn new() -> Self {
+            Self {
+                children: (1..=3).map(Nested::new).collect(),
+            }
+        }
+    }
+
+    impl Message for KitchenSink {
+        fn decode<D: Digest>(
+            _decoder: &mut decoder::Decoder<D>,
+            _input: &[u8],
+        ) -> Result<Self, Error> {
+            unimplemented!()
+        }
+
+        fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+            let children = self.children.iter().map(|msg| msg as &dyn Message);
+
+            let mut encoder = Encoder::new(buffer);
+            encoder.bool(Tag::new_const(0), false, false)?;
+            encoder.bool(Tag::new_const(1), true, true)?;
+            encoder.uint64(Tag::new_const(2), false, 42)?;
+            encoder.sint64(Tag::new_const(3), false, -42)?;
+            encoder.bytes(Tag::new_const(4), false, b"foobar")?;
+            encoder.string(Tag::new_const(5), false, "baz")?;
+            encoder.bytes(Tag::new_const(6), false, b"")?;
+            encoder.message(Tag::new_const(7), false, &Nested::new(2))?;
+            encoder.message_seq(Tag::new_const(8), false, children)?;
+            encoder.message_seq(Tag::new_const(9), false, iter::empty::<&Nested>())?;
+            Ok(encoder.finish())
+        }
+
+        fn encoded_len(&self) -> usize {
+            let children = self.children.iter().map(|msg| msg as &dyn Message);
+
+            2 + field::length::uint64(Tag::new_const(2), 42)
+                + field::length::sint64(Tag::new_const(3), -42)
+                + field::length::bytes(Tag::new_const(4), b"foobar")
+                + field::length::string(Tag::new_const(5), "baz")
+                + field::length::bytes(Tag::new_const(6), b"")
+                + field::length::message(Tag::new_const(7), &Nested::new(2))
+                + field::length::message_seq(Tag::new_const(8), children)
+                + field::length::message_seq(Tag::new_const(9), iter::empty::<&Nested>())
+        }
+    }
+
+    #[test]
+    fn kitchen_sink() {
+        check::<Sha256, _>(&KitchenSink::new()).unwrap();
+    }
+
+    #[test]
+    fn deeply_nested() {
+        // The outermost message counts towards the maximum depth of 16
+        check::<Sha256, _>(&Nested::new(16)).unwrap();
+
+        let err = check::<Sha256, _>(&Nested::new(17)).err().unwrap();
+        assert!(matches!(err, Mismatch::Error(_)));
+    }
+
+    #[cfg(feature = "tai64")]
+    #[test]
+    fn timestamp() {
+        use core::convert::TryInto;
+
+        let timestamp: crate::builtins::Timestamp =
+            [64, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 7].try_into().unwrap();
+
+        check::<Sha256, _>(&timestamp).unwrap();
+    }
+
+    #[cfg(feature = "uuid")]
+    #[test]
+    fn uuid() {
+        let uuid = crate::builtins::Uuid::from_bytes([42; 16]);
+        check::<Sha256, _>(&uuid).unwrap();
+    }
+
+    #[test]
+    fn diverging_field() {
+        let encoded = KitchenSink::new().encode_vec().unwrap();
+        let fields = Replay::<Sha256>::fields(&encoded).unwrap();
+        assert_eq!(fields.len(), 10);
+
+        // Decoder-side hash which is domain separated once the input
+        // includes the field with tag 4
+        let (_, end) = fields[4];
+        let decoder_hash = |input: &[u8]| {
+            if input.len() < end {
+                return verihash::<Sha256>(input);
+            }
+
+            let mut hasher = decoder::Hasher::<Sha256>::new_with_domain(b"example");
+            hasher.hash_message(input)?;
+            hasher.finish()
+        };
+
+        let err = compare::<Sha256, _, _>(&encoded, encoder_digest::<Sha256>, decoder_hash)
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            err,
+            Mismatch::Field {
+                tag: Tag::new_const(4)
+            }
+        );
+        assert_eq!(err.to_string(), "digests diverged at field: tag=4");
+    }
+
+    #[test]
+    fn diverging_empty_message() {
+        let decoder_hash = |_: &[u8]| Ok(Output::<Sha256>::new(Default::default()));
+        let err = compare::<Sha256, _, _>(&[], encoder_digest::<Sha256>, decoder_hash)
+            .err()
+            .unwrap();
+
+        assert_eq!(err, Mismatch::Empty);
+    }
+}
@@ -0,0 +1,201 @@
+//! Verihash digest outputs
+
+use super::DigestOutput;
+use crate::error::{self, Error};
+use core::{
+    any::{self, TypeId},
+    fmt::{self, Debug, Display, LowerHex},
+};
+use digest::Digest;
+use subtle::ConstantTimeEq;
+
+#[cfg(feature = "alloc")]
+use alloc::string::String;
+
+/// Finalized Verihash digest.
+///
+/// Outputs are parameterized by the digest algorithm `D` which produced
+/// them, so comparing e.g. a SHA-256 output with a BLAKE2 output is a type
+/// error. Comparisons (including `==`) are constant-time.
+pub struct Output<D: Digest> {
+    bytes: DigestOutput<D>,
+}
+
+impl<D> Output<D>
+where
+    D: Digest,
+{
+    /// Create a new [`Output`] from a finalized digest
+    pub fn new(bytes: DigestOutput<D>) -> Self {
+        Self { bytes }
+    }
+
+    /// Name of the digest algorithm which produced this output.
+    ///
+    /// This is intended for diagnostics and its exact contents aren't stable:
+    /// it isn't used to compare algorithms (see [`Output::verify_output`]).
+    pub fn algorithm(&self) -> &'static str {
+        any::type_name::<D>()
+    }
+
+    /// Borrow the digest as a byte slice
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Convert this output into the underlying digest
+    pub fn into_bytes(self) -> DigestOutput<D> {
+        self.bytes
+    }
+
+    /// Compare this output against an expected digest in constant time.
+    ///
+    /// Returns an error if `expected` differs in either length or contents.
+    pub fn verify(&self, expected: &[u8]) -> Result<(), Error> {
+        if bool::from(self.bytes.as_slice().ct_eq(expected)) {
+            Ok(())
+        } else {
+            Err(error::HashingReason::DigestMismatch.into())
+        }
+    }
+
+    /// Compare this output against one produced by a possibly different
+    /// digest algorithm in constant time.
+    ///
+    /// Returns an error if the outputs were produced by different algorithms,
+    /// i.e. different digest types.
+    pub fn verify_output<E>(&self, expected: &Output<E>) -> Result<(), Error>
+    where
+        D: 'static,
+        E: Digest + 'static,
+    {
+        if TypeId::of::<D>() != TypeId::of::<E>() {
+            return Err(error::HashingReason::AlgorithmMismatch.into());
+        }
+
+        self.verify(expected.as_bytes())
+    }
+
+    /// Encode this output as lower-case hexadecimal
+    #[cfg(feature = "alloc")]
+    pub fn to_hex(&self) -> String {
+        format!("{:x}", self)
+    }
+}
+
+impl<D> AsRef<[u8]> for Output<D>
+where
+    D: Digest,
+{
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<D> From<DigestOutput<D>> for Output<D>
+where
+    D: Digest,
+{
+    fn from(bytes: DigestOutput<D>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl<D> Clone for Output<D>
+where
+    D: Digest,
+{
+    fn clone(&self) -> Self {
+        Self::new(self.bytes.clone())
+    }
+}
+
+impl<D> PartialEq for Output<D>
+where
+    D: Digest,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes.as_slice().ct_eq(&other.bytes).into()
+    }
+}
+
+impl<D> Eq for Output<D> where D: Digest {}
+
+impl<D> Debug for Output<D>
+where
+    D: Digest,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "verihash::Output<{}>({:x})", self.algorithm(), self)
+    }
+}
+
+impl<D> Display for Output<D>
+where
+    D: Digest,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        LowerHex::fmt(self, f)
+    }
+}
+
+impl<D> LowerHex for Output<D>
+where
+    D: Digest,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.bytes.iter() {
+            write!(f, "{:02x}", byte)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::Output;
+    use crate::{error, verihash::verihash};
+    use sha2::{Sha256, Sha512};
+
+    /// Verihash of `{ 1: uint64(42) }` (see the `uint64: 42` test vector)
+    #[cfg(feature = "alloc")]
+    const EXAMPLE_HEX: &str = "e65d768a5b5c59ba890afbeb17b9fa11ef9db1bd086eab72326e01040c0bb3e8";
+
+    fn example() -> Output<Sha256> {
+        verihash::<Sha256>(&[37, 85]).unwrap()
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn to_hex() {
+        use alloc::string::ToString;
+
+        assert_eq!(example().to_hex(), EXAMPLE_HEX);
+        assert_eq!(example().to_string(), EXAMPLE_HEX);
+    }
+
+    #[test]
+    fn verify() {
+        let output = example();
+        output.verify(output.as_bytes()).unwrap();
+
+        let mut tampered = output.clone().into_bytes();
+        tampered[31] ^= 1;
+        let err = output.verify(&tampered).err().unwrap();
+        assert_eq!(err.kind(), error::HashingReason::DigestMismatch.into());
+        assert_ne!(output, Output::from(tampered));
+
+        assert!(output.verify(&output.as_bytes()[..16]).is_err());
+    }
+
+    #[test]
+    fn algorithm_mismatch() {
+        let sha256 = example();
+        let sha512 = verihash::<Sha512>(&[37, 85]).unwrap();
+
+        sha256.verify_output(&sha256.clone()).unwrap();
+        assert_ne!(sha256.algorithm(), sha512.algorithm());
+        assert!(sha256.verify_output(&sha512).is_err());
+    }
+}
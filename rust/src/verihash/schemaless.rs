@@ -0,0 +1,159 @@
+//! Schemaless Verihash computation.
+//!
+//! Computes the Verihash digest of a serialized message without knowledge of
+//! its schema: every field is hashed according to the wire type in its
+//! header, recursing into nested messages and sequences.
+
+use super::{DigestOutput, Output};
+use crate::{
+    decoder::{message, sequence, Decodable, Event},
+    error::{self, Error},
+    field::{Path, WireType},
+};
+use digest::Digest;
+
+/// Default maximum message nesting depth (matching the `Decoder`)
+pub(crate) const DEFAULT_MAX_DEPTH: usize = 16;
+
+/// Nesting depth of the message being hashed, where the outermost message
+/// is at depth 1
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Depth {
+    /// Depth of the current message
+    current: usize,
+
+    /// Maximum allowed depth
+    max: usize,
+}
+
+impl Depth {
+    /// Depth of an outermost message with the given maximum depth
+    pub fn new(max: usize) -> Self {
+        Self { current: 1, max }
+    }
+
+    /// Depth of a message nested within the current one, or an error if
+    /// it exceeds the maximum depth
+    pub fn nested(self) -> Result<Self, Error> {
+        let current = self.current.checked_add(1).unwrap();
+
+        if current > self.max {
+            return Err(error::Kind::NestingDepth {
+                depth: current,
+                max: self.max,
+            }
+            .into());
+        }
+
+        Ok(Self { current, ..self })
+    }
+}
+
+impl Default for Depth {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_DEPTH)
+    }
+}
+
+/// Compute the Verihash digest of a message nested at the given depth
+pub(crate) fn nested_message_digest<D: Digest>(
+    input: &[u8],
+    chunk_size: usize,
+    depth: Depth,
+) -> Result<DigestOutput<D>, Error> {
+    let mut hasher = message::Hasher::<D>::new().with_max_depth(depth.max);
+    hasher.hash_message_at_depth(input, chunk_size, depth)?;
+    hasher.finish().map(Output::into_bytes)
+}
+
+/// Compute the Verihash digest of a sequence containing the given wire type,
+/// contained in a message at the given depth.
+///
+/// Errors carry their offset within the sequence's body.
+pub(crate) fn sequence_digest<D: Digest>(
+    wire_type: WireType,
+    input: &[u8],
+    chunk_size: usize,
+    depth: Depth,
+) -> Result<DigestOutput<D>, Error> {
+    let mut decoder = sequence::Decoder::<D>::new(wire_type, input.len());
+
+    hash_sequence(&mut decoder, wire_type, input, chunk_size, depth)
+        .map_err(|err| err.with_context(decoder.position(), None, Path::default()))?;
+
+    let position = decoder.position();
+    decoder
+        .compute_digest()
+        .and_then(|digest| digest.ok_or_else(|| error::HashingReason::Incomplete.into()))
+        .map_err(|err| err.with_context(position, None, Path::default()))
+}
+
+/// Decode the given sequence with the given decoder, hashing its elements
+fn hash_sequence<D: Digest>(
+    decoder: &mut sequence::Decoder<D>,
+    wire_type: WireType,
+    input: &[u8],
+    chunk_size: usize,
+    depth: Depth,
+) -> Result<(), Error> {
+    let mut body_start = 0;
+    let mut pos: usize = 0;
+
+    // Element wire type of the nested sequence currently being decoded
+    let mut inner_wire_type = wire_type;
+
+    loop {
+        let end = pos.checked_add(chunk_size).unwrap().min(input.len());
+        let mut chunk = &input[pos..end];
+        let event = decoder.decode(&mut chunk)?;
+        pos = end.checked_sub(chunk.len()).unwrap();
+
+        match event {
+            // Booleans are emitted without consuming input, so continue
+            // decoding until no further events are produced
+            None if pos == input.len() => break,
+            Some(Event::LengthDelimiter { wire_type, length }) => {
+                body_start = pos;
+
+                if wire_type == WireType::Message && length == 0 {
+                    let digest = nested_message_digest::<D>(&[], chunk_size, depth.nested()?)
+                        .map_err(|err| err.in_sequence(body_start, None))?;
+                    decoder.hash_message_digest(&digest)?;
+                }
+            }
+            Some(Event::ValueChunk {
+                wire_type: WireType::Message,
+                remaining: 0,
+                ..
+            }) => {
+                let body = &input[body_start..pos];
+                let digest = nested_message_digest::<D>(body, chunk_size, depth.nested()?)
+                    .map_err(|err| err.in_sequence(body_start, None))?;
+                decoder.hash_message_digest(&digest)?;
+            }
+            Some(Event::SequenceHeader { wire_type, length }) => {
+                body_start = pos;
+                inner_wire_type = wire_type;
+
+                if length == 0 {
+                    let digest = sequence_digest::<D>(wire_type, &[], chunk_size, depth)
+                        .map_err(|err| err.in_sequence(body_start, None))?;
+                    decoder.hash_sequence_digest(&digest)?;
+                }
+            }
+            Some(Event::ValueChunk {
+                wire_type: WireType::Sequence,
+                remaining: 0,
+                ..
+            }) => {
+                let body = &input[body_start..pos];
+                let digest = sequence_digest::<D>(inner_wire_type, body, chunk_size, depth)
+                    .map_err(|err| err.in_sequence(body_start, None))?;
+                decoder.hash_sequence_digest(&digest)?;
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! Verihash known-answer test vectors.
+//!
+//! The vectors live in `vectors/verihash.tjson` at the root of the Veriform
+//! repository, where they're shared with other Veriform implementations so
+//! they can check their Verihash transcripts are conformant.
+//!
+//! Each vector is decoded without a schema: every field is hashed according
+//! to the wire type in its header, recursing into nested messages and
+//! sequences. Vectors are run both with the entire input available at once
+//! and with the input fed to the decoder one byte at a time (so that e.g.
+//! `bytes` fields are hashed across multiple chunks).
+//!
+//! Vectors with a `domain` are hashed with a domain separated hasher
+//! (see `decoder::Hasher::new_with_domain`).
+
+use crate::{
+    decoder,
+    error::{self, Error},
+};
+use alloc::{string::String, vec::Vec};
+use core::str;
+use digest::Digest;
+use serde_json::Value;
+
+/// Verihash test vector
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TestVector {
+    /// Description of this test vector
+    pub name: String,
+
+    /// Serialized Veriform message
+    pub input: Vec<u8>,
+
+    /// Domain separation string the message is hashed under (if any)
+    pub domain: Option<Vec<u8>>,
+
+    /// Name of the digest algorithm used to compute the output
+    pub digest_alg: String,
+
+    /// Expected Verihash digest of the message
+    pub output: Vec<u8>,
+}
+
+/// Load test vectors from the TJSON format of `vectors/verihash.tjson`.
+///
+/// Returns [`error::Kind::TestVectors`] if the vectors are malformed.
+pub fn load(tjson: &str) -> Result<Vec<TestVector>, Error> {
+    let vectors: Value = serde_json::from_str(tjson).map_err(|_| malformed("invalid JSON"))?;
+
+    vectors["examples:A<O>"]
+        .as_array()
+        .ok_or_else(|| malformed("missing examples"))?
+        .iter()
+        .map(|example| {
+            let string = |key: &str| -> Result<&str, Error> {
+                example[key]
+                    .as_str()
+                    .ok_or_else(|| malformed("missing or non-string member"))
+            };
+
+            let domain = match example["domain:d16"].as_str() {
+                Some(domain) => Some(decode_hex(domain)?),
+                None => None,
+            };
+
+            Ok(TestVector {
+                name: string("name:s")?.into(),
+                input: decode_hex(string("input:d16")?)?,
+                domain,
+                digest_alg: string("algorithm:s")?.into(),
+                output: decode_hex(string("digest:d16")?)?,
+            })
+        })
+        .collect()
+}
+
+/// Run the given test vector, returning an error if the computed digest
+/// doesn't match the expected output.
+///
+/// The caller is responsible for selecting a digest algorithm `D` which
+/// matches the vector's `digest_alg`.
+pub fn run_vector<D: Digest + Clone>(vector: &TestVector) -> Result<(), Error> {
+    let whole = vector.input.len().max(1);
+
+    for &chunk_size in &[whole, 1] {
+        let mut hasher = match &vector.domain {
+            Some(domain) => decoder::Hasher::<D>::new_with_domain(domain),
+            None => decoder::Hasher::<D>::new(),
+        };
+
+        hasher.hash_message_chunked(&vector.input, chunk_size)?;
+        hasher.finish()?.verify(&vector.output)?;
+    }
+
+    Ok(())
+}
+
+/// Decode a hex string into bytes
+fn decode_hex(hex: &str) -> Result<Vec<u8>, Error> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => str::from_utf8(pair)
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok())
+                .ok_or_else(|| malformed("invalid hex")),
+            _ => Err(malformed("odd length hex string")),
+        })
+        .collect()
+}
+
+/// Error for malformed test vectors
+fn malformed(reason: &'static str) -> Error {
+    error::Kind::TestVectors { reason }.into()
+}
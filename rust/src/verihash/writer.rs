@@ -0,0 +1,286 @@
+//! `std::io::Write` adapter which computes the Verihash digest of the
+//! message bytes written through it.
+
+use super::{
+    schemaless::{self, Depth},
+    Output,
+};
+use crate::{
+    decoder::{self, message, Decodable, Event},
+    error::{self, Error},
+    field::{Tag, WireType},
+};
+use alloc::vec::Vec;
+use core::fmt::{self, Debug};
+use digest::Digest;
+use std::io;
+
+/// Writer which forwards bytes to an inner [`io::Write`] while decoding
+/// them as a serialized message and computing its Verihash digest.
+///
+/// Fields are hashed as bytes arrive, so large `bytes` and `string` fields
+/// are never buffered. Nested message and sequence values are buffered until
+/// complete, at which point their digests are computed structurally.
+///
+/// Only bytes accepted by the inner writer are hashed, so partial writes and
+/// [`io::ErrorKind::Interrupted`] errors (e.g. when retried by
+/// [`io::Write::write_all`]) don't affect the digest. Errors decoding or
+/// hashing the message don't interrupt writing: they're reported by
+/// [`Writer::finish`].
+pub struct Writer<D: Digest, W: io::Write> {
+    /// Inner writer
+    inner: W,
+
+    /// Decoder for the outermost message
+    decoder: message::Decoder<D>,
+
+    /// Hasher for the outermost message
+    hasher: decoder::Hasher<D>,
+
+    /// Tag of the field currently being decoded
+    tag: Option<Tag>,
+
+    /// Wire type of the elements of the sequence currently being decoded
+    element_type: Option<WireType>,
+
+    /// Body of the nested message or sequence currently being decoded
+    body: Vec<u8>,
+
+    /// First error encountered decoding or hashing the message (if any)
+    error: Option<Error>,
+}
+
+impl<D, W> Writer<D, W>
+where
+    D: Digest,
+    W: io::Write,
+{
+    /// Create a new [`Writer`] which forwards bytes to the given writer
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            decoder: message::Decoder::without_hasher(),
+            hasher: decoder::Hasher::new(),
+            tag: None,
+            element_type: None,
+            body: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Borrow the inner writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Finish computing the digest of the message, returning the inner
+    /// writer along with the digest.
+    ///
+    /// Returns an error if the bytes written weren't a complete, well-formed
+    /// message (e.g. if they ended in the middle of a field).
+    pub fn finish(self) -> Result<(W, Output<D>), Error> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        self.decoder.ensure_complete()?;
+        let digest = self.hasher.finish()?;
+        Ok((self.inner, digest))
+    }
+
+    /// Decode and hash the given bytes of the message
+    fn absorb(&mut self, mut input: &[u8]) -> Result<(), Error> {
+        loop {
+            let event = match self.decoder.decode(&mut input)? {
+                Some(event) => event,
+                // Booleans are emitted without consuming input, so continue
+                // decoding until no further events are produced
+                None if input.is_empty() => return Ok(()),
+                None => continue,
+            };
+
+            self.hasher.hash_event(&event)?;
+
+            match event {
+                Event::FieldHeader(header) => self.tag = Some(header.tag),
+                Event::LengthDelimiter {
+                    wire_type: WireType::Message,
+                    length: 0,
+                } => self.hash_body(WireType::Message)?,
+                Event::SequenceHeader { wire_type, length } => {
+                    self.element_type = Some(wire_type);
+
+                    if length == 0 {
+                        self.hash_body(WireType::Sequence)?;
+                    }
+                }
+                Event::ValueChunk {
+                    wire_type: wire_type @ WireType::Message,
+                    bytes,
+                    remaining,
+                }
+                | Event::ValueChunk {
+                    wire_type: wire_type @ WireType::Sequence,
+                    bytes,
+                    remaining,
+                } => {
+                    self.body.extend_from_slice(bytes);
+
+                    if remaining == 0 {
+                        self.hash_body(wire_type)?;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Hash the buffered body of a nested message or sequence
+    fn hash_body(&mut self, wire_type: WireType) -> Result<(), Error> {
+        let tag = self.tag.ok_or(error::HashingReason::UnexpectedEvent)?;
+        let chunk_size = self.body.len().max(1);
+        let depth = Depth::default();
+
+        if wire_type == WireType::Message {
+            let digest =
+                schemaless::nested_message_digest::<D>(&self.body, chunk_size, depth.nested()?)?;
+            self.hasher.hash_message_digest(tag, &digest)?;
+        } else {
+            let element_type = self
+                .element_type
+                .ok_or(error::HashingReason::UnexpectedEvent)?;
+            let digest =
+                schemaless::sequence_digest::<D>(element_type, &self.body, chunk_size, depth)?;
+            self.hasher.hash_sequence_digest(tag, &digest)?;
+        }
+
+        self.body.clear();
+        Ok(())
+    }
+}
+
+impl<D, W> io::Write for Writer<D, W>
+where
+    D: Digest,
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+
+        if self.error.is_none() {
+            if let Err(err) = self.absorb(&buf[..written]) {
+                self.error = Some(err);
+            }
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<D, W> Debug for Writer<D, W>
+where
+    D: Digest,
+    W: io::Write + Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("verihash::Writer")
+            .field("inner", &self.inner)
+            .field("error", &self.error)
+            .finish()
+    }
+}
+
+#[cfg(all(test, feature = "sha2"))]
+mod tests {
+    use super::Writer;
+    use crate::{error, field::Tag, verihash::verihash};
+    use sha2::Sha256;
+    use std::{io, io::Write, vec::Vec};
+
+    /// `{ 1: message({ 0: uint64(42) }), 2: sequence<uint64>([42, 43]), 3: string("baz") }`
+    const EXAMPLE_MESSAGE: &[u8] = &[45, 5, 5, 85, 79, 69, 85, 87, 107, 7, 98, 97, 122];
+
+    /// Writer which accepts at most one byte per call, and fails every
+    /// other call with `Interrupted`
+    #[derive(Default)]
+    struct TrickleWriter {
+        bytes: Vec<u8>,
+        interrupt: bool,
+    }
+
+    impl Write for TrickleWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.interrupt = !self.interrupt;
+
+            if self.interrupt {
+                return Err(io::ErrorKind::Interrupted.into());
+            }
+
+            self.bytes.extend_from_slice(&buf[..buf.len().min(1)]);
+            Ok(buf.len().min(1))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_all() {
+        let mut writer = Writer::<Sha256, _>::new(Vec::new());
+        writer.write_all(EXAMPLE_MESSAGE).unwrap();
+
+        let (inner, digest) = writer.finish().unwrap();
+        assert_eq!(inner, EXAMPLE_MESSAGE);
+        assert_eq!(digest, verihash::<Sha256>(EXAMPLE_MESSAGE).unwrap());
+    }
+
+    #[test]
+    fn partial_writes() {
+        let mut writer = Writer::<Sha256, _>::new(TrickleWriter::default());
+
+        for chunk in EXAMPLE_MESSAGE.chunks(3) {
+            writer.write_all(chunk).unwrap();
+        }
+
+        let (inner, digest) = writer.finish().unwrap();
+        assert_eq!(inner.bytes, EXAMPLE_MESSAGE);
+        assert_eq!(digest, verihash::<Sha256>(EXAMPLE_MESSAGE).unwrap());
+    }
+
+    #[test]
+    fn interrupted_final_field() {
+        for len in 1..EXAMPLE_MESSAGE.len() {
+            // Lengths which end on a field boundary are complete messages
+            if [4, 8].contains(&len) {
+                continue;
+            }
+
+            let mut writer = Writer::<Sha256, _>::new(Vec::new());
+            writer.write_all(&EXAMPLE_MESSAGE[..len]).unwrap();
+            assert!(writer.finish().is_err(), "length {} accepted", len);
+        }
+    }
+
+    #[test]
+    fn malformed_message() {
+        // Duplicate field: repeated tags are rejected by the decoder
+        let input = [37, 85, 37, 85];
+
+        let mut writer = Writer::<Sha256, _>::new(Vec::new());
+        writer.write_all(&input).unwrap();
+        assert_eq!(writer.get_ref().as_slice(), &input);
+
+        let err = writer.finish().err().unwrap();
+        assert_eq!(
+            err.kind(),
+            error::Kind::DuplicateTag {
+                tag: Tag::new_const(1)
+            }
+        );
+    }
+}
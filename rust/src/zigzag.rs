@@ -0,0 +1,52 @@
+//! Zigzag encoding for signed integers.
+//!
+//! Signed integers are zigzag encoded before being serialized as `vint64`,
+//! and Verihash absorbs the same zigzag-encoded `u64`, so the encoder, the
+//! decoder, and the hasher all share these helpers.
+//!
+//! Zigzag encoding interleaves negative and non-negative integers so values
+//! of small magnitude have small encodings: `0, -1, 1, -2, 2, ...` are
+//! encoded as `0, 1, 2, 3, 4, ...`.
+
+/// Zigzag encode a signed integer as an unsigned integer.
+///
+/// This is `(value << 1) ^ (value >> 63)` (using an arithmetic right shift),
+/// reinterpreted as a `u64`, i.e. `2 * value` for non-negative values and
+/// `-2 * value - 1` for negative ones.
+#[inline]
+pub fn encode(value: i64) -> u64 {
+    vint64::signed::zigzag::encode(value)
+}
+
+/// Decode a zigzag encoded unsigned integer as a signed integer.
+///
+/// This is `(encoded >> 1) ^ -(encoded & 1)`, reinterpreted as an `i64`,
+/// and is the inverse of [`encode`].
+#[inline]
+pub fn decode(encoded: u64) -> i64 {
+    vint64::signed::zigzag::decode(encoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode};
+
+    /// Known-answer tests which pin the mapping
+    const EXAMPLES: &[(i64, u64)] = &[
+        (0, 0),
+        (-1, 1),
+        (1, 2),
+        (-2, 3),
+        (2, 4),
+        (i64::MAX, u64::MAX - 1),
+        (i64::MIN, u64::MAX),
+    ];
+
+    #[test]
+    fn known_answers() {
+        for &(value, encoded) in EXAMPLES {
+            assert_eq!(encode(value), encoded, "encoding {}", value);
+            assert_eq!(decode(encoded), value, "decoding {}", encoded);
+        }
+    }
+}
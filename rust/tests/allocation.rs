@@ -0,0 +1,91 @@
+//! Tests that forged length delimiters don't cause large allocations
+
+#![cfg(all(feature = "sha2", feature = "std"))]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use veriform::{
+    decoder::{Limits, ReadError},
+    error::Kind,
+    field::{Tag, WireType},
+    framing::FrameDecoder,
+    Decoder,
+};
+
+/// Allocator which records the size of the largest allocation
+struct Recording;
+
+/// Size of the largest allocation so far
+static LARGEST: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Recording {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LARGEST.fetch_max(layout.size(), Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        LARGEST.fetch_max(new_size, Ordering::SeqCst);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Recording = Recording;
+
+/// Largest allocation any of the decoders below may make
+const MAX_ALLOCATION: usize = 0x10_0000;
+
+/// Length claimed by the forged length delimiters (4 GiB)
+const FORGED_LEN: u64 = 1 << 32;
+
+// Every check is in a single test, so the test harness running other tests
+// concurrently can't affect the largest allocation recorded
+#[test]
+fn forged_lengths() {
+    let prefix = vint64::encode(FORGED_LEN);
+
+    // `2: bytes`, claiming to be 4 GiB, followed by 5 bytes
+    let mut field = vec![73];
+    field.extend_from_slice(prefix.as_ref());
+    field.extend_from_slice(b"bytes");
+
+    let err = Decoder::new()
+        .with_limits(Limits::unlimited())
+        .decode_bytes_capped(Tag::new_const(2), &mut field.as_slice(), usize::MAX)
+        .err()
+        .unwrap();
+
+    assert_eq!(
+        err.kind(),
+        Kind::Truncated {
+            remaining: FORGED_LEN as usize - 5,
+            wire_type: WireType::Bytes
+        }
+    );
+
+    // A frame claiming to be 4 GiB, followed by 5 bytes
+    let mut frame = prefix.as_ref().to_vec();
+    frame.extend_from_slice(b"bytes");
+
+    let mut decoder = FrameDecoder::new(frame.as_slice()).with_max_frame_len(usize::MAX);
+
+    match decoder.read_frame() {
+        Err(ReadError::Decode(err)) => assert_eq!(
+            err.kind(),
+            Kind::Truncated {
+                remaining: FORGED_LEN as usize - 5,
+                wire_type: WireType::Message
+            }
+        ),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert!(LARGEST.load(Ordering::SeqCst) < MAX_ALLOCATION);
+}
@@ -0,0 +1,91 @@
+//! Fuzz-style tests using structure-aware inputs generated from
+//! pseudorandom bytes with the `arbitrary` crate
+
+#![cfg(all(feature = "arbitrary", feature = "sha2", feature = "std"))]
+
+use arbitrary::{Arbitrary, Unstructured};
+use veriform::{
+    arbitrary::ArbitraryMessage,
+    decoder::{Event, Hasher},
+    field::Header,
+    verihash, Decoder, Message,
+};
+
+/// Number of inputs to generate for each test
+const ITERATIONS: usize = 1000;
+
+/// Run the given test with pseudorandom inputs of varying sizes, which are
+/// the same on every run
+fn fuzz(mut test: impl FnMut(Unstructured<'_>)) {
+    // xorshift64*
+    let mut state = 0x5EED_u64;
+    let mut input = Vec::new();
+
+    for i in 0..ITERATIONS {
+        input.clear();
+
+        for _ in 0..(i % 64) * 32 {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            input.push((state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8);
+        }
+
+        test(Unstructured::new(&input));
+    }
+}
+
+#[test]
+fn message_round_trip() {
+    let mut nonempty = 0;
+
+    fuzz(|mut u| {
+        let message = ArbitraryMessage::arbitrary(&mut u).unwrap();
+        let encoded = message.encode_vec().unwrap();
+        assert_eq!(encoded.len(), message.encoded_len());
+
+        // Encoding into a larger buffer produces the same bytes
+        let mut buffer = vec![0xFF; encoded.len() + 16];
+        assert_eq!(message.encode(&mut buffer).unwrap(), encoded.as_slice());
+
+        let mut decoder = Decoder::new();
+        let decoded = ArbitraryMessage::decode(&mut decoder, &encoded).unwrap();
+        assert_eq!(decoded, message);
+
+        // The decoder computes the same digest as schemaless hashing
+        let mut digest = [0u8; 32];
+        decoder.fill_digest(&mut digest).unwrap();
+        let expected = verihash::<sha2::Sha256>(&encoded).unwrap();
+        assert_eq!(digest, expected.as_bytes());
+
+        if !message.fields.is_empty() {
+            nonempty += 1;
+        }
+    });
+
+    assert!(nonempty > ITERATIONS / 4);
+}
+
+#[test]
+fn header_round_trip() {
+    fuzz(|mut u| {
+        let header = Header::arbitrary(&mut u).unwrap();
+        assert_eq!(Header::from(u64::from(header)), header);
+        assert_eq!(header.encode().as_ref().len(), header.encoded_len());
+    });
+}
+
+#[test]
+fn hash_arbitrary_events() {
+    fuzz(|mut u| {
+        let mut hasher = Hasher::<sha2::Sha256>::new();
+
+        // Arbitrary events are almost never a valid transcript, but hashing
+        // them must fail cleanly rather than panicking
+        while let Ok(event) = Event::arbitrary(&mut u) {
+            if u.is_empty() || hasher.hash_event(&event).is_err() {
+                break;
+            }
+        }
+    });
+}
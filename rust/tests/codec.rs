@@ -0,0 +1,183 @@
+//! `tokio_util::codec` integration tests
+
+#![cfg(all(feature = "tokio-codec", feature = "sha2"))]
+
+use bytes::BytesMut;
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use digest::Digest;
+use futures_core::Stream;
+use futures_sink::Sink;
+use tokio_util::codec::{Decoder as _, Encoder as _, FramedRead, FramedWrite};
+use veriform::{
+    decoder::Decoder,
+    error::{Error, Kind},
+    field::Tag,
+    framing::CodecError,
+    value::{Decodable, Encodable},
+    Encoder, Message, VeriformCodec,
+};
+
+/// `{ 0: uint64(id), 1: bytes(payload) }`
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Payload {
+    id: u64,
+    payload: Vec<u8>,
+}
+
+impl Payload {
+    /// Create a payload whose size varies with its ID
+    fn new(id: u64) -> Self {
+        let len = (id as usize * 37) % 5000;
+        Self {
+            id,
+            payload: (0..len).map(|i| i as u8).collect(),
+        }
+    }
+}
+
+impl Message for Payload {
+    fn decode<D>(decoder: &mut Decoder<D>, mut input: &[u8]) -> Result<Self, Error>
+    where
+        D: Digest,
+    {
+        let id = u64::decode_field(decoder, Tag::new_const(0), &mut input)?;
+        let payload = Vec::<u8>::decode_field(decoder, Tag::new_const(1), &mut input)?;
+        Ok(Self { id, payload })
+    }
+
+    fn encode<'a>(&self, buffer: &'a mut [u8]) -> Result<&'a [u8], Error> {
+        let mut encoder = Encoder::new(buffer);
+        self.id
+            .encode_field(&mut encoder, Tag::new_const(0), false)?;
+        self.payload
+            .encode_field(&mut encoder, Tag::new_const(1), false)?;
+        Ok(encoder.finish())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.id.encoded_field_len(Tag::new_const(0))
+            + self.payload.encoded_field_len(Tag::new_const(1))
+    }
+}
+
+/// Future which polls the given closure until it is ready
+struct PollFn<F>(F);
+
+impl<F, T> Future for PollFn<F>
+where
+    F: FnMut(&mut Context<'_>) -> Poll<T> + Unpin,
+{
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        (self.0)(cx)
+    }
+}
+
+/// Send an item to the given sink and flush it
+async fn send<S, T>(sink: &mut S, item: T) -> Result<(), S::Error>
+where
+    S: Sink<T> + Unpin,
+{
+    PollFn(|cx: &mut Context<'_>| Pin::new(&mut *sink).poll_ready(cx)).await?;
+    Pin::new(&mut *sink).start_send(item)?;
+    PollFn(|cx: &mut Context<'_>| Pin::new(&mut *sink).poll_flush(cx)).await
+}
+
+/// Receive the next item from the given stream
+async fn next<S>(stream: &mut S) -> Option<S::Item>
+where
+    S: Stream + Unpin,
+{
+    PollFn(|cx: &mut Context<'_>| Pin::new(&mut *stream).poll_next(cx)).await
+}
+
+#[tokio::test]
+async fn loopback() {
+    let (client, server) = tokio::io::duplex(4096);
+
+    let writer = tokio::spawn(async move {
+        let mut sink = FramedWrite::new(client, VeriformCodec::<Payload>::new());
+
+        for id in 0..1000 {
+            send(&mut sink, Payload::new(id)).await.unwrap();
+        }
+    });
+
+    let mut stream = FramedRead::new(server, VeriformCodec::<Payload>::new());
+
+    for id in 0..1000 {
+        assert_eq!(next(&mut stream).await.unwrap().unwrap(), Payload::new(id));
+    }
+
+    assert!(next(&mut stream).await.is_none());
+    writer.await.unwrap();
+}
+
+#[test]
+fn oversized_frame_is_skipped() {
+    let mut codec = VeriformCodec::<Payload>::new();
+    let mut buffer = BytesMut::new();
+
+    for id in &[1, 100, 2] {
+        codec.encode(Payload::new(*id), &mut buffer).unwrap();
+    }
+
+    let mut codec = VeriformCodec::<Payload>::new().with_max_frame_len(1000);
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(Payload::new(1)));
+
+    match codec.decode(&mut buffer) {
+        Err(CodecError::Frame(err)) => assert!(matches!(err.kind(), Kind::FrameTooLong { .. })),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // The rest of the oversized frame is discarded, resynchronizing with the
+    // frame which follows it
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(Payload::new(2)));
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn invalid_message_is_skipped() {
+    let mut buffer = BytesMut::new();
+
+    // `{ 0: sint64(1) }` doesn't decode as a `Payload`
+    buffer.extend_from_slice(&[5, 7, 3]);
+    VeriformCodec::<Payload>::new()
+        .encode(Payload::new(1), &mut buffer)
+        .unwrap();
+
+    let mut codec = VeriformCodec::<Payload>::new();
+
+    match codec.decode(&mut buffer) {
+        Err(CodecError::Message(err)) => {
+            assert!(matches!(err.kind(), Kind::WireTypeMismatch { .. }))
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(Payload::new(1)));
+}
+
+#[test]
+fn truncated_final_frame() {
+    let mut buffer = BytesMut::new();
+    let mut codec = VeriformCodec::<Payload>::new();
+    codec.encode(Payload::new(10), &mut buffer).unwrap();
+    buffer.truncate(buffer.len() - 3);
+
+    match codec.decode_eof(&mut buffer) {
+        Err(CodecError::Frame(err)) => assert_eq!(
+            err.kind(),
+            Kind::Truncated {
+                remaining: 3,
+                wire_type: veriform::field::WireType::Message
+            }
+        ),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
@@ -1,10 +1,10 @@
 //! Integration tests for `veriform_derive`
 
 use heapless::{
-    consts::{U1024, U8},
+    consts::{U1024, U4, U8},
     Vec,
 };
-use veriform::{Decoder, Message};
+use veriform::{field::Tag, Decoder, Message, MessageRef, RawMessage};
 
 /// Buffer type.
 ///
@@ -45,10 +45,10 @@ fn enum_round_trip() {
 
     let mut encoded_buf = new_buffer();
     let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
-    encoded_buf.truncate(encoded_len);
+    let encoded_buf = &encoded_buf[..encoded_len];
 
     let mut decoder = Decoder::new();
-    let decoded = ExampleEnum::decode(&mut decoder, &encoded_buf).unwrap();
+    let decoded = ExampleEnum::decode(&mut decoder, encoded_buf).unwrap();
 
     assert_eq!(example, decoded);
 }
@@ -91,16 +91,1825 @@ fn struct_round_trip() {
 
     let mut encoded_buf = new_buffer();
     let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
-    encoded_buf.truncate(encoded_len);
+    let encoded_buf = &encoded_buf[..encoded_len];
 
     let mut decoder = Decoder::new();
-    let decoded = ExampleStruct::decode(&mut decoder, &encoded_buf).unwrap();
+    let decoded = ExampleStruct::decode(&mut decoder, encoded_buf).unwrap();
 
     // Expected digest
     example.digest = Some([
-        184, 86, 174, 227, 32, 40, 78, 107, 138, 228, 168, 208, 186, 189, 128, 164, 210, 15, 126,
-        220, 90, 4, 255, 19, 230, 230, 211, 218, 188, 104, 230, 87,
+        140, 140, 163, 99, 19, 8, 179, 168, 0, 115, 77, 143, 156, 230, 194, 69, 57, 48, 42, 199,
+        208, 3, 20, 101, 40, 114, 62, 162, 219, 72, 222, 157,
     ]);
 
     assert_eq!(example, decoded);
 }
+
+#[cfg(all(feature = "alloc", feature = "sha2"))]
+#[test]
+fn struct_verihash() {
+    let example = ExampleStruct::default();
+
+    let mut encoded_buf = new_buffer();
+    let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
+
+    // Hashing the message directly matches the decoder's transcript hash
+    assert_eq!(
+        example.verihash::<sha2::Sha256>().unwrap(),
+        veriform::verihash::<sha2::Sha256>(&encoded_buf[..encoded_len]).unwrap()
+    );
+}
+
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct BorrowedStruct<'a> {
+    #[field(tag = 0, wire_type = "bytes", critical = true)]
+    pub bytes_field: &'a [u8],
+
+    #[field(tag = 1, wire_type = "string", critical = true)]
+    pub string_field: &'a str,
+
+    #[field(tag = 2, wire_type = "message", critical = true)]
+    pub message_field: ExampleEnum,
+}
+
+#[test]
+fn borrowed_struct_round_trip() {
+    let example = BorrowedStruct {
+        bytes_field: b"bytes",
+        string_field: "string",
+        message_field: ExampleEnum::default(),
+    };
+
+    let mut encoded_buf = new_buffer();
+    let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
+    assert_eq!(encoded_len, example.encoded_len());
+    let encoded_buf = &encoded_buf[..encoded_len];
+
+    let mut decoder = Decoder::new();
+    let decoded = BorrowedStruct::decode(&mut decoder, encoded_buf).unwrap();
+    assert_eq!(example, decoded);
+
+    // Decoded fields are slices of the input
+    let start = encoded_buf.as_ptr() as usize;
+    let input = start..(start + encoded_buf.len());
+    assert!(input.contains(&(decoded.bytes_field.as_ptr() as usize)));
+    assert!(input.contains(&(decoded.string_field.as_ptr() as usize)));
+}
+
+/// Struct containing a nested [`BorrowedStruct`], which borrows from the
+/// same input
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct NestedBorrowedStruct<'a> {
+    #[field(tag = 0, wire_type = "string", critical = true)]
+    pub name: &'a str,
+
+    #[field(tag = 1, wire_type = "message", critical = true)]
+    pub inner: BorrowedStruct<'a>,
+
+    #[field(tag = 2, wire_type = "message")]
+    pub extra: Option<BorrowedStruct<'a>>,
+}
+
+#[test]
+fn nested_borrowed_struct_round_trip() {
+    let example = NestedBorrowedStruct {
+        name: "outer",
+        inner: BorrowedStruct {
+            bytes_field: b"bytes",
+            string_field: "string",
+            message_field: ExampleEnum::default(),
+        },
+        extra: None,
+    };
+
+    let mut encoded_buf = new_buffer();
+    let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
+    assert_eq!(encoded_len, example.encoded_len());
+    let encoded_buf = &encoded_buf[..encoded_len];
+
+    let mut decoder = Decoder::new();
+    let decoded = NestedBorrowedStruct::decode(&mut decoder, encoded_buf).unwrap();
+    assert_eq!(example, decoded);
+
+    // Fields of the nested struct are slices of the outer input
+    let start = encoded_buf.as_ptr() as usize;
+    let input = start..(start + encoded_buf.len());
+    assert!(input.contains(&(decoded.inner.string_field.as_ptr() as usize)));
+}
+
+/// Envelope whose payload is decoded later (or not at all)
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct Envelope<'a> {
+    #[field(tag = 0, wire_type = "uint64", critical = true)]
+    pub route: u64,
+
+    #[field(tag = 1, wire_type = "message", critical = true)]
+    pub payload: RawMessage<'a>,
+}
+
+/// [`Envelope`] with its payload decoded eagerly
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct TypedEnvelope {
+    #[field(tag = 0, wire_type = "uint64", critical = true)]
+    pub route: u64,
+
+    #[field(tag = 1, wire_type = "message", critical = true)]
+    pub payload: ExampleEnum,
+}
+
+#[test]
+fn raw_message_round_trip() {
+    let example = TypedEnvelope {
+        route: 42,
+        payload: ExampleEnum::default(),
+    };
+
+    let mut encoded_buf = new_buffer();
+    let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
+    let encoded_buf = &encoded_buf[..encoded_len];
+
+    let mut decoder = Decoder::new();
+    let envelope = Envelope::decode(&mut decoder, encoded_buf).unwrap();
+    assert_eq!(envelope.route, 42);
+
+    // The envelope's digest matches the eagerly decoded message's
+    let mut raw_digest = [0u8; 32];
+    decoder.fill_digest(&mut raw_digest).unwrap();
+
+    let mut decoder = Decoder::new();
+    TypedEnvelope::decode(&mut decoder, encoded_buf).unwrap();
+    let mut typed_digest = [0u8; 32];
+    decoder.fill_digest(&mut typed_digest).unwrap();
+    assert_eq!(raw_digest, typed_digest);
+
+    let payload: ExampleEnum = envelope.payload.decode(&mut Decoder::new()).unwrap();
+    assert_eq!(payload, example.payload);
+
+    // The payload is forwarded unmodified
+    let mut reencoded_buf = new_buffer();
+    assert_eq!(envelope.encoded_len(), encoded_len);
+    assert_eq!(envelope.encode(&mut reencoded_buf).unwrap(), encoded_buf);
+}
+
+/// Struct which copies `bytes` and `string` fields into fixed-capacity
+/// buffers
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct OwnedStruct {
+    #[field(tag = 0, wire_type = "bytes")]
+    pub bytes_field: heapless::Vec<u8, U8>,
+
+    #[field(tag = 1, wire_type = "string")]
+    pub string_field: heapless::String<U8>,
+}
+
+#[test]
+fn owned_struct_round_trip() {
+    let mut example = OwnedStruct::default();
+    example.bytes_field.extend_from_slice(b"bytes").unwrap();
+    example.string_field.push_str("string").unwrap();
+
+    let mut encoded_buf = new_buffer();
+    let encoded = example.encode(&mut encoded_buf).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded = OwnedStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+/// Version of [`OwnedStruct`] with smaller buffers
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct SmallOwnedStruct {
+    #[field(tag = 0, wire_type = "bytes")]
+    pub bytes_field: heapless::Vec<u8, U4>,
+
+    #[field(tag = 1, wire_type = "string")]
+    pub string_field: heapless::String<U4>,
+}
+
+#[test]
+fn owned_struct_too_small() {
+    let mut example = OwnedStruct::default();
+    example.bytes_field.extend_from_slice(b"bytes").unwrap();
+
+    let mut encoded_buf = new_buffer();
+    let encoded = example.encode(&mut encoded_buf).unwrap();
+
+    let err = SmallOwnedStruct::decode(&mut Decoder::new(), encoded)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::BufferTooSmall { needed: 5 }
+    );
+    assert_eq!(err.tag(), Some(Tag::new_const(0)));
+}
+
+#[test]
+fn encode_buffer_too_small() {
+    let mut example = OwnedStruct::default();
+    example.bytes_field.extend_from_slice(b"bytes").unwrap();
+    example.string_field.push_str("string").unwrap();
+
+    let encoded_len = example.encoded_len();
+    let mut encoded_buf = new_buffer();
+
+    for len in 0..encoded_len {
+        let err = example.encode(&mut encoded_buf[..len]).err().unwrap();
+        assert_eq!(
+            err.kind(),
+            veriform::error::Kind::BufferTooSmall {
+                needed: encoded_len
+            }
+        );
+    }
+}
+
+/// Message containing an [`OwnedStruct`]
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct OwnedEnvelope {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub route: u64,
+
+    #[field(tag = 1, wire_type = "message")]
+    pub payload: OwnedStruct,
+}
+
+#[test]
+fn encode_error_context() {
+    let mut example = OwnedEnvelope::default();
+    example.payload.string_field.push_str("ståte").unwrap();
+
+    let mut encoded_buf = new_buffer();
+
+    // Errors name the field being encoded
+    let err = example.encode(&mut encoded_buf[..3]).err().unwrap();
+    assert_eq!(err.tag(), Some(Tag::new_const(1)));
+    assert_eq!(err.field_name(), Some("payload"));
+
+    // ...or the innermost field, along with the path to it
+    let err = example.encode(&mut encoded_buf).err().unwrap();
+    assert_eq!(err.kind(), veriform::error::Kind::UnicodeNormalization);
+    assert_eq!(err.tag(), Some(Tag::new_const(1)));
+    assert_eq!(err.field_name(), Some("string_field"));
+    assert!(err.path().iter().eq(core::iter::once(Tag::new_const(1))));
+    assert_eq!(err.position(), Some(6));
+}
+
+#[test]
+fn encode_to_array() {
+    let mut example = OwnedStruct::default();
+    example.bytes_field.extend_from_slice(b"bytes").unwrap();
+    example.string_field.push_str("string").unwrap();
+
+    let mut encoded_buf = new_buffer();
+    let encoded = example.encode(&mut encoded_buf).unwrap();
+    assert_eq!(encoded.len(), 15);
+
+    // Exactly filling the array
+    let (array, len) = example.encode_to_array::<15>().unwrap();
+    assert_eq!(len, 15);
+    assert_eq!(&array[..], encoded);
+
+    // One byte short
+    let err = example.encode_to_array::<14>().err().unwrap();
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::BufferTooSmall { needed: 15 }
+    );
+}
+
+#[test]
+fn wire_type_mismatch() {
+    let example = OwnedStruct::default();
+    let mut encoded_buf = new_buffer();
+    let encoded = example.encode(&mut encoded_buf).unwrap();
+
+    // Field 0 is `bytes` rather than the `uint64` `OptionalStruct` expects
+    let err = OptionalStruct::decode(&mut Decoder::new(), encoded)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::WireTypeMismatch {
+            tag: Tag::new_const(0),
+            expected: veriform::field::WireType::UInt64,
+            actual: veriform::field::WireType::Bytes,
+        }
+    );
+}
+
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct OptionalStruct {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub required_field: u64,
+
+    #[field(tag = 1, wire_type = "sint64")]
+    pub optional_field: Option<i64>,
+
+    #[field(tag = 2, wire_type = "message")]
+    pub optional_message: Option<EmptyStruct>,
+
+    #[field(tag = 3, wire_type = "uint64")]
+    pub other_required_field: u64,
+}
+
+#[test]
+fn optional_fields() {
+    for &optional_field in &[None, Some(-42)] {
+        let example = OptionalStruct {
+            optional_field,
+            ..Default::default()
+        };
+
+        let mut encoded_buf = new_buffer();
+        let encoded = example.encode(&mut encoded_buf).unwrap();
+        assert_eq!(encoded.len(), example.encoded_len());
+
+        let decoded = OptionalStruct::decode(&mut Decoder::new(), encoded).unwrap();
+        assert_eq!(example, decoded);
+    }
+}
+
+#[test]
+fn missing_required_fields() {
+    // `{ 1: sint64(-42) }`
+    let err = OptionalStruct::decode(&mut Decoder::new(), &[39, 167])
+        .err()
+        .unwrap();
+
+    let missing = match err.kind() {
+        veriform::error::Kind::MissingFields { fields } => fields,
+        other => panic!("unexpected error: {:?}", other),
+    };
+
+    let names = missing
+        .iter()
+        .map(|field| (field.tag.to_u64(), field.name.unwrap()))
+        .collect::<Vec<_, U8>>();
+    assert_eq!(names, [(0, "required_field"), (3, "other_required_field")]);
+}
+
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct SkipDefaultStruct {
+    #[field(tag = 0, wire_type = "uint64", skip_default)]
+    pub uint64_field: u32,
+
+    #[field(tag = 1, wire_type = "sint64")]
+    pub sint64_field: i64,
+
+    #[field(tag = 2, wire_type = "string", skip_default)]
+    pub string_field: heapless::String<U8>,
+
+    #[field(tag = 3, wire_type = "bytes", skip_default)]
+    pub bytes_field: Vec<u8, U8>,
+}
+
+#[test]
+fn skip_default_fields() {
+    for &uint64_field in &[0, 42] {
+        for &string_field in &["", "string"] {
+            for &bytes_field in &[&b""[..], b"bytes"] {
+                let example = SkipDefaultStruct {
+                    uint64_field,
+                    sint64_field: 0,
+                    string_field: string_field.into(),
+                    bytes_field: Vec::from_slice(bytes_field).unwrap(),
+                };
+
+                let mut encoded_buf = new_buffer();
+                let encoded = example.encode(&mut encoded_buf).unwrap();
+                assert_eq!(encoded.len(), example.encoded_len());
+
+                // Only the fields with non-default values are encoded
+                let mut expected_len = veriform::field::length::sint64(Tag::new_const(1), 0);
+
+                if uint64_field != 0 {
+                    expected_len +=
+                        veriform::field::length::uint64(Tag::new_const(0), uint64_field.into());
+                }
+
+                if !string_field.is_empty() {
+                    expected_len +=
+                        veriform::field::length::string(Tag::new_const(2), string_field);
+                }
+
+                if !bytes_field.is_empty() {
+                    expected_len += veriform::field::length::bytes(Tag::new_const(3), bytes_field);
+                }
+
+                assert_eq!(encoded.len(), expected_len);
+
+                let decoded = SkipDefaultStruct::decode(&mut Decoder::new(), encoded).unwrap();
+                assert_eq!(example, decoded);
+            }
+        }
+    }
+}
+
+#[test]
+fn skip_default_non_canonical() {
+    // `{ 0: uint64(0), 1: sint64(0) }`: the default value of field 0 must be
+    // omitted
+    let mut encoded_buf = new_buffer();
+    let mut encoder = veriform::Encoder::new(&mut encoded_buf);
+    encoder.uint64(Tag::new_const(0), false, 0).unwrap();
+    encoder.sint64(Tag::new_const(1), false, 0).unwrap();
+    let encoded = encoder.finish();
+
+    let err = SkipDefaultStruct::decode(&mut Decoder::new(), encoded)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::DefaultValue {
+            tag: Tag::new_const(0)
+        }
+    );
+}
+
+/// Default value of [`DefaultStruct::timeout`]
+const DEFAULT_TIMEOUT: u32 = 30;
+
+/// Struct whose fields may be missing, in which case they're decoded as
+/// their default values
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct DefaultStruct {
+    #[field(tag = 0, wire_type = "uint64", default)]
+    pub retries: u64,
+
+    #[field(tag = 1, wire_type = "sint64", default = "-1")]
+    pub offset: i64,
+
+    #[field(
+        tag = 2,
+        wire_type = "uint64",
+        skip_default,
+        default = "DEFAULT_TIMEOUT"
+    )]
+    pub timeout: u32,
+
+    #[field(tag = 3, wire_type = "string", default = "\"guest\".into()")]
+    pub name: heapless::String<U8>,
+}
+
+impl DefaultStruct {
+    /// Message with every field set to its default value
+    fn defaults() -> Self {
+        Self {
+            retries: 0,
+            offset: -1,
+            timeout: DEFAULT_TIMEOUT,
+            name: "guest".into(),
+        }
+    }
+}
+
+#[test]
+fn default_fields_present() {
+    let example = DefaultStruct {
+        retries: 3,
+        offset: 42,
+        timeout: 5,
+        name: "veriform".into(),
+    };
+
+    let mut encoded_buf = new_buffer();
+    let encoded = example.encode(&mut encoded_buf).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded = DefaultStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+#[test]
+fn default_fields_absent() {
+    // An empty message isn't missing any required fields
+    let decoded = DefaultStruct::decode(&mut Decoder::new(), &[]).unwrap();
+    assert_eq!(decoded, DefaultStruct::defaults());
+
+    // Only `skip_default` fields are omitted when re-encoded
+    let mut encoded_buf = new_buffer();
+    let encoded = decoded.encode(&mut encoded_buf).unwrap();
+    assert_eq!(encoded.len(), decoded.encoded_len());
+
+    let mut expected_buf = new_buffer();
+    let mut encoder = veriform::Encoder::new(&mut expected_buf);
+    encoder.uint64(Tag::new_const(0), false, 0).unwrap();
+    encoder.sint64(Tag::new_const(1), false, -1).unwrap();
+    encoder.string(Tag::new_const(3), false, "guest").unwrap();
+    assert_eq!(encoded, encoder.finish());
+
+    let decoded = DefaultStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(decoded, DefaultStruct::defaults());
+}
+
+#[test]
+fn default_fields_explicit() {
+    // `default` fields may have their default value encoded explicitly...
+    let mut encoded_buf = new_buffer();
+    let mut encoder = veriform::Encoder::new(&mut encoded_buf);
+    encoder.sint64(Tag::new_const(1), false, -1).unwrap();
+    let encoded = encoder.finish();
+
+    let decoded = DefaultStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(decoded, DefaultStruct::defaults());
+
+    // ...but `skip_default` ones can't, even when their default is an
+    // expression
+    let mut encoded_buf = new_buffer();
+    let mut encoder = veriform::Encoder::new(&mut encoded_buf);
+    encoder
+        .uint64(Tag::new_const(2), false, DEFAULT_TIMEOUT.into())
+        .unwrap();
+    let encoded = encoder.finish();
+
+    let err = DefaultStruct::decode(&mut Decoder::new(), encoded)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::DefaultValue {
+            tag: Tag::new_const(2)
+        }
+    );
+}
+
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct AllOptionalStruct {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub optional_field: Option<u64>,
+
+    #[field(tag = 1, wire_type = "message")]
+    pub optional_message: Option<EmptyStruct>,
+}
+
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct UnitStruct;
+
+#[test]
+fn empty_message_round_trip() {
+    let mut encoded_buf = new_buffer();
+    assert!(EmptyStruct {}.encode(&mut encoded_buf).unwrap().is_empty());
+    assert_eq!(EmptyStruct {}.encoded_len(), 0);
+    assert_eq!(
+        EmptyStruct::decode(&mut Decoder::new(), &[]).unwrap(),
+        EmptyStruct {}
+    );
+
+    assert!(UnitStruct.encode(&mut encoded_buf).unwrap().is_empty());
+    assert_eq!(
+        UnitStruct::decode(&mut Decoder::new(), &[]).unwrap(),
+        UnitStruct
+    );
+
+    // Every field of a message without required fields may be absent
+    let example = AllOptionalStruct::default();
+    assert!(example.encode(&mut encoded_buf).unwrap().is_empty());
+    assert_eq!(
+        AllOptionalStruct::decode(&mut Decoder::new(), &[]).unwrap(),
+        example
+    );
+}
+
+#[test]
+fn empty_message_missing_required_fields() {
+    let err = OptionalStruct::decode(&mut Decoder::new(), &[])
+        .err()
+        .unwrap();
+
+    let missing = match err.kind() {
+        veriform::error::Kind::MissingFields { fields } => fields,
+        other => panic!("unexpected error: {:?}", other),
+    };
+
+    let tags = missing
+        .iter()
+        .map(|field| field.tag.to_u64())
+        .collect::<Vec<_, U8>>();
+    assert_eq!(tags, [0, 3]);
+}
+
+#[test]
+fn nested_empty_message() {
+    let example = AllOptionalStruct {
+        optional_field: None,
+        optional_message: Some(EmptyStruct {}),
+    };
+
+    // `{ 1: message({}) }`
+    let mut encoded_buf = new_buffer();
+    let encoded = example.encode(&mut encoded_buf).unwrap();
+    assert_eq!(encoded, &[45, 1]);
+
+    let decoded = AllOptionalStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+
+    let decoded = ExampleEnum::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(decoded, ExampleEnum::MessageVariant(EmptyStruct {}));
+}
+
+#[cfg(all(feature = "alloc", feature = "sha2"))]
+#[test]
+fn empty_message_verihash() {
+    // `SHA-256(0x12 || 0x03 || 0x13)`: the message transcript's framing,
+    // and nothing else
+    let expected = [
+        24, 79, 211, 29, 179, 214, 2, 166, 38, 114, 174, 152, 205, 197, 170, 117, 253, 173, 168,
+        33, 189, 15, 88, 157, 147, 75, 186, 28, 67, 159, 1, 164,
+    ];
+
+    assert_eq!(
+        EmptyStruct {}
+            .verihash::<sha2::Sha256>()
+            .unwrap()
+            .as_bytes(),
+        expected
+    );
+    assert_eq!(
+        UnitStruct.verihash::<sha2::Sha256>().unwrap().as_bytes(),
+        expected
+    );
+    assert_eq!(
+        veriform::verihash::<sha2::Sha256>(&[]).unwrap().as_bytes(),
+        expected
+    );
+}
+
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct NarrowStruct {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub u32_field: u32,
+
+    #[field(tag = 1, wire_type = "sint64")]
+    pub i32_field: i32,
+
+    #[field(tag = 2, wire_type = "uint64")]
+    pub u16_field: u16,
+
+    #[field(tag = 3, wire_type = "uint64")]
+    pub u8_field: u8,
+
+    #[field(tag = 4, wire_type = "sint64")]
+    pub optional_field: Option<i32>,
+}
+
+#[test]
+fn narrow_struct_round_trip() {
+    let example = NarrowStruct {
+        u32_field: u32::MAX,
+        i32_field: i32::MIN,
+        u16_field: u16::MAX,
+        u8_field: u8::MAX,
+        optional_field: Some(i32::MAX),
+    };
+
+    let mut encoded_buf = new_buffer();
+    let encoded = example.encode(&mut encoded_buf).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded = NarrowStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+#[test]
+fn narrow_struct_out_of_range() {
+    let mut buffer = [0u8; 64];
+    let mut encoder = veriform::Encoder::new(&mut buffer);
+    encoder.uint64(Tag::new_const(0), false, 0).unwrap();
+    encoder.sint64(Tag::new_const(1), false, 0).unwrap();
+    encoder
+        .uint64(Tag::new_const(2), false, u64::from(u16::MAX) + 1)
+        .unwrap();
+    encoder.uint64(Tag::new_const(3), false, 0).unwrap();
+    let encoded = encoder.finish();
+
+    let err = NarrowStruct::decode(&mut Decoder::new(), encoded)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::OutOfRange {
+            tag: Tag::new_const(2),
+            value: 65536,
+            max: 65535,
+        }
+    );
+    assert_eq!(err.tag(), Some(Tag::new_const(2)));
+}
+
+/// Newer version of [`ExampleStructV1`] with additional fields
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct ExampleStructV2 {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub uint64_field: u64,
+
+    #[field(tag = 1, wire_type = "message")]
+    pub message_field: ExampleEnum,
+
+    #[field(tag = 2, wire_type = "sint64")]
+    pub sint64_field: i64,
+
+    #[field(tag = 3, wire_type = "sequence", max = 8)]
+    pub msg_sequence_field: heapless::Vec<ExampleEnum, U8>,
+
+    #[digest(alg = "sha256")]
+    pub digest: Option<veriform::Sha256Digest>,
+}
+
+/// Older version of [`ExampleStructV2`] which predates fields 1 and 3
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct ExampleStructV1 {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub uint64_field: u64,
+
+    #[field(tag = 2, wire_type = "sint64")]
+    pub sint64_field: i64,
+
+    #[digest(alg = "sha256")]
+    pub digest: Option<veriform::Sha256Digest>,
+}
+
+#[test]
+fn skip_unknown_fields() {
+    let mut example = ExampleStructV2 {
+        uint64_field: 42,
+        message_field: ExampleEnum::default(),
+        sint64_field: -42,
+        msg_sequence_field: ExampleStruct::default().msg_sequence_field,
+        digest: None,
+    };
+
+    let mut encoded_buf = new_buffer();
+    let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
+    let encoded_buf = &encoded_buf[..encoded_len];
+
+    let v2 = ExampleStructV2::decode(&mut Decoder::new(), encoded_buf).unwrap();
+    let v1 = ExampleStructV1::decode(&mut Decoder::new(), encoded_buf).unwrap();
+
+    assert_eq!(v1.uint64_field, 42);
+    assert_eq!(v1.sint64_field, -42);
+
+    // Skipped fields are still included in the digest
+    assert_eq!(v1.digest, v2.digest);
+    example.digest = v2.digest;
+    assert_eq!(example, v2);
+}
+
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct CriticalStructV2 {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub uint64_field: u64,
+
+    #[field(tag = 1, wire_type = "uint64", critical = true)]
+    pub critical_field: u64,
+}
+
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct CriticalStructV1 {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub uint64_field: u64,
+}
+
+#[test]
+fn reject_unknown_critical_fields() {
+    let example = CriticalStructV2 {
+        uint64_field: 42,
+        critical_field: 43,
+    };
+
+    let mut encoded_buf = new_buffer();
+    let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
+    let encoded_buf = &encoded_buf[..encoded_len];
+
+    let err = CriticalStructV1::decode(&mut Decoder::new(), encoded_buf)
+        .err()
+        .unwrap();
+
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::UnknownCritical {
+            tag: Tag::new_const(1)
+        }
+    );
+}
+
+/// Message whose field has the largest tag a header can encode
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct MaxTagStruct {
+    #[field(tag = 1152921504606846975, wire_type = "uint64")]
+    pub uint64_field: u64,
+}
+
+#[test]
+fn max_tag_round_trip() {
+    let example = MaxTagStruct { uint64_field: 42 };
+
+    let mut encoded_buf = new_buffer();
+    let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
+    let encoded_buf = &encoded_buf[..encoded_len];
+
+    let header = veriform::decoder::raw::decode_header(encoded_buf)
+        .unwrap()
+        .0;
+    assert_eq!(header.tag, Tag::MAX);
+
+    let decoded = MaxTagStruct::decode(&mut Decoder::new(), encoded_buf).unwrap();
+    assert_eq!(example, decoded);
+}
+
+/// Version of [`ExampleStructV1`] which preserves unknown fields
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct PreservingStructV1 {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub uint64_field: u64,
+
+    #[field(tag = 2, wire_type = "sint64")]
+    pub sint64_field: i64,
+
+    #[field(unknown)]
+    pub unknown_fields: veriform::field::UnknownFields,
+
+    #[digest(alg = "sha256")]
+    pub digest: Option<veriform::Sha256Digest>,
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn preserve_unknown_fields() {
+    let example = ExampleStructV2 {
+        uint64_field: 42,
+        message_field: ExampleEnum::default(),
+        sint64_field: -42,
+        msg_sequence_field: ExampleStruct::default().msg_sequence_field,
+        digest: None,
+    };
+
+    let mut encoded_buf = new_buffer();
+    let encoded_len = example.encode(&mut encoded_buf).unwrap().len();
+    let encoded_buf = &encoded_buf[..encoded_len];
+
+    let v2 = ExampleStructV2::decode(&mut Decoder::new(), encoded_buf).unwrap();
+    let v1 = PreservingStructV1::decode(&mut Decoder::new(), encoded_buf).unwrap();
+
+    assert_eq!(v1.uint64_field, 42);
+    assert_eq!(v1.sint64_field, -42);
+    assert_eq!(v1.digest, v2.digest);
+
+    let unknown_tags = v1
+        .unknown_fields
+        .iter()
+        .map(|f| f.tag().to_u64())
+        .collect::<Vec<_, U8>>();
+    assert_eq!(unknown_tags, [1, 3]);
+
+    // Re-encoding with the older schema reproduces the original message
+    assert_eq!(v1.encoded_len(), encoded_len);
+
+    let mut reencoded_buf = new_buffer();
+    let reencoded = v1.encode(&mut reencoded_buf).unwrap();
+    assert_eq!(reencoded, encoded_buf);
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct ScalarSeqStruct {
+    #[field(tag = 0, wire_type = "sequence")]
+    pub uint64_seq_field: std::vec::Vec<u64>,
+
+    #[field(tag = 1, wire_type = "sequence", max = 8)]
+    pub sint64_seq_field: heapless::Vec<i64, U8>,
+
+    #[field(tag = 2, wire_type = "sequence")]
+    pub empty_seq_field: std::vec::Vec<u64>,
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn scalar_seq_round_trip() {
+    let mut example = ScalarSeqStruct::default();
+    example.uint64_seq_field.extend_from_slice(&[1, 2, 1 << 40]);
+    example
+        .sint64_seq_field
+        .extend_from_slice(&[-1, 0, 1])
+        .unwrap();
+
+    let mut buffer = new_buffer();
+    let encoded = example.encode(&mut buffer).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded = ScalarSeqStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct MessageSeqStruct {
+    #[field(tag = 0, wire_type = "sequence")]
+    pub msg_sequence_field: std::vec::Vec<ExampleEnum>,
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn message_seq_round_trip() {
+    let example = MessageSeqStruct {
+        msg_sequence_field: vec![
+            ExampleEnum::default(),
+            ExampleEnum::MessageVariant(EmptyStruct {}),
+            ExampleEnum::default(),
+        ],
+    };
+
+    let mut buffer = new_buffer();
+    let encoded = example.encode(&mut buffer).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded = MessageSeqStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct MapStruct {
+    #[field(tag = 0, wire_type = "sequence")]
+    pub names: std::collections::BTreeMap<u64, std::string::String>,
+
+    #[field(tag = 1, wire_type = "sequence")]
+    pub messages: std::collections::BTreeMap<i64, EmptyStruct>,
+
+    #[field(tag = 2, wire_type = "sequence")]
+    pub empty_map: std::collections::BTreeMap<u64, u64>,
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn map_round_trip() {
+    let mut example = MapStruct::default();
+    example.names.insert(2, "two".into());
+    example.names.insert(1, "one".into());
+    example.messages.insert(-1, EmptyStruct {});
+
+    let mut buffer = new_buffer();
+    let encoded = example.encode(&mut buffer).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded = MapStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct OwnedOptionalStruct {
+    #[field(tag = 0, wire_type = "uint64", critical = true)]
+    pub id: u64,
+
+    #[field(tag = 2, wire_type = "uint64", critical = true)]
+    pub count: Option<u64>,
+
+    #[field(tag = 3, wire_type = "string")]
+    pub name: Option<std::string::String>,
+
+    #[field(tag = 5, wire_type = "string")]
+    pub label: std::string::String,
+
+    #[field(tag = 6, wire_type = "bytes")]
+    pub data: Option<std::vec::Vec<u8>>,
+
+    #[field(tag = 9, wire_type = "message")]
+    pub nested: Option<OptionalStruct>,
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn owned_optional_fields() {
+    let full = OwnedOptionalStruct {
+        id: 1,
+        count: Some(42),
+        name: Some("name".into()),
+        label: "label".into(),
+        data: Some(vec![1, 2, 3]),
+        nested: Some(OptionalStruct {
+            optional_field: Some(-1),
+            ..Default::default()
+        }),
+    };
+
+    // Absent fields (including the critical `count`) are simply omitted
+    let sparse = OwnedOptionalStruct {
+        id: 1,
+        label: "label".into(),
+        ..Default::default()
+    };
+
+    for example in &[full, sparse] {
+        let mut buffer = new_buffer();
+        let encoded = example.encode(&mut buffer).unwrap();
+        assert_eq!(encoded.len(), example.encoded_len());
+
+        let decoded = OwnedOptionalStruct::decode(&mut Decoder::new(), encoded).unwrap();
+        assert_eq!(example, &decoded);
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn owned_missing_required_fields() {
+    // `{ 3: string("name"), 9: message({ ... }) }`
+    let mut buffer = new_buffer();
+    let mut encoder = veriform::Encoder::new(&mut buffer);
+    encoder.string(Tag::new_const(3), false, "name").unwrap();
+    encoder
+        .message(Tag::new_const(9), false, &OptionalStruct::default())
+        .unwrap();
+    let encoded = encoder.finish();
+
+    let err = OwnedOptionalStruct::decode(&mut Decoder::new(), encoded)
+        .err()
+        .unwrap();
+
+    let missing = match err.kind() {
+        veriform::error::Kind::MissingFields { fields } => fields,
+        other => panic!("unexpected error: {:?}", other),
+    };
+
+    let names = missing
+        .iter()
+        .map(|field| (field.tag.to_u64(), field.name.unwrap()))
+        .collect::<Vec<_, U8>>();
+    assert_eq!(names, [(0, "id"), (5, "label")]);
+}
+
+/// Newtype around `bytes` whose wire type can't be inferred
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Blob(std::vec::Vec<u8>);
+
+#[cfg(feature = "alloc")]
+impl AsRef<[u8]> for Blob {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<&[u8]> for Blob {
+    fn from(bytes: &[u8]) -> Self {
+        Blob(bytes.to_vec())
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Default, Eq, PartialEq)]
+pub struct VecStruct {
+    #[field(tag = 0, wire_type = "bytes")]
+    pub bytes: std::vec::Vec<u8>,
+
+    #[field(tag = 1, wire_type = "sequence")]
+    pub uint64s: std::vec::Vec<u64>,
+
+    #[field(tag = 2, wire_type = "sequence")]
+    pub strings: std::vec::Vec<std::string::String>,
+
+    #[field(tag = 3, wire_type = "sequence")]
+    pub byte_strings: std::vec::Vec<std::vec::Vec<u8>>,
+
+    #[field(tag = 4, wire_type = "sequence")]
+    pub messages: std::vec::Vec<OptionalStruct>,
+
+    #[field(tag = 5, wire_type = "bytes", bytes)]
+    pub blob: Blob,
+
+    #[field(tag = 6, wire_type = "sequence", bytes)]
+    pub blobs: std::vec::Vec<Blob>,
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn vec_round_trip() {
+    let example = VecStruct {
+        bytes: vec![1, 2, 3],
+        uint64s: vec![1, 2, 3],
+        strings: vec!["veri".into(), "".into(), "form".into()],
+        byte_strings: vec![vec![1], vec![], vec![2, 3]],
+        messages: vec![OptionalStruct::default(), OptionalStruct::default()],
+        blob: Blob(vec![4, 5]),
+        blobs: vec![Blob(vec![6]), Blob::default()],
+    };
+
+    let mut buffer = new_buffer();
+    let encoded = example.encode(&mut buffer).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded = VecStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn empty_vec_round_trip() {
+    let example = VecStruct::default();
+
+    // Empty vectors are encoded as empty `bytes` values and sequences
+    let mut buffer = new_buffer();
+    let encoded = example.encode(&mut buffer).unwrap();
+    assert_eq!(
+        encoded,
+        &[9, 1, 47, 5, 79, 11, 111, 9, 143, 13, 169, 1, 207, 9]
+    );
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded = VecStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+/// Oneof with a variant of every shape
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Eq, PartialEq)]
+pub enum OneofEnum {
+    #[field(tag = 0, wire_type = "uint64")]
+    UInt64(u64),
+
+    #[field(tag = 1, wire_type = "sint64")]
+    SInt64(i64),
+
+    #[field(tag = 2, wire_type = "bytes", size = 4)]
+    Bytes([u8; 4]),
+
+    #[field(tag = 3, wire_type = "string")]
+    String(std::string::String),
+
+    #[field(tag = 4, wire_type = "message")]
+    Message(OptionalStruct),
+
+    #[field(tag = 5, wire_type = "message")]
+    Unit,
+
+    #[field(tag = 6, wire_type = "message", critical = true)]
+    Boxed(Box<OneofEnum>),
+
+    #[field(unknown)]
+    Unknown(veriform::field::UnknownField),
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn oneof_round_trip() {
+    let variants = [
+        OneofEnum::UInt64(42),
+        OneofEnum::SInt64(-42),
+        OneofEnum::Bytes([1, 2, 3, 4]),
+        OneofEnum::String("veriform".into()),
+        OneofEnum::Message(OptionalStruct {
+            optional_field: Some(-1),
+            ..Default::default()
+        }),
+        OneofEnum::Unit,
+        OneofEnum::Boxed(Box::new(OneofEnum::Unit)),
+        OneofEnum::Boxed(Box::new(OneofEnum::Boxed(Box::new(OneofEnum::UInt64(1))))),
+    ];
+
+    for example in &variants {
+        let mut buffer = new_buffer();
+        let encoded = example.encode(&mut buffer).unwrap();
+        assert_eq!(encoded.len(), example.encoded_len());
+
+        let decoded = OneofEnum::decode(&mut Decoder::new(), encoded).unwrap();
+        assert_eq!(example, &decoded);
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn oneof_unit_variant() {
+    // `{ 5: message({}) }`
+    let encoded = OneofEnum::Unit.encode_vec().unwrap();
+    assert_eq!(encoded, [173, 1]);
+
+    // Unknown non-critical fields of the unit variant's message are skipped
+    // `{ 5: message({ 1: uint64(42) }) }`
+    let decoded = OneofEnum::decode(&mut Decoder::new(), &[173, 5, 37, 85]).unwrap();
+    assert_eq!(decoded, OneofEnum::Unit);
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn oneof_unknown_variant() {
+    // `{ 7: uint64(42) }`
+    let encoded = [229, 85];
+    let decoded = OneofEnum::decode(&mut Decoder::new(), &encoded).unwrap();
+
+    match &decoded {
+        OneofEnum::Unknown(field) => assert_eq!(field.tag(), Tag::new_const(7)),
+        other => panic!("unexpected variant: {:?}", other),
+    }
+
+    // Unknown variants are re-encoded exactly as they were decoded
+    assert_eq!(decoded.encode_vec().unwrap(), encoded);
+
+    // Unknown critical fields are still rejected
+    // `{ 7!: uint64(42) }`
+    let err = OneofEnum::decode(&mut Decoder::new(), &[245, 85])
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::UnknownCritical {
+            tag: Tag::new_const(7)
+        }
+    );
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn oneof_multiple_variants() {
+    // `{ 0: uint64(42), 1: sint64(-1) }`
+    let encoded = [5, 85, 39, 3];
+
+    let err = OneofEnum::decode(&mut Decoder::new(), &encoded)
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::MultipleVariants {
+            tag: Tag::new_const(1)
+        }
+    );
+
+    // Permissive decoders keep the first variant
+    let decoded = OneofEnum::decode(&mut Decoder::new().permissive(), &encoded).unwrap();
+    assert_eq!(decoded, OneofEnum::UInt64(42));
+}
+
+/// Oneof with a `sequence` variant of every element type
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Eq, PartialEq)]
+pub enum SeqOneofEnum {
+    #[field(tag = 0, wire_type = "sequence")]
+    UInt64(std::vec::Vec<u64>),
+
+    #[field(tag = 1, wire_type = "sequence")]
+    SInt64(std::vec::Vec<i64>),
+
+    #[field(tag = 2, wire_type = "sequence", max = 8)]
+    Heapless(heapless::Vec<u64, U8>),
+
+    #[field(tag = 3, wire_type = "sequence")]
+    Bytes(std::vec::Vec<std::vec::Vec<u8>>),
+
+    #[field(tag = 4, wire_type = "sequence")]
+    String(std::vec::Vec<std::string::String>),
+
+    #[field(tag = 5, wire_type = "sequence")]
+    Message(std::vec::Vec<ExampleEnum>),
+
+    #[field(tag = 6, wire_type = "sequence")]
+    Map(std::collections::BTreeMap<u64, std::string::String>),
+
+    #[field(tag = 7, wire_type = "uint64")]
+    Scalar(u64),
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn oneof_sequence_round_trip() {
+    let mut map = std::collections::BTreeMap::new();
+    map.insert(2, "two".into());
+    map.insert(1, "one".into());
+
+    let mut heapless_seq = heapless::Vec::new();
+    heapless_seq.extend_from_slice(&[3, 1 << 40]).unwrap();
+
+    let variants = [
+        SeqOneofEnum::UInt64(vec![1, 2, 1 << 40]),
+        SeqOneofEnum::UInt64(vec![]),
+        SeqOneofEnum::SInt64(vec![-1, 0, 1]),
+        SeqOneofEnum::SInt64(vec![]),
+        SeqOneofEnum::Heapless(heapless_seq),
+        SeqOneofEnum::Heapless(heapless::Vec::new()),
+        SeqOneofEnum::Bytes(vec![b"foo".to_vec(), vec![], b"bar".to_vec()]),
+        SeqOneofEnum::Bytes(vec![]),
+        SeqOneofEnum::String(vec!["foo".into(), "".into(), "bar".into()]),
+        SeqOneofEnum::String(vec![]),
+        SeqOneofEnum::Message(vec![
+            ExampleEnum::default(),
+            ExampleEnum::MessageVariant(EmptyStruct {}),
+        ]),
+        SeqOneofEnum::Message(vec![]),
+        SeqOneofEnum::Map(map),
+        SeqOneofEnum::Map(std::collections::BTreeMap::new()),
+        SeqOneofEnum::Scalar(42),
+    ];
+
+    for example in &variants {
+        let mut buffer = new_buffer();
+        let encoded = example.encode(&mut buffer).unwrap();
+        assert_eq!(encoded.len(), example.encoded_len());
+
+        let decoded = SeqOneofEnum::decode(&mut Decoder::new(), encoded).unwrap();
+        assert_eq!(example, &decoded);
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn oneof_sequence_encoding() {
+    // Sequence variants are encoded the same way as sequence struct members
+    let mut buffer = new_buffer();
+    let mut encoder = veriform::Encoder::new(&mut buffer);
+    encoder
+        .sint64_seq(Tag::new_const(1), false, &[-1, 0, 1])
+        .unwrap();
+    let expected = encoder.finish();
+
+    let example = SeqOneofEnum::SInt64(vec![-1, 0, 1]);
+    assert_eq!(example.encode_vec().unwrap(), expected);
+}
+
+/// Struct with in-memory only members which aren't part of the message
+#[cfg(feature = "std")]
+#[derive(Message, Debug)]
+pub struct SkipStruct {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub id: u64,
+
+    #[field(skip)]
+    pub cache: Option<u64>,
+
+    #[field(skip, default = "std::time::Instant::now()")]
+    pub decoded_at: std::time::Instant,
+
+    #[field(tag = 1, wire_type = "string")]
+    pub name: std::string::String,
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn skip_fields() {
+    let example = SkipStruct {
+        id: 42,
+        cache: Some(1),
+        decoded_at: std::time::Instant::now(),
+        name: "veriform".into(),
+    };
+
+    let mut buffer = new_buffer();
+    let encoded = example.encode(&mut buffer).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    // Skipped members are omitted: `{ 0: uint64(42), 1: string("veriform") }`
+    assert_eq!(
+        encoded,
+        &[5, 85, 43, 17, 118, 101, 114, 105, 102, 111, 114, 109]
+    );
+
+    let decoded = SkipStruct::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(decoded.id, 42);
+    assert_eq!(decoded.cache, None);
+    assert!(decoded.decoded_at >= example.decoded_at);
+    assert_eq!(decoded.name, "veriform");
+}
+
+/// Batch of messages of any type
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct Batch<T> {
+    #[field(tag = 0, wire_type = "sequence")]
+    pub items: heapless::Vec<T, U4>,
+}
+
+/// Envelope around a header and payload of any type
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct GenericEnvelope<H, T>
+where
+    T: Message + core::fmt::Debug,
+{
+    #[field(tag = 0, wire_type = "message", critical = true)]
+    pub header: H,
+
+    #[field(tag = 1, wire_type = "message")]
+    pub payload: T,
+
+    #[field(tag = 2, wire_type = "message")]
+    pub reply_to: Option<H>,
+}
+
+/// Message holding a fixed-size key of any length
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct Keyed<const N: usize> {
+    #[field(tag = 0, wire_type = "bytes", critical = true)]
+    pub key: [u8; N],
+}
+
+/// Generic members which need `Default`
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct GenericDefaults<T> {
+    #[field(tag = 0, wire_type = "message", skip_default)]
+    pub value: T,
+
+    #[field(skip)]
+    pub cache: T,
+}
+
+#[test]
+fn generic_round_trip() {
+    let mut items = Vec::new();
+    items.push(EmptyStruct {}).unwrap();
+    items.push(EmptyStruct {}).unwrap();
+
+    // Neither `Keyed` nor `Batch<EmptyStruct>` impl `Default`
+    let example = GenericEnvelope {
+        header: Keyed { key: [42; 4] },
+        payload: Batch { items },
+        reply_to: None,
+    };
+
+    let mut buffer = new_buffer();
+    let encoded = example.encode(&mut buffer).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded: GenericEnvelope<Keyed<4>, Batch<EmptyStruct>> =
+        GenericEnvelope::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+
+    // Keys of the wrong length are rejected
+    let result: Result<GenericEnvelope<Keyed<8>, Batch<EmptyStruct>>, _> =
+        GenericEnvelope::decode(&mut Decoder::new(), encoded);
+    assert!(result.is_err());
+}
+
+#[test]
+fn generic_nested_round_trip() {
+    let mut items = Vec::new();
+    items.push(ExampleEnum::default()).unwrap();
+
+    let example = GenericEnvelope {
+        header: GenericDefaults {
+            value: ExampleEnum::default(),
+            cache: ExampleEnum::default(),
+        },
+        payload: Batch { items },
+        reply_to: Some(GenericDefaults {
+            value: ExampleEnum::MessageVariant(EmptyStruct {}),
+            cache: ExampleEnum::default(),
+        }),
+    };
+
+    let mut buffer = new_buffer();
+    let encoded = example.encode(&mut buffer).unwrap();
+    assert_eq!(encoded.len(), example.encoded_len());
+
+    let decoded: GenericEnvelope<GenericDefaults<ExampleEnum>, Batch<ExampleEnum>> =
+        GenericEnvelope::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+/// Range whose bounds must be consistent, and whose name can't be empty
+#[derive(Message, Debug, Eq, PartialEq)]
+#[veriform(validate = "ValidatedRange::validate")]
+pub struct ValidatedRange<'a> {
+    #[field(tag = 0, wire_type = "string", validate = "non_empty")]
+    pub name: &'a str,
+
+    #[field(tag = 1, wire_type = "uint64", critical = true)]
+    pub start: u64,
+
+    #[field(
+        tag = 2,
+        wire_type = "uint64",
+        critical = true,
+        validate = "at_most_1000"
+    )]
+    pub end: Option<u64>,
+}
+
+impl ValidatedRange<'_> {
+    fn validate(&self) -> Result<(), veriform::Error> {
+        match self.end {
+            Some(end) if end < self.start => Err(veriform::error::Kind::Validation {
+                reason: "range ends before it starts",
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn non_empty(name: &&str) -> Result<(), veriform::error::Kind> {
+    if name.is_empty() {
+        Err(veriform::error::Kind::Validation {
+            reason: "name is empty",
+        })
+    } else {
+        Ok(())
+    }
+}
+
+fn at_most_1000(value: &u64) -> Result<(), veriform::error::Kind> {
+    if *value > 1000 {
+        Err(veriform::error::Kind::Validation {
+            reason: "end exceeds 1000",
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Encode a [`ValidatedRange`], which isn't validated when encoding
+fn encode_range<'b>(range: &ValidatedRange<'_>, buffer: &'b mut [u8]) -> &'b [u8] {
+    range.encode(buffer).unwrap()
+}
+
+#[test]
+fn validate_ok() {
+    let example = ValidatedRange {
+        name: "range",
+        start: 1,
+        end: Some(10),
+    };
+
+    let mut buffer = new_buffer();
+    let encoded = encode_range(&example, &mut buffer);
+
+    let decoded = ValidatedRange::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(example, decoded);
+}
+
+#[test]
+fn validate_message_error() {
+    let example = ValidatedRange {
+        name: "range",
+        start: 10,
+        end: Some(1),
+    };
+
+    let mut buffer = new_buffer();
+    let encoded = encode_range(&example, &mut buffer);
+
+    let err = ValidatedRange::decode(&mut Decoder::new(), encoded).unwrap_err();
+    assert_eq!(
+        err.kind(),
+        veriform::error::Kind::Validation {
+            reason: "range ends before it starts"
+        }
+    );
+    assert_eq!(err.code(), veriform::error::ErrorCode::Validation);
+}
+
+#[test]
+fn validate_field_error() {
+    let mut buffer = new_buffer();
+
+    for &(ref example, reason, tag) in &[
+        (
+            ValidatedRange {
+                name: "",
+                start: 0,
+                end: None,
+            },
+            "name is empty",
+            0,
+        ),
+        (
+            ValidatedRange {
+                name: "range",
+                start: 0,
+                end: Some(1001),
+            },
+            "end exceeds 1000",
+            2,
+        ),
+    ] {
+        let encoded = encode_range(example, &mut buffer);
+
+        let err = ValidatedRange::decode(&mut Decoder::new(), encoded).unwrap_err();
+        assert_eq!(err.kind(), veriform::error::Kind::Validation { reason });
+        assert_eq!(err.tag(), Some(Tag::new_const(tag)));
+    }
+}
+
+/// Oneof whose number can't be zero
+#[derive(Message, Debug, Eq, PartialEq)]
+#[veriform(validate = "ValidatedEnum::validate")]
+pub enum ValidatedEnum {
+    #[field(tag = 0, wire_type = "uint64")]
+    Number(u64),
+
+    #[field(tag = 1, wire_type = "message")]
+    Empty,
+}
+
+impl ValidatedEnum {
+    fn validate(&self) -> Result<(), veriform::error::Kind> {
+        match self {
+            ValidatedEnum::Number(0) => Err(veriform::error::Kind::Validation {
+                reason: "number is zero",
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn validate_enum() {
+    let mut buffer = new_buffer();
+
+    let encoded = ValidatedEnum::Number(1).encode(&mut buffer).unwrap();
+    let decoded = ValidatedEnum::decode(&mut Decoder::new(), encoded).unwrap();
+    assert_eq!(decoded, ValidatedEnum::Number(1));
+
+    let encoded = ValidatedEnum::Number(0).encode(&mut buffer).unwrap();
+    let err = ValidatedEnum::decode(&mut Decoder::new(), encoded).unwrap_err();
+    assert_eq!(err.code(), veriform::error::ErrorCode::Validation);
+}
+
+/// Identifier which is encoded exactly like the `u64` it wraps
+#[derive(Message, Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[veriform(transparent)]
+pub struct UserId(u64);
+
+/// Newtype of a newtype, which is also encoded like a `u64`
+#[derive(Message, Copy, Clone, Debug, Eq, PartialEq)]
+#[veriform(transparent)]
+pub struct AdminId(UserId);
+
+/// Struct with a plain `u64` field
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct PlainUser {
+    #[field(tag = 0, wire_type = "uint64", critical = true)]
+    pub id: u64,
+
+    #[field(tag = 1, wire_type = "uint64")]
+    pub referrer: Option<u64>,
+
+    #[field(tag = 2, wire_type = "uint64", skip_default)]
+    pub admin: u64,
+
+    #[digest(alg = "sha256")]
+    pub digest: Option<veriform::Sha256Digest>,
+}
+
+/// [`PlainUser`], but with newtypes in place of its `u64` fields
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct TypedUser {
+    #[field(tag = 0, wire_type = "uint64", critical = true)]
+    pub id: UserId,
+
+    #[field(tag = 1, wire_type = "uint64")]
+    pub referrer: Option<UserId>,
+
+    #[field(tag = 2, wire_type = "uint64", skip_default)]
+    pub admin: UserId,
+
+    #[digest(alg = "sha256")]
+    pub digest: Option<veriform::Sha256Digest>,
+}
+
+#[test]
+fn transparent_encodes_like_inner() {
+    for &(referrer, admin) in &[(None, 0), (Some(3), 0), (Some(3), 42)] {
+        let plain = PlainUser {
+            id: 7,
+            referrer,
+            admin,
+            digest: None,
+        };
+
+        let typed = TypedUser {
+            id: UserId(7),
+            referrer: referrer.map(UserId),
+            admin: UserId(admin),
+            digest: None,
+        };
+
+        let mut plain_buffer = new_buffer();
+        let plain_encoded = plain.encode(&mut plain_buffer).unwrap();
+
+        let mut typed_buffer = new_buffer();
+        let typed_encoded = typed.encode(&mut typed_buffer).unwrap();
+        assert_eq!(plain_encoded, typed_encoded);
+        assert_eq!(typed_encoded.len(), typed.encoded_len());
+
+        let plain_decoded = PlainUser::decode(&mut Decoder::new(), plain_encoded).unwrap();
+        let typed_decoded = TypedUser::decode(&mut Decoder::new(), typed_encoded).unwrap();
+        assert_eq!(typed_decoded.id, typed.id);
+        assert_eq!(typed_decoded.referrer, typed.referrer);
+        assert_eq!(typed_decoded.admin, typed.admin);
+
+        // Newtypes are hashed exactly like their inner values
+        assert!(typed_decoded.digest.is_some());
+        assert_eq!(typed_decoded.digest, plain_decoded.digest);
+    }
+
+    // `UserId(7)` in field 0 is encoded as the header and value of a `u64`
+    let typed = TypedUser {
+        id: UserId(7),
+        referrer: None,
+        admin: UserId::default(),
+        digest: None,
+    };
+
+    let mut buffer = new_buffer();
+    assert_eq!(typed.encode(&mut buffer).unwrap(), &[21, 15]);
+}
+
+/// Bytes which are encoded exactly like the `Vec<u8>` they wrap
+#[cfg(feature = "alloc")]
+#[derive(Message, Clone, Debug, Eq, PartialEq)]
+#[veriform(transparent)]
+pub struct Payload(std::vec::Vec<u8>);
+
+/// Message which is encoded exactly like the [`ExampleEnum`] it wraps
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Eq, PartialEq)]
+#[veriform(transparent)]
+pub struct Attachment(ExampleEnum);
+
+/// Struct with plain fields of every type which may be wrapped in newtypes
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct PlainGroup {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub owner: u64,
+
+    #[field(tag = 1, wire_type = "bytes")]
+    pub payload: std::vec::Vec<u8>,
+
+    #[field(tag = 2, wire_type = "message")]
+    pub attachment: Option<ExampleEnum>,
+
+    #[field(tag = 3, wire_type = "sequence")]
+    pub members: std::vec::Vec<u64>,
+
+    #[field(tag = 4, wire_type = "sequence")]
+    pub admins: std::vec::Vec<u64>,
+
+    #[field(tag = 5, wire_type = "sequence")]
+    pub payloads: std::vec::Vec<std::vec::Vec<u8>>,
+
+    #[field(tag = 6, wire_type = "sequence")]
+    pub attachments: std::vec::Vec<ExampleEnum>,
+}
+
+/// [`PlainGroup`], but with newtypes in place of its fields' values
+#[cfg(feature = "alloc")]
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct TypedGroup {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub owner: AdminId,
+
+    #[field(tag = 1, wire_type = "bytes")]
+    pub payload: Payload,
+
+    #[field(tag = 2, wire_type = "message")]
+    pub attachment: Option<Attachment>,
+
+    #[field(tag = 3, wire_type = "sequence")]
+    pub members: std::vec::Vec<UserId>,
+
+    #[field(tag = 4, wire_type = "sequence")]
+    pub admins: std::vec::Vec<AdminId>,
+
+    #[field(tag = 5, wire_type = "sequence")]
+    pub payloads: std::vec::Vec<Payload>,
+
+    #[field(tag = 6, wire_type = "sequence")]
+    pub attachments: std::vec::Vec<Attachment>,
+}
+
+#[cfg(feature = "alloc")]
+#[test]
+fn transparent_sequences() {
+    let plain = PlainGroup {
+        owner: 1,
+        payload: b"veriform".to_vec(),
+        attachment: Some(ExampleEnum::MessageVariant(EmptyStruct {})),
+        members: vec![1, 2, 3],
+        admins: vec![1],
+        payloads: vec![b"veri".to_vec(), vec![], b"form".to_vec()],
+        attachments: vec![ExampleEnum::default()],
+    };
+
+    let typed = TypedGroup {
+        owner: AdminId(UserId(1)),
+        payload: Payload(b"veriform".to_vec()),
+        attachment: Some(Attachment(ExampleEnum::MessageVariant(EmptyStruct {}))),
+        members: vec![UserId(1), UserId(2), UserId(3)],
+        admins: vec![AdminId(UserId(1))],
+        payloads: vec![
+            Payload(b"veri".to_vec()),
+            Payload(vec![]),
+            Payload(b"form".to_vec()),
+        ],
+        attachments: vec![Attachment(ExampleEnum::default())],
+    };
+
+    let mut plain_buffer = new_buffer();
+    let plain_encoded = plain.encode(&mut plain_buffer).unwrap();
+
+    let mut typed_buffer = new_buffer();
+    let typed_encoded = typed.encode(&mut typed_buffer).unwrap();
+    assert_eq!(plain_encoded, typed_encoded);
+    assert_eq!(typed_encoded.len(), typed.encoded_len());
+
+    let decoded = TypedGroup::decode(&mut Decoder::new(), typed_encoded).unwrap();
+    assert_eq!(typed, decoded);
+}
@@ -0,0 +1,16 @@
+//! Compile-fail tests for `veriform_derive`
+
+// trybuild only forwards features declared in `[features]` to the crates it
+// builds, so the optional `veriform_derive` dependency is only enabled for
+// them through the default features
+#![cfg(all(feature = "veriform_derive", feature = "builtins-std"))]
+
+// The expected compiler output in `tests/ui/*.stderr` varies between Rust
+// releases and is only kept up to date for stable, so CI runs this test on
+// stable alone with `cargo test --test derive_ui -- --ignored`
+#[test]
+#[ignore]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,69 @@
+//! Interoperability of Veriform errors with `std::error::Error`
+
+#![cfg(all(feature = "std", feature = "sha2"))]
+
+use sha2::Sha256;
+use std::io;
+use veriform::{
+    decoder::{ReadError, Reader},
+    error::Kind,
+    field::Tag,
+    Decoder,
+};
+
+/// `{ 1: string(b"\xFF") }`, whose string isn't valid UTF-8
+const INVALID_UTF8: &[u8] = &[43, 3, 0xFF];
+
+/// Decode the string in field 1, converting errors with `?`
+fn decode_string(mut input: &[u8]) -> anyhow::Result<String> {
+    let s = Decoder::new().decode_string(Tag::new_const(1), &mut input)?;
+    Ok(s.to_owned())
+}
+
+/// Read all of the events of a message, converting errors with `?`
+fn read_events(input: &[u8]) -> anyhow::Result<usize> {
+    let mut reader = Reader::<Sha256, _>::new(input);
+    let mut count = 0;
+
+    while reader.next_event()?.is_some() {
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[test]
+fn anyhow_downcast() {
+    let err = decode_string(INVALID_UTF8).err().unwrap();
+    let err = err.downcast_ref::<veriform::Error>().unwrap();
+    assert_eq!(err.kind(), Kind::Utf8 { valid_up_to: 0 });
+}
+
+#[test]
+fn anyhow_source_chain() {
+    let err = read_events(INVALID_UTF8).err().unwrap();
+    assert!(err.downcast_ref::<ReadError>().is_some());
+
+    let cause = err.root_cause().downcast_ref::<veriform::Error>().unwrap();
+    assert_eq!(cause.kind(), Kind::Utf8 { valid_up_to: 0 });
+}
+
+#[test]
+fn io_error_source() {
+    let err = ReadError::from(io::Error::from(io::ErrorKind::ConnectionReset));
+    assert_eq!(err.to_string(), "I/O error");
+
+    let source = std::error::Error::source(&err).unwrap();
+    let source = source.downcast_ref::<io::Error>().unwrap();
+    assert_eq!(source.kind(), io::ErrorKind::ConnectionReset);
+}
+
+#[test]
+fn utf8_error_conversion() {
+    let utf8_err = std::str::from_utf8(&INVALID_UTF8[2..]).err().unwrap();
+    let err = veriform::Error::from(utf8_err);
+    assert_eq!(err.kind(), Kind::Utf8 { valid_up_to: 0 });
+
+    let boxed: Box<dyn std::error::Error + Send + Sync> = err.into();
+    assert!(boxed.source().is_none());
+}
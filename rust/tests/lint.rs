@@ -0,0 +1,101 @@
+//! Tests for reporting every problem in a message
+
+#![cfg(feature = "alloc")]
+
+use veriform::{
+    decoder::{lint, Issue},
+    error::Kind,
+    field::{Tag, WireType},
+    Encoder,
+};
+
+/// Get the offset, path (as numeric tags), and kind of each issue
+fn summarize(issues: &[Issue]) -> Vec<(usize, Vec<u64>, Kind)> {
+    issues
+        .iter()
+        .map(|issue| {
+            let path = issue.path.iter().map(Tag::to_u64).collect();
+            (issue.offset, path, issue.kind)
+        })
+        .collect()
+}
+
+#[test]
+fn independent_defects() {
+    let input = [
+        // `1: uint64(42)`, with a non-minimal `vint64` value
+        37, 170, 0, //
+        // `3: sequence<true>`, which isn't a supported element type
+        111, 67, 0, 0, //
+        // `2: string("baz")`, which is out-of-order
+        75, 7, 98, 97, 122,
+    ];
+
+    assert_eq!(
+        summarize(&lint(&input)),
+        [
+            (1, vec![1], Kind::NonCanonicalVInt64),
+            (5, vec![3], Kind::InvalidWireType),
+            (
+                7,
+                vec![2],
+                Kind::Order {
+                    tag: Tag::new_const(2)
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn resynchronize_after_nested_message() {
+    let input = [
+        // `1: message({ 0: bytes(...) })`, whose length delimiter claims
+        // more data than the nested message contains
+        45, 7, 9, 11, 1, //
+        // `0: uint64(1)`, which is out-of-order
+        5, 3,
+    ];
+
+    assert_eq!(
+        summarize(&lint(&input)),
+        [
+            (
+                4,
+                vec![1, 0],
+                Kind::Truncated {
+                    remaining: 4,
+                    wire_type: WireType::Bytes
+                }
+            ),
+            (
+                5,
+                vec![0],
+                Kind::Order {
+                    tag: Tag::new_const(0)
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn truncated() {
+    // `1: uint64`, missing its value
+    assert_eq!(summarize(&lint(&[37])), [(1, vec![1], Kind::VInt64)]);
+}
+
+#[test]
+fn valid_message() {
+    let mut buffer = [0u8; 64];
+    let mut encoder = Encoder::new(&mut buffer);
+    encoder.uint64(Tag::new_const(1), false, 42).unwrap();
+    encoder.sint64(Tag::new_const(2), false, -42).unwrap();
+    encoder.string(Tag::new_const(3), false, "baz").unwrap();
+    encoder.bool(Tag::new_const(4), false, true).unwrap();
+    encoder
+        .uint64_seq(Tag::new_const(5), false, &[1, 300, 70000])
+        .unwrap();
+
+    assert!(lint(encoder.finish()).is_empty());
+}
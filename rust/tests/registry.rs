@@ -0,0 +1,149 @@
+//! Decoding messages whose type is chosen at runtime with a `Registry`
+
+#![cfg(all(feature = "sha2", feature = "alloc", feature = "veriform_derive"))]
+
+use sha2::Sha256;
+use veriform::{
+    error::Kind,
+    message::AnyMessage,
+    registry::{DecodeFn, Registry},
+    Decoder, Message,
+};
+
+/// Type ID of [`Ping`]
+const PING: u64 = 1;
+
+/// Type ID of [`Greeting`]
+const GREETING: u64 = 2;
+
+/// Type ID of [`Transfer`]
+const TRANSFER: u64 = 3;
+
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct Ping {
+    #[field(tag = 0, wire_type = "uint64", critical = true)]
+    pub nonce: u64,
+}
+
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct Greeting {
+    #[field(tag = 0, wire_type = "string", critical = true)]
+    pub name: String,
+}
+
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct Transfer {
+    #[field(tag = 0, wire_type = "string", critical = true)]
+    pub to: String,
+
+    #[field(tag = 1, wire_type = "sint64", critical = true)]
+    pub amount: i64,
+
+    #[field(tag = 2, wire_type = "message")]
+    pub ping: Option<Ping>,
+}
+
+/// Create a registry of the example message types
+fn registry() -> Registry<Sha256> {
+    let mut registry = Registry::new();
+
+    registry
+        .register::<Ping>(PING)
+        .register::<Greeting>(GREETING)
+        .register::<Transfer>(TRANSFER);
+
+    registry
+}
+
+/// Round trip a message through the registry, returning the decoded message
+fn round_trip<M>(registry: &Registry<Sha256>, type_id: u64, message: &M) -> Box<dyn AnyMessage>
+where
+    M: Message + std::fmt::Debug + PartialEq + 'static,
+{
+    let encoded = message.encode_vec().unwrap();
+    let decoded = registry.decode(type_id, &encoded).unwrap();
+
+    // Decoded messages encode the same as the original
+    assert_eq!(decoded.encoded_len(), encoded.len());
+    assert_eq!(decoded.encode_vec().unwrap(), encoded);
+    assert_eq!(decoded.downcast_ref::<M>(), Some(message));
+
+    decoded
+}
+
+#[test]
+fn round_trip_each_type() {
+    let registry = registry();
+    let ping = Ping { nonce: 42 };
+
+    let decoded = round_trip(&registry, PING, &ping);
+    assert!(decoded.is::<Ping>());
+    assert!(!decoded.is::<Greeting>());
+    assert_eq!(*decoded.downcast::<Ping>().unwrap(), ping);
+
+    let greeting = Greeting {
+        name: "veriform".into(),
+    };
+    let decoded = round_trip(&registry, GREETING, &greeting);
+    let decoded = decoded.downcast::<Transfer>().unwrap_err();
+    assert_eq!(*decoded.downcast::<Greeting>().unwrap(), greeting);
+
+    let transfer = Transfer {
+        to: "alice".into(),
+        amount: -7,
+        ping: Some(ping),
+    };
+    let decoded = round_trip(&registry, TRANSFER, &transfer);
+    assert_eq!(*decoded.downcast::<Transfer>().unwrap(), transfer);
+}
+
+#[test]
+fn unknown_type_id() {
+    let registry = registry();
+    assert!(!registry.contains(4));
+
+    let encoded = Ping { nonce: 1 }.encode_vec().unwrap();
+    let err = registry.decode(4, &encoded).err().unwrap();
+    assert_eq!(err.kind(), Kind::UnknownType { type_id: 4 });
+}
+
+#[test]
+fn wrong_type_id() {
+    let registry = registry();
+
+    // A `Ping` isn't a valid `Greeting`
+    let encoded = Ping { nonce: 1 }.encode_vec().unwrap();
+    assert!(registry.decode(GREETING, &encoded).is_err());
+}
+
+#[test]
+fn custom_decode_fn() {
+    /// Decode pings with a nonce of zero as greetings
+    fn decode_ping_or_greeting(
+        decoder: &mut Decoder,
+        input: &[u8],
+    ) -> Result<Box<dyn AnyMessage>, veriform::Error> {
+        let ping = Ping::decode(decoder, input)?;
+
+        if ping.nonce == 0 {
+            Ok(Box::new(Greeting {
+                name: "zero".into(),
+            }))
+        } else {
+            Ok(Box::new(ping))
+        }
+    }
+
+    let mut registry = Registry::new();
+    registry.register_fn(PING, decode_ping_or_greeting as DecodeFn<Sha256>);
+
+    let encoded = Ping { nonce: 0 }.encode_vec().unwrap();
+    let decoded = registry.decode(PING, &encoded).unwrap();
+    assert!(decoded.is::<Greeting>());
+}
+
+#[test]
+#[should_panic(expected = "type ID 1 is already registered")]
+fn duplicate_type_id() {
+    registry().register::<Greeting>(PING);
+}
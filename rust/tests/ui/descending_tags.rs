@@ -0,0 +1,12 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct DescendingTags {
+    #[field(tag = 2, wire_type = "uint64")]
+    pub first: u64,
+
+    #[field(tag = 1, wire_type = "uint64")]
+    pub second: u64,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct DuplicateTag {
+    #[field(tag = 1, wire_type = "uint64")]
+    pub first: u64,
+
+    #[field(tag = 1, wire_type = "sint64")]
+    pub second: i64,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub enum DuplicateVariantTag {
+    #[field(tag = 0, wire_type = "uint64")]
+    First(u64),
+
+    #[field(tag = 0, wire_type = "sint64")]
+    Second(i64),
+}
+
+fn main() {}
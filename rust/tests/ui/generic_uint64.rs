@@ -0,0 +1,9 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct GenericUInt64<T> {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub value: T,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub enum IncompatibleVariantWireType {
+    #[field(tag = 0, wire_type = "string")]
+    Bytes([u8; 32]),
+}
+
+fn main() {}
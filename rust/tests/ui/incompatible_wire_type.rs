@@ -0,0 +1,9 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct IncompatibleWireType {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub name: Option<String>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct MisplacedBytes {
+    #[field(tag = 0, wire_type = "uint64", bytes)]
+    pub count: u64,
+}
+
+fn main() {}
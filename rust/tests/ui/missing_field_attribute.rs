@@ -0,0 +1,11 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct MissingFieldAttribute {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub first: u64,
+
+    pub second: u64,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct MissingTag {
+    #[field(wire_type = "uint64")]
+    pub value: u64,
+}
+
+fn main() {}
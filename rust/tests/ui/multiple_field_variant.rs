@@ -0,0 +1,9 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub enum MultipleFieldVariant {
+    #[field(tag = 0, wire_type = "uint64")]
+    Pair(u64, u64),
+}
+
+fn main() {}
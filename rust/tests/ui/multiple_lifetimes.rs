@@ -0,0 +1,12 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct MultipleLifetimes<'a, 'b> {
+    #[field(tag = 0, wire_type = "bytes")]
+    pub bytes: &'a [u8],
+
+    #[field(tag = 1, wire_type = "string")]
+    pub string: &'b str,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct OptionDefault {
+    #[field(tag = 0, wire_type = "uint64", default)]
+    pub value: Option<u64>,
+}
+
+fn main() {}
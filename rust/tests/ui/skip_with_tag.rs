@@ -0,0 +1,12 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub struct SkipWithTag {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub id: u64,
+
+    #[field(skip, tag = 1)]
+    pub cache: u64,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use veriform::Message;
+
+#[derive(Message)]
+#[veriform(transparent)]
+pub struct UserId {
+    #[field(tag = 0, wire_type = "uint64")]
+    id: u64,
+}
+
+fn main() {}
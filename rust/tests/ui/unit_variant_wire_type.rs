@@ -0,0 +1,9 @@
+use veriform::Message;
+
+#[derive(Message)]
+pub enum UnitVariantWireType {
+    #[field(tag = 0, wire_type = "uint64")]
+    Empty,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use veriform::Message;
+
+fn positive(value: &u64) -> Result<(), veriform::Error> {
+    if *value > 0 {
+        Ok(())
+    } else {
+        Err(veriform::error::Kind::Validation { reason: "zero" }.into())
+    }
+}
+
+#[derive(Message)]
+pub enum VariantValidate {
+    #[field(tag = 0, wire_type = "uint64", validate = "positive")]
+    Number(u64),
+}
+
+fn main() {}
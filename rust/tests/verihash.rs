@@ -0,0 +1,57 @@
+//! Verihash known-answer tests
+
+#![cfg(all(feature = "test-vectors", feature = "sha2"))]
+
+use veriform::{
+    error::Kind,
+    verihash::test_vectors::{self, TestVector},
+};
+
+/// Load the test vectors shared with other implementations
+fn vectors() -> Vec<TestVector> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../vectors/verihash.tjson");
+    test_vectors::load(&std::fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn sha256_vectors() {
+    let vectors = vectors();
+    assert!(!vectors.is_empty());
+
+    for vector in &vectors {
+        assert_eq!(vector.digest_alg, "SHA256");
+
+        if let Err(e) = test_vectors::run_vector::<sha2::Sha256>(vector) {
+            panic!("test vector failed: {} ({})", vector.name, e);
+        }
+    }
+}
+
+#[test]
+fn domain_separation() {
+    let vectors = vectors();
+    let find = |domain: &[u8]| {
+        vectors
+            .iter()
+            .find(|v| v.domain.as_deref() == Some(domain))
+            .unwrap()
+    };
+
+    let protocol_a = find(b"example.protocol-a");
+    let protocol_b = find(b"example.protocol-b");
+
+    assert_eq!(protocol_a.input, protocol_b.input);
+    assert_ne!(protocol_a.output, protocol_b.output);
+}
+
+#[test]
+fn malformed_vectors() {
+    let missing_digest =
+        r#"{"examples:A<O>": [{"name:s": "x", "algorithm:s": "SHA256", "input:d16": ""}]}"#;
+    let bad_hex = r#"{"examples:A<O>": [{"name:s": "x", "algorithm:s": "SHA256", "input:d16": "0g", "digest:d16": ""}]}"#;
+
+    for tjson in &["", "{}", missing_digest, bad_hex] {
+        let err = test_vectors::load(tjson).unwrap_err();
+        assert!(matches!(err.kind(), Kind::TestVectors { .. }), "{:?}", err);
+    }
+}
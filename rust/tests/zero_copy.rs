@@ -0,0 +1,118 @@
+//! Zero-copy decoding tests.
+//!
+//! These use a counting global allocator to check that decoding borrowed
+//! messages, or copying values into caller-supplied buffers, doesn't
+//! allocate. As the count is global, this file contains a
+//! single test so no other tests allocate concurrently.
+
+#![cfg(all(feature = "sha2", feature = "veriform_derive"))]
+
+use heapless::consts::U1024;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use veriform::{decoder::Decode, field::Tag, Decoder, Message, MessageRef};
+
+/// Allocator which counts allocations
+struct CountingAllocator;
+
+/// Number of allocations made so far
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct Borrowed<'a> {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub uint64_field: u64,
+
+    #[field(tag = 1, wire_type = "bytes")]
+    pub bytes_field: &'a [u8],
+
+    #[field(tag = 2, wire_type = "string")]
+    pub string_field: &'a str,
+}
+
+#[derive(Message, Debug, Eq, PartialEq)]
+pub struct Owned {
+    #[field(tag = 0, wire_type = "uint64")]
+    pub uint64_field: u64,
+
+    #[field(tag = 1, wire_type = "bytes")]
+    pub bytes_field: heapless::Vec<u8, U1024>,
+
+    #[field(tag = 2, wire_type = "string")]
+    pub string_field: heapless::String<U1024>,
+}
+
+#[test]
+fn decode_without_allocating() {
+    let bytes = [0xAA; 1024];
+    let string = "x".repeat(1024);
+
+    let example = Borrowed {
+        uint64_field: 42,
+        bytes_field: &bytes,
+        string_field: &string,
+    };
+
+    let mut encoded = vec![0; example.encoded_len()];
+    example.encode(&mut encoded).unwrap();
+
+    let before = ALLOCATIONS.load(Ordering::SeqCst);
+
+    // Decode with the derived `MessageRef` impl
+    let mut decoder = Decoder::new();
+    let decoded = Borrowed::decode(&mut decoder, &encoded).unwrap();
+
+    // Decode with the decoder's borrowed accessors
+    let mut decoder = Decoder::new();
+    let mut input = &encoded[..];
+    let uint64_field: u64 = decoder.decode(Tag::new_const(0), &mut input).unwrap();
+    let bytes_field = decoder.decode_bytes(Tag::new_const(1), &mut input).unwrap();
+    let string_field = decoder
+        .decode_string(Tag::new_const(2), &mut input)
+        .unwrap();
+
+    // Decode with the derived `Message` impl for owned `heapless` fields
+    let mut decoder = Decoder::new();
+    let owned = Owned::decode(&mut decoder, &encoded).unwrap();
+
+    // Decode into caller-supplied buffers
+    let mut decoder = Decoder::new();
+    let mut input = &encoded[..];
+    let mut bytes_buf = [0u8; 1024];
+    let mut string_buf = [0u8; 1024];
+    let _: u64 = decoder.decode(Tag::new_const(0), &mut input).unwrap();
+    let bytes_len = decoder
+        .decode_bytes_into(Tag::new_const(1), &mut input, &mut bytes_buf)
+        .unwrap();
+    let string_len = decoder
+        .decode_string_into(Tag::new_const(2), &mut input, &mut string_buf)
+        .unwrap();
+
+    let after = ALLOCATIONS.load(Ordering::SeqCst);
+    assert_eq!(before, after, "decoding allocated");
+
+    assert_eq!(decoded, example);
+    assert_eq!(uint64_field, 42);
+    assert_eq!(bytes_field, &bytes[..]);
+    assert_eq!(string_field, string);
+    assert_eq!(&owned.bytes_field[..], &bytes[..]);
+    assert_eq!(owned.string_field, string.as_str());
+    assert_eq!(&bytes_buf[..bytes_len], &bytes[..]);
+    assert_eq!(&string_buf[..string_len], string.as_bytes());
+}
@@ -11,7 +11,7 @@ const EXAMPLE_VALUES: [u64; 8] = [
     0x0f0f_f0f0_0f0f,
     0x0f0f_f0f0_0f0f_f0f0,
     0xffff_ffff_0f0f_f0f0,
-    core::u64::MAX,
+    u64::MAX,
 ];
 
 fn bench(c: &mut Criterion<CyclesPerByte>) {
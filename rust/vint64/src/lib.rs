@@ -205,7 +205,7 @@ pub fn encode(value: u64) -> VInt64 {
 #[inline]
 pub fn decode(input: &mut &[u8]) -> Result<u64, Error> {
     let bytes = *input;
-    let length = decoded_len(*bytes.first().ok_or_else(|| Error::Truncated)?);
+    let length = decoded_len(*bytes.first().ok_or(Error::Truncated)?);
 
     if bytes.len() < length {
         return Err(Error::Truncated);
@@ -256,7 +256,7 @@ mod tests {
     #[test]
     fn encode_maxint() {
         assert_eq!(
-            encode(core::u64::MAX).as_ref(),
+            encode(u64::MAX).as_ref(),
             &[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
         );
     }
@@ -302,7 +302,7 @@ mod tests {
     #[test]
     fn decode_maxint() {
         let mut slice = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff].as_ref();
-        assert_eq!(decode(&mut slice).unwrap(), core::u64::MAX);
+        assert_eq!(decode(&mut slice).unwrap(), u64::MAX);
         assert!(slice.is_empty());
     }
 